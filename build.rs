@@ -0,0 +1,6 @@
+fn main() {
+    slint_build::compile("ui/main.slint").unwrap();
+    // 依赖本 crate 的应用在 build.rs 中通过 DEP_SLINT_OPENCV_UI_DIR 引用 VideoView.
+    let ui_dir = std::path::Path::new(&std::env::var("CARGO_MANIFEST_DIR").unwrap()).join("ui");
+    println!("cargo:ui_dir={}", ui_dir.display());
+}
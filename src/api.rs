@@ -0,0 +1,329 @@
+use std::{io::Write, sync::mpsc::Sender};
+
+use anyhow::Result;
+
+use crate::{
+    auth::{self, Auth, Connection, Listener, Request, Role},
+    capture::Command,
+    faults::{self, Fault},
+    parking,
+    printer::PrintEvent,
+    thumbnail::Thumbnail,
+};
+
+// 在 addr 上提供 HTTP 控制接口:
+// GET /scenes 列出场景名称, POST /scenes/<名称> 切换场景, GET /thumbnail.jpg 返回最新的缩略图.
+// POST /printer/started?name=..., /printer/layer?layer=N, /printer/finished 为打印机的 webhook, 用于按层延时摄影.
+// GET /parking 返回停车位预设最近一次的状态 (JSON).
+// faults 为 true 时 POST /faults/<故障> 注入来源故障, 见 faults::Fault.
+// 读取需要 viewer 权限, 切换场景和注入故障需要 operator 权限.
+pub fn serve(
+    addr: &str,
+    auth: &Auth,
+    scenes: Vec<String>,
+    commands: Sender<Command>,
+    thumbnail: Thumbnail,
+    faults: bool,
+) -> Result<Listener> {
+    let checker = auth.clone();
+    auth.listen(addr, "control API", move |stream| {
+        respond(stream, &checker, &scenes, &commands, &thumbnail, faults)
+    })
+}
+
+fn respond(
+    mut stream: Connection,
+    auth: &Auth,
+    scenes: &[String],
+    commands: &Sender<Command>,
+    thumbnail: &Thumbnail,
+    faults: bool,
+) -> Result<()> {
+    let request = Request::read(&mut stream)?;
+    let method = request.method.as_str();
+    let path = request.path.as_str();
+    let role = if method == "GET" {
+        Role::Viewer
+    } else {
+        Role::Operator
+    };
+    if auth.authorize(&mut stream, &request, role)?.is_none() {
+        return Ok(());
+    }
+    if path == "/thumbnail.jpg" {
+        return match (method, thumbnail.latest()) {
+            ("GET", Some(jpeg)) => write_response(&mut stream, "200 OK", "image/jpeg", &jpeg),
+            ("GET", None) => write_response(
+                &mut stream,
+                "404 Not Found",
+                "text/plain; charset=utf-8",
+                b"no thumbnail, enable [thumbnail]\n",
+            ),
+            _ => write_response(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain; charset=utf-8",
+                b"method not allowed\n",
+            ),
+        };
+    }
+    if path == "/parking" {
+        return match (method, parking::latest()) {
+            ("GET", Some(status)) => write_response(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                status.to_string().as_bytes(),
+            ),
+            ("GET", None) => write_response(
+                &mut stream,
+                "404 Not Found",
+                "text/plain; charset=utf-8",
+                b"parking spaces are not enabled\n",
+            ),
+            _ => write_response(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain; charset=utf-8",
+                b"method not allowed\n",
+            ),
+        };
+    }
+    if let Some(kind) = path.strip_prefix("/faults/").filter(|_| faults) {
+        let (status, body) = match (method, fault(kind, &request.query)) {
+            ("POST", Ok(fault)) => {
+                faults::inject(&fault);
+                ("200 OK", format!("injected {:?}\n", fault))
+            }
+            ("POST", Err(err)) => ("400 Bad Request", format!("{}\n", err)),
+            _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+        };
+        return write_response(
+            &mut stream,
+            status,
+            "text/plain; charset=utf-8",
+            body.as_bytes(),
+        );
+    }
+    if let Some(kind) = path.strip_prefix("/printer/") {
+        let (status, body) = match (method, print_event(kind, &request.query)) {
+            ("POST", Ok(event)) => {
+                if commands.send(Command::Print(event)).is_err() {
+                    ("503 Service Unavailable", "capture stopped\n".to_string())
+                } else {
+                    ("200 OK", "ok\n".to_string())
+                }
+            }
+            ("POST", Err(err)) => ("400 Bad Request", format!("{}\n", err)),
+            _ => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+        };
+        return write_response(
+            &mut stream,
+            status,
+            "text/plain; charset=utf-8",
+            body.as_bytes(),
+        );
+    }
+    let (status, body) = match (method, path.strip_prefix("/scenes")) {
+        ("GET", Some("" | "/")) => ("200 OK", scenes.join("\n") + "\n"),
+        ("POST", Some(name)) if name.starts_with('/') => {
+            let name = decode(&name[1..]);
+            if !scenes.contains(&name) {
+                ("404 Not Found", format!("unknown scene {}\n", name))
+            } else if commands.send(Command::Scene(name.clone())).is_err() {
+                ("503 Service Unavailable", "capture stopped\n".to_string())
+            } else {
+                ("200 OK", format!("switching to {}\n", name))
+            }
+        }
+        (_, Some(_)) => ("405 Method Not Allowed", "method not allowed\n".to_string()),
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    write_response(
+        &mut stream,
+        status,
+        "text/plain; charset=utf-8",
+        body.as_bytes(),
+    )
+}
+
+// 例如 /faults/timeout?seconds=5, /faults/corrupt?frames=10, /faults/resolution?width=640&height=480.
+fn fault(kind: &str, query: &str) -> Result<Fault, String> {
+    let number = |name: &str, default: Option<f64>| -> Result<f64, String> {
+        match auth::param(query, name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("{} must be a number", name)),
+            None => default.ok_or_else(|| format!("missing {}", name)),
+        }
+    };
+    Ok(match kind {
+        "timeout" => Fault::Timeout {
+            seconds: number("seconds", Some(5.0))?,
+        },
+        "corrupt" => Fault::Corrupt {
+            frames: number("frames", Some(10.0))? as u32,
+        },
+        "resolution" => Fault::Resolution {
+            width: number("width", None)? as i32,
+            height: number("height", None)? as i32,
+        },
+        "disconnect" => Fault::Disconnect {
+            seconds: number("seconds", Some(10.0))?,
+        },
+        "clear" => Fault::Clear,
+        kind => return Err(format!("unknown fault {}", kind)),
+    })
+}
+
+fn print_event(kind: &str, query: &str) -> Result<PrintEvent, String> {
+    Ok(match kind {
+        "started" => PrintEvent::Started(auth::param(query, "name")),
+        "layer" => PrintEvent::Layer(match auth::param(query, "layer") {
+            Some(layer) => Some(layer.parse().map_err(|_| "layer must be a number")?),
+            None => None,
+        }),
+        "finished" => PrintEvent::Finished,
+        kind => return Err(format!("unknown printer event {}", kind)),
+    })
+}
+
+fn write_response(stream: &mut Connection, status: &str, kind: &str, body: &[u8]) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        status,
+        kind,
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    Ok(())
+}
+
+// 解码 URL 中的 %XX, 场景名称可以包含空格等字符.
+pub fn decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| text.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        net::{TcpListener, TcpStream},
+        sync::mpsc::channel,
+    };
+
+    use super::*;
+    use crate::auth::AuthConfig;
+
+    fn request(port: u16, method: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            method, path
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn decode_percent() {
+        assert_eq!(decode("Night%20Mode"), "Night Mode");
+        assert_eq!(decode("%E5%A4%9C"), "夜");
+        assert_eq!(decode("100%"), "100%");
+        assert_eq!(decode("%zz"), "%zz");
+    }
+
+    // 没有配置用户时只监听本机, 不检查身份.
+    #[test]
+    fn switch_scenes() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (sender, receiver) = channel();
+        let _listener = serve(
+            &format!("127.0.0.1:{}", port),
+            &Auth::new(&AuthConfig::default()).unwrap(),
+            vec!["Day".to_string(), "Night Mode".to_string()],
+            sender,
+            Thumbnail::default(),
+            false,
+        )
+        .unwrap();
+
+        let response = request(port, "GET", "/scenes");
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.ends_with("\r\n\r\nDay\nNight Mode\n"));
+
+        let response = request(port, "POST", "/scenes/Night%20Mode");
+        assert!(response.ends_with("switching to Night Mode\n"));
+        let Ok(Command::Scene(name)) = receiver.try_recv() else {
+            panic!("no scene command");
+        };
+        assert_eq!(name, "Night Mode");
+
+        assert!(request(port, "POST", "/scenes/Dusk").starts_with("HTTP/1.1 404"));
+        assert!(request(port, "DELETE", "/scenes").starts_with("HTTP/1.1 405"));
+        assert!(request(port, "GET", "/thumbnail.jpg").starts_with("HTTP/1.1 404"));
+        // 没有启用时不接受故障注入.
+        assert!(request(port, "POST", "/faults/timeout").starts_with("HTTP/1.1 404"));
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn fault_params() {
+        assert!(matches!(
+            fault("timeout", ""),
+            Ok(Fault::Timeout { seconds }) if seconds == 5.0
+        ));
+        assert!(matches!(
+            fault("disconnect", "seconds=2.5"),
+            Ok(Fault::Disconnect { seconds }) if seconds == 2.5
+        ));
+        assert!(matches!(
+            fault("corrupt", "frames=3"),
+            Ok(Fault::Corrupt { frames: 3 })
+        ));
+        assert!(matches!(
+            fault("resolution", "width=640&height=480"),
+            Ok(Fault::Resolution {
+                width: 640,
+                height: 480
+            })
+        ));
+        assert!(matches!(fault("clear", ""), Ok(Fault::Clear)));
+        assert_eq!(
+            fault("resolution", "width=640").unwrap_err(),
+            "missing height"
+        );
+        assert_eq!(
+            fault("timeout", "seconds=soon").unwrap_err(),
+            "seconds must be a number"
+        );
+        assert_eq!(fault("melt", "").unwrap_err(), "unknown fault melt");
+    }
+}
@@ -0,0 +1,1551 @@
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    path::Path,
+    rc::Rc,
+    sync::{mpsc::Sender, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{Local, TimeDelta};
+use slint::{
+    ComponentHandle, Model, ModelRc, PhysicalPosition, PhysicalSize, SharedString, Timer,
+    TimerMode, VecModel, Weak,
+};
+
+use crate::{
+    api,
+    audio::AudioEffect,
+    auth::Auth,
+    best_shot,
+    camera::{self, CameraConfig, Device},
+    capture::{self, Command},
+    clips, clock,
+    config::{Config, CONFIG_FILE},
+    connections,
+    controls::{self, Property},
+    crash, daily_summary,
+    day_night::Phase,
+    effects::EffectChain,
+    events::{self, Event, EventStore},
+    faults,
+    feed::VideoFeed,
+    health, jobs,
+    mdns::Advertiser,
+    measure::Tool,
+    metrics,
+    models::Models,
+    occupancy::{self, Transition},
+    palette, photo, presentation, privacy,
+    remote::RemoteServer,
+    restore::{self, SavedState},
+    retention,
+    scenes::SceneConfig,
+    session::Replay,
+    source, stats, storage,
+    stream::{self, MjpegServer},
+    threads::Shutdown,
+    throttle,
+    update::{self, Update},
+    Main, SessionReport,
+};
+
+// 桌面应用: 窗口, 采集和所有按配置启动的服务. 窗口关闭后全部停止, 同一个进程中可以
+// 再次创建和运行, 例如切换配置后重新启动.
+pub struct App {
+    config: Config,
+    replay: Option<Replay>,
+    calibrate: bool,
+}
+
+impl App {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config,
+            replay: None,
+            calibrate: false,
+        }
+    }
+
+    // 回放会话: 使用会话中保存的配置和视频, 不恢复也不保存运行状态.
+    pub fn replay(mut self, mut session: Replay) -> Self {
+        self.config = std::mem::take(&mut session.config);
+        self.replay = Some(session);
+        self
+    }
+
+    // 启动后提示点击画面中的灰色区域校准白平衡, 设置向导中选择时使用.
+    pub fn calibrate(mut self, enabled: bool) -> Self {
+        self.calibrate = enabled;
+        self
+    }
+
+    // 阻塞, 直到窗口被关闭. 返回时摄像头, 录像文件, 监听的端口和后台线程都已关闭.
+    pub fn run(self) -> Result<()> {
+        let shutdown = Shutdown::default();
+        crash::reset();
+        let result = self.run_window(&shutdown);
+        shutdown.stop();
+        result
+    }
+
+    fn run_window(self, shutdown: &Shutdown) -> Result<()> {
+        let Self {
+            config,
+            replay,
+            calibrate,
+        } = self;
+        let events = EventStore::open(&config.events_file)?;
+        stats::begin();
+        events.on_event(|event| stats::event(&event.kind));
+        let auth = Auth::new(&config.auth)?;
+        // 监听的端口在这次运行结束时释放.
+        let _metrics = match &config.metrics {
+            Some(addr) => Some(metrics::serve(addr, &auth)?),
+            None => None,
+        };
+        health::start(&config.health, events.clone(), shutdown);
+        clock::start(&config.clock, events.clone(), shutdown);
+        faults::start(&config.faults, shutdown)?;
+        retention::start(
+            &config.retention,
+            events.clone(),
+            &config.threads.workers,
+            shutdown,
+        )?;
+        throttle::configure(&config.throttle);
+        storage::start(
+            &config.storage,
+            events.clone(),
+            &config.threads.workers,
+            shutdown,
+        )?;
+        daily_summary::start(
+            &config.daily_summary,
+            &config.clips,
+            &config.events_file,
+            events.clone(),
+            &config.threads.workers,
+            shutdown,
+        )?;
+
+        let window = Main::new()?;
+        // 新窗口中的隐私快门是打开的, 上一次运行关闭的快门也要打开.
+        privacy::set_shutter(false);
+        let window_clone = window.as_weak();
+        window.set_raw_enabled(config.raw.enabled);
+        window.set_raw_exposure(config.raw.exposure as f32);
+        window.set_raw_gamma(config.raw.gamma as f32);
+        window.set_lanes_enabled(config.lanes.enabled);
+        window.set_chroma_enabled(config.chroma_key.enabled);
+        window.set_chroma_matte(config.chroma_key.matte_view);
+        window.set_chroma_spill(config.chroma_key.spill as f32);
+        window.set_chroma_edge_despill(config.chroma_key.edge_despill as f32);
+        window.set_audio_overlay_enabled(config.audio.overlay.enabled);
+        window.set_audio_effect(config.audio.overlay.effect.label().into());
+        window.set_audio_sensitivity(config.audio.overlay.sensitivity as f32);
+        window.set_speed_enabled(config.speed.enabled);
+        window.set_dwell_enabled(config.dwell.enabled);
+        window.set_occupancy_enabled(config.occupancy.enabled);
+        window.set_tracking_enabled(config.tracking.enabled);
+        window.set_detection_enabled(config.detection.enabled);
+        window.set_wildlife_enabled(config.wildlife.enabled);
+        window.set_meteor_enabled(config.meteor.enabled);
+        window.set_gauges_enabled(config.gauges.enabled);
+        window.set_reaction_enabled(config.reaction.enabled);
+        window.set_queue_enabled(config.queue.enabled);
+        window.set_parking_enabled(config.parking.enabled);
+        window.set_intrusion_enabled(config.intrusion.enabled);
+        window.set_printer_enabled(config.printer.enabled);
+        window.set_ppe_enabled(config.ppe.enabled);
+        window.set_fall_enabled(config.fall.enabled);
+        window.set_monitor_enabled(config.monitor.enabled);
+        window.set_tamper_enabled(config.tamper.enabled);
+        window.set_summary_enabled(config.summary.enabled);
+        if config.denoise.enabled {
+            window.set_denoise_strength(config.denoise.strength.clamp(0.0, 0.95) as f32);
+        }
+        window.set_photo_hotkey(config.photo.hotkey.clone().into());
+        if calibrate {
+            window.set_picking_white(true);
+            window.set_status("Click a neutral gray area to calibrate white balance".into());
+        }
+        let routes: Vec<SharedString> = config
+            .routes
+            .iter()
+            .map(|route| route.describe().into())
+            .collect();
+        window.set_routes(ModelRc::new(VecModel::from(routes)));
+        let crash_report = crash::pending(&config.crash.dir);
+        let crash_submit_url = config.crash.submit_url.clone();
+        let workers = config.threads.workers.clone();
+        if let Some(report) = &crash_report {
+            window.set_crash_report(report.display().to_string().into());
+            window.set_can_submit_crash(config.crash.submit_url.is_some());
+        }
+
+        // 回放会话时不恢复也不保存运行状态.
+        let replaying = replay.is_some();
+        let saved = if replaying {
+            None
+        } else {
+            restore::load(&config.restore)
+        };
+        if let Some(saved) = &saved {
+            window.set_restore_summary(saved.summary().into());
+        }
+        let restore_config = config.restore.clone();
+        let stats_config = config.stats.clone();
+
+        // 打开摄像头, 回放时打开会话视频
+        let uri = match &replay {
+            Some(replay) => format!("file://{}", replay.video.display()),
+            None => source::default_uri(&config),
+        };
+        let camera_config = config.camera.clone();
+        let effect_chain = EffectChain::new(&config.effects);
+        let scenes = Rc::new(config.scenes.clone());
+        let api_addr = config.api.clone();
+        let faults_enabled = config.faults.enabled;
+        let stream_config = config.stream.clone();
+        let mdns_config = config.mdns.clone();
+        let remote_config = config.remote.clone();
+        let burst_workers = config.threads.workers.clone();
+        let (clip_config, clip_workers) = (config.clips.clone(), config.threads.workers.clone());
+        let history = events::read(&config.events_file).unwrap_or_default();
+        let mut recent: VecDeque<Event> = history
+            .iter()
+            .rev()
+            .take(clip_config.recent)
+            .cloned()
+            .collect();
+        let (machines, timeline) = (
+            config.occupancy.machines.clone(),
+            config.occupancy.timeline.max(1.0),
+        );
+        let transitions: Vec<Transition> =
+            history.iter().filter_map(Transition::from_event).collect();
+        let photo_config = config.photo.clone();
+        let models = Arc::new(Models::new(&config.models_dir, &config.models));
+        let feed = Rc::new(VideoFeed::start(
+            config,
+            &uri,
+            events,
+            window.as_weak(),
+            replay,
+        )?);
+        let fps = feed.fps();
+        let command_sender = feed.commands();
+        let _api = match &api_addr {
+            Some(addr) => {
+                let names = scenes.iter().map(|scene| scene.name.clone()).collect();
+                Some(api::serve(
+                    addr,
+                    &auth,
+                    names,
+                    command_sender.clone(),
+                    feed.thumbnail(),
+                    faults_enabled,
+                )?)
+            }
+            None => None,
+        };
+        if let Some(addr) = &stream_config.addr {
+            let weak = window.as_weak();
+            let mut server = MjpegServer::start(addr, &stream_config, &auth, move |clients| {
+                let _ = weak
+                    .upgrade_in_event_loop(move |window| window.set_stream_clients(clients as i32));
+            })?;
+            window.set_stream_clients(0);
+            window.set_monitor_link(stream::link(auth.scheme(), addr).into());
+            let weak = window.as_weak();
+            let mut shown = Instant::now();
+            feed.on_frame(move |frame| {
+                server.push(frame);
+                // 每秒更新一次推流码率.
+                if shown.elapsed() >= Duration::from_secs(1) {
+                    shown = Instant::now();
+                    let text = server.bitrate().unwrap_or_default();
+                    let _ = weak.upgrade_in_event_loop(move |window| {
+                        window.set_stream_bitrate(text.into())
+                    });
+                }
+            });
+            println!("Streaming MJPEG on {}://{}/", auth.scheme(), addr);
+        }
+        if let Some(addr) = &remote_config.addr {
+            let names = scenes.iter().map(|scene| scene.name.clone()).collect();
+            let weak = window.as_weak();
+            let mut server = RemoteServer::start(
+                addr,
+                &remote_config,
+                &auth,
+                names,
+                command_sender.clone(),
+                feed.shared_active(),
+                move |clients| {
+                    let _ = weak.upgrade_in_event_loop(move |window| {
+                        window.set_remote_clients(clients as i32)
+                    });
+                },
+            )?;
+            feed.on_frame(move |frame| server.push(frame));
+            println!(
+                "Remote control on {}://{}/",
+                auth.scheme().replace("http", "ws"),
+                addr
+            );
+        }
+        // 退出时被丢弃, 其他设备收到下线通知.
+        let _advertiser = Advertiser::start(
+            &mdns_config,
+            api_addr.as_deref(),
+            stream_config.addr.as_deref(),
+            remote_config.addr.as_deref(),
+            auth.scheme() == "https",
+        )?;
+
+        let timer = Timer::default();
+        timer.start(
+            TimerMode::Repeated,
+            Duration::from_secs_f32(1. / (fps + 10.0) as f32), // fps + 10  是加快 slint 显示图片的频率, 显示的视频更流畅
+            move || {
+                if let Some(window) = window_clone.upgrade() {
+                    window.set_frame(window.get_frame() + 1);
+                }
+            },
+        );
+
+        // 连接面板每秒刷新, 重新连接按钮按刷新时的顺序找到连接.
+        let names = Rc::new(RefCell::new(Vec::new()));
+        let connections_timer = Timer::default();
+        let (weak, list) = (window.as_weak(), names.clone());
+        connections_timer.start(TimerMode::Repeated, Duration::from_secs(1), move || {
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            let connections = connections::list();
+            let lines: Vec<SharedString> = connections
+                .iter()
+                .map(|(name, connection)| connection.describe(name).into())
+                .collect();
+            let reconnectable: Vec<bool> = connections
+                .iter()
+                .map(|(_, connection)| connection.reconnectable())
+                .collect();
+            window.set_connections(ModelRc::new(VecModel::from(lines)));
+            window.set_connection_reconnectable(ModelRc::new(VecModel::from(reconnectable)));
+            *list.borrow_mut() = connections.into_iter().map(|(name, _)| name).collect();
+        });
+        window.on_reconnect(move |index| {
+            if let Some(name) = names.borrow().get(index as usize) {
+                connections::request_reconnect(name);
+            }
+        });
+
+        // 任务面板每秒刷新, 取消按钮按刷新时的顺序找到任务.
+        let ids = Rc::new(RefCell::new(Vec::new()));
+        let jobs_timer = Timer::default();
+        let (weak, list) = (window.as_weak(), ids.clone());
+        jobs_timer.start(TimerMode::Repeated, Duration::from_secs(1), move || {
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            let jobs = jobs::list();
+            let lines: Vec<SharedString> = jobs.iter().map(|job| job.describe().into()).collect();
+            let cancellable: Vec<bool> = jobs.iter().map(jobs::Job::cancellable).collect();
+            window.set_jobs(ModelRc::new(VecModel::from(lines)));
+            window.set_job_cancellable(ModelRc::new(VecModel::from(cancellable)));
+            *list.borrow_mut() = jobs.into_iter().map(|job| job.id).collect();
+        });
+        window.on_cancel_job(move |index| {
+            if let Some(&id) = ids.borrow().get(index as usize) {
+                jobs::cancel(id);
+            }
+        });
+        window.on_clear_jobs(jobs::clear);
+
+        // 连拍选择窗口关闭时保存选中的照片, 丢弃时不写入任何文件.
+        let weak = window.as_weak();
+        window.on_burst_choose(move |choice| {
+            let photos = match choice {
+                -2 => {
+                    best_shot::take(None);
+                    return;
+                }
+                -1 => best_shot::take(None),
+                index => best_shot::take(Some(index as usize)),
+            };
+            if photos.is_empty() {
+                return;
+            }
+            let (config, models) = (photo_config.clone(), models.clone());
+            jobs::spawn(&weak, "Saving burst", &burst_workers, move |job| {
+                photo::save(&config, &models, photos, job)
+            });
+        });
+
+        // 最近的事件列在控制面板中, 新的在前, 可以截取事件前后的录像片段.
+        let rows = |recent: &VecDeque<Event>| -> Vec<SharedString> {
+            recent
+                .iter()
+                .map(|event| {
+                    format!("{} {}", event.time.format("%m-%d %H:%M:%S"), event.kind).into()
+                })
+                .collect()
+        };
+        window.set_event_rows(ModelRc::new(VecModel::from(rows(&recent))));
+        let limit = clip_config.recent;
+        let recent = Arc::new(Mutex::new(recent));
+        let (weak, list) = (window.as_weak(), recent.clone());
+        feed.on_event(move |event| {
+            let mut list = list.lock().unwrap();
+            list.push_front(event.clone());
+            list.truncate(limit);
+            let lines = rows(&list);
+            let _ = weak.upgrade_in_event_loop(move |window| {
+                window.set_event_rows(ModelRc::new(VecModel::from(lines)));
+            });
+        });
+        // 状态机的时间线每 5 秒向左移动一次, 切换来自 state 事件.
+        let transitions = Arc::new(Mutex::new(transitions));
+        let list = transitions.clone();
+        feed.on_event(move |event| {
+            if let Some(transition) = Transition::from_event(event) {
+                list.lock().unwrap().push(transition);
+            }
+        });
+        let states_timer = Timer::default();
+        let weak = window.as_weak();
+        let refresh = move || {
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            if machines.is_empty() {
+                return;
+            }
+            let now = Local::now();
+            let mut list = transitions.lock().unwrap();
+            occupancy::prune(
+                &mut list,
+                now - TimeDelta::milliseconds((timeline * 1000.0) as i64),
+            );
+            let (rows, spans) = occupancy::timeline(&list, &machines, now, timeline);
+            let rows: Vec<SharedString> = rows.into_iter().map(Into::into).collect();
+            window.set_state_rows(ModelRc::new(VecModel::from(rows)));
+            window.set_state_span_rows(ModelRc::new(VecModel::from(
+                spans.iter().map(|span| span.0 as i32).collect::<Vec<_>>(),
+            )));
+            window.set_state_span_starts(ModelRc::new(VecModel::from(
+                spans.iter().map(|span| span.1 as f32).collect::<Vec<_>>(),
+            )));
+            window.set_state_span_ends(ModelRc::new(VecModel::from(
+                spans.iter().map(|span| span.2 as f32).collect::<Vec<_>>(),
+            )));
+        };
+        refresh();
+        states_timer.start(TimerMode::Repeated, Duration::from_secs(5), refresh);
+
+        let weak = window.as_weak();
+        window.on_export_clip(move |index| {
+            let Some(event) = recent.lock().unwrap().get(index as usize).cloned() else {
+                return;
+            };
+            let config = clip_config.clone();
+            jobs::spawn(&weak, "Clip export", &clip_workers, move |job| {
+                clips::export(&config, &event, job)
+            });
+        });
+
+        // 枚举可能需要打开摄像头, 在后台线程中进行.
+        let cameras = Arc::new(Mutex::new(Vec::new()));
+        list_cameras(&window.as_weak(), &cameras, &camera_config, &uri);
+        let (weak, list, active) = (window.as_weak(), cameras.clone(), feed.clone());
+        window.on_refresh_cameras(move || {
+            let current = active.active().source;
+            list_cameras(&weak, &list, &camera_config, &current);
+        });
+        let (sender, weak) = (command_sender.clone(), window.as_weak());
+        window.on_camera_mode(move |resolution, fps| {
+            let mode = controls::parse_resolution(&resolution)
+                .zip(fps.trim().parse::<f64>().ok().filter(|fps| *fps > 0.0));
+            match mode {
+                Some(((width, height), fps)) => {
+                    let _ = sender.send(Command::CameraMode(width, height, fps));
+                }
+                None => capture::set_status(
+                    &weak,
+                    format!("Invalid camera mode {} {}", resolution, fps),
+                ),
+            }
+        });
+        let sender = command_sender.clone();
+        window.on_camera_control(move |index, value| {
+            if let Some(&property) = Property::ALL.get(index as usize) {
+                let _ = sender.send(Command::CameraControl(property, value as f64));
+            }
+        });
+        let sender = command_sender.clone();
+        window.on_day_night(move |mode| {
+            let phase = match mode {
+                1 => Some(Phase::Day),
+                2 => Some(Phase::Night),
+                _ => None,
+            };
+            let _ = sender.send(Command::DayNight(phase));
+        });
+        let sender = command_sender.clone();
+        window.on_exclusion_draw(move |drawing| {
+            let _ = sender.send(Command::ExclusionDraw(drawing));
+        });
+        let sender = command_sender.clone();
+        window.on_exclusion_click(move |x, y| {
+            let _ = sender.send(Command::ExclusionClick(x, y));
+        });
+        let sender = command_sender.clone();
+        window.on_clear_exclusions(move || {
+            let _ = sender.send(Command::ClearExclusions);
+        });
+        window.set_source_uri(uri.clone().into());
+        let sender = command_sender.clone();
+        window.on_open_source(move |uri| {
+            let uri = uri.trim();
+            if !uri.is_empty() {
+                let _ = sender.send(Command::SetSource(uri.to_string()));
+            }
+        });
+        let sender = command_sender.clone();
+        window.on_playback_pause(move |paused| {
+            let command = if paused {
+                Command::PlaybackPause
+            } else {
+                Command::PlaybackResume
+            };
+            let _ = sender.send(command);
+        });
+        let sender = command_sender.clone();
+        window.on_playback_seek(move |fraction| {
+            let _ = sender.send(Command::PlaybackSeek(fraction as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_playback_event(move |forward| {
+            let _ = sender.send(Command::PlaybackEvent(forward));
+        });
+        let sender = command_sender.clone();
+        window.on_playback_skip_quiet(move |enabled| {
+            let _ = sender.send(Command::PlaybackSkipQuiet(enabled));
+        });
+        let (sender, list) = (command_sender.clone(), cameras.clone());
+        window.on_select_camera(move |index| {
+            let Some(device) = list.lock().unwrap().get(index as usize).cloned() else {
+                return;
+            };
+            let _ = sender.send(Command::SetSource(format!("camera://{}", device.index)));
+        });
+
+        // 效果的顺序和参数保存在界面线程, 每次改变时把启用的效果发送给采集线程.
+        let (kernel, low, high) = effect_chain.params();
+        window.set_blur_kernel(kernel as f32);
+        window.set_edge_low(low as f32);
+        window.set_edge_high(high as f32);
+        show_effects(&window, &effect_chain);
+        let effect_chain = Rc::new(RefCell::new(effect_chain));
+        let (sender, chain, weak) = (
+            command_sender.clone(),
+            effect_chain.clone(),
+            window.as_weak(),
+        );
+        window.on_effect_toggled(move |index, enabled| {
+            let mut chain = chain.borrow_mut();
+            chain.toggle(index as usize, enabled);
+            update_effects(&weak, &chain, &sender);
+        });
+        let (sender, chain, weak) = (
+            command_sender.clone(),
+            effect_chain.clone(),
+            window.as_weak(),
+        );
+        window.on_effect_moved(move |index, delta| {
+            let mut chain = chain.borrow_mut();
+            chain.move_by(index as usize, delta);
+            update_effects(&weak, &chain, &sender);
+        });
+        let (sender, chain, weak) = (
+            command_sender.clone(),
+            effect_chain.clone(),
+            window.as_weak(),
+        );
+        window.on_effect_params(move |kernel, low, high| {
+            let mut chain = chain.borrow_mut();
+            chain.set_blur(kernel);
+            chain.set_edges(low as f64, high as f64);
+            update_effects(&weak, &chain, &sender);
+        });
+
+        // 场景可以从控制面板, 热键和控制接口切换, 切换完成后由采集线程通知界面.
+        let names: Vec<SharedString> = scenes
+            .iter()
+            .map(|scene| scene.name.clone().into())
+            .collect();
+        window.set_scenes(ModelRc::new(VecModel::from(names)));
+        let (sender, list) = (command_sender.clone(), scenes.clone());
+        window.on_select_scene(move |index| {
+            if let Some(scene) = list.get(index as usize) {
+                let _ = sender.send(Command::Scene(scene.name.clone()));
+            }
+        });
+        let (sender, list) = (command_sender.clone(), scenes.clone());
+        window.on_scene_key(move |text| {
+            let Some(scene) = list
+                .iter()
+                .find(|scene| scene.hotkey.as_deref() == Some(text.as_str()))
+            else {
+                return false;
+            };
+            let _ = sender.send(Command::Scene(scene.name.clone()));
+            true
+        });
+        let weak = window.as_weak();
+        window.on_scene_changed(move |index| {
+            let (Some(window), Some(scene)) = (weak.upgrade(), scenes.get(index as usize)) else {
+                return;
+            };
+            show_scene(&window, scene, &effect_chain, &cameras);
+        });
+
+        let sender = command_sender.clone();
+        window.on_calibrate_color(move || {
+            let _ = sender.send(Command::CalibrateColor);
+        });
+        let sender = command_sender.clone();
+        window.on_reset_color(move || {
+            let _ = sender.send(Command::ResetColor);
+        });
+
+        let sender = command_sender.clone();
+        window.on_white_balance(move |x, y| {
+            let _ = sender.send(Command::WhiteBalance(x, y));
+        });
+        let sender = command_sender.clone();
+        window.on_reset_white_balance(move || {
+            let _ = sender.send(Command::ResetWhiteBalance);
+        });
+
+        let sender = command_sender.clone();
+        window.on_calibrate_hot_pixels(move || {
+            let _ = sender.send(Command::CalibrateHotPixels);
+        });
+        let sender = command_sender.clone();
+        window.on_reset_hot_pixels(move || {
+            let _ = sender.send(Command::ResetHotPixels);
+        });
+
+        let sender = command_sender.clone();
+        window.on_capture_flat_field(move || {
+            let _ = sender.send(Command::CaptureFlatField);
+        });
+        let sender = command_sender.clone();
+        window.on_toggle_flat_field(move || {
+            let _ = sender.send(Command::ToggleFlatField);
+        });
+
+        let sender = command_sender.clone();
+        window.on_measure_tool(move |tool, reference| {
+            let reference = reference.parse().unwrap_or(0.0);
+            let _ = sender.send(Command::MeasureTool(Tool::parse(&tool), reference));
+        });
+        let sender = command_sender.clone();
+        window.on_measure_click(move |x, y| {
+            let _ = sender.send(Command::MeasureClick(x, y));
+        });
+        window.on_bubble_moved(presentation::move_bubble);
+        window.on_bubble_resized(presentation::resize_bubble);
+        let sender = command_sender.clone();
+        window.on_close_area(move || {
+            let _ = sender.send(Command::CloseArea);
+        });
+        let sender = command_sender.clone();
+        window.on_export_measurements(move || {
+            let _ = sender.send(Command::ExportMeasurements);
+        });
+
+        let sender = command_sender.clone();
+        window.on_start_panorama(move || {
+            let _ = sender.send(Command::StartPanorama);
+        });
+        let sender = command_sender.clone();
+        window.on_save_panorama(move || {
+            let _ = sender.send(Command::SavePanorama);
+        });
+        let sender = command_sender.clone();
+        window.on_cancel_panorama(move || {
+            let _ = sender.send(Command::CancelPanorama);
+        });
+
+        let sender = command_sender.clone();
+        window.on_high_quality_still(move || {
+            let _ = sender.send(Command::HighQualityStill);
+        });
+        let sender = command_sender.clone();
+        window.on_onion_hold(move || {
+            let _ = sender.send(Command::OnionHold);
+        });
+        let sender = command_sender.clone();
+        window.on_onion_load(move |path| {
+            let _ = sender.send(Command::OnionLoad(path.to_string()));
+        });
+        let sender = command_sender.clone();
+        window.on_onion_opacity(move |opacity| {
+            let _ = sender.send(Command::OnionOpacity(opacity as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_onion_clear(move || {
+            let _ = sender.send(Command::OnionClear);
+        });
+        let sender = command_sender.clone();
+        window.on_projection_pick(move || {
+            let _ = sender.send(Command::ProjectionPick);
+        });
+        let sender = command_sender.clone();
+        window.on_projection_click(move |x, y| {
+            let _ = sender.send(Command::ProjectionClick(x, y));
+        });
+        let sender = command_sender.clone();
+        window.on_projection_content(move |path| {
+            let _ = sender.send(Command::ProjectionContent(path.to_string()));
+        });
+        let sender = command_sender.clone();
+        window.on_projection_clear(move || {
+            let _ = sender.send(Command::ProjectionClear);
+        });
+        let sender = command_sender.clone();
+        window.on_stop_motion_capture(move || {
+            let _ = sender.send(Command::StopMotionCapture);
+        });
+        let sender = command_sender.clone();
+        window.on_stop_motion_delete(move || {
+            let _ = sender.send(Command::StopMotionDelete);
+        });
+        let sender = command_sender.clone();
+        window.on_stop_motion_play(move |playing, fps| {
+            let _ = sender.send(Command::StopMotionPlay(playing, fps as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_stop_motion_export(move |fps| {
+            let _ = sender.send(Command::StopMotionExport(fps as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_timeshift_pause(move || {
+            let _ = sender.send(Command::TimeShiftPause);
+        });
+        let sender = command_sender.clone();
+        window.on_timeshift_live(move || {
+            let _ = sender.send(Command::TimeShiftLive);
+        });
+        let sender = command_sender.clone();
+        window.on_timeshift_step(move |frames| {
+            let _ = sender.send(Command::TimeShiftStep(frames as i64));
+        });
+        let sender = command_sender.clone();
+        window.on_timeshift_seek(move |fraction| {
+            let _ = sender.send(Command::TimeShiftSeek(fraction as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_timeshift_mark(move || {
+            let _ = sender.send(Command::TimeShiftMark);
+        });
+        let sender = command_sender.clone();
+        window.on_timeshift_clear_loop(move || {
+            let _ = sender.send(Command::TimeShiftClearLoop);
+        });
+        let sender = command_sender.clone();
+        window.on_timeshift_speed(move |speed| {
+            let _ = sender.send(Command::TimeShiftSpeed(speed as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_record(move |enabled| {
+            let _ = sender.send(Command::Record(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_high_speed(move |enabled| {
+            let _ = sender.send(Command::HighSpeed(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_denoise(move |strength| {
+            let _ = sender.send(Command::Denoise(strength as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_take_photo(move |burst, timer| {
+            let _ = sender.send(Command::TakePhoto(burst, timer));
+        });
+
+        let sender = command_sender.clone();
+        window.on_depth_mapping(move |exposure, gamma| {
+            let _ = sender.send(Command::DepthMapping(exposure as f64, gamma as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_save_raw_still(move || {
+            let _ = sender.send(Command::SaveRawStill);
+        });
+
+        let sender = command_sender.clone();
+        window.on_capture_reference(move || {
+            let _ = sender.send(Command::CaptureReference);
+        });
+        let sender = command_sender.clone();
+        window.on_clear_reference(move || {
+            let _ = sender.send(Command::ClearReference);
+        });
+        let sender = command_sender.clone();
+        window.on_change_params(move |blur, threshold| {
+            let _ = sender.send(Command::ChangeParams(blur, threshold as f64));
+        });
+
+        let sender = command_sender.clone();
+        window.on_lanes(move |enabled| {
+            let _ = sender.send(Command::Lanes(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_chroma_key(move |enabled| {
+            let _ = sender.send(Command::ChromaKey(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_chroma_matte_view(move |enabled| {
+            let _ = sender.send(Command::ChromaMatte(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_chroma_despill(move |spill, edge| {
+            let _ = sender.send(Command::ChromaSpill(spill as f64, edge as f64));
+        });
+        let sender = command_sender.clone();
+        window.on_audio_overlay(move |enabled, effect, sensitivity| {
+            let effect = AudioEffect::from_label(&effect);
+            let _ = sender.send(Command::AudioOverlay(enabled, effect, sensitivity as f64));
+        });
+
+        let sender = command_sender.clone();
+        window.on_speed(move |enabled| {
+            let _ = sender.send(Command::Speed(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_speed_calibrate(move |meters| {
+            let _ = sender.send(Command::SpeedCalibrate(meters.parse().unwrap_or(1.0)));
+        });
+        let sender = command_sender.clone();
+        window.on_speed_click(move |x, y| {
+            let _ = sender.send(Command::SpeedClick(x, y));
+        });
+
+        let sender = command_sender.clone();
+        window.on_dwell(move |enabled| {
+            let _ = sender.send(Command::Dwell(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_occupancy(move |enabled| {
+            let _ = sender.send(Command::Occupancy(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_tracking(move |enabled| {
+            let _ = sender.send(Command::Tracking(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_detection(move |enabled| {
+            let _ = sender.send(Command::Detection(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_wildlife(move |enabled| {
+            let _ = sender.send(Command::Wildlife(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_meteor(move |enabled| {
+            let _ = sender.send(Command::Meteor(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_gauges(move |enabled| {
+            let _ = sender.send(Command::Gauges(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_reaction(move |enabled| {
+            let _ = sender.send(Command::Reaction(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_printer(move |enabled| {
+            let _ = sender.send(Command::Printer(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_ppe(move |enabled| {
+            let _ = sender.send(Command::Ppe(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_fall(move |enabled| {
+            let _ = sender.send(Command::Fall(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_fall_acknowledge(move || {
+            let _ = sender.send(Command::FallAcknowledge);
+        });
+        let sender = command_sender.clone();
+        window.on_queue(move |enabled| {
+            let _ = sender.send(Command::Queue(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_queue_draw(move |drawing| {
+            let _ = sender.send(Command::QueueDraw(drawing));
+        });
+        let sender = command_sender.clone();
+        window.on_queue_click(move |x, y| {
+            let _ = sender.send(Command::QueueClick(x, y));
+        });
+        let sender = command_sender.clone();
+        window.on_parking(move |enabled| {
+            let _ = sender.send(Command::Parking(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_intrusion(move |enabled| {
+            let _ = sender.send(Command::Intrusion(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_reaction_pick(move || {
+            let _ = sender.send(Command::ReactionPick);
+        });
+        let sender = command_sender.clone();
+        window.on_reaction_click(move |x, y| {
+            let _ = sender.send(Command::ReactionClick(x, y));
+        });
+
+        window.on_privacy_shutter_changed(privacy::set_shutter);
+
+        let sender = command_sender.clone();
+        window.on_monitor(move |enabled| {
+            let _ = sender.send(Command::Monitor(enabled));
+        });
+        let sender = command_sender.clone();
+        window.on_tamper(move |enabled| {
+            let _ = sender.send(Command::Tamper(enabled));
+        });
+
+        let sender = command_sender.clone();
+        window.on_export_trajectories(move || {
+            let _ = sender.send(Command::ExportTrajectories);
+        });
+
+        let sender = command_sender.clone();
+        window.on_clear_trajectories(move || {
+            let _ = sender.send(Command::ClearTrajectories);
+        });
+
+        let sender = command_sender.clone();
+        window.on_export_summary(move || {
+            let _ = sender.send(Command::SummaryExport);
+        });
+
+        let sender = command_sender.clone();
+        window.on_reset_summary(move || {
+            let _ = sender.send(Command::SummaryReset);
+        });
+
+        if let Some(report) = crash_report {
+            let path = report.clone();
+            window.on_open_crash_report(move || {
+                if let Err(err) = crash::open(&path) {
+                    eprintln!("{:?}", err);
+                }
+            });
+            let path = report.clone();
+            let url = crash_submit_url;
+            let weak = window.as_weak();
+            window.on_submit_crash_report(move || {
+                let (path, Some(url)) = (path.clone(), url.clone()) else {
+                    return;
+                };
+                jobs::spawn(&weak, "Crash report upload", &workers, move |_| {
+                    crash::submit(&url, &path)?;
+                    Ok("Crash report submitted, thank you".to_string())
+                });
+            });
+            let weak = window.as_weak();
+            window.on_dismiss_crash_report(move || {
+                if let Err(err) = crash::mark_seen(&report) {
+                    eprintln!("{:?}", err);
+                }
+                if let Some(window) = weak.upgrade() {
+                    window.set_crash_report("".into());
+                }
+            });
+        }
+
+        if let Some(saved) = saved {
+            let weak = window.as_weak();
+            let restore = feed.clone();
+            window.on_restore_session(move || {
+                let Some(window) = weak.upgrade() else {
+                    return;
+                };
+                if saved.source != restore.active().source {
+                    restore.set_source(&saved.source);
+                }
+                // 裁剪只能由场景设置, 随场景一起恢复.
+                let stages: Vec<&str> = saved
+                    .pipeline
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|&stage| stage != "crop")
+                    .collect();
+                restore.set_pipeline(&stages);
+                if let Some(scene) = &saved.scene {
+                    restore.set_scene(scene);
+                }
+                for stage in capture::TOGGLED_STAGES {
+                    capture::set_stage_enabled(&window, stage, stages.contains(&stage));
+                }
+                if let Some((x, y)) = saved.position {
+                    window.window().set_position(PhysicalPosition::new(x, y));
+                }
+                if let Some((width, height)) = saved.size {
+                    window.window().set_size(PhysicalSize::new(width, height));
+                }
+                window.set_restore_summary("".into());
+            });
+        }
+        let weak = window.as_weak();
+        window.on_dismiss_restore(move || {
+            if let Some(window) = weak.upgrade() {
+                window.set_restore_summary("".into());
+            }
+        });
+
+        // 新版本提示, 下载和打开发布页面使用最近一次找到的版本.
+        let pending: Arc<Mutex<Option<Update>>> = Arc::default();
+        let (weak, found) = (window.as_weak(), pending.clone());
+        update::start(&config.update, shutdown, move |update| {
+            let version = update.version.clone();
+            let changelog = update.changelog.clone();
+            let downloadable = update.asset.is_some() && update.checksum.is_some();
+            *found.lock().unwrap() = Some(update);
+            let _ = weak.upgrade_in_event_loop(move |window| {
+                window.set_update_version(version.into());
+                window.set_update_changelog(changelog.into());
+                window.set_update_downloadable(downloadable);
+            });
+        })?;
+        let (weak, found) = (window.as_weak(), pending.clone());
+        let update_config = config.update.clone();
+        let update_workers = config.threads.workers.clone();
+        window.on_download_update(move || {
+            let assets = found
+                .lock()
+                .unwrap()
+                .as_ref()
+                .and_then(|update| Some((update.asset.clone()?, update.checksum.clone()?)));
+            let Some((asset, checksum)) = assets else {
+                return;
+            };
+            let config = update_config.clone();
+            jobs::spawn(&weak, "Update download", &update_workers, move |job| {
+                let path = update::download(&config, &asset, &checksum, job)?;
+                Ok(format!(
+                    "Downloaded {}, replace the installed program with it to upgrade",
+                    path.display()
+                ))
+            });
+        });
+        let (weak, found) = (window.as_weak(), pending);
+        window.on_open_update_page(move || {
+            if let Some(update) = &*found.lock().unwrap() {
+                if let Err(err) = update::open_page(&update.page) {
+                    capture::set_status(&weak, format!("Open release page failed: {}", err));
+                }
+            }
+        });
+        let weak = window.as_weak();
+        window.on_dismiss_update(move || {
+            if let Some(window) = weak.upgrade() {
+                window.set_update_version("".into());
+            }
+        });
+
+        // 命令面板: 每次输入时按当前状态重新生成操作列表, 只显示得分最高的几个.
+        let shown: Rc<RefCell<Vec<Action>>> = Rc::default();
+        let (weak, results) = (window.as_weak(), shown.clone());
+        window.on_palette_query(move |query| {
+            let Some(window) = weak.upgrade() else {
+                return;
+            };
+            let actions = palette_actions(&window);
+            let titles: Vec<String> = actions.iter().map(|(title, _)| title.clone()).collect();
+            let matches: Vec<usize> = palette::search(&query, &titles)
+                .into_iter()
+                .take(PALETTE_RESULTS)
+                .collect();
+            let items: Vec<SharedString> = matches
+                .iter()
+                .map(|&index| titles[index].clone().into())
+                .collect();
+            window.set_palette_items(ModelRc::new(VecModel::from(items)));
+            *results.borrow_mut() = matches
+                .iter()
+                .map(|&index| actions[index].1.clone())
+                .collect();
+        });
+        let weak = window.as_weak();
+        window.on_palette_run(move |index| {
+            let action = shown.borrow().get(index as usize).cloned();
+            if let (Some(window), Some(action)) = (weak.upgrade(), action) {
+                action(&window);
+            }
+        });
+
+        let render = feed.clone();
+        window.on_render_image(move |_frame| render.image());
+        // 阻塞, 直到窗口被关闭.
+        window.run()?;
+
+        // 关闭摄像头和文件.
+        let position = window.window().position();
+        let size = window.window().size();
+        let result = feed.stop();
+        println!("Camera Stopped And File Closed {:?}", result);
+        if !replaying {
+            let active = feed.active();
+            let state = SavedState {
+                source: active.source,
+                pipeline: active.pipeline,
+                scene: active.scene,
+                position: Some((position.x, position.y)),
+                size: Some((size.width, size.height)),
+            };
+            if let Err(err) = restore::save(&restore_config, &state) {
+                eprintln!("{:?}", err);
+            }
+        }
+        if let Some(session) = stats::end().filter(|_| stats_config.enabled) {
+            let report = session.report("Session", Local::now());
+            println!("{}", report);
+            if let Err(err) = stats::append(&stats_config, &report) {
+                eprintln!("session log: {:?}", err);
+            }
+            if stats_config.dialog && !replaying {
+                if let Err(err) = session_report(&report) {
+                    eprintln!("session summary: {:?}", err);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// 退出时的统计对话框, 关闭后返回.
+fn session_report(report: &str) -> Result<()> {
+    let dialog = SessionReport::new()?;
+    dialog.set_report(report.trim_end().into());
+    dialog.on_close(|| {
+        let _ = slint::quit_event_loop();
+    });
+    dialog.run()?;
+    Ok(())
+}
+
+// 在后台线程中枚举摄像头, 更新控制面板中的列表并选中 uri 对应的摄像头.
+fn list_cameras(
+    window: &Weak<Main>,
+    list: &Arc<Mutex<Vec<Device>>>,
+    config: &CameraConfig,
+    uri: &str,
+) {
+    let (window, list, config, uri) = (
+        window.clone(),
+        list.clone(),
+        config.clone(),
+        uri.to_string(),
+    );
+    thread::spawn(move || {
+        let devices = camera::enumerate(&config);
+        let current = uri
+            .strip_prefix("camera://")
+            .and_then(|selector| match selector {
+                "" => camera::select(&config).ok(),
+                selector => camera::find(&config, selector).ok(),
+            });
+        let index = current
+            .and_then(|current| {
+                devices
+                    .iter()
+                    .position(|device| device.index == current.index)
+            })
+            .map_or(-1, |index| index as i32);
+        let names: Vec<String> = devices.iter().map(ToString::to_string).collect();
+        *list.lock().unwrap() = devices;
+        let _ = window.upgrade_in_event_loop(move |window| {
+            let names: Vec<SharedString> = names.into_iter().map(Into::into).collect();
+            window.set_cameras(ModelRc::new(VecModel::from(names)));
+            window.set_camera_index(index);
+        });
+    });
+}
+
+// 场景切换后效果列表按场景中的效果重新排列, 摄像头列表选中场景的来源.
+fn show_scene(
+    window: &Main,
+    scene: &SceneConfig,
+    chain: &RefCell<EffectChain>,
+    cameras: &Mutex<Vec<Device>>,
+) {
+    let mut chain = chain.borrow_mut();
+    *chain = EffectChain::new(&scene.effects);
+    show_effects(window, &chain);
+    let (kernel, low, high) = chain.params();
+    window.set_blur_kernel(kernel as f32);
+    window.set_edge_low(low as f32);
+    window.set_edge_high(high as f32);
+    if let Some(uri) = &scene.source {
+        let index = uri
+            .strip_prefix("camera://")
+            .and_then(|index| index.parse::<i32>().ok())
+            .and_then(|index| {
+                cameras
+                    .lock()
+                    .unwrap()
+                    .iter()
+                    .position(|device| device.index == index)
+            })
+            .map_or(-1, |index| index as i32);
+        window.set_camera_index(index);
+    }
+}
+
+fn show_effects(window: &Main, chain: &EffectChain) {
+    let labels: Vec<SharedString> = chain.labels().into_iter().map(Into::into).collect();
+    window.set_effect_names(ModelRc::new(VecModel::from(labels)));
+    window.set_effect_enabled(ModelRc::new(VecModel::from(chain.enabled())));
+}
+
+fn update_effects(window: &Weak<Main>, chain: &EffectChain, sender: &Sender<Command>) {
+    if let Some(window) = window.upgrade() {
+        show_effects(&window, chain);
+    }
+    let _ = sender.send(Command::Effects(chain.effects()));
+}
+
+// 命令面板最多显示的结果数.
+const PALETTE_RESULTS: usize = 12;
+
+type Action = Rc<dyn Fn(&Main)>;
+
+// 命令面板中的所有操作, 与控制面板上的按钮调用同一个回调, 名称按当前状态生成.
+fn palette_actions(window: &Main) -> Vec<(String, Action)> {
+    let mut actions: Vec<(String, Action)> = Vec::new();
+    let mut add = |title: String, action: Action| actions.push((title, action));
+    let toggle = |name: &str, enabled: bool| {
+        format!("{} {}", if enabled { "Disable" } else { "Enable" }, name)
+    };
+
+    let recording = window.get_recording();
+    add(
+        if recording {
+            "Stop recording"
+        } else {
+            "Start recording"
+        }
+        .to_string(),
+        Rc::new(move |window: &Main| {
+            window.set_recording(!recording);
+            window.invoke_record(!recording);
+        }),
+    );
+    let high_speed = window.get_high_speed_enabled();
+    add(
+        toggle("high speed (slow motion)", high_speed),
+        Rc::new(move |window: &Main| {
+            window.set_high_speed_enabled(!high_speed);
+            window.invoke_high_speed(!high_speed);
+        }),
+    );
+    let shutter = window.get_privacy_shutter();
+    add(
+        if shutter {
+            "Open privacy shutter"
+        } else {
+            "Close privacy shutter"
+        }
+        .to_string(),
+        Rc::new(move |window: &Main| {
+            window.set_privacy_shutter(!shutter);
+            window.invoke_privacy_shutter_changed(!shutter);
+        }),
+    );
+
+    // 分析和预设, 与控制面板中的复选框相同.
+    let switches: [(&str, bool, fn(&Main, bool)); 11] = [
+        ("chroma key", window.get_chroma_enabled(), |window, on| {
+            window.set_chroma_enabled(on);
+            window.invoke_chroma_key(on);
+        }),
+        (
+            "chroma key matte view",
+            window.get_chroma_matte(),
+            |window, on| {
+                window.set_chroma_matte(on);
+                window.invoke_chroma_matte_view(on);
+            },
+        ),
+        (
+            "lane detection",
+            window.get_lanes_enabled(),
+            |window, on| {
+                window.set_lanes_enabled(on);
+                window.invoke_lanes(on);
+            },
+        ),
+        (
+            "speed estimation",
+            window.get_speed_enabled(),
+            |window, on| {
+                window.set_speed_enabled(on);
+                window.invoke_speed(on);
+            },
+        ),
+        (
+            "zone dwell time",
+            window.get_dwell_enabled(),
+            |window, on| {
+                window.set_dwell_enabled(on);
+                window.invoke_dwell(on);
+            },
+        ),
+        (
+            "zone states",
+            window.get_occupancy_enabled(),
+            |window, on| {
+                window.set_occupancy_enabled(on);
+                window.invoke_occupancy(on);
+            },
+        ),
+        ("queue length", window.get_queue_enabled(), |window, on| {
+            window.set_queue_enabled(on);
+            window.invoke_queue(on);
+        }),
+        (
+            "parking spaces",
+            window.get_parking_enabled(),
+            |window, on| {
+                window.set_parking_enabled(on);
+                window.invoke_parking(on);
+            },
+        ),
+        (
+            "intrusion detection",
+            window.get_intrusion_enabled(),
+            |window, on| {
+                window.set_intrusion_enabled(on);
+                window.invoke_intrusion(on);
+            },
+        ),
+        (
+            "person tracking",
+            window.get_tracking_enabled(),
+            |window, on| {
+                window.set_tracking_enabled(on);
+                window.invoke_tracking(on);
+            },
+        ),
+        (
+            "object detection",
+            window.get_detection_enabled(),
+            |window, on| {
+                window.set_detection_enabled(on);
+                window.invoke_detection(on);
+            },
+        ),
+        (
+            "wildlife preset",
+            window.get_wildlife_enabled(),
+            |window, on| {
+                window.set_wildlife_enabled(on);
+                window.invoke_wildlife(on);
+            },
+        ),
+        (
+            "meteor detection",
+            window.get_meteor_enabled(),
+            |window, on| {
+                window.set_meteor_enabled(on);
+                window.invoke_meteor(on);
+            },
+        ),
+        (
+            "gauge reading",
+            window.get_gauges_enabled(),
+            |window, on| {
+                window.set_gauges_enabled(on);
+                window.invoke_gauges(on);
+            },
+        ),
+        (
+            "color monitor",
+            window.get_reaction_enabled(),
+            |window, on| {
+                window.set_reaction_enabled(on);
+                window.invoke_reaction(on);
+            },
+        ),
+        ("3d printer", window.get_printer_enabled(), |window, on| {
+            window.set_printer_enabled(on);
+            window.invoke_printer(on);
+        }),
+        ("safety gear", window.get_ppe_enabled(), |window, on| {
+            window.set_ppe_enabled(on);
+            window.invoke_ppe(on);
+        }),
+        ("fall detection", window.get_fall_enabled(), |window, on| {
+            window.set_fall_enabled(on);
+            window.invoke_fall(on);
+        }),
+        (
+            "monitor mode",
+            window.get_monitor_enabled(),
+            |window, on| {
+                window.set_monitor_enabled(on);
+                window.invoke_monitor(on);
+            },
+        ),
+        (
+            "tamper detection",
+            window.get_tamper_enabled(),
+            |window, on| {
+                window.set_tamper_enabled(on);
+                window.invoke_tamper(on);
+            },
+        ),
+        (
+            "audio overlay",
+            window.get_audio_overlay_enabled(),
+            |window, on| {
+                window.set_audio_overlay_enabled(on);
+                window.invoke_audio_overlay(
+                    on,
+                    window.get_audio_effect(),
+                    window.get_audio_sensitivity(),
+                );
+            },
+        ),
+    ];
+    for (name, enabled, action) in switches {
+        add(
+            toggle(name, enabled),
+            Rc::new(move |window: &Main| action(window, !enabled)),
+        );
+    }
+    for (index, (name, enabled)) in window
+        .get_effect_names()
+        .iter()
+        .zip(window.get_effect_enabled().iter())
+        .enumerate()
+    {
+        add(
+            toggle(&format!("effect {}", name), enabled),
+            Rc::new(move |window: &Main| window.invoke_effect_toggled(index as i32, !enabled)),
+        );
+    }
+
+    let commands: [(&str, fn(&Main)); 24] = [
+        ("Take photo", |window| {
+            window.invoke_take_photo(false, false)
+        }),
+        ("Take burst", |window| window.invoke_take_photo(true, false)),
+        ("Take photo with self-timer", |window| {
+            window.invoke_take_photo(false, true)
+        }),
+        ("High-quality still", |window| {
+            window.invoke_high_quality_still()
+        }),
+        ("Pause time shift", |window| window.invoke_timeshift_pause()),
+        ("Back to live view", |window| window.invoke_timeshift_live()),
+        ("Mark loop point", |window| window.invoke_timeshift_mark()),
+        ("Clear loop", |window| window.invoke_timeshift_clear_loop()),
+        ("Capture stop-motion frame", |window| {
+            window.invoke_stop_motion_capture()
+        }),
+        ("Delete last stop-motion frame", |window| {
+            window.invoke_stop_motion_delete()
+        }),
+        ("Start panorama", |window| window.invoke_start_panorama()),
+        ("Save panorama", |window| window.invoke_save_panorama()),
+        ("Cancel panorama", |window| window.invoke_cancel_panorama()),
+        ("Capture change-detection reference", |window| {
+            window.invoke_capture_reference()
+        }),
+        ("Clear change-detection reference", |window| {
+            window.invoke_clear_reference()
+        }),
+        ("Calibrate color", |window| window.invoke_calibrate_color()),
+        ("Reset color calibration", |window| {
+            window.invoke_reset_color()
+        }),
+        ("Reset white balance", |window| {
+            window.invoke_reset_white_balance()
+        }),
+        ("Calibrate hot pixels", |window| {
+            window.invoke_calibrate_hot_pixels()
+        }),
+        ("Capture flat field", |window| {
+            window.invoke_capture_flat_field()
+        }),
+        ("Toggle flat-field correction", |window| {
+            window.invoke_toggle_flat_field()
+        }),
+        ("Export trajectories", |window| {
+            window.invoke_export_trajectories()
+        }),
+        ("Refresh cameras", |window| window.invoke_refresh_cameras()),
+        ("Use the day profile", |window| {
+            window.set_day_night_mode(1);
+            window.invoke_day_night(1);
+        }),
+        ("Use the night profile", |window| {
+            window.set_day_night_mode(2);
+            window.invoke_day_night(2);
+        }),
+        ("Switch day and night automatically", |window| {
+            window.set_day_night_mode(0);
+            window.invoke_day_night(0);
+        }),
+        ("Draw glare exclusion", |window| {
+            window.set_drawing_exclusion(true);
+            window.invoke_exclusion_draw(true);
+        }),
+        ("Clear exclusions", |window| {
+            window.invoke_clear_exclusions()
+        }),
+        ("Open settings file", |window| {
+            if let Err(err) = crash::open(Path::new(CONFIG_FILE)) {
+                capture::set_status(&window.as_weak(), format!("Open settings failed: {}", err));
+            }
+        }),
+    ];
+    for (title, action) in commands {
+        add(title.to_string(), Rc::new(action));
+    }
+
+    for (index, scene) in window.get_scenes().iter().enumerate() {
+        add(
+            format!("Switch to scene {}", scene),
+            Rc::new(move |window: &Main| window.invoke_select_scene(index as i32)),
+        );
+    }
+    for (index, camera) in window.get_cameras().iter().enumerate() {
+        add(
+            format!("Switch to camera {}", camera),
+            Rc::new(move |window: &Main| window.invoke_select_camera(index as i32)),
+        );
+    }
+    actions
+}
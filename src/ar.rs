@@ -0,0 +1,349 @@
+use anyhow::{bail, Result};
+use opencv::{
+    calib3d,
+    core::{self, Mat, Point, Point2f, Point3f, Scalar, Vector, CV_64F},
+    imgproc::{self, COLOR_BGR2GRAY, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    objdetect::{self, ArucoDetector, DetectorParameters, RefineParameters},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::FrameProcessor;
+
+// 增强现实叠加: 检测 ArUco 标记, 用相机内参求出每个标记的位姿, 在标记上画坐标轴, 立方体和文字.
+// 作为 AR 原型的基础, 画面和录像中都有叠加的图形.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArConfig {
+    pub enabled: bool,
+    // 标记字典, 例如 "4x4_50", "5x5_100", "6x6_250", "original".
+    pub dictionary: String,
+    // 打印出的标记边长 (米), 决定位姿和图形的尺度.
+    pub marker_length: f64,
+    // 没有内参标定时按水平视场角 (度) 估计焦距, 忽略镜头畸变.
+    pub fov: f64,
+    // 每个标记上画的图形, 为空时在所有标记上画坐标轴.
+    pub objects: Vec<ArObject>,
+}
+
+impl Default for ArConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dictionary: "4x4_50".to_string(),
+            marker_length: 0.05,
+            fov: 60.0,
+            objects: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Shape {
+    Axes,
+    Cube,
+    // 标记上方始终朝向镜头的文字.
+    Text,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArObject {
+    // 标记 ID, 不设置时画在所有标记上.
+    pub marker: Option<i32>,
+    pub shape: Shape,
+    // 相对标记边长的大小; 文字为离开标记平面的高度.
+    #[serde(default = "default_size")]
+    pub size: f64,
+    #[serde(default)]
+    pub text: String,
+    // BGR.
+    #[serde(default = "default_color")]
+    pub color: [u8; 3],
+}
+
+fn default_size() -> f64 {
+    1.0
+}
+
+fn default_color() -> [u8; 3] {
+    [0, 200, 255]
+}
+
+// 摄像头内参, 保存在 camera profile 中, 由标定时的分辨率按比例换算到当前画面.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Intrinsics {
+    // 标定时的画面宽高.
+    pub size: [i32; 2],
+    // fx, fy, cx, cy (像素).
+    pub focal: [f64; 2],
+    pub center: [f64; 2],
+    // OpenCV 的畸变系数 k1, k2, p1, p2[, k3...].
+    #[serde(default)]
+    pub distortion: Vec<f64>,
+}
+
+impl Intrinsics {
+    // 没有标定时的估计: 主点在画面中心, 方形像素, 无畸变.
+    pub fn estimate(width: i32, height: i32, fov: f64) -> Self {
+        let focal = width as f64 / 2.0 / (fov.clamp(1.0, 179.0).to_radians() / 2.0).tan();
+        Self {
+            size: [width, height],
+            focal: [focal, focal],
+            center: [width as f64 / 2.0, height as f64 / 2.0],
+            distortion: Vec::new(),
+        }
+    }
+
+    // 换算到 width x height 的相机矩阵和畸变系数.
+    fn matrices(&self, width: i32, height: i32) -> Result<(Mat, Mat)> {
+        let sx = width as f64 / self.size[0].max(1) as f64;
+        let sy = height as f64 / self.size[1].max(1) as f64;
+        let camera = Mat::from_slice_2d(&[
+            [self.focal[0] * sx, 0.0, self.center[0] * sx],
+            [0.0, self.focal[1] * sy, self.center[1] * sy],
+            [0.0, 0.0, 1.0],
+        ])?;
+        let distortion = if self.distortion.is_empty() {
+            Mat::zeros(1, 5, CV_64F)?.to_mat()?
+        } else {
+            Mat::from_slice(&self.distortion)?.try_clone()?
+        };
+        Ok((camera, distortion))
+    }
+}
+
+fn dictionary(name: &str) -> Result<i32> {
+    Ok(match name.to_lowercase().as_str() {
+        "4x4_50" => objdetect::DICT_4X4_50,
+        "4x4_100" => objdetect::DICT_4X4_100,
+        "4x4_250" => objdetect::DICT_4X4_250,
+        "4x4_1000" => objdetect::DICT_4X4_1000,
+        "5x5_50" => objdetect::DICT_5X5_50,
+        "5x5_100" => objdetect::DICT_5X5_100,
+        "5x5_250" => objdetect::DICT_5X5_250,
+        "5x5_1000" => objdetect::DICT_5X5_1000,
+        "6x6_50" => objdetect::DICT_6X6_50,
+        "6x6_100" => objdetect::DICT_6X6_100,
+        "6x6_250" => objdetect::DICT_6X6_250,
+        "6x6_1000" => objdetect::DICT_6X6_1000,
+        "original" => objdetect::DICT_ARUCO_ORIGINAL,
+        _ => bail!("unknown ArUco dictionary {:?}", name),
+    })
+}
+
+// 一个标记的位姿: 标记中心为原点, x 向右, y 向上, z 垂直标记平面指向镜头.
+struct Pose {
+    id: i32,
+    rvec: Mat,
+    tvec: Mat,
+}
+
+pub struct ArOverlay {
+    detector: ArucoDetector,
+    marker_length: f64,
+    fov: f64,
+    objects: Vec<ArObject>,
+    intrinsics: Option<Intrinsics>,
+    // 按当前画面尺寸换算的相机矩阵和畸变系数.
+    camera: Option<((i32, i32), Mat, Mat)>,
+    gray: Mat,
+}
+
+impl ArOverlay {
+    pub fn new(config: &ArConfig, intrinsics: Option<&Intrinsics>) -> Result<Self> {
+        let dictionary = objdetect::get_predefined_dictionary_i32(dictionary(&config.dictionary)?)?;
+        let detector = ArucoDetector::new(
+            &dictionary,
+            &DetectorParameters::default()?,
+            RefineParameters::new(10.0, 3.0, true)?,
+        )?;
+        if intrinsics.is_none() {
+            eprintln!(
+                "ar: camera is not calibrated, estimating intrinsics from a {} degree field of view",
+                config.fov
+            );
+        }
+        let mut objects = config.objects.clone();
+        if objects.is_empty() {
+            objects.push(ArObject {
+                marker: None,
+                shape: Shape::Axes,
+                size: default_size(),
+                text: String::new(),
+                color: default_color(),
+            });
+        }
+        Ok(Self {
+            detector,
+            marker_length: config.marker_length.max(0.001),
+            fov: config.fov,
+            objects,
+            intrinsics: intrinsics.cloned(),
+            camera: None,
+            gray: Mat::default(),
+        })
+    }
+
+    fn camera(&mut self, width: i32, height: i32) -> Result<(&Mat, &Mat)> {
+        if !matches!(&self.camera, Some((size, _, _)) if *size == (width, height)) {
+            let intrinsics = self
+                .intrinsics
+                .clone()
+                .unwrap_or_else(|| Intrinsics::estimate(width, height, self.fov));
+            let (camera, distortion) = intrinsics.matrices(width, height)?;
+            self.camera = Some(((width, height), camera, distortion));
+        }
+        let (_, camera, distortion) = self.camera.as_ref().unwrap();
+        Ok((camera, distortion))
+    }
+
+    fn poses(&mut self, frame: &Mat) -> Result<Vec<Pose>> {
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        let mut corners = Vector::<Vector<Point2f>>::new();
+        let mut ids = Vector::<i32>::new();
+        let mut rejected = Vector::<Vector<Point2f>>::new();
+        self.detector
+            .detect_markers(&self.gray, &mut corners, &mut ids, &mut rejected)?;
+        let half = self.marker_length as f32 / 2.0;
+        // SOLVEPNP_IPPE_SQUARE 要求的角点顺序: 左上, 右上, 右下, 左下.
+        let square = Vector::from_slice(&[
+            Point3f::new(-half, half, 0.0),
+            Point3f::new(half, half, 0.0),
+            Point3f::new(half, -half, 0.0),
+            Point3f::new(-half, -half, 0.0),
+        ]);
+        let (camera, distortion) = self.camera(frame.cols(), frame.rows())?;
+        let mut poses = Vec::new();
+        for (id, corners) in ids.iter().zip(corners.iter()) {
+            let (mut rvec, mut tvec) = (Mat::default(), Mat::default());
+            let solved = calib3d::solve_pnp(
+                &square,
+                &corners,
+                camera,
+                distortion,
+                &mut rvec,
+                &mut tvec,
+                false,
+                calib3d::SOLVEPNP_IPPE_SQUARE,
+            )?;
+            if solved {
+                poses.push(Pose { id, rvec, tvec });
+            }
+        }
+        Ok(poses)
+    }
+
+    // 把标记坐标系中的点投影到画面上.
+    fn project(&self, pose: &Pose, points: &[Point3f]) -> Result<Vec<Point>> {
+        let (_, camera, distortion) = self.camera.as_ref().unwrap();
+        let mut projected = Vector::<Point2f>::new();
+        calib3d::project_points(
+            &Vector::from_slice(points),
+            &pose.rvec,
+            &pose.tvec,
+            camera,
+            distortion,
+            &mut projected,
+            &mut core::no_array(),
+            0.0,
+        )?;
+        Ok(projected
+            .iter()
+            .map(|p| Point::new(p.x.round() as i32, p.y.round() as i32))
+            .collect())
+    }
+
+    fn draw(&self, frame: &mut Mat, pose: &Pose, object: &ArObject) -> Result<()> {
+        let length = (self.marker_length * object.size) as f32;
+        let [b, g, r] = object.color;
+        let color = Scalar::new(b as f64, g as f64, r as f64, 0.0);
+        match object.shape {
+            Shape::Axes => {
+                let (_, camera, distortion) = self.camera.as_ref().unwrap();
+                calib3d::draw_frame_axes(
+                    frame, camera, distortion, &pose.rvec, &pose.tvec, length, 2,
+                )?;
+            }
+            Shape::Cube => {
+                let half = length / 2.0;
+                let mut corners = Vec::new();
+                for z in [0.0, length] {
+                    corners.extend([
+                        Point3f::new(-half, half, z),
+                        Point3f::new(half, half, z),
+                        Point3f::new(half, -half, z),
+                        Point3f::new(-half, -half, z),
+                    ]);
+                }
+                let points = self.project(pose, &corners)?;
+                for i in 0..4 {
+                    let next = (i + 1) % 4;
+                    for (a, b) in [(i, next), (i + 4, next + 4), (i, i + 4)] {
+                        imgproc::line(frame, points[a], points[b], color, 2, LINE_AA, 0)?;
+                    }
+                }
+            }
+            Shape::Text => {
+                let half = self.marker_length as f32 / 2.0;
+                let points = self.project(
+                    pose,
+                    &[
+                        Point3f::new(0.0, 0.0, length),
+                        Point3f::new(-half, 0.0, 0.0),
+                        Point3f::new(half, 0.0, 0.0),
+                    ],
+                )?;
+                // 文字大小跟随标记在画面上的宽度, 离得越远越小.
+                let (left, right) = (points[1], points[2]);
+                let width = ((right.x - left.x) as f64).hypot((right.y - left.y) as f64);
+                let scale = (width / 120.0).clamp(0.3, 3.0);
+                let thickness = (scale * 2.0).round().max(1.0) as i32;
+                let mut baseline = 0;
+                let text = imgproc::get_text_size(
+                    &object.text,
+                    FONT_HERSHEY_SIMPLEX,
+                    scale,
+                    thickness,
+                    &mut baseline,
+                )?;
+                let origin =
+                    Point::new(points[0].x - text.width / 2, points[0].y + text.height / 2);
+                // 从标记中心连到文字下方.
+                let center = Point::new((left.x + right.x) / 2, (left.y + right.y) / 2);
+                imgproc::line(frame, center, points[0], color, 1, LINE_AA, 0)?;
+                imgproc::put_text(
+                    frame,
+                    &object.text,
+                    origin,
+                    FONT_HERSHEY_SIMPLEX,
+                    scale,
+                    color,
+                    thickness,
+                    LINE_AA,
+                    false,
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for ArOverlay {
+    fn name(&self) -> &str {
+        "ar"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let poses = self.poses(frame)?;
+        for pose in &poses {
+            for object in &self.objects {
+                if object.marker.map_or(true, |marker| marker == pose.id) {
+                    self.draw(frame, pose, object)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
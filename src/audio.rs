@@ -0,0 +1,516 @@
+use std::{
+    f32::consts::PI,
+    io::Read,
+    process::{Child, ChildStderr, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{Mat, Rect, Scalar},
+    imgproc::{self, LINE_8},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{pipeline::FrameProcessor, threads::ThreadConfig};
+
+// 采样率和每次分析的采样数, 16 kHz 下每块 32 ms.
+const SAMPLE_RATE: usize = 16000;
+const BLOCK: usize = 512;
+// 电平按 -60..0 dBFS 映射到 0..1.
+const FLOOR_DB: f32 = -60.0;
+// 频段范围 (Hz), 对数等分.
+const LOW_HZ: f32 = 50.0;
+const HIGH_HZ: f32 = 8000.0;
+// 平均电平的时间常数, 节拍是比平均电平高出一截的瞬间.
+const AVERAGE: Duration = Duration::from_secs(1);
+// 两次节拍的最短间隔和边框闪烁的时长.
+const BEAT_HOLD: Duration = Duration::from_millis(200);
+const PULSE: Duration = Duration::from_millis(250);
+// 超过这个时间没有新的音频数据时不再显示电平.
+const STALE: Duration = Duration::from_secs(1);
+
+// 音频输入, 用 ffmpeg 从声卡或麦克风读取单声道 PCM.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioConfig {
+    // ffmpeg 的输入格式和设备, 例如 "pulse" 和 "default", "alsa" 和 "hw:1",
+    // "avfoundation" 和 ":0", "dshow" 和 "audio=Microphone (USB Audio)".
+    pub format: String,
+    pub device: String,
+    pub overlay: AudioOverlayConfig,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        let (format, device) = if cfg!(target_os = "macos") {
+            ("avfoundation", ":0")
+        } else if cfg!(target_os = "windows") {
+            // dshow 没有默认设备, 需要在配置中指定.
+            ("dshow", "")
+        } else {
+            ("pulse", "default")
+        };
+        Self {
+            format: format.to_string(),
+            device: device.to_string(),
+            overlay: AudioOverlayConfig::default(),
+        }
+    }
+}
+
+// 随声音变化的叠加效果, 给直播用.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AudioOverlayConfig {
+    pub enabled: bool,
+    pub effect: AudioEffect,
+    // 灵敏度, 相当于音量增益: 2 为 +6 dB, 同时降低节拍的门限.
+    pub sensitivity: f64,
+    // VU 条的频段数.
+    pub bands: usize,
+    // 节拍边框的颜色 (RGB).
+    pub color: [u8; 3],
+}
+
+impl Default for AudioOverlayConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            effect: AudioEffect::Both,
+            sensitivity: 1.0,
+            bands: 16,
+            color: [255, 0, 255],
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AudioEffect {
+    // 底部的频段 VU 条.
+    Bars,
+    // 节拍时闪烁的边框.
+    Border,
+    #[default]
+    Both,
+}
+
+impl AudioEffect {
+    pub const LABELS: [&'static str; 3] = ["VU bars", "Beat border", "Both"];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            AudioEffect::Bars => Self::LABELS[0],
+            AudioEffect::Border => Self::LABELS[1],
+            AudioEffect::Both => Self::LABELS[2],
+        }
+    }
+
+    pub fn from_label(label: &str) -> Self {
+        match label {
+            "VU bars" => AudioEffect::Bars,
+            "Beat border" => AudioEffect::Border,
+            _ => AudioEffect::Both,
+        }
+    }
+
+    fn bars(self) -> bool {
+        self != AudioEffect::Border
+    }
+
+    fn border(self) -> bool {
+        self != AudioEffect::Bars
+    }
+}
+
+// 最新一块音频的分析结果.
+#[derive(Debug, Clone, Default)]
+pub struct Levels {
+    // 电平 0..1, 见 FLOOR_DB.
+    pub level: f32,
+    // 约 1 秒的平均电平.
+    pub average: f32,
+    // 各频段的电平 0..1, 低频在前.
+    pub bands: Vec<f32>,
+    pub updated: Option<Instant>,
+    // ffmpeg 异常退出时的错误.
+    pub error: Option<String>,
+}
+
+impl Levels {
+    pub fn is_fresh(&self) -> bool {
+        self.updated
+            .is_some_and(|updated| updated.elapsed() < STALE)
+    }
+
+    // 电平对应的 dBFS, 低于 FLOOR_DB 时为 FLOOR_DB.
+    pub fn dbfs(&self) -> f64 {
+        (FLOOR_DB + self.level * -FLOOR_DB) as f64
+    }
+}
+
+// 同一设备的 ffmpeg 进程由叠加效果和监护预设共用, 最后一个使用者丢弃时结束.
+struct Input {
+    source: (String, String),
+    levels: Arc<Mutex<Levels>>,
+    child: Child,
+}
+
+impl Drop for Input {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+const NOT_BUILT: &str = "audio input requires the audio build feature";
+
+static INPUTS: Mutex<Vec<Weak<Input>>> = Mutex::new(Vec::new());
+
+#[derive(Clone)]
+pub struct AudioInput(Arc<Input>);
+
+impl AudioInput {
+    // 设备已经在读取时共用同一个进程. 设备打不开时 ffmpeg 随后退出, 错误见 Levels::error.
+    pub fn open(config: &AudioConfig, workers: &ThreadConfig) -> Result<Self> {
+        if !cfg!(feature = "audio") {
+            bail!(NOT_BUILT);
+        }
+        if config.device.is_empty() {
+            bail!("[audio] device is not set");
+        }
+        let source = (config.format.clone(), config.device.clone());
+        let mut inputs = INPUTS.lock().unwrap();
+        inputs.retain(|input| input.strong_count() > 0);
+        for input in inputs.iter().filter_map(Weak::upgrade) {
+            if input.source == source {
+                return Ok(Self(input));
+            }
+        }
+        let mut child = Command::new("ffmpeg")
+            .args([
+                "-v",
+                "error",
+                "-nostats",
+                "-f",
+                &config.format,
+                "-i",
+                &config.device,
+            ])
+            .args(["-vn", "-ac", "1", "-ar"])
+            .arg(SAMPLE_RATE.to_string())
+            .args(["-f", "s16le", "-"])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .context("run ffmpeg")?;
+        let stdout = child.stdout.take().context("ffmpeg stdout")?;
+        let stderr = child.stderr.take().context("ffmpeg stderr")?;
+        let levels = Arc::new(Mutex::new(Levels::default()));
+        let input = Arc::new(Input {
+            source,
+            levels: levels.clone(),
+            child,
+        });
+        let (bands, workers) = (config.overlay.bands.clamp(1, 64), workers.clone());
+        thread::Builder::new()
+            .name("audio".to_string())
+            .spawn(move || {
+                workers.apply("audio");
+                read(stdout, stderr, &levels, bands);
+            })?;
+        inputs.push(Arc::downgrade(&input));
+        Ok(Self(input))
+    }
+
+    pub fn levels(&self) -> Levels {
+        self.0.levels.lock().unwrap().clone()
+    }
+}
+
+// 读取 PCM 直到 ffmpeg 退出, 每块更新一次 levels. 进程被结束时没有人再读取结果.
+fn read(mut stdout: ChildStdout, mut stderr: ChildStderr, levels: &Mutex<Levels>, bands: usize) {
+    let mut buffer = vec![0u8; BLOCK * 2];
+    let mut samples = vec![0f32; BLOCK];
+    let alpha = BLOCK as f32 / (SAMPLE_RATE as f32 * AVERAGE.as_secs_f32());
+    let mut average = 0.0;
+    while stdout.read_exact(&mut buffer).is_ok() {
+        for (sample, bytes) in samples.iter_mut().zip(buffer.chunks_exact(2)) {
+            *sample = i16::from_le_bytes([bytes[0], bytes[1]]) as f32 / i16::MAX as f32;
+        }
+        let (level, spectrum) = analyse(&samples, bands);
+        average += (level - average) * alpha;
+        let mut levels = levels.lock().unwrap();
+        levels.level = level;
+        levels.average = average;
+        levels.bands = spectrum;
+        levels.updated = Some(Instant::now());
+    }
+    let mut message = String::new();
+    let _ = stderr.read_to_string(&mut message);
+    let message = message.lines().last().unwrap_or("audio input ended").trim();
+    levels.lock().unwrap().error = Some(format!("ffmpeg: {}", message));
+}
+
+// 一块采样的电平和 bands 个频段的电平, 都映射到 0..1.
+pub fn analyse(samples: &[f32], bands: usize) -> (f32, Vec<f32>) {
+    let n = samples.len();
+    let rms = (samples.iter().map(|s| s * s).sum::<f32>() / n.max(1) as f32).sqrt();
+    // 加 Hann 窗后做 FFT, 满幅正弦的峰值为 n / 4.
+    let mut re: Vec<f32> = samples
+        .iter()
+        .enumerate()
+        .map(|(i, s)| s * 0.5 * (1.0 - (2.0 * PI * i as f32 / n as f32).cos()))
+        .collect();
+    let mut im = vec![0.0; n];
+    fft(&mut re, &mut im);
+    let magnitude = |k: usize| (re[k] * re[k] + im[k] * im[k]).sqrt() * 4.0 / n as f32;
+    let bin = |hz: f32| ((hz * n as f32 / SAMPLE_RATE as f32) as usize).clamp(1, n / 2 - 1);
+    let spectrum = (0..bands)
+        .map(|band| {
+            let edge = |i: usize| LOW_HZ * (HIGH_HZ / LOW_HZ).powf(i as f32 / bands as f32);
+            let (low, high) = (bin(edge(band)), bin(edge(band + 1)));
+            let peak = (low..high.max(low + 1)).map(magnitude).fold(0.0, f32::max);
+            scale(peak)
+        })
+        .collect();
+    (scale(rms * 2f32.sqrt()), spectrum)
+}
+
+// 幅度 (满幅为 1) 转为 0..1 的电平.
+fn scale(amplitude: f32) -> f32 {
+    let db = 20.0 * amplitude.max(1e-6).log10();
+    ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+}
+
+// 原地基 2 FFT, 长度必须是 2 的幂.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+// 电平比平均值高出门限时为一次节拍, 之后 BEAT_HOLD 内不再触发.
+#[derive(Default)]
+pub struct BeatDetector {
+    above: bool,
+    last: Option<Instant>,
+}
+
+impl BeatDetector {
+    pub fn update(&mut self, level: f32, average: f32, sensitivity: f64, now: Instant) -> bool {
+        let margin = 0.1 / sensitivity.max(0.1) as f32;
+        let above = level > average + margin;
+        let rising = above && !self.above;
+        self.above = above;
+        if rising && !self.last.is_some_and(|last| now - last < BEAT_HOLD) {
+            self.last = Some(now);
+            return true;
+        }
+        false
+    }
+
+    pub fn last(&self) -> Option<Instant> {
+        self.last
+    }
+}
+
+// 随声音变化的叠加效果, 排在画面效果之后, 不会被效果改变颜色.
+pub struct AudioOverlay {
+    config: AudioOverlayConfig,
+    input: AudioInput,
+    beats: BeatDetector,
+}
+
+impl AudioOverlay {
+    pub fn new(config: &AudioConfig, workers: &ThreadConfig) -> Result<Self> {
+        let overlay = &config.overlay;
+        Ok(Self {
+            config: overlay.clone(),
+            input: AudioInput::open(config, workers)?,
+            beats: BeatDetector::default(),
+        })
+    }
+
+    // 按灵敏度提高的电平.
+    fn gain(&self, level: f32) -> f32 {
+        let gain = 20.0 * self.config.sensitivity.max(0.01).log10() as f32 / -FLOOR_DB;
+        if level > 0.0 {
+            (level + gain).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+
+    fn draw_bars(&self, frame: &mut Mat, bands: &[f32]) -> Result<()> {
+        let (width, height) = (frame.cols(), frame.rows());
+        let area = Rect::new(width / 20, height * 3 / 4, width * 3 / 10, height / 5);
+        let step = area.width / bands.len().max(1) as i32;
+        for (i, &band) in bands.iter().enumerate() {
+            let value = self.gain(band);
+            let bar = (area.height as f32 * value) as i32;
+            if bar <= 0 {
+                continue;
+            }
+            // 低电平为绿色, 接近满幅时变为红色.
+            let color = Scalar::new(
+                0.0,
+                255.0 * (1.0 - value as f64).min(0.5) * 2.0,
+                255.0 * (value as f64 * 2.0).min(1.0),
+                0.0,
+            );
+            let x = area.x + i as i32 * step;
+            let rect = Rect::new(x, area.y + area.height - bar, (step - 2).max(1), bar);
+            imgproc::rectangle(frame, rect, color, -1, LINE_8, 0)?;
+        }
+        Ok(())
+    }
+
+    fn draw_border(&self, frame: &mut Mat, pulse: f32) -> Result<()> {
+        let thickness = (frame.cols().min(frame.rows()) as f32 * 0.03 * pulse) as i32;
+        if thickness < 1 {
+            return Ok(());
+        }
+        let [r, g, b] = self.config.color;
+        let rect = Rect::new(0, 0, frame.cols(), frame.rows());
+        let color = Scalar::new(b as f64, g as f64, r as f64, 0.0);
+        imgproc::rectangle(frame, rect, color, thickness * 2, LINE_8, 0)?;
+        Ok(())
+    }
+}
+
+impl FrameProcessor for AudioOverlay {
+    fn name(&self) -> &str {
+        "audio-overlay"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let levels = self.input.levels();
+        if let Some(error) = levels.error {
+            bail!("audio input: {}", error);
+        }
+        if !levels.is_fresh() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        self.beats
+            .update(levels.level, levels.average, self.config.sensitivity, now);
+        if self.config.effect.bars() {
+            self.draw_bars(frame, &levels.bands)?;
+        }
+        if self.config.effect.border() {
+            if let Some(beat) = self.beats.last() {
+                let pulse = 1.0 - (now - beat).as_secs_f32() / PULSE.as_secs_f32();
+                if pulse > 0.0 {
+                    self.draw_border(frame, pulse)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn order(&self) -> i32 {
+        60
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tone(hz: f32, amplitude: f32) -> Vec<f32> {
+        (0..BLOCK)
+            .map(|i| amplitude * (2.0 * PI * hz * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    // 满幅正弦约为 0 dBFS, 静音为 0, 每降低 20 dB 电平减少 1/3.
+    #[test]
+    fn level_scale() {
+        let (full, _) = analyse(&tone(1000.0, 1.0), 16);
+        assert!((full - 1.0).abs() < 0.01, "{}", full);
+        let (quiet, _) = analyse(&tone(1000.0, 0.1), 16);
+        assert!((quiet - 2.0 / 3.0).abs() < 0.01, "{}", quiet);
+        let (silent, bands) = analyse(&[0.0; BLOCK], 16);
+        assert_eq!(silent, 0.0);
+        assert!(bands.iter().all(|&band| band == 0.0));
+        let levels = Levels {
+            level: quiet,
+            ..Levels::default()
+        };
+        assert!((levels.dbfs() + 20.0).abs() < 0.5, "{}", levels.dbfs());
+    }
+
+    // 单音只出现在它所在的频段, 低音在前.
+    #[test]
+    fn tone_lands_in_its_band() {
+        for (hz, expected) in [(100.0, 2), (1000.0, 9), (6000.0, 15)] {
+            let (_, bands) = analyse(&tone(hz, 0.5), 16);
+            let loudest = (0..bands.len())
+                .max_by(|&a, &b| bands[a].total_cmp(&bands[b]))
+                .unwrap();
+            assert_eq!(loudest, expected, "{} Hz: {:?}", hz, bands);
+            assert!(bands[loudest] > 0.85, "{} Hz: {:?}", hz, bands);
+        }
+    }
+
+    // 超过门限的上升沿才是节拍, BEAT_HOLD 内不重复触发; 灵敏度越高门限越低.
+    #[test]
+    fn beat_detection() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let mut beats = BeatDetector::default();
+        assert!(beats.update(0.8, 0.5, 1.0, at(0)));
+        assert!(!beats.update(0.8, 0.5, 1.0, at(30)));
+        assert!(!beats.update(0.5, 0.5, 1.0, at(60)));
+        assert!(!beats.update(0.8, 0.5, 1.0, at(90)));
+        assert!(!beats.update(0.5, 0.5, 1.0, at(300)));
+        assert!(beats.update(0.8, 0.5, 1.0, at(330)));
+        assert_eq!(beats.last(), Some(at(330)));
+
+        let mut beats = BeatDetector::default();
+        assert!(!beats.update(0.55, 0.5, 1.0, at(0)));
+        let mut beats = BeatDetector::default();
+        assert!(beats.update(0.55, 0.5, 4.0, at(0)));
+    }
+
+    #[test]
+    fn effect_labels() {
+        for label in AudioEffect::LABELS {
+            assert_eq!(AudioEffect::from_label(label).label(), label);
+        }
+    }
+}
@@ -0,0 +1,694 @@
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread::{spawn, JoinHandle},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{api::decode, hmac};
+
+// 请求行和请求头的最大长度, 超出部分不读取.
+const MAX_HEADER: u64 = 16 * 1024;
+const REALM: &str = "slint-opencv";
+// TLS 握手和请求头必须在这个时间内读完, 不发送数据的客户端不能一直占用线程.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// 客户端不读取数据时写入在这个时间后出错, 连接随之断开, 推流和 WebSocket 线程不会一直阻塞.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+// 每个监听端口同时处理的连接数, 超出时直接关闭新连接.
+const MAX_CONNECTIONS: usize = 64;
+// hash-password 生成的密码哈希使用的 PBKDF2 迭代次数.
+const ITERATIONS: u32 = 600_000;
+const HASH_SCHEME: &str = "pbkdf2-sha256";
+
+// 远程接口 (控制接口, 指标, MJPEG 推流, 字幕 WebSocket) 共用的用户和 TLS 设置.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    // 为空时不检查身份, 只适合监听 127.0.0.1.
+    pub users: Vec<UserConfig>,
+    // PEM 格式的证书链和私钥, 设置后所有接口只接受 HTTPS 和 WSS.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    pub name: String,
+    pub role: Role,
+    // 加盐的密码哈希, 由 hash-password 命令生成, 用于 HTTP Basic 认证.
+    pub password_hash: Option<String>,
+    // 访问令牌, 放在 Authorization: Bearer 头或 ?token= 参数中.
+    pub token: Option<String>,
+}
+
+// viewer 只能观看 (推流, 缩略图, 场景列表, 指标), operator 还可以切换场景和发送字幕.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    Viewer,
+    Operator,
+}
+
+#[cfg(feature = "tls")]
+type TlsConfig = rustls::ServerConfig;
+#[cfg(not(feature = "tls"))]
+type TlsConfig = ();
+
+// 检查请求的身份, 并按配置为监听的连接加上 TLS.
+#[derive(Clone)]
+pub struct Auth {
+    users: Arc<Vec<UserConfig>>,
+    tls: Option<Arc<TlsConfig>>,
+    // 验证成功过的用户名和 SHA-256(盐 + 密码), 同一个密码不再重复计算 PBKDF2.
+    verified: Arc<Mutex<HashMap<String, [u8; 32]>>>,
+}
+
+impl Auth {
+    pub fn new(config: &AuthConfig) -> Result<Self> {
+        for user in &config.users {
+            if let Some(hash) = &user.password_hash {
+                PasswordHash::parse(hash)
+                    .with_context(|| format!("user {:?} password_hash", user.name))?;
+            }
+            if user.password_hash.is_none() && user.token.is_none() {
+                bail!("user {:?} needs password_hash or token", user.name);
+            }
+        }
+        let tls = match (&config.tls_cert, &config.tls_key) {
+            (Some(cert), Some(key)) => Some(Arc::new(load_tls(cert, key)?)),
+            (None, None) => None,
+            _ => bail!("tls_cert and tls_key must be set together"),
+        };
+        Ok(Self {
+            users: Arc::new(config.users.clone()),
+            tls,
+            verified: Arc::default(),
+        })
+    }
+
+    pub fn scheme(&self) -> &'static str {
+        if self.tls.is_some() {
+            "https"
+        } else {
+            "http"
+        }
+    }
+
+    // 在 addr 上接受连接, 每个连接在单独的线程中完成 TLS 握手后交给 handle.
+    // 返回的 Listener 被丢弃时停止接受连接.
+    pub fn listen(
+        &self,
+        addr: &str,
+        what: &'static str,
+        handle: impl Fn(Connection) -> Result<()> + Clone + Send + 'static,
+    ) -> Result<Listener> {
+        let listener =
+            TcpListener::bind(addr).with_context(|| format!("bind {} {}", what, addr))?;
+        if self.users.is_empty() && !loopback(&listener) {
+            eprintln!(
+                "warning: {} on {} has no [auth] users, anyone on the network can use it",
+                what, addr
+            );
+        }
+        let tls = self.tls.clone();
+        let local = listener.local_addr()?;
+        let stopped = Arc::new(AtomicBool::new(false));
+        let active = Arc::new(AtomicUsize::new(0));
+        let thread = {
+            let stopped = stopped.clone();
+            spawn(move || {
+                for stream in listener.incoming().flatten() {
+                    if stopped.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        eprintln!(
+                            "{}: too many connections, closing {:?}",
+                            what,
+                            stream.peer_addr()
+                        );
+                        continue;
+                    }
+                    let handle = handle.clone();
+                    let tls = tls.clone();
+                    let active = active.clone();
+                    spawn(move || {
+                        let result = stream
+                            .set_read_timeout(Some(READ_TIMEOUT))
+                            .and_then(|()| stream.set_write_timeout(Some(WRITE_TIMEOUT)))
+                            .map_err(anyhow::Error::from)
+                            .and_then(|()| Connection::new(stream, tls))
+                            .and_then(handle);
+                        if let Err(err) = result {
+                            eprintln!("{}: {:?}", what, err);
+                        }
+                        active.fetch_sub(1, Ordering::SeqCst);
+                    });
+                }
+            })
+        };
+        Ok(Listener {
+            addr: local,
+            stopped,
+            thread: Some(thread),
+        })
+    }
+
+    // 检查请求的用户是否有 role 的权限, 返回用户名 (没有配置用户时为空).
+    // 没有权限时回复 401 或 403 并返回 None.
+    pub fn authorize(
+        &self,
+        connection: &mut Connection,
+        request: &Request,
+        role: Role,
+    ) -> Result<Option<String>> {
+        match self.check(request.header("Authorization"), &request.query, role) {
+            Ok((name, _)) => Ok(Some(name)),
+            Err(status) => {
+                eprintln!(
+                    "{} {} from {}: {}",
+                    request.method,
+                    request.path,
+                    connection.peer(),
+                    status
+                );
+                write!(
+                    connection,
+                    "HTTP/1.1 {}\r\nWWW-Authenticate: Basic realm=\"{}\"\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}\n",
+                    status,
+                    REALM,
+                    status.len() + 1,
+                    status
+                )?;
+                Ok(None)
+            }
+        }
+    }
+
+    // 成功时返回用户名和角色, 失败时返回 HTTP 状态. 没有配置用户时所有请求都是 operator.
+    pub fn check(
+        &self,
+        authorization: Option<&str>,
+        query: &str,
+        role: Role,
+    ) -> Result<(String, Role), &'static str> {
+        if self.users.is_empty() {
+            return Ok((String::new(), Role::Operator));
+        }
+        let user = match authorization.and_then(|value| value.split_once(' ')) {
+            Some((scheme, value)) if scheme.eq_ignore_ascii_case("basic") => self.basic(value),
+            Some((scheme, value)) if scheme.eq_ignore_ascii_case("bearer") => {
+                self.token(value.trim())
+            }
+            _ => param(query, "token").and_then(|token| self.token(&token)),
+        };
+        match user {
+            None => Err("401 Unauthorized"),
+            Some(user) if user.role < role => Err("403 Forbidden"),
+            Some(user) => Ok((user.name.clone(), user.role)),
+        }
+    }
+
+    // 完成 WebSocket 握手, 返回连接和用户名, 角色. 没有权限时以 401 或 403 拒绝握手.
+    // 浏览器的 WebSocket 不能设置 Authorization 头, 可以用 ?token= 参数.
+    #[cfg(feature = "streaming")]
+    pub fn accept_websocket(
+        &self,
+        connection: Connection,
+        role: Role,
+    ) -> Result<(tungstenite::WebSocket<Connection>, String, Role)> {
+        use tungstenite::{
+            handshake::server::{ErrorResponse, Request, Response},
+            http::{header, StatusCode},
+        };
+
+        let mut user = (String::new(), Role::Viewer);
+        let check = |request: &Request, response: Response| -> Result<Response, ErrorResponse> {
+            let authorization = request
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok());
+            let query = request.uri().query().unwrap_or("");
+            match self.check(authorization, query, role) {
+                Ok(found) => {
+                    user = found;
+                    Ok(response)
+                }
+                Err(status) => {
+                    let mut error = ErrorResponse::new(Some(status.to_string()));
+                    *error.status_mut() = if status.starts_with("403") {
+                        StatusCode::FORBIDDEN
+                    } else {
+                        StatusCode::UNAUTHORIZED
+                    };
+                    Err(error)
+                }
+            }
+        };
+        // 握手错误中包含借用了 user 的回调, 只保留错误信息.
+        let socket = tungstenite::accept_hdr(connection, check)
+            .map_err(|err| anyhow::anyhow!("websocket handshake: {}", err))?;
+        // 握手之后的消息可能间隔很久, 去掉读取请求时的超时. 写入仍然有超时.
+        socket.get_ref().set_read_timeout(None)?;
+        socket.get_ref().set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok((socket, user.0, user.1))
+    }
+
+    fn basic(&self, value: &str) -> Option<&UserConfig> {
+        let decoded = STANDARD.decode(value.trim()).ok()?;
+        let decoded = String::from_utf8(decoded).ok()?;
+        let (name, password) = decoded.split_once(':')?;
+        let user = self.users.iter().find(|user| user.name == name)?;
+        let hash = PasswordHash::parse(user.password_hash.as_deref()?).ok()?;
+        let quick: [u8; 32] = Sha256::new()
+            .chain_update(&hash.salt)
+            .chain_update(password)
+            .finalize()
+            .into();
+        if self.verified.lock().unwrap().get(name) == Some(&quick) {
+            return Some(user);
+        }
+        if !hash.verify(password) {
+            return None;
+        }
+        self.verified
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), quick);
+        Some(user)
+    }
+
+    fn token(&self, token: &str) -> Option<&UserConfig> {
+        self.users.iter().find(|user| {
+            user.token
+                .as_ref()
+                .is_some_and(|expected| same(expected, token))
+        })
+    }
+}
+
+// 监听线程的句柄, 被丢弃时停止接受连接并释放端口, 已经建立的连接继续到结束.
+pub struct Listener {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // accept 没有超时, 连接一次让它返回.
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+                SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        if TcpStream::connect_timeout(&addr, Duration::from_secs(1)).is_ok() {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+// 比较时间不取决于第一个不同字符的位置, 不能逐字符猜测密码和令牌.
+fn same(a: &str, b: &str) -> bool {
+    same_bytes(a.as_bytes(), b.as_bytes())
+}
+
+fn same_bytes(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+// password_hash 的格式: pbkdf2-sha256$<迭代次数>$<盐 (十六进制)>$<哈希 (十六进制)>.
+struct PasswordHash {
+    iterations: u32,
+    salt: Vec<u8>,
+    hash: Vec<u8>,
+}
+
+impl PasswordHash {
+    fn parse(text: &str) -> Result<Self> {
+        let parts: Vec<&str> = text.trim().split('$').collect();
+        let [scheme, iterations, salt, hash] = parts[..] else {
+            bail!("expected {}$<iterations>$<salt>$<hash>", HASH_SCHEME);
+        };
+        if scheme != HASH_SCHEME {
+            bail!("unsupported scheme {:?}, expected {}", scheme, HASH_SCHEME);
+        }
+        let iterations: u32 = iterations.parse().context("iterations")?;
+        let (salt, hash) = (unhex(salt).context("salt")?, unhex(hash).context("hash")?);
+        if iterations == 0 || salt.len() < 8 || hash.len() != 32 {
+            bail!("needs at least 1 iteration, an 8 byte salt and a 32 byte hash");
+        }
+        Ok(Self {
+            iterations,
+            salt,
+            hash,
+        })
+    }
+
+    fn verify(&self, password: &str) -> bool {
+        let derived = hmac::pbkdf2(password.as_bytes(), &self.salt, self.iterations);
+        same_bytes(&derived, &self.hash)
+    }
+}
+
+// 生成 password_hash 的值. 盐只需要每次不同, 取自标准库用于哈希表的随机种子.
+pub fn hash_password(password: &str) -> String {
+    hash_with(password, ITERATIONS)
+}
+
+fn hash_with(password: &str, iterations: u32) -> String {
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let mut salt = Vec::with_capacity(16);
+    for _ in 0..2 {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        salt.extend_from_slice(&hasher.finish().to_le_bytes());
+    }
+    let hash = hmac::pbkdf2(password.as_bytes(), &salt, iterations);
+    format!(
+        "{}${}${}${}",
+        HASH_SCHEME,
+        iterations,
+        hex(&salt),
+        hex(&hash)
+    )
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Result<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("invalid hex {:?}", text);
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&text[i..i + 2], 16)?))
+        .collect()
+}
+
+fn loopback(listener: &TcpListener) -> bool {
+    listener
+        .local_addr()
+        .map(|addr| addr.ip().is_loopback())
+        .unwrap_or(false)
+}
+
+// 查询参数的值, 已解码 %XX.
+pub fn param(query: &str, name: &str) -> Option<String> {
+    query
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| decode(value))
+}
+
+// 请求行和请求头, 不读取请求体.
+pub struct Request {
+    pub method: String,
+    // 不含查询参数的路径.
+    pub path: String,
+    pub query: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    pub fn read(connection: &mut Connection) -> Result<Self> {
+        let mut reader = BufReader::new(connection).take(MAX_HEADER);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let method = parts.next().unwrap_or("GET").to_string();
+        let target = parts.next().unwrap_or("/");
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let (path, query) = (path.to_string(), query.to_string());
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+        }
+        Ok(Self {
+            method,
+            path,
+            query,
+            headers,
+        })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+// 明文或 TLS 连接.
+pub enum Connection {
+    Plain(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
+}
+
+impl Connection {
+    #[cfg(feature = "tls")]
+    fn new(stream: TcpStream, tls: Option<Arc<TlsConfig>>) -> Result<Self> {
+        Ok(match tls {
+            // 握手在第一次读写时进行.
+            Some(config) => Self::Tls(Box::new(rustls::StreamOwned::new(
+                rustls::ServerConnection::new(config)?,
+                stream,
+            ))),
+            None => Self::Plain(stream),
+        })
+    }
+
+    #[cfg(not(feature = "tls"))]
+    fn new(stream: TcpStream, _tls: Option<Arc<TlsConfig>>) -> Result<Self> {
+        Ok(Self::Plain(stream))
+    }
+
+    fn socket(&self) -> &TcpStream {
+        match self {
+            Self::Plain(stream) => stream,
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => &stream.sock,
+        }
+    }
+
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket().set_read_timeout(timeout)
+    }
+
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.socket().set_write_timeout(timeout)
+    }
+
+    pub fn peer(&self) -> String {
+        self.socket()
+            .peer_addr()
+            .map_or_else(|_| "unknown".to_string(), |addr| addr.to_string())
+    }
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.read(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::Plain(stream) => stream.write(buf),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::Plain(stream) => stream.flush(),
+            #[cfg(feature = "tls")]
+            Self::Tls(stream) => stream.flush(),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn load_tls(cert: &std::path::Path, key: &std::path::Path) -> Result<TlsConfig> {
+    use rustls::pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer};
+
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("read certificate {}", cert.display()))?;
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("read private key {}", key.display()))?;
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    Ok(rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)?)
+}
+
+#[cfg(not(feature = "tls"))]
+fn load_tls(_cert: &std::path::Path, _key: &std::path::Path) -> Result<TlsConfig> {
+    bail!("TLS requires the tls build feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "f1c9a8e0d2b74c53";
+
+    fn auth() -> Auth {
+        Auth::new(&AuthConfig {
+            users: vec![
+                UserConfig {
+                    name: "family".to_string(),
+                    role: Role::Viewer,
+                    password_hash: Some(hash_with("secret", 1000)),
+                    ..UserConfig::default()
+                },
+                UserConfig {
+                    name: "obs".to_string(),
+                    role: Role::Operator,
+                    token: Some(TOKEN.to_string()),
+                    ..UserConfig::default()
+                },
+            ],
+            ..AuthConfig::default()
+        })
+        .unwrap()
+    }
+
+    fn basic(name: &str, password: &str) -> String {
+        format!(
+            "Basic {}",
+            STANDARD.encode(format!("{}:{}", name, password))
+        )
+    }
+
+    // PBKDF2-HMAC-SHA256, 密码 secret, 盐 saltsalt, 4096 次.
+    #[test]
+    fn password_hash_known_answer() {
+        let hash = PasswordHash::parse(
+            "pbkdf2-sha256$4096$73616c7473616c74$f1dfbb338aca1df31d224ed8972b2c94375c20eedf3f071cb0da2f62b83d65a2",
+        )
+        .unwrap();
+        assert!(hash.verify("secret"));
+        assert!(!hash.verify("Secret"));
+        // 每次生成的盐不同.
+        assert_ne!(hash_with("secret", 1), hash_with("secret", 1));
+        assert!(PasswordHash::parse(&hash_with("secret", 1))
+            .unwrap()
+            .verify("secret"));
+        for bad in [
+            "",
+            "sha256$1$73616c7473616c74$00",
+            "pbkdf2-sha256$0$73616c7473616c74$f1dfbb338aca1df31d224ed8972b2c94375c20eedf3f071cb0da2f62b83d65a2",
+            "pbkdf2-sha256$4096$73$f1dfbb338aca1df31d224ed8972b2c94375c20eedf3f071cb0da2f62b83d65a2",
+            "pbkdf2-sha256$4096$73616c7473616c74$f1df",
+        ] {
+            assert!(PasswordHash::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn check_credentials() {
+        let auth = auth();
+        let family = Ok(("family".to_string(), Role::Viewer));
+        let obs = Ok(("obs".to_string(), Role::Operator));
+        let basic_ok = basic("family", "secret");
+        assert_eq!(auth.check(Some(&basic_ok), "", Role::Viewer), family);
+        // 第二次使用缓存的结果.
+        assert_eq!(auth.check(Some(&basic_ok), "", Role::Viewer), family);
+        assert_eq!(
+            auth.check(Some(&basic_ok), "", Role::Operator),
+            Err("403 Forbidden")
+        );
+        for wrong in [
+            basic("family", "wrong"),
+            basic("obs", "secret"),
+            basic("nobody", "secret"),
+            "Basic not-base64".to_string(),
+            format!("Bearer {}x", TOKEN),
+        ] {
+            assert_eq!(
+                auth.check(Some(&wrong), "", Role::Viewer),
+                Err("401 Unauthorized"),
+                "{}",
+                wrong
+            );
+        }
+        assert_eq!(
+            auth.check(Some(&format!("Bearer {}", TOKEN)), "", Role::Operator),
+            obs
+        );
+        assert_eq!(
+            auth.check(Some(&format!("bearer  {} ", TOKEN)), "", Role::Operator),
+            obs
+        );
+        assert_eq!(
+            auth.check(None, &format!("a=1&token={}", TOKEN), Role::Operator),
+            obs
+        );
+        assert_eq!(
+            auth.check(None, "token=f1c9a8e0d2b74c5", Role::Viewer),
+            Err("401 Unauthorized")
+        );
+        assert_eq!(auth.check(None, "", Role::Viewer), Err("401 Unauthorized"));
+
+        let open = Auth::new(&AuthConfig::default()).unwrap();
+        assert_eq!(
+            open.check(None, "", Role::Operator),
+            Ok((String::new(), Role::Operator))
+        );
+    }
+
+    // 既没有密码也没有令牌的用户在启动时被拒绝.
+    #[test]
+    fn rejects_users_without_credentials() {
+        let config = AuthConfig {
+            users: vec![UserConfig {
+                name: "family".to_string(),
+                ..UserConfig::default()
+            }],
+            ..AuthConfig::default()
+        };
+        assert!(Auth::new(&config).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Ptr, Size, Vector},
+    imgproc::{self, COLOR_BGR2Lab, COLOR_Lab2BGR, CLAHE, COLOR_BGR2GRAY, INTER_AREA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{events::EventStore, exclusions::ExclusionMask, pipeline::FrameProcessor};
+
+// 检测用的缩小宽度.
+const DETECT_WIDTH: i32 = 160;
+// CLAHE 的分块数.
+const TILES: i32 = 8;
+
+// 逆光补偿: 画面同时有大片过暗和过亮的区域 (例如背对窗户的人脸) 时自动提亮暗部.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BacklightConfig {
+    pub enabled: bool,
+    // 低于 dark 的像素为暗部, 高于 bright 的为亮部.
+    pub dark: u8,
+    pub bright: u8,
+    // 暗部和亮部都超过 enter 比例时开始补偿, 其中一个低于 exit 时停止.
+    pub enter: f64,
+    pub exit: f64,
+    // 条件持续多少秒才切换, 避免在临界时反复开关.
+    pub hold: f64,
+    // 补偿渐入渐出的时间 (秒).
+    pub fade: f64,
+    // 局部对比度限制, 越大暗部提亮越明显, 噪点也越多.
+    pub clip_limit: f64,
+}
+
+impl Default for BacklightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dark: 50,
+            bright: 230,
+            enter: 0.15,
+            exit: 0.08,
+            hold: 2.0,
+            fade: 1.0,
+            clip_limit: 2.5,
+        }
+    }
+}
+
+pub struct BacklightCompensation {
+    config: BacklightConfig,
+    events: EventStore,
+    clahe: Ptr<CLAHE>,
+    active: bool,
+    // 检测结果与 active 不同的开始时间.
+    pending: Option<Instant>,
+    // 当前补偿强度, 0 到 1.
+    strength: f64,
+    last_frame: Option<Instant>,
+    small: Mat,
+    gray: Mat,
+    exclusions: ExclusionMask,
+    lab: Mat,
+    lightness: Mat,
+    equalized: Mat,
+}
+
+impl BacklightCompensation {
+    pub fn new(config: &BacklightConfig, events: EventStore) -> Result<Self> {
+        Ok(Self {
+            config: BacklightConfig {
+                bright: config.bright.max(config.dark),
+                exit: config.exit.min(config.enter),
+                ..config.clone()
+            },
+            events,
+            clahe: imgproc::create_clahe(config.clip_limit.max(1.0), Size::new(TILES, TILES))?,
+            active: false,
+            pending: None,
+            strength: 0.0,
+            last_frame: None,
+            small: Mat::default(),
+            gray: Mat::default(),
+            exclusions: ExclusionMask::default(),
+            lab: Mat::default(),
+            lightness: Mat::default(),
+            equalized: Mat::default(),
+        })
+    }
+
+    // 暗部和亮部像素的比例.
+    fn measure(&mut self, frame: &Mat) -> Result<(f64, f64)> {
+        let scale = (DETECT_WIDTH as f64 / frame.cols() as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        imgproc::cvt_color(&self.small, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        let pixels = self.gray.data_bytes()?;
+        // 排除区域 (例如天空) 不参与测光.
+        let included = match self.exclusions.get(self.gray.size()?)? {
+            Some(mask) => mask.data_bytes()?.iter().map(|&m| m != 0).collect(),
+            None => vec![true; pixels.len()],
+        };
+        let pixels = pixels
+            .iter()
+            .zip(included)
+            .filter_map(|(&p, included)| included.then_some(p))
+            .collect::<Vec<u8>>();
+        let dark = pixels.iter().filter(|&&p| p < self.config.dark).count();
+        let bright = pixels.iter().filter(|&&p| p > self.config.bright).count();
+        let total = pixels.len().max(1) as f64;
+        Ok((dark as f64 / total, bright as f64 / total))
+    }
+
+    // 检测结果持续 hold 秒后才改变状态.
+    fn update(&mut self, dark: f64, bright: f64) {
+        let backlit = if self.active {
+            dark.min(bright) >= self.config.exit
+        } else {
+            dark.min(bright) >= self.config.enter
+        };
+        if backlit == self.active {
+            self.pending = None;
+            return;
+        }
+        let since = *self.pending.get_or_insert_with(Instant::now);
+        if since.elapsed().as_secs_f64() < self.config.hold {
+            return;
+        }
+        self.active = backlit;
+        self.pending = None;
+        self.events.log(
+            "backlight",
+            json!({ "active": backlit, "dark": dark, "bright": bright }),
+        );
+    }
+
+    // 只处理 Lab 的亮度通道, 颜色不变.
+    fn compensate(&mut self, frame: &mut Mat) -> Result<()> {
+        imgproc::cvt_color(frame, &mut self.lab, COLOR_BGR2Lab, 0)?;
+        let mut channels = Vector::<Mat>::new();
+        core::split(&self.lab, &mut channels)?;
+        self.lightness = channels.get(0)?;
+        self.clahe.apply(&self.lightness, &mut self.equalized)?;
+        if self.strength < 1.0 {
+            let mut blended = Mat::default();
+            core::add_weighted(
+                &self.lightness,
+                1.0 - self.strength,
+                &self.equalized,
+                self.strength,
+                0.0,
+                &mut blended,
+                -1,
+            )?;
+            self.equalized = blended;
+        }
+        channels.set(0, self.equalized.try_clone()?)?;
+        core::merge(&channels, &mut self.lab)?;
+        imgproc::cvt_color(&self.lab, frame, COLOR_Lab2BGR, 0)?;
+        Ok(())
+    }
+}
+
+impl FrameProcessor for BacklightCompensation {
+    fn name(&self) -> &str {
+        "backlight"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let now = Instant::now();
+        let elapsed = self
+            .last_frame
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_frame = Some(now);
+
+        let (dark, bright) = self.measure(frame)?;
+        self.update(dark, bright);
+        // 渐变而不是突然切换, 画面亮度不会跳变.
+        let step = if self.config.fade > 0.0 {
+            elapsed / self.config.fade
+        } else {
+            1.0
+        };
+        let target = if self.active { 1.0 } else { 0.0 };
+        self.strength += (target - self.strength).clamp(-step, step);
+        if self.strength > 0.0 {
+            self.compensate(frame)?;
+        }
+        Ok(())
+    }
+
+    // 在传感器校正和裁剪之后, 分析之前.
+    fn order(&self) -> i32 {
+        -2
+    }
+}
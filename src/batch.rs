@@ -0,0 +1,289 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{Mat, Size, CV_8UC3},
+    prelude::*,
+    videoio::{self, VideoCapture, VideoWriter},
+};
+
+use crate::{
+    config::Config, events::EventStore, profile::CameraProfile, sink, stages::Stages,
+    trajectory::Trajectories,
+};
+
+const VIDEO_EXTENSIONS: &[&str] = &["mp4", "avi", "mov", "mkv", "m4v", "webm"];
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+// 离线处理: input 为视频文件或包含视频文件的目录, 结果写到 output 目录.
+pub fn run(config: &Config, input: &Path, output: &Path, camera: &str) -> Result<()> {
+    let inputs = if input.is_dir() {
+        let mut files: Vec<PathBuf> = fs::read_dir(input)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| is_video(path))
+            .collect();
+        files.sort();
+        files
+    } else {
+        vec![input.to_path_buf()]
+    };
+    if inputs.is_empty() {
+        bail!("no video files in {}", input.display());
+    }
+    fs::create_dir_all(output)?;
+    for path in inputs {
+        process_file(config, &path, output, camera)?;
+    }
+    Ok(())
+}
+
+// 监视目录中新出现的视频文件, 大小连续两次轮询不变 (写入完成) 后处理,
+// 成功的移到 done/, 失败的移到 failed/.
+pub fn watch(
+    config: &Config,
+    dir: &Path,
+    output: &Path,
+    camera: &str,
+    interval: f64,
+) -> Result<()> {
+    fs::create_dir_all(dir.join("done"))?;
+    fs::create_dir_all(dir.join("failed"))?;
+    fs::create_dir_all(output)?;
+    println!("watching {}", dir.display());
+    let mut sizes: HashMap<PathBuf, u64> = HashMap::new();
+    loop {
+        poll(config, dir, output, camera, &mut sizes)?;
+        sleep(Duration::from_secs_f64(interval));
+    }
+}
+
+// 一次轮询, sizes 为上一次看到的文件大小.
+fn poll(
+    config: &Config,
+    dir: &Path,
+    output: &Path,
+    camera: &str,
+    sizes: &mut HashMap<PathBuf, u64>,
+) -> Result<()> {
+    let mut seen = HashMap::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file() || !is_video(&path) {
+            continue;
+        }
+        let size = fs::metadata(&path)?.len();
+        seen.insert(path.clone(), size);
+        if sizes.get(&path) != Some(&size) {
+            continue;
+        }
+        let target = match process_file(config, &path, output, camera) {
+            Ok(()) => "done",
+            Err(err) => {
+                eprintln!("{}: {:?}", path.display(), err);
+                "failed"
+            }
+        };
+        fs::rename(&path, dir.join(target).join(path.file_name().unwrap()))?;
+        seen.remove(&path);
+    }
+    *sizes = seen;
+    Ok(())
+}
+
+// 尽可能快地用配置的处理管线处理一个视频文件, 写出
+// <output>/<name>-processed.mp4 和 <output>/<name>-events.jsonl,
+// 启用了跟踪时还会导出轨迹.
+pub fn process_file(config: &Config, input: &Path, output: &Path, camera: &str) -> Result<()> {
+    let stem = input
+        .file_stem()
+        .with_context(|| format!("invalid input {}", input.display()))?
+        .to_string_lossy()
+        .to_string();
+    let mut video = VideoCapture::from_file(&input.to_string_lossy(), videoio::CAP_ANY)?;
+    if !video.is_opened()? {
+        bail!("can not open {}", input.display());
+    }
+    let width = video.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32;
+    let height = video.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32;
+    let fps = video.get(videoio::CAP_PROP_FPS)?;
+    let total = video.get(videoio::CAP_PROP_FRAME_COUNT)? as u64;
+
+    let output_file = output.join(format!("{}-processed.mp4", stem));
+    let fourcc = VideoWriter::fourcc('m', 'p', '4', 'v')?;
+    let mut writer = VideoWriter::new(
+        &output_file.to_string_lossy(),
+        fourcc,
+        if fps > 0.0 { fps } else { 30.0 },
+        Size::new(width, height),
+        true,
+    )?;
+    if !writer.is_opened()? {
+        bail!("can not open video writer {}", output_file.display());
+    }
+
+    let profile_dir = CameraProfile::dir(&config.profiles_dir, camera);
+    let profile = CameraProfile::load(&profile_dir)?;
+    let events = EventStore::open(output.join(format!("{}-events.jsonl", stem)))?;
+    let trajectories = Trajectories::default();
+    let stages = Stages {
+        config,
+        profile: &profile,
+        profile_dir: &profile_dir,
+        events: &events,
+        frame_size: (width, height),
+        window: None,
+        models_ready: None,
+    };
+    let mut pipeline = stages.pipeline(&trajectories)?;
+
+    println!("processing {} ({} frames)", input.display(), total);
+    let start = Instant::now();
+    let mut frame = Mat::default();
+    let mut frames = 0u64;
+    while video.read(&mut frame)? {
+        if frame.empty() {
+            break;
+        }
+        pipeline.process(&mut frame);
+        for failure in pipeline.take_failures() {
+            eprintln!(
+                "{} bypassed after repeated errors: {}",
+                failure.stage, failure.error
+            );
+        }
+        writer.write(&frame)?;
+        frames += 1;
+        if frames % 100 == 0 {
+            println!(
+                "{}: {}/{} frames, {:.1} FPS",
+                stem,
+                frames,
+                total,
+                frames as f64 / start.elapsed().as_secs_f64()
+            );
+        }
+    }
+    if config.tracking.enabled && frames > 0 {
+        // 读到文件末尾后 frame 为空, 轨迹图使用黑色底图.
+        let background = Mat::zeros(height, width, CV_8UC3)?.to_mat()?;
+        let stem = output.join(format!("{}-trajectories", stem));
+        trajectories.export(&stem.to_string_lossy(), &background)?;
+    }
+    // 片段录制在 pipeline 被丢弃时关闭.
+    drop(pipeline);
+    sink::wait_finished();
+    println!(
+        "{}: {} frames in {:.1}s, written to {}",
+        input.display(),
+        frames,
+        start.elapsed().as_secs_f64(),
+        output_file.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::Scalar;
+
+    use super::*;
+
+    // 写一段 frames 帧的 MJPG 视频.
+    fn write_video(path: &Path, frames: u32) {
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        for i in 0..frames {
+            let frame =
+                Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(i as f64 * 20.))
+                    .unwrap();
+            writer.write(&frame).unwrap();
+        }
+    }
+
+    fn count_frames(path: &Path) -> u32 {
+        let mut video = VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).unwrap();
+        let mut frame = Mat::default();
+        let mut frames = 0;
+        while video.read(&mut frame).unwrap() && !frame.empty() {
+            frames += 1;
+        }
+        frames
+    }
+
+    // 第一次轮询只记录大小, 大小不变后才处理.
+    #[test]
+    fn watch_moves_processed_files() {
+        let dir = std::env::temp_dir().join(format!("batch-watch-{}", std::process::id()));
+        let output = dir.join("output");
+        for sub in ["done", "failed"] {
+            fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        let config = Config {
+            profiles_dir: dir.join("profiles"),
+            ..Default::default()
+        };
+        write_video(&dir.join("good.avi"), 5);
+        fs::write(dir.join("broken.mp4"), "not a video").unwrap();
+        let mut sizes = HashMap::new();
+        poll(&config, &dir, &output, "camera-0", &mut sizes).unwrap();
+        assert_eq!(sizes.len(), 2);
+        assert!(dir.join("good.avi").exists());
+        assert!(!output.exists());
+
+        fs::create_dir_all(&output).unwrap();
+        poll(&config, &dir, &output, "camera-0", &mut sizes).unwrap();
+        assert!(sizes.is_empty());
+        assert!(dir.join("done").join("good.avi").exists());
+        assert!(dir.join("failed").join("broken.mp4").exists());
+        assert_eq!(count_frames(&output.join("good-processed.mp4")), 5);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn video_extensions() {
+        assert!(is_video(Path::new("in/clip.mp4")));
+        assert!(is_video(Path::new("in/CLIP.MKV")));
+        assert!(!is_video(Path::new("in/notes.txt")));
+        assert!(!is_video(Path::new("in/mp4")));
+    }
+
+    #[test]
+    fn processes_directory() {
+        let dir = std::env::temp_dir().join(format!("batch-test-{}", std::process::id()));
+        let input = dir.join("input");
+        let output = dir.join("output");
+        fs::create_dir_all(&input).unwrap();
+        let config = Config {
+            profiles_dir: dir.join("profiles"),
+            ..Default::default()
+        };
+        let err = run(&config, &input, &output, "camera-0").unwrap_err();
+        assert!(err.to_string().starts_with("no video files"));
+
+        write_video(&input.join("clip.avi"), 10);
+        fs::write(input.join("notes.txt"), "not a video").unwrap();
+        run(&config, &input, &output, "camera-0").unwrap();
+        assert_eq!(count_frames(&output.join("clip-processed.mp4")), 10);
+        assert!(output.join("clip-events.jsonl").exists());
+        assert!(!output.join("notes-processed.mp4").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+use std::{path::PathBuf, sync::Mutex};
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Rect, Scalar, Size, Vector, CV_64F},
+    imgproc::{self, COLOR_BGR2GRAY, INTER_AREA},
+    objdetect::CascadeClassifier,
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    exif::Exif,
+    frames::{self, FrameBuffer},
+    jobs::Handle,
+};
+
+// 计算清晰度和检测人脸使用的缩小宽度, 连拍帧之间只比较相对值.
+const SCORE_WIDTH: i32 = 960;
+// 选择窗口中缩略图的宽度.
+const THUMBNAIL_WIDTH: i32 = 240;
+
+// 连拍后挑选最好的一张: 按清晰度 (拉普拉斯方差) 和睁眼的人脸数打分, 预先选中得分最高的一张,
+// 在选择窗口中确认后再保存.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BestShotConfig {
+    pub enabled: bool,
+    // OpenCV data/haarcascades 中的级联文件, 找不到时只按清晰度打分.
+    pub face_cascade: PathBuf,
+    // 闭着的眼睛检测不到, 检测到两只眼睛的人脸计为睁眼.
+    pub eye_cascade: PathBuf,
+}
+
+impl Default for BestShotConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            face_cascade: PathBuf::from("haarcascade_frontalface_default.xml"),
+            eye_cascade: PathBuf::from("haarcascade_eye_tree_eyeglasses.xml"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Score {
+    pub sharpness: f64,
+    pub faces: usize,
+    pub eyes_open: usize,
+}
+
+impl Score {
+    pub fn describe(&self) -> String {
+        let mut text = format!("sharpness {:.0}", self.sharpness);
+        if self.faces > 0 {
+            text += &format!(", eyes open {}/{}", self.eyes_open, self.faces);
+        }
+        text
+    }
+}
+
+// 每张的综合得分: 清晰度按本组最大值归一化, 加上睁眼人脸的比例. 有人脸时睁眼比清晰度更重要.
+pub fn rank(scores: &[Score]) -> Vec<f64> {
+    let sharpest = scores
+        .iter()
+        .map(|score| score.sharpness)
+        .fold(0.0, f64::max);
+    scores
+        .iter()
+        .map(|score| {
+            let sharpness = if sharpest > 0.0 {
+                score.sharpness / sharpest
+            } else {
+                0.0
+            };
+            let eyes = if score.faces > 0 {
+                score.eyes_open as f64 / score.faces as f64
+            } else {
+                0.0
+            };
+            sharpness + 2.0 * eyes
+        })
+        .collect()
+}
+
+// 综合得分最高的一张, 同分时取先拍的.
+pub fn best(scores: &[Score]) -> usize {
+    rank(scores)
+        .iter()
+        .enumerate()
+        .fold((0, f64::MIN), |best, (index, &value)| {
+            if value > best.1 {
+                (index, value)
+            } else {
+                best
+            }
+        })
+        .0
+}
+
+struct Scorer {
+    cascades: Option<(CascadeClassifier, CascadeClassifier)>,
+    small: Mat,
+    gray: Mat,
+    laplacian: Mat,
+}
+
+impl Scorer {
+    fn new(config: &BestShotConfig) -> Self {
+        let load = |path: &PathBuf| -> Option<CascadeClassifier> {
+            let cascade = CascadeClassifier::new(&path.to_string_lossy()).ok()?;
+            (!cascade.empty().ok()?).then_some(cascade)
+        };
+        let cascades = load(&config.face_cascade).zip(load(&config.eye_cascade));
+        if cascades.is_none() {
+            eprintln!("best shot: face or eye cascade not found, scoring sharpness only");
+        }
+        Self {
+            cascades,
+            small: Mat::default(),
+            gray: Mat::default(),
+            laplacian: Mat::default(),
+        }
+    }
+
+    fn score(&mut self, frame: &Mat) -> Result<Score> {
+        let scale = (SCORE_WIDTH as f64 / frame.cols().max(1) as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        imgproc::cvt_color(&self.small, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        imgproc::laplacian(
+            &self.gray,
+            &mut self.laplacian,
+            CV_64F,
+            1,
+            1.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )?;
+        let (mut mean, mut stddev) = (Scalar::default(), Scalar::default());
+        core::mean_std_dev(&self.laplacian, &mut mean, &mut stddev, &core::no_array())?;
+        let mut score = Score {
+            sharpness: stddev[0] * stddev[0],
+            ..Score::default()
+        };
+        let Some((face_cascade, eye_cascade)) = &mut self.cascades else {
+            return Ok(score);
+        };
+        imgproc::equalize_hist(&self.gray, &mut self.small)?;
+        let mut faces = Vector::<Rect>::new();
+        face_cascade.detect_multi_scale(
+            &self.small,
+            &mut faces,
+            1.1,
+            4,
+            0,
+            Size::new(48, 48),
+            Size::new(0, 0),
+        )?;
+        for face in faces.iter() {
+            // 眼睛在人脸的上半部分.
+            let upper = Rect::new(face.x, face.y, face.width, face.height / 2);
+            let region = Mat::roi(&self.small, upper)?;
+            let mut eyes = Vector::<Rect>::new();
+            eye_cascade.detect_multi_scale(
+                &region,
+                &mut eyes,
+                1.1,
+                3,
+                0,
+                Size::new(face.width / 10, face.width / 10),
+                Size::new(0, 0),
+            )?;
+            score.faces += 1;
+            if eyes.len() >= 2 {
+                score.eyes_open += 1;
+            }
+        }
+        Ok(score)
+    }
+}
+
+// 等待选择的连拍, 同一时间只有一组, 新的连拍替换还没有选择的一组.
+static PENDING: Mutex<Vec<(PathBuf, Mat, Exif)>> = Mutex::new(Vec::new());
+
+// 在后台任务中为每张打分, 返回缩略图, 得分和得分最高的一张. 连拍放入等待选择的位置.
+pub fn score(
+    config: &BestShotConfig,
+    photos: Vec<(PathBuf, Mat, Exif)>,
+    job: &Handle,
+) -> Result<(Vec<FrameBuffer>, Vec<Score>, usize)> {
+    let mut scorer = Scorer::new(config);
+    let mut scores = Vec::new();
+    let mut thumbnails = Vec::new();
+    for (index, (_, frame, _)) in photos.iter().enumerate() {
+        job.progress(Some(index as f64 / photos.len() as f64))?;
+        scores.push(scorer.score(frame)?);
+        thumbnails.push(frames::thumbnail(frame, THUMBNAIL_WIDTH)?);
+    }
+    *PENDING.lock().unwrap() = photos;
+    let best = best(&scores);
+    Ok((thumbnails, scores, best))
+}
+
+// 取出等待选择的连拍中选中的照片, index 为 None 时取出全部.
+pub fn take(index: Option<usize>) -> Vec<(PathBuf, Mat, Exif)> {
+    let photos = std::mem::take(&mut *PENDING.lock().unwrap());
+    match index {
+        Some(index) => photos.into_iter().skip(index).take(1).collect(),
+        None => photos,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::{core::CV_8UC3, imgproc::LINE_8};
+
+    use super::*;
+
+    fn score(sharpness: f64, faces: usize, eyes_open: usize) -> Score {
+        Score {
+            sharpness,
+            faces,
+            eyes_open,
+        }
+    }
+
+    // 清晰度按本组最大值归一化, 睁眼比例的权重为 2.
+    #[test]
+    fn ranking() {
+        let scores = [score(400.0, 2, 1), score(800.0, 2, 2), score(800.0, 0, 0)];
+        assert_eq!(rank(&scores), vec![1.5, 3.0, 1.0]);
+        // 清晰但有人闭眼的一张输给稍模糊但都睁眼的一张.
+        assert_eq!(best(&[score(1000.0, 2, 1), score(300.0, 2, 2)]), 1);
+        // 同分时取先拍的.
+        assert_eq!(best(&[score(500.0, 0, 0), score(500.0, 0, 0)]), 0);
+        assert_eq!(rank(&[score(0.0, 0, 0)]), vec![0.0]);
+    }
+
+    #[test]
+    fn describe() {
+        assert_eq!(score(412.3, 0, 0).describe(), "sharpness 412");
+        assert_eq!(
+            score(412.3, 2, 1).describe(),
+            "sharpness 412, eyes open 1/2"
+        );
+    }
+
+    // 没有级联文件时只按清晰度打分, 平坦的画面清晰度为 0.
+    #[test]
+    fn sharpness_only() {
+        let mut scorer = Scorer::new(&BestShotConfig {
+            face_cascade: PathBuf::from("missing-face.xml"),
+            eye_cascade: PathBuf::from("missing-eye.xml"),
+            ..Default::default()
+        });
+        let flat = Mat::new_rows_cols_with_default(64, 64, CV_8UC3, Scalar::all(128.0)).unwrap();
+        let mut edges = flat.clone();
+        let rect = Rect::new(16, 16, 32, 32);
+        imgproc::rectangle(&mut edges, rect, Scalar::all(255.0), -1, LINE_8, 0).unwrap();
+        assert_eq!(scorer.score(&flat).unwrap(), Score::default());
+        let sharp = scorer.score(&edges).unwrap();
+        assert!(sharp.sharpness > 0.0);
+        assert_eq!(sharp.faces, 0);
+    }
+
+    // 选择后取出选中的一张, 等待的连拍随之清空.
+    #[test]
+    fn take_selected() {
+        let photo = |name: &str| (PathBuf::from(name), Mat::default(), Exif::default());
+        *PENDING.lock().unwrap() = vec![photo("a.jpg"), photo("b.jpg"), photo("c.jpg")];
+        let taken = take(Some(1));
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].0, PathBuf::from("b.jpg"));
+        assert!(take(None).is_empty());
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+// 迷你图保留的样本数.
+const HISTORY: usize = 60;
+// 瞬时码率的统计窗口.
+const WINDOW: Duration = Duration::from_secs(1);
+
+// 编码后实际输出的码率: 瞬时值 (最近一秒), 平均值, 以及最近的样本大小, 画成迷你图.
+#[derive(Debug, Default)]
+pub struct BitrateMeter {
+    started: Option<Instant>,
+    total: u64,
+    // 最近一秒内的 (时间, 字节数).
+    recent: VecDeque<(Instant, u64)>,
+    // 每个样本的字节数, 推流为每帧, 录像为每秒.
+    history: VecDeque<u64>,
+}
+
+impl BitrateMeter {
+    pub fn add(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.started.get_or_insert(now);
+        self.total += bytes;
+        self.recent.push_back((now, bytes));
+        while self
+            .recent
+            .front()
+            .is_some_and(|(time, _)| now.duration_since(*time) > WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.history.push_back(bytes);
+        if self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    // 最近一秒的码率 (bit/s).
+    pub fn current(&self) -> f64 {
+        self.recent.iter().map(|(_, bytes)| *bytes).sum::<u64>() as f64 * 8.0 / WINDOW.as_secs_f64()
+    }
+
+    // 开始以来的平均码率 (bit/s).
+    pub fn average(&self) -> f64 {
+        let seconds = self
+            .started
+            .map_or(0.0, |started| started.elapsed().as_secs_f64());
+        if seconds < WINDOW.as_secs_f64() {
+            return self.current();
+        }
+        self.total as f64 * 8.0 / seconds
+    }
+
+    // 界面上显示的一行, pixels 为每秒编码的像素数, 用来估计画质.
+    pub fn describe(&self, pixels: f64) -> String {
+        let current = self.current();
+        let mut text = format!(
+            "{} now, {} average",
+            format_bitrate(current),
+            format_bitrate(self.average())
+        );
+        if pixels > 0.0 {
+            let bits_per_pixel = current / pixels;
+            text += &format!(
+                ", {:.3} bit/pixel ({})",
+                bits_per_pixel,
+                rating(bits_per_pixel)
+            );
+        }
+        text
+    }
+
+    pub fn sparkline(&self) -> String {
+        sparkline(self.history.iter().copied())
+    }
+}
+
+pub fn format_bitrate(bits: f64) -> String {
+    if bits >= 1e6 {
+        format!("{:.1} Mbit/s", bits / 1e6)
+    } else {
+        format!("{:.0} kbit/s", bits / 1e3)
+    }
+}
+
+// 按 H.264 的经验粗略评价每像素比特数, 繁忙的画面码率不变时每像素比特数下降.
+pub fn rating(bits_per_pixel: f64) -> &'static str {
+    if bits_per_pixel < 0.03 {
+        "starved"
+    } else if bits_per_pixel < 0.07 {
+        "fair"
+    } else {
+        "good"
+    }
+}
+
+// 每个值一个字符, 按最大值缩放到八级方块.
+pub fn sparkline(values: impl Iterator<Item = u64> + Clone) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.clone().max().unwrap_or(0).max(1);
+    values
+        .map(|value| BLOCKS[(value * 7 / max) as usize])
+        .collect()
+}
+
+// 录像的码率: 每秒检查一次正在写入的文件增长了多少. 编码器和封装有缓冲, 只能按秒统计.
+#[derive(Default)]
+pub struct FileMeter {
+    meter: BitrateMeter,
+    files: Vec<PathBuf>,
+    size: u64,
+    last: Option<Instant>,
+}
+
+impl FileMeter {
+    // 每帧调用, 每秒返回一次界面上显示的文字, 录像停止时返回一次空字符串.
+    pub fn update<'a>(
+        &mut self,
+        paths: impl Iterator<Item = &'a Path>,
+        pixels: f64,
+    ) -> Option<String> {
+        if self.last.is_some_and(|last| last.elapsed() < WINDOW) {
+            return None;
+        }
+        self.last = Some(Instant::now());
+        let files: Vec<PathBuf> = paths.map(Path::to_path_buf).collect();
+        let changed = files != self.files;
+        if changed {
+            self.meter.reset();
+            self.size = 0;
+            self.files = files;
+        }
+        if self.files.is_empty() {
+            return changed.then(String::new);
+        }
+        let size = self
+            .files
+            .iter()
+            .filter_map(|path| fs::metadata(path).ok())
+            .map(|metadata| metadata.len())
+            .sum::<u64>();
+        self.meter.add(size.saturating_sub(self.size));
+        self.size = size;
+        Some(format!(
+            "{}\n{}",
+            self.meter.describe(pixels),
+            self.meter.sparkline()
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sparkline_scales_to_max() {
+        assert_eq!(sparkline([0, 100, 50, 700].into_iter()), "▁▂▁█");
+        assert_eq!(sparkline([0, 0].into_iter()), "▁▁");
+        assert_eq!(sparkline(std::iter::empty()), "");
+    }
+
+    #[test]
+    fn describe() {
+        assert_eq!(format_bitrate(2_500_000.0), "2.5 Mbit/s");
+        assert_eq!(format_bitrate(640_000.0), "640 kbit/s");
+        assert_eq!([0.01, 0.05, 0.1].map(rating), ["starved", "fair", "good"]);
+
+        let mut meter = BitrateMeter::default();
+        meter.add(125_000);
+        meter.add(125_000);
+        assert_eq!(meter.current(), 2_000_000.0);
+        assert_eq!(
+            meter.describe(1280.0 * 720.0 * 30.0),
+            "2.0 Mbit/s now, 2.0 Mbit/s average, 0.072 bit/pixel (good)"
+        );
+        assert_eq!(meter.describe(0.0), "2.0 Mbit/s now, 2.0 Mbit/s average");
+        meter.reset();
+        assert_eq!(meter.current(), 0.0);
+    }
+
+    // 录像的码率按文件每秒增长的大小统计, 录像停止时清空一次.
+    #[test]
+    fn file_growth() {
+        let path = std::env::temp_dir().join(format!("bitrate-{}.mp4", std::process::id()));
+        fs::write(&path, vec![0; 1000]).unwrap();
+        let mut meter = FileMeter::default();
+        let text = meter.update([path.as_path()].into_iter(), 0.0).unwrap();
+        assert_eq!(text, "8 kbit/s now, 8 kbit/s average\n█");
+        // 一秒内不再检查.
+        assert_eq!(meter.update([path.as_path()].into_iter(), 0.0), None);
+
+        meter.last = None;
+        assert_eq!(meter.update(std::iter::empty(), 0.0), Some(String::new()));
+        meter.last = None;
+        assert_eq!(meter.update(std::iter::empty(), 0.0), None);
+        fs::remove_file(path).unwrap();
+    }
+}
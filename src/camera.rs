@@ -0,0 +1,507 @@
+use std::{
+    cell::Cell,
+    collections::BTreeMap,
+    fmt, process,
+    rc::Rc,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{self, Mat},
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+use serde::Deserialize;
+use slint::ComponentHandle;
+
+use crate::CameraError;
+
+// OpenCV 的摄像头后端.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    // macOS 上使用 AVFoundation, Android 上使用 NDK Camera2, 其他平台由 OpenCV 选择.
+    #[default]
+    Auto,
+    Any,
+    Msmf,
+    Dshow,
+    Avfoundation,
+    V4l2,
+    // OpenCV Android SDK 中基于 NDK Camera2 的后端.
+    Android,
+}
+
+impl Backend {
+    pub fn api(self) -> i32 {
+        match self {
+            // macOS 上明确使用 AVFoundation, 避免 CAP_ANY 选到其他后端.
+            Backend::Auto if cfg!(target_os = "macos") => videoio::CAP_AVFOUNDATION,
+            Backend::Auto if cfg!(target_os = "android") => videoio::CAP_ANDROID,
+            Backend::Auto | Backend::Any => videoio::CAP_ANY,
+            Backend::Msmf => videoio::CAP_MSMF,
+            Backend::Dshow => videoio::CAP_DSHOW,
+            Backend::Avfoundation => videoio::CAP_AVFOUNDATION,
+            Backend::V4l2 => videoio::CAP_V4L2,
+            Backend::Android => videoio::CAP_ANDROID,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraConfig {
+    pub index: i32,
+    // 按设备名称选择摄像头, 优先于 index.
+    pub device: Option<String>,
+    pub backend: Backend,
+    // 按设备名称单独指定后端, 例如某些摄像头只在 DirectShow 下支持高分辨率.
+    pub backends: BTreeMap<String, Backend>,
+    // 打开后丢弃黑帧的最长时间 (秒), macOS 上摄像头刚打开时会先输出黑帧.
+    pub warmup: f64,
+    // 请求的分辨率, 摄像头不支持时使用最接近的分辨率.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            index: 0,
+            device: None,
+            backend: Backend::default(),
+            backends: BTreeMap::new(),
+            warmup: 2.0,
+            width: None,
+            height: None,
+        }
+    }
+}
+
+// 系统中的一个摄像头, name 为系统显示的友好名称.
+#[derive(Debug, Clone)]
+pub struct Device {
+    pub index: i32,
+    pub name: Option<String>,
+    pub backend: Backend,
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "camera {} ({})", self.index, name),
+            None => write!(f, "camera {}", self.index),
+        }
+    }
+}
+
+// 列出摄像头, 不支持枚举的平台返回空列表.
+pub fn devices(config: &CameraConfig) -> Vec<Device> {
+    let names = device_names().unwrap_or_else(|err| {
+        eprintln!("enumerate cameras: {:?}", err);
+        Vec::new()
+    });
+    named(config, names)
+}
+
+// 按设备名称选择后端.
+fn named(config: &CameraConfig, names: Vec<(i32, String)>) -> Vec<Device> {
+    names
+        .into_iter()
+        .map(|(index, name)| Device {
+            index,
+            backend: config
+                .backends
+                .get(&name)
+                .copied()
+                .unwrap_or(config.backend),
+            name: Some(name),
+        })
+        .collect()
+}
+
+// 不支持枚举的平台上依次尝试打开的摄像头数量.
+const PROBE_COUNT: i32 = 8;
+
+// 列出可以切换的摄像头, 系统不支持枚举时从 0 开始尝试打开, 直到第一个打不开的编号.
+pub fn enumerate(config: &CameraConfig) -> Vec<Device> {
+    let devices = devices(config);
+    if !devices.is_empty() {
+        return devices;
+    }
+    probe(config, |device| {
+        VideoCapture::new(device.index, device.backend.api())
+            .and_then(|camera| camera.is_opened())
+            .unwrap_or(false)
+    })
+}
+
+// 从 0 开始的编号中连续能打开的摄像头.
+fn probe(config: &CameraConfig, opens: impl Fn(&Device) -> bool) -> Vec<Device> {
+    (0..PROBE_COUNT)
+        .map(|index| by_index(config, index))
+        .take_while(opens)
+        .collect()
+}
+
+// 按配置选择要打开的摄像头.
+pub fn select(config: &CameraConfig) -> Result<Device> {
+    match &config.device {
+        Some(name) => by_name(config, name),
+        None => Ok(by_index(config, config.index)),
+    }
+}
+
+// 按编号或名称查找摄像头, 例如 camera://1 或 camera://Logitech BRIO.
+pub fn find(config: &CameraConfig, selector: &str) -> Result<Device> {
+    match selector.parse() {
+        Ok(index) => Ok(by_index(config, index)),
+        Err(_) => by_name(config, selector),
+    }
+}
+
+fn by_name(config: &CameraConfig, name: &str) -> Result<Device> {
+    devices(config)
+        .into_iter()
+        .find(|device| device.name.as_deref() == Some(name))
+        .with_context(|| format!("camera {:?} not found", name))
+}
+
+// 不支持枚举的平台上也可以按编号打开.
+fn by_index(config: &CameraConfig, index: i32) -> Device {
+    devices(config)
+        .into_iter()
+        .find(|device| device.index == index)
+        .unwrap_or(Device {
+            index,
+            name: None,
+            backend: config.backend,
+        })
+}
+
+// Windows 上使用 Media Foundation 枚举, 顺序与 OpenCV MSMF/DirectShow 的编号一致.
+#[cfg(target_os = "windows")]
+fn device_names() -> Result<Vec<(i32, String)>> {
+    use windows::{
+        core::PWSTR,
+        Win32::{
+            Media::MediaFoundation::*,
+            System::Com::{CoInitializeEx, CoTaskMemFree, COINIT_APARTMENTTHREADED},
+        },
+    };
+
+    let mut names = Vec::new();
+    unsafe {
+        // 主线程之后会被 UI 使用, 需要是 STA.
+        let _ = CoInitializeEx(None, COINIT_APARTMENTTHREADED);
+        MFStartup(MF_VERSION, MFSTARTUP_NOSOCKET)?;
+        let mut attributes = None;
+        MFCreateAttributes(&mut attributes, 1)?;
+        let attributes: IMFAttributes = attributes.context("create attributes")?;
+        attributes.SetGUID(
+            &MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE,
+            &MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_GUID,
+        )?;
+        let mut sources: *mut Option<IMFActivate> = std::ptr::null_mut();
+        let mut count = 0;
+        MFEnumDeviceSources(&attributes, &mut sources, &mut count)?;
+        for i in 0..count as usize {
+            let Some(source) = (*sources.add(i)).take() else {
+                continue;
+            };
+            let mut name = PWSTR::null();
+            let mut len = 0;
+            source.GetAllocatedString(
+                &MF_DEVSOURCE_ATTRIBUTE_FRIENDLY_NAME,
+                &mut name,
+                &mut len,
+            )?;
+            names.push((i as i32, name.to_string()?));
+            CoTaskMemFree(Some(name.0 as _));
+        }
+        CoTaskMemFree(Some(sources as _));
+        MFShutdown()?;
+    }
+    Ok(names)
+}
+
+// Linux 上读取 V4L2 设备名称, 编号与 /dev/videoN 一致.
+#[cfg(target_os = "linux")]
+fn device_names() -> Result<Vec<(i32, String)>> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir("/sys/class/video4linux")? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let Some(index) = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix("video"))
+            .and_then(|index| index.parse().ok())
+        else {
+            continue;
+        };
+        let name = std::fs::read_to_string(entry.path().join("name"))?;
+        names.push((index, name.trim().to_string()));
+    }
+    names.sort();
+    Ok(names)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn device_names() -> Result<Vec<(i32, String)>> {
+    Ok(Vec::new())
+}
+
+pub fn set_resolution(camera: &mut VideoCapture, width: i32, height: i32) -> Result<()> {
+    camera.set(videoio::CAP_PROP_FRAME_WIDTH, width as f64)?;
+    camera.set(videoio::CAP_PROP_FRAME_HEIGHT, height as f64)?;
+    Ok(())
+}
+
+// 三个通道的均值都低于该值时认为是黑帧.
+const BLACK_LEVEL: f64 = 2.0;
+
+// 打开摄像头, 失败时弹窗说明原因, 用户可以重试或退出.
+pub fn open(device: &Device) -> Result<VideoCapture> {
+    loop {
+        let message = match try_open(device) {
+            Ok(camera) => return Ok(camera),
+            Err(err) => err.to_string(),
+        };
+        if !ask_retry(&message)? {
+            bail!("Unable to open {}", device);
+        }
+    }
+}
+
+// 打开摄像头并确认能读到帧, 错误说明不能使用的原因. 断开后重新连接时使用, 不弹窗.
+pub fn try_open(device: &Device) -> Result<VideoCapture> {
+    let mut camera = VideoCapture::new(device.index, device.backend.api())?;
+    if !camera.is_opened()? {
+        bail!("{}", open_failure(device));
+    }
+    if !first_frame(&mut camera) {
+        // Windows 上摄像头被其他程序占用时可以打开, 但读不到帧.
+        bail!(
+            "{} is in use by another application. Close the other application, then retry.",
+            device
+        );
+    }
+    Ok(camera)
+}
+
+fn open_failure(device: &Device) -> String {
+    if cfg!(target_os = "macos") {
+        // 首次打开时 AVFoundation 会弹出系统授权对话框, 授权前打开会失败.
+        format!(
+            "{} could not be opened. macOS requires camera permission: allow this \
+             application (or the terminal it was started from) in System Settings > Privacy & \
+             Security > Camera, then retry.",
+            device
+        )
+    } else if cfg!(target_os = "windows") {
+        format!(
+            "{} could not be opened. Check that it is connected, not used by another \
+             application and that camera access is allowed in Settings > Privacy > Camera, \
+             then retry.",
+            device
+        )
+    } else {
+        format!(
+            "{} could not be opened. Check that it is connected and not used by \
+             another application, then retry.",
+            device
+        )
+    }
+}
+
+// 部分摄像头打开后需要一点时间才输出第一帧.
+pub fn first_frame(camera: &mut VideoCapture) -> bool {
+    let start = Instant::now();
+    let mut frame = Mat::default();
+    while start.elapsed() < Duration::from_secs(2) {
+        // 被占用时 read() 可能返回错误, 和读不到帧一样处理.
+        if camera.read(&mut frame).unwrap_or(false) && !frame.empty() {
+            return true;
+        }
+        sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+// 显示提示对话框, 返回用户是否选择重试.
+fn ask_retry(message: &str) -> Result<bool> {
+    let dialog = CameraError::new()?;
+    dialog.set_message(message.into());
+    dialog.set_can_open_settings(cfg!(any(target_os = "macos", target_os = "windows")));
+    let retry = Rc::new(Cell::new(false));
+    let flag = retry.clone();
+    dialog.on_retry(move || {
+        flag.set(true);
+        let _ = slint::quit_event_loop();
+    });
+    dialog.on_quit(|| {
+        let _ = slint::quit_event_loop();
+    });
+    dialog.on_open_settings(|| {
+        if let Err(err) = open_settings() {
+            eprintln!("{:?}", err);
+        }
+    });
+    dialog.run()?;
+    Ok(retry.get())
+}
+
+// 打开系统设置中的摄像头权限页面.
+fn open_settings() -> Result<()> {
+    #[cfg(target_os = "windows")]
+    let mut command = {
+        let mut command = process::Command::new("cmd");
+        command.args(["/C", "start", "ms-settings:privacy-webcam"]);
+        command
+    };
+    #[cfg(not(target_os = "windows"))]
+    let mut command = {
+        let mut command = process::Command::new("open");
+        command.arg("x-apple.systempreferences:com.apple.preference.security?Privacy_Camera");
+        command
+    };
+    command.spawn().context("open camera privacy settings")?;
+    Ok(())
+}
+
+// 丢弃刚打开时的黑帧, 避免录像以黑帧开始. 真实场景全黑时最多等待 timeout 秒.
+pub fn warm_up(camera: &mut VideoCapture, timeout: f64) -> Result<()> {
+    let start = Instant::now();
+    let mut frame = Mat::default();
+    let mut dropped = 0;
+    while start.elapsed().as_secs_f64() < timeout {
+        if !camera.read(&mut frame)? || frame.empty() {
+            sleep(Duration::from_millis(10));
+            continue;
+        }
+        let mean = core::mean(&frame, &core::no_array())?;
+        if mean[0].max(mean[1]).max(mean[2]) > BLACK_LEVEL {
+            break;
+        }
+        dropped += 1;
+    }
+    if dropped > 0 {
+        println!("camera: dropped {} black warmup frames", dropped);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::{
+        core::{Scalar, Size, CV_8UC3},
+        videoio::VideoWriter,
+    };
+
+    use super::*;
+
+    #[cfg(not(any(target_os = "macos", target_os = "android")))]
+    #[test]
+    fn auto_backend() {
+        assert_eq!(Backend::Auto.api(), videoio::CAP_ANY);
+        assert_eq!(Backend::Avfoundation.api(), videoio::CAP_AVFOUNDATION);
+    }
+
+    #[test]
+    fn android_backend() {
+        let config: CameraConfig = toml::from_str("backend = \"android\"").unwrap();
+        assert_eq!(config.backend.api(), videoio::CAP_ANDROID);
+    }
+
+    #[test]
+    fn failure_names_device() {
+        let device = Device {
+            index: 1,
+            name: Some("FaceTime HD Camera".to_string()),
+            backend: Backend::Auto,
+        };
+        let message = open_failure(&device);
+        assert!(message.starts_with("camera 1 (FaceTime HD Camera) could not be opened."));
+        assert!(message.ends_with("then retry."));
+    }
+
+    #[test]
+    fn backend_per_device() {
+        let config: CameraConfig =
+            toml::from_str("backend = \"msmf\"\n[backends]\n\"Logitech BRIO\" = \"dshow\"")
+                .unwrap();
+        let devices = named(
+            &config,
+            vec![
+                (0, "Integrated Camera".to_string()),
+                (1, "Logitech BRIO".to_string()),
+            ],
+        );
+        assert_eq!(devices[0].backend, Backend::Msmf);
+        assert_eq!(devices[1].backend, Backend::Dshow);
+        assert_eq!(devices[1].to_string(), "camera 1 (Logitech BRIO)");
+    }
+
+    // 不在系统摄像头列表中的编号仍然可以打开, 名称必须能找到.
+    #[test]
+    fn find_by_index_or_name() {
+        let config = CameraConfig {
+            backend: Backend::V4l2,
+            ..Default::default()
+        };
+        let device = find(&config, "42").unwrap();
+        assert_eq!((device.index, device.backend), (42, Backend::V4l2));
+        assert_eq!(device.to_string(), "camera 42");
+        let err = find(&config, "No Such Camera").unwrap_err();
+        assert_eq!(err.to_string(), "camera \"No Such Camera\" not found");
+        let config = CameraConfig {
+            device: Some("No Such Camera".to_string()),
+            ..config
+        };
+        assert!(select(&config).is_err());
+    }
+
+    // 三帧黑帧之后是正常画面, 预热后从第一帧正常画面之后开始读.
+    #[test]
+    fn drops_black_warmup_frames() {
+        let path = std::env::temp_dir().join(format!("camera-warmup-{}.avi", process::id()));
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        for level in [0., 0., 0., 100., 200.] {
+            let frame =
+                Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(level)).unwrap();
+            writer.write(&frame).unwrap();
+        }
+        drop(writer);
+        let mut video = VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).unwrap();
+        warm_up(&mut video, 2.0).unwrap();
+        let mut frame = Mat::default();
+        assert!(video.read(&mut frame).unwrap());
+        let mean = core::mean(&frame, &core::no_array()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!((mean[0] - 200.).abs() < 5.);
+    }
+
+    // 不支持枚举时只列出第一个打不开的编号之前的摄像头.
+    #[test]
+    fn probe_until_first_failure() {
+        let config: CameraConfig = toml::from_str("backend = \"v4l2\"").unwrap();
+        let devices = probe(&config, |device| device.index != 2);
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[1].index, 1);
+        assert!(devices
+            .iter()
+            .all(|device| device.backend == config.backend));
+        assert_eq!(probe(&config, |_| true).len(), PROBE_COUNT as usize);
+        assert!(probe(&config, |_| false).is_empty());
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::auth::{Auth, Listener};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptionConfig {
+    // 跟踪文件新增的行, 每行作为一条字幕.
+    pub file: Option<PathBuf>,
+    // 监听 WebSocket, 每条文本消息作为一条字幕. 发送需要 operator 权限.
+    pub websocket: Option<String>,
+    // 字幕显示时长 (秒), 超时后自动消失.
+    pub hold: f64,
+}
+
+impl Default for CaptionConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            websocket: None,
+            hold: 5.0,
+        }
+    }
+}
+
+// 最近一条字幕, 可以在多个线程之间共享.
+#[derive(Clone)]
+pub struct Captions {
+    latest: Arc<Mutex<Option<(String, Instant)>>>,
+    hold: Duration,
+    listener: Arc<Mutex<Option<Listener>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Captions {
+    pub fn start(config: &CaptionConfig, auth: &Auth) -> Result<Self> {
+        let captions = Self {
+            latest: Arc::new(Mutex::new(None)),
+            hold: Duration::from_secs_f64(config.hold),
+            listener: Arc::default(),
+            stopped: Arc::default(),
+        };
+        if let Some(path) = &config.file {
+            let captions = captions.clone();
+            let path = path.clone();
+            spawn(move || {
+                if let Err(err) = captions.tail(&path) {
+                    eprintln!("caption file {}: {:?}", path.display(), err);
+                }
+            });
+        }
+        if let Some(addr) = &config.websocket {
+            captions.listen(addr, auth)?;
+        }
+        Ok(captions)
+    }
+
+    #[cfg(feature = "streaming")]
+    fn listen(&self, addr: &str, auth: &Auth) -> Result<()> {
+        let captions = self.clone();
+        let checker = auth.clone();
+        let listener = auth.listen(addr, "caption websocket", move |stream| {
+            captions.serve(stream, &checker)
+        })?;
+        *self.listener.lock().unwrap() = Some(listener);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "streaming"))]
+    fn listen(&self, addr: &str, _auth: &Auth) -> Result<()> {
+        anyhow::bail!(
+            "caption websocket {} needs the streaming feature, rebuild with --features streaming",
+            addr
+        )
+    }
+
+    // 停止跟踪文件和监听 WebSocket, 关闭 VideoFeed 时调用.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.listener.lock().unwrap().take();
+    }
+
+    pub fn set(&self, text: &str) {
+        let text = text.trim();
+        let mut latest = self.latest.lock().unwrap();
+        *latest = if text.is_empty() {
+            None
+        } else {
+            Some((text.to_string(), Instant::now()))
+        };
+    }
+
+    pub fn current(&self) -> Option<String> {
+        let latest = self.latest.lock().unwrap();
+        latest
+            .as_ref()
+            .filter(|(_, at)| at.elapsed() < self.hold)
+            .map(|(text, _)| text.clone())
+    }
+
+    // 类似 tail -f, 只读取启动后新增的内容, 文件被截断时从头读.
+    fn tail(&self, path: &PathBuf) -> Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut pos = reader.seek(SeekFrom::End(0))?;
+        let mut line = String::new();
+        while !self.stopped.load(Ordering::SeqCst) {
+            line.clear();
+            let n = reader.read_line(&mut line)?;
+            if n == 0 {
+                if std::fs::metadata(path)?.len() < pos {
+                    pos = reader.seek(SeekFrom::Start(0))?;
+                }
+                sleep(Duration::from_millis(200));
+                continue;
+            }
+            pos += n as u64;
+            self.set(&line);
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "streaming")]
+    fn serve(&self, stream: crate::auth::Connection, auth: &Auth) -> Result<()> {
+        let (mut socket, _, _) = auth.accept_websocket(stream, crate::auth::Role::Operator)?;
+        loop {
+            match socket.read()? {
+                tungstenite::Message::Text(text) => self.set(&text),
+                tungstenite::Message::Close(_) => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, io::Write};
+
+    use super::*;
+    use crate::auth::AuthConfig;
+
+    fn start(config: &CaptionConfig) -> Captions {
+        Captions::start(config, &Auth::new(&AuthConfig::default()).unwrap()).unwrap()
+    }
+
+    // 空白的字幕清除当前字幕, 超过 hold 后自动消失.
+    #[test]
+    fn hold() {
+        let captions = start(&CaptionConfig::default());
+        assert_eq!(captions.current(), None);
+        captions.set("  Welcome to the talk \n");
+        assert_eq!(captions.current().as_deref(), Some("Welcome to the talk"));
+        captions.set(" ");
+        assert_eq!(captions.current(), None);
+
+        let short = start(&CaptionConfig {
+            hold: 0.05,
+            ..CaptionConfig::default()
+        });
+        short.set("gone soon");
+        sleep(Duration::from_millis(100));
+        assert_eq!(short.current(), None);
+    }
+
+    // 只显示启动后追加的行, 文件被截断后从头读.
+    #[test]
+    fn tail_file() {
+        let dir = std::env::temp_dir().join(format!("captions-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("captions.txt");
+        fs::write(&path, "old line\n").unwrap();
+        let captions = start(&CaptionConfig {
+            file: Some(path.clone()),
+            ..CaptionConfig::default()
+        });
+        let wait = |expected: &str| {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while captions.current().as_deref() != Some(expected) {
+                assert!(Instant::now() < deadline, "waiting for {:?}", expected);
+                sleep(Duration::from_millis(20));
+            }
+        };
+        sleep(Duration::from_millis(300));
+        assert_eq!(captions.current(), None);
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        writeln!(file, "first caption").unwrap();
+        wait("first caption");
+        fs::write(&path, "new\n").unwrap();
+        wait("new");
+        captions.stop();
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // WebSocket 收到的文本消息作为字幕.
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn websocket() {
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let captions = start(&CaptionConfig {
+            websocket: Some(format!("127.0.0.1:{}", port)),
+            ..CaptionConfig::default()
+        });
+        let (mut socket, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/", port)).unwrap();
+        socket
+            .send(tungstenite::Message::Text("live caption".to_string()))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while captions.current().as_deref() != Some("live caption") {
+            assert!(Instant::now() < deadline);
+            sleep(Duration::from_millis(20));
+        }
+        socket.close(None).unwrap();
+        captions.stop();
+    }
+
+    #[cfg(not(feature = "streaming"))]
+    #[test]
+    fn websocket_needs_streaming() {
+        let err = Captions::start(
+            &CaptionConfig {
+                websocket: Some("127.0.0.1:0".to_string()),
+                ..CaptionConfig::default()
+            },
+            &Auth::new(&AuthConfig::default()).unwrap(),
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("--features streaming"));
+    }
+}
@@ -0,0 +1,2843 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{Receiver, TryRecvError},
+        Arc, Mutex,
+    },
+    thread::{self, sleep, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, MatTraitConst},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use slint::{ComponentHandle, Image, ModelRc, SharedString, Timer, VecModel, Weak};
+
+use crate::{
+    audio::{AudioEffect, AudioOverlay},
+    best_shot,
+    bitrate::FileMeter,
+    captions::Captions,
+    change::ChangeDetection,
+    chroma_key::ChromaKey,
+    clock,
+    color::{self, ColorCorrection},
+    color_match,
+    config::Config,
+    connections::{self, Connection, Jitter, Kind, Status},
+    controls::{self, Property},
+    crash,
+    day_night::{DayNight, Phase},
+    denoise::TemporalDenoise,
+    effects::{Effect, Effects},
+    events::EventStore,
+    exclusions,
+    exif::Exif,
+    feed::{Active, Frame, FrameCallbacks},
+    flat_field::{self, FlatFieldCorrection, FlatFrames},
+    frames::{self, FrameSlot},
+    gauges::GaugeReading,
+    gps::{Gps, Tracks},
+    high_speed::{self, Mode, PreviewPacing},
+    hot_pixels::{DarkFrames, HotPixelCorrection},
+    infrared::Infrared,
+    jobs,
+    lanes::LaneDetection,
+    measure::{Measure, Outcome, Tool},
+    metrics,
+    models::{Downloading, Models},
+    onion::OnionSkin,
+    osd::{OsdInfo, OsdProfile},
+    panorama::Panorama,
+    photo::{self, Shoot},
+    pipeline::{Failure, Pipeline},
+    playback::Playback,
+    printer::{PrintEvent, PrintTimelapse},
+    privacy,
+    profile::CameraProfile,
+    projection::PlanarProjection,
+    queue::QueueLength,
+    raw::{self, DepthMapping},
+    reaction::ReactionMonitor,
+    review::Review,
+    routing::{self, Router},
+    scenes::Crop,
+    session::{Replay, SessionLog},
+    sink::{self, SinkInfo, Sinks},
+    source::{self, FrameSource},
+    speed::SpeedCalibration,
+    stages::Stages,
+    stats,
+    still::Burst,
+    stop_motion::{self, StopMotion},
+    summary::Summary,
+    supervisor::{Event, Supervisor},
+    sync::{self, Manifests},
+    tamper::TamperDetection,
+    telemetry::{Sidecars, Telemetry},
+    throttle,
+    thumbnail::{Thumbnail, Thumbnails},
+    timeshift::{LoopMark, TimeShift},
+    trajectory::Trajectories,
+    waveform,
+    white_balance::{self, WhiteBalance},
+    zones::Zone,
+    Main,
+};
+
+// 拍照后显示的小图宽度和显示时长.
+const PHOTO_PREVIEW_WIDTH: i32 = 200;
+const PHOTO_PREVIEW_TIME: Duration = Duration::from_secs(3);
+
+// UI 发送给采集线程的命令.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    Exit,
+    CalibrateColor,
+    ResetColor,
+    // 点击位置, 取值 0..1, 相对于画面宽高.
+    WhiteBalance(f32, f32),
+    ResetWhiteBalance,
+    CalibrateHotPixels,
+    ResetHotPixels,
+    CaptureFlatField,
+    ToggleFlatField,
+    // 测量工具及标定参考长度 (um).
+    MeasureTool(Tool, f64),
+    MeasureClick(f32, f32),
+    CloseArea,
+    ExportMeasurements,
+    StartPanorama,
+    SavePanorama,
+    CancelPanorama,
+    HighQualityStill,
+    // 叠加当前帧, 载入参考图像, 设置不透明度, 关闭叠加.
+    OnionHold,
+    OnionLoad(String),
+    OnionOpacity(f64),
+    OnionClear,
+    StopMotionCapture,
+    StopMotionDelete,
+    // 开始或停止回放, 帧率.
+    StopMotionPlay(bool, f64),
+    // 导出视频的帧率.
+    StopMotionExport(f64),
+    // 时移: 暂停, 回到实时画面, 前后移动若干帧, 跳到时间线位置 (0..1).
+    TimeShiftPause,
+    TimeShiftLive,
+    TimeShiftStep(i64),
+    TimeShiftSeek(f64),
+    // 在当前帧设置 A 点或 B 点, 取消循环, 回放速度倍数.
+    TimeShiftMark,
+    TimeShiftClearLoop,
+    TimeShiftSpeed(f64),
+    // 连拍, 自拍定时.
+    TakePhoto(bool, bool),
+    // 高位深显示映射: 曝光补偿 (EV) 和 gamma.
+    DepthMapping(f64, f64),
+    SaveRawStill,
+    CaptureReference,
+    ClearReference,
+    // 变化检测的模糊核大小和阈值.
+    ChangeParams(i32, f64),
+    Lanes(bool),
+    // 绿幕抠像: 开关, 显示 alpha 通道, 溢色抑制和边缘溢色抑制的强度.
+    ChromaKey(bool),
+    ChromaMatte(bool),
+    ChromaSpill(f64, f64),
+    Speed(bool),
+    // 开始速度标定, 参数为两点之间的实际距离 (米).
+    SpeedCalibrate(f64),
+    SpeedClick(f32, f32),
+    // 平面投影: 开始点选平面的四个角, 点选, 投影的图片或视频, 关闭投影.
+    ProjectionPick,
+    ProjectionClick(f32, f32),
+    ProjectionContent(String),
+    ProjectionClear,
+    Dwell(bool),
+    // 区域状态机 (门开关, 工位占用).
+    Occupancy(bool),
+    Tracking(bool),
+    // 目标检测叠加 (人脸或检测模型).
+    Detection(bool),
+    // 野生动物预设: 运动触发分类并记录目击.
+    Wildlife(bool),
+    Meteor(bool),
+    // 仪表读数预设.
+    Gauges(bool),
+    // 排队长度: 开关, 开始和结束画区域, 点选区域顶点.
+    Queue(bool),
+    QueueDraw(bool),
+    QueueClick(f32, f32),
+    // 停车位预设.
+    Parking(bool),
+    Intrusion(bool),
+    // 颜色变化监测: 开关, 开始点选区域的两个角, 点选.
+    Reaction(bool),
+    ReactionPick,
+    ReactionClick(f32, f32),
+    // 3D 打印监控: 开关失败检测, 打印机 webhook 通知.
+    Printer(bool),
+    Print(PrintEvent),
+    // 劳保用品检测预设.
+    Ppe(bool),
+    // 跌倒检测: 开关, 确认报警.
+    Fall(bool),
+    FallAcknowledge,
+    Monitor(bool),
+    Tamper(bool),
+    ExportTrajectories,
+    ClearTrajectories,
+    // 导出或清空长时间统计 (平均图, 最大值, 运动频率).
+    SummaryExport,
+    SummaryReset,
+    // 来源 URI, 例如 "camera://1".
+    SetSource(String),
+    // 文件来源: 暂停, 继续播放, 跳到位置 (0..1).
+    PlaybackPause,
+    PlaybackResume,
+    PlaybackSeek(f64),
+    // 跳到下一个 (true) 或上一个事件, 自动跳过没有事件的片段.
+    PlaybackEvent(bool),
+    PlaybackSkipQuiet(bool),
+    // 按名称重建处理管线.
+    Pipeline(Vec<String>),
+    // 按顺序执行的画面效果, 为空时移除.
+    Effects(Vec<Effect>),
+    // 随声音变化的叠加效果: 开关, 效果, 灵敏度.
+    AudioOverlay(bool, AudioEffect, f64),
+    // 开始或停止录制到主 sinks.
+    Record(bool),
+    // 录像时域降噪的强度, 0 为关闭.
+    Denoise(f64),
+    // 高速采集, 录制为慢动作.
+    HighSpeed(bool),
+    // 请求的分辨率和帧率, 摄像头不支持时使用最接近的值.
+    CameraMode(i32, i32, f64),
+    // 曝光, 亮度, 对比度和增益, 数值直接交给后端.
+    CameraControl(Property, f64),
+    // 按名称切换 [[scenes]] 中的场景.
+    Scene(String),
+    // 固定使用白天或夜晚的参数, None 为自动切换.
+    DayNight(Option<Phase>),
+    // 排除区域: 开始和结束画区域, 点选区域顶点, 清除全部.
+    ExclusionDraw(bool),
+    ExclusionClick(f32, f32),
+    ClearExclusions,
+}
+
+pub struct Capture {
+    pub source: Box<dyn FrameSource>,
+    pub frame_width: f64,
+    pub frame_height: f64,
+    pub fps: f64,
+    // 高速模式下录制文件的播放帧率, None 时与 fps 相同.
+    pub playback_fps: Option<f64>,
+    pub preview_osd: OsdProfile,
+    pub recording_osd: OsdProfile,
+    pub captions: Captions,
+    pub telemetry: Telemetry,
+    pub gps: Option<Gps>,
+    pub profile: CameraProfile,
+    pub profile_dir: PathBuf,
+    pub config: Config,
+    pub events: EventStore,
+    pub window: Weak<Main>,
+    // 回放录制的会话, 此时 camera 为会话视频.
+    pub replay: Option<Replay>,
+    pub callbacks: FrameCallbacks,
+    // 当前来源的 URI.
+    pub uri: String,
+    pub active: Arc<Mutex<Active>>,
+    pub thumbnail: Thumbnail,
+    // 后台下载的模型结束时置位, 见 Stages::models_ready.
+    pub models_ready: Arc<AtomicBool>,
+}
+
+impl Capture {
+    pub fn start(
+        self,
+        frames: FrameSlot,
+        command_receiver: Receiver<Command>,
+    ) -> JoinHandle<Result<()>> {
+        thread::Builder::new()
+            .name(crash::CAPTURE_THREAD.to_string())
+            .spawn(move || self.run(frames, command_receiver))
+            .expect("spawn capture thread")
+    }
+
+    fn run(mut self, frames: FrameSlot, commands: Receiver<Command>) -> Result<()> {
+        // 退出或 panic 时 sinks 被 drop, mp4 文件已经完整.
+        let _finalized = crash::FinalizeGuard;
+        self.config.threads.capture.apply("capture");
+        // 开始录制时才打开 sinks, 每次录制生成新的文件.
+        let mut sinks = Sinks::default();
+        self.recordings();
+
+        let mut state = State {
+            pipeline: Pipeline::default(),
+            dark_frames: None,
+            flat_frames: None,
+            measure: Measure::new(self.profile.um_per_px),
+            panorama: None,
+            burst: None,
+            shoot: None,
+            onion: OnionSkin::default(),
+            onion_hold: false,
+            stop_motion: None,
+            stop_motion_capture: false,
+            timeshift: TimeShift::new(&self.config.timeshift, self.preview_fps()),
+            playback: self.new_playback(),
+            review: Review::default(),
+            skip_quiet: false,
+            recording: false,
+            normal_mode: None,
+            pacing: PreviewPacing::new(self.config.preview_fps),
+            scene: None,
+            detections: None,
+            raw: Mat::default(),
+            depth: DepthMapping::new(&self.config.raw),
+            reference: None,
+            capture_reference: false,
+            change_blur: 5,
+            change_threshold: 30.0,
+            speed_calibration: None,
+            surface_clicks: None,
+            projection_surface: self.config.projection.corners.clone(),
+            projection_content: self.config.projection.content.clone(),
+            queue_clicks: None,
+            queue_zone: None,
+            exclusion_clicks: None,
+            reaction_clicks: None,
+            reaction_region: self.config.reaction.roi,
+            print_timelapse: None,
+            trajectories: Trajectories::default(),
+            summary: self
+                .config
+                .summary
+                .enabled
+                .then(|| Summary::new(&self.config.summary)),
+            router: self.router(),
+            sidecars: Sidecars::default(),
+            tracks: Tracks::default(),
+            sync: Manifests::new(&self.config.sync),
+            denoise: TemporalDenoise::new(&self.config.denoise),
+            day_night: DayNight::new(&self.config.day_night)?,
+            infrared: Infrared::new(&self.config.infrared),
+            bitrate: FileMeter::default(),
+            jitter: Jitter::default(),
+        };
+        state.pipeline = self.stages().pipeline(&state.trajectories)?;
+        state.pacing = self.normal_pacing(&state.pipeline);
+        self.publish(&state);
+        self.playback(state.playback.as_ref());
+        self.waveform(state.playback.as_ref());
+        self.review(&mut state);
+        self.controls();
+        privacy::audit("capture", true, &self.uri);
+
+        let mut thumbnails = Thumbnails::new(&self.config.thumbnail, self.thumbnail.clone());
+        let mut frame_bgr = Mat::default();
+        let mut frame_osd = Mat::default();
+        // 实际帧率, 用指数平滑避免 OSD 上的数字跳动.
+        let mut measured_fps = self.fps;
+        let mut last_frame = Instant::now();
+        let mut frame_index = 0u64;
+        let mut supervisor = Supervisor::new(&self.config.reconnect);
+        // 来源断开期间收到的命令, 读到帧后再执行.
+        let mut pending = None;
+        let mut session = None;
+        if self.config.session.enabled {
+            let size = core::Size2i::new(self.frame_width as i32, self.frame_height as i32);
+            let (log, path) =
+                SessionLog::create(&self.config.session, self.fps, size, &self.profile)?;
+            self.status(format!("Recording session to {}", path.display()));
+            session = Some(log);
+        }
+        loop {
+            let command = match pending.take() {
+                Some(command) => Some(command),
+                None => match commands.try_recv() {
+                    Ok(command) => Some(command),
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                },
+            };
+            if let Some(Command::Exit) = command {
+                break;
+            }
+            // 界面线程 panic 时尽快关闭录像文件.
+            if crash::stopping() {
+                break;
+            }
+            self.reconnect_requests(&mut sinks, &mut state);
+            if self.models_ready.swap(false, Ordering::Relaxed) {
+                self.models_ready(&mut state);
+            }
+
+            // 暂停的文件来源重复处理同一帧, 这些帧不录制也不计入帧号.
+            let mut still = false;
+            if self.config.raw.enabled {
+                // 保留原始 16 位帧用于保存照片, 之后的处理都在映射后的 8 位图像上进行.
+                if !self.read(&mut state.raw)? {
+                    pending = command;
+                    self.source_failed(&mut supervisor, &mut pending, &mut sinks, &mut state);
+                    continue;
+                }
+                if state.raw.depth() == core::CV_16U {
+                    state.depth.apply(&state.raw, &mut frame_bgr)?;
+                } else {
+                    state.raw.copy_to(&mut frame_bgr)?;
+                }
+            } else {
+                // 暂停或播放结束的文件重复处理同一帧, 不再读取.
+                if let Some(playback) = &mut state.playback {
+                    playback.pace();
+                    if let Some(held) = playback.held() {
+                        held.copy_to(&mut frame_bgr)?;
+                        still = true;
+                    }
+                }
+                if !still {
+                    if !self.read(&mut frame_bgr)? {
+                        if self.replay.is_some() {
+                            self.status("Replay finished".to_string());
+                            break;
+                        }
+                        let ended = match (&mut state.playback, self.source.capture()) {
+                            (Some(playback), Some(camera)) => playback.end(camera)?,
+                            _ => false,
+                        };
+                        if ended {
+                            self.status(format!("End of {}", self.uri));
+                            self.playback(state.playback.as_ref());
+                        }
+                        if state.playback.is_none() {
+                            pending = command;
+                            self.source_failed(
+                                &mut supervisor,
+                                &mut pending,
+                                &mut sinks,
+                                &mut state,
+                            );
+                        }
+                        continue;
+                    }
+                    if let Some(playback) = &mut state.playback {
+                        playback.read(&frame_bgr)?;
+                    }
+                }
+                let report = match (&mut state.playback, self.source.capture()) {
+                    (Some(playback), Some(camera)) => playback.report(camera)?,
+                    _ => None,
+                };
+                if let Some(report) = report {
+                    self.playback_position(report);
+                    self.skip_quiet(&mut state)?;
+                }
+            }
+
+            // 外部数据按读取帧的时间对应.
+            let captured = chrono::Local::now();
+            if self.config.sync.enabled && !still {
+                let window = self.config.sync.window;
+                if let Err(err) = sync::add(routing::MAIN, captured, &frame_bgr, window) {
+                    eprintln!("sync: {:?}", err);
+                }
+            }
+            if !still && supervisor.frame() {
+                self.source_recovered(&mut supervisor, &mut sinks, &mut state);
+            }
+            if !still && connections::is_network(&self.uri) {
+                let jitter = state.jitter.frame();
+                self.connection(|connection| {
+                    connection.set_status(Status::Connected);
+                    connection.jitter = Some(jitter);
+                });
+            }
+            let size = frame_bgr.size()?;
+            if !still && size != self.sink_info().size {
+                self.resolution_changed(size, &mut sinks, &mut state);
+            }
+
+            // 回放时按原始帧率播放, 并在记录的帧号执行命令.
+            let mut due = Vec::new();
+            if let Some(replay) = &mut self.replay {
+                let interval = Duration::from_secs_f64(1.0 / replay.fps.max(1.0));
+                if let Some(wait) = interval.checked_sub(last_frame.elapsed()) {
+                    sleep(wait);
+                }
+                due = replay.commands(frame_index);
+            }
+            due.extend(command);
+            if let Some(session) = &mut session {
+                if !still {
+                    session.frame(&frame_bgr)?;
+                }
+                for command in &due {
+                    session.command(frame_index, command)?;
+                }
+            }
+            let index = frame_index;
+            if !still {
+                frame_index += 1;
+                stats::frame();
+            }
+
+            // 校准需要未经处理的原始帧.
+            let changed = !due.is_empty();
+            for command in due {
+                if let Command::SetSource(uri) = command {
+                    // 新来源的分辨率可能不同, 当前帧丢弃, 录像文件重新打开.
+                    match self.set_source(&uri, &mut state) {
+                        Ok(()) if state.recording => {
+                            // 先关闭旧文件, 新文件可能使用同一个文件名.
+                            sinks = Sinks::default();
+                            sinks = self.sinks();
+                        }
+                        Ok(()) => {}
+                        Err(err) => self.status(format!("Open source failed: {}", err)),
+                    }
+                    continue;
+                }
+                if let Command::HighSpeed(enabled) = command {
+                    // 分辨率和帧率改变, 录像文件和路由重新打开.
+                    match self.set_high_speed(enabled, &mut state) {
+                        Ok(()) if state.recording => {
+                            sinks = Sinks::default();
+                            sinks = self.sinks();
+                        }
+                        Ok(()) => {}
+                        Err(err) => {
+                            self.status(format!("High-speed mode failed: {}", err));
+                            let high_speed = state.normal_mode.is_some();
+                            let _ = self.window.upgrade_in_event_loop(move |window| {
+                                window.set_high_speed_enabled(high_speed)
+                            });
+                        }
+                    }
+                    continue;
+                }
+                if let Command::CameraMode(width, height, fps) = command {
+                    // 与高速模式一样, 分辨率改变后录像文件和路由重新打开.
+                    match self.set_mode(width, height, fps, &mut state) {
+                        Ok(()) if state.recording => {
+                            sinks = Sinks::default();
+                            sinks = self.sinks();
+                        }
+                        Ok(()) => {}
+                        Err(err) => {
+                            self.status(format!("Change camera mode failed: {}", err));
+                            self.controls();
+                        }
+                    }
+                    continue;
+                }
+                if let Command::Scene(name) = command {
+                    // 场景可能切换来源, 与 SetSource 一样重新打开录像文件.
+                    match self.set_scene(&name, &mut state) {
+                        Ok(true) if state.recording => {
+                            sinks = Sinks::default();
+                            sinks = self.sinks();
+                        }
+                        Ok(_) => {}
+                        Err(err) => self.status(format!("Switch scene failed: {}", err)),
+                    }
+                    continue;
+                }
+                if let Command::Record(enabled) = command {
+                    let was_recording = state.recording;
+                    // drop 时 sinks 写入文件尾.
+                    sinks = Sinks::default();
+                    if enabled {
+                        sinks = self.sinks();
+                    } else if state.recording {
+                        self.status("Recording stopped".to_string());
+                    }
+                    state.recording = !sinks.is_empty();
+                    if state.recording != was_recording {
+                        privacy::audit("recording", state.recording, &self.uri);
+                    }
+                    self.recording(state.recording);
+                    continue;
+                }
+                self.handle(command, &frame_bgr, &mut state)?;
+            }
+            if changed {
+                self.publish(&state);
+            }
+            self.calibrate(&frame_bgr, &mut state)?;
+            // 按处理前的画面判断白天和夜晚.
+            match state.day_night.update(&frame_bgr) {
+                Ok(Some(phase)) => self.day_night(phase, false, &mut state),
+                Ok(None) => {}
+                Err(err) => eprintln!("day/night: {:?}", err),
+            }
+            match state.infrared.update(&frame_bgr) {
+                Ok(Some(active)) => self.infrared(active, &state),
+                Ok(None) => {}
+                Err(err) => eprintln!("infrared: {:?}", err),
+            }
+            // 重建管线后同样暂停.
+            state.pipeline.skip(state.infrared.skipped());
+            if !still {
+                state.router.process(&frame_bgr);
+            }
+
+            state.pipeline.process(&mut frame_bgr);
+            for failure in state.pipeline.take_failures() {
+                self.quarantined(&failure, &state);
+            }
+            state.infrared.colorize(&mut frame_bgr)?;
+            if state.router.color_match && !still {
+                let interval = self.config.color_match.interval;
+                if let Err(err) = color_match::publish(&frame_bgr, interval) {
+                    eprintln!("color match: {:?}", err);
+                }
+            }
+            if let Some(summary) = &mut state.summary {
+                if !still {
+                    if let Err(err) = summary.add(&frame_bgr) {
+                        eprintln!("summary: {:?}", err);
+                    }
+                }
+                if summary.export_due() {
+                    self.export_summary(summary);
+                }
+            }
+            if state.capture_reference {
+                state.capture_reference = false;
+                let stage =
+                    ChangeDetection::new(&frame_bgr, state.change_blur, state.change_threshold)?;
+                state.pipeline.set(Box::new(stage));
+                state.reference = Some(frame_bgr.try_clone()?);
+                self.status("Reference frame captured".to_string());
+            }
+            if state.onion_hold {
+                state.onion_hold = false;
+                state.onion.set(&frame_bgr)?;
+                self.status("Overlaying the held frame".to_string());
+            }
+            if state.stop_motion_capture {
+                state.stop_motion_capture = false;
+                self.capture_stop_motion(&frame_bgr, &mut state);
+            }
+            if !still {
+                self.callbacks.call(&Frame {
+                    index,
+                    time: chrono::Local::now(),
+                    image: &frame_bgr,
+                });
+            }
+            if let Some(panorama) = &mut state.panorama {
+                panorama.add(&frame_bgr)?;
+            }
+            if let Some(burst) = &mut state.burst {
+                if burst.add(&frame_bgr)? {
+                    let burst = state.burst.take().unwrap();
+                    self.status("Merging high-quality still...".to_string());
+                    jobs::spawn(
+                        &self.window,
+                        "High-quality still",
+                        &self.config.threads.workers,
+                        move |_| {
+                            let path = burst.merge()?;
+                            Ok(format!("Still saved to {}", path.display()))
+                        },
+                    );
+                }
+            }
+            if let Some(shoot) = &mut state.shoot {
+                let deferred = shoot.deferred();
+                let pipeline = &state.pipeline;
+                match shoot.add(&frame_bgr, || self.photo_metadata(captured, pipeline)) {
+                    Ok(None) => {}
+                    Ok(Some(saved)) => {
+                        if !deferred {
+                            self.status(photo::describe(saved));
+                        }
+                        self.photo_preview(&frame_bgr);
+                        if let Some(shoot) = state.shoot.take().filter(|_| deferred) {
+                            self.process_photos(shoot);
+                        }
+                    }
+                    Err(err) => {
+                        self.status(format!("Take photo failed: {}", err));
+                        state.shoot = None;
+                    }
+                }
+            }
+
+            let now = Instant::now();
+            let elapsed = now.duration_since(last_frame).as_secs_f64();
+            last_frame = now;
+            if elapsed > 0.0 {
+                measured_fps = measured_fps * 0.9 + (1.0 / elapsed) * 0.1;
+            }
+            let detections = state.pipeline.detections();
+            if detections != state.detections {
+                state.detections = detections;
+                self.detections(detections);
+            }
+            // 预览, 录像和 sidecar 使用同一个样本.
+            let sample = self.telemetry.at(captured);
+            let fix = self.gps.as_ref().and_then(|gps| gps.at(captured));
+            let info = OsdInfo {
+                time: chrono::Local::now(),
+                frame: index,
+                fps: measured_fps,
+                detections: detections.unwrap_or(0),
+                caption: self.captions.current(),
+                telemetry: sample
+                    .as_ref()
+                    .map(|sample| self.telemetry.describe(sample)),
+                gps: self
+                    .gps
+                    .as_ref()
+                    .zip(fix.as_ref())
+                    .map(|(gps, (_, fix))| gps.describe(fix)),
+                clock: clock::state(),
+            };
+
+            // 时域降噪默认只处理录像, 在预览之后执行, 预览画面不受影响.
+            let denoise = !still && (state.recording || self.config.denoise.preview);
+            if !denoise {
+                state.denoise.reset();
+            } else if self.config.denoise.preview {
+                if let Err(err) = state.denoise.apply(&mut frame_bgr) {
+                    eprintln!("denoise: {:?}", err);
+                }
+            }
+
+            // 高速模式下只显示部分帧, 每一帧都会录制.
+            if state.pacing.due() {
+                // 预览和录制使用各自的 OSD profile, 在原始帧的副本上绘制.
+                // 测量标注只出现在预览中.
+                let preview = if self.preview_osd.is_empty()
+                    && !state.measure.has_overlay()
+                    && state.panorama.is_none()
+                    && state.shoot.is_none()
+                    && !state.onion.is_active()
+                    && !state.pipeline.has_overlay()
+                {
+                    &frame_bgr
+                } else {
+                    frame_bgr.copy_to(&mut frame_osd)?;
+                    // 叠加在 OSD 之下, 避免文字也被半透明化.
+                    state.onion.draw(&mut frame_osd)?;
+                    state.pipeline.draw_overlay(&mut frame_osd)?;
+                    self.preview_osd.draw(&mut frame_osd, &info)?;
+                    state.measure.draw(&mut frame_osd)?;
+                    if let Some(panorama) = &state.panorama {
+                        panorama.draw(&mut frame_osd)?;
+                    }
+                    if let Some(shoot) = &state.shoot {
+                        shoot.draw(&mut frame_osd)?;
+                    }
+                    &frame_osd
+                };
+                // 缓存带 OSD 的预览画面, 回看时时间等信息与当时一致.
+                if let Err(err) = state.timeshift.push(preview) {
+                    eprintln!("time shift: {:?}", err);
+                }
+                if state.timeshift.is_paused() {
+                    self.timeline(&state.timeshift);
+                }
+                let preview = match state.timeshift.frame() {
+                    Ok(Some(shifted)) => shifted,
+                    Ok(None) => preview,
+                    Err(err) => {
+                        eprintln!("time shift: {:?}", err);
+                        preview
+                    }
+                };
+                // 定格动画回放时代替实时画面.
+                let preview = match &state.stop_motion {
+                    Some(stop_motion) => stop_motion.playback().unwrap_or(preview),
+                    None => preview,
+                };
+                // 直接转换为 Slint 显示的 RGBA 像素格式, 界面没来得及显示的上一帧被丢弃.
+                frames.publish(preview)?;
+            }
+            metrics::gauge("capture_fps", "Measured capture frame rate", measured_fps);
+
+            if denoise && !self.config.denoise.preview {
+                if let Err(err) = state.denoise.apply(&mut frame_bgr) {
+                    eprintln!("denoise: {:?}", err);
+                }
+            }
+            let recording = draw_osd(&self.recording_osd, &frame_bgr, &mut frame_osd, &info)?;
+            if let Err(err) = thumbnails.update(recording) {
+                eprintln!("thumbnail: {:?}", err);
+            }
+            if !still {
+                sinks.write(recording);
+                if self.telemetry.sidecar {
+                    state
+                        .sidecars
+                        .write(sinks.paths(), index, captured, sample.as_ref());
+                }
+                if self.gps.as_ref().is_some_and(|gps| gps.gpx) {
+                    state.tracks.write(sinks.paths(), fix.as_ref());
+                }
+                state.sync.write(sinks.paths(), &self.uri);
+                // 按采集帧率计算每像素比特数, 高速模式下文件的播放帧率较低.
+                let pixels = self.frame_width * self.frame_height * self.fps;
+                if let Some(text) = state.bitrate.update(sinks.paths(), pixels) {
+                    let _ = self.window.upgrade_in_event_loop(move |window| {
+                        window.set_recording_bitrate(text.into())
+                    });
+                }
+            }
+        }
+        // 校准完成时也会改变管线, 退出时再更新一次.
+        self.publish(&state);
+        if state.recording {
+            privacy::audit("recording", false, &self.uri);
+        }
+        privacy::audit("capture", false, &self.uri);
+        Ok(())
+    }
+
+    fn handle(&mut self, command: Command, frame: &Mat, state: &mut State) -> Result<()> {
+        let pipeline = &mut state.pipeline;
+        match command {
+            Command::CalibrateColor => match color::calibrate(frame) {
+                Ok(matrix) => {
+                    pipeline.set(Box::new(ColorCorrection::new(&matrix)?));
+                    self.profile.color_matrix = Some(matrix);
+                    self.save_profile("Color calibration saved");
+                }
+                Err(err) => self.status(format!("Color calibration failed: {}", err)),
+            },
+            Command::ResetColor => {
+                pipeline.remove("color-correction");
+                self.profile.color_matrix = None;
+                self.save_profile("Color correction reset");
+            }
+            Command::WhiteBalance(x, y) => {
+                let x = (x * frame.cols() as f32) as i32;
+                let y = (y * frame.rows() as f32) as i32;
+                let gains = match white_balance::measure(
+                    frame,
+                    x,
+                    y,
+                    self.config.white_balance.sample_size,
+                ) {
+                    Ok(gains) => gains,
+                    Err(err) => {
+                        self.status(format!("White balance failed: {}", err));
+                        return Ok(());
+                    }
+                };
+                // 摄像头支持时优先调整硬件白平衡, 否则在软件中乘以增益.
+                if self.config.white_balance.push_to_camera
+                    && self.source.capture().map_or(Ok(false), |camera| {
+                        white_balance::push_to_camera(camera, &gains)
+                    })?
+                {
+                    pipeline.remove("white-balance");
+                    self.profile.white_balance = None;
+                    self.status("White balance applied to camera".to_string());
+                    return Ok(());
+                }
+                pipeline.set(Box::new(WhiteBalance::new(&gains)?));
+                self.profile.white_balance = Some(gains);
+                self.save_profile("White balance saved");
+            }
+            Command::ResetWhiteBalance => {
+                pipeline.remove("white-balance");
+                self.profile.white_balance = None;
+                if self.config.white_balance.push_to_camera {
+                    if let Some(camera) = self.source.capture() {
+                        white_balance::reset_camera(camera)?;
+                    }
+                }
+                self.save_profile("White balance reset");
+            }
+            Command::CalibrateHotPixels => {
+                state.dark_frames = Some(DarkFrames::new(&self.config.hot_pixels));
+                self.status("Capturing dark frames, keep the lens covered".to_string());
+            }
+            Command::ResetHotPixels => {
+                pipeline.remove("hot-pixels");
+                self.profile.hot_pixels.clear();
+                self.save_profile("Hot pixel map reset");
+            }
+            Command::CaptureFlatField => {
+                state.flat_frames = Some(FlatFrames::new(&self.config.flat_field));
+                self.status("Capturing flat-field reference, keep the target still".to_string());
+            }
+            Command::ToggleFlatField => {
+                if self.profile.flat_field {
+                    pipeline.remove("flat-field");
+                    self.profile.flat_field = false;
+                    self.save_profile("Flat-field correction disabled");
+                } else {
+                    match flat_field::load(&self.profile_dir) {
+                        Ok(flat) => {
+                            pipeline.set(Box::new(FlatFieldCorrection::new(&flat)?));
+                            self.profile.flat_field = true;
+                            self.save_profile("Flat-field correction enabled");
+                        }
+                        Err(err) => self.status(format!("Flat-field correction: {}", err)),
+                    }
+                }
+            }
+            Command::MeasureTool(tool, reference_um) => state.measure.set_tool(tool, reference_um),
+            Command::MeasureClick(x, y) => {
+                let p = core::Point::new(
+                    (x * frame.cols() as f32) as i32,
+                    (y * frame.rows() as f32) as i32,
+                );
+                match state.measure.click(p) {
+                    Some(Outcome::Calibrated(scale)) => {
+                        self.profile.um_per_px = Some(scale);
+                        self.save_profile(&format!("Scale calibrated: {:.4} um/px", scale));
+                    }
+                    Some(Outcome::Measured(text)) => self.status(text),
+                    None => {}
+                }
+            }
+            Command::CloseArea => {
+                if let Some(text) = state.measure.close_area() {
+                    self.status(text);
+                }
+            }
+            Command::ExportMeasurements => {
+                let path = PathBuf::from(format!(
+                    "measurements-{}.csv",
+                    chrono::Local::now().format("%Y-%m-%dT%H-%M-%S")
+                ));
+                match state.measure.export_csv(&path) {
+                    Ok(n) => {
+                        self.status(format!("{} measurements exported to {}", n, path.display()))
+                    }
+                    Err(err) => self.status(format!("Export measurements failed: {}", err)),
+                }
+            }
+            Command::StartPanorama => {
+                state.panorama = Some(Panorama::new());
+                self.status("Panorama started, pan the camera slowly".to_string());
+            }
+            Command::SavePanorama => {
+                if let Some(panorama) = state.panorama.take() {
+                    self.status(format!("Stitching {} frames...", panorama.len()));
+                    jobs::spawn(
+                        &self.window,
+                        "Panorama",
+                        &self.config.threads.workers,
+                        move |_| {
+                            let path = panorama.stitch()?;
+                            Ok(format!("Panorama saved to {}", path.display()))
+                        },
+                    );
+                }
+            }
+            Command::CancelPanorama => {
+                state.panorama = None;
+                self.status("Panorama cancelled".to_string());
+            }
+            Command::HighQualityStill => {
+                if state.burst.is_none() {
+                    state.burst = Some(Burst::new(&self.config.still));
+                    self.status("Capturing burst, hold the camera still".to_string());
+                }
+            }
+            Command::DepthMapping(exposure, gamma) => state.depth.set(exposure, gamma),
+            // 叠加处理后的画面, 与预览中看到的一致.
+            Command::OnionHold => state.onion_hold = true,
+            Command::OnionLoad(path) => match state.onion.load(&path) {
+                Ok(()) => self.status(format!("Overlaying {}", path)),
+                Err(err) => self.status(format!("Load overlay failed: {}", err)),
+            },
+            Command::OnionOpacity(opacity) => state.onion.opacity = opacity,
+            Command::OnionClear => state.onion.clear(),
+            // 和洋葱皮一样使用处理后的画面.
+            Command::StopMotionCapture => state.stop_motion_capture = true,
+            Command::StopMotionDelete => self.delete_stop_motion(state),
+            Command::StopMotionPlay(playing, fps) => {
+                let Some(stop_motion) = &mut state.stop_motion else {
+                    self.status("No stop-motion frames captured".to_string());
+                    return Ok(());
+                };
+                if !playing {
+                    stop_motion.stop();
+                } else if let Err(err) = stop_motion.play(fps) {
+                    self.status(format!("Playback failed: {}", err));
+                }
+            }
+            Command::StopMotionExport(fps) => {
+                let Some(stop_motion) = &state.stop_motion else {
+                    self.status("No stop-motion frames captured".to_string());
+                    return Ok(());
+                };
+                let frames = stop_motion.frames();
+                let path = stop_motion.export_path();
+                let fourcc = self.config.stop_motion.fourcc.clone();
+                self.status(format!("Exporting {} frames...", frames.len()));
+                jobs::spawn(
+                    &self.window,
+                    "Stop-motion export",
+                    &self.config.threads.workers,
+                    move |job| {
+                        stop_motion::export(&frames, &path, fps, &fourcc, |progress| {
+                            job.progress(Some(progress))
+                        })?;
+                        Ok(format!("Stop motion exported to {}", path.display()))
+                    },
+                );
+            }
+            Command::TimeShiftPause => state.timeshift.pause(),
+            Command::TimeShiftLive => {
+                state.timeshift.live();
+                self.timeline(&state.timeshift);
+            }
+            Command::TimeShiftStep(frames) => state.timeshift.step(frames),
+            Command::TimeShiftSeek(fraction) => state.timeshift.seek(fraction),
+            Command::TimeShiftMark => match state.timeshift.mark() {
+                Some(LoopMark::A) => self.status("Loop start set, mark the end".to_string()),
+                Some(LoopMark::B) => self.status("Looping the marked section".to_string()),
+                None => self.status("Nothing buffered yet".to_string()),
+            },
+            Command::TimeShiftClearLoop => {
+                state.timeshift.clear_loop();
+                self.timeline(&state.timeshift);
+            }
+            Command::TimeShiftSpeed(speed) => state.timeshift.set_speed(speed),
+            Command::Denoise(strength) => state.denoise.set_strength(strength),
+            Command::PlaybackPause => {
+                if let Some(playback) = &mut state.playback {
+                    playback.pause();
+                }
+                self.playback(state.playback.as_ref());
+            }
+            Command::PlaybackResume => {
+                if let (Some(playback), Some(camera)) = (&mut state.playback, self.source.capture())
+                {
+                    playback.resume(camera)?;
+                }
+                self.playback(state.playback.as_ref());
+            }
+            Command::PlaybackSeek(fraction) => {
+                let seeked = match (&mut state.playback, self.source.capture()) {
+                    (Some(playback), Some(camera)) => playback.seek(camera, fraction)?,
+                    _ => false,
+                };
+                if !seeked {
+                    self.status("This source cannot seek".to_string());
+                }
+                self.playback(state.playback.as_ref());
+            }
+            Command::PlaybackEvent(forward) => {
+                let seconds = match (&state.playback, self.source.capture()) {
+                    (Some(playback), Some(camera)) => playback.seconds(camera)?,
+                    _ => None,
+                };
+                let Some((played, total)) = seconds else {
+                    return Ok(());
+                };
+                let target = if forward {
+                    state.review.next(played)
+                } else {
+                    state.review.previous(played)
+                };
+                let Some(target) = target else {
+                    self.status(format!(
+                        "No {} event in this recording",
+                        if forward { "next" } else { "previous" }
+                    ));
+                    return Ok(());
+                };
+                if let (Some(playback), Some(camera)) = (&mut state.playback, self.source.capture())
+                {
+                    playback.seek(camera, target / total)?;
+                }
+                self.playback(state.playback.as_ref());
+            }
+            Command::PlaybackSkipQuiet(enabled) => state.skip_quiet = enabled,
+            Command::TakePhoto(burst, timer) => {
+                if state.shoot.is_some() {
+                    return Ok(());
+                }
+                match Shoot::new(&self.config.photo, burst, timer) {
+                    Ok(shoot) => state.shoot = Some(shoot),
+                    Err(err) => self.status(format!("Take photo failed: {}", err)),
+                }
+            }
+            Command::SaveRawStill => {
+                if state.raw.empty() || state.raw.depth() != core::CV_16U {
+                    self.status("Camera is not delivering high bit-depth frames".to_string());
+                    return Ok(());
+                }
+                match raw::save_still(&state.raw, &self.config.raw) {
+                    Ok(path) => self.status(format!("16-bit still saved to {}", path.display())),
+                    Err(err) => self.status(format!("Save 16-bit still failed: {}", err)),
+                }
+            }
+            // 参考帧取自处理后的下一帧, 先移除旧的比较阶段避免把标注也当成参考.
+            Command::CaptureReference => {
+                pipeline.remove("change-detection");
+                state.capture_reference = true;
+            }
+            Command::ClearReference => {
+                pipeline.remove("change-detection");
+                state.reference = None;
+            }
+            Command::ChangeParams(blur, threshold) => {
+                state.change_blur = blur;
+                state.change_threshold = threshold;
+                if let Some(reference) = &state.reference {
+                    pipeline.set(Box::new(ChangeDetection::new(reference, blur, threshold)?));
+                }
+            }
+            Command::Lanes(enabled) => {
+                if enabled {
+                    pipeline.set(Box::new(LaneDetection::new(&self.config.lanes)));
+                } else {
+                    pipeline.remove("lanes");
+                }
+            }
+            Command::ChromaKey(enabled) => {
+                self.config.chroma_key.enabled = enabled;
+                self.chroma_key(pipeline)?;
+            }
+            Command::ChromaMatte(matte_view) => {
+                self.config.chroma_key.matte_view = matte_view;
+                self.chroma_key(pipeline)?;
+            }
+            Command::ChromaSpill(spill, edge_despill) => {
+                self.config.chroma_key.spill = spill;
+                self.config.chroma_key.edge_despill = edge_despill;
+                self.chroma_key(pipeline)?;
+            }
+            Command::Speed(enabled) => {
+                self.config.speed.enabled = enabled;
+                if enabled {
+                    self.speed(pipeline);
+                } else {
+                    pipeline.remove("speed");
+                }
+            }
+            Command::SpeedCalibrate(meters) => {
+                state.speed_calibration = Some(SpeedCalibration::new(meters));
+                self.status(format!("Click two points {} m apart", meters));
+            }
+            Command::SpeedClick(x, y) => {
+                let Some(calibration) = &mut state.speed_calibration else {
+                    return Ok(());
+                };
+                let p = core::Point::new(
+                    (x * frame.cols() as f32) as i32,
+                    (y * frame.rows() as f32) as i32,
+                );
+                if let Some(scale) = calibration.click(p) {
+                    state.speed_calibration = None;
+                    self.profile.meters_per_px = Some(scale);
+                    if self.config.speed.enabled {
+                        self.speed(pipeline);
+                    }
+                    self.save_profile(&format!("Speed calibrated: {:.5} m/px", scale));
+                    let _ = self
+                        .window
+                        .upgrade_in_event_loop(|window| window.set_calibrating_speed(false));
+                }
+            }
+            Command::ProjectionPick => {
+                state.surface_clicks = Some(Vec::new());
+                self.status(
+                    "Click the corners of the surface: top left, top right, bottom right, bottom left"
+                        .to_string(),
+                );
+            }
+            Command::ProjectionClick(x, y) => {
+                let Some(clicks) = &mut state.surface_clicks else {
+                    return Ok(());
+                };
+                clicks.push([x as f64, y as f64]);
+                if clicks.len() == 4 {
+                    state.projection_surface = state.surface_clicks.take().unwrap_or_default();
+                    let _ = self
+                        .window
+                        .upgrade_in_event_loop(|window| window.set_picking_surface(false));
+                    self.project(state);
+                }
+            }
+            Command::ProjectionContent(path) => {
+                state.projection_content = Some(PathBuf::from(path));
+                self.project(state);
+            }
+            Command::ProjectionClear => {
+                state.pipeline.remove("projection");
+                state.projection_surface.clear();
+                state.surface_clicks = None;
+            }
+            Command::Dwell(enabled) => {
+                if !enabled {
+                    pipeline.remove("dwell");
+                } else {
+                    match self.stages().dwell_analytics() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("dwell"),
+                        Err(err) => {
+                            self.status(format!("Dwell analytics failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_dwell_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Occupancy(enabled) => {
+                if !enabled {
+                    pipeline.remove("occupancy");
+                } else {
+                    match self.stages().occupancy() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("occupancy"),
+                        Err(err) => {
+                            self.status(format!("Zone states failed: {}", err));
+                            let _ = self.window.upgrade_in_event_loop(|window| {
+                                window.set_occupancy_enabled(false)
+                            });
+                        }
+                    }
+                }
+            }
+            Command::Tracking(enabled) => {
+                if !enabled {
+                    pipeline.remove("tracking");
+                } else {
+                    match self.stages().object_tracking(&state.trajectories) {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("tracking"),
+                        Err(err) => {
+                            self.status(format!("Object tracking failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_tracking_enabled(false));
+                        }
+                    }
+                }
+            }
+            // 找不到级联文件或模型时只提示, 不影响采集.
+            Command::Detection(enabled) => {
+                if !enabled {
+                    pipeline.remove("detection");
+                } else {
+                    match self.stages().object_detection() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("detection"),
+                        Err(err) => {
+                            self.status(format!("Object detection failed: {}", err));
+                            let _ = self.window.upgrade_in_event_loop(|window| {
+                                window.set_detection_enabled(false)
+                            });
+                        }
+                    }
+                }
+            }
+            Command::Wildlife(enabled) => {
+                if !enabled {
+                    pipeline.remove("wildlife");
+                } else {
+                    match self.stages().wildlife() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("wildlife"),
+                        Err(err) => {
+                            self.status(format!("Wildlife preset failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_wildlife_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Meteor(enabled) => {
+                if !enabled {
+                    pipeline.remove("meteor");
+                } else {
+                    match self.stages().meteor_detection() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) => {
+                            self.status(format!("Meteor detection failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_meteor_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Gauges(enabled) => {
+                if !enabled {
+                    pipeline.remove("gauges");
+                } else {
+                    match GaugeReading::new(&self.config.gauges) {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) => {
+                            self.status(format!("Gauge reading failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_gauges_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Queue(enabled) => {
+                if enabled {
+                    self.queue(state);
+                } else {
+                    state.pipeline.remove("queue");
+                }
+            }
+            Command::QueueDraw(true) => {
+                state.queue_clicks = Some(Vec::new());
+                self.status("Click the corners of the queue zone, then finish drawing".to_string());
+            }
+            Command::QueueDraw(false) => {
+                let points = state.queue_clicks.take().unwrap_or_default();
+                if points.len() < 3 {
+                    self.status("The queue zone needs at least 3 corners".to_string());
+                    return Ok(());
+                }
+                let name = self.config.queue.zone.clone();
+                state.queue_zone = Some(Zone {
+                    name: name.unwrap_or_else(|| "queue".to_string()),
+                    points,
+                });
+                let _ = self
+                    .window
+                    .upgrade_in_event_loop(|window| window.set_queue_enabled(true));
+                self.queue(state);
+            }
+            Command::QueueClick(x, y) => {
+                if let Some(clicks) = &mut state.queue_clicks {
+                    clicks.push([x as f64, y as f64]);
+                }
+            }
+            Command::Parking(enabled) => {
+                if !enabled {
+                    pipeline.remove("parking");
+                } else {
+                    match self.stages().parking() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("parking"),
+                        Err(err) => {
+                            self.status(format!("Parking spaces failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_parking_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Intrusion(enabled) => {
+                if !enabled {
+                    pipeline.remove("intrusion");
+                } else {
+                    match self.stages().intrusion() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("intrusion"),
+                        Err(err) => {
+                            self.status(format!("Intrusion detection failed: {}", err));
+                            let _ = self.window.upgrade_in_event_loop(|window| {
+                                window.set_intrusion_enabled(false)
+                            });
+                        }
+                    }
+                }
+            }
+            Command::Reaction(enabled) => {
+                if enabled {
+                    self.react(state);
+                } else {
+                    state.pipeline.remove("reaction");
+                }
+            }
+            Command::ReactionPick => {
+                state.reaction_clicks = Some(Vec::new());
+                self.status("Click two opposite corners of the region to watch".to_string());
+            }
+            Command::ReactionClick(x, y) => {
+                let Some(clicks) = &mut state.reaction_clicks else {
+                    return Ok(());
+                };
+                clicks.push([x as f64, y as f64]);
+                if let [a, b] = clicks[..] {
+                    state.reaction_clicks = None;
+                    state.reaction_region = [
+                        a[0].min(b[0]),
+                        a[1].min(b[1]),
+                        (a[0] - b[0]).abs(),
+                        (a[1] - b[1]).abs(),
+                    ];
+                    let _ = self.window.upgrade_in_event_loop(|window| {
+                        window.set_picking_reaction(false);
+                        window.set_reaction_enabled(true);
+                    });
+                    self.react(state);
+                }
+            }
+            Command::Printer(enabled) => {
+                if !enabled {
+                    pipeline.remove("printer");
+                } else {
+                    match self.stages().printer() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("printer"),
+                        Err(err) => {
+                            self.status(format!("3D printer preset failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_printer_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Print(event) => self.print(event, frame, state),
+            Command::Ppe(enabled) => {
+                if !enabled {
+                    pipeline.remove("ppe");
+                } else {
+                    match self.stages().ppe() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("ppe"),
+                        Err(err) => {
+                            self.status(format!("Safety gear preset failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_ppe_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Fall(enabled) => {
+                if !enabled {
+                    pipeline.remove("fall");
+                } else {
+                    match self.stages().fall_detection() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) if err.is::<Downloading>() => pipeline.wait("fall"),
+                        Err(err) => {
+                            self.status(format!("Fall detection failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_fall_enabled(false));
+                        }
+                    }
+                }
+            }
+            // 确认后重新创建阶段, 停止报警升级并重新开始检测.
+            Command::FallAcknowledge => {
+                if pipeline.names().contains(&"fall") {
+                    self.events.log("fall-acknowledged", json!({}));
+                    pipeline.remove("fall");
+                    match self.stages().fall_detection() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) => {
+                            self.status(format!("Fall detection failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_fall_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::Monitor(enabled) => {
+                if !enabled {
+                    pipeline.remove("monitor");
+                } else {
+                    match self.stages().monitor() {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) => {
+                            self.status(format!("Monitor preset failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_monitor_enabled(false));
+                        }
+                    }
+                }
+                // 高速模式有自己的预览帧率.
+                if state.normal_mode.is_none() {
+                    state.pacing = self.normal_pacing(pipeline);
+                }
+            }
+            Command::Tamper(enabled) => {
+                if !enabled {
+                    pipeline.remove("tamper");
+                } else {
+                    match TamperDetection::new(&self.config.tamper, self.events.clone()) {
+                        Ok(stage) => pipeline.set(Box::new(stage)),
+                        Err(err) => {
+                            self.status(format!("Tamper detection failed: {}", err));
+                            let _ = self
+                                .window
+                                .upgrade_in_event_loop(|window| window.set_tamper_enabled(false));
+                        }
+                    }
+                }
+            }
+            Command::ExportTrajectories => {
+                let stem = format!(
+                    "trajectories-{}",
+                    chrono::Local::now().format("%Y-%m-%dT%H-%M-%S")
+                );
+                match state.trajectories.export(&stem, frame) {
+                    Ok(n) => self.status(format!("{} trajectories exported to {}.*", n, stem)),
+                    Err(err) => self.status(format!("Export trajectories failed: {}", err)),
+                }
+            }
+            Command::ClearTrajectories => {
+                state.trajectories.clear();
+                self.status("Trajectories cleared".to_string());
+            }
+            Command::SummaryExport => match &mut state.summary {
+                Some(summary) => self.export_summary(summary),
+                None => self.status("Summary is not enabled".to_string()),
+            },
+            Command::SummaryReset => {
+                if let Some(summary) = &mut state.summary {
+                    summary.reset();
+                    self.status("Summary reset".to_string());
+                }
+            }
+            Command::Effects(effects) => {
+                if effects.is_empty() {
+                    pipeline.remove("effects");
+                } else {
+                    pipeline.set(Box::new(Effects::new(&effects)));
+                }
+            }
+            Command::AudioOverlay(enabled, effect, sensitivity) => {
+                let overlay = &mut self.config.audio.overlay;
+                (overlay.enabled, overlay.effect, overlay.sensitivity) =
+                    (enabled, effect, sensitivity);
+                self.audio_overlay(pipeline);
+            }
+            Command::Pipeline(names) => {
+                let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+                match self.stages().build(&names, &state.trajectories) {
+                    Ok(built) => *pipeline = built,
+                    Err(err) => self.status(format!("Set pipeline failed: {}", err)),
+                }
+            }
+            Command::ExclusionDraw(true) => {
+                state.exclusion_clicks = Some(Vec::new());
+                self.status(
+                    "Click the corners of the glare region, then finish drawing".to_string(),
+                );
+            }
+            Command::ExclusionDraw(false) => {
+                let points = state.exclusion_clicks.take().unwrap_or_default();
+                if points.len() < 3 {
+                    self.status("The exclusion needs at least 3 corners".to_string());
+                    return Ok(());
+                }
+                exclusions::add(Zone {
+                    name: "drawn".to_string(),
+                    points,
+                });
+                self.status(format!(
+                    "{} regions excluded from metering and motion",
+                    exclusions::regions().len()
+                ));
+            }
+            Command::ExclusionClick(x, y) => {
+                if let Some(clicks) = &mut state.exclusion_clicks {
+                    clicks.push([x as f64, y as f64]);
+                }
+            }
+            Command::ClearExclusions => {
+                exclusions::set(Vec::new());
+                self.status("Exclusions cleared".to_string());
+            }
+            Command::DayNight(phase) => {
+                if let Some(phase) = state.day_night.fix(phase) {
+                    self.day_night(phase, true, state);
+                }
+            }
+            Command::CameraControl(property, value) => {
+                if !self
+                    .source
+                    .reconfigure(property.id(), value)
+                    .unwrap_or(false)
+                {
+                    self.status(format!("The camera ignores {}", property.label()));
+                }
+                self.controls();
+            }
+            Command::Exit
+            | Command::SetSource(_)
+            | Command::Record(_)
+            | Command::HighSpeed(_)
+            | Command::CameraMode(..)
+            | Command::Scene(_) => {}
+        }
+        Ok(())
+    }
+
+    // 拍照后在画面角落显示几秒刚保存的照片.
+    // 在后台线程写文件, 统计继续进行.
+    fn export_summary(&self, summary: &mut Summary) {
+        let snapshot = match summary.snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                self.status(format!("Export summary failed: {}", err));
+                return;
+            }
+        };
+        self.status(format!(
+            "Exporting summary of {} samples...",
+            summary.samples()
+        ));
+        jobs::spawn(
+            &self.window,
+            "Summary export",
+            &self.config.threads.workers,
+            move |_| {
+                let stem = snapshot.export()?;
+                Ok(format!("Summary exported to {}-*.png", stem.display()))
+            },
+        );
+    }
+
+    // 照片的元数据, 摄像头设置在保存时读取.
+    fn photo_metadata(
+        &mut self,
+        captured: chrono::DateTime<chrono::Local>,
+        pipeline: &Pipeline,
+    ) -> Exif {
+        let values = controls::current(self.source.as_mut());
+        let settings = values
+            .iter()
+            .map(|(property, value)| format!("{} {}", property.label(), value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Exif {
+            time: Some(captured),
+            camera: Some(self.source.metadata().description.clone()),
+            settings: (!settings.is_empty()).then_some(settings),
+            exposure: values
+                .iter()
+                .find(|(property, _)| *property == Property::Exposure)
+                .map(|(_, value)| controls::exposure_seconds(*value)),
+            gps: self
+                .gps
+                .as_ref()
+                .and_then(|gps| gps.at(captured))
+                .map(|(_, fix)| fix),
+            filters: pipeline.filters(),
+        }
+    }
+
+    // 照片在后台任务中处理后才写入文件, 采集和录像不等待.
+    // 挑选最好一张的连拍先打分, 在选择窗口中确认后由界面保存.
+    fn process_photos(&self, shoot: Shoot) {
+        let best_of_burst = shoot.best_of_burst();
+        let photos = shoot.into_pending();
+        if best_of_burst {
+            let config = self.config.photo.best_shot.clone();
+            let window = self.window.clone();
+            jobs::spawn(
+                &self.window,
+                "Burst selection",
+                &self.config.threads.workers,
+                move |job| {
+                    let count = photos.len();
+                    let (thumbnails, scores, best) = best_shot::score(&config, photos, job)?;
+                    let _ = window.upgrade_in_event_loop(move |window| {
+                        let shots: Vec<Image> =
+                            thumbnails.into_iter().map(Image::from_rgba8).collect();
+                        let scores: Vec<SharedString> =
+                            scores.iter().map(|score| score.describe().into()).collect();
+                        window.set_burst_shots(ModelRc::new(VecModel::from(shots)));
+                        window.set_burst_scores(ModelRc::new(VecModel::from(scores)));
+                        window.set_burst_selected(best as i32);
+                        window.set_burst_open(true);
+                    });
+                    Ok(format!("Pick the best of {} shots", count))
+                },
+            );
+            return;
+        }
+        self.status(format!("Processing {} photos...", photos.len()));
+        let config = self.config.photo.clone();
+        let models = Models::new(&self.config.models_dir, &self.config.models);
+        jobs::spawn(
+            &self.window,
+            "Photo processing",
+            &self.config.threads.workers,
+            move |job| photo::save(&config, &models, photos, job),
+        );
+    }
+
+    fn photo_preview(&self, frame: &Mat) {
+        let buffer = match frames::thumbnail(frame, PHOTO_PREVIEW_WIDTH) {
+            Ok(buffer) => buffer,
+            Err(err) => {
+                eprintln!("photo preview: {:?}", err);
+                return;
+            }
+        };
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_photo_preview(Image::from_rgba8(buffer));
+            window.set_photo_preview_visible(true);
+            let weak = window.as_weak();
+            Timer::single_shot(PHOTO_PREVIEW_TIME, move || {
+                if let Some(window) = weak.upgrade() {
+                    window.set_photo_preview_visible(false);
+                }
+            });
+        });
+    }
+
+    // 界面上的检测计数, 只在数量变化时更新.
+    fn detections(&self, count: Option<usize>) {
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_detections(count.map_or(-1, |count| count as i32));
+        });
+    }
+
+    // 更新崩溃报告和 VideoFeed::active 中的来源和管线.
+    // 切换到白天或夜晚的一组参数, 没有设置的保持不变.
+    fn day_night(&mut self, phase: Phase, manual: bool, state: &mut State) {
+        let profile = state.day_night.config().profile(phase).clone();
+        for (property, value) in &profile.controls {
+            if !self
+                .source
+                .reconfigure(property.id(), *value)
+                .unwrap_or(false)
+            {
+                self.status(format!("The camera ignores {}", property.label()));
+            }
+        }
+        if !profile.controls.is_empty() {
+            self.controls();
+        }
+        if let Some(strength) = profile.denoise {
+            state.denoise.set_strength(strength);
+            let _ = self.window.upgrade_in_event_loop(move |window| {
+                window.set_denoise_strength(strength.clamp(0.0, 0.95) as f32)
+            });
+        }
+        if let Some(min_score) = profile.min_score {
+            self.config.detection.min_score = min_score;
+            if state.pipeline.names().contains(&"detection") {
+                match self.stages().object_detection() {
+                    Ok(stage) => state.pipeline.set(Box::new(stage)),
+                    Err(err) => self.status(format!("Object detection failed: {}", err)),
+                }
+            }
+        }
+        match &profile.effects {
+            Some(effects) if effects.is_empty() => state.pipeline.remove("effects"),
+            Some(effects) => state.pipeline.set(Box::new(Effects::new(effects))),
+            None => {}
+        }
+        self.events.set_phase(phase);
+        self.events.log(
+            "day-night",
+            json!({ "phase": phase.as_str(), "manual": manual }),
+        );
+        self.publish(state);
+        self.status(format!("Switched to the {} profile", phase.as_str()));
+        let _ = self
+            .window
+            .upgrade_in_event_loop(move |window| window.set_day_night_phase(phase.as_str().into()));
+    }
+
+    // 摄像头切换到红外或回到彩色画面.
+    fn infrared(&self, active: bool, state: &State) {
+        self.events
+            .set_phase(if active { Phase::Night } else { Phase::Day });
+        let names = state.pipeline.names();
+        let skipped = state
+            .infrared
+            .config()
+            .skip
+            .iter()
+            .filter(|name| names.contains(&name.as_str()))
+            .collect::<Vec<_>>();
+        self.events
+            .log("infrared", json!({ "active": active, "skipped": skipped }));
+        self.status(if active {
+            "The camera switched to infrared, color stages are paused".to_string()
+        } else {
+            "The camera switched back to color".to_string()
+        });
+    }
+
+    // 处理阶段反复出错, 已被跳过, 其余阶段照常运行.
+    fn quarantined(&self, failure: &Failure, state: &State) {
+        self.events.log(
+            "quarantine",
+            json!({ "stage": failure.stage, "error": failure.error }),
+        );
+        self.status(format!(
+            "{} bypassed after repeated errors: {}",
+            failure.stage, failure.error
+        ));
+        self.publish(state);
+    }
+
+    fn publish(&self, state: &State) {
+        let warning = state
+            .pipeline
+            .quarantined()
+            .iter()
+            .map(|failure| format!("{} bypassed: {}", failure.stage, failure.error))
+            .collect::<Vec<_>>()
+            .join("; ");
+        let _ = self
+            .window
+            .upgrade_in_event_loop(move |window| window.set_warning(warning.into()));
+        let names = state.pipeline.names();
+        crash::set_state("pipeline", names.join(", "));
+        let mut active = self.active.lock().unwrap();
+        active.source = self.uri.clone();
+        active.pipeline = names.iter().map(|name| name.to_string()).collect();
+        active.scene = state.scene.clone();
+    }
+
+    // 普通模式的预览节奏, 监护模式下降低帧率以省电.
+    fn normal_pacing(&self, pipeline: &Pipeline) -> PreviewPacing {
+        let mut fps = self.config.preview_fps;
+        if pipeline.names().contains(&"monitor") {
+            let monitor = self.config.monitor.preview_fps;
+            fps = Some(fps.map_or(monitor, |fps| fps.min(monitor)));
+        }
+        PreviewPacing::new(fps)
+    }
+
+    // 预览实际显示的帧率, 时移缓存按它计算长度.
+    fn preview_fps(&self) -> f64 {
+        self.config
+            .preview_fps
+            .map_or(self.fps, |fps| fps.min(self.fps))
+    }
+
+    // 高速模式下录制文件按较低的帧率播放, 得到慢动作.
+    fn sink_info(&self) -> SinkInfo {
+        SinkInfo {
+            fps: self.playback_fps.unwrap_or(self.fps),
+            size: core::Size2i::new(self.frame_width as i32, self.frame_height as i32),
+        }
+    }
+
+    // 打开失败或换用了其他编码时在状态栏提示, 避免录制出空文件而不自知.
+    fn sinks(&self) -> Sinks {
+        let sinks = Sinks::open(routing::main_sinks(&self.config), &self.sink_info());
+        for path in sinks.paths() {
+            stats::file(path);
+        }
+        if !sinks.is_empty() {
+            self.status("Recording started".to_string());
+        }
+        for message in &sinks.messages {
+            self.status(message.clone());
+        }
+        sinks
+    }
+
+    // 录制结束时的统计, 追加到 log 并显示在窗口中.
+    fn recording_report(&self) {
+        let Some(recording) = stats::end_recording() else {
+            return;
+        };
+        if !self.config.stats.enabled {
+            return;
+        }
+        let report = recording.report("Recording", chrono::Local::now());
+        if let Err(err) = stats::append(&self.config.stats, &report) {
+            eprintln!("session log: {:?}", err);
+        }
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_recording_report(report.trim_end().into())
+        });
+    }
+
+    // 分辨率和帧率随来源变化, 更新估算的文件大小.
+    fn recordings(&self) {
+        let info = self.sink_info();
+        let recordings: Vec<String> = routing::main_sinks(&self.config)
+            .map(|config| sink::describe(config, &info))
+            .collect();
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            let recordings: Vec<SharedString> = recordings.into_iter().map(Into::into).collect();
+            window.set_recordings(ModelRc::new(VecModel::from(recordings)));
+        });
+    }
+
+    // 打开失败时界面上的录制按钮恢复为未录制.
+    fn recording(&self, recording: bool) {
+        let was = std::mem::replace(&mut self.active.lock().unwrap().recording, recording);
+        if recording && !was {
+            stats::begin_recording();
+        } else if was && !recording {
+            self.recording_report();
+        }
+        throttle::set_recording(recording);
+        let _ = self
+            .window
+            .upgrade_in_event_loop(move |window| window.set_recording(recording));
+    }
+
+    // 路由出错时只影响路由本身, 预览和录制继续运行.
+    fn router(&self) -> Router {
+        let router = Router::open(&self.stages(), &self.sink_info());
+        let active = router.is_active();
+        let _ = self
+            .window
+            .upgrade_in_event_loop(move |window| window.set_routes_active(active));
+        router
+    }
+
+    // 实时来源读取出错和空帧都当作没有新帧, 由 supervisor 判断是否断开.
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        let live = self.source.metadata().live && self.replay.is_none();
+        match self.source.read(frame) {
+            Ok(_) => Ok(!frame.empty()),
+            Err(_) if live => {
+                *frame = Mat::default();
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    // 实时来源没有读到帧. 断开时结束录制, mp4 在 sinks drop 时写入文件尾保持完整,
+    // 之后按退避时间重新打开来源. 断开期间可以切换到其他来源.
+    fn source_failed(
+        &mut self,
+        supervisor: &mut Supervisor,
+        pending: &mut Option<Command>,
+        sinks: &mut Sinks,
+        state: &mut State,
+    ) {
+        if !self.source.metadata().live || self.replay.is_some() {
+            return;
+        }
+        stats::dropped(supervisor.missed(self.fps));
+        let description = self.source.metadata().description.clone();
+        match supervisor.failure() {
+            Event::None => {}
+            Event::Disconnected => {
+                supervisor.recording = state.recording;
+                if state.recording {
+                    *sinks = Sinks::default();
+                    state.recording = false;
+                    self.recording(false);
+                    privacy::audit("recording", false, &self.uri);
+                }
+                privacy::audit("capture", false, &self.uri);
+                let timeout = self.config.reconnect.timeout;
+                self.connection(|connection| {
+                    connection.set_status(Status::Reconnecting);
+                    connection.error(format!("no frames for {}s", timeout));
+                });
+                state.jitter.reset();
+                let problem = format!("{} disconnected, retrying…", description);
+                self.status(problem.clone());
+                self.source_problem(problem);
+            }
+            Event::Retry(attempt) => match source::reopen(&self.uri, &self.config) {
+                Ok(source) => match self.reconnect(source, state) {
+                    Ok(()) => self.connection(|connection| connection.reconnects += 1),
+                    Err(err) => eprintln!("reconnect {}: {:?}", self.uri, err),
+                },
+                Err(err) => {
+                    eprintln!("reconnect {}: {:?}", self.uri, err);
+                    self.connection(|connection| connection.error(format!("{:#}", err)));
+                    let problem = format!(
+                        "{} disconnected, retrying in {}s (attempt {})…",
+                        description,
+                        supervisor.backoff().as_secs_f64().round(),
+                        attempt
+                    );
+                    self.status(problem.clone());
+                    self.source_problem(problem);
+                }
+            },
+        }
+        if supervisor.is_disconnected() {
+            if let Some(Command::SetSource(uri)) = pending.take() {
+                // 切换到其他来源时不再恢复录制.
+                match self.set_source(&uri, state) {
+                    Ok(()) => {
+                        supervisor.frame();
+                        supervisor.recording = false;
+                        self.source_problem(String::new());
+                    }
+                    Err(err) => self.status(format!("Open source failed: {}", err)),
+                }
+            }
+        }
+        // 避免断开期间空转.
+        sleep(Duration::from_millis(50));
+    }
+
+    // 网络来源在连接面板中的状态, 其他来源不显示.
+    fn connection(&self, update: impl FnOnce(&mut Connection)) {
+        if connections::is_network(&self.uri) {
+            connections::update(&self.uri, Kind::Source, update);
+        }
+    }
+
+    // 连接面板中的重新连接按钮. 来源重新打开; 路由来源和网络输出重新打开所有路由,
+    // 正在录制时主 sinks 也重新打开, 录制到新文件.
+    fn reconnect_requests(&mut self, sinks: &mut Sinks, state: &mut State) {
+        if connections::take_reconnect(&self.uri) {
+            let result = source::reopen(&self.uri, &self.config)
+                .and_then(|source| self.reconnect(source, state));
+            match result {
+                Ok(()) => {
+                    state.jitter.reset();
+                    self.connection(|connection| connection.reconnects += 1);
+                    self.status(format!("{} reconnected", self.uri));
+                }
+                Err(err) => {
+                    self.status(format!("Reconnect {} failed: {}", self.uri, err));
+                    self.connection(|connection| connection.error(format!("{:#}", err)));
+                }
+            }
+        }
+        let routes = connections::take_reconnect_kind(Kind::Route);
+        let outputs = connections::take_reconnect_kind(Kind::Sink);
+        if routes || outputs {
+            state.router = Router::default();
+            state.router = self.router();
+        }
+        if outputs && state.recording {
+            *sinks = Sinks::default();
+            *sinks = self.sinks();
+            state.recording = !sinks.is_empty();
+            self.recording(state.recording);
+        }
+    }
+
+    // 重新打开后分辨率不变时只替换来源, 处理管线, 路由和录制设置保持不变.
+    fn reconnect(&mut self, source: Box<dyn FrameSource>, state: &mut State) -> Result<()> {
+        let metadata = source.metadata();
+        if metadata.width == self.frame_width && metadata.height == self.frame_height {
+            self.fps = metadata.fps;
+            self.source = source;
+            self.controls();
+            return Ok(());
+        }
+        let uri = self.uri.clone();
+        self.use_source(&uri, source, state)
+    }
+
+    // 来源重新读到帧, 断开前正在录制时录制到新文件.
+    fn source_recovered(
+        &mut self,
+        supervisor: &mut Supervisor,
+        sinks: &mut Sinks,
+        state: &mut State,
+    ) {
+        let description = self.source.metadata().description.clone();
+        self.source_problem(String::new());
+        privacy::audit("capture", true, &self.uri);
+        if std::mem::take(&mut supervisor.recording) && self.config.reconnect.resume_recording {
+            *sinks = self.sinks();
+            state.recording = !sinks.is_empty();
+            self.recording(state.recording);
+            if state.recording {
+                privacy::audit("recording", true, &self.uri);
+            }
+        }
+        self.status(format!("{} reconnected", description));
+    }
+
+    // 来源自己改变了分辨率 (摄像头重新协商, 网络流切换码率), 按新尺寸重建状态.
+    // 正在录制时换到新文件, 编码器不支持中途改变尺寸.
+    fn resolution_changed(&mut self, size: core::Size, sinks: &mut Sinks, state: &mut State) {
+        let description = self.source.metadata().description.clone();
+        self.frame_width = size.width as f64;
+        self.frame_height = size.height as f64;
+        self.mode_changed(self.preview_fps(), state);
+        if state.recording {
+            *sinks = self.sinks();
+            state.recording = !sinks.is_empty();
+            self.recording(state.recording);
+            if !state.recording {
+                privacy::audit("recording", false, &self.uri);
+            }
+        }
+        self.status(format!(
+            "{} changed to {}x{}",
+            description, size.width, size.height
+        ));
+    }
+
+    // 显示在预览画面上的来源问题, 为空时隐藏.
+    fn source_problem(&self, text: String) {
+        let _ = self
+            .window
+            .upgrade_in_event_loop(move |window| window.set_source_problem(text.into()));
+    }
+
+    // 打开新来源, 重新加载对应的 camera profile 和处理管线.
+    fn set_source(&mut self, uri: &str, state: &mut State) -> Result<()> {
+        let source = source::open(uri, &self.config)?;
+        self.use_source(uri, source, state)
+    }
+
+    fn use_source(
+        &mut self,
+        uri: &str,
+        source: Box<dyn FrameSource>,
+        state: &mut State,
+    ) -> Result<()> {
+        let metadata = source.metadata();
+        self.frame_width = metadata.width;
+        self.frame_height = metadata.height;
+        self.fps = metadata.fps;
+        self.profile_dir = CameraProfile::dir(&self.config.profiles_dir, &metadata.profile);
+        self.source = source;
+        self.profile = CameraProfile::load(&self.profile_dir)?;
+        state.measure = Measure::new(self.profile.um_per_px);
+        // 新来源按普通模式打开.
+        if state.normal_mode.take().is_some() {
+            self.playback_fps = None;
+            let _ = self
+                .window
+                .upgrade_in_event_loop(|window| window.set_high_speed_enabled(false));
+        }
+        state.playback = self.new_playback();
+        self.playback(state.playback.as_ref());
+        self.waveform(state.playback.as_ref());
+        self.review(state);
+        // 缓存长度按新来源的帧率计算.
+        state.timeshift = TimeShift::new(&self.config.timeshift, self.preview_fps());
+        self.timeline(&state.timeshift);
+        state.pipeline = self.stages().pipeline(&state.trajectories)?;
+        state.pacing = self.normal_pacing(&state.pipeline);
+        // 先关闭旧路由, 它们可能使用同一个摄像头.
+        state.router = Router::default();
+        state.router = self.router();
+        if uri != self.uri {
+            privacy::audit("capture", false, &self.uri);
+            privacy::audit("capture", true, uri);
+            connections::remove(&self.uri);
+            state.jitter.reset();
+        }
+        self.uri = uri.to_string();
+        let uri = self.uri.clone();
+        let _ = self
+            .window
+            .upgrade_in_event_loop(move |window| window.set_source_uri(uri.into()));
+        self.recordings();
+        self.controls();
+        self.status(format!("Source switched to {}", uri));
+        Ok(())
+    }
+
+    // 文件来源按原始帧率播放, 可以暂停和跳转. 回放会话时由会话控制节奏.
+    fn new_playback(&self) -> Option<Playback> {
+        let live = self.source.metadata().live;
+        (!live && self.replay.is_none()).then(|| Playback::new(self.fps))
+    }
+
+    // 只有文件来源显示播放控制.
+    fn playback(&self, playback: Option<&Playback>) {
+        let enabled = playback.is_some();
+        let paused = playback.is_some_and(Playback::is_paused);
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_playback_enabled(enabled);
+            window.set_playback_paused(paused);
+        });
+    }
+
+    // 文件播放时在后台解码音轨, 时间线下显示波形, 没有音轨时不显示.
+    fn waveform(&self, playback: Option<&Playback>) {
+        let window = self.window.clone();
+        if playback.is_none() {
+            let _ = window.upgrade_in_event_loop(|window| window.set_playback_has_waveform(false));
+            return;
+        }
+        let path = self.source.metadata().description.clone();
+        let workers = self.config.threads.workers.clone();
+        thread::spawn(move || {
+            workers.apply("waveform");
+            let buffer = match waveform::load(&path, waveform::WIDTH) {
+                Ok(peaks) if !peaks.is_empty() => Some(waveform::render(&peaks)),
+                Ok(_) => None,
+                Err(err) => {
+                    eprintln!("waveform: {:?}", err);
+                    None
+                }
+            };
+            let _ = window.upgrade_in_event_loop(move |window| {
+                let visible = buffer.is_some();
+                if let Some(buffer) = buffer {
+                    window.set_playback_waveform(Image::from_rgba8(buffer));
+                }
+                window.set_playback_has_waveform(visible);
+            });
+        });
+    }
+
+    // 按事件存储找出录像期间的事件, 在时间线上标出.
+    fn review(&mut self, state: &mut State) {
+        state.review = Review::default();
+        let seconds = match (&state.playback, self.source.capture()) {
+            (Some(playback), Some(camera)) => playback.seconds(camera).ok().flatten(),
+            _ => None,
+        };
+        if let Some((_, total)) = seconds {
+            let recording = PathBuf::from(&self.source.metadata().description);
+            match Review::load(
+                &self.config.review,
+                &recording,
+                &self.config.events_file,
+                total,
+            ) {
+                Ok(review) => state.review = review,
+                Err(err) => eprintln!("review: {:?}", err),
+            }
+        }
+        let marks: Vec<f32> = state.review.marks().into_iter().map(|m| m as f32).collect();
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_playback_event_marks(ModelRc::new(VecModel::from(marks)));
+        });
+    }
+
+    // 播放到没有事件的片段时跳到下一个事件, 最后一个事件之后跳到结尾.
+    fn skip_quiet(&mut self, state: &mut State) -> Result<()> {
+        if !state.skip_quiet || state.review.is_empty() {
+            return Ok(());
+        }
+        let (Some(playback), Some(camera)) = (&mut state.playback, self.source.capture()) else {
+            return Ok(());
+        };
+        if playback.is_paused() {
+            return Ok(());
+        }
+        let Some((played, total)) = playback.seconds(camera)? else {
+            return Ok(());
+        };
+        // 已经在结尾时不再跳转.
+        if let Some(target) = state.review.skip(played).filter(|t| *t > played + 1.0) {
+            playback.seek(camera, target / total)?;
+        }
+        Ok(())
+    }
+
+    fn playback_position(&self, (position, played, total): (f64, f64, f64)) {
+        let time = format!("{} / {}", clock(played), clock(total));
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_playback_position(position as f32);
+            window.set_playback_time(time.into());
+        });
+    }
+
+    // 切换场景, 返回来源是否改变. 场景中没有设置的裁剪, 效果和 OSD 恢复为默认.
+    fn set_scene(&mut self, name: &str, state: &mut State) -> Result<bool> {
+        let Some(index) = self
+            .config
+            .scenes
+            .iter()
+            .position(|scene| scene.name == name)
+        else {
+            bail!("unknown scene {:?}", name);
+        };
+        let scene = self.config.scenes[index].clone();
+        // 先检查 OSD profile, 避免切换到一半失败.
+        let osd = &self.config.osd;
+        let preview_osd = osd.profile(scene.preview_osd.as_deref().unwrap_or(&osd.preview))?;
+        let recording_osd =
+            osd.profile(scene.recording_osd.as_deref().unwrap_or(&osd.recording))?;
+        let switched = match &scene.source {
+            Some(uri) if *uri != self.uri => {
+                self.set_source(uri, state)?;
+                true
+            }
+            _ => false,
+        };
+        self.preview_osd = preview_osd;
+        self.recording_osd = recording_osd;
+        match scene.crop {
+            Some(rect) => state.pipeline.set(Box::new(Crop::new(rect))),
+            None => state.pipeline.remove("crop"),
+        }
+        if scene.effects.is_empty() {
+            state.pipeline.remove("effects");
+        } else {
+            state.pipeline.set(Box::new(Effects::new(&scene.effects)));
+        }
+        state.scene = Some(scene.name);
+        let index = index as i32;
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_scene_index(index);
+            window.invoke_scene_changed(index);
+        });
+        self.status(format!("Scene {}", name));
+        Ok(switched)
+    }
+
+    // 切换到高速模式时保存原来的分辨率和帧率, 关闭时恢复.
+    fn set_high_speed(&mut self, enabled: bool, state: &mut State) -> Result<()> {
+        if enabled == state.normal_mode.is_some() {
+            return Ok(());
+        }
+        let config = self.config.high_speed.clone();
+        let current = Mode {
+            width: self.frame_width,
+            height: self.frame_height,
+            fps: self.fps,
+        };
+        let requested = state.normal_mode.unwrap_or(Mode {
+            width: config.width as f64,
+            height: config.height as f64,
+            fps: config.fps,
+        });
+        let mode = high_speed::apply(self.source.as_mut(), requested)?;
+        self.frame_width = mode.width;
+        self.frame_height = mode.height;
+        self.fps = mode.fps;
+        let preview_fps = if enabled {
+            state.normal_mode = Some(current);
+            self.playback_fps = Some(config.playback_fps);
+            let limit = self
+                .config
+                .preview_fps
+                .map_or(config.preview_fps, |fps| fps.min(config.preview_fps));
+            state.pacing = PreviewPacing::new(Some(limit));
+            self.status(format!(
+                "High-speed capture at {}x{} {:.0} FPS, recordings play at {:.0} FPS",
+                mode.width, mode.height, mode.fps, config.playback_fps
+            ));
+            mode.fps.min(limit)
+        } else {
+            state.normal_mode = None;
+            self.playback_fps = None;
+            state.pacing = self.normal_pacing(&state.pipeline);
+            self.status(format!(
+                "Normal capture at {}x{} {:.0} FPS",
+                mode.width, mode.height, mode.fps
+            ));
+            self.preview_fps()
+        };
+        self.mode_changed(preview_fps, state);
+        Ok(())
+    }
+
+    // 控制面板中修改分辨率和帧率, 高速模式下不可用.
+    fn set_mode(&mut self, width: i32, height: i32, fps: f64, state: &mut State) -> Result<()> {
+        if state.normal_mode.is_some() {
+            bail!("turn off high-speed mode first");
+        }
+        let requested = Mode {
+            width: width as f64,
+            height: height as f64,
+            fps,
+        };
+        let mode = high_speed::apply(self.source.as_mut(), requested)?;
+        self.frame_width = mode.width;
+        self.frame_height = mode.height;
+        self.fps = mode.fps;
+        self.status(format!(
+            "Camera mode {}x{} {:.0} FPS",
+            mode.width, mode.height, mode.fps
+        ));
+        self.mode_changed(self.preview_fps(), state);
+        Ok(())
+    }
+
+    // 分辨率或帧率改变后重建依赖它们的状态. 预览缓冲区在下一帧按新尺寸分配.
+    fn mode_changed(&mut self, preview_fps: f64, state: &mut State) {
+        // 时移缓存只保存显示的帧.
+        state.timeshift = TimeShift::new(&self.config.timeshift, preview_fps);
+        self.timeline(&state.timeshift);
+        state.router = Router::default();
+        state.router = self.router();
+        self.recordings();
+        self.controls();
+    }
+
+    // 探测当前来源支持的控制, 更新控制面板. 不支持的控制在界面上禁用.
+    fn controls(&mut self) {
+        let probe = controls::probe(self.source.as_mut());
+        let (width, height) = (self.frame_width as i32, self.frame_height as i32);
+        let mut resolutions = controls::RESOLUTIONS.to_vec();
+        if !resolutions.contains(&(width, height)) {
+            resolutions.push((width, height));
+            resolutions.sort();
+        }
+        let index = resolutions.iter().position(|&r| r == (width, height));
+        let fps = self.fps;
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            let names: Vec<SharedString> = resolutions
+                .iter()
+                .map(|(width, height)| format!("{}x{}", width, height).into())
+                .collect();
+            window.set_resolutions(ModelRc::new(VecModel::from(names)));
+            window.set_resolution_index(index.map_or(-1, |i| i as i32));
+            window.set_camera_fps(format!("{:.0}", fps).into());
+            window.set_mode_supported(probe.mode);
+            let (minimum, maximum): (Vec<f32>, Vec<f32>) = Property::ALL
+                .iter()
+                .zip(&probe.values)
+                .map(|(property, value)| {
+                    let (low, high) = property.range();
+                    // 当前值超出预设范围时扩大范围.
+                    let value = value.unwrap_or(low);
+                    (low.min(value) as f32, high.max(value) as f32)
+                })
+                .unzip();
+            let labels: Vec<SharedString> =
+                Property::ALL.iter().map(|p| p.label().into()).collect();
+            let supported: Vec<bool> = probe.values.iter().map(Option::is_some).collect();
+            let values: Vec<f32> = probe
+                .values
+                .iter()
+                .zip(&minimum)
+                .map(|(value, minimum)| value.map_or(*minimum, |value| value as f32))
+                .collect();
+            window.set_control_names(ModelRc::new(VecModel::from(labels)));
+            window.set_control_supported(ModelRc::new(VecModel::from(supported)));
+            window.set_control_minimum(ModelRc::new(VecModel::from(minimum)));
+            window.set_control_maximum(ModelRc::new(VecModel::from(maximum)));
+            window.set_control_values(ModelRc::new(VecModel::from(values)));
+        });
+    }
+
+    fn stages(&self) -> Stages<'_> {
+        Stages {
+            config: &self.config,
+            profile: &self.profile,
+            profile_dir: &self.profile_dir,
+            events: &self.events,
+            frame_size: (self.frame_width as i32, self.frame_height as i32),
+            window: Some(&self.window),
+            models_ready: Some(&self.models_ready),
+        }
+    }
+
+    // 后台下载的模型结束后创建等待它的阶段, 其他模型仍在下载的继续等待.
+    // 下载或创建失败时提示并关闭对应的开关.
+    fn models_ready(&mut self, state: &mut State) {
+        for name in state.pipeline.take_waiting() {
+            // 点选的排队区域只保存在采集线程中.
+            if name == "queue" {
+                self.queue(state);
+                continue;
+            }
+            match self.stages().stage(&name, &state.trajectories) {
+                Ok(stage) => state.pipeline.set(stage),
+                Err(err) if err.is::<Downloading>() => state.pipeline.wait(&name),
+                Err(err) => {
+                    self.status(format!("Stage {} failed: {}", name, err));
+                    let _ = self.window.upgrade_in_event_loop(move |window| {
+                        set_stage_enabled(&window, &name, false)
+                    });
+                }
+            }
+        }
+        if state.router.is_waiting() {
+            state.router = Router::default();
+            state.router = self.router();
+        }
+        self.publish(state);
+    }
+
+    // 按修改后的设置重建抠像阶段.
+    fn chroma_key(&self, pipeline: &mut Pipeline) -> Result<()> {
+        if self.config.chroma_key.enabled {
+            pipeline.set(Box::new(ChromaKey::new(&self.config.chroma_key)?));
+        } else {
+            pipeline.remove("chroma-key");
+        }
+        Ok(())
+    }
+
+    // 按修改后的设置重建音频叠加. 打不开音频输入时关闭并提示, 采集继续.
+    fn audio_overlay(&mut self, pipeline: &mut Pipeline) {
+        if !self.config.audio.overlay.enabled {
+            pipeline.remove("audio-overlay");
+            return;
+        }
+        match AudioOverlay::new(&self.config.audio, &self.config.threads.workers) {
+            Ok(stage) => pipeline.set(Box::new(stage)),
+            Err(err) => {
+                self.config.audio.overlay.enabled = false;
+                self.status(format!("Audio overlay failed: {}", err));
+                let _ = self
+                    .window
+                    .upgrade_in_event_loop(|window| window.set_audio_overlay_enabled(false));
+            }
+        }
+    }
+
+    // 按当前标定重建测速阶段. 检测器或模型不可用时关闭测速并提示, 采集继续.
+    fn speed(&mut self, pipeline: &mut Pipeline) {
+        match self.stages().speed_estimation() {
+            Ok(stage) => pipeline.set(Box::new(stage)),
+            Err(err) => {
+                self.config.speed.enabled = false;
+                self.status(format!("Speed estimation failed: {}", err));
+                let _ = self
+                    .window
+                    .upgrade_in_event_loop(|window| window.set_speed_enabled(false));
+            }
+        }
+    }
+
+    // 平面和内容都已选好时开始投影, 替换之前的投影.
+    fn project(&self, state: &mut State) {
+        let Some(content) = &state.projection_content else {
+            self.status("Choose an image or video to project".to_string());
+            return;
+        };
+        if state.projection_surface.len() != 4 {
+            self.status("Pick the surface to project onto".to_string());
+            return;
+        }
+        let config = &self.config.projection;
+        match PlanarProjection::new(config, content, &state.projection_surface) {
+            Ok(stage) => {
+                state.pipeline.set(Box::new(stage));
+                self.status(format!("Projecting {}", content.display()));
+            }
+            Err(err) => self.status(format!("Projection failed: {}", err)),
+        }
+    }
+
+    // 按点选的区域重新开始颜色变化监测, 曲线和开始颜色随之清空.
+    fn queue(&self, state: &mut State) {
+        let zone = match &state.queue_zone {
+            Some(zone) => Ok(zone.clone()),
+            None => QueueLength::zone(&self.config.queue, &self.config.zones),
+        };
+        match zone.and_then(|zone| self.stages().queue_length(zone)) {
+            Ok(stage) => state.pipeline.set(Box::new(stage)),
+            Err(err) if err.is::<Downloading>() => state.pipeline.wait("queue"),
+            Err(err) => {
+                self.status(format!("Queue length failed: {}", err));
+                let _ = self
+                    .window
+                    .upgrade_in_event_loop(|window| window.set_queue_enabled(false));
+            }
+        }
+    }
+
+    fn react(&self, state: &mut State) {
+        let config = &self.config.reaction;
+        match ReactionMonitor::new(config, state.reaction_region, self.events.clone()) {
+            Ok(stage) => state.pipeline.set(Box::new(stage)),
+            Err(err) => {
+                self.status(format!("Color monitor failed: {}", err));
+                let _ = self
+                    .window
+                    .upgrade_in_event_loop(|window| window.set_reaction_enabled(false));
+            }
+        }
+    }
+
+    // 开始打印时新建延时摄影, 每层保存一帧, 打印结束后在后台导出视频.
+    // 没有收到开始通知时在第一层开始.
+    fn print(&self, event: PrintEvent, frame: &Mat, state: &mut State) {
+        let config = &self.config.printer;
+        match event {
+            PrintEvent::Started(name) => match PrintTimelapse::start(config, name.as_deref()) {
+                Ok(timelapse) => state.print_timelapse = Some(timelapse),
+                Err(err) => self.status(format!("Print timelapse failed: {}", err)),
+            },
+            PrintEvent::Layer(layer) => {
+                if state.print_timelapse.is_none() {
+                    match PrintTimelapse::start(config, None) {
+                        Ok(timelapse) => state.print_timelapse = Some(timelapse),
+                        Err(err) => return self.status(format!("Print timelapse failed: {}", err)),
+                    }
+                }
+                if let Some(timelapse) = &mut state.print_timelapse {
+                    if let Err(err) = timelapse.layer(frame, layer) {
+                        self.status(format!("Print timelapse frame failed: {}", err));
+                    }
+                }
+            }
+            PrintEvent::Finished => {
+                let Some(timelapse) = state.print_timelapse.take() else {
+                    return;
+                };
+                let frames = timelapse.frames();
+                if frames.is_empty() {
+                    return;
+                }
+                let path = timelapse.output();
+                let (fps, fourcc) = (config.fps, config.fourcc.clone());
+                jobs::spawn(
+                    &self.window,
+                    "Print timelapse",
+                    &self.config.threads.workers,
+                    move |job| {
+                        stop_motion::export(&frames, &path, fps, &fourcc, |progress| {
+                            job.progress(Some(progress))
+                        })?;
+                        Ok(format!("Print timelapse saved to {}", path.display()))
+                    },
+                );
+            }
+        }
+    }
+
+    // 处理需要跨越多帧的校准.
+    fn calibrate(&mut self, frame: &Mat, state: &mut State) -> Result<()> {
+        if let Some(dark_frames) = &mut state.dark_frames {
+            let result = dark_frames.add(frame);
+            if !matches!(result, Ok(None)) {
+                state.dark_frames = None;
+            }
+            match result {
+                Ok(None) => {}
+                Ok(Some(pixels)) => {
+                    let count = pixels.len();
+                    state
+                        .pipeline
+                        .set(Box::new(HotPixelCorrection::new(&pixels)));
+                    self.profile.hot_pixels = pixels;
+                    self.save_profile(&format!("{} hot pixels mapped", count));
+                }
+                Err(err) => self.status(format!("Hot pixel calibration failed: {}", err)),
+            }
+        }
+        if let Some(flat_frames) = &mut state.flat_frames {
+            let result = flat_frames.add(frame, &self.profile_dir);
+            if !matches!(result, Ok(None)) {
+                state.flat_frames = None;
+            }
+            match result {
+                Ok(None) => {}
+                Ok(Some(flat)) => {
+                    state
+                        .pipeline
+                        .set(Box::new(FlatFieldCorrection::new(&flat)?));
+                    self.profile.flat_field = true;
+                    self.save_profile("Flat-field reference saved");
+                }
+                Err(err) => self.status(format!("Flat-field capture failed: {}", err)),
+            }
+        }
+        Ok(())
+    }
+
+    fn save_profile(&self, done: &str) {
+        if self.replay.is_some() {
+            self.status(format!("{} (replay, profile not saved)", done));
+            return;
+        }
+        match self.profile.save(&self.profile_dir) {
+            Ok(()) => self.status(done.to_string()),
+            Err(err) => self.status(format!("Save camera profile failed: {}", err)),
+        }
+    }
+
+    // 第一次拍摄时打开序列, 之前保存的帧会继续使用.
+    fn capture_stop_motion(&self, frame: &Mat, state: &mut State) {
+        if state.stop_motion.is_none() {
+            match StopMotion::open(&self.config.stop_motion) {
+                Ok(stop_motion) => state.stop_motion = Some(stop_motion),
+                Err(err) => return self.status(format!("Stop motion failed: {}", err)),
+            }
+        }
+        let Some(stop_motion) = &mut state.stop_motion else {
+            return;
+        };
+        if let Err(err) = stop_motion.capture(frame) {
+            return self.status(format!("Capture frame failed: {}", err));
+        }
+        // 下一帧对照刚拍摄的这一帧摆放.
+        if let Err(err) = state.onion.set(frame) {
+            eprintln!("onion skin: {:?}", err);
+        }
+        self.status(format!("Frame {} captured", stop_motion.len()));
+    }
+
+    fn delete_stop_motion(&self, state: &mut State) {
+        let Some(stop_motion) = &mut state.stop_motion else {
+            return;
+        };
+        match stop_motion.delete_last() {
+            Ok(true) => {}
+            Ok(false) => return self.status("No frame to delete".to_string()),
+            Err(err) => return self.status(format!("Delete frame failed: {}", err)),
+        }
+        match stop_motion.last() {
+            Ok(Some(last)) => state.onion.image_from(last),
+            Ok(None) => state.onion.clear(),
+            Err(err) => eprintln!("onion skin: {:?}", err),
+        }
+        self.status(format!("{} frames left", stop_motion.len()));
+    }
+
+    // 更新界面上的时间线位置, 落后实时画面的秒数和 A-B 循环的位置.
+    fn timeline(&self, timeshift: &TimeShift) {
+        let paused = timeshift.is_paused();
+        let (position, behind) = timeshift.timeline();
+        let (a, b) = timeshift.marks();
+        let _ = self.window.upgrade_in_event_loop(move |window| {
+            window.set_timeshift_paused(paused);
+            window.set_timeshift_position(position as f32);
+            window.set_timeshift_behind(behind as f32);
+            window.set_timeshift_a(a as f32);
+            window.set_timeshift_b(b as f32);
+        });
+    }
+
+    fn status(&self, text: String) {
+        set_status(&self.window, text);
+    }
+}
+
+// 在 UI 线程中更新状态栏.
+pub fn set_status(window: &Weak<Main>, text: String) {
+    println!("{}", text);
+    crash::log(&text);
+    let _ = window.upgrade_in_event_loop(move |window| window.set_status(text.into()));
+}
+
+// 控制面板上有开关的处理阶段.
+pub const TOGGLED_STAGES: [&str; 20] = [
+    "chroma-key",
+    "lanes",
+    "speed",
+    "tracking",
+    "detection",
+    "wildlife",
+    "meteor",
+    "gauges",
+    "reaction",
+    "queue",
+    "parking",
+    "intrusion",
+    "printer",
+    "ppe",
+    "fall",
+    "monitor",
+    "tamper",
+    "dwell",
+    "occupancy",
+    "audio-overlay",
+];
+
+// 同步控制面板上的阶段开关, 没有开关的阶段忽略.
+pub fn set_stage_enabled(window: &Main, stage: &str, enabled: bool) {
+    match stage {
+        "chroma-key" => window.set_chroma_enabled(enabled),
+        "lanes" => window.set_lanes_enabled(enabled),
+        "speed" => window.set_speed_enabled(enabled),
+        "tracking" => window.set_tracking_enabled(enabled),
+        "detection" => window.set_detection_enabled(enabled),
+        "wildlife" => window.set_wildlife_enabled(enabled),
+        "meteor" => window.set_meteor_enabled(enabled),
+        "gauges" => window.set_gauges_enabled(enabled),
+        "reaction" => window.set_reaction_enabled(enabled),
+        "queue" => window.set_queue_enabled(enabled),
+        "parking" => window.set_parking_enabled(enabled),
+        "intrusion" => window.set_intrusion_enabled(enabled),
+        "printer" => window.set_printer_enabled(enabled),
+        "ppe" => window.set_ppe_enabled(enabled),
+        "fall" => window.set_fall_enabled(enabled),
+        "monitor" => window.set_monitor_enabled(enabled),
+        "tamper" => window.set_tamper_enabled(enabled),
+        "dwell" => window.set_dwell_enabled(enabled),
+        "occupancy" => window.set_occupancy_enabled(enabled),
+        "audio-overlay" => window.set_audio_overlay_enabled(enabled),
+        _ => {}
+    }
+}
+
+// 采集线程内部的运行期状态.
+struct State {
+    pipeline: Pipeline,
+    dark_frames: Option<DarkFrames>,
+    flat_frames: Option<FlatFrames>,
+    measure: Measure,
+    panorama: Option<Panorama>,
+    burst: Option<Burst>,
+    shoot: Option<Shoot>,
+    onion: OnionSkin,
+    onion_hold: bool,
+    stop_motion: Option<StopMotion>,
+    stop_motion_capture: bool,
+    timeshift: TimeShift,
+    // 文件来源的播放控制, 其他来源为 None.
+    playback: Option<Playback>,
+    // 录像中的事件, 和是否自动跳过没有事件的片段.
+    review: Review,
+    skip_quiet: bool,
+    // 主 sinks 正在录制.
+    recording: bool,
+    // 高速模式下保存切换前的分辨率和帧率, 普通模式时为 None.
+    normal_mode: Option<Mode>,
+    pacing: PreviewPacing,
+    // 当前场景名称, 没有切换过场景时为 None.
+    scene: Option<String>,
+    // 界面上显示的检测目标数, 没有检测阶段时为 None.
+    detections: Option<usize>,
+    // 高位深模式下最近一帧原始数据.
+    raw: Mat,
+    depth: DepthMapping,
+    reference: Option<Mat>,
+    capture_reference: bool,
+    change_blur: i32,
+    change_threshold: f64,
+    speed_calibration: Option<SpeedCalibration>,
+    // 点选中的平面角点 (比例坐标), 不在点选时为 None.
+    surface_clicks: Option<Vec<[f64; 2]>>,
+    projection_surface: Vec<[f64; 2]>,
+    projection_content: Option<PathBuf>,
+    // 正在画的排队区域顶点, 不在画时为 None. 画好的区域代替 [queue] zone.
+    queue_clicks: Option<Vec<[f64; 2]>>,
+    queue_zone: Option<Zone>,
+    // 正在画的排除区域顶点, 不在画时为 None.
+    exclusion_clicks: Option<Vec<[f64; 2]>>,
+    // 点选中的颜色监测区域角点, 不在点选时为 None.
+    reaction_clicks: Option<Vec<[f64; 2]>>,
+    reaction_region: [f64; 4],
+    // 正在打印时的按层延时摄影.
+    print_timelapse: Option<PrintTimelapse>,
+    trajectories: Trajectories,
+    // [summary] 启用时的长时间统计.
+    summary: Option<Summary>,
+    router: Router,
+    // 录像文件旁的外部数据记录.
+    sidecars: Sidecars,
+    // 录像文件旁的 GPX 轨迹.
+    tracks: Tracks,
+    sync: Manifests,
+    denoise: TemporalDenoise,
+    day_night: DayNight,
+    infrared: Infrared,
+    // 录像文件实际的码率.
+    bitrate: FileMeter,
+    // 网络来源帧到达间隔的抖动.
+    jitter: Jitter,
+}
+
+// 秒数显示为 m:ss.
+fn clock(seconds: f64) -> String {
+    let seconds = seconds.max(0.0) as u64;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+// profile 为空时直接返回原始帧, 避免多余的拷贝.
+fn draw_osd<'a>(
+    profile: &OsdProfile,
+    frame: &'a Mat,
+    scratch: &'a mut Mat,
+    info: &OsdInfo,
+) -> Result<&'a Mat> {
+    if profile.is_empty() {
+        return Ok(frame);
+    }
+    frame.copy_to(scratch)?;
+    profile.draw(scratch, info)?;
+    Ok(scratch)
+}
@@ -0,0 +1,159 @@
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size, Vector},
+    imgproc::{self, CHAIN_APPROX_SIMPLE, COLOR_BGR2GRAY, LINE_8, RETR_EXTERNAL, THRESH_BINARY},
+    prelude::*,
+};
+
+use crate::pipeline::FrameProcessor;
+
+// 与参考帧逐像素比较, 用红色标出变化的区域.
+pub struct ChangeDetection {
+    reference: Mat,
+    blur: i32,
+    threshold: f64,
+    blurred: Mat,
+    diff: Mat,
+    gray: Mat,
+    mask: Mat,
+    tinted: Mat,
+}
+
+impl ChangeDetection {
+    pub fn new(reference: &Mat, blur: i32, threshold: f64) -> Result<Self> {
+        let blur = blur.max(1) | 1;
+        let mut blurred = Mat::default();
+        smooth(reference, &mut blurred, blur)?;
+        Ok(Self {
+            reference: blurred,
+            blur,
+            threshold,
+            blurred: Mat::default(),
+            diff: Mat::default(),
+            gray: Mat::default(),
+            mask: Mat::default(),
+            tinted: Mat::default(),
+        })
+    }
+}
+
+fn smooth(src: &Mat, dst: &mut Mat, blur: i32) -> Result<()> {
+    imgproc::gaussian_blur(
+        src,
+        dst,
+        Size::new(blur, blur),
+        0.0,
+        0.0,
+        core::BORDER_DEFAULT,
+    )?;
+    Ok(())
+}
+
+impl FrameProcessor for ChangeDetection {
+    fn name(&self) -> &str {
+        "change-detection"
+    }
+
+    // 排在所有处理之后, 比较的是最终画面.
+    fn order(&self) -> i32 {
+        100
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        if frame.size()? != self.reference.size()? {
+            return Ok(());
+        }
+        smooth(frame, &mut self.blurred, self.blur)?;
+        core::absdiff(&self.blurred, &self.reference, &mut self.diff)?;
+        imgproc::cvt_color(&self.diff, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        imgproc::threshold(
+            &self.gray,
+            &mut self.mask,
+            self.threshold,
+            255.0,
+            THRESH_BINARY,
+        )?;
+
+        // 变化区域半透明着色并描边.
+        let red = Scalar::new(0., 0., 255., 0.);
+        frame.copy_to(&mut self.tinted)?;
+        self.tinted.set_to(&red, &self.mask)?;
+        let mut blended = Mat::default();
+        core::add_weighted(frame, 0.5, &self.tinted, 0.5, 0.0, &mut blended, -1)?;
+        blended.copy_to(frame)?;
+        let mut contours = Vector::<Vector<Point>>::new();
+        imgproc::find_contours(
+            &self.mask,
+            &mut contours,
+            RETR_EXTERNAL,
+            CHAIN_APPROX_SIMPLE,
+            Point::new(0, 0),
+        )?;
+        imgproc::draw_contours(
+            frame,
+            &contours,
+            -1,
+            red,
+            1,
+            LINE_8,
+            &core::no_array(),
+            i32::MAX,
+            Point::new(0, 0),
+        )?;
+
+        let pixels = (self.mask.rows() * self.mask.cols()) as f64;
+        let changed = core::count_non_zero(&self.mask)? as f64 / pixels;
+        imgproc::put_text(
+            frame,
+            &format!("changed {:.1}%", changed * 100.0),
+            Point::new(8, frame.rows() / 2),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.6,
+            red,
+            2,
+            LINE_8,
+            false,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Rect, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    fn gray(rows: i32, cols: i32) -> Mat {
+        Mat::new_rows_cols_with_default(rows, cols, CV_8UC3, Scalar::all(80.)).unwrap()
+    }
+
+    // 变化的区域染成半透明红色, 没有变化的地方保持原样.
+    #[test]
+    fn tints_changes() {
+        let mut detection = ChangeDetection::new(&gray(100, 100), 5, 30.0).unwrap();
+        let mut frame = gray(100, 100);
+        imgproc::rectangle(
+            &mut frame,
+            Rect::new(70, 70, 20, 20),
+            Scalar::all(255.),
+            -1,
+            LINE_8,
+            0,
+        )
+        .unwrap();
+        detection.process(&mut frame).unwrap();
+        assert_eq!(
+            *frame.at_2d::<Vec3b>(80, 80).unwrap(),
+            Vec3b::from([128, 128, 255])
+        );
+        assert_eq!(*frame.at_2d::<Vec3b>(10, 10).unwrap(), Vec3b::all(80));
+        assert_eq!(*frame.at_2d::<Vec3b>(80, 20).unwrap(), Vec3b::all(80));
+
+        // 分辨率和参考帧不同时不比较, 也不显示变化比例.
+        let mut other = gray(50, 50);
+        detection.process(&mut other).unwrap();
+        assert_eq!(*other.at_2d::<Vec3b>(22, 12).unwrap(), Vec3b::all(80));
+        assert_eq!(core::mean(&other, &core::no_array()).unwrap()[2], 80.0);
+    }
+}
@@ -0,0 +1,309 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size, Vector, CV_32F, CV_8UC1, CV_8UC3},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc::{self, COLOR_BGR2YCrCb, COLOR_GRAY2BGR, INTER_AREA, LINE_8, MORPH_ELLIPSE},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+// 绿幕抠像: 按 CrCb 平面上到幕布颜色的距离得到 alpha, 去除溢色后合成到背景上.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChromaKeyConfig {
+    pub enabled: bool,
+    // 幕布颜色 (BGR), 默认为常见的绿幕颜色.
+    pub color: [u8; 3],
+    // CrCb 距离小于 threshold 的像素完全透明, 大于 threshold + softness 的完全不透明.
+    pub threshold: f64,
+    pub softness: f64,
+    // 整个前景的溢色抑制强度 0..1: 幕布颜色的主通道不超过另外两个通道的最大值.
+    pub spill: f64,
+    // 边缘 (半透明区域及其附近 edge_width 像素) 的溢色抑制强度 0..1, 头发和轮廓上的溢色最明显.
+    pub edge_despill: f64,
+    pub edge_width: i32,
+    // 显示 alpha 通道 (白色为前景) 而不是合成结果, 用于调整参数.
+    pub matte_view: bool,
+    // 背景图片, 按画面大小缩放; 不设置时使用 background_color.
+    pub background: Option<PathBuf>,
+    pub background_color: [u8; 3],
+    // 垃圾遮罩: 按区域强制为背景或前景, 例如幕布外的灯架, 或与幕布同色的道具.
+    pub garbage: Vec<GarbageMatte>,
+}
+
+impl Default for ChromaKeyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            color: [64, 177, 0],
+            threshold: 30.0,
+            softness: 20.0,
+            spill: 0.5,
+            edge_despill: 1.0,
+            edge_width: 5,
+            matte_view: false,
+            background: None,
+            background_color: [0, 0, 0],
+            garbage: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatteMode {
+    // 区域内总是背景.
+    Exclude,
+    // 区域内总是前景.
+    Include,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GarbageMatte {
+    pub mode: MatteMode,
+    // 多边形顶点, 取值为画面宽高的比例.
+    pub points: Vec<[f64; 2]>,
+}
+
+pub struct ChromaKey {
+    config: ChromaKeyConfig,
+    // 幕布颜色的 Cr, Cb 和 B, G, R 中占主导的通道.
+    key: (f64, f64),
+    dominant: usize,
+    background: Option<Mat>,
+    // 按画面尺寸生成的背景和垃圾遮罩.
+    size: Size,
+    backdrop: Mat,
+    exclude: Mat,
+    include: Mat,
+    ycrcb: Mat,
+    alpha: Mat,
+    inverse: Mat,
+    edge: Mat,
+    scratch: Mat,
+}
+
+impl ChromaKey {
+    pub fn new(config: &ChromaKeyConfig) -> Result<Self> {
+        let [b, g, r] = config.color;
+        let color = Mat::new_rows_cols_with_default(
+            1,
+            1,
+            CV_8UC3,
+            Scalar::new(b as f64, g as f64, r as f64, 0.0),
+        )?;
+        let mut ycrcb = Mat::default();
+        imgproc::cvt_color(&color, &mut ycrcb, COLOR_BGR2YCrCb, 0)?;
+        let key = *ycrcb.at_2d::<core::Vec3b>(0, 0)?;
+        let dominant = (0..3).max_by_key(|&i| config.color[i]).unwrap_or(1);
+        let background = match &config.background {
+            Some(path) => {
+                let image = imgcodecs::imread(&path.to_string_lossy(), IMREAD_COLOR)?;
+                if image.empty() {
+                    bail!("read {}", path.display());
+                }
+                Some(image)
+            }
+            None => None,
+        };
+        Ok(Self {
+            config: config.clone(),
+            key: (key[1] as f64, key[2] as f64),
+            dominant,
+            background,
+            size: Size::default(),
+            backdrop: Mat::default(),
+            exclude: Mat::default(),
+            include: Mat::default(),
+            ycrcb: Mat::default(),
+            alpha: Mat::default(),
+            inverse: Mat::default(),
+            edge: Mat::default(),
+            scratch: Mat::default(),
+        })
+    }
+
+    // 画面尺寸变化时重新生成背景和垃圾遮罩.
+    fn resize(&mut self, size: Size) -> Result<()> {
+        if size == self.size {
+            return Ok(());
+        }
+        self.size = size;
+        match &self.background {
+            Some(image) => imgproc::resize(image, &mut self.backdrop, size, 0.0, 0.0, INTER_AREA)?,
+            None => {
+                let [b, g, r] = self.config.background_color;
+                self.backdrop = Mat::new_size_with_default(
+                    size,
+                    CV_8UC3,
+                    Scalar::new(b as f64, g as f64, r as f64, 0.0),
+                )?;
+            }
+        }
+        self.exclude = Mat::zeros_size(size, CV_8UC1)?.to_mat()?;
+        self.include = Mat::zeros_size(size, CV_8UC1)?.to_mat()?;
+        for matte in &self.config.garbage {
+            let polygon: Vector<Point> = matte
+                .points
+                .iter()
+                .map(|[x, y]| {
+                    Point::new(
+                        (x * size.width as f64) as i32,
+                        (y * size.height as f64) as i32,
+                    )
+                })
+                .collect();
+            let target = match matte.mode {
+                MatteMode::Exclude => &mut self.exclude,
+                MatteMode::Include => &mut self.include,
+            };
+            imgproc::fill_poly(
+                target,
+                &Vector::<Vector<Point>>::from_iter([polygon]),
+                Scalar::all(255.0),
+                LINE_8,
+                0,
+                Point::default(),
+            )?;
+        }
+        Ok(())
+    }
+
+    // 0..1 的 alpha (CV_32F), 1 为前景.
+    fn matte(&mut self, frame: &Mat) -> Result<()> {
+        imgproc::cvt_color(frame, &mut self.ycrcb, COLOR_BGR2YCrCb, 0)?;
+        let mut channels = Vector::<Mat>::new();
+        core::split(&self.ycrcb, &mut channels)?;
+        let (mut cr, mut cb) = (Mat::default(), Mat::default());
+        channels
+            .get(1)?
+            .convert_to(&mut cr, CV_32F, 1.0, -self.key.0)?;
+        channels
+            .get(2)?
+            .convert_to(&mut cb, CV_32F, 1.0, -self.key.1)?;
+        core::magnitude(&cr, &cb, &mut self.scratch)?;
+        let softness = self.config.softness.max(1.0);
+        self.scratch.convert_to(
+            &mut self.alpha,
+            CV_32F,
+            1.0 / softness,
+            -self.config.threshold / softness,
+        )?;
+        imgproc::threshold(
+            &self.alpha,
+            &mut self.scratch,
+            1.0,
+            1.0,
+            imgproc::THRESH_TRUNC,
+        )?;
+        imgproc::threshold(
+            &self.scratch,
+            &mut self.alpha,
+            0.0,
+            1.0,
+            imgproc::THRESH_TOZERO,
+        )?;
+        self.alpha.set_to(&Scalar::all(0.0), &self.exclude)?;
+        self.alpha.set_to(&Scalar::all(1.0), &self.include)?;
+        Ok(())
+    }
+
+    // 幕布主通道超出另外两个通道最大值的部分为溢色, 按强度减去. 边缘使用 edge_despill 的强度.
+    fn despill(&mut self, frame: &mut Mat) -> Result<()> {
+        let (spill, edge) = (
+            self.config.spill.clamp(0.0, 1.0),
+            self.config.edge_despill.clamp(0.0, 1.0),
+        );
+        if spill <= 0.0 && edge <= 0.0 {
+            return Ok(());
+        }
+        // 半透明处为 1, 完全透明或不透明处为 0, 再向外扩展 edge_width 像素.
+        self.alpha
+            .convert_to(&mut self.scratch, CV_32F, -2.0, 1.0)?;
+        core::absdiff(&self.scratch, &Scalar::all(0.0), &mut self.edge)?;
+        self.edge.convert_to(&mut self.scratch, CV_32F, -1.0, 1.0)?;
+        let width = self.config.edge_width.max(0) * 2 + 1;
+        let kernel = imgproc::get_structuring_element(
+            MORPH_ELLIPSE,
+            Size::new(width, width),
+            Point::new(-1, -1),
+        )?;
+        imgproc::dilate(
+            &self.scratch,
+            &mut self.edge,
+            &kernel,
+            Point::new(-1, -1),
+            1,
+            core::BORDER_CONSTANT,
+            Scalar::all(0.0),
+        )?;
+        // 每个像素的抑制强度.
+        self.edge.convert_to(&mut self.scratch, CV_32F, edge, 0.0)?;
+        core::max(&self.scratch, &Scalar::all(spill), &mut self.edge)?;
+
+        let mut channels = Vector::<Mat>::new();
+        core::split(&*frame, &mut channels)?;
+        let others: Vec<usize> = (0..3).filter(|&i| i != self.dominant).collect();
+        let mut limit = Mat::default();
+        core::max(
+            &channels.get(others[0])?,
+            &channels.get(others[1])?,
+            &mut limit,
+        )?;
+        let dominant = channels.get(self.dominant)?;
+        let mut excess = Mat::default();
+        core::subtract(&dominant, &limit, &mut excess, &core::no_array(), CV_32F)?;
+        imgproc::threshold(&excess, &mut self.scratch, 0.0, 0.0, imgproc::THRESH_TOZERO)?;
+        core::multiply(&self.scratch, &self.edge, &mut excess, 1.0, -1)?;
+        let mut despilled = Mat::default();
+        core::subtract(
+            &dominant,
+            &excess,
+            &mut despilled,
+            &core::no_array(),
+            CV_8UC1,
+        )?;
+        channels.set(self.dominant, despilled)?;
+        core::merge(&channels, frame)?;
+        Ok(())
+    }
+}
+
+impl FrameProcessor for ChromaKey {
+    fn name(&self) -> &str {
+        "chroma-key"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        self.resize(frame.size()?)?;
+        self.matte(frame)?;
+        if self.config.matte_view {
+            self.alpha
+                .convert_to(&mut self.scratch, CV_8UC1, 255.0, 0.0)?;
+            imgproc::cvt_color(&self.scratch, frame, COLOR_GRAY2BGR, 0)?;
+            return Ok(());
+        }
+        self.despill(frame)?;
+        // inverse = 1 - alpha.
+        self.alpha.convert_to(&mut self.inverse, -1, -1.0, 1.0)?;
+        imgproc::blend_linear(
+            &*frame,
+            &self.backdrop,
+            &self.alpha,
+            &self.inverse,
+            &mut self.scratch,
+        )?;
+        std::mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+
+    // 在分析之后, 聚光灯和其他效果之前.
+    fn order(&self) -> i32 {
+        40
+    }
+}
@@ -0,0 +1,47 @@
+use anyhow::Result;
+use opencv::core::Mat;
+
+// 图像分类器, 对整张输入 (通常是裁剪出的目标) 给出最可能的类别.
+pub trait Classifier: Send {
+    // 返回 (类别 ID, 0~1 的分数).
+    fn classify(&mut self, image: &Mat) -> Result<(usize, f64)>;
+    fn label(&self, class: usize) -> String;
+
+    // 实际使用的计算设备, 用于显示.
+    fn device(&self) -> String {
+        "cpu".to_string()
+    }
+}
+
+// 分类模型的输出为 [1, N]. 有的模型已经带 softmax, 有的输出 logits, 不是概率分布时先做 softmax.
+pub fn best(output: &[f32]) -> (usize, f64) {
+    let sum: f32 = output.iter().sum();
+    let probabilities =
+        output.iter().all(|&p| (0.0..=1.0).contains(&p)) && (sum - 1.0).abs() < 0.01;
+    let max = output.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+    let (class, &value) = output
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap_or((0, &0.0));
+    if probabilities {
+        return (class, value as f64);
+    }
+    let total: f64 = output.iter().map(|&v| ((v - max) as f64).exp()).sum();
+    (class, ((value - max) as f64).exp() / total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn best_class() {
+        // 已经是概率分布时直接使用.
+        assert_eq!(best(&[0.1, 0.7, 0.2]), (1, 0.7f32 as f64));
+        // logits 先做 softmax.
+        let (class, score) = best(&[0.0, 2.0f32.ln(), 0.0]);
+        assert_eq!(class, 1);
+        assert!((score - 0.5).abs() < 1e-6, "{score}");
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use opencv::{
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+use serde::Deserialize;
+
+use crate::{events::Event, jobs::Handle, retention, review};
+
+// 事件片段: 从录像中截取事件前后几秒保存为单独的文件, 便于只分享发生的事情.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClipConfig {
+    // 事件前后截取的秒数.
+    pub before: f64,
+    pub after: f64,
+    // 查找录像的目录和文件名模式 (支持 * 和 ?).
+    pub recordings: PathBuf,
+    pub patterns: Vec<String>,
+    // 片段保存的目录.
+    pub dir: PathBuf,
+    // 控制面板中列出的最近事件数.
+    pub recent: usize,
+}
+
+impl Default for ClipConfig {
+    fn default() -> Self {
+        Self {
+            before: 10.0,
+            after: 10.0,
+            recordings: PathBuf::from("."),
+            patterns: vec!["capture-*.mp4".to_string(), "capture-*.mkv".to_string()],
+            dir: PathBuf::from("clips"),
+            recent: 20,
+        }
+    }
+}
+
+// 一个录像文件, 开始时间和时长 (秒).
+#[derive(Debug, Clone)]
+pub struct Recording {
+    pub path: PathBuf,
+    pub started: DateTime<Local>,
+    pub total: f64,
+}
+
+impl Recording {
+    // time 距录像开始的秒数.
+    pub fn offset(&self, time: DateTime<Local>) -> f64 {
+        (time - self.started).num_milliseconds() as f64 / 1000.0
+    }
+}
+
+// 目录中匹配的录像, 按开始时间排序. 打不开或不知道开始时间的文件被忽略.
+pub fn recordings(config: &ClipConfig) -> Result<Vec<Recording>> {
+    let entries = fs::read_dir(&config.recordings)
+        .with_context(|| format!("read {}", config.recordings.display()))?;
+    let mut recordings: Vec<Recording> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            config
+                .patterns
+                .iter()
+                .any(|pattern| retention::matches(pattern, &name))
+                .then(|| entry.path())
+        })
+        .filter_map(|path| {
+            let total = duration(&path)?;
+            let started = review::started(&path, total)?;
+            Some(Recording {
+                path,
+                started,
+                total,
+            })
+        })
+        .collect();
+    recordings.sort_by_key(|recording| recording.started);
+    Ok(recordings)
+}
+
+// 包含 time 的录像和事件距录像开始的秒数.
+pub fn find(config: &ClipConfig, time: DateTime<Local>) -> Result<Option<(PathBuf, f64)>> {
+    Ok(recordings(config)?.into_iter().find_map(|recording| {
+        let offset = recording.offset(time);
+        (0.0..=recording.total)
+            .contains(&offset)
+            .then_some((recording.path, offset))
+    }))
+}
+
+// 录像时长 (秒), 打不开或不知道帧数时返回 None.
+fn duration(path: &Path) -> Option<f64> {
+    let capture = VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).ok()?;
+    let count = capture.get(videoio::CAP_PROP_FRAME_COUNT).ok()?;
+    let fps = capture.get(videoio::CAP_PROP_FPS).ok()?;
+    (count > 0.0 && fps > 0.0).then(|| count / fps)
+}
+
+// 截取事件前后的片段, 返回状态栏消息. 先用 ffmpeg 直接复制码流, 失败时重新编码.
+// 复制码流从关键帧开始, 片段的开头可能比 before 秒略早.
+pub fn export(config: &ClipConfig, event: &Event, job: &Handle) -> Result<String> {
+    let Some((recording, offset)) = find(config, event.time)? else {
+        bail!(
+            "no recording in {} contains the {} event at {}",
+            config.recordings.display(),
+            event.kind,
+            event.time.format("%H:%M:%S")
+        );
+    };
+    job.progress(None)?;
+    fs::create_dir_all(&config.dir)?;
+    let start = (offset - config.before.max(0.0)).max(0.0);
+    let length = offset - start + config.after.max(0.0);
+    let extension = recording
+        .extension()
+        .map(|e| e.to_string_lossy().to_string())
+        .unwrap_or_else(|| "mp4".to_string());
+    let output = config.dir.join(format!(
+        "{}-{}.{}",
+        event.kind,
+        event.time.format("%Y-%m-%dT%H-%M-%S"),
+        extension
+    ));
+    let copied = cut(&recording, start, length, &output, &["-c", "copy"]);
+    if let Err(err) = copied {
+        eprintln!("clip stream copy: {:?}", err);
+        job.progress(None)?;
+        cut(
+            &recording,
+            start,
+            length,
+            &output,
+            &["-c:v", "libx264", "-preset", "veryfast", "-c:a", "aac"],
+        )?;
+    }
+    Ok(format!("Clip saved to {}", output.display()))
+}
+
+// 用 ffmpeg 截取从 start 秒开始的一段, 输出长度为 length 秒, args 为编码等输出参数.
+pub fn cut(input: &Path, start: f64, length: f64, output: &Path, args: &[&str]) -> Result<()> {
+    let result = Command::new("ffmpeg")
+        .args(["-y", "-v", "error", "-ss"])
+        .arg(format!("{:.3}", start))
+        .arg("-i")
+        .arg(input)
+        .arg("-t")
+        .arg(format!("{:.3}", length))
+        .args(args)
+        .args(["-avoid_negative_ts", "make_zero"])
+        .arg(output)
+        .stdin(Stdio::null())
+        .output()
+        .context("run ffmpeg")?;
+    if !result.status.success() {
+        let _ = fs::remove_file(output);
+        bail!("ffmpeg: {}", String::from_utf8_lossy(&result.stderr).trim());
+    }
+    Ok(())
+}
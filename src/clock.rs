@@ -0,0 +1,300 @@
+use std::{
+    net::UdpSocket,
+    sync::Mutex,
+    thread::spawn,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{events::EventStore, metrics, threads::Shutdown};
+
+// NTP 时间从 1900 年开始, 与 Unix 时间相差的秒数.
+const NTP_EPOCH: f64 = 2_208_988_800.0;
+// 检查系统时钟是否被调整的间隔.
+const TICK: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockConfig {
+    pub enabled: bool,
+    // 按顺序尝试, 使用第一个回复的服务器. 不带端口时使用 123.
+    pub servers: Vec<String>,
+    // 与 NTP 比较的间隔 (秒).
+    pub interval: f64,
+    // 与 NTP 相差超过该秒数时在时间戳旁标出, 系统时钟跳变超过该秒数时记录事件.
+    pub max_drift: f64,
+    // 等待服务器回复的时间 (秒).
+    pub timeout: f64,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            servers: vec!["pool.ntp.org".to_string()],
+            interval: 600.0,
+            max_drift: 1.0,
+            timeout: 2.0,
+        }
+    }
+}
+
+// 时间戳的可信程度, OSD 按它标注时间戳.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClockState {
+    // 没有启用检查.
+    Off,
+    // 启用后还没有得到结果, 或者 NTP 服务器都没有回复.
+    Unverified,
+    // 系统时钟与 NTP 的差 (秒), 在 max_drift 以内.
+    Verified(f64),
+    // 超出 max_drift.
+    Drift(f64),
+}
+
+static STATE: Mutex<ClockState> = Mutex::new(ClockState::Off);
+
+pub fn state() -> ClockState {
+    *STATE.lock().unwrap()
+}
+
+// 在后台线程中定期与 NTP 比较, 状态变化和系统时钟调整记录为 clock 事件.
+pub fn start(config: &ClockConfig, events: EventStore, shutdown: &Shutdown) {
+    if !config.enabled || config.servers.is_empty() {
+        // 上一次运行可能启用过.
+        *STATE.lock().unwrap() = ClockState::Off;
+        return;
+    }
+    *STATE.lock().unwrap() = ClockState::Unverified;
+    let (config, shutdown) = (config.clone(), shutdown.clone());
+    spawn(move || {
+        let interval = Duration::from_secs_f64(config.interval.max(10.0));
+        let mut last_check: Option<Instant> = None;
+        let mut last = (Instant::now(), unix_time(SystemTime::now()));
+        loop {
+            // 系统时钟的走时与单调时钟不一致说明时间被调整了 (NTP 校正, 手动修改, 休眠恢复).
+            let now = (Instant::now(), unix_time(SystemTime::now()));
+            let jump = (now.1 - last.1) - now.0.duration_since(last.0).as_secs_f64();
+            last = now;
+            if jump.abs() > config.max_drift {
+                eprintln!("clock: system time adjusted by {:+.1}s", jump);
+                events.log("clock", json!({ "change": "adjusted", "jump": jump }));
+                last_check = None;
+            }
+            if !last_check.is_some_and(|check| check.elapsed() < interval) {
+                check(&config, &events);
+                last_check = Some(Instant::now());
+            }
+            if shutdown.wait(TICK) {
+                return;
+            }
+        }
+    });
+}
+
+fn check(config: &ClockConfig, events: &EventStore) {
+    let timeout = Duration::from_secs_f64(config.timeout.max(0.1));
+    let measured = config.servers.iter().find_map(|server| {
+        query(server, timeout)
+            .map_err(|err| eprintln!("clock: NTP {}: {:?}", server, err))
+            .ok()
+            .map(|offset| (server, offset))
+    });
+    let state = match measured {
+        Some((_, offset)) if offset.abs() > config.max_drift => ClockState::Drift(offset),
+        Some((_, offset)) => ClockState::Verified(offset),
+        None => ClockState::Unverified,
+    };
+    if let Some((_, offset)) = measured {
+        metrics::gauge(
+            "clock_offset_seconds",
+            "System clock minus NTP time",
+            offset,
+        );
+    }
+    let previous = std::mem::replace(&mut *STATE.lock().unwrap(), state);
+    // 只在状态类别变化时记录, 每次检查的细微差别不产生事件.
+    if std::mem::discriminant(&previous) == std::mem::discriminant(&state) {
+        return;
+    }
+    let server = measured.map(|(server, _)| server.as_str());
+    match state {
+        ClockState::Drift(offset) => {
+            eprintln!("clock: system time is off by {:+.1}s from NTP", offset);
+            events.log(
+                "clock",
+                json!({ "change": "drift", "offset": offset, "server": server }),
+            );
+        }
+        ClockState::Verified(offset) => events.log(
+            "clock",
+            json!({ "change": "verified", "offset": offset, "server": server }),
+        ),
+        ClockState::Unverified => events.log("clock", json!({ "change": "unreachable" })),
+        ClockState::Off => {}
+    }
+}
+
+// SNTP 请求, 返回本机时钟减去服务器时间 (秒).
+fn query(server: &str, timeout: Duration) -> Result<f64> {
+    let addr = if server.contains(':') {
+        server.to_string()
+    } else {
+        format!("{}:123", server)
+    };
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(timeout))?;
+    socket.connect(&addr)?;
+    let mut request = [0u8; 48];
+    // LI 0, 版本 4, 客户端模式.
+    request[0] = 0x23;
+    let sent = SystemTime::now();
+    request[40..48].copy_from_slice(&to_ntp(unix_time(sent)));
+    socket.send(&request)?;
+    let mut reply = [0u8; 48];
+    let length = socket.recv(&mut reply)?;
+    let received = unix_time(SystemTime::now());
+    if length < 48 || reply[0] & 0x07 != 4 {
+        bail!("not an NTP server reply");
+    }
+    if reply[1] == 0 {
+        bail!("server refused the request (kiss-o'-death)");
+    }
+    // 回复中的原始时间戳必须是请求中的发送时间, 否则不是这次请求的回复.
+    if reply[24..32] != request[40..48] {
+        bail!("reply does not match the request");
+    }
+    let server_received = from_ntp(&reply[32..40]);
+    let server_sent = from_ntp(&reply[40..48]);
+    let sent = unix_time(sent);
+    Ok(((sent - server_received) + (received - server_sent)) / 2.0)
+}
+
+fn unix_time(time: SystemTime) -> f64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs_f64(),
+        Err(err) => -err.duration().as_secs_f64(),
+    }
+}
+
+fn to_ntp(unix: f64) -> [u8; 8] {
+    let ntp = unix + NTP_EPOCH;
+    let seconds = ntp.floor();
+    let fraction = ((ntp - seconds) * 4_294_967_296.0) as u32;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&(seconds as u32).to_be_bytes());
+    bytes[4..].copy_from_slice(&fraction.to_be_bytes());
+    bytes
+}
+
+fn from_ntp(bytes: &[u8]) -> f64 {
+    let seconds = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64;
+    let fraction = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as f64;
+    seconds + fraction / 4_294_967_296.0 - NTP_EPOCH
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread::JoinHandle};
+
+    use super::*;
+
+    // 只回复一次请求的 SNTP 服务器, 服务器时间比本机慢 behind 秒.
+    fn server(behind: f64, stratum: u8, matching: bool) -> (String, JoinHandle<()>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap().to_string();
+        let handle = spawn(move || {
+            let mut request = [0u8; 48];
+            let (_, client) = socket.recv_from(&mut request).unwrap();
+            let mut reply = [0u8; 48];
+            // 版本 4, 服务器模式.
+            reply[0] = 0x24;
+            reply[1] = stratum;
+            if matching {
+                reply[24..32].copy_from_slice(&request[40..48]);
+            }
+            let now = to_ntp(unix_time(SystemTime::now()) - behind);
+            reply[32..40].copy_from_slice(&now);
+            reply[40..48].copy_from_slice(&now);
+            socket.send_to(&reply, client).unwrap();
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn ntp_timestamps() {
+        assert_eq!(to_ntp(0.0)[..4], (NTP_EPOCH as u32).to_be_bytes());
+        for unix in [0.0, 1_700_000_000.25, 1_800_000_000.5] {
+            assert!((from_ntp(&to_ntp(unix)) - unix).abs() < 1e-6, "{unix}");
+        }
+        assert_eq!(unix_time(UNIX_EPOCH + Duration::from_secs(5)), 5.0);
+        assert_eq!(unix_time(UNIX_EPOCH - Duration::from_secs(2)), -2.0);
+    }
+
+    // 偏差为本机时钟减去服务器时间, 不是回复或拒绝服务时出错.
+    #[test]
+    fn queries_server() {
+        let timeout = Duration::from_secs(5);
+        let (addr, handle) = server(5.0, 1, true);
+        let offset = query(&addr, timeout).unwrap();
+        assert!((offset - 5.0).abs() < 0.1, "{offset}");
+        handle.join().unwrap();
+
+        let (addr, handle) = server(0.0, 0, true);
+        let err = query(&addr, timeout).unwrap_err();
+        assert!(err.to_string().contains("kiss-o'-death"), "{err}");
+        handle.join().unwrap();
+
+        let (addr, handle) = server(0.0, 1, false);
+        let err = query(&addr, timeout).unwrap_err();
+        assert!(err.to_string().contains("does not match"), "{err}");
+        handle.join().unwrap();
+    }
+
+    // 只在状态类别变化时记录事件, 服务器都不回复时为 Unverified.
+    #[test]
+    fn records_state_changes() {
+        let path = std::env::temp_dir().join(format!("clock-events-{}.jsonl", std::process::id()));
+        let events = EventStore::open(&path).unwrap();
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let silent_addr = silent.local_addr().unwrap().to_string();
+        let config = |addr: &str| ClockConfig {
+            enabled: true,
+            servers: vec![silent_addr.clone(), addr.to_string()],
+            timeout: 0.1,
+            ..Default::default()
+        };
+
+        start(
+            &ClockConfig::default(),
+            events.clone(),
+            &Shutdown::default(),
+        );
+        assert_eq!(state(), ClockState::Off);
+
+        let (addr, handle) = server(5.0, 1, true);
+        check(&config(&addr), &events);
+        handle.join().unwrap();
+        assert!(matches!(state(), ClockState::Drift(offset) if offset > 4.0));
+        for _ in 0..2 {
+            let (addr, handle) = server(0.0, 1, true);
+            check(&config(&addr), &events);
+            handle.join().unwrap();
+            assert!(matches!(state(), ClockState::Verified(_)));
+        }
+        check(&config(&silent_addr), &events);
+        assert_eq!(state(), ClockState::Unverified);
+
+        let logged = crate::events::read(&path).unwrap();
+        let changes: Vec<_> = logged
+            .iter()
+            .map(|event| event.data["change"].as_str().unwrap())
+            .collect();
+        assert_eq!(changes, ["drift", "verified", "unreachable"]);
+        assert_eq!(logged[0].data["server"], json!(addr));
+        fs::remove_file(&path).unwrap();
+    }
+}
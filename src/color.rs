@@ -0,0 +1,195 @@
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, CV_64F},
+    mcc::{MCC_CCheckerDetector, MCC_CCheckerDetectorTrait, MCC_CCheckerTraitConst, MCC_TYPECHART},
+    prelude::*,
+};
+
+use crate::pipeline::FrameProcessor;
+
+// X-Rite ColorChecker Classic 24 色块的 sRGB 参考值, 按色卡上从左到右、从上到下的顺序.
+const REFERENCE_RGB: [[f64; 3]; 24] = [
+    [115., 82., 68.],
+    [194., 150., 130.],
+    [98., 122., 157.],
+    [87., 108., 67.],
+    [133., 128., 177.],
+    [103., 189., 170.],
+    [214., 126., 44.],
+    [80., 91., 166.],
+    [193., 90., 99.],
+    [94., 60., 108.],
+    [157., 188., 64.],
+    [224., 163., 46.],
+    [56., 61., 150.],
+    [70., 148., 73.],
+    [175., 54., 60.],
+    [231., 199., 31.],
+    [187., 86., 149.],
+    [8., 133., 161.],
+    [243., 243., 242.],
+    [200., 200., 200.],
+    [160., 160., 160.],
+    [122., 122., 121.],
+    [85., 85., 85.],
+    [52., 52., 52.],
+];
+
+pub type ColorMatrix = [[f64; 4]; 3];
+
+// 在画面中查找色卡, 用最小二乘拟合 BGR 仿射校色矩阵.
+pub fn calibrate(frame: &Mat) -> Result<ColorMatrix> {
+    let mut detector = MCC_CCheckerDetector::create()?;
+    if !detector.process_def(frame, MCC_TYPECHART::MCC24)? {
+        bail!("no color chart found in view");
+    }
+    let checker = detector.get_best_color_checker()?;
+    let mut charts = Mat::default();
+    // 每个色块占 3 行 (R, G, B), 第 1 列是色块均值.
+    checker
+        .get_charts_rgb()?
+        .convert_to(&mut charts, CV_64F, 1.0, 0.0)?;
+    if charts.rows() != 3 * REFERENCE_RGB.len() as i32 {
+        bail!("unexpected color chart size {}", charts.rows());
+    }
+
+    let mut measured = Vec::with_capacity(REFERENCE_RGB.len());
+    let mut reference = Vec::with_capacity(REFERENCE_RGB.len());
+    for (i, [r, g, b]) in REFERENCE_RGB.iter().enumerate() {
+        let row = 3 * i as i32;
+        let mr = *charts.at_2d::<f64>(row, 1)?;
+        let mg = *charts.at_2d::<f64>(row + 1, 1)?;
+        let mb = *charts.at_2d::<f64>(row + 2, 1)?;
+        measured.push([mb, mg, mr, 1.0]);
+        reference.push([*b, *g, *r]);
+    }
+    fit_affine(&measured, &reference)
+}
+
+// 求解正规方程 (AᵀA) X = AᵀB, A 为 Nx4, B 为 Nx3, 返回 Xᵀ (3x4).
+fn fit_affine(a: &[[f64; 4]], b: &[[f64; 3]]) -> Result<ColorMatrix> {
+    let mut ata = [[0.0; 4]; 4];
+    let mut atb = [[0.0; 3]; 4];
+    for (row_a, row_b) in a.iter().zip(b) {
+        for i in 0..4 {
+            for j in 0..4 {
+                ata[i][j] += row_a[i] * row_a[j];
+            }
+            for j in 0..3 {
+                atb[i][j] += row_a[i] * row_b[j];
+            }
+        }
+    }
+    // 高斯消元 (列主元).
+    for col in 0..4 {
+        let pivot = (col..4)
+            .max_by(|&x, &y| ata[x][col].abs().total_cmp(&ata[y][col].abs()))
+            .unwrap();
+        if ata[pivot][col].abs() < 1e-9 {
+            bail!("color chart samples are degenerate");
+        }
+        ata.swap(col, pivot);
+        atb.swap(col, pivot);
+        for row in 0..4 {
+            if row != col {
+                let factor = ata[row][col] / ata[col][col];
+                for k in 0..4 {
+                    ata[row][k] -= factor * ata[col][k];
+                }
+                for k in 0..3 {
+                    atb[row][k] -= factor * atb[col][k];
+                }
+            }
+        }
+    }
+    let mut m = [[0.0; 4]; 3];
+    for (c, row) in m.iter_mut().enumerate() {
+        for (k, v) in row.iter_mut().enumerate() {
+            *v = atb[k][c] / ata[k][k];
+        }
+    }
+    Ok(m)
+}
+
+pub struct ColorCorrection {
+    matrix: Mat,
+    scratch: Mat,
+}
+
+impl ColorCorrection {
+    pub fn new(matrix: &ColorMatrix) -> Result<Self> {
+        Ok(Self {
+            matrix: Mat::from_slice_2d(matrix)?,
+            scratch: Mat::default(),
+        })
+    }
+}
+
+impl FrameProcessor for ColorCorrection {
+    fn name(&self) -> &str {
+        "color-correction"
+    }
+
+    fn order(&self) -> i32 {
+        -10
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        core::transform(frame, &mut self.scratch, &self.matrix)?;
+        std::mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    // 用已知的矩阵生成色块的测量值, 拟合应该得到同一个矩阵.
+    #[test]
+    fn fit_recovers_matrix() {
+        let expected: ColorMatrix = [
+            [1.1, 0.05, 0.0, -5.0],
+            [0.02, 0.95, 0.03, 3.0],
+            [0.0, 0.1, 0.9, 8.0],
+        ];
+        let measured: Vec<[f64; 4]> = REFERENCE_RGB
+            .iter()
+            .map(|[r, g, b]| [*b, *g, *r, 1.0])
+            .collect();
+        let reference: Vec<[f64; 3]> = measured
+            .iter()
+            .map(|a| expected.map(|row| (0..4).map(|k| row[k] * a[k]).sum()))
+            .collect();
+        let fitted = fit_affine(&measured, &reference).unwrap();
+        for (row, expected) in fitted.iter().zip(&expected) {
+            for (v, e) in row.iter().zip(expected) {
+                assert!((v - e).abs() < 1e-6, "{:?}", fitted);
+            }
+        }
+        // 所有色块都一样时无法求解.
+        let gray = vec![[128.0, 128.0, 128.0, 1.0]; 24];
+        assert!(fit_affine(&gray, &reference).is_err());
+    }
+
+    #[test]
+    fn correction_applies_matrix() {
+        // 交换 B 和 R, G 加 10.
+        let matrix: ColorMatrix = [
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 1.0, 0.0, 10.0],
+            [1.0, 0.0, 0.0, 0.0],
+        ];
+        let mut correction = ColorCorrection::new(&matrix).unwrap();
+        let mut frame =
+            Mat::new_rows_cols_with_default(2, 2, CV_8UC3, Scalar::new(30.0, 60.0, 250.0, 0.0))
+                .unwrap();
+        correction.process(&mut frame).unwrap();
+        assert_eq!(
+            *frame.at_2d::<Vec3b>(1, 1).unwrap(),
+            Vec3b::from([250, 70, 30])
+        );
+    }
+}
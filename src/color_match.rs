@@ -0,0 +1,233 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Size, Vec3b, CV_8UC3},
+    imgproc::{self, INTER_AREA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+// 统计直方图用的缩小宽度.
+const SAMPLE_WIDTH: i32 = 160;
+
+// 多机位调色: 路由中其他摄像头的 color-match 阶段按直方图匹配到主摄像头 (预览来源) 处理后的画面,
+// 多机位录像剪辑在一起时颜色一致.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ColorMatchConfig {
+    // 重新统计主摄像头和本摄像头直方图的间隔 (秒).
+    pub interval: f64,
+    // 匹配的程度, 0 为不变, 1 为完全匹配.
+    pub strength: f64,
+    // 调整跟随画面变化的时间常数 (秒), 避免切换时颜色跳变.
+    pub smoothing: f64,
+}
+
+impl Default for ColorMatchConfig {
+    fn default() -> Self {
+        Self {
+            interval: 1.0,
+            strength: 1.0,
+            smoothing: 3.0,
+        }
+    }
+}
+
+// B, G, R 三个通道的直方图.
+pub type Histograms = [[u64; 256]; 3];
+
+// 主摄像头最近的直方图和统计时间.
+static REFERENCE: Mutex<Option<(Histograms, Instant)>> = Mutex::new(None);
+
+// 采集线程在主摄像头的画面处理后调用, 每 interval 秒统计一次.
+pub fn publish(frame: &Mat, interval: f64) -> Result<()> {
+    let due = REFERENCE
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map_or(true, |(_, time)| time.elapsed().as_secs_f64() >= interval);
+    if due {
+        let histograms = histograms(frame, &mut Mat::default())?;
+        *REFERENCE.lock().unwrap() = Some((histograms, Instant::now()));
+    }
+    Ok(())
+}
+
+fn histograms(frame: &Mat, small: &mut Mat) -> Result<Histograms> {
+    let scale = (SAMPLE_WIDTH as f64 / frame.cols() as f64).min(1.0);
+    imgproc::resize(frame, small, Size::new(0, 0), scale, scale, INTER_AREA)?;
+    let mut histograms = [[0u64; 256]; 3];
+    for pixel in small.data_bytes()?.chunks_exact(3) {
+        for (channel, &value) in pixel.iter().enumerate() {
+            histograms[channel][value as usize] += 1;
+        }
+    }
+    Ok(histograms)
+}
+
+// 直方图匹配: 把 source 的每个值映射到 reference 中累积比例相同的值, 结果单调不减.
+pub fn match_lut(source: &[u64; 256], reference: &[u64; 256]) -> [u8; 256] {
+    let cdf = |histogram: &[u64; 256]| {
+        let total = histogram.iter().sum::<u64>().max(1) as f64;
+        let mut sum = 0;
+        histogram.map(|count| {
+            sum += count;
+            sum as f64 / total
+        })
+    };
+    let (source, reference) = (cdf(source), cdf(reference));
+    let mut lut = [0u8; 256];
+    let mut target = 0;
+    for (value, &fraction) in source.iter().enumerate() {
+        while target < 255 && reference[target] < fraction - 1e-9 {
+            target += 1;
+        }
+        lut[value] = target as u8;
+    }
+    lut
+}
+
+pub struct ColorMatch {
+    interval: Duration,
+    strength: f64,
+    smoothing: f64,
+    last: Option<Instant>,
+    // 当前使用的映射, 平滑地向目标靠近.
+    curves: [[f64; 256]; 3],
+    lut: Mat,
+    small: Mat,
+    scratch: Mat,
+}
+
+impl ColorMatch {
+    pub fn new(config: &ColorMatchConfig) -> Self {
+        let identity = std::array::from_fn(|value| value as f64);
+        Self {
+            interval: Duration::from_secs_f64(config.interval.max(0.1)),
+            strength: config.strength.clamp(0.0, 1.0),
+            smoothing: config.smoothing.max(0.0),
+            last: None,
+            curves: [identity; 3],
+            lut: Mat::default(),
+            small: Mat::default(),
+            scratch: Mat::default(),
+        }
+    }
+
+    fn update(&mut self, frame: &Mat) -> Result<()> {
+        let Some((reference, _)) = *REFERENCE.lock().unwrap() else {
+            return Ok(());
+        };
+        let elapsed = self
+            .last
+            .map_or(f64::INFINITY, |last| last.elapsed().as_secs_f64());
+        self.last = Some(Instant::now());
+        let source = histograms(frame, &mut self.small)?;
+        // 第一次直接使用, 之后按时间常数平滑.
+        let alpha = if self.smoothing > 0.0 && elapsed.is_finite() {
+            1.0 - (-elapsed / self.smoothing).exp()
+        } else {
+            1.0
+        };
+        for ((curves, source), reference) in self.curves.iter_mut().zip(&source).zip(&reference) {
+            let lut = match_lut(source, reference);
+            for (value, curve) in curves.iter_mut().enumerate() {
+                let target = value as f64 + (lut[value] as f64 - value as f64) * self.strength;
+                *curve += (target - *curve) * alpha;
+            }
+        }
+        let mut lut = Mat::new_rows_cols_with_default(1, 256, CV_8UC3, Default::default())?;
+        for value in 0..256 {
+            let [b, g, r] = self
+                .curves
+                .map(|curve| curve[value].round().clamp(0.0, 255.0) as u8);
+            *lut.at_2d_mut::<Vec3b>(0, value as i32)? = Vec3b::from([b, g, r]);
+        }
+        self.lut = lut;
+        Ok(())
+    }
+}
+
+impl FrameProcessor for ColorMatch {
+    fn name(&self) -> &str {
+        "color-match"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let due = self
+            .last
+            .map_or(true, |last| last.elapsed() >= self.interval);
+        if due {
+            self.update(frame)?;
+        }
+        if self.lut.empty() {
+            return Ok(());
+        }
+        core::lut(frame, &self.lut, &mut self.scratch)?;
+        std::mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+
+    // 在本摄像头自己的白平衡和颜色校正之后, 裁剪之前.
+    fn order(&self) -> i32 {
+        -8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::Scalar;
+
+    use super::*;
+
+    fn histogram(values: &[(usize, u64)]) -> [u64; 256] {
+        let mut histogram = [0; 256];
+        for &(value, count) in values {
+            histogram[value] = count;
+        }
+        histogram
+    }
+
+    // 累积比例相同的值对应起来: 暗的一半到暗的一半, 亮的一半到亮的一半.
+    #[test]
+    fn lut_maps_matching_fractions() {
+        let lut = match_lut(&histogram(&[(50, 10)]), &histogram(&[(150, 10)]));
+        assert_eq!((lut[0], lut[50], lut[255]), (0, 150, 150));
+
+        let lut = match_lut(
+            &histogram(&[(40, 5), (200, 5)]),
+            &histogram(&[(100, 50), (220, 50)]),
+        );
+        assert_eq!((lut[40], lut[120], lut[200]), (100, 100, 220));
+
+        let source = histogram(&[(10, 3), (90, 1), (250, 7)]);
+        let lut = match_lut(&source, &source);
+        assert_eq!((lut[10], lut[90], lut[250]), (10, 90, 250));
+    }
+
+    // 其他摄像头按 strength 向主摄像头的颜色靠近, 每个通道单独匹配.
+    #[test]
+    fn matches_the_reference_camera() {
+        let frame = |b: f64, g: f64, r: f64| {
+            Mat::new_rows_cols_with_default(8, 8, CV_8UC3, Scalar::new(b, g, r, 0.0)).unwrap()
+        };
+        publish(&frame(150.0, 120.0, 90.0), 0.0).unwrap();
+        let mut stage = ColorMatch::new(&ColorMatchConfig {
+            strength: 0.5,
+            smoothing: 0.0,
+            ..Default::default()
+        });
+        let mut image = frame(50.0, 50.0, 50.0);
+        stage.process(&mut image).unwrap();
+        assert_eq!(
+            *image.at_2d::<Vec3b>(3, 3).unwrap(),
+            Vec3b::from([100, 85, 70])
+        );
+    }
+}
@@ -0,0 +1,270 @@
+use std::{path::Path, time::Duration};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size},
+    highgui,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+    videoio::{self, VideoCapture, VideoWriter},
+};
+
+use crate::{
+    config::Config, events::EventStore, pipeline::Pipeline, profile::CameraProfile, stages::Stages,
+    trajectory::Trajectories,
+};
+
+struct Variant {
+    name: String,
+    pipeline: Pipeline,
+    timings: Vec<(String, Duration)>,
+    frame: Mat,
+}
+
+impl Variant {
+    fn load(
+        path: &Path,
+        output: &Path,
+        stem: &str,
+        camera: &str,
+        frame_size: (i32, i32),
+    ) -> Result<Self> {
+        // load_from 在文件不存在时返回默认配置, 这里需要显式检查.
+        if !path.exists() {
+            bail!("config {} not found", path.display());
+        }
+        let config = Config::load_from(path)?;
+        let name = path.file_stem().context("config has no file name")?;
+        let name = name.to_string_lossy().to_string();
+        let profile_dir = CameraProfile::dir(&config.profiles_dir, camera);
+        let profile = CameraProfile::load(&profile_dir)?;
+        let events = EventStore::open(output.join(format!("{}-{}-events.jsonl", stem, name)))?;
+        let stages = Stages {
+            config: &config,
+            profile: &profile,
+            profile_dir: &profile_dir,
+            events: &events,
+            frame_size,
+            window: None,
+            models_ready: None,
+        };
+        let pipeline = stages.pipeline(&Trajectories::default())?;
+        Ok(Self {
+            name,
+            pipeline,
+            timings: Vec::new(),
+            frame: Mat::default(),
+        })
+    }
+
+    fn process(&mut self, frame: &Mat) -> Result<()> {
+        frame.copy_to(&mut self.frame)?;
+        self.pipeline
+            .process_timed(&mut self.frame, &mut self.timings);
+        imgproc::put_text(
+            &mut self.frame,
+            &self.name,
+            Point::new(8, 28),
+            FONT_HERSHEY_SIMPLEX,
+            0.9,
+            Scalar::new(0., 255., 255., 0.),
+            2,
+            LINE_AA,
+            false,
+        )?;
+        Ok(())
+    }
+
+    fn per_frame(&self, stage: &str, frames: u64) -> Option<f64> {
+        self.timings
+            .iter()
+            .find(|(name, _)| name == stage)
+            .map(|(_, total)| total.as_secs_f64() * 1000.0 / frames as f64)
+    }
+}
+
+// 用两份配置各自创建处理管线, 对同一视频的每一帧分别处理, 左右拼接输出,
+// 最后打印每个阶段的平均耗时对比.
+pub fn run(
+    input: &Path,
+    a: &Path,
+    b: &Path,
+    output: &Path,
+    camera: &str,
+    show: bool,
+) -> Result<()> {
+    let mut video = VideoCapture::from_file(&input.to_string_lossy(), videoio::CAP_ANY)?;
+    if !video.is_opened()? {
+        bail!("can not open {}", input.display());
+    }
+    let width = video.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32;
+    let height = video.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32;
+    let fps = video.get(videoio::CAP_PROP_FPS)?;
+    let stem = input.file_stem().context("input has no file name")?;
+    let stem = stem.to_string_lossy().to_string();
+
+    std::fs::create_dir_all(output)?;
+    let mut variants = [
+        Variant::load(a, output, &stem, camera, (width, height))?,
+        Variant::load(b, output, &stem, camera, (width, height))?,
+    ];
+    let output_file = output.join(format!(
+        "{}-{}-vs-{}.mp4",
+        stem, variants[0].name, variants[1].name
+    ));
+    let mut writer = VideoWriter::new(
+        &output_file.to_string_lossy(),
+        VideoWriter::fourcc('m', 'p', '4', 'v')?,
+        if fps > 0.0 { fps } else { 30.0 },
+        Size::new(width * 2, height),
+        true,
+    )?;
+
+    let mut frame = Mat::default();
+    let mut combined = Mat::default();
+    let mut frames = 0u64;
+    while video.read(&mut frame)? && !frame.empty() {
+        for variant in variants.iter_mut() {
+            variant.process(&frame)?;
+        }
+        core::hconcat2(&variants[0].frame, &variants[1].frame, &mut combined)?;
+        writer.write(&combined)?;
+        frames += 1;
+        if show {
+            highgui::imshow("compare", &combined)?;
+            // Esc 或 q 提前结束.
+            let key = highgui::wait_key(1)?;
+            if key == 27 || key == 'q' as i32 {
+                break;
+            }
+        }
+    }
+    if frames == 0 {
+        bail!("no frames in {}", input.display());
+    }
+
+    let [a, b] = &variants;
+    let mut stages: Vec<&str> = a.timings.iter().map(|(name, _)| name.as_str()).collect();
+    for (name, _) in &b.timings {
+        if !stages.contains(&name.as_str()) {
+            stages.push(name);
+        }
+    }
+    let cell = |ms: Option<f64>| ms.map_or("-".to_string(), |ms| format!("{:.2}", ms));
+    println!("{} frames, ms per frame", frames);
+    println!(
+        "{:<20} {:>10} {:>10} {:>10}",
+        "stage", a.name, b.name, "diff"
+    );
+    let (mut total_a, mut total_b) = (0.0, 0.0);
+    for stage in stages {
+        let (ta, tb) = (a.per_frame(stage, frames), b.per_frame(stage, frames));
+        total_a += ta.unwrap_or(0.0);
+        total_b += tb.unwrap_or(0.0);
+        let diff = ta.zip(tb).map(|(ta, tb)| tb - ta);
+        println!(
+            "{:<20} {:>10} {:>10} {:>10}",
+            stage,
+            cell(ta),
+            cell(tb),
+            cell(diff)
+        );
+    }
+    println!(
+        "{:<20} {:>10.2} {:>10.2} {:>10.2}",
+        "total",
+        total_a,
+        total_b,
+        total_b - total_a
+    );
+    println!("side-by-side output written to {}", output_file.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    #[test]
+    fn per_frame() {
+        let variant = Variant {
+            name: "a".to_string(),
+            pipeline: Pipeline::default(),
+            timings: vec![("speed".to_string(), Duration::from_millis(30))],
+            frame: Mat::default(),
+        };
+        assert_eq!(variant.per_frame("speed", 10), Some(3.0));
+        assert_eq!(variant.per_frame("tracking", 10), None);
+    }
+
+    #[test]
+    fn side_by_side() {
+        let dir = std::env::temp_dir().join(format!("compare-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("clip.avi");
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &input.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        for i in 0..5 {
+            let frame =
+                Mat::new_rows_cols_with_default(48, 64, core::CV_8UC3, Scalar::all(i as f64 * 40.))
+                    .unwrap();
+            writer.write(&frame).unwrap();
+        }
+        drop(writer);
+        let profiles = format!(
+            "profiles_dir = {:?}\n",
+            dir.join("profiles").display().to_string()
+        );
+        fs::write(dir.join("plain.toml"), &profiles).unwrap();
+        fs::write(
+            dir.join("speed.toml"),
+            format!("{}[speed]\nenabled = true\n", profiles),
+        )
+        .unwrap();
+        let output = dir.join("output");
+
+        let missing = run(
+            &input,
+            &dir.join("plain.toml"),
+            &dir.join("none.toml"),
+            &output,
+            "camera-0",
+            false,
+        );
+        assert!(missing.unwrap_err().to_string().contains("not found"));
+
+        run(
+            &input,
+            &dir.join("plain.toml"),
+            &dir.join("speed.toml"),
+            &output,
+            "camera-0",
+            false,
+        )
+        .unwrap();
+        assert!(output.join("clip-plain-events.jsonl").exists());
+        assert!(output.join("clip-speed-events.jsonl").exists());
+        let mut video = VideoCapture::from_file(
+            &output.join("clip-plain-vs-speed.mp4").to_string_lossy(),
+            videoio::CAP_ANY,
+        )
+        .unwrap();
+        let mut frame = Mat::default();
+        let mut frames = 0;
+        while video.read(&mut frame).unwrap() && !frame.empty() {
+            assert_eq!((frame.cols(), frame.rows()), (128, 48));
+            frames += 1;
+        }
+        assert_eq!(frames, 5);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::{
+    ar::ArConfig,
+    audio::AudioConfig,
+    auth::AuthConfig,
+    backlight::BacklightConfig,
+    camera::CameraConfig,
+    captions::CaptionConfig,
+    chroma_key::ChromaKeyConfig,
+    clips::ClipConfig,
+    clock::ClockConfig,
+    color_match::ColorMatchConfig,
+    crash::CrashConfig,
+    daily_summary::DailySummaryConfig,
+    day_night::DayNightConfig,
+    denoise::DenoiseConfig,
+    detection::DetectionConfig,
+    effects::Effect,
+    fall::FallConfig,
+    faults::FaultsConfig,
+    flat_field::FlatFieldConfig,
+    framing::FramingConfig,
+    gauges::GaugeConfig,
+    gps::GpsConfig,
+    health::HealthConfig,
+    high_speed::HighSpeedConfig,
+    hot_pixels::HotPixelConfig,
+    infrared::InfraredConfig,
+    intrusion::IntrusionConfig,
+    lanes::LaneConfig,
+    libcamera::LibcameraConfig,
+    mdns::MdnsConfig,
+    meteor::MeteorConfig,
+    migrate::{self, CONFIG_VERSION},
+    models::ModelConfig,
+    monitor::MonitorConfig,
+    occupancy::OccupancyConfig,
+    ocr::OcrConfig,
+    osd::OsdProfile,
+    parking::ParkingConfig,
+    photo::PhotoConfig,
+    ppe::PpeConfig,
+    presentation::PresentationConfig,
+    printer::PrinterConfig,
+    projection::ProjectionConfig,
+    queue::QueueConfig,
+    raw::RawConfig,
+    reaction::ReactionConfig,
+    remote::RemoteConfig,
+    restore::RestoreConfig,
+    retention::RetentionConfig,
+    review::ReviewConfig,
+    routing::RouteConfig,
+    scenes::SceneConfig,
+    session::SessionConfig,
+    sink::SinkConfig,
+    slate::SlateConfig,
+    soak::SoakConfig,
+    speed::SpeedConfig,
+    spotlight::SpotlightConfig,
+    stats::StatsConfig,
+    still::StillConfig,
+    stop_motion::StopMotionConfig,
+    storage::StorageConfig,
+    stream::StreamConfig,
+    summary::SummaryConfig,
+    supervisor::ReconnectConfig,
+    sync::SyncConfig,
+    tamper::TamperConfig,
+    telemetry::TelemetryConfig,
+    threads::ThreadsConfig,
+    throttle::ThrottleConfig,
+    thumbnail::ThumbnailConfig,
+    timeshift::TimeShiftConfig,
+    tracking::TrackingConfig,
+    update::UpdateConfig,
+    white_balance::WhiteBalanceConfig,
+    wildlife::WildlifeConfig,
+    zones::{DwellConfig, Zone},
+};
+
+// 默认配置文件, 不存在时使用内置默认值.
+pub const CONFIG_FILE: &str = "slint-opencv.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // 配置文件的版本, 旧版本的文件在读取时自动迁移.
+    pub version: i64,
+    // 视频来源 URI, 例如 "camera://1", "rtsp://..." 或 "screen://0",
+    // 不设置时按 [camera] 和 [libcamera] 选择.
+    pub source: Option<String>,
+    // 实时来源断开后自动重新连接.
+    pub reconnect: ReconnectConfig,
+    pub faults: FaultsConfig,
+    // 预览的最高帧率, 不设置时显示每一帧. 录制和输出不受影响.
+    pub preview_fps: Option<f64>,
+    // 摄像头校准数据 (camera profile) 的保存目录.
+    pub profiles_dir: PathBuf,
+    // 下载的模型文件缓存目录.
+    pub models_dir: PathBuf,
+    pub models: BTreeMap<String, ModelConfig>,
+    pub osd: OsdConfig,
+    pub clock: ClockConfig,
+    pub captions: CaptionConfig,
+    pub telemetry: TelemetryConfig,
+    pub gps: GpsConfig,
+    pub sync: SyncConfig,
+    pub white_balance: WhiteBalanceConfig,
+    pub hot_pixels: HotPixelConfig,
+    pub flat_field: FlatFieldConfig,
+    pub backlight: BacklightConfig,
+    pub color_match: ColorMatchConfig,
+    pub framing: FramingConfig,
+    pub still: StillConfig,
+    pub photo: PhotoConfig,
+    pub presentation: PresentationConfig,
+    pub stop_motion: StopMotionConfig,
+    pub timeshift: TimeShiftConfig,
+    pub high_speed: HighSpeedConfig,
+    // 按顺序执行的画面效果, 为空时不启用.
+    pub effects: Vec<Effect>,
+    // 音频输入和随声音变化的叠加效果.
+    pub audio: AudioConfig,
+    pub spotlight: SpotlightConfig,
+    pub chroma_key: ChromaKeyConfig,
+    pub ar: ArConfig,
+    pub projection: ProjectionConfig,
+    pub denoise: DenoiseConfig,
+    pub day_night: DayNightConfig,
+    pub infrared: InfraredConfig,
+    pub raw: RawConfig,
+    pub lanes: LaneConfig,
+    pub speed: SpeedConfig,
+    // 事件存储文件 (JSON Lines).
+    pub events_file: PathBuf,
+    // 审计日志文件 (JSON Lines), 记录采集, 录制, 推流和隐私快门的开始和结束.
+    pub audit_file: PathBuf,
+    pub tracking: TrackingConfig,
+    pub detection: DetectionConfig,
+    pub wildlife: WildlifeConfig,
+    pub meteor: MeteorConfig,
+    pub gauges: GaugeConfig,
+    pub reaction: ReactionConfig,
+    pub printer: PrinterConfig,
+    pub ppe: PpeConfig,
+    pub ocr: OcrConfig,
+    pub fall: FallConfig,
+    pub monitor: MonitorConfig,
+    pub tamper: TamperConfig,
+    pub retention: RetentionConfig,
+    pub storage: StorageConfig,
+    pub throttle: ThrottleConfig,
+    pub review: ReviewConfig,
+    pub clips: ClipConfig,
+    pub daily_summary: DailySummaryConfig,
+    pub session: SessionConfig,
+    pub crash: CrashConfig,
+    pub update: UpdateConfig,
+    pub stats: StatsConfig,
+    pub health: HealthConfig,
+    // Prometheus 指标地址, 例如 "127.0.0.1:9100", 不设置时不启动.
+    pub metrics: Option<String>,
+    // 控制接口 (HTTP) 地址, 例如 "127.0.0.1:9200", 不设置时不启动.
+    pub api: Option<String>,
+    pub stream: StreamConfig,
+    pub remote: RemoteConfig,
+    // 上面几个远程接口, 远程控制和字幕 WebSocket 的用户和 TLS.
+    pub auth: AuthConfig,
+    pub mdns: MdnsConfig,
+    pub scenes: Vec<SceneConfig>,
+    pub thumbnail: ThumbnailConfig,
+    pub summary: SummaryConfig,
+    pub slate: SlateConfig,
+    pub soak: SoakConfig,
+    pub zones: Vec<Zone>,
+    // 不参与亮度测量和运动统计的区域, 例如天空和反光的水面.
+    pub exclusions: Vec<Zone>,
+    pub occupancy: OccupancyConfig,
+    pub dwell: DwellConfig,
+    pub queue: QueueConfig,
+    pub parking: ParkingConfig,
+    pub intrusion: IntrusionConfig,
+    pub threads: ThreadsConfig,
+    pub libcamera: LibcameraConfig,
+    pub camera: CameraConfig,
+    // 录制输出, 不设置时录制到 capture-<时间>.mp4.
+    pub sinks: Vec<SinkConfig>,
+    pub routes: Vec<RouteConfig>,
+    pub restore: RestoreConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            source: None,
+            reconnect: ReconnectConfig::default(),
+            faults: FaultsConfig::default(),
+            preview_fps: None,
+            profiles_dir: PathBuf::from("profiles"),
+            models_dir: PathBuf::from("models"),
+            models: BTreeMap::new(),
+            osd: OsdConfig::default(),
+            clock: ClockConfig::default(),
+            captions: CaptionConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            gps: GpsConfig::default(),
+            sync: SyncConfig::default(),
+            white_balance: WhiteBalanceConfig::default(),
+            hot_pixels: HotPixelConfig::default(),
+            flat_field: FlatFieldConfig::default(),
+            backlight: BacklightConfig::default(),
+            color_match: ColorMatchConfig::default(),
+            framing: FramingConfig::default(),
+            still: StillConfig::default(),
+            photo: PhotoConfig::default(),
+            presentation: PresentationConfig::default(),
+            stop_motion: StopMotionConfig::default(),
+            timeshift: TimeShiftConfig::default(),
+            high_speed: HighSpeedConfig::default(),
+            effects: Vec::new(),
+            audio: AudioConfig::default(),
+            spotlight: SpotlightConfig::default(),
+            chroma_key: ChromaKeyConfig::default(),
+            ar: ArConfig::default(),
+            projection: ProjectionConfig::default(),
+            denoise: DenoiseConfig::default(),
+            day_night: DayNightConfig::default(),
+            infrared: InfraredConfig::default(),
+            raw: RawConfig::default(),
+            lanes: LaneConfig::default(),
+            speed: SpeedConfig::default(),
+            events_file: PathBuf::from(crate::events::EVENTS_FILE),
+            audit_file: PathBuf::from(crate::privacy::AUDIT_FILE),
+            tracking: TrackingConfig::default(),
+            detection: DetectionConfig::default(),
+            wildlife: WildlifeConfig::default(),
+            meteor: MeteorConfig::default(),
+            gauges: GaugeConfig::default(),
+            reaction: ReactionConfig::default(),
+            printer: PrinterConfig::default(),
+            ppe: PpeConfig::default(),
+            ocr: OcrConfig::default(),
+            fall: FallConfig::default(),
+            monitor: MonitorConfig::default(),
+            tamper: TamperConfig::default(),
+            retention: RetentionConfig::default(),
+            storage: StorageConfig::default(),
+            throttle: ThrottleConfig::default(),
+            review: ReviewConfig::default(),
+            clips: ClipConfig::default(),
+            daily_summary: DailySummaryConfig::default(),
+            session: SessionConfig::default(),
+            crash: CrashConfig::default(),
+            update: UpdateConfig::default(),
+            stats: StatsConfig::default(),
+            health: HealthConfig::default(),
+            metrics: None,
+            api: None,
+            stream: StreamConfig::default(),
+            remote: RemoteConfig::default(),
+            auth: AuthConfig::default(),
+            mdns: MdnsConfig::default(),
+            scenes: Vec::new(),
+            thumbnail: ThumbnailConfig::default(),
+            summary: SummaryConfig::default(),
+            slate: SlateConfig::default(),
+            soak: SoakConfig::default(),
+            zones: Vec::new(),
+            exclusions: Vec::new(),
+            occupancy: OccupancyConfig::default(),
+            dwell: DwellConfig::default(),
+            queue: QueueConfig::default(),
+            parking: ParkingConfig::default(),
+            intrusion: IntrusionConfig::default(),
+            threads: ThreadsConfig::default(),
+            libcamera: LibcameraConfig::default(),
+            camera: CameraConfig::default(),
+            sinks: SinkConfig::defaults(),
+            routes: Vec::new(),
+            restore: RestoreConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Result<Self> {
+        Self::load_from(CONFIG_FILE)
+    }
+
+    pub fn load_from(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text =
+            fs::read_to_string(path).with_context(|| format!("read config {}", path.display()))?;
+        let text = migrate::upgrade(path, &text)?.unwrap_or(text);
+        toml::from_str(&text).with_context(|| format!("parse config {}", path.display()))
+    }
+}
+
+// OSD 配置: 命名的 profile 集合, 以及预览和录制各自使用的 profile 名称.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct OsdConfig {
+    pub preview: String,
+    pub recording: String,
+    pub profiles: BTreeMap<String, OsdProfile>,
+}
+
+impl Default for OsdConfig {
+    fn default() -> Self {
+        let mut profiles = BTreeMap::new();
+        profiles.insert("full".to_string(), OsdProfile::full());
+        profiles.insert("none".to_string(), OsdProfile::default());
+        Self {
+            preview: "full".to_string(),
+            recording: "none".to_string(),
+            profiles,
+        }
+    }
+}
+
+impl OsdConfig {
+    pub fn profile(&self, name: &str) -> Result<OsdProfile> {
+        self.profiles
+            .get(name)
+            .cloned()
+            .with_context(|| format!("unknown OSD profile {:?}", name))
+    }
+}
@@ -0,0 +1,207 @@
+use std::{collections::BTreeMap, sync::Mutex, time::Instant};
+
+use chrono::{DateTime, Local};
+
+// 网络连接面板: 网络来源 (rtsp, http), 路由的网络来源, MJPEG 推流, 推到网络地址的 ffmpeg 输出和录像存储.
+// 持有连接的线程更新这张表, 界面每秒读取一次. 重新连接的请求也记在表中, 由采集线程处理.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Source,
+    Route,
+    Stream,
+    Sink,
+    Storage,
+}
+
+impl Kind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Source => "source",
+            Kind::Route => "route source",
+            Kind::Stream => "stream",
+            Kind::Sink => "output",
+            Kind::Storage => "storage",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Status {
+    Connected,
+    Reconnecting,
+    Failed,
+    Listening,
+}
+
+impl Status {
+    pub fn label(self) -> &'static str {
+        match self {
+            Status::Connected => "connected",
+            Status::Reconnecting => "reconnecting",
+            Status::Failed => "failed",
+            Status::Listening => "listening",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Connection {
+    pub kind: Kind,
+    pub status: Status,
+    // 进入当前状态的时间.
+    pub since: DateTime<Local>,
+    // 成功重新连接的次数.
+    pub reconnects: u32,
+    // 帧到达间隔的抖动 (毫秒), 未知时为 None. OpenCV 和 ffmpeg 不提供 RTT.
+    pub jitter: Option<f64>,
+    // 推流的观看人数.
+    pub clients: Option<usize>,
+    pub last_error: Option<(DateTime<Local>, String)>,
+    // 界面上请求了重新连接, 还没有处理.
+    reconnect: bool,
+}
+
+impl Connection {
+    fn new(kind: Kind) -> Self {
+        Self {
+            kind,
+            status: Status::Connected,
+            since: Local::now(),
+            reconnects: 0,
+            jitter: None,
+            clients: None,
+            last_error: None,
+            reconnect: false,
+        }
+    }
+
+    // 状态改变时记录时间.
+    pub fn set_status(&mut self, status: Status) {
+        if self.status != status {
+            self.status = status;
+            self.since = Local::now();
+        }
+    }
+
+    pub fn error(&mut self, error: impl ToString) {
+        self.last_error = Some((Local::now(), error.to_string()));
+    }
+
+    // 推流没有连接可以重新建立.
+    pub fn reconnectable(&self) -> bool {
+        self.kind != Kind::Stream
+    }
+
+    // 面板中显示的一行.
+    pub fn describe(&self, name: &str) -> String {
+        let mut text = format!(
+            "{} {}: {} since {}",
+            self.kind.label(),
+            name,
+            self.status.label(),
+            self.since.format("%H:%M:%S")
+        );
+        if self.reconnects > 0 {
+            text += &format!(", {} reconnects", self.reconnects);
+        }
+        if let Some(jitter) = self.jitter {
+            text += &format!(", jitter {:.1} ms", jitter);
+        }
+        if let Some(clients) = self.clients {
+            text += &format!(", {} viewers", clients);
+        }
+        if let Some((time, error)) = &self.last_error {
+            text += &format!("\nlast error {}: {}", time.format("%H:%M:%S"), error);
+        }
+        text
+    }
+}
+
+static CONNECTIONS: Mutex<BTreeMap<String, Connection>> = Mutex::new(BTreeMap::new());
+
+// 经过网络的来源和输出地址.
+pub fn is_network(uri: &str) -> bool {
+    const SCHEMES: [&str; 10] = [
+        "rtsp", "rtsps", "http", "https", "rtmp", "rtmps", "srt", "udp", "tcp", "rtp",
+    ];
+    uri.split_once("://")
+        .is_some_and(|(scheme, _)| SCHEMES.contains(&scheme))
+}
+
+// 更新 name 对应的连接, 不存在时以 kind 新建.
+pub fn update(name: &str, kind: Kind, update: impl FnOnce(&mut Connection)) {
+    let mut connections = CONNECTIONS.lock().unwrap();
+    let connection = connections
+        .entry(name.to_string())
+        .or_insert_with(|| Connection::new(kind));
+    connection.kind = kind;
+    update(connection);
+}
+
+pub fn remove(name: &str) {
+    CONNECTIONS.lock().unwrap().remove(name);
+}
+
+// 按名称排序的所有连接.
+pub fn list() -> Vec<(String, Connection)> {
+    CONNECTIONS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(name, connection)| (name.clone(), connection.clone()))
+        .collect()
+}
+
+// 界面上点击重新连接时调用.
+pub fn request_reconnect(name: &str) {
+    if let Some(connection) = CONNECTIONS.lock().unwrap().get_mut(name) {
+        connection.reconnect = true;
+    }
+}
+
+// 取走 name 的重新连接请求.
+pub fn take_reconnect(name: &str) -> bool {
+    CONNECTIONS
+        .lock()
+        .unwrap()
+        .get_mut(name)
+        .is_some_and(|connection| std::mem::take(&mut connection.reconnect))
+}
+
+// 取走所有 kind 类型连接的重新连接请求, 有请求时返回 true.
+pub fn take_reconnect_kind(kind: Kind) -> bool {
+    let mut requested = false;
+    for connection in CONNECTIONS.lock().unwrap().values_mut() {
+        if connection.kind == kind {
+            requested |= std::mem::take(&mut connection.reconnect);
+        }
+    }
+    requested
+}
+
+// 帧到达间隔的抖动, 与 RTP (RFC 3550) 相同的 1/16 平滑.
+#[derive(Debug, Default)]
+pub struct Jitter {
+    last: Option<Instant>,
+    interval: Option<f64>,
+    jitter: f64,
+}
+
+impl Jitter {
+    // 每读到一帧调用, 返回当前的抖动 (毫秒).
+    pub fn frame(&mut self) -> f64 {
+        let now = Instant::now();
+        if let Some(last) = self.last.replace(now) {
+            let elapsed = now.duration_since(last).as_secs_f64() * 1000.0;
+            let interval = self.interval.get_or_insert(elapsed);
+            self.jitter += ((elapsed - *interval).abs() - self.jitter) / 16.0;
+            *interval += (elapsed - *interval) / 16.0;
+        }
+        self.jitter
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
@@ -0,0 +1,172 @@
+use opencv::{prelude::*, videoio};
+use serde::{Deserialize, Serialize};
+
+use crate::source::FrameSource;
+
+// 控制面板中可以调节的摄像头属性.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Property {
+    Exposure,
+    Brightness,
+    Contrast,
+    Gain,
+}
+
+impl Property {
+    // 控制面板中的顺序.
+    pub const ALL: [Property; 4] = [
+        Property::Exposure,
+        Property::Brightness,
+        Property::Contrast,
+        Property::Gain,
+    ];
+
+    pub fn id(self) -> i32 {
+        match self {
+            Property::Exposure => videoio::CAP_PROP_EXPOSURE,
+            Property::Brightness => videoio::CAP_PROP_BRIGHTNESS,
+            Property::Contrast => videoio::CAP_PROP_CONTRAST,
+            Property::Gain => videoio::CAP_PROP_GAIN,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Property::Exposure => "Exposure",
+            Property::Brightness => "Brightness",
+            Property::Contrast => "Contrast",
+            Property::Gain => "Gain",
+        }
+    }
+
+    // 滑块范围. 单位由后端决定: DirectShow/MSMF 的曝光为 2 的幂次秒, V4L2 为 100 us.
+    pub fn range(self) -> (f64, f64) {
+        match self {
+            Property::Exposure if cfg!(target_os = "windows") => (-13.0, 0.0),
+            Property::Exposure => (1.0, 5000.0),
+            _ => (0.0, 255.0),
+        }
+    }
+}
+
+// 分辨率下拉框中的预设, 摄像头不支持时使用最接近的分辨率.
+pub const RESOLUTIONS: [(i32, i32); 5] = [
+    (320, 240),
+    (640, 480),
+    (1280, 720),
+    (1920, 1080),
+    (3840, 2160),
+];
+
+// 解析 "1280x720".
+pub fn parse_resolution(text: &str) -> Option<(i32, i32)> {
+    let (width, height) = text.split_once('x')?;
+    Some((width.trim().parse().ok()?, height.trim().parse().ok()?))
+}
+
+// 当前来源支持的控制, 打开来源时探测一次.
+#[derive(Debug, Clone, Default)]
+pub struct Probe {
+    // 可以修改分辨率和帧率.
+    pub mode: bool,
+    // 按 Property::ALL 的顺序, 不支持的属性为 None.
+    pub values: Vec<Option<f64>>,
+}
+
+// 后端不支持的属性 get 返回 -1 或 0, set 返回 false. 写回当前值不会改变画面.
+pub fn probe(source: &mut dyn FrameSource) -> Probe {
+    let live = source.metadata().live;
+    let Some(camera) = source.capture() else {
+        return Probe {
+            mode: false,
+            values: vec![None; Property::ALL.len()],
+        };
+    };
+    let mut supported = |id: i32| -> Option<f64> {
+        let value = camera.get(id).ok()?;
+        if value == -1.0 {
+            return None;
+        }
+        camera.set(id, value).ok()?.then_some(value)
+    };
+    // 文件和网络流不能改变分辨率.
+    let mode = live && supported(videoio::CAP_PROP_FPS).is_some();
+    let values = Property::ALL
+        .iter()
+        .map(|property| supported(property.id()))
+        .collect();
+    Probe { mode, values }
+}
+
+// 当前的属性值, 不支持的属性不返回. 拍照时写入 EXIF, 文件和网络流没有摄像头设置.
+pub fn current(source: &mut dyn FrameSource) -> Vec<(Property, f64)> {
+    if !source.metadata().live {
+        return Vec::new();
+    }
+    let Some(camera) = source.capture() else {
+        return Vec::new();
+    };
+    Property::ALL
+        .iter()
+        .filter_map(|&property| {
+            let value = camera.get(property.id()).ok()?;
+            (value != -1.0).then_some((property, value))
+        })
+        .collect()
+}
+
+// 曝光值换算成秒, 单位与 Property::range 相同.
+pub fn exposure_seconds(value: f64) -> f64 {
+    if cfg!(target_os = "windows") {
+        2f64.powf(value)
+    } else {
+        value / 10000.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Config, source};
+
+    #[test]
+    fn resolution() {
+        assert_eq!(parse_resolution("1280x720"), Some((1280, 720)));
+        assert_eq!(parse_resolution(" 640 x 480 "), Some((640, 480)));
+        assert_eq!(parse_resolution("HD"), None);
+        assert!(RESOLUTIONS
+            .iter()
+            .all(
+                |(width, height)| parse_resolution(&format!("{}x{}", width, height))
+                    == Some((*width, *height))
+            ));
+    }
+
+    #[test]
+    fn properties() {
+        let labels: Vec<_> = Property::ALL
+            .iter()
+            .map(|property| property.label())
+            .collect();
+        assert_eq!(labels, ["Exposure", "Brightness", "Contrast", "Gain"]);
+        assert_eq!(Property::Gain.id(), videoio::CAP_PROP_GAIN);
+        assert_eq!(Property::Contrast.range(), (0.0, 255.0));
+        let (low, high) = Property::Exposure.range();
+        assert!(low < high);
+        #[cfg(not(target_os = "windows"))]
+        assert_eq!(exposure_seconds(100.0), 0.01);
+        #[cfg(target_os = "windows")]
+        assert_eq!(exposure_seconds(-3.0), 0.125);
+    }
+
+    // 不基于 VideoCapture 的来源没有可以调节的控制.
+    #[test]
+    fn probe_without_capture() {
+        let mut source = source::open("synthetic://64x48@30", &Config::default()).unwrap();
+        let probe = probe(source.as_mut());
+        assert!(!probe.mode);
+        assert_eq!(probe.values, [None; 4]);
+        assert!(current(source.as_mut()).is_empty());
+    }
+}
@@ -0,0 +1,230 @@
+use std::{
+    backtrace::Backtrace,
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    fs, panic,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use serde::Deserialize;
+
+// 保留最近的日志行数.
+const LOG_LINES: usize = 200;
+// 等待采集线程关闭录像文件的最长时间.
+const FINALIZE_TIMEOUT: Duration = Duration::from_secs(3);
+// 采集线程的名称.
+pub const CAPTURE_THREAD: &str = "capture";
+// UI 线程 (主线程) panic 时进程随后退出, 只有这时才需要等待采集线程关闭录像文件.
+const UI_THREAD: &str = "main";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CrashConfig {
+    pub dir: PathBuf,
+    // 提交崩溃报告的地址, 报告以纯文本 POST.
+    pub submit_url: Option<String>,
+}
+
+impl Default for CrashConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("crashes"),
+            submit_url: None,
+        }
+    }
+}
+
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+static STATE: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+// UI 线程 panic 后通知采集线程尽快退出并关闭 VideoWriter.
+static STOPPING: AtomicBool = AtomicBool::new(false);
+static FINALIZED: AtomicBool = AtomicBool::new(false);
+
+pub fn log(line: &str) {
+    let mut log = LOG.lock().unwrap();
+    if log.len() >= LOG_LINES {
+        log.pop_front();
+    }
+    log.push_back(format!("{} {}", Local::now().format("%H:%M:%S%.3f"), line));
+}
+
+// 记录写入崩溃报告的应用状态, 例如当前视频源和处理管线.
+pub fn set_state(key: &str, value: impl Into<String>) {
+    STATE.lock().unwrap().insert(key.to_string(), value.into());
+}
+
+pub fn stopping() -> bool {
+    STOPPING.load(Ordering::SeqCst)
+}
+
+// 每次运行开始时调用, 上一次运行留下的状态不影响新的采集线程.
+pub fn reset() {
+    STOPPING.store(false, Ordering::SeqCst);
+    FINALIZED.store(false, Ordering::SeqCst);
+}
+
+// 采集线程结束 (包括 panic 展开) 时标记录像文件已关闭.
+pub struct FinalizeGuard;
+
+impl Drop for FinalizeGuard {
+    fn drop(&mut self) {
+        FINALIZED.store(true, Ordering::SeqCst);
+    }
+}
+
+pub fn install(dir: &Path) {
+    let dir = dir.to_path_buf();
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let backtrace = Backtrace::force_capture();
+        let current = thread::current();
+        // 其他线程 panic 时只写报告, 采集和窗口继续运行.
+        if current.name() == Some(UI_THREAD) {
+            STOPPING.store(true, Ordering::SeqCst);
+            let start = Instant::now();
+            while !FINALIZED.load(Ordering::SeqCst) && start.elapsed() < FINALIZE_TIMEOUT {
+                thread::sleep(Duration::from_millis(20));
+            }
+        }
+        match write_report(&dir, &info.to_string(), current.name(), &backtrace) {
+            Ok(path) => eprintln!("crash report written to {}", path.display()),
+            Err(err) => eprintln!("write crash report: {:?}", err),
+        }
+        default_hook(info);
+    }));
+}
+
+fn write_report(
+    dir: &Path,
+    message: &str,
+    thread: Option<&str>,
+    backtrace: &Backtrace,
+) -> Result<PathBuf> {
+    let mut report = String::new();
+    writeln!(report, "time: {}", Local::now().to_rfc3339())?;
+    writeln!(report, "version: {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(report, "thread: {}", thread.unwrap_or("<unnamed>"))?;
+    writeln!(report, "panic: {}", message)?;
+    // 锁可能被 panic 的线程持有, 这里不能阻塞.
+    if let Ok(state) = STATE.try_lock() {
+        writeln!(report, "\n== state ==")?;
+        for (key, value) in state.iter() {
+            writeln!(report, "{}: {}", key, value)?;
+        }
+    }
+    if let Ok(log) = LOG.try_lock() {
+        writeln!(report, "\n== recent log ==")?;
+        for line in log.iter() {
+            writeln!(report, "{}", line)?;
+        }
+    }
+    writeln!(report, "\n== backtrace ==\n{}", backtrace)?;
+
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!(
+        "crash-{}.txt",
+        Local::now().format("%Y-%m-%dT%H-%M-%S")
+    ));
+    fs::write(&path, report)?;
+    Ok(path)
+}
+
+// 上次运行留下的、还没有提示过用户的崩溃报告.
+pub fn pending(dir: &Path) -> Option<PathBuf> {
+    let mut reports: Vec<PathBuf> = fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with("crash-") && name.ends_with(".txt") && !name.ends_with(".seen.txt")
+        })
+        .collect();
+    reports.sort();
+    reports.pop()
+}
+
+// 提示过的报告改名为 .seen.txt, 下次启动不再提示.
+pub fn mark_seen(report: &Path) -> Result<()> {
+    let seen = report.with_extension("seen.txt");
+    fs::rename(report, &seen)?;
+    Ok(())
+}
+
+pub fn open(report: &Path) -> Result<()> {
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(target_os = "windows")]
+    let program = "notepad";
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let program = "xdg-open";
+    std::process::Command::new(program)
+        .arg(report)
+        .spawn()
+        .with_context(|| format!("open {}", report.display()))?;
+    Ok(())
+}
+
+pub fn submit(url: &str, report: &Path) -> Result<()> {
+    let text = fs::read_to_string(report)?;
+    ureq::post(url)
+        .set("Content-Type", "text/plain; charset=utf-8")
+        .send_string(&text)
+        .with_context(|| format!("submit crash report to {}", url))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_contents() {
+        for i in 0..LOG_LINES + 5 {
+            log(&format!("line {}", i));
+        }
+        set_state("source", "camera 0");
+        let dir = std::env::temp_dir().join(format!("crash-test-{}", std::process::id()));
+        let path =
+            write_report(&dir, "boom", Some(CAPTURE_THREAD), &Backtrace::disabled()).unwrap();
+        let report = fs::read_to_string(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(report.contains("thread: capture\npanic: boom\n"));
+        assert!(report.contains("== state ==\nsource: camera 0\n"));
+        // 只保留最近的日志.
+        assert!(!report.contains(" line 4\n"));
+        assert!(report.contains(" line 5\n"));
+        assert!(report.contains(&format!(" line {}\n", LOG_LINES + 4)));
+    }
+
+    #[test]
+    fn pending_reports() {
+        let dir = std::env::temp_dir().join(format!("crash-pending-{}", std::process::id()));
+        assert_eq!(pending(&dir), None);
+        fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "crash-2024-05-01T10-00-00.txt",
+            "crash-2024-05-02T10-00-00.txt",
+            "crash-2024-05-03T10-00-00.seen.txt",
+            "notes.txt",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let latest = pending(&dir).unwrap();
+        assert_eq!(latest, dir.join("crash-2024-05-02T10-00-00.txt"));
+        mark_seen(&latest).unwrap();
+        assert!(dir.join("crash-2024-05-02T10-00-00.seen.txt").exists());
+        assert_eq!(
+            pending(&dir),
+            Some(dir.join("crash-2024-05-01T10-00-00.txt"))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
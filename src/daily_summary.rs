@@ -0,0 +1,427 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Days, Local, NaiveDate, NaiveTime, TimeDelta, TimeZone};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    clips::{self, ClipConfig},
+    events::{self, Event, EventStore},
+    jobs::{self, Handle},
+    threads::{Shutdown, ThreadConfig},
+};
+
+// 每日摘要视频: 每天把前一天事件前后的录像片段拼成一个视频, 每个事件一个章节,
+// 可以在事件之间加入没有事件的时段的延时摄影.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DailySummaryConfig {
+    pub enabled: bool,
+    // 每天生成前一天摘要的时间 (本地时间 HH:MM).
+    pub at: String,
+    pub dir: PathBuf,
+    // 使用的事件类型, 为空时使用全部.
+    pub kinds: Vec<String>,
+    // 事件前后保留的秒数, 重叠的片段合并为一个.
+    pub before: f64,
+    pub after: f64,
+    // 大于 1 时没有事件的时段按这个倍数加速后放在事件之间, 例如 120 把一小时压缩为 30 秒.
+    pub hyperlapse: f64,
+    // 输出的分辨率和帧率, 不同录像的片段缩放到同样的大小后拼接.
+    pub width: i32,
+    pub height: i32,
+    pub fps: f64,
+}
+
+impl Default for DailySummaryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            at: "00:30".to_string(),
+            dir: PathBuf::from("summaries"),
+            kinds: ["motion", "speed", "sighting", "meteor"]
+                .map(String::from)
+                .to_vec(),
+            before: 5.0,
+            after: 10.0,
+            hyperlapse: 0.0,
+            width: 1280,
+            height: 720,
+            fps: 30.0,
+        }
+    }
+}
+
+// 摘要中的一段: 录像中从 start 开始 length 秒, 按 speed 倍速播放.
+struct Piece {
+    path: PathBuf,
+    start: f64,
+    length: f64,
+    speed: f64,
+    title: String,
+}
+
+// 每天 at 之后生成前一天的摘要, 启动时补上还没有生成的前一天.
+pub fn start(
+    config: &DailySummaryConfig,
+    clips: &ClipConfig,
+    events_file: &Path,
+    events: EventStore,
+    workers: &ThreadConfig,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let at = NaiveTime::parse_from_str(&config.at, "%H:%M")
+        .with_context(|| format!("daily_summary.at {:?}, expected HH:MM", config.at))?;
+    let (config, clips) = (config.clone(), clips.clone());
+    let events_file = events_file.to_path_buf();
+    let (workers, shutdown) = (workers.clone(), shutdown.clone());
+    thread::Builder::new()
+        .name("daily-summary".to_string())
+        .spawn(move || {
+            workers.apply("daily-summary");
+            loop {
+                let now = Local::now().naive_local();
+                let today = now.date();
+                if now.time() >= at {
+                    let day = today - Days::new(1);
+                    if !output(&config, day).exists() {
+                        let job = jobs::start(format!("Daily summary {}", day));
+                        let text = match compile(&config, &clips, &events_file, &events, day, &job)
+                        {
+                            Ok(message) => job.done(message),
+                            Err(err) => job.failed(&err),
+                        };
+                        println!("{}", text);
+                    }
+                }
+                let next = if now.time() < at {
+                    today.and_time(at)
+                } else {
+                    (today + Days::new(1)).and_time(at)
+                };
+                let wait = (next - now).to_std().unwrap_or_default();
+                if shutdown.wait(wait.max(Duration::from_secs(60))) {
+                    return;
+                }
+            }
+        })?;
+    Ok(())
+}
+
+pub fn output(config: &DailySummaryConfig, day: NaiveDate) -> PathBuf {
+    config
+        .dir
+        .join(format!("summary-{}.mp4", day.format("%Y-%m-%d")))
+}
+
+// 生成 day 的摘要, 返回状态栏消息. 当天没有事件时不生成文件.
+pub fn compile(
+    config: &DailySummaryConfig,
+    clips: &ClipConfig,
+    events_file: &Path,
+    store: &EventStore,
+    day: NaiveDate,
+    job: &Handle,
+) -> Result<String> {
+    let from = midnight(day)?;
+    let to = midnight(day + Days::new(1))?;
+    let events: Vec<Event> = events::read(events_file)?
+        .into_iter()
+        .filter(|event| (from..to).contains(&event.time))
+        .filter(|event| config.kinds.is_empty() || config.kinds.contains(&event.kind))
+        .collect();
+    let pieces = pieces(config, clips, &events, from, to)?;
+    let chapters = pieces.iter().filter(|piece| piece.speed == 1.0).count();
+    if chapters == 0 {
+        return Ok(format!("No recorded events on {}", day));
+    }
+
+    let parts = config.dir.join(format!(".parts-{}", day));
+    fs::create_dir_all(&parts)?;
+    let result = render(config, &pieces, &parts, &output(config, day), job);
+    let _ = fs::remove_dir_all(&parts);
+    result?;
+    let path = output(config, day);
+    store.log(
+        "daily-summary",
+        json!({
+            "day": day.to_string(),
+            "path": path.display().to_string(),
+            "events": events.len(),
+            "chapters": chapters,
+        }),
+    );
+    Ok(format!("Daily summary saved to {}", path.display()))
+}
+
+fn midnight(day: NaiveDate) -> Result<DateTime<Local>> {
+    Local
+        .from_local_datetime(&day.and_time(NaiveTime::MIN))
+        .earliest()
+        .context("local midnight")
+}
+
+// 按录像开始时间排列的片段: 每个录像中事件前后的片段, 和它们之间加速的时段.
+fn pieces(
+    config: &DailySummaryConfig,
+    clips: &ClipConfig,
+    events: &[Event],
+    from: DateTime<Local>,
+    to: DateTime<Local>,
+) -> Result<Vec<Piece>> {
+    let mut pieces = Vec::new();
+    for recording in clips::recordings(clips)? {
+        // 录像中属于这一天的部分.
+        let window = (
+            recording.offset(from).max(0.0),
+            recording.offset(to).min(recording.total),
+        );
+        if window.0 >= window.1 {
+            continue;
+        }
+        let inside: Vec<(f64, &Event)> = events
+            .iter()
+            .map(|event| (recording.offset(event.time), event))
+            .filter(|(offset, _)| (window.0..=window.1).contains(offset))
+            .collect();
+        let times: Vec<f64> = inside.iter().map(|(offset, _)| *offset).collect();
+        let segments = merge(&times, config.before, config.after, window);
+        let mut here: Vec<Piece> = segments
+            .iter()
+            .map(|&(start, end)| {
+                let mut kinds: Vec<&str> = Vec::new();
+                let mut first = None;
+                for (offset, event) in &inside {
+                    if (start..=end).contains(offset) {
+                        first.get_or_insert(event.time);
+                        if !kinds.contains(&event.kind.as_str()) {
+                            kinds.push(&event.kind);
+                        }
+                    }
+                }
+                let time = first.map(|time| time.format("%H:%M:%S").to_string());
+                Piece {
+                    path: recording.path.clone(),
+                    start,
+                    length: end - start,
+                    speed: 1.0,
+                    title: format!("{} {}", time.unwrap_or_default(), kinds.join(", ")),
+                }
+            })
+            .collect();
+        if config.hyperlapse > 1.0 && !segments.is_empty() {
+            for (start, end) in gaps(&segments, window) {
+                // 加速后不足一秒的时段不加入.
+                if end - start < config.hyperlapse {
+                    continue;
+                }
+                let time = |offset: f64| {
+                    let time =
+                        recording.started + TimeDelta::milliseconds((offset * 1000.0) as i64);
+                    time.format("%H:%M").to_string()
+                };
+                here.push(Piece {
+                    path: recording.path.clone(),
+                    start,
+                    length: end - start,
+                    speed: config.hyperlapse,
+                    title: format!("{}–{} ×{}", time(start), time(end), config.hyperlapse),
+                });
+            }
+        }
+        here.sort_by(|a, b| a.start.total_cmp(&b.start));
+        pieces.extend(here);
+    }
+    Ok(pieces)
+}
+
+// 事件前后的时段 (秒), 重叠的合并, 限制在 window 内. times 不需要有序.
+pub fn merge(times: &[f64], before: f64, after: f64, window: (f64, f64)) -> Vec<(f64, f64)> {
+    let mut times = times.to_vec();
+    times.sort_by(f64::total_cmp);
+    let mut segments: Vec<(f64, f64)> = Vec::new();
+    for time in times {
+        let start = (time - before.max(0.0)).max(window.0);
+        let end = (time + after.max(0.0)).min(window.1);
+        if start >= end {
+            continue;
+        }
+        match segments.last_mut() {
+            Some(last) if start <= last.1 => last.1 = last.1.max(end),
+            _ => segments.push((start, end)),
+        }
+    }
+    segments
+}
+
+// window 中不属于任何时段的部分, segments 有序且不重叠.
+pub fn gaps(segments: &[(f64, f64)], window: (f64, f64)) -> Vec<(f64, f64)> {
+    let mut gaps = Vec::new();
+    let mut position = window.0;
+    for &(start, end) in segments {
+        if start > position {
+            gaps.push((position, start));
+        }
+        position = position.max(end);
+    }
+    if window.1 > position {
+        gaps.push((position, window.1));
+    }
+    gaps
+}
+
+// 每段重新编码为同样的格式, 再用 concat 直接拼接并写入章节.
+fn render(
+    config: &DailySummaryConfig,
+    pieces: &[Piece],
+    parts: &Path,
+    output: &Path,
+    job: &Handle,
+) -> Result<()> {
+    let (w, h) = (config.width, config.height);
+    let mut list = String::new();
+    let mut chapters = String::from(";FFMETADATA1\n");
+    let mut position = 0.0;
+    for (i, piece) in pieces.iter().enumerate() {
+        job.progress(Some(i as f64 / pieces.len() as f64))?;
+        let filter = format!(
+            "setpts=PTS/{speed},scale={w}:{h}:force_original_aspect_ratio=decrease,\
+             pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,fps={fps}",
+            speed = piece.speed,
+            fps = config.fps,
+        );
+        let name = format!("part-{:04}.mp4", i);
+        let length = piece.length / piece.speed;
+        clips::cut(
+            &piece.path,
+            piece.start,
+            length,
+            &parts.join(&name),
+            &[
+                "-vf", &filter, "-an", "-c:v", "libx264", "-preset", "veryfast", "-pix_fmt",
+                "yuv420p",
+            ],
+        )?;
+        list.push_str(&format!("file '{}'\n", name));
+        chapters.push_str(&format!(
+            "[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            (position * 1000.0) as i64,
+            ((position + length) * 1000.0) as i64,
+            escape(&piece.title),
+        ));
+        position += length;
+    }
+    fs::write(parts.join("list.txt"), list)?;
+    fs::write(parts.join("chapters.txt"), chapters)?;
+    job.progress(Some(1.0))?;
+    let result = Command::new("ffmpeg")
+        .args(["-y", "-v", "error", "-f", "concat", "-safe", "0", "-i"])
+        .arg(parts.join("list.txt"))
+        .arg("-i")
+        .arg(parts.join("chapters.txt"))
+        .args(["-map", "0", "-map_metadata", "1", "-c", "copy"])
+        .arg(output)
+        .stdin(Stdio::null())
+        .output()
+        .context("run ffmpeg")?;
+    if !result.status.success() {
+        let _ = fs::remove_file(output);
+        bail!("ffmpeg: {}", String::from_utf8_lossy(&result.stderr).trim());
+    }
+    Ok(())
+}
+
+// ffmetadata 中 = ; # \ 和换行需要转义.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 事件前后的片段按时间排列, 重叠的合并, 超出窗口的截掉.
+    #[test]
+    fn merge_segments() {
+        let window = (0.0, 3600.0);
+        assert_eq!(
+            merge(&[100.0, 50.0, 108.0, 3590.0], 5.0, 10.0, window),
+            vec![(45.0, 60.0), (95.0, 118.0), (3585.0, 3600.0)]
+        );
+        assert_eq!(merge(&[2.0], 5.0, 10.0, window), vec![(0.0, 12.0)]);
+        assert_eq!(merge(&[4000.0], 5.0, 10.0, window), vec![]);
+        assert_eq!(merge(&[100.0], 0.0, 0.0, window), vec![]);
+    }
+
+    // 片段之间的时段用于延时摄影.
+    #[test]
+    fn gaps_between_segments() {
+        let segments = [(45.0, 60.0), (95.0, 118.0), (3585.0, 3600.0)];
+        assert_eq!(
+            gaps(&segments, (0.0, 3600.0)),
+            vec![(0.0, 45.0), (60.0, 95.0), (118.0, 3585.0)]
+        );
+        assert_eq!(gaps(&[], (10.0, 20.0)), vec![(10.0, 20.0)]);
+    }
+
+    #[test]
+    fn chapter_titles() {
+        assert_eq!(escape("12:00 motion; a=b"), r"12:00 motion\; a\=b");
+        let config = DailySummaryConfig::default();
+        let day = NaiveDate::from_ymd_opt(2024, 5, 1).unwrap();
+        assert_eq!(
+            output(&config, day),
+            Path::new("summaries/summary-2024-05-01.mp4")
+        );
+    }
+
+    // 当天有事件但没有录像时不生成文件.
+    #[test]
+    fn nothing_recorded() {
+        let dir = std::env::temp_dir().join(format!("daily-summary-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let time = Local.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+        let event = json!({ "time": time.to_rfc3339(), "kind": "motion", "data": {} });
+        let events_file = dir.join("events.jsonl");
+        fs::write(&events_file, format!("{}\n", event)).unwrap();
+        let config = DailySummaryConfig {
+            dir: dir.join("summaries"),
+            ..Default::default()
+        };
+        let clips = ClipConfig {
+            recordings: dir.clone(),
+            ..Default::default()
+        };
+        let store = EventStore::open(dir.join("log.jsonl")).unwrap();
+        let day = time.date_naive();
+        let message = compile(
+            &config,
+            &clips,
+            &events_file,
+            &store,
+            day,
+            &jobs::start("test"),
+        )
+        .unwrap();
+        assert_eq!(message, "No recorded events on 2024-05-01");
+        assert!(!output(&config, day).exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,295 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chrono::{Local, NaiveTime};
+use opencv::{
+    core::{self, Mat, Size},
+    imgproc::{self, COLOR_BGR2GRAY, INTER_AREA},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{controls::Property, effects::Effect, exclusions::ExclusionMask, occupancy::Debounce};
+
+// 测量亮度使用的画面宽度.
+const WIDTH: i32 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Phase {
+    Day,
+    Night,
+}
+
+impl Phase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Phase::Day => "day",
+            Phase::Night => "night",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    // 按画面的平均亮度切换.
+    #[default]
+    Luminance,
+    // 按 night_from 和 night_to 的时间切换.
+    Schedule,
+}
+
+// 白天和夜晚两组参数, 按画面亮度或时间自动切换, 控制面板中可以手动固定为其中一组.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DayNightConfig {
+    pub enabled: bool,
+    pub mode: Mode,
+    // luminance: 平均亮度 (0..255) 低于 night_below 时切换到夜晚, 高于 day_above 时切换到白天,
+    // 两者之间保持不变. 新的亮度持续 after 秒才切换, 避免车灯和云.
+    pub night_below: f64,
+    pub day_above: f64,
+    pub after: f64,
+    // 测量间隔 (秒).
+    pub interval: f64,
+    // schedule: 夜晚的开始和结束时间 (本地时间 HH:MM).
+    pub night_from: String,
+    pub night_to: String,
+    pub day: Profile,
+    pub night: Profile,
+}
+
+impl Default for DayNightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            mode: Mode::Luminance,
+            night_below: 40.0,
+            day_above: 70.0,
+            after: 30.0,
+            interval: 2.0,
+            night_from: "19:00".to_string(),
+            night_to: "07:00".to_string(),
+            day: Profile::default(),
+            night: Profile::default(),
+        }
+    }
+}
+
+// 一组参数, 没有设置的保持不变.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    // 摄像头属性, 例如 { exposure = 300.0, gain = 40.0 }.
+    pub controls: BTreeMap<Property, f64>,
+    // 降噪强度 (0..0.95).
+    pub denoise: Option<f64>,
+    // 目标检测的 min_score.
+    pub min_score: Option<f64>,
+    // 替换效果链, 例如夜晚使用 colormap.
+    pub effects: Option<Vec<Effect>>,
+}
+
+impl DayNightConfig {
+    pub fn profile(&self, phase: Phase) -> &Profile {
+        match phase {
+            Phase::Day => &self.day,
+            Phase::Night => &self.night,
+        }
+    }
+}
+
+// 亮度的迟滞判断, 第一次测量时以两个阈值的中间为界.
+pub fn by_luminance(brightness: f64, current: Option<Phase>, config: &DayNightConfig) -> Phase {
+    match current {
+        Some(Phase::Night) if brightness <= config.day_above => Phase::Night,
+        Some(Phase::Day) if brightness >= config.night_below => Phase::Day,
+        Some(_) if brightness > config.day_above => Phase::Day,
+        Some(_) => Phase::Night,
+        None if brightness < (config.night_below + config.day_above) / 2.0 => Phase::Night,
+        None => Phase::Day,
+    }
+}
+
+// from 晚于 to 时夜晚跨过午夜.
+pub fn by_schedule(time: NaiveTime, from: NaiveTime, to: NaiveTime) -> Phase {
+    let night = if from <= to {
+        time >= from && time < to
+    } else {
+        time >= from || time < to
+    };
+    if night {
+        Phase::Night
+    } else {
+        Phase::Day
+    }
+}
+
+pub struct DayNight {
+    config: DayNightConfig,
+    schedule: (NaiveTime, NaiveTime),
+    // 自动判断的结果和手动固定的一组.
+    automatic: Option<Phase>,
+    fixed: Option<Phase>,
+    debounce: Debounce,
+    started: Instant,
+    last: Option<Instant>,
+    small: Mat,
+    gray: Mat,
+    exclusions: ExclusionMask,
+}
+
+impl DayNight {
+    pub fn new(config: &DayNightConfig) -> Result<Self> {
+        let time = |text: &str| {
+            NaiveTime::parse_from_str(text, "%H:%M")
+                .with_context(|| format!("day_night time {:?}, expected HH:MM", text))
+        };
+        Ok(Self {
+            config: config.clone(),
+            schedule: (time(&config.night_from)?, time(&config.night_to)?),
+            automatic: None,
+            fixed: None,
+            debounce: Debounce::default(),
+            started: Instant::now(),
+            last: None,
+            small: Mat::default(),
+            gray: Mat::default(),
+            exclusions: ExclusionMask::default(),
+        })
+    }
+
+    pub fn config(&self) -> &DayNightConfig {
+        &self.config
+    }
+
+    // 当前使用的一组.
+    pub fn phase(&self) -> Option<Phase> {
+        self.fixed.or(self.automatic)
+    }
+
+    // 手动固定为白天或夜晚, None 恢复自动. 返回需要切换到的一组.
+    pub fn fix(&mut self, phase: Option<Phase>) -> Option<Phase> {
+        let before = self.phase();
+        self.fixed = phase;
+        self.phase().filter(|&after| Some(after) != before)
+    }
+
+    // 每帧调用, 返回需要切换到的一组. 没有启用时只能手动切换.
+    pub fn update(&mut self, frame: &Mat) -> Result<Option<Phase>> {
+        if !self.config.enabled {
+            return Ok(None);
+        }
+        let interval = Duration::from_secs_f64(self.config.interval.max(0.1));
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(None);
+        }
+        self.last = Some(Instant::now());
+        let before = self.phase();
+        let phase = match self.config.mode {
+            Mode::Schedule => by_schedule(Local::now().time(), self.schedule.0, self.schedule.1),
+            Mode::Luminance => {
+                let scale = (WIDTH as f64 / frame.cols().max(1) as f64).min(1.0);
+                imgproc::resize(
+                    frame,
+                    &mut self.small,
+                    Size::new(0, 0),
+                    scale,
+                    scale,
+                    INTER_AREA,
+                )?;
+                imgproc::cvt_color(&self.small, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+                // 排除区域 (例如朝阳的天空) 不参与测量.
+                let brightness = match self.exclusions.get(self.gray.size()?)? {
+                    Some(mask) => core::mean(&self.gray, mask)?[0],
+                    None => core::mean(&self.gray, &core::no_array())?[0],
+                };
+                by_luminance(brightness, self.automatic, &self.config)
+            }
+        };
+        // 第一次直接采用, 之后新的一组持续 after 秒才切换.
+        let now = self.started.elapsed().as_secs_f64();
+        let after = self.config.after.max(0.0);
+        if let Some(night) = self
+            .debounce
+            .update(phase == Phase::Night, now, after, after)
+        {
+            self.automatic = Some(if night { Phase::Night } else { Phase::Day });
+        }
+        Ok(self.phase().filter(|&after| Some(after) != before))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, CV_8UC3};
+
+    use super::*;
+
+    // 默认阈值 40 和 70, 之间保持当前的一组.
+    #[test]
+    fn luminance_hysteresis() {
+        let config = DayNightConfig::default();
+        let steps = [
+            (50.0, None, Phase::Night),
+            (60.0, None, Phase::Day),
+            (65.0, Some(Phase::Night), Phase::Night),
+            (71.0, Some(Phase::Night), Phase::Day),
+            (45.0, Some(Phase::Day), Phase::Day),
+            (39.0, Some(Phase::Day), Phase::Night),
+        ];
+        for (brightness, current, expected) in steps {
+            assert_eq!(
+                by_luminance(brightness, current, &config),
+                expected,
+                "{} from {:?}",
+                brightness,
+                current
+            );
+        }
+    }
+
+    #[test]
+    fn schedule() {
+        let time = |text| NaiveTime::parse_from_str(text, "%H:%M").unwrap();
+        let (from, to) = (time("19:00"), time("07:00"));
+        assert_eq!(by_schedule(time("23:30"), from, to), Phase::Night);
+        assert_eq!(by_schedule(time("06:59"), from, to), Phase::Night);
+        assert_eq!(by_schedule(time("07:00"), from, to), Phase::Day);
+        assert_eq!(by_schedule(time("12:00"), from, to), Phase::Day);
+        // 不跨午夜的时段.
+        let (from, to) = (time("01:00"), time("05:00"));
+        assert_eq!(by_schedule(time("03:00"), from, to), Phase::Night);
+        assert_eq!(by_schedule(time("23:00"), from, to), Phase::Day);
+        let config = DayNightConfig {
+            night_to: "7am".to_string(),
+            ..DayNightConfig::default()
+        };
+        assert!(DayNight::new(&config).is_err());
+    }
+
+    // 第一次测量直接采用, 手动固定优先, 取消固定后回到自动判断的一组.
+    #[test]
+    fn fixed_phase() {
+        let dark = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(10.0)).unwrap();
+        let mut disabled = DayNight::new(&DayNightConfig::default()).unwrap();
+        assert_eq!(disabled.update(&dark).unwrap(), None);
+        assert_eq!(disabled.fix(Some(Phase::Day)), Some(Phase::Day));
+
+        let config = DayNightConfig {
+            enabled: true,
+            ..DayNightConfig::default()
+        };
+        let mut day_night = DayNight::new(&config).unwrap();
+        assert_eq!(day_night.phase(), None);
+        assert_eq!(day_night.update(&dark).unwrap(), Some(Phase::Night));
+        assert_eq!(day_night.fix(Some(Phase::Night)), None);
+        assert_eq!(day_night.fix(Some(Phase::Day)), Some(Phase::Day));
+        assert_eq!(day_night.phase(), Some(Phase::Day));
+        assert_eq!(day_night.fix(None), Some(Phase::Night));
+    }
+}
@@ -0,0 +1,163 @@
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Point2d, Scalar, Size, BORDER_DEFAULT, BORDER_REPLICATE, CV_32F},
+    imgproc::{self, COLOR_BGR2GRAY, INTER_AREA, INTER_LINEAR, THRESH_TOZERO},
+    prelude::*,
+};
+use serde::Deserialize;
+
+// 估计全局运动用的缩小宽度.
+const SAMPLE_WIDTH: i32 = 320;
+
+// 录像的时域降噪: 在编码前把静止区域与之前的画面平均, 噪声减少后同样的码率画质更好.
+// 先按整体平移对齐 (摄像头轻微晃动), 再按逐像素的差异判断运动, 运动区域不平均, 避免拖影.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DenoiseConfig {
+    // 启动时是否降噪, 录制面板中的滑块可以随时调整强度.
+    pub enabled: bool,
+    // 静止区域中之前画面的权重, 0 为不降噪, 越大越平滑.
+    pub strength: f64,
+    // 灰度差超过该值的像素认为在运动, 不平均.
+    pub threshold: f64,
+    // 预览也显示降噪后的画面, 默认只处理录像, 预览没有额外延迟和开销.
+    pub preview: bool,
+}
+
+impl Default for DenoiseConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            strength: 0.6,
+            threshold: 12.0,
+            preview: false,
+        }
+    }
+}
+
+pub struct TemporalDenoise {
+    strength: f64,
+    threshold: f64,
+    // 上一帧的输出.
+    average: Mat,
+    // 上一帧缩小后的灰度图, 32 位浮点.
+    previous: Mat,
+    current: Mat,
+    aligned: Mat,
+    diff: Mat,
+    gray: Mat,
+    weight: Mat,
+    inverse: Mat,
+    scratch: Mat,
+}
+
+impl TemporalDenoise {
+    pub fn new(config: &DenoiseConfig) -> Self {
+        Self {
+            strength: if config.enabled { config.strength } else { 0.0 }.clamp(0.0, 0.95),
+            threshold: config.threshold.max(1.0),
+            average: Mat::default(),
+            previous: Mat::default(),
+            current: Mat::default(),
+            aligned: Mat::default(),
+            diff: Mat::default(),
+            gray: Mat::default(),
+            weight: Mat::default(),
+            inverse: Mat::default(),
+            scratch: Mat::default(),
+        }
+    }
+
+    pub fn set_strength(&mut self, strength: f64) {
+        self.strength = strength.clamp(0.0, 0.95);
+        if self.strength == 0.0 {
+            self.reset();
+        }
+    }
+
+    // 画面不连续 (停止录制, 切换来源) 时丢弃之前的画面.
+    pub fn reset(&mut self) {
+        self.average = Mat::default();
+        self.previous = Mat::default();
+    }
+
+    pub fn apply(&mut self, frame: &mut Mat) -> Result<()> {
+        if self.strength == 0.0 || frame.channels() != 3 {
+            return Ok(());
+        }
+        let scale = (SAMPLE_WIDTH as f64 / frame.cols().max(1) as f64).min(1.0);
+        imgproc::cvt_color(&*frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        imgproc::resize(
+            &self.gray,
+            &mut self.scratch,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        self.scratch
+            .convert_to(&mut self.current, CV_32F, 1.0, 0.0)?;
+        if self.average.size()? != frame.size()? || self.average.typ() != frame.typ() {
+            self.average = frame.try_clone()?;
+            std::mem::swap(&mut self.previous, &mut self.current);
+            return Ok(());
+        }
+
+        // 整体平移, 把之前的画面移到当前位置.
+        let mut response = 0.0;
+        let shift = imgproc::phase_correlate(
+            &self.previous,
+            &self.current,
+            &core::no_array(),
+            &mut response,
+        )?;
+        let shift = Point2d::new(shift.x / scale, shift.y / scale);
+        std::mem::swap(&mut self.previous, &mut self.current);
+        let aligned = if response > 0.1 && shift.x.hypot(shift.y) >= 0.5 {
+            let m = Mat::from_slice_2d(&[[1.0, 0.0, shift.x], [0.0, 1.0, shift.y]])?;
+            imgproc::warp_affine(
+                &self.average,
+                &mut self.aligned,
+                &m,
+                frame.size()?,
+                INTER_LINEAR,
+                BORDER_REPLICATE,
+                Scalar::default(),
+            )?;
+            &self.aligned
+        } else {
+            &self.average
+        };
+
+        // 之前画面的权重 = strength * (1 - 差异 / threshold), 差异超过 threshold 时为 0.
+        core::absdiff(aligned, &*frame, &mut self.diff)?;
+        imgproc::cvt_color(&self.diff, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        // 模糊后再比较, 噪声本身不会被当成运动.
+        imgproc::gaussian_blur(
+            &self.gray,
+            &mut self.scratch,
+            Size::new(5, 5),
+            0.0,
+            0.0,
+            BORDER_DEFAULT,
+        )?;
+        self.scratch.convert_to(
+            &mut self.diff,
+            CV_32F,
+            -self.strength / self.threshold,
+            self.strength,
+        )?;
+        imgproc::threshold(&self.diff, &mut self.weight, 0.0, 0.0, THRESH_TOZERO)?;
+        self.weight.convert_to(&mut self.inverse, -1, -1.0, 1.0)?;
+        imgproc::blend_linear(
+            aligned,
+            &*frame,
+            &self.weight,
+            &self.inverse,
+            &mut self.scratch,
+        )?;
+        self.scratch.copy_to(&mut self.average)?;
+        std::mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+}
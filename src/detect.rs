@@ -0,0 +1,208 @@
+use std::{path::Path, time::Instant};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Rect, Size, Vector},
+    imgproc::{self, COLOR_BGR2GRAY, INTER_AREA},
+    objdetect::{CascadeClassifier, HOGDescriptor},
+    prelude::*,
+};
+
+use crate::tracking::Detection;
+
+// 检测时把画面缩小到该宽度, HOG 在全分辨率下太慢.
+const DETECT_WIDTH: i32 = 640;
+
+// 目标检测器, 返回原始画面坐标下的检测框.
+pub trait Detector: Send {
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>>;
+    fn label(&self, class: usize) -> String;
+
+    // 实际使用的计算设备, 用于显示.
+    fn device(&self) -> String {
+        "cpu".to_string()
+    }
+}
+
+// 在黑色画面上连续推理若干次, 返回平均每秒推理次数. 第一次推理包含初始化开销, 不计入.
+pub fn benchmark(detector: &mut dyn Detector, width: i32, height: i32) -> Result<f64> {
+    const RUNS: u32 = 10;
+    let frame = Mat::zeros(height, width, core::CV_8UC3)?.to_mat()?;
+    detector.detect(&frame)?;
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        detector.detect(&frame)?;
+    }
+    Ok(RUNS as f64 / start.elapsed().as_secs_f64())
+}
+
+// OpenCV 自带的 HOG + SVM 行人检测器.
+pub struct PersonDetector {
+    hog: HOGDescriptor,
+    small: Mat,
+}
+
+impl PersonDetector {
+    pub fn new() -> Result<Self> {
+        let mut hog = HOGDescriptor::default()?;
+        hog.set_svm_detector(&HOGDescriptor::get_default_people_detector()?)?;
+        Ok(Self {
+            hog,
+            small: Mat::default(),
+        })
+    }
+}
+
+impl Detector for PersonDetector {
+    // 分数为 SVM 的输出.
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>> {
+        let scale = (DETECT_WIDTH as f64 / frame.cols() as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        let mut found = Vector::<Rect>::new();
+        let mut weights = Vector::<f64>::new();
+        self.hog.detect_multi_scale_weights(
+            &self.small,
+            &mut found,
+            &mut weights,
+            0.0,
+            Size::new(8, 8),
+            Size::new(0, 0),
+            1.05,
+            2.0,
+            false,
+        )?;
+        Ok(found
+            .iter()
+            .zip(weights.iter())
+            .map(|(r, score)| Detection {
+                rect: Rect::new(
+                    (r.x as f64 / scale) as i32,
+                    (r.y as f64 / scale) as i32,
+                    (r.width as f64 / scale) as i32,
+                    (r.height as f64 / scale) as i32,
+                ),
+                score,
+                class: 0,
+            })
+            .collect())
+    }
+
+    fn label(&self, _class: usize) -> String {
+        "person".to_string()
+    }
+}
+
+// OpenCV 的 Haar 级联分类器, 例如 haarcascade_frontalface_default.xml 人脸检测.
+pub struct CascadeDetector {
+    cascade: CascadeClassifier,
+    label: String,
+    small: Mat,
+    gray: Mat,
+}
+
+impl CascadeDetector {
+    pub fn new(path: &Path, label: &str) -> Result<Self> {
+        if !path.exists() {
+            bail!(
+                "cascade {} not found, copy it from OpenCV's data/haarcascades",
+                path.display()
+            );
+        }
+        let cascade = CascadeClassifier::new(&path.to_string_lossy())?;
+        if cascade.empty()? {
+            bail!("cascade {} could not be loaded", path.display());
+        }
+        Ok(Self {
+            cascade,
+            label: label.to_string(),
+            small: Mat::default(),
+            gray: Mat::default(),
+        })
+    }
+}
+
+impl Detector for CascadeDetector {
+    // 级联分类器没有置信度, 分数固定为 1.
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>> {
+        let scale = (DETECT_WIDTH as f64 / frame.cols() as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        imgproc::cvt_color(&self.small, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        imgproc::equalize_hist(&self.gray, &mut self.small)?;
+        let mut found = Vector::<Rect>::new();
+        self.cascade.detect_multi_scale(
+            &self.small,
+            &mut found,
+            1.1,
+            4,
+            0,
+            Size::new(24, 24),
+            Size::new(0, 0),
+        )?;
+        Ok(found
+            .iter()
+            .map(|r| Detection {
+                rect: Rect::new(
+                    (r.x as f64 / scale) as i32,
+                    (r.y as f64 / scale) as i32,
+                    (r.width as f64 / scale) as i32,
+                    (r.height as f64 / scale) as i32,
+                ),
+                score: 1.0,
+                class: 0,
+            })
+            .collect())
+    }
+
+    fn label(&self, _class: usize) -> String {
+        self.label.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 记录调用次数和画面尺寸.
+    #[derive(Default)]
+    struct Counting {
+        calls: u32,
+        size: (i32, i32),
+    }
+
+    impl Detector for Counting {
+        fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>> {
+            self.calls += 1;
+            self.size = (frame.cols(), frame.rows());
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            Ok(Vec::new())
+        }
+
+        fn label(&self, class: usize) -> String {
+            class.to_string()
+        }
+    }
+
+    #[test]
+    fn benchmark_skips_warmup() {
+        let mut detector = Counting::default();
+        let fps = benchmark(&mut detector, 320, 240).unwrap();
+        assert_eq!(detector.calls, 11);
+        assert_eq!(detector.size, (320, 240));
+        assert!(fps > 0.0 && fps <= 500.0);
+        assert_eq!(detector.device(), "cpu");
+    }
+}
@@ -0,0 +1,298 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{Mat, Point, Rect, Scalar},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    detect::Detector,
+    pipeline::FrameProcessor,
+    sink,
+    threads::{self, ThreadConfig},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DetectionConfig {
+    pub enabled: bool,
+    // 检测模型名称 (见 [models]), 不设置时使用 cascade 指定的 Haar 级联分类器.
+    pub model: Option<String>,
+    // 使用检测模型时只显示该类别 ID.
+    pub class: Option<usize>,
+    // OpenCV data/haarcascades 中的级联文件.
+    pub cascade: PathBuf,
+    // 级联分类器检测框的标签.
+    pub label: String,
+    // 每秒最多检测的帧数, 检测在后台线程运行, 不会拖慢采集.
+    pub fps: f64,
+    // 低于该分数的检测框不显示.
+    pub min_score: f64,
+    // 检测框同时画进录像和输出流, 否则只显示在预览中.
+    pub record: bool,
+    // 检测结果 (JSON Lines) 文件, 支持 strftime 格式, 不设置时不保存.
+    pub sidecar: Option<PathBuf>,
+}
+
+impl Default for DetectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            class: None,
+            cascade: PathBuf::from("haarcascade_frontalface_default.xml"),
+            label: "face".to_string(),
+            fps: 5.0,
+            min_score: 0.5,
+            record: false,
+            sidecar: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct Found {
+    label: String,
+    score: f64,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+}
+
+// sidecar 中的一行, frame 为检测开始后的帧序号.
+#[derive(Serialize)]
+struct Record<'a> {
+    frame: u64,
+    time: String,
+    detections: &'a [Found],
+}
+
+struct Job {
+    frame: u64,
+    time: DateTime<Local>,
+    image: Mat,
+}
+
+// 目标检测叠加: 按 fps 把帧交给后台线程检测, 每帧都画出最近一次的结果.
+// 后台线程还在检测上一帧时直接跳过, 检测器再慢也不会积压.
+pub struct ObjectDetection {
+    jobs: SyncSender<Job>,
+    latest: Arc<Mutex<Vec<Found>>>,
+    interval: Duration,
+    last: Option<Instant>,
+    record: bool,
+    frames: u64,
+}
+
+impl ObjectDetection {
+    pub fn new(
+        config: &DetectionConfig,
+        mut detector: Box<dyn Detector>,
+        workers: &ThreadConfig,
+    ) -> Result<Self> {
+        let mut sidecar = match &config.sidecar {
+            Some(path) => Some(BufWriter::new(File::create(sink::timestamped(path))?)),
+            None => None,
+        };
+        let latest = Arc::new(Mutex::new(Vec::new()));
+        let results = latest.clone();
+        let (class, min_score) = (config.class, config.min_score);
+        // 线程结束时 sidecar 被丢弃, BufWriter 写出剩余内容.
+        let jobs = threads::worker("detection", 1, workers, move |job: Job| {
+            let found: Vec<Found> = detector
+                .detect(&job.image)?
+                .iter()
+                .filter(|d| d.score >= min_score)
+                .filter(|d| class.map_or(true, |class| d.class == class))
+                .map(|d| Found {
+                    label: detector.label(d.class),
+                    score: d.score,
+                    x: d.rect.x,
+                    y: d.rect.y,
+                    width: d.rect.width,
+                    height: d.rect.height,
+                })
+                .collect();
+            if let Some(log) = &mut sidecar {
+                let record = Record {
+                    frame: job.frame,
+                    time: job.time.to_rfc3339(),
+                    detections: &found,
+                };
+                let written = serde_json::to_string(&record)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|line| Ok(writeln!(log, "{}", line)?));
+                if let Err(err) = written {
+                    eprintln!("detection sidecar: {:?}", err);
+                    sidecar = None;
+                }
+            }
+            *results.lock().unwrap() = found;
+            Ok(())
+        })?;
+        Ok(Self {
+            jobs,
+            latest,
+            interval: Duration::from_secs_f64(1.0 / config.fps.max(0.1)),
+            last: None,
+            record: config.record,
+            frames: 0,
+        })
+    }
+
+    fn draw(&self, frame: &mut Mat) -> Result<()> {
+        let color = Scalar::new(0., 200., 255., 0.);
+        for found in self.latest.lock().unwrap().iter() {
+            let rect = Rect::new(found.x, found.y, found.width, found.height);
+            imgproc::rectangle(frame, rect, color, 2, LINE_AA, 0)?;
+            imgproc::put_text(
+                frame,
+                &format!("{} {:.0}%", found.label, found.score * 100.0),
+                Point::new(rect.x, rect.y - 6),
+                FONT_HERSHEY_SIMPLEX,
+                0.5,
+                color,
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for ObjectDetection {
+    fn name(&self) -> &str {
+        "detection"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let due = self
+            .last
+            .map_or(true, |last| last.elapsed() >= self.interval);
+        if due {
+            let job = Job {
+                frame: self.frames,
+                time: Local::now(),
+                image: frame.try_clone()?,
+            };
+            match self.jobs.try_send(job) {
+                Ok(()) => self.last = Some(Instant::now()),
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => bail!("detection thread exited"),
+            }
+        }
+        self.frames += 1;
+        if self.record {
+            self.draw(frame)?;
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        !self.record
+    }
+
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        if !self.record {
+            self.draw(frame)?;
+        }
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.latest.lock().unwrap().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread::sleep};
+
+    use opencv::core::{self, CV_8UC3};
+
+    use super::*;
+    use crate::tracking::Detection;
+
+    struct Fixed(Vec<Detection>);
+
+    impl Detector for Fixed {
+        fn detect(&mut self, _frame: &Mat) -> Result<Vec<Detection>> {
+            Ok(self.0.clone())
+        }
+
+        fn label(&self, class: usize) -> String {
+            ["person", "car"][class].to_string()
+        }
+    }
+
+    // 分数低于 min_score 和类别不符的检测框被过滤, 结果写入 sidecar.
+    #[test]
+    fn filters_and_logs() {
+        let dir = std::env::temp_dir().join(format!("detection-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sidecar = dir.join("detections.jsonl");
+        let config = DetectionConfig {
+            enabled: true,
+            class: Some(1),
+            fps: 1000.0,
+            record: true,
+            sidecar: Some(sidecar.clone()),
+            ..Default::default()
+        };
+        let detection = |class, score| Detection {
+            rect: Rect::new(8, 8, 16, 16),
+            score,
+            class,
+        };
+        let detector = Fixed(vec![
+            detection(1, 0.9),
+            detection(1, 0.2),
+            detection(0, 0.9),
+        ]);
+        let mut stage =
+            ObjectDetection::new(&config, Box::new(detector), &ThreadConfig::default()).unwrap();
+        assert!(!stage.has_overlay());
+        let blank = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(0.)).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while stage.detections() != Some(1) {
+            assert!(Instant::now() < deadline, "timed out");
+            stage.process(&mut blank.clone()).unwrap();
+            sleep(Duration::from_millis(5));
+        }
+        // record 时检测框画进帧中.
+        let mut frame = blank.clone();
+        stage.process(&mut frame).unwrap();
+        let changed = core::norm2(&frame, &blank, core::NORM_INF, &core::no_array()).unwrap();
+        assert!(changed > 0.0);
+
+        // 后台线程结束时写出 sidecar.
+        drop(stage);
+        let mut lines = String::new();
+        while lines.is_empty() {
+            assert!(Instant::now() < deadline, "timed out");
+            sleep(Duration::from_millis(20));
+            lines = fs::read_to_string(&sidecar).unwrap();
+        }
+        let record: serde_json::Value =
+            serde_json::from_str(lines.lines().last().unwrap()).unwrap();
+        assert_eq!(record["detections"].as_array().unwrap().len(), 1);
+        assert_eq!(record["detections"][0]["label"], "car");
+        assert_eq!(record["detections"][0]["width"], 16);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
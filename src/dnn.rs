@@ -0,0 +1,363 @@
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use opencv::{
+    core::{self, Mat, Rect, Scalar, Size, Vector},
+    dnn, imgproc,
+    prelude::*,
+};
+
+use crate::{
+    classify::{self, Classifier},
+    detect::Detector,
+    models::{DnnBackend, DnnTarget, ModelConfig},
+    pose::{self, Keypoint, PoseEstimator},
+    segment::{self, Segmenter},
+    tracking::Detection,
+};
+
+// OpenCV DNN 加载的 SSD 类检测模型.
+pub struct DnnDetector {
+    net: dnn::Net,
+    config: ModelConfig,
+    // 只保留该类别, None 表示保留全部.
+    class: Option<usize>,
+    device: String,
+}
+
+impl DnnDetector {
+    pub fn new(path: &Path, config: &ModelConfig, class: Option<usize>) -> Result<Self> {
+        let (net, device) = load(path, config)?;
+        Ok(Self {
+            net,
+            config: config.clone(),
+            class,
+            device,
+        })
+    }
+}
+
+// OpenCV DNN 加载的分类模型, 输出为 [1, N] 的类别分数.
+pub struct DnnClassifier {
+    net: dnn::Net,
+    config: ModelConfig,
+    device: String,
+}
+
+impl DnnClassifier {
+    pub fn new(path: &Path, config: &ModelConfig) -> Result<Self> {
+        let (net, device) = load(path, config)?;
+        Ok(Self {
+            net,
+            config: config.clone(),
+            device,
+        })
+    }
+}
+
+impl Classifier for DnnClassifier {
+    fn classify(&mut self, image: &Mat) -> Result<(usize, f64)> {
+        let blob = blob(image, &self.config)?;
+        self.net.set_input(&blob, "", 1.0, Scalar::default())?;
+        let output = self.net.forward_single("")?;
+        Ok(classify::best(output.data_typed::<f32>()?))
+    }
+
+    fn label(&self, class: usize) -> String {
+        self.config.label(class)
+    }
+
+    fn device(&self) -> String {
+        self.device.clone()
+    }
+}
+
+// OpenCV DNN 加载的分割模型, 前景为 class 通道.
+pub struct DnnSegmenter {
+    net: dnn::Net,
+    config: ModelConfig,
+    class: usize,
+    device: String,
+}
+
+impl DnnSegmenter {
+    pub fn new(path: &Path, config: &ModelConfig, class: usize) -> Result<Self> {
+        let (net, device) = load(path, config)?;
+        Ok(Self {
+            net,
+            config: config.clone(),
+            class,
+            device,
+        })
+    }
+}
+
+impl Segmenter for DnnSegmenter {
+    fn segment(&mut self, frame: &Mat) -> Result<Mat> {
+        let blob = blob(frame, &self.config)?;
+        self.net.set_input(&blob, "", 1.0, Scalar::default())?;
+        let output = self.net.forward_single("")?;
+        segment::foreground(
+            output.data_typed::<f32>()?,
+            self.config.input_size,
+            self.class,
+        )
+    }
+
+    fn device(&self) -> String {
+        self.device.clone()
+    }
+}
+
+// OpenCV DNN 加载的姿态估计模型, 有 labels 时只使用前面对应数量的热图通道.
+pub struct DnnPose {
+    net: dnn::Net,
+    config: ModelConfig,
+    device: String,
+}
+
+impl DnnPose {
+    pub fn new(path: &Path, config: &ModelConfig) -> Result<Self> {
+        let (net, device) = load(path, config)?;
+        Ok(Self {
+            net,
+            config: config.clone(),
+            device,
+        })
+    }
+}
+
+impl PoseEstimator for DnnPose {
+    fn estimate(&mut self, frame: &Mat) -> Result<Vec<Keypoint>> {
+        let blob = blob(frame, &self.config)?;
+        self.net.set_input(&blob, "", 1.0, Scalar::default())?;
+        let output = self.net.forward_single("")?;
+        let shape: Vec<usize> = output.mat_size().iter().map(|&d| d as usize).collect();
+        let count = (!self.config.labels.is_empty()).then_some(self.config.labels.len());
+        pose::keypoints(
+            output.data_typed::<f32>()?,
+            &shape,
+            count,
+            frame.cols(),
+            frame.rows(),
+        )
+    }
+
+    fn device(&self) -> String {
+        self.device.clone()
+    }
+}
+
+// 加载模型并按配置选择后端和设备, 返回实际使用的设备名称.
+fn load(path: &Path, config: &ModelConfig) -> Result<(dnn::Net, String)> {
+    let mut net = dnn::read_net(&path.to_string_lossy(), "", "")
+        .with_context(|| format!("load model {}", path.display()))?;
+    let (backend, target) = (config.dnn_backend, config.dnn_target);
+    // 当前 OpenCV 构建不支持所选的后端/设备时退回 CPU.
+    let available = dnn::get_available_targets(backend_enum(backend))?;
+    let device = if available.iter().any(|t| t as i32 == target_id(target)) {
+        net.set_preferable_backend(backend_id(backend))?;
+        net.set_preferable_target(target_id(target))?;
+        format!("{:?}/{:?}", backend, target).to_lowercase()
+    } else {
+        eprintln!(
+            "DNN backend {:?} with target {:?} is not available, using CPU",
+            backend, target
+        );
+        "cpu".to_string()
+    };
+    Ok((net, device))
+}
+
+fn backend_enum(backend: DnnBackend) -> dnn::Backend {
+    match backend {
+        DnnBackend::Default => dnn::Backend::DNN_BACKEND_DEFAULT,
+        DnnBackend::Opencv => dnn::Backend::DNN_BACKEND_OPENCV,
+        DnnBackend::Cuda => dnn::Backend::DNN_BACKEND_CUDA,
+        DnnBackend::Openvino => dnn::Backend::DNN_BACKEND_INFERENCE_ENGINE,
+    }
+}
+
+fn backend_id(backend: DnnBackend) -> i32 {
+    backend_enum(backend) as i32
+}
+
+fn target_id(target: DnnTarget) -> i32 {
+    let target = match target {
+        DnnTarget::Cpu => dnn::Target::DNN_TARGET_CPU,
+        DnnTarget::Opencl => dnn::Target::DNN_TARGET_OPENCL,
+        DnnTarget::OpenclFp16 => dnn::Target::DNN_TARGET_OPENCL_FP16,
+        DnnTarget::Cuda => dnn::Target::DNN_TARGET_CUDA,
+        DnnTarget::CudaFp16 => dnn::Target::DNN_TARGET_CUDA_FP16,
+        DnnTarget::Myriad => dnn::Target::DNN_TARGET_MYRIAD,
+    };
+    target as i32
+}
+
+impl Detector for DnnDetector {
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>> {
+        let blob = blob(frame, &self.config)?;
+        self.net.set_input(&blob, "", 1.0, Scalar::default())?;
+        let output = self.net.forward_single("")?;
+        let data = output.data_typed::<f32>()?;
+        Ok(parse_ssd(data, frame, self.class))
+    }
+
+    fn label(&self, class: usize) -> String {
+        self.config.label(class)
+    }
+
+    fn device(&self) -> String {
+        self.device.clone()
+    }
+}
+
+// 超分辨率模型 (例如 Real-ESRGAN 的 ONNX 导出), 输入整幅图像, 输出放大后的图像.
+// 只在拍照后处理时使用, 每次加载一次模型.
+pub fn super_resolve(path: &Path, config: &ModelConfig, image: &Mat) -> Result<Mat> {
+    let (mut net, _) = load(path, config)?;
+    let [b, g, r] = config.mean;
+    let input = dnn::blob_from_image(
+        image,
+        config.scale,
+        image.size()?,
+        Scalar::new(b, g, r, 0.),
+        config.swap_rb,
+        false,
+        core::CV_32F,
+    )?;
+    net.set_input(&input, "", 1.0, Scalar::default())?;
+    let output = net.forward_single("")?;
+    let mut images = Vector::<Mat>::new();
+    dnn::images_from_blob(&output, &mut images)?;
+    // 还原预处理: 除以 scale, 加回均值, 换回 BGR.
+    let mut scaled = Mat::default();
+    images
+        .get(0)?
+        .convert_to(&mut scaled, core::CV_32F, 1.0 / config.scale, 0.0)?;
+    let mut restored = Mat::default();
+    core::add(
+        &scaled,
+        &Scalar::new(b, g, r, 0.),
+        &mut restored,
+        &core::no_array(),
+        -1,
+    )?;
+    let mut result = Mat::default();
+    restored.convert_to(&mut result, core::CV_8U, 1.0, 0.0)?;
+    if !config.swap_rb {
+        return Ok(result);
+    }
+    let mut bgr = Mat::default();
+    imgproc::cvt_color(&result, &mut bgr, imgproc::COLOR_RGB2BGR, 0)?;
+    Ok(bgr)
+}
+
+// 按模型配置把画面转换成 NCHW 的 float 输入.
+pub fn blob(frame: &Mat, config: &ModelConfig) -> Result<Mat> {
+    let [width, height] = config.input_size;
+    let [b, g, r] = config.mean;
+    Ok(dnn::blob_from_image(
+        frame,
+        config.scale,
+        Size::new(width, height),
+        Scalar::new(b, g, r, 0.),
+        config.swap_rb,
+        false,
+        core::CV_32F,
+    )?)
+}
+
+// SSD 输出为 [1, 1, N, 7]: (image_id, class, score, x1, y1, x2, y2), 坐标为相对值.
+pub fn parse_ssd(data: &[f32], frame: &Mat, only: Option<usize>) -> Vec<Detection> {
+    let (cols, rows) = (frame.cols() as f32, frame.rows() as f32);
+    data.chunks_exact(7)
+        .filter(|row| only.map_or(true, |c| c == row[1] as usize))
+        .map(|row| {
+            let (x1, y1) = (row[3] * cols, row[4] * rows);
+            let (x2, y2) = (row[5] * cols, row[6] * rows);
+            Detection {
+                rect: Rect::new(x1 as i32, y1 as i32, (x2 - x1) as i32, (y2 - y1) as i32),
+                score: row[2] as f64,
+                class: row[1] as usize,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> ModelConfig {
+        toml::from_str("url = \"https://example.com/ssd.onnx\"\ntask = \"detection\"").unwrap()
+    }
+
+    #[test]
+    fn ssd_output() {
+        let frame =
+            Mat::new_rows_cols_with_default(100, 200, core::CV_8UC3, Scalar::all(0.)).unwrap();
+        #[rustfmt::skip]
+        let data = [
+            0., 1., 0.9, 0.1, 0.2, 0.3, 0.6,
+            0., 3., 0.4, 0.5, 0.5, 1.0, 1.0,
+            // 不完整的行被忽略.
+            0., 1., 0.9,
+        ];
+        let all = parse_ssd(&data, &frame, None);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].rect, Rect::new(20, 20, 40, 40));
+        assert_eq!((all[0].class, all[0].score), (1, 0.9f32 as f64));
+        assert_eq!(all[1].rect, Rect::new(100, 50, 100, 50));
+        let people = parse_ssd(&data, &frame, Some(3));
+        assert_eq!(people.len(), 1);
+        assert_eq!(people[0].class, 3);
+    }
+
+    #[test]
+    fn blob_layout() {
+        let config = ModelConfig {
+            input_size: [4, 2],
+            scale: 0.5,
+            mean: [10., 10., 10.],
+            ..config()
+        };
+        let frame = Mat::new_rows_cols_with_default(
+            8,
+            16,
+            core::CV_8UC3,
+            Scalar::new(110., 120., 130., 0.),
+        )
+        .unwrap();
+        let blob = blob(&frame, &config).unwrap();
+        assert_eq!(*blob.mat_size(), [1, 3, 2, 4]);
+        // swap_rb 后通道顺序为 RGB, 每个值为 (像素 - 10) * 0.5.
+        let data = blob.data_typed::<f32>().unwrap();
+        assert_eq!((data[0], data[8], data[16]), (60., 55., 50.));
+    }
+
+    #[test]
+    fn backend_and_target() {
+        let config: ModelConfig = toml::from_str(
+            "url = \"a\"\ntask = \"detection\"\n\
+             dnn_backend = \"cuda\"\ndnn_target = \"cuda-fp16\"",
+        )
+        .unwrap();
+        assert_eq!(
+            backend_id(config.dnn_backend),
+            dnn::Backend::DNN_BACKEND_CUDA as i32
+        );
+        assert_eq!(
+            target_id(config.dnn_target),
+            dnn::Target::DNN_TARGET_CUDA_FP16 as i32
+        );
+        assert_eq!(
+            (config().dnn_backend, config().dnn_target),
+            (DnnBackend::Default, DnnTarget::Cpu)
+        );
+        assert_eq!(
+            backend_id(DnnBackend::Openvino),
+            dnn::Backend::DNN_BACKEND_INFERENCE_ENGINE as i32
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{self, Size},
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+
+use crate::{
+    camera::{self, Device},
+    config::{Config, CONFIG_FILE},
+    sink::{self, SinkInfo, CODECS},
+};
+
+// 低于该写入速度 (MB/s) 时提示不适合录制高清视频.
+pub const MIN_WRITE_SPEED: f64 = 10.0;
+
+// 打印诊断报告, 用户可以附在问题报告中.
+pub fn run(config: &Config) -> Result<()> {
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "slint-opencv {} ({} {})",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    );
+    let features: Vec<&str> = [
+        ("dnn", cfg!(feature = "dnn")),
+        ("streaming", cfg!(feature = "streaming")),
+        ("onnxruntime", cfg!(feature = "onnxruntime")),
+        ("cuda", cfg!(feature = "cuda")),
+        ("directml", cfg!(feature = "directml")),
+        ("tls", cfg!(feature = "tls")),
+        ("mdns", cfg!(feature = "mdns")),
+        ("encryption", cfg!(feature = "encryption")),
+        ("record", cfg!(feature = "record")),
+        ("audio", cfg!(feature = "audio")),
+    ]
+    .into_iter()
+    .filter(|(_, enabled)| *enabled)
+    .map(|(name, _)| name)
+    .collect();
+    let _ = writeln!(report, "features: {}", features.join(", "));
+    let config_file = if Path::new(CONFIG_FILE).exists() {
+        CONFIG_FILE
+    } else {
+        "none (defaults)"
+    };
+    let _ = writeln!(report, "config: {}", config_file);
+
+    section(&mut report, "OpenCV");
+    opencv_info(&mut report)
+        .unwrap_or_else(|err| line(&mut report, "build information", &Err(err)));
+
+    section(&mut report, "Cameras");
+    let mut devices = camera::devices(&config.camera);
+    if devices.is_empty() {
+        let _ = writeln!(
+            report,
+            "  enumeration not supported, trying the configured camera"
+        );
+        match camera::select(&config.camera) {
+            Ok(device) => devices.push(device),
+            Err(err) => line(&mut report, "configured camera", &Err(err)),
+        }
+    }
+    for device in &devices {
+        line(&mut report, &device.to_string(), &test_camera(device, None));
+    }
+
+    section(&mut report, "Output");
+    for dir in output_dirs(config) {
+        let name = dir.display().to_string();
+        let speed = write_speed(&dir).map(|speed| {
+            let mut text = format!("writable, {:.0} MB/s", speed);
+            if speed < MIN_WRITE_SPEED {
+                text += " (too slow for HD recordings)";
+            }
+            text
+        });
+        line(&mut report, &name, &speed);
+        if speed.is_ok() {
+            let codecs = available_codecs(&dir);
+            let text = if codecs.is_empty() {
+                "none".to_string()
+            } else {
+                codecs.join(", ")
+            };
+            let _ = writeln!(report, "  {} codecs: {}", name, text);
+        }
+    }
+    print!("{}", report);
+    Ok(())
+}
+
+fn section(report: &mut String, title: &str) {
+    let _ = writeln!(report, "\n[{}]", title);
+}
+
+fn line(report: &mut String, name: &str, result: &Result<String>) {
+    let _ = match result {
+        Ok(text) => writeln!(report, "  {}: {}", name, text),
+        Err(err) => writeln!(report, "  {}: FAILED {:#}", name, err),
+    };
+}
+
+fn opencv_info(report: &mut String) -> Result<()> {
+    let _ = writeln!(report, "  version: {}", core::CV_VERSION);
+    // 只保留和视频读写及加速相关的构建选项.
+    let build = core::get_build_information()?;
+    for key in [
+        "GStreamer",
+        "FFMPEG",
+        "V4L/V4L2",
+        "AVFoundation",
+        "MSMF",
+        "DirectShow",
+    ] {
+        if let Some(value) = build_flag(&build, key) {
+            let _ = writeln!(report, "  {}: {}", key, value);
+        }
+    }
+    let _ = writeln!(report, "  OpenCL: {}", core::have_opencl()?);
+    let backends = videoio::get_backends()?
+        .iter()
+        .filter_map(|api| videoio::get_backend_name(api).ok())
+        .collect::<Vec<_>>();
+    let _ = writeln!(report, "  videoio backends: {}", backends.join(", "));
+    Ok(())
+}
+
+// 构建信息中 "    FFMPEG:    YES" 形式的行.
+fn build_flag<'a>(build: &'a str, key: &str) -> Option<&'a str> {
+    build.lines().find_map(|line| {
+        let (name, value) = line.split_once(':')?;
+        (name.trim() == key).then(|| value.trim())
+    })
+}
+
+// 打开摄像头并读取一帧, 返回实际的分辨率和帧率.
+pub fn test_camera(device: &Device, resolution: Option<(i32, i32)>) -> Result<String> {
+    let mut camera = VideoCapture::new(device.index, device.backend.api())?;
+    if !camera.is_opened()? {
+        bail!("could not be opened");
+    }
+    if let Some((width, height)) = resolution {
+        camera::set_resolution(&mut camera, width, height)?;
+    }
+    if !camera::first_frame(&mut camera) {
+        bail!("opened but delivers no frames, it may be in use by another application");
+    }
+    Ok(format!(
+        "{}x{} at {} FPS ({:?})",
+        camera.get(videoio::CAP_PROP_FRAME_WIDTH)?,
+        camera.get(videoio::CAP_PROP_FRAME_HEIGHT)?,
+        camera.get(videoio::CAP_PROP_FPS)?,
+        device.backend
+    ))
+}
+
+// file sink 的输出目录, 没有时为当前目录.
+fn output_dirs(config: &Config) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for sink in config.sinks.iter().filter(|sink| sink.kind == "file") {
+        let Some(path) = sink.options.get("path").and_then(|path| path.as_str()) else {
+            continue;
+        };
+        let dir = match Path::new(path).parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    if dirs.is_empty() {
+        dirs.push(PathBuf::from("."));
+    }
+    dirs
+}
+
+// 写入 32 MB 测试文件测量写入速度 (MB/s), 包括 sync 的时间.
+pub fn write_speed(dir: &Path) -> Result<f64> {
+    fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+    let probe = dir.join("write-test.tmp");
+    let block = vec![0u8; 1 << 20];
+    let start = Instant::now();
+    let mut file = File::create(&probe).with_context(|| format!("write to {}", dir.display()))?;
+    for _ in 0..32 {
+        file.write_all(&block)?;
+    }
+    file.sync_all()?;
+    drop(file);
+    let speed = 32.0 / start.elapsed().as_secs_f64();
+    fs::remove_file(&probe)?;
+    Ok(speed)
+}
+
+// 实际写入几帧检查编码是否可用.
+pub fn available_codecs(dir: &Path) -> Vec<&'static str> {
+    let info = SinkInfo {
+        fps: 30.0,
+        size: Size::new(640, 480),
+    };
+    CODECS
+        .iter()
+        .filter(|(code, extension)| {
+            let path = dir.join(format!("codec-test.{}", extension));
+            sink::fourcc(code).is_ok_and(|fourcc| sink::probe(&path, fourcc, &info))
+        })
+        .map(|(code, _)| *code)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sink::SinkConfig;
+
+    #[test]
+    fn build_flags() {
+        let build = "  Video I/O:\n    FFMPEG:                      YES\n      avcodec:   YES (58.134.100)\n    GStreamer:                   NO\n";
+        assert_eq!(build_flag(build, "FFMPEG"), Some("YES"));
+        assert_eq!(build_flag(build, "GStreamer"), Some("NO"));
+        assert_eq!(build_flag(build, "MSMF"), None);
+    }
+
+    #[test]
+    fn report_lines() {
+        let mut report = String::new();
+        section(&mut report, "Camera");
+        line(&mut report, "camera 0", &Ok("640x480".to_string()));
+        line(&mut report, "camera 1", &Err(anyhow::anyhow!("busy")));
+        assert_eq!(
+            report,
+            "\n[Camera]\n  camera 0: 640x480\n  camera 1: FAILED busy\n"
+        );
+    }
+
+    // 每个目录只检查一次, 没有文件输出时检查当前目录.
+    #[test]
+    fn output_dirs_from_file_sinks() {
+        assert_eq!(output_dirs(&Config::default()), [PathBuf::from(".")]);
+        let sink = |kind: &str, path: &str| -> SinkConfig {
+            toml::from_str(&format!("kind = {:?}\npath = {:?}", kind, path)).unwrap()
+        };
+        let config = Config {
+            sinks: vec![
+                sink("file", "clips/a.mp4"),
+                sink("file", "clips/b.mp4"),
+                sink("file", "c.mp4"),
+                sink("snapshot", "stills/d.png"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(
+            output_dirs(&config),
+            [PathBuf::from("clips"), PathBuf::from(".")]
+        );
+    }
+
+    #[test]
+    fn write_speed_removes_probe() {
+        let dir = std::env::temp_dir().join(format!("doctor-test-{}", std::process::id()));
+        assert!(write_speed(&dir).unwrap() > 0.0);
+        assert!(!dir.join("write-test.tmp").exists());
+        // MJPG 由 OpenCV 内置实现, 总是可用.
+        assert!(available_codecs(&dir).contains(&"MJPG"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
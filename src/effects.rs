@@ -0,0 +1,396 @@
+use std::mem;
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Size, Vector},
+    imgproc::{self, COLOR_BGR2YCrCb, COLOR_YCrCb2BGR, COLOR_BGR2GRAY, COLOR_GRAY2BGR},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::pipeline::FrameProcessor;
+
+// 画面效果, 同时作用于预览和录制. 配置中为 [[effects]], kind 选择效果.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum Effect {
+    Grayscale,
+    // 高斯模糊, 核大小取奇数.
+    Blur {
+        #[serde(default = "default_kernel")]
+        kernel: i32,
+    },
+    // Canny 边缘, 两个滞后阈值.
+    Edges {
+        #[serde(default = "default_low")]
+        low: f64,
+        #[serde(default = "default_high")]
+        high: f64,
+    },
+    // 亮度直方图均衡化.
+    Equalize,
+    // 左右镜像.
+    Mirror,
+    // 上下翻转.
+    Flip,
+    // 按亮度着色, 夜晚的红外画面更容易看清.
+    Colormap {
+        #[serde(default)]
+        map: ColorMap,
+    },
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMap {
+    #[default]
+    Inferno,
+    Jet,
+    Turbo,
+    Viridis,
+    Bone,
+    Hot,
+}
+
+impl ColorMap {
+    pub fn code(self) -> i32 {
+        match self {
+            ColorMap::Inferno => imgproc::COLORMAP_INFERNO,
+            ColorMap::Jet => imgproc::COLORMAP_JET,
+            ColorMap::Turbo => imgproc::COLORMAP_TURBO,
+            ColorMap::Viridis => imgproc::COLORMAP_VIRIDIS,
+            ColorMap::Bone => imgproc::COLORMAP_BONE,
+            ColorMap::Hot => imgproc::COLORMAP_HOT,
+        }
+    }
+}
+
+fn default_kernel() -> i32 {
+    9
+}
+
+fn default_low() -> f64 {
+    50.0
+}
+
+fn default_high() -> f64 {
+    150.0
+}
+
+impl Effect {
+    // 全部效果的默认参数, 也是控制面板中的初始顺序.
+    pub fn all() -> [Effect; 7] {
+        [
+            Effect::Grayscale,
+            Effect::Blur {
+                kernel: default_kernel(),
+            },
+            Effect::Edges {
+                low: default_low(),
+                high: default_high(),
+            },
+            Effect::Equalize,
+            Effect::Mirror,
+            Effect::Flip,
+            Effect::Colormap {
+                map: ColorMap::default(),
+            },
+        ]
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Effect::Grayscale => "Grayscale",
+            Effect::Blur { .. } => "Blur",
+            Effect::Edges { .. } => "Edges",
+            Effect::Equalize => "Equalize",
+            Effect::Mirror => "Mirror",
+            Effect::Flip => "Flip",
+            Effect::Colormap { .. } => "Colormap",
+        }
+    }
+
+    fn same_kind(&self, other: &Effect) -> bool {
+        mem::discriminant(self) == mem::discriminant(other)
+    }
+
+    fn apply(&self, frame: &mut Mat, gray: &mut Mat, scratch: &mut Mat) -> Result<()> {
+        match *self {
+            Effect::Grayscale => {
+                imgproc::cvt_color(frame, gray, COLOR_BGR2GRAY, 0)?;
+                imgproc::cvt_color(gray, frame, COLOR_GRAY2BGR, 0)?;
+            }
+            Effect::Blur { kernel } => {
+                let kernel = kernel.max(1) | 1;
+                imgproc::gaussian_blur(
+                    frame,
+                    scratch,
+                    Size::new(kernel, kernel),
+                    0.0,
+                    0.0,
+                    core::BORDER_DEFAULT,
+                )?;
+                scratch.copy_to(frame)?;
+            }
+            Effect::Edges { low, high } => {
+                imgproc::cvt_color(frame, gray, COLOR_BGR2GRAY, 0)?;
+                imgproc::canny(gray, scratch, low, high, 3, false)?;
+                imgproc::cvt_color(scratch, frame, COLOR_GRAY2BGR, 0)?;
+            }
+            // 只均衡亮度通道, 避免颜色偏移.
+            Effect::Equalize => {
+                imgproc::cvt_color(frame, scratch, COLOR_BGR2YCrCb, 0)?;
+                let mut channels = Vector::<Mat>::new();
+                core::split(scratch, &mut channels)?;
+                imgproc::equalize_hist(&channels.get(0)?, gray)?;
+                channels.set(0, gray.try_clone()?)?;
+                core::merge(&channels, scratch)?;
+                imgproc::cvt_color(scratch, frame, COLOR_YCrCb2BGR, 0)?;
+            }
+            Effect::Mirror => {
+                core::flip(frame, scratch, 1)?;
+                scratch.copy_to(frame)?;
+            }
+            Effect::Flip => {
+                core::flip(frame, scratch, 0)?;
+                scratch.copy_to(frame)?;
+            }
+            Effect::Colormap { map } => {
+                imgproc::cvt_color(frame, gray, COLOR_BGR2GRAY, 0)?;
+                imgproc::apply_color_map(gray, frame, map.code())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 按顺序执行的效果, 排在分析之后, 变化检测之前.
+pub struct Effects {
+    effects: Vec<Effect>,
+    gray: Mat,
+    scratch: Mat,
+}
+
+impl Effects {
+    pub fn new(effects: &[Effect]) -> Self {
+        Self {
+            effects: effects.to_vec(),
+            gray: Mat::default(),
+            scratch: Mat::default(),
+        }
+    }
+}
+
+impl FrameProcessor for Effects {
+    fn name(&self) -> &str {
+        "effects"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        for effect in &self.effects {
+            effect.apply(frame, &mut self.gray, &mut self.scratch)?;
+        }
+        Ok(())
+    }
+
+    fn order(&self) -> i32 {
+        50
+    }
+
+    fn filters(&self) -> Vec<String> {
+        self.effects
+            .iter()
+            .map(|effect| effect.label().to_lowercase())
+            .collect()
+    }
+}
+
+// 控制面板中的效果列表: 顺序, 是否启用和参数.
+pub struct EffectChain {
+    items: Vec<(Effect, bool)>,
+}
+
+impl EffectChain {
+    // 配置中的效果按顺序启用, 其余效果以默认参数排在后面.
+    pub fn new(enabled: &[Effect]) -> Self {
+        let mut items: Vec<(Effect, bool)> = Vec::new();
+        for effect in enabled {
+            if !items.iter().any(|(item, _)| item.same_kind(effect)) {
+                items.push((*effect, true));
+            }
+        }
+        for effect in Effect::all() {
+            if !items.iter().any(|(item, _)| item.same_kind(&effect)) {
+                items.push((effect, false));
+            }
+        }
+        Self { items }
+    }
+
+    pub fn labels(&self) -> Vec<String> {
+        self.items
+            .iter()
+            .map(|(effect, _)| effect.label().to_string())
+            .collect()
+    }
+
+    pub fn enabled(&self) -> Vec<bool> {
+        self.items.iter().map(|(_, enabled)| *enabled).collect()
+    }
+
+    pub fn toggle(&mut self, index: usize, enabled: bool) {
+        if let Some(item) = self.items.get_mut(index) {
+            item.1 = enabled;
+        }
+    }
+
+    // 上移 (-1) 或下移 (1) 一位.
+    pub fn move_by(&mut self, index: usize, delta: i32) {
+        let target = index as i64 + i64::from(delta);
+        if index < self.items.len() && (0..self.items.len() as i64).contains(&target) {
+            self.items.swap(index, target as usize);
+        }
+    }
+
+    pub fn set_blur(&mut self, kernel: i32) {
+        self.set(Effect::Blur { kernel });
+    }
+
+    pub fn set_edges(&mut self, low: f64, high: f64) {
+        self.set(Effect::Edges { low, high });
+    }
+
+    // 模糊核大小和边缘阈值, 用于控制面板的初始值.
+    pub fn params(&self) -> (i32, f64, f64) {
+        let (mut kernel, mut low, mut high) = (default_kernel(), default_low(), default_high());
+        for (effect, _) in &self.items {
+            match *effect {
+                Effect::Blur { kernel: k } => kernel = k,
+                Effect::Edges { low: l, high: h } => (low, high) = (l, h),
+                _ => {}
+            }
+        }
+        (kernel, low, high)
+    }
+
+    fn set(&mut self, effect: Effect) {
+        if let Some(item) = self
+            .items
+            .iter_mut()
+            .find(|(item, _)| item.same_kind(&effect))
+        {
+            item.0 = effect;
+        }
+    }
+
+    // 启用的效果, 按列表顺序.
+    pub fn effects(&self) -> Vec<Effect> {
+        self.items
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(effect, _)| *effect)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    #[test]
+    fn parse_config() {
+        #[derive(Deserialize)]
+        struct Config {
+            effects: Vec<Effect>,
+        }
+        let config: Config = toml::from_str(
+            r#"
+            [[effects]]
+            kind = "blur"
+
+            [[effects]]
+            kind = "edges"
+            low = 10.0
+
+            [[effects]]
+            kind = "colormap"
+            map = "jet"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.effects,
+            [
+                Effect::Blur { kernel: 9 },
+                Effect::Edges {
+                    low: 10.0,
+                    high: 150.0
+                },
+                Effect::Colormap { map: ColorMap::Jet },
+            ]
+        );
+    }
+
+    // 左半边黑, 右半边白. 先镜像再转灰度, 效果按顺序执行.
+    #[test]
+    fn applies_in_order() {
+        let mut frame = Mat::new_rows_cols_with_default(4, 4, CV_8UC3, Scalar::all(0.)).unwrap();
+        for row in 0..4 {
+            for col in 2..4 {
+                *frame.at_2d_mut::<Vec3b>(row, col).unwrap() = Vec3b::from([0, 0, 255]);
+            }
+        }
+        let mut effects = Effects::new(&[Effect::Mirror, Effect::Grayscale]);
+        assert_eq!(effects.filters(), ["mirror", "grayscale"]);
+        effects.process(&mut frame).unwrap();
+        assert_eq!(*frame.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::all(76));
+        assert_eq!(*frame.at_2d::<Vec3b>(0, 3).unwrap(), Vec3b::all(0));
+
+        Effects::new(&[Effect::Flip]).process(&mut frame).unwrap();
+        assert_eq!(*frame.at_2d::<Vec3b>(3, 0).unwrap(), Vec3b::all(76));
+    }
+
+    // 配置中的效果按顺序启用, 其余以默认参数排在后面.
+    #[test]
+    fn chain() {
+        let mut chain = EffectChain::new(&[Effect::Flip, Effect::Blur { kernel: 5 }, Effect::Flip]);
+        assert_eq!(
+            chain.labels(),
+            [
+                "Flip",
+                "Blur",
+                "Grayscale",
+                "Edges",
+                "Equalize",
+                "Mirror",
+                "Colormap"
+            ]
+        );
+        assert_eq!(
+            chain.enabled(),
+            [true, true, false, false, false, false, false]
+        );
+        assert_eq!(chain.params(), (5, 50.0, 150.0));
+
+        chain.move_by(1, -1);
+        chain.move_by(0, -1);
+        chain.move_by(6, 1);
+        chain.toggle(3, true);
+        chain.set_edges(20.0, 80.0);
+        chain.set_blur(3);
+        assert_eq!(
+            chain.effects(),
+            [
+                Effect::Blur { kernel: 3 },
+                Effect::Flip,
+                Effect::Edges {
+                    low: 20.0,
+                    high: 80.0
+                },
+            ]
+        );
+        assert_eq!(chain.params(), (3, 20.0, 80.0));
+    }
+}
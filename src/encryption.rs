@@ -0,0 +1,364 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+// 加密文件格式: MAGIC, 7 字节随机 nonce 前缀, 之后是按 CHUNK 分块的 AES-256-GCM 密文,
+// 每块多 16 字节认证标签. 每块的 nonce 为前缀 + 4 字节块序号 (大端) + 1 字节最后一块标记,
+// 分块被截断, 重排或替换时解密失败.
+const MAGIC: &[u8; 8] = b"SOCENC1\n";
+const PREFIX: usize = 7;
+const CHUNK: usize = 64 * 1024;
+const TAG: usize = 16;
+
+// 加密后的文件名为原文件名加上该扩展名.
+pub const EXTENSION: &str = "enc";
+pub const KEY_FILE: &str = "recording.key";
+
+const NOT_BUILT: &str = "encryption requires the encryption build feature";
+
+// AES-256 密钥, 密钥文件中保存为 64 个十六进制字符.
+#[cfg_attr(not(feature = "encryption"), allow(dead_code))]
+pub struct Key([u8; 32]);
+
+impl Key {
+    pub fn load(path: &Path) -> Result<Self> {
+        if !cfg!(feature = "encryption") {
+            bail!(NOT_BUILT);
+        }
+        let text =
+            fs::read_to_string(path).with_context(|| format!("read key {}", path.display()))?;
+        let text = text.trim();
+        if text.len() != 64 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+            bail!("key {} must be 64 hex characters", path.display());
+        }
+        let mut key = [0u8; 32];
+        for (i, byte) in key.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&text[i * 2..i * 2 + 2], 16)?;
+        }
+        Ok(Self(key))
+    }
+
+    // 生成新的随机密钥, 不覆盖已有的文件. 密钥丢失后加密的录像无法恢复.
+    pub fn generate(path: &Path) -> Result<()> {
+        let mut key = [0u8; 32];
+        random(&mut key)?;
+        let text: String = key.iter().map(|b| format!("{:02x}", b)).collect();
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("create key {}", path.display()))?;
+        writeln!(file, "{}", text)?;
+        Ok(())
+    }
+}
+
+// 加密为 <input>.enc, 完成后删除明文. 先写临时文件, 中断时不会留下不完整的加密文件.
+pub fn encrypt_file(input: &Path, key: &Key) -> Result<PathBuf> {
+    let mut name = input.as_os_str().to_owned();
+    name.push(format!(".{}", EXTENSION));
+    let output = PathBuf::from(name);
+    let partial = output.with_extension(format!("{}.part", EXTENSION));
+    let reader = File::open(input).with_context(|| format!("open {}", input.display()))?;
+    let mut writer = BufWriter::new(File::create(&partial)?);
+    encrypt(&mut BufReader::new(reader), &mut writer, key)?;
+    writer.into_inner()?.sync_all()?;
+    fs::rename(&partial, &output)?;
+    fs::remove_file(input)?;
+    Ok(output)
+}
+
+// 解密到 output, 不覆盖已有文件. 密钥错误或文件损坏时删除不完整的输出.
+pub fn decrypt_file(input: &Path, output: &Path, key: &Key) -> Result<()> {
+    let reader = File::open(input).with_context(|| format!("open {}", input.display()))?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)
+        .with_context(|| format!("create {}", output.display()))?;
+    let mut writer = BufWriter::new(file);
+    let result =
+        decrypt(&mut BufReader::new(reader), &mut writer, key).and_then(|()| Ok(writer.flush()?));
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result
+}
+
+// <name>.enc 解密为 <name>, 其他文件名加上 .decrypted.
+pub fn decrypted_path(input: &Path) -> PathBuf {
+    if input
+        .extension()
+        .is_some_and(|extension| extension == EXTENSION)
+    {
+        input.with_extension("")
+    } else {
+        let mut name = input.as_os_str().to_owned();
+        name.push(".decrypted");
+        PathBuf::from(name)
+    }
+}
+
+fn encrypt(reader: &mut impl Read, writer: &mut impl Write, key: &Key) -> Result<()> {
+    let mut prefix = [0u8; PREFIX];
+    random(&mut prefix)?;
+    writer.write_all(MAGIC)?;
+    writer.write_all(&prefix)?;
+    let mut current = vec![0u8; CHUNK];
+    let mut next = vec![0u8; CHUNK];
+    let mut length = read_full(reader, &mut current)?;
+    let mut counter = 0u32;
+    loop {
+        // 读到下一块才知道当前块是不是最后一块.
+        let following = if length == CHUNK {
+            read_full(reader, &mut next)?
+        } else {
+            0
+        };
+        let last = following == 0;
+        let sealed = seal(key, &nonce(&prefix, counter, last), &current[..length])?;
+        writer.write_all(&sealed)?;
+        if last {
+            return Ok(());
+        }
+        std::mem::swap(&mut current, &mut next);
+        length = following;
+        counter = counter.checked_add(1).context("file too large")?;
+    }
+}
+
+fn decrypt(reader: &mut impl Read, writer: &mut impl Write, key: &Key) -> Result<()> {
+    let mut header = [0u8; MAGIC.len() + PREFIX];
+    if read_full(reader, &mut header)? < header.len() || &header[..MAGIC.len()] != MAGIC {
+        bail!("not an encrypted recording");
+    }
+    let prefix: [u8; PREFIX] = header[MAGIC.len()..].try_into()?;
+    let mut current = vec![0u8; CHUNK + TAG];
+    let mut next = vec![0u8; CHUNK + TAG];
+    let mut length = read_full(reader, &mut current)?;
+    let mut counter = 0u32;
+    loop {
+        let following = if length == CHUNK + TAG {
+            read_full(reader, &mut next)?
+        } else {
+            0
+        };
+        let last = following == 0;
+        let opened = open(key, &nonce(&prefix, counter, last), &current[..length])
+            .with_context(|| format!("wrong key or damaged file at chunk {}", counter))?;
+        writer.write_all(&opened)?;
+        if last {
+            return Ok(());
+        }
+        std::mem::swap(&mut current, &mut next);
+        length = following;
+        counter = counter.checked_add(1).context("file too large")?;
+    }
+}
+
+fn nonce(prefix: &[u8; PREFIX], counter: u32, last: bool) -> [u8; 12] {
+    let mut nonce = [0u8; 12];
+    nonce[..PREFIX].copy_from_slice(prefix);
+    nonce[PREFIX..PREFIX + 4].copy_from_slice(&counter.to_be_bytes());
+    nonce[11] = last as u8;
+    nonce
+}
+
+#[cfg(feature = "encryption")]
+fn seal(key: &Key, nonce: &[u8; 12], data: &[u8]) -> Result<Vec<u8>> {
+    use aes_gcm::{
+        aead::{Aead, KeyInit},
+        Aes256Gcm, Nonce,
+    };
+
+    Aes256Gcm::new(&key.0.into())
+        .encrypt(Nonce::from_slice(nonce), data)
+        .map_err(|_| anyhow::anyhow!("encryption failed"))
+}
+
+#[cfg(feature = "encryption")]
+fn open(key: &Key, nonce: &[u8; 12], data: &[u8]) -> Result<Vec<u8>> {
+    use aes_gcm::{
+        aead::{Aead, KeyInit},
+        Aes256Gcm, Nonce,
+    };
+
+    Aes256Gcm::new(&key.0.into())
+        .decrypt(Nonce::from_slice(nonce), data)
+        .map_err(|_| anyhow::anyhow!("authentication failed"))
+}
+
+#[cfg(feature = "encryption")]
+fn random(buffer: &mut [u8]) -> Result<()> {
+    use aes_gcm::aead::{rand_core::RngCore, OsRng};
+
+    OsRng.fill_bytes(buffer);
+    Ok(())
+}
+
+#[cfg(not(feature = "encryption"))]
+fn seal(_key: &Key, _nonce: &[u8; 12], _data: &[u8]) -> Result<Vec<u8>> {
+    bail!(NOT_BUILT)
+}
+
+#[cfg(not(feature = "encryption"))]
+fn open(_key: &Key, _nonce: &[u8; 12], _data: &[u8]) -> Result<Vec<u8>> {
+    bail!(NOT_BUILT)
+}
+
+#[cfg(not(feature = "encryption"))]
+fn random(_buffer: &mut [u8]) -> Result<()> {
+    bail!(NOT_BUILT)
+}
+
+// 读满 buffer 或读到文件末尾, 返回读到的字节数.
+fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match reader.read(&mut buffer[filled..])? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(all(test, feature = "encryption"))]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        (0..text.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&text[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn encrypted(plain: &[u8], key: &Key) -> Vec<u8> {
+        let mut output = Vec::new();
+        encrypt(&mut Cursor::new(plain), &mut output, key).unwrap();
+        output
+    }
+
+    fn decrypted(data: &[u8], key: &Key) -> Result<Vec<u8>> {
+        let mut output = Vec::new();
+        decrypt(&mut Cursor::new(data), &mut output, key)?;
+        Ok(output)
+    }
+
+    // GCM 规范 (McGrew, Viega) 的 Test Case 13 和 14: 全零的 256 位密钥和 96 位 IV.
+    #[test]
+    fn aes_gcm_known_answer() {
+        let key = Key([0; 32]);
+        assert_eq!(
+            seal(&key, &[0; 12], &[]).unwrap(),
+            hex("530f8afbc74536b9a963b4f1c4cb738b")
+        );
+        assert_eq!(
+            seal(&key, &[0; 12], &[0; 16]).unwrap(),
+            hex("cea7403d4d606b6e074ec5d3baf39d18d0d1c8a799996bf0265b98b5d48ab919")
+        );
+    }
+
+    // 空文件, 不满一块, 正好一块和多块都能还原, 每块多一个认证标签.
+    #[test]
+    fn round_trip() {
+        let key = Key([7; 32]);
+        for length in [0, 1, CHUNK - 1, CHUNK, CHUNK + 1, 2 * CHUNK + 5] {
+            let plain: Vec<u8> = (0..length).map(|i| (i * 31 % 251) as u8).collect();
+            let data = encrypted(&plain, &key);
+            let chunks = length.div_ceil(CHUNK).max(1);
+            assert_eq!(data.len(), MAGIC.len() + PREFIX + length + chunks * TAG);
+            assert_eq!(decrypted(&data, &key).unwrap(), plain);
+        }
+    }
+
+    // 修改任意一个字节, 换用其他密钥, 截掉最后一块或交换两块时解密失败.
+    #[test]
+    fn tampering_is_detected() {
+        let key = Key([7; 32]);
+        let plain = vec![42u8; 2 * CHUNK + 100];
+        let data = encrypted(&plain, &key);
+        let start = MAGIC.len() + PREFIX;
+        for position in [start, start + CHUNK, data.len() - 1] {
+            let mut changed = data.clone();
+            changed[position] ^= 1;
+            assert!(decrypted(&changed, &key).is_err());
+        }
+        assert!(decrypted(&data, &Key([8; 32])).is_err());
+        let sealed = CHUNK + TAG;
+        assert!(decrypted(&data[..start + 2 * sealed], &key).is_err());
+        let mut swapped = data[..start].to_vec();
+        swapped.extend_from_slice(&data[start + sealed..start + 2 * sealed]);
+        swapped.extend_from_slice(&data[start..start + sealed]);
+        swapped.extend_from_slice(&data[start + 2 * sealed..]);
+        assert!(decrypted(&swapped, &key).is_err());
+        assert!(decrypted(b"plain text", &key).is_err());
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("encryption-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 生成的密钥可以读回, 不覆盖已有的密钥文件, 格式不对的密钥被拒绝.
+    #[test]
+    fn key_files() {
+        let dir = temp_dir("keys");
+        let path = dir.join(KEY_FILE);
+        Key::generate(&path).unwrap();
+        let key = Key::load(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().trim().len(), 64);
+        assert!(Key::generate(&path).is_err());
+        assert_eq!(Key::load(&path).unwrap().0, key.0);
+
+        fs::write(&path, "abc\n").unwrap();
+        assert!(Key::load(&path).is_err());
+        fs::write(&path, "zz".repeat(32)).unwrap();
+        assert!(Key::load(&path).is_err());
+        assert!(Key::load(&dir.join("missing.key")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 加密后删除明文, 解密不覆盖已有文件, 密钥错误时不留下输出.
+    #[test]
+    fn file_round_trip() {
+        let dir = temp_dir("files");
+        let input = dir.join("clip.mp4");
+        let plain: Vec<u8> = (0..CHUNK + 10).map(|i| (i % 256) as u8).collect();
+        fs::write(&input, &plain).unwrap();
+        let key = Key([3; 32]);
+        let encrypted = encrypt_file(&input, &key).unwrap();
+        assert_eq!(encrypted, dir.join("clip.mp4.enc"));
+        assert!(!input.exists());
+        assert!(!dir.join("clip.mp4.enc.part").exists());
+
+        let output = decrypted_path(&encrypted);
+        assert_eq!(output, input);
+        decrypt_file(&encrypted, &output, &key).unwrap();
+        assert_eq!(fs::read(&output).unwrap(), plain);
+        assert!(decrypt_file(&encrypted, &output, &key).is_err());
+        assert_eq!(fs::read(&output).unwrap(), plain);
+
+        let wrong = dir.join("wrong.mp4");
+        assert!(decrypt_file(&encrypted, &wrong, &Key([4; 32])).is_err());
+        assert!(!wrong.exists());
+        assert_eq!(
+            decrypted_path(Path::new("clip.bin")),
+            Path::new("clip.bin.decrypted")
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
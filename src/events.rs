@@ -0,0 +1,99 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde_json::{json, Value};
+
+use crate::day_night::Phase;
+
+pub const EVENTS_FILE: &str = "events.jsonl";
+
+// 一条事件, 例如 "speed" 或 "zone-exit".
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub time: DateTime<Local>,
+    pub kind: String,
+    pub data: Value,
+}
+
+type EventCallback = Box<dyn FnMut(&Event) + Send>;
+
+// 事件存储: 每行一个 JSON 对象 {"time", "kind", "data"}, 只追加写入.
+#[derive(Clone)]
+pub struct EventStore {
+    file: Arc<Mutex<File>>,
+    // 写入后依次调用, 在产生事件的线程中执行.
+    callbacks: Arc<Mutex<Vec<EventCallback>>>,
+    // 白天或夜晚, 知道时写入每个事件的 phase.
+    phase: Arc<Mutex<Option<Phase>>>,
+}
+
+impl EventStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("open event store {}", path.display()))?;
+        Ok(Self {
+            file: Arc::new(Mutex::new(file)),
+            callbacks: Arc::default(),
+            phase: Arc::default(),
+        })
+    }
+
+    pub fn on_event(&self, callback: impl FnMut(&Event) + Send + 'static) {
+        self.callbacks.lock().unwrap().push(Box::new(callback));
+    }
+
+    pub fn set_phase(&self, phase: Phase) {
+        *self.phase.lock().unwrap() = Some(phase);
+    }
+
+    pub fn log(&self, kind: &str, mut data: Value) {
+        if let (Some(phase), Value::Object(data)) = (*self.phase.lock().unwrap(), &mut data) {
+            data.entry("phase").or_insert_with(|| phase.as_str().into());
+        }
+        let event = Event {
+            time: Local::now(),
+            kind: kind.to_string(),
+            data,
+        };
+        let line = json!({
+            "time": event.time.to_rfc3339(),
+            "kind": event.kind,
+            "data": event.data,
+        });
+        if let Err(err) = writeln!(self.file.lock().unwrap(), "{}", line) {
+            eprintln!("write event: {:?}", err);
+        }
+        for callback in self.callbacks.lock().unwrap().iter_mut() {
+            callback(&event);
+        }
+    }
+}
+
+// 读取事件存储中的所有事件, 跳过无法解析的行 (例如写到一半的最后一行).
+pub fn read(path: impl AsRef<Path>) -> Result<Vec<Event>> {
+    let path = path.as_ref();
+    let text =
+        fs::read_to_string(path).with_context(|| format!("read event store {}", path.display()))?;
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let mut value: Value = serde_json::from_str(line).ok()?;
+            let time = DateTime::parse_from_rfc3339(value["time"].as_str()?).ok()?;
+            Some(Event {
+                time: time.with_timezone(&Local),
+                kind: value["kind"].as_str()?.to_string(),
+                data: value["data"].take(),
+            })
+        })
+        .collect())
+}
@@ -0,0 +1,70 @@
+use std::sync::RwLock;
+
+use anyhow::Result;
+use opencv::{
+    core::{Mat, Point, Scalar, Size, Vector, CV_8U},
+    imgproc::{self, LINE_8},
+    prelude::*,
+};
+
+use crate::zones::Zone;
+
+// 排除区域: 天空, 水面, 玻璃等会反光的地方, 不参与亮度测量和运动统计.
+// 配置中为 [[exclusions]], 也可以在控制面板中画出. 第一个值在每次修改时加一.
+static REGIONS: RwLock<(u64, Vec<Zone>)> = RwLock::new((0, Vec::new()));
+
+pub fn set(regions: Vec<Zone>) {
+    let mut current = REGIONS.write().unwrap();
+    *current = (current.0 + 1, regions);
+}
+
+pub fn add(region: Zone) {
+    let mut current = REGIONS.write().unwrap();
+    current.0 += 1;
+    current.1.push(region);
+}
+
+pub fn regions() -> Vec<Zone> {
+    REGIONS.read().unwrap().1.clone()
+}
+
+// 按画面尺寸缓存的掩码, 参与统计的像素为 255, 排除的为 0. 没有排除区域时为 None.
+#[derive(Default)]
+pub struct ExclusionMask {
+    generation: Option<u64>,
+    size: Size,
+    mask: Option<Mat>,
+}
+
+impl ExclusionMask {
+    pub fn get(&mut self, size: Size) -> Result<Option<&Mat>> {
+        let (generation, regions) = {
+            let current = REGIONS.read().unwrap();
+            if self.generation == Some(current.0) && self.size == size {
+                return Ok(self.mask.as_ref());
+            }
+            (current.0, current.1.clone())
+        };
+        self.generation = Some(generation);
+        self.size = size;
+        self.mask = if regions.iter().all(|region| region.points.len() < 3) {
+            None
+        } else {
+            let mut mask = Mat::new_size_with_default(size, CV_8U, Scalar::all(255.0))?;
+            let mut polygons = Vector::<Vector<Point>>::new();
+            for region in regions.iter().filter(|region| region.points.len() >= 3) {
+                polygons.push(region.pixels(size.width, size.height));
+            }
+            imgproc::fill_poly(
+                &mut mask,
+                &polygons,
+                Scalar::all(0.0),
+                LINE_8,
+                0,
+                Point::new(0, 0),
+            )?;
+            Some(mask)
+        };
+        Ok(self.mask.as_ref())
+    }
+}
@@ -0,0 +1,483 @@
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+
+use crate::gps::Fix;
+
+// 写入照片的 EXIF 和 XMP 元数据. EXIF 按 TIFF 小端格式生成, JPEG 中放在 APP1 段, PNG 中放在 eXIf 块.
+// XMP 另外记录使用的滤镜 (dc:subject), 照片管理软件可以按关键字整理.
+
+const XMP_NAMESPACE: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+
+// 标签值的类型, 只包含用到的几种.
+#[derive(Clone)]
+enum Value {
+    Byte(Vec<u8>),
+    Ascii(String),
+    Long(u32),
+    Rational(Vec<(u32, u32)>),
+}
+
+impl Value {
+    fn typ(&self) -> u16 {
+        match self {
+            Value::Byte(_) => 1,
+            Value::Ascii(_) => 2,
+            Value::Long(_) => 4,
+            Value::Rational(_) => 5,
+        }
+    }
+
+    fn count(&self) -> u32 {
+        match self {
+            Value::Byte(bytes) => bytes.len() as u32,
+            Value::Ascii(text) => text.len() as u32 + 1,
+            Value::Long(_) => 1,
+            Value::Rational(values) => values.len() as u32,
+        }
+    }
+
+    fn bytes(&self) -> Vec<u8> {
+        match self {
+            Value::Byte(bytes) => bytes.clone(),
+            Value::Ascii(text) => text.bytes().chain([0]).collect(),
+            Value::Long(value) => value.to_le_bytes().to_vec(),
+            Value::Rational(values) => values
+                .iter()
+                .flat_map(|(n, d)| n.to_le_bytes().into_iter().chain(d.to_le_bytes()))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Exif {
+    pub time: Option<DateTime<Local>>,
+    // 来源的描述, 写入 Model.
+    pub camera: Option<String>,
+    // 拍摄时的摄像头设置, 例如 "Exposure 156, Gain 32", 写入 ImageDescription.
+    pub settings: Option<String>,
+    // 曝光时间 (秒), 后端的单位无法换算时为 None.
+    pub exposure: Option<f64>,
+    pub gps: Option<Fix>,
+    // 拍摄时启用的处理阶段和效果.
+    pub filters: Vec<String>,
+}
+
+impl Exif {
+    // TIFF 头和 IFD0, Exif IFD, GPS IFD 依次排列, 偏移量从 TIFF 头开始计算.
+    fn tiff(&self) -> Vec<u8> {
+        let mut ifd0 = vec![(0x0131, Value::Ascii("slint-opencv".to_string()))];
+        if let Some(settings) = &self.settings {
+            ifd0.push((0x010E, Value::Ascii(settings.clone())));
+        }
+        if let Some(camera) = &self.camera {
+            ifd0.push((0x0110, Value::Ascii(camera.clone())));
+        }
+        let time = self
+            .time
+            .map(|time| time.format("%Y:%m:%d %H:%M:%S").to_string());
+        if let Some(time) = &time {
+            ifd0.push((0x0132, Value::Ascii(time.clone())));
+        }
+
+        let mut exif = Vec::new();
+        if let Some(exposure) = self.exposure.filter(|&exposure| exposure > 0.0) {
+            exif.push((0x829A, Value::Rational(vec![rational(exposure)])));
+        }
+        if let Some(time) = time {
+            exif.push((0x9003, Value::Ascii(time)));
+        }
+        let gps = self.gps.as_ref().map(gps_ifd).unwrap_or_default();
+
+        // IFD0 的长度与指针的值无关, 先按 0 计算出后面两个 IFD 的位置.
+        let with_pointers = |exif_offset, gps_offset| {
+            let mut entries = ifd0.clone();
+            if !exif.is_empty() {
+                entries.push((0x8769, Value::Long(exif_offset)));
+            }
+            if !gps.is_empty() {
+                entries.push((0x8825, Value::Long(gps_offset)));
+            }
+            write_ifd(entries, 8)
+        };
+        let exif_offset = 8 + with_pointers(0, 0).len() as u32;
+        let exif_bytes = if exif.is_empty() {
+            Vec::new()
+        } else {
+            write_ifd(exif.clone(), exif_offset)
+        };
+        let gps_offset = exif_offset + exif_bytes.len() as u32;
+
+        let mut tiff = b"II*\0".to_vec();
+        tiff.extend(8u32.to_le_bytes());
+        tiff.extend(with_pointers(exif_offset, gps_offset));
+        tiff.extend(exif_bytes);
+        if !gps.is_empty() {
+            tiff.extend(write_ifd(gps.clone(), gps_offset));
+        }
+        tiff
+    }
+
+    // XMP 数据包, 与 EXIF 中的信息相同, 另外包含滤镜列表.
+    pub fn xmp(&self) -> String {
+        let mut attributes = vec![("xmp:CreatorTool", "slint-opencv".to_string())];
+        if let Some(time) = self.time {
+            attributes.push(("xmp:CreateDate", time.to_rfc3339()));
+        }
+        if let Some(camera) = &self.camera {
+            attributes.push(("tiff:Model", camera.clone()));
+        }
+        if let Some(exposure) = self.exposure.filter(|&exposure| exposure > 0.0) {
+            let (n, d) = rational(exposure);
+            attributes.push(("exif:ExposureTime", format!("{}/{}", n, d)));
+        }
+        if let Some(fix) = &self.gps {
+            attributes.push(("exif:GPSLatitude", xmp_coordinate(fix.lat, 'N', 'S')));
+            attributes.push(("exif:GPSLongitude", xmp_coordinate(fix.lon, 'E', 'W')));
+            if let Some(alt) = fix.alt {
+                let (n, d) = rational(alt.abs());
+                attributes.push(("exif:GPSAltitudeRef", ((alt < 0.0) as u8).to_string()));
+                attributes.push(("exif:GPSAltitude", format!("{}/{}", n, d)));
+            }
+        }
+        let mut xml = String::from(
+            "<?xpacket begin=\"\u{feff}\" id=\"W5M0MpCehiHzreSzNTczkc9d\"?>\n\
+             <x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\n\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\n\
+             <rdf:Description rdf:about=\"\"\n\
+             \x20xmlns:xmp=\"http://ns.adobe.com/xap/1.0/\"\n\
+             \x20xmlns:tiff=\"http://ns.adobe.com/tiff/1.0/\"\n\
+             \x20xmlns:exif=\"http://ns.adobe.com/exif/1.0/\"\n\
+             \x20xmlns:dc=\"http://purl.org/dc/elements/1.1/\"",
+        );
+        for (name, value) in attributes {
+            xml += &format!("\n {}=\"{}\"", name, escape(&value));
+        }
+        xml += ">\n";
+        if let Some(settings) = &self.settings {
+            xml += &format!(
+                "<dc:description><rdf:Alt><rdf:li xml:lang=\"x-default\">{}</rdf:li></rdf:Alt></dc:description>\n",
+                escape(settings)
+            );
+        }
+        if !self.filters.is_empty() {
+            xml += "<dc:subject><rdf:Bag>";
+            for filter in &self.filters {
+                xml += &format!("<rdf:li>{}</rdf:li>", escape(filter));
+            }
+            xml += "</rdf:Bag></dc:subject>\n";
+        }
+        xml += "</rdf:Description>\n</rdf:RDF>\n</x:xmpmeta>\n<?xpacket end=\"w\"?>";
+        xml
+    }
+
+    // 在 JPEG 的 SOI 和 JFIF APP0 之后插入 EXIF 和 XMP 两个 APP1 段.
+    pub fn insert(&self, jpeg: &[u8]) -> Result<Vec<u8>> {
+        if !jpeg.starts_with(&[0xFF, 0xD8]) {
+            bail!("not a JPEG image");
+        }
+        let mut position = 2;
+        if jpeg.get(2..4) == Some(&[0xFF, 0xE0]) {
+            let Some(length) = jpeg.get(4..6) else {
+                bail!("truncated JPEG image");
+            };
+            position += 2 + u16::from_be_bytes([length[0], length[1]]) as usize;
+        }
+        let position = position.min(jpeg.len());
+        let mut output = jpeg[..position].to_vec();
+        app1(
+            &mut output,
+            &[b"Exif\0\0".as_slice(), &self.tiff()].concat(),
+        )?;
+        app1(
+            &mut output,
+            &[XMP_NAMESPACE, self.xmp().as_bytes()].concat(),
+        )?;
+        output.extend(&jpeg[position..]);
+        Ok(output)
+    }
+
+    // 在 PNG 的 IHDR 块之后插入 eXIf 块和 XMP 的 iTXt 块.
+    pub fn insert_png(&self, png: &[u8]) -> Result<Vec<u8>> {
+        // 8 字节签名, 然后是 IHDR: 长度, 类型, 13 字节数据, CRC.
+        const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+        if png.len() < IHDR_END || !png.starts_with(b"\x89PNG\r\n\x1a\n") || &png[12..16] != b"IHDR"
+        {
+            bail!("not a PNG image");
+        }
+        let mut output = png[..IHDR_END].to_vec();
+        png_chunk(&mut output, b"eXIf", &self.tiff());
+        // 关键字, 不压缩, 没有语言标签和翻译的关键字.
+        let text = [
+            b"XML:com.adobe.xmp\0\0\0\0\0".as_slice(),
+            self.xmp().as_bytes(),
+        ]
+        .concat();
+        png_chunk(&mut output, b"iTXt", &text);
+        output.extend(&png[IHDR_END..]);
+        Ok(output)
+    }
+}
+
+fn app1(output: &mut Vec<u8>, data: &[u8]) -> Result<()> {
+    let length = 2 + data.len();
+    if length > u16::MAX as usize {
+        bail!("metadata too large");
+    }
+    output.extend([0xFF, 0xE1]);
+    output.extend((length as u16).to_be_bytes());
+    output.extend(data);
+    Ok(())
+}
+
+fn png_chunk(output: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    output.extend((data.len() as u32).to_be_bytes());
+    let start = output.len();
+    output.extend(kind);
+    output.extend(data);
+    let crc = crc32(&output[start..]);
+    output.extend(crc.to_be_bytes());
+}
+
+// PNG 块使用的 CRC-32 (多项式 0xEDB88320).
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// XMP 的坐标格式: "31,12.3456N".
+fn xmp_coordinate(value: f64, positive: char, negative: char) -> String {
+    let degrees = value.abs().trunc();
+    let minutes = (value.abs() - degrees) * 60.0;
+    let direction = if value >= 0.0 { positive } else { negative };
+    format!("{},{:.6}{}", degrees, minutes, direction)
+}
+
+// 一个 IFD 和它引用的数据, offset 为它在 TIFF 中的位置. 标签按升序排列.
+fn write_ifd(mut entries: Vec<(u16, Value)>, offset: u32) -> Vec<u8> {
+    entries.sort_by_key(|(tag, _)| *tag);
+    let mut data_offset = offset + 2 + 12 * entries.len() as u32 + 4;
+    let mut ifd = (entries.len() as u16).to_le_bytes().to_vec();
+    let mut data = Vec::new();
+    for (tag, value) in &entries {
+        ifd.extend(tag.to_le_bytes());
+        ifd.extend(value.typ().to_le_bytes());
+        ifd.extend(value.count().to_le_bytes());
+        let mut bytes = value.bytes();
+        if bytes.len() <= 4 {
+            bytes.resize(4, 0);
+            ifd.extend(bytes);
+        } else {
+            ifd.extend(data_offset.to_le_bytes());
+            // 数据按字对齐.
+            if bytes.len() % 2 == 1 {
+                bytes.push(0);
+            }
+            data_offset += bytes.len() as u32;
+            data.extend(bytes);
+        }
+    }
+    // 没有下一个 IFD.
+    ifd.extend(0u32.to_le_bytes());
+    ifd.extend(data);
+    ifd
+}
+
+fn gps_ifd(fix: &Fix) -> Vec<(u16, Value)> {
+    let mut entries = vec![
+        (0x0000, Value::Byte(vec![2, 3, 0, 0])),
+        (
+            0x0001,
+            Value::Ascii(if fix.lat >= 0.0 { "N" } else { "S" }.to_string()),
+        ),
+        (0x0002, Value::Rational(degrees(fix.lat.abs()))),
+        (
+            0x0003,
+            Value::Ascii(if fix.lon >= 0.0 { "E" } else { "W" }.to_string()),
+        ),
+        (0x0004, Value::Rational(degrees(fix.lon.abs()))),
+    ];
+    if let Some(alt) = fix.alt {
+        entries.push((0x0005, Value::Byte(vec![(alt < 0.0) as u8])));
+        entries.push((0x0006, Value::Rational(vec![rational(alt.abs())])));
+    }
+    // 速度以 km/h 记录.
+    if let Some(speed) = fix.speed {
+        entries.push((0x000C, Value::Ascii("K".to_string())));
+        entries.push((0x000D, Value::Rational(vec![rational(speed * 3.6)])));
+    }
+    if let Some(track) = fix.track {
+        entries.push((0x000E, Value::Ascii("T".to_string())));
+        entries.push((0x000F, Value::Rational(vec![rational(track)])));
+    }
+    entries
+}
+
+// 度, 分, 秒, 秒保留 4 位小数.
+pub fn degrees(value: f64) -> Vec<(u32, u32)> {
+    let degrees = value.trunc();
+    let minutes = ((value - degrees) * 60.0).trunc();
+    let seconds = ((value - degrees) * 60.0 - minutes) * 60.0;
+    vec![
+        (degrees as u32, 1),
+        (minutes as u32, 1),
+        ((seconds * 10000.0).round() as u32, 10000),
+    ]
+}
+
+// 小于 1 的值 (曝光时间) 写成 1/n, 其他保留 4 位小数.
+fn rational(value: f64) -> (u32, u32) {
+    if value > 0.0 && value < 1.0 {
+        (1, (1.0 / value).round().min(u32::MAX as f64) as u32)
+    } else {
+        ((value * 10000.0).round().min(u32::MAX as f64) as u32, 10000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn u16_at(bytes: &[u8], at: usize) -> u16 {
+        u16::from_le_bytes([bytes[at], bytes[at + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    // IFD 中标签的 (类型, 个数, 数据), 数据不超过 4 字节时在项中, 否则在偏移处.
+    fn entry(tiff: &[u8], ifd: usize, tag: u16) -> Option<(u16, u32, Vec<u8>)> {
+        (0..u16_at(tiff, ifd) as usize)
+            .map(|i| ifd + 2 + 12 * i)
+            .find(|&at| u16_at(tiff, at) == tag)
+            .map(|at| {
+                let (typ, count) = (u16_at(tiff, at + 2), u32_at(tiff, at + 4));
+                let size = count as usize * [0, 1, 1, 0, 4, 8][typ as usize];
+                let data = if size <= 4 {
+                    tiff[at + 8..at + 8 + size].to_vec()
+                } else {
+                    let offset = u32_at(tiff, at + 8) as usize;
+                    tiff[offset..offset + size].to_vec()
+                };
+                (typ, count, data)
+            })
+    }
+
+    fn rationals(data: &[u8]) -> Vec<(u32, u32)> {
+        data.chunks(8)
+            .map(|pair| (u32_at(pair, 0), u32_at(pair, 4)))
+            .collect()
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(degrees(52.3676), vec![(52, 1), (22, 1), (33600, 10000)]);
+        assert_eq!(rational(0.004), (1, 250));
+        assert_eq!(rational(12.5), (125000, 10000));
+        assert_eq!(xmp_coordinate(-4.5, 'E', 'W'), "4,30.000000W");
+        // PNG IEND 块的 CRC.
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    // 时间写入 IFD0 和 Exif IFD, 坐标写入 GPS IFD, 南纬和负海拔用参考标签表示.
+    #[test]
+    fn tiff_tags() {
+        let exif = Exif {
+            time: Some(Local.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap()),
+            camera: Some("Front door".to_string()),
+            exposure: Some(0.004),
+            gps: Some(Fix {
+                lat: -33.5,
+                lon: 151.25,
+                alt: Some(-10.0),
+                ..Fix::default()
+            }),
+            ..Exif::default()
+        };
+        let tiff = exif.tiff();
+        assert_eq!(&tiff[..8], b"II*\0\x08\0\0\0");
+        let ascii = |ifd, tag| entry(&tiff, ifd, tag).map(|(_, _, data)| data);
+        assert_eq!(ascii(8, 0x0110).unwrap(), b"Front door\0");
+        assert_eq!(ascii(8, 0x0132).unwrap(), b"2024:05:01 12:30:00\0");
+
+        let pointer = |tag| u32_at(&entry(&tiff, 8, tag).unwrap().2, 0) as usize;
+        let (exif_ifd, gps_ifd) = (pointer(0x8769), pointer(0x8825));
+        assert_eq!(ascii(exif_ifd, 0x9003).unwrap(), b"2024:05:01 12:30:00\0");
+        assert_eq!(rationals(&ascii(exif_ifd, 0x829A).unwrap()), vec![(1, 250)]);
+        assert_eq!(ascii(gps_ifd, 0x0001).unwrap(), b"S\0");
+        assert_eq!(
+            rationals(&ascii(gps_ifd, 0x0002).unwrap()),
+            vec![(33, 1), (30, 1), (0, 10000)]
+        );
+        assert_eq!(ascii(gps_ifd, 0x0003).unwrap(), b"E\0");
+        assert_eq!(ascii(gps_ifd, 0x0005).unwrap(), vec![1]);
+        assert_eq!(
+            rationals(&ascii(gps_ifd, 0x0006).unwrap()),
+            vec![(100000, 10000)]
+        );
+        assert!(entry(&tiff, gps_ifd, 0x000D).is_none());
+    }
+
+    // EXIF 和 XMP 段插在 JFIF APP0 之后, 图像数据不变.
+    #[test]
+    fn insert_after_jfif() {
+        let jpeg = [
+            0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0xAA, 0xBB, 0xFF, 0xDA, 1, 2, 3,
+        ];
+        let exif = Exif {
+            filters: vec!["sepia".to_string(), "a&b".to_string()],
+            ..Exif::default()
+        };
+        let output = exif.insert(&jpeg).unwrap();
+        assert_eq!(&output[..8], &jpeg[..8]);
+        assert_eq!(&output[8..10], &[0xFF, 0xE1]);
+        assert_eq!(&output[12..18], b"Exif\0\0");
+        let xmp = 10 + u16::from_be_bytes([output[10], output[11]]) as usize;
+        assert_eq!(&output[xmp..xmp + 2], &[0xFF, 0xE1]);
+        let end = xmp + 2 + u16::from_be_bytes([output[xmp + 2], output[xmp + 3]]) as usize;
+        let packet = String::from_utf8_lossy(&output[xmp + 4..end]);
+        assert!(packet.starts_with("http://ns.adobe.com/xap/1.0/\0"));
+        assert!(packet.contains("<rdf:li>sepia</rdf:li><rdf:li>a&amp;b</rdf:li>"));
+        assert_eq!(&output[end..], &jpeg[8..]);
+
+        assert!(exif.insert(b"\x89PNG").is_err());
+    }
+
+    // PNG 中 eXIf 和 iTXt 块紧跟 IHDR, CRC 正确.
+    #[test]
+    fn insert_png() {
+        let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+        png_chunk(&mut png, b"IHDR", &[0; 13]);
+        png_chunk(&mut png, b"IEND", &[]);
+        let output = Exif::default().insert_png(&png).unwrap();
+        let chunk = &output[33..];
+        let length = u32::from_be_bytes(chunk[..4].try_into().unwrap()) as usize;
+        assert_eq!(&chunk[4..8], b"eXIf");
+        assert_eq!(&chunk[8..12], b"II*\0");
+        let crc = u32::from_be_bytes(chunk[8 + length..12 + length].try_into().unwrap());
+        assert_eq!(crc, crc32(&chunk[4..8 + length]));
+        assert_eq!(&chunk[16 + length..20 + length], b"iTXt");
+        assert!(output.ends_with(&png[33..]));
+        assert!(Exif::default().insert_png(&png[..20]).is_err());
+    }
+}
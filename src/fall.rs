@@ -0,0 +1,579 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::PathBuf,
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{Local, TimeDelta};
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, Vector},
+    imgcodecs,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    events::EventStore,
+    pipeline::FrameProcessor,
+    pose::{Keypoint, PoseEstimator},
+    threads::{self, ThreadConfig},
+};
+
+// 跌倒检测预设 (老人看护): 姿态估计得到人体的外接框, 身体中心快速下降,
+// 随后保持横躺且几乎不动时报警. 报警逐级升级: 先在画面上提示,
+// 无人确认时发送 webhook, 再发送 Telegram 消息.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FallConfig {
+    pub enabled: bool,
+    // 姿态估计模型名称 (见 [models]).
+    pub model: Option<String>,
+    // 姿态估计间隔 (秒).
+    pub interval: f64,
+    // 关键点置信度低于该值时不使用.
+    pub min_score: f64,
+    // fall_window 秒内身体中心下降超过站立时身高的 drop 倍时认为在下落.
+    pub drop: f64,
+    pub fall_window: f64,
+    // 外接框宽高比达到 lying_ratio 时认为横躺.
+    pub lying_ratio: f64,
+    // 下落后横躺 still_for 秒, 身体中心移动不超过身体尺寸的 still 倍时判定跌倒.
+    pub still_for: f64,
+    pub still: f64,
+    // 报警后无人确认, 经过这么多秒发送 webhook 和 Telegram 消息.
+    pub webhook_after: f64,
+    pub alert_urls: Vec<String>,
+    pub telegram_after: f64,
+    pub telegram: Option<TelegramConfig>,
+    // 跌倒时的画面.
+    pub dir: PathBuf,
+}
+
+impl Default for FallConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            interval: 0.2,
+            min_score: 0.3,
+            drop: 0.4,
+            fall_window: 1.5,
+            lying_ratio: 1.0,
+            still_for: 5.0,
+            still: 0.15,
+            webhook_after: 30.0,
+            alert_urls: Vec::new(),
+            telegram_after: 60.0,
+            telegram: None,
+            dir: PathBuf::from("falls"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    // 只包含机器人 token 的文件.
+    pub token_file: PathBuf,
+    pub chat_id: String,
+}
+
+impl TelegramConfig {
+    pub fn send(&self, text: &str) -> Result<()> {
+        let token = fs::read_to_string(&self.token_file)
+            .with_context(|| format!("read {}", self.token_file.display()))?;
+        let url = format!("https://api.telegram.org/bot{}/sendMessage", token.trim());
+        ureq::post(&url)
+            .set("Content-Type", "application/json")
+            .timeout(Duration::from_secs(10))
+            .send_string(&json!({ "chat_id": self.chat_id, "text": text }).to_string())
+            .context("send Telegram message")?;
+        Ok(())
+    }
+}
+
+// 关键点的外接框, 坐标为画面像素.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Body {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl Body {
+    // 置信度足够的关键点少于 3 个时认为画面中没有人.
+    pub fn from_keypoints(points: &[Keypoint], min_score: f64) -> Option<Body> {
+        let points: Vec<&Keypoint> = points
+            .iter()
+            .filter(|point| point.score as f64 >= min_score)
+            .collect();
+        if points.len() < 3 {
+            return None;
+        }
+        let xs = points.iter().map(|point| point.x as f64);
+        let ys = points.iter().map(|point| point.y as f64);
+        let left = xs.clone().fold(f64::INFINITY, f64::min);
+        let right = xs.fold(f64::NEG_INFINITY, f64::max);
+        let top = ys.clone().fold(f64::INFINITY, f64::min);
+        let bottom = ys.fold(f64::NEG_INFINITY, f64::max);
+        Some(Body {
+            x: left,
+            y: top,
+            width: right - left,
+            height: bottom - top,
+        })
+    }
+
+    pub fn center(&self) -> (f64, f64) {
+        (self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    pub fn lying(&self, ratio: f64) -> bool {
+        self.width >= self.height * ratio
+    }
+
+    fn size(&self) -> f64 {
+        self.width.max(self.height)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Phase {
+    Upright,
+    // 下落开始的时间, 等待横躺静止.
+    Dropped(f64),
+    Fallen,
+}
+
+// 跌倒判定, 时间为秒. 判定跌倒后直到再次站起才重新检测.
+pub struct FallTracker {
+    samples: VecDeque<(f64, Body)>,
+    phase: Phase,
+}
+
+impl Default for FallTracker {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            phase: Phase::Upright,
+        }
+    }
+}
+
+impl FallTracker {
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    // 判定跌倒时返回 true, 每次跌倒只返回一次.
+    pub fn update(&mut self, time: f64, body: Body, config: &FallConfig) -> bool {
+        self.samples.push_back((time, body));
+        let keep = config.fall_window + config.still_for;
+        while self
+            .samples
+            .front()
+            .is_some_and(|(sample, _)| *sample < time - keep)
+        {
+            self.samples.pop_front();
+        }
+        let lying = body.lying(config.lying_ratio);
+        match self.phase {
+            Phase::Upright => {
+                let (_, y) = body.center();
+                let dropped = self
+                    .samples
+                    .iter()
+                    .filter(|(sample, _)| time - sample <= config.fall_window)
+                    .any(|(_, before)| {
+                        !before.lying(config.lying_ratio)
+                            && y - before.center().1 >= config.drop * before.height
+                    });
+                if dropped {
+                    self.phase = Phase::Dropped(time);
+                }
+                false
+            }
+            Phase::Dropped(start) => {
+                // 下落后一直没有静止下来, 例如坐下或弯腰后又站起.
+                if time - start > config.fall_window + config.still_for * 2.0 {
+                    self.phase = Phase::Upright;
+                    return false;
+                }
+                if time - start < config.still_for || !lying {
+                    return false;
+                }
+                let (x, y) = body.center();
+                let reach = config.still * body.size();
+                let still = self
+                    .samples
+                    .iter()
+                    .filter(|(sample, _)| time - sample <= config.still_for)
+                    .all(|(_, before)| {
+                        let (bx, by) = before.center();
+                        before.lying(config.lying_ratio) && (bx - x).hypot(by - y) <= reach
+                    });
+                if still {
+                    self.phase = Phase::Fallen;
+                }
+                still
+            }
+            Phase::Fallen => {
+                if !lying {
+                    self.phase = Phase::Upright;
+                }
+                false
+            }
+        }
+    }
+}
+
+struct Job {
+    image: Mat,
+    time: f64,
+}
+
+#[derive(Default)]
+struct Status {
+    keypoints: Vec<Keypoint>,
+    body: Option<Body>,
+    phase: Option<Phase>,
+    // 报警开始的时间, 确认后阶段被重新创建.
+    alarm: Option<Instant>,
+}
+
+type AlarmCallback = Box<dyn Fn(bool) + Send>;
+
+pub struct FallDetection {
+    config: FallConfig,
+    jobs: SyncSender<Job>,
+    status: Arc<Mutex<Status>>,
+    events: EventStore,
+    on_alarm: AlarmCallback,
+    start: Instant,
+    last: Option<Instant>,
+    alarmed: bool,
+    webhook_sent: bool,
+    telegram_sent: bool,
+}
+
+impl FallDetection {
+    // on_alarm 在报警开始和阶段移除 (确认或关闭) 时调用, 用于更新界面.
+    pub fn new(
+        config: &FallConfig,
+        mut estimator: Box<dyn PoseEstimator>,
+        events: EventStore,
+        workers: &ThreadConfig,
+        on_alarm: impl Fn(bool) + Send + 'static,
+    ) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("create {}", config.dir.display()))?;
+        let status = Arc::new(Mutex::new(Status::default()));
+        let shared = status.clone();
+        let logging = events.clone();
+        let worker_config = config.clone();
+        let mut tracker = FallTracker::default();
+        let jobs = threads::worker("fall", 1, workers, move |job: Job| {
+            let config = &worker_config;
+            let keypoints = estimator.estimate(&job.image)?;
+            let body = Body::from_keypoints(&keypoints, config.min_score);
+            let fallen = body.is_some_and(|body| tracker.update(job.time, body, config));
+            {
+                let mut status = shared.lock().unwrap();
+                status.keypoints = keypoints;
+                status.body = body;
+                status.phase = Some(tracker.phase());
+                if !fallen || status.alarm.is_some() {
+                    return Ok(());
+                }
+                status.alarm = Some(Instant::now());
+            }
+            let snapshot = config.dir.join(format!(
+                "fall-{}.jpg",
+                Local::now().format("%Y-%m-%dT%H-%M-%S")
+            ));
+            let snapshot =
+                match imgcodecs::imwrite(&snapshot.to_string_lossy(), &job.image, &Vector::new()) {
+                    Ok(true) => Some(snapshot.display().to_string()),
+                    _ => None,
+                };
+            logging.log("fall", json!({ "snapshot": snapshot }));
+            Ok(())
+        })?;
+        Ok(Self {
+            config: config.clone(),
+            jobs,
+            status,
+            events,
+            on_alarm: Box::new(on_alarm),
+            start: Instant::now(),
+            last: None,
+            alarmed: false,
+            webhook_sent: false,
+            telegram_sent: false,
+        })
+    }
+
+    // 报警升级到下一级, 在后台线程中发送, 不阻塞采集.
+    fn escalate(&self, level: &'static str, since: Instant) {
+        let elapsed = since.elapsed();
+        let time = Local::now() - TimeDelta::from_std(elapsed).unwrap_or_default();
+        let message = format!(
+            "Possible fall at {}, not acknowledged after {} s",
+            time.format("%H:%M:%S"),
+            elapsed.as_secs()
+        );
+        self.events.log("fall-escalated", json!({ "level": level }));
+        let urls = self.config.alert_urls.clone();
+        let telegram = self.config.telegram.clone();
+        let body = json!({
+            "title": "Fall detected",
+            "message": message,
+            "time": time.to_rfc3339(),
+        })
+        .to_string();
+        let _ = thread::Builder::new()
+            .name("fall-alert".to_string())
+            .spawn(move || match level {
+                "telegram" => {
+                    if let Some(telegram) = telegram {
+                        if let Err(err) = telegram.send(&message) {
+                            eprintln!("fall alert: {:?}", err);
+                        }
+                    }
+                }
+                _ => {
+                    for url in urls {
+                        if let Err(err) = ureq::post(&url)
+                            .set("Content-Type", "application/json")
+                            .send_string(&body)
+                        {
+                            eprintln!("fall alert to {}: {:?}", url, err);
+                        }
+                    }
+                }
+            });
+    }
+}
+
+impl Drop for FallDetection {
+    fn drop(&mut self) {
+        if self.alarmed {
+            (self.on_alarm)(false);
+        }
+    }
+}
+
+impl FrameProcessor for FallDetection {
+    fn name(&self) -> &str {
+        "fall"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let alarm = self.status.lock().unwrap().alarm;
+        if let Some(since) = alarm {
+            if !self.alarmed {
+                self.alarmed = true;
+                (self.on_alarm)(true);
+            }
+            let elapsed = since.elapsed().as_secs_f64();
+            if !self.webhook_sent
+                && !self.config.alert_urls.is_empty()
+                && elapsed >= self.config.webhook_after
+            {
+                self.webhook_sent = true;
+                self.escalate("webhook", since);
+            }
+            if !self.telegram_sent
+                && self.config.telegram.is_some()
+                && elapsed >= self.config.telegram_after
+            {
+                self.telegram_sent = true;
+                self.escalate("telegram", since);
+            }
+        }
+        let interval = Duration::from_secs_f64(self.config.interval.max(0.0));
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        match self.jobs.try_send(Job {
+            image: frame.try_clone()?,
+            time: self.start.elapsed().as_secs_f64(),
+        }) {
+            Ok(()) => self.last = Some(Instant::now()),
+            Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => bail!("fall detection thread exited"),
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 关键点和外接框: 站立为绿色, 下落中为橙色, 跌倒为红色. 报警时画面边框闪烁.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let status = self.status.lock().unwrap();
+        let color = match status.phase {
+            Some(Phase::Dropped(_)) => Scalar::new(0., 160., 255., 0.),
+            Some(Phase::Fallen) => Scalar::new(0., 0., 255., 0.),
+            _ => Scalar::new(0., 220., 0., 0.),
+        };
+        for point in &status.keypoints {
+            if (point.score as f64) < self.config.min_score {
+                continue;
+            }
+            let center = Point::new(point.x as i32, point.y as i32);
+            imgproc::circle(frame, center, 4, color, -1, LINE_AA, 0)?;
+        }
+        if let Some(body) = status.body {
+            let rect = Rect::new(
+                body.x as i32,
+                body.y as i32,
+                body.width as i32,
+                body.height as i32,
+            );
+            imgproc::rectangle(frame, rect, color, 2, LINE_AA, 0)?;
+        }
+        let Some(since) = status.alarm else {
+            return Ok(());
+        };
+        let red = Scalar::new(0., 0., 255., 0.);
+        if since.elapsed().as_millis() / 500 % 2 == 0 {
+            let border = Rect::new(0, 0, frame.cols(), frame.rows());
+            imgproc::rectangle(frame, border, red, 16, LINE_AA, 0)?;
+        }
+        imgproc::put_text(
+            frame,
+            &format!("FALL DETECTED {} s", since.elapsed().as_secs()),
+            Point::new(30, 60),
+            FONT_HERSHEY_SIMPLEX,
+            1.4,
+            red,
+            3,
+            LINE_AA,
+            false,
+        )?;
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.status.lock().unwrap().body.is_some() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 站立时 60x180, 倒地后 180x45 横躺在脚边.
+    const STANDING: Body = Body {
+        x: 100.0,
+        y: 50.0,
+        width: 60.0,
+        height: 180.0,
+    };
+    const LYING: Body = Body {
+        x: 40.0,
+        y: 230.0,
+        width: 180.0,
+        height: 45.0,
+    };
+
+    // 每 0.2 秒一个样本, 返回判定跌倒的次数.
+    fn run(tracker: &mut FallTracker, from: f64, to: f64, body: impl Fn(f64) -> Body) -> usize {
+        let config = FallConfig::default();
+        let mut falls = 0;
+        let mut step = (from * 5.0).round() as i32;
+        while step as f64 / 5.0 <= to + 1e-9 {
+            let time = step as f64 / 5.0;
+            falls += tracker.update(time, body(time), &config) as usize;
+            step += 1;
+        }
+        falls
+    }
+
+    #[test]
+    fn keypoints_bounding_box() {
+        let point = |x, y, score| Keypoint { x, y, score };
+        let points = [
+            point(10.0, 20.0, 0.9),
+            point(50.0, 200.0, 0.8),
+            point(30.0, 5.0, 0.1),
+            point(40.0, 60.0, 0.5),
+        ];
+        assert_eq!(
+            Body::from_keypoints(&points, 0.3),
+            Some(Body {
+                x: 10.0,
+                y: 20.0,
+                width: 40.0,
+                height: 180.0,
+            })
+        );
+        assert_eq!(Body::from_keypoints(&points[..3], 0.3), None);
+        assert!(LYING.lying(1.0) && !STANDING.lying(1.0));
+    }
+
+    // 倒地后横躺不动 5 秒判定一次跌倒, 站起来后重新检测.
+    #[test]
+    fn fall_and_get_up() {
+        let mut tracker = FallTracker::default();
+        assert_eq!(run(&mut tracker, 0.0, 1.0, |_| STANDING), 0);
+        assert_eq!(run(&mut tracker, 1.2, 1.2, |_| LYING), 0);
+        assert_eq!(tracker.phase(), Phase::Dropped(1.2));
+        assert_eq!(run(&mut tracker, 1.4, 6.0, |_| LYING), 0);
+        assert_eq!(run(&mut tracker, 6.2, 6.2, |_| LYING), 1);
+        assert_eq!(tracker.phase(), Phase::Fallen);
+        assert_eq!(run(&mut tracker, 6.4, 20.0, |_| LYING), 0);
+        run(&mut tracker, 20.2, 20.2, |_| STANDING);
+        assert_eq!(tracker.phase(), Phase::Upright);
+    }
+
+    // 站立时的晃动和慢慢躺下不算下落.
+    #[test]
+    fn lying_down_slowly() {
+        let mut tracker = FallTracker::default();
+        let sway = |time: f64| Body {
+            x: STANDING.x + (time * 7.0).sin() * 5.0,
+            ..STANDING
+        };
+        assert_eq!(run(&mut tracker, 0.0, 10.0, sway), 0);
+        // 10 秒内逐渐降低, 每个 1.5 秒窗口内下降不到身高的 0.4 倍.
+        let settle = |time: f64| {
+            let t = ((time - 10.0) / 10.0).min(1.0);
+            Body {
+                x: STANDING.x + (LYING.x - STANDING.x) * t,
+                y: STANDING.y + (LYING.y - STANDING.y) * t,
+                width: STANDING.width + (LYING.width - STANDING.width) * t,
+                height: STANDING.height + (LYING.height - STANDING.height) * t,
+            }
+        };
+        assert_eq!(run(&mut tracker, 10.2, 30.0, settle), 0);
+        assert_eq!(tracker.phase(), Phase::Upright);
+    }
+
+    // 下落后一直在动 (例如在地上挣扎), 超过等待时间后回到站立检测.
+    #[test]
+    fn moving_after_drop() {
+        let mut tracker = FallTracker::default();
+        run(&mut tracker, 0.0, 1.0, |_| STANDING);
+        let rolling = |time: f64| Body {
+            x: LYING.x + (time * 5.0) as i64 as f64 % 2.0 * 60.0,
+            ..LYING
+        };
+        assert_eq!(run(&mut tracker, 1.2, 12.6, rolling), 0);
+        assert_eq!(tracker.phase(), Phase::Dropped(1.2));
+        run(&mut tracker, 12.8, 12.8, rolling);
+        assert_eq!(tracker.phase(), Phase::Upright);
+    }
+}
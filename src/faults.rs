@@ -0,0 +1,347 @@
+use std::{
+    sync::Mutex,
+    thread::{self, sleep},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Rect, Scalar},
+    imgproc::{self, INTER_AREA},
+    prelude::*,
+    videoio::VideoCapture,
+};
+use serde::Deserialize;
+
+use crate::{
+    source::{FrameSource, Metadata},
+    threads::Shutdown,
+};
+
+// 模拟读取超时时每次 read 阻塞的最长时间, 与摄像头驱动的超时相近.
+const STALL: Duration = Duration::from_millis(200);
+
+// 调试用的故障注入, 用于检验断线重连, 看门狗和恢复逻辑. 不要在正式使用的机器上启用.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FaultsConfig {
+    pub enabled: bool,
+    // 启动后按时间注入的故障, 用于无人值守的测试.
+    pub schedule: Vec<ScheduledFault>,
+}
+
+// flatten 不能与 deny_unknown_fields 一起使用.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScheduledFault {
+    // 启动后多少秒注入.
+    pub after: f64,
+    #[serde(flatten)]
+    pub fault: Fault,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "fault", rename_all = "snake_case")]
+pub enum Fault {
+    // 在 seconds 秒内读取阻塞后出错, 来源本身仍然可以打开.
+    Timeout { seconds: f64 },
+    // 接下来的 frames 帧下半部分变为噪声, 与解码出错的画面相似.
+    Corrupt { frames: u32 },
+    // 之后读到的帧缩放到该尺寸, 直到 clear.
+    Resolution { width: i32, height: i32 },
+    // 在 seconds 秒内读取出错, 重新打开来源也失败.
+    Disconnect { seconds: f64 },
+    // 清除所有故障.
+    Clear,
+}
+
+#[derive(Default)]
+struct Injected {
+    timeout: Option<Instant>,
+    disconnect: Option<Instant>,
+    corrupt: u32,
+    resolution: Option<core::Size>,
+}
+
+static INJECTED: Mutex<Injected> = Mutex::new(Injected {
+    timeout: None,
+    disconnect: None,
+    corrupt: 0,
+    resolution: None,
+});
+
+pub fn inject(fault: &Fault) {
+    eprintln!("fault: injecting {:?}", fault);
+    let mut injected = INJECTED.lock().unwrap();
+    let until = |seconds: f64| Some(Instant::now() + Duration::from_secs_f64(seconds.max(0.0)));
+    match *fault {
+        Fault::Timeout { seconds } => injected.timeout = until(seconds),
+        Fault::Corrupt { frames } => injected.corrupt = frames,
+        Fault::Resolution { width, height } => {
+            injected.resolution = Some(core::Size::new(width.max(2), height.max(2)))
+        }
+        Fault::Disconnect { seconds } => injected.disconnect = until(seconds),
+        Fault::Clear => *injected = Injected::default(),
+    }
+}
+
+// 按 schedule 在后台注入故障.
+pub fn start(config: &FaultsConfig, shutdown: &Shutdown) -> Result<()> {
+    if !config.enabled || config.schedule.is_empty() {
+        return Ok(());
+    }
+    let mut schedule = config.schedule.clone();
+    schedule.sort_by(|a, b| a.after.total_cmp(&b.after));
+    let start = Instant::now();
+    let shutdown = shutdown.clone();
+    thread::Builder::new()
+        .name("faults".to_string())
+        .spawn(move || {
+            for entry in schedule {
+                let due = start + Duration::from_secs_f64(entry.after.max(0.0));
+                if shutdown.wait(due.saturating_duration_since(Instant::now())) {
+                    return;
+                }
+                inject(&entry.fault);
+            }
+        })?;
+    Ok(())
+}
+
+// 打开来源之前调用, 模拟断开期间设备不存在.
+pub fn check_open(config: &FaultsConfig) -> Result<()> {
+    if config.enabled && active(&mut INJECTED.lock().unwrap().disconnect) {
+        bail!("injected disconnect");
+    }
+    Ok(())
+}
+
+// 启用时包装来源, 所有来源 (包括插件注册的) 都可以注入故障.
+pub fn wrap(source: Box<dyn FrameSource>, config: &FaultsConfig) -> Box<dyn FrameSource> {
+    if !config.enabled {
+        return source;
+    }
+    Box::new(FaultySource {
+        inner: source,
+        scaled: Mat::default(),
+    })
+}
+
+struct FaultySource {
+    inner: Box<dyn FrameSource>,
+    scaled: Mat,
+}
+
+impl FrameSource for FaultySource {
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        let (failure, corrupt, resolution) = {
+            let mut injected = INJECTED.lock().unwrap();
+            let failure = if active(&mut injected.disconnect) {
+                Some("injected disconnect")
+            } else if active(&mut injected.timeout) {
+                Some("injected read timeout")
+            } else {
+                None
+            };
+            let corrupt = failure.is_none() && injected.corrupt > 0;
+            if corrupt {
+                injected.corrupt -= 1;
+            }
+            (failure, corrupt, injected.resolution)
+        };
+        if let Some(failure) = failure {
+            // 真实的超时会阻塞读取, 不能立即返回.
+            sleep(STALL);
+            bail!(failure);
+        }
+        if !self.inner.read(frame)? || frame.empty() {
+            return Ok(false);
+        }
+        if let Some(size) = resolution {
+            if frame.size()? != size {
+                imgproc::resize(frame, &mut self.scaled, size, 0.0, 0.0, INTER_AREA)?;
+                std::mem::swap(frame, &mut self.scaled);
+            }
+        }
+        if corrupt {
+            let (cols, rows) = (frame.cols(), frame.rows());
+            let rect = Rect::new(0, rows / 2, cols, rows - rows / 2);
+            let mut band = Mat::roi_mut(frame, rect)?;
+            core::randu(&mut *band, &Scalar::all(0.0), &Scalar::all(256.0))?;
+        }
+        Ok(true)
+    }
+
+    fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    fn reconfigure(&mut self, property: i32, value: f64) -> Result<bool> {
+        self.inner.reconfigure(property, value)
+    }
+
+    fn capture(&mut self) -> Option<&mut VideoCapture> {
+        self.inner.capture()
+    }
+}
+
+// 故障是否仍在持续, 到期后清除.
+fn active(until: &mut Option<Instant>) -> bool {
+    match *until {
+        Some(until) if Instant::now() < until => true,
+        Some(_) => {
+            *until = None;
+            false
+        }
+        None => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Size, CV_8UC3};
+
+    use super::*;
+
+    // 只产生纯色帧的来源.
+    struct Solid(Metadata);
+
+    impl FrameSource for Solid {
+        fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+            *frame = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(128.))?;
+            Ok(true)
+        }
+
+        fn metadata(&self) -> &Metadata {
+            &self.0
+        }
+    }
+
+    fn solid() -> Box<dyn FrameSource> {
+        Box::new(Solid(Metadata {
+            description: "solid".to_string(),
+            profile: "default".to_string(),
+            width: 64.0,
+            height: 48.0,
+            fps: 30.0,
+            live: true,
+        }))
+    }
+
+    fn read(source: &mut Box<dyn FrameSource>) -> Result<Mat> {
+        let mut frame = Mat::default();
+        assert!(source.read(&mut frame)?);
+        Ok(frame)
+    }
+
+    // 下半部分的最大偏差, 没有损坏时为 0.
+    fn damage(frame: &Mat) -> f64 {
+        let rect = Rect::new(0, frame.rows() / 2, frame.cols(), frame.rows() / 2);
+        let band = Mat::roi(frame, rect).unwrap();
+        let solid =
+            Mat::new_rows_cols_with_default(rect.height, rect.width, CV_8UC3, Scalar::all(128.))
+                .unwrap();
+        core::norm2(&*band, &solid, core::NORM_INF, &core::no_array()).unwrap()
+    }
+
+    #[test]
+    fn active_until() {
+        let mut until = None;
+        assert!(!active(&mut until));
+        until = Some(Instant::now() + Duration::from_secs(60));
+        assert!(active(&mut until));
+        until = Some(Instant::now());
+        assert!(!active(&mut until));
+        assert_eq!(until, None);
+    }
+
+    #[test]
+    fn parse_schedule() {
+        let config: FaultsConfig = toml::from_str(
+            r#"
+            enabled = true
+            schedule = [
+                { after = 30.0, fault = "disconnect", seconds = 5.0 },
+                { after = 10.0, fault = "resolution", width = 640, height = 480 },
+                { after = 60.0, fault = "clear" },
+            ]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.schedule.len(), 3);
+        assert!(matches!(
+            config.schedule[1].fault,
+            Fault::Resolution {
+                width: 640,
+                height: 480
+            }
+        ));
+        assert!(
+            toml::from_str::<FaultsConfig>("schedule = [{ after = 1.0, fault = \"melt\" }]")
+                .is_err()
+        );
+    }
+
+    // 注入的故障是全局的, 按顺序在一个测试中检查.
+    #[test]
+    fn injects_faults() {
+        let config = FaultsConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        let mut source = wrap(solid(), &config);
+        assert_eq!(damage(&read(&mut source).unwrap()), 0.0);
+
+        inject(&Fault::Corrupt { frames: 1 });
+        assert!(damage(&read(&mut source).unwrap()) > 0.0);
+        assert_eq!(damage(&read(&mut source).unwrap()), 0.0);
+
+        inject(&Fault::Resolution {
+            width: 32,
+            height: 24,
+        });
+        assert_eq!(
+            read(&mut source).unwrap().size().unwrap(),
+            Size::new(32, 24)
+        );
+        inject(&Fault::Clear);
+        assert_eq!(
+            read(&mut source).unwrap().size().unwrap(),
+            Size::new(64, 48)
+        );
+
+        inject(&Fault::Timeout { seconds: 60.0 });
+        let err = read(&mut source).unwrap_err();
+        assert_eq!(err.to_string(), "injected read timeout");
+        assert!(check_open(&config).is_ok());
+        inject(&Fault::Clear);
+
+        // 断开期间重新打开也失败, 没有启用时不影响打开.
+        inject(&Fault::Disconnect { seconds: 60.0 });
+        assert_eq!(
+            read(&mut source).unwrap_err().to_string(),
+            "injected disconnect"
+        );
+        assert!(check_open(&config).is_err());
+        assert!(check_open(&FaultsConfig::default()).is_ok());
+        inject(&Fault::Disconnect { seconds: 0.0 });
+        assert!(check_open(&config).is_ok());
+
+        // 按 schedule 在后台注入.
+        let scheduled = FaultsConfig {
+            enabled: true,
+            schedule: vec![ScheduledFault {
+                after: 0.0,
+                fault: Fault::Corrupt { frames: 3 },
+            }],
+        };
+        let shutdown = Shutdown::default();
+        start(&scheduled, &shutdown).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while INJECTED.lock().unwrap().corrupt != 3 {
+            assert!(Instant::now() < deadline, "timed out");
+            sleep(Duration::from_millis(10));
+        }
+        shutdown.stop();
+        inject(&Fault::Clear);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{
+    cell::RefCell,
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread::JoinHandle,
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use opencv::core::Mat;
+use slint::{Image, Weak};
+
+use crate::{
+    auth::Auth,
+    captions::Captions,
+    capture::{Capture, Command},
+    config::Config,
+    effects::Effect,
+    events::{Event, EventStore},
+    frames::{FrameBuffer, FrameSlot},
+    gps::Gps,
+    privacy,
+    profile::CameraProfile,
+    session::Replay,
+    sink, source,
+    telemetry::Telemetry,
+    thumbnail::Thumbnail,
+    Main,
+};
+
+// 传给帧回调的处理后的帧. index 从 0 开始, 切换来源后继续累加.
+pub struct Frame<'a> {
+    pub index: u64,
+    pub time: DateTime<Local>,
+    // BGR 图像, 已经过处理管线, 还没有绘制 OSD.
+    pub image: &'a Mat,
+}
+
+// 采集线程当前使用的来源和处理阶段, 来源或管线改变时更新.
+#[derive(Debug, Clone, Default)]
+pub struct Active {
+    pub source: String,
+    pub pipeline: Vec<String>,
+    // 当前场景, 没有切换过场景时为 None.
+    pub scene: Option<String>,
+    // 主 sinks 正在录制.
+    pub recording: bool,
+}
+
+type FrameCallback = Box<dyn FnMut(&Frame) + Send>;
+
+// 处理后的帧回调, 在采集线程中依次调用.
+#[derive(Clone, Default)]
+pub struct FrameCallbacks(Arc<Mutex<Vec<FrameCallback>>>);
+
+impl FrameCallbacks {
+    pub fn add(&self, callback: impl FnMut(&Frame) + Send + 'static) {
+        self.0.lock().unwrap().push(Box::new(callback));
+    }
+
+    pub fn call(&self, frame: &Frame) {
+        for callback in self.0.lock().unwrap().iter_mut() {
+            callback(frame);
+        }
+    }
+}
+
+// 嵌入到其他 Slint 应用时使用的视频句柄: 在后台线程中采集和处理,
+// 在 UI 线程中通过 image() 取得最新一帧显示到 VideoView.
+pub struct VideoFeed {
+    commands: Sender<Command>,
+    frames: FrameSlot,
+    // 正在显示的一帧, 换成新帧后还给采集线程.
+    latest: RefCell<Option<FrameBuffer>>,
+    callbacks: FrameCallbacks,
+    events: EventStore,
+    active: Arc<Mutex<Active>>,
+    thumbnail: Thumbnail,
+    captions: Captions,
+    telemetry: Telemetry,
+    gps: Option<Gps>,
+    task: RefCell<Option<JoinHandle<Result<()>>>>,
+    fps: f64,
+}
+
+impl VideoFeed {
+    // 使用配置中的事件文件, 状态只打印到终端.
+    pub fn open(config: Config, uri: &str) -> Result<Self> {
+        let events = EventStore::open(&config.events_file)?;
+        Self::start(config, uri, events, Weak::default(), None)
+    }
+
+    // 状态显示在 window 的状态栏, 回放会话时 replay 提供命令和 camera profile.
+    pub(crate) fn start(
+        config: Config,
+        uri: &str,
+        events: EventStore,
+        window: Weak<Main>,
+        replay: Option<Replay>,
+    ) -> Result<Self> {
+        privacy::open_audit(&config.audit_file)?;
+        let source = source::open(uri, &config)?;
+        let metadata = source.metadata().clone();
+        let profile_dir = CameraProfile::dir(&config.profiles_dir, &metadata.profile);
+        let profile = match &replay {
+            Some(replay) => replay.profile.clone(),
+            None => CameraProfile::load(&profile_dir)?,
+        };
+        let preview_osd = config.osd.profile(&config.osd.preview)?;
+        let recording_osd = config.osd.profile(&config.osd.recording)?;
+        let auth = Auth::new(&config.auth)?;
+        let captions = Captions::start(&config.captions, &auth)?;
+        let telemetry = Telemetry::start(&config.telemetry, &auth)?;
+        let gps = Gps::start(&config.gps, &auth)?;
+        let fps = metadata.fps;
+        let callbacks = FrameCallbacks::default();
+        let active = Arc::new(Mutex::new(Active {
+            source: uri.to_string(),
+            pipeline: Vec::new(),
+            scene: None,
+            recording: false,
+        }));
+
+        // 命令 channel, 退出时通过它优雅关闭, 确保文件和 camera 对象被正常关闭, 否则 mp4 文件不完整
+        let (command_sender, command_receiver) = channel();
+        let frames = FrameSlot::default();
+        let thumbnail = Thumbnail::default();
+        let task = Capture {
+            source,
+            frame_width: metadata.width,
+            frame_height: metadata.height,
+            fps,
+            playback_fps: None,
+            preview_osd,
+            recording_osd,
+            captions: captions.clone(),
+            telemetry: telemetry.clone(),
+            gps: gps.clone(),
+            profile,
+            profile_dir,
+            config,
+            events: events.clone(),
+            window,
+            replay,
+            callbacks: callbacks.clone(),
+            uri: uri.to_string(),
+            active: active.clone(),
+            thumbnail: thumbnail.clone(),
+            models_ready: Arc::default(),
+        }
+        .start(frames.clone(), command_receiver);
+        Ok(Self {
+            commands: command_sender,
+            frames,
+            latest: RefCell::new(None),
+            callbacks,
+            events,
+            active,
+            thumbnail,
+            captions,
+            telemetry,
+            gps,
+            task: RefCell::new(Some(task)),
+            fps,
+        })
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    // 当前的来源 URI 和处理阶段.
+    pub fn active(&self) -> Active {
+        self.active.lock().unwrap().clone()
+    }
+
+    // 在其他线程中读取 active(), 例如远程控制接口.
+    pub(crate) fn shared_active(&self) -> Arc<Mutex<Active>> {
+        self.active.clone()
+    }
+
+    // [thumbnail] 启用时定期更新的缩略图.
+    pub(crate) fn thumbnail(&self) -> Thumbnail {
+        self.thumbnail.clone()
+    }
+
+    // 用于在其他线程或回调中发送命令.
+    pub(crate) fn commands(&self) -> Sender<Command> {
+        self.commands.clone()
+    }
+
+    pub(crate) fn send(&self, command: Command) {
+        let _ = self.commands.send(command);
+    }
+
+    // 切换视频来源, 例如 "camera://1" 或 "rtsp://...", camera profile 和处理管线随之重新加载.
+    pub fn set_source(&self, uri: &str) {
+        self.send(Command::SetSource(uri.to_string()));
+    }
+
+    // 切换到 [[scenes]] 中的场景, 来源, 裁剪, 效果和 OSD 一起改变.
+    pub fn set_scene(&self, name: &str) {
+        self.send(Command::Scene(name.to_string()));
+    }
+
+    // 开始或停止录制到配置的 sinks, 每次开始录制生成新的文件.
+    pub fn record(&self, enabled: bool) {
+        self.send(Command::Record(enabled));
+    }
+
+    // 按名称设置处理阶段, 例如 ["white-balance", "lanes"].
+    pub fn set_pipeline(&self, stages: &[&str]) {
+        let stages = stages.iter().map(|stage| stage.to_string()).collect();
+        self.send(Command::Pipeline(stages));
+    }
+
+    // 设置画面效果, 按顺序执行, 为空时关闭.
+    pub fn set_effects(&self, effects: &[Effect]) {
+        self.send(Command::Effects(effects.to_vec()));
+    }
+
+    // 每个处理后的帧都会在采集线程中传给 callback, 不要在其中做耗时的操作.
+    pub fn on_frame(&self, callback: impl FnMut(&Frame) + Send + 'static) {
+        self.callbacks.add(callback);
+    }
+
+    // 由调用者控制开始和停止的录制, 见 recorder 模块.
+    #[cfg(feature = "record")]
+    pub fn recorder(&self) -> crate::recorder::Recorder {
+        let (recorder, callback) = crate::recorder::Recorder::new(self.fps, self.events.clone());
+        self.callbacks.add(callback);
+        recorder
+    }
+
+    // 外部数据的输入, 可以在任意线程中 push 样本, 见 telemetry 模块.
+    pub fn telemetry(&self) -> Telemetry {
+        self.telemetry.clone()
+    }
+
+    // 检测事件 (速度, 区域停留, 健康告警等), 在产生事件的线程中调用.
+    pub fn on_event(&self, callback: impl FnMut(&Event) + Send + 'static) {
+        self.events.on_event(callback);
+    }
+
+    // 最新一帧, 没有新帧时返回上一帧. 需要在 UI 线程中调用.
+    pub fn image(&self) -> Image {
+        let mut latest = self.latest.borrow_mut();
+        if let Some(buffer) = self.frames.take() {
+            if let Some(shown) = latest.replace(buffer) {
+                self.frames.recycle(shown);
+            }
+        }
+        match &*latest {
+            Some(buffer) => Image::from_rgba8(buffer.clone()),
+            None => Image::default(),
+        }
+    }
+
+    // 停止采集线程, 等待录像文件关闭.
+    pub fn stop(&self) -> Result<()> {
+        let Some(task) = self.task.borrow_mut().take() else {
+            return Ok(());
+        };
+        self.send(Command::Exit);
+        self.captions.stop();
+        self.telemetry.stop();
+        if let Some(gps) = &self.gps {
+            gps.stop();
+        }
+        let result = task.join().unwrap();
+        sink::wait_finished();
+        result
+    }
+}
+
+impl Drop for VideoFeed {
+    fn drop(&mut self) {
+        if let Err(err) = self.stop() {
+            eprintln!("{:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+        thread::sleep,
+        time::{Duration, Instant},
+    };
+
+    use opencv::prelude::*;
+
+    use super::*;
+
+    const SOURCE: &str = "synthetic://64x48@30";
+
+    fn config(dir: &Path) -> Config {
+        Config {
+            events_file: dir.join("events.jsonl"),
+            audit_file: dir.join("audit.jsonl"),
+            profiles_dir: dir.join("profiles"),
+            ..Default::default()
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn wait_until(mut done: impl FnMut() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !done() {
+            assert!(Instant::now() < deadline, "timed out");
+            sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn video_feed() {
+        let dir = temp_dir("feed-test");
+        let feed = VideoFeed::open(config(&dir), SOURCE).unwrap();
+        assert_eq!(feed.fps(), 30.0);
+        // 没有新帧时保留上一帧.
+        wait_until(|| feed.image().size().width > 0);
+        assert_eq!(feed.image().size().height, 48);
+
+        feed.set_pipeline(&["tamper"]);
+        wait_until(|| feed.active().pipeline == ["tamper"]);
+        assert_eq!(feed.active().source, SOURCE);
+        feed.stop().unwrap();
+        // 重复停止没有影响.
+        feed.stop().unwrap();
+        drop(feed);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn subscriptions() {
+        let dir = temp_dir("feed-subscriptions");
+        let feed = VideoFeed::open(config(&dir), SOURCE).unwrap();
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let seen = frames.clone();
+        feed.on_frame(move |frame| {
+            seen.lock()
+                .unwrap()
+                .push((frame.index, frame.image.cols(), frame.image.rows()));
+        });
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+        feed.on_event(move |event| seen.lock().unwrap().push(event.kind.clone()));
+
+        wait_until(|| frames.lock().unwrap().len() >= 3);
+        feed.events.log("speed", serde_json::json!({"max": 12.0}));
+        feed.stop().unwrap();
+        // 帧号连续递增.
+        let frames = frames.lock().unwrap();
+        for pair in frames.windows(2) {
+            assert_eq!(pair[1].0, pair[0].0 + 1);
+        }
+        assert_eq!((frames[0].1, frames[0].2), (64, 48));
+        assert!(events.lock().unwrap().iter().any(|kind| kind == "speed"));
+        drop(feed);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
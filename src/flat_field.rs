@@ -0,0 +1,229 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{self, Mat, Scalar, Size, Vector, CV_16U, CV_32F, CV_32FC3, CV_8U},
+    imgcodecs, imgproc,
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+pub const FLAT_FIELD_FILE: &str = "flat-field.png";
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlatFieldConfig {
+    // 参考帧平均的帧数.
+    pub frames: u32,
+    // 参考帧的高斯模糊核大小 (奇数), 去掉噪声和纹理只保留亮度分布.
+    pub blur: i32,
+}
+
+impl Default for FlatFieldConfig {
+    fn default() -> Self {
+        Self {
+            frames: 16,
+            blur: 31,
+        }
+    }
+}
+
+// 对着均匀照明的白色目标平均若干帧, 得到平场参考帧.
+pub struct FlatFrames {
+    remaining: u32,
+    count: u32,
+    blur: i32,
+    sum: Mat,
+}
+
+impl FlatFrames {
+    pub fn new(config: &FlatFieldConfig) -> Self {
+        Self {
+            remaining: config.frames.max(1),
+            count: 0,
+            blur: config.blur | 1,
+            sum: Mat::default(),
+        }
+    }
+
+    // 收集够帧数后把参考帧保存到 dir, 返回参考帧.
+    pub fn add(&mut self, frame: &Mat, dir: &Path) -> Result<Option<Mat>> {
+        if self.sum.empty() || self.sum.size()? != frame.size()? {
+            self.sum = Mat::new_rows_cols_with_default(
+                frame.rows(),
+                frame.cols(),
+                CV_32FC3,
+                Scalar::all(0.),
+            )?;
+            self.count = 0;
+        }
+        imgproc::accumulate(frame, &mut self.sum, &core::no_array())?;
+        self.count += 1;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            return Ok(None);
+        }
+
+        let mut mean = Mat::default();
+        self.sum
+            .convert_to(&mut mean, CV_32F, 1.0 / self.count as f64, 0.0)?;
+        let mut flat = Mat::default();
+        imgproc::gaussian_blur(
+            &mean,
+            &mut flat,
+            Size::new(self.blur, self.blur),
+            0.0,
+            0.0,
+            core::BORDER_REPLICATE,
+        )?;
+        let mut gray = Mat::default();
+        imgproc::cvt_color(&flat, &mut gray, imgproc::COLOR_BGR2GRAY, 0)?;
+        let (mut min, mut max) = (0.0, 0.0);
+        core::min_max_loc(
+            &gray,
+            Some(&mut min),
+            Some(&mut max),
+            None,
+            None,
+            &core::no_array(),
+        )?;
+        if min < 10.0 {
+            bail!("reference is too dark, light it evenly and retry");
+        }
+        if max > 250.0 {
+            bail!("reference is overexposed, lower the exposure and retry");
+        }
+        save(&flat, dir)?;
+        Ok(Some(flat))
+    }
+}
+
+// 以 16 位 PNG 保存, 保留平均后的小数精度.
+fn save(flat: &Mat, dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(FLAT_FIELD_FILE);
+    let mut flat16 = Mat::default();
+    flat.convert_to(&mut flat16, CV_16U, 256.0, 0.0)?;
+    if !imgcodecs::imwrite(&path.to_string_lossy(), &flat16, &Vector::new())? {
+        bail!("write {}", path.display());
+    }
+    Ok(())
+}
+
+pub fn load(dir: &Path) -> Result<Mat> {
+    let path = dir.join(FLAT_FIELD_FILE);
+    let flat16 = imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_UNCHANGED)
+        .with_context(|| format!("read {}", path.display()))?;
+    if flat16.empty() {
+        bail!("missing flat-field reference {}", path.display());
+    }
+    let mut flat = Mat::default();
+    flat16.convert_to(&mut flat, CV_32F, 1.0 / 256.0, 0.0)?;
+    Ok(flat)
+}
+
+// 每帧乘以 mean / flat, 抵消暗角和不均匀照明.
+pub struct FlatFieldCorrection {
+    gain: Mat,
+    frame32: Mat,
+    corrected: Mat,
+}
+
+impl FlatFieldCorrection {
+    pub fn new(flat: &Mat) -> Result<Self> {
+        let mean = core::mean(flat, &core::no_array())?;
+        let mean = Mat::new_rows_cols_with_default(flat.rows(), flat.cols(), CV_32FC3, mean)?;
+        let mut gain = Mat::default();
+        core::divide2(&mean, flat, &mut gain, 1.0, -1)?;
+        Ok(Self {
+            gain,
+            frame32: Mat::default(),
+            corrected: Mat::default(),
+        })
+    }
+}
+
+impl FrameProcessor for FlatFieldCorrection {
+    fn name(&self) -> &str {
+        "flat-field"
+    }
+
+    fn order(&self) -> i32 {
+        -30
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        // 分辨率变化后参考帧不再适用.
+        if frame.size()? != self.gain.size()? {
+            return Ok(());
+        }
+        frame.convert_to(&mut self.frame32, CV_32F, 1.0, 0.0)?;
+        core::multiply(&self.frame32, &self.gain, &mut self.corrected, 1.0, -1)?;
+        self.corrected.convert_to(frame, CV_8U, 1.0, 0.0)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Vec3b, CV_8UC3};
+
+    use super::*;
+
+    // 左暗右亮的白色目标, 从 100 到 199.
+    fn vignetted() -> Mat {
+        let mut frame = Mat::new_rows_cols_with_default(50, 100, CV_8UC3, Scalar::all(0.)).unwrap();
+        for y in 0..50 {
+            for x in 0..100 {
+                *frame.at_2d_mut::<Vec3b>(y, x).unwrap() = Vec3b::all(100 + x as u8);
+            }
+        }
+        frame
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("flat-field-{}-{}", name, std::process::id()))
+    }
+
+    // 校正后整个画面的亮度接近均匀, 参考帧保存后可以重新加载.
+    #[test]
+    fn evens_out_vignetting() {
+        let dir = temp_dir("even");
+        let config = FlatFieldConfig { frames: 2, blur: 3 };
+        let mut frames = FlatFrames::new(&config);
+        assert!(frames.add(&vignetted(), &dir).unwrap().is_none());
+        let flat = frames.add(&vignetted(), &dir).unwrap().unwrap();
+        let loaded = load(&dir).unwrap();
+        let mut diff = Mat::default();
+        core::absdiff(&flat, &loaded, &mut diff).unwrap();
+        let mut max = 0.0;
+        core::min_max_loc(&diff, None, Some(&mut max), None, None, &core::no_array()).unwrap();
+        assert!(max < 0.01, "{}", max);
+
+        let mut correction = FlatFieldCorrection::new(&loaded).unwrap();
+        let mut frame = vignetted();
+        correction.process(&mut frame).unwrap();
+        for x in [0, 50, 99] {
+            let value = frame.at_2d::<Vec3b>(25, x).unwrap()[1];
+            assert!((148..=151).contains(&value), "{} at {}", value, x);
+        }
+        // 分辨率不同时不处理.
+        let mut small = Mat::new_rows_cols_with_default(10, 10, CV_8UC3, Scalar::all(60.)).unwrap();
+        correction.process(&mut small).unwrap();
+        assert_eq!(*small.at_2d::<Vec3b>(5, 5).unwrap(), Vec3b::all(60));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn unusable_reference() {
+        let dir = temp_dir("dark");
+        let config = FlatFieldConfig { frames: 1, blur: 3 };
+        let dark = Mat::new_rows_cols_with_default(20, 20, CV_8UC3, Scalar::all(5.)).unwrap();
+        assert!(FlatFrames::new(&config).add(&dark, &dir).is_err());
+        let white = Mat::new_rows_cols_with_default(20, 20, CV_8UC3, Scalar::all(255.)).unwrap();
+        assert!(FlatFrames::new(&config).add(&white, &dir).is_err());
+        assert!(load(&dir).is_err());
+    }
+}
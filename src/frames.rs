@@ -0,0 +1,196 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, CV_8UC4},
+    imgproc::{self, cvt_color, COLOR_BGR2RGBA, INTER_AREA},
+    prelude::*,
+};
+use slint::{Rgba8Pixel, SharedPixelBuffer};
+
+// 采集线程发送给界面的 RGBA 帧.
+pub type FrameBuffer = SharedPixelBuffer<Rgba8Pixel>;
+
+// 循环使用的缓冲区: 界面正在显示的一帧, 等待显示的一帧和采集线程正在写入的一帧.
+pub(crate) const POOL_SIZE: usize = 3;
+
+// 界面来不及显示而丢弃的帧数.
+pub static DROPPED_FRAMES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Default)]
+struct Slots {
+    latest: Option<FrameBuffer>,
+    free: VecDeque<FrameBuffer>,
+}
+
+impl Slots {
+    fn recycle(&mut self, buffer: FrameBuffer) {
+        if self.free.len() < POOL_SIZE {
+            self.free.push_back(buffer);
+        }
+    }
+}
+
+// 把 BGR 帧转换为 RGBA 写入尺寸相同的缓冲区.
+// 缓冲区仍被界面上的 Image 引用时 make_mut_bytes 会先复制一份, 不会改写正在显示的画面.
+fn convert(frame: &Mat, buffer: &mut FrameBuffer) -> Result<()> {
+    let (width, height) = (frame.cols(), frame.rows());
+    let bytes = buffer.make_mut_bytes();
+    // SAFETY: bytes 是 height 行 width * 4 字节的连续内存, 在 target 使用期间不会移动或释放.
+    let mut target = unsafe {
+        Mat::new_rows_cols_with_data_unsafe(
+            height,
+            width,
+            CV_8UC4,
+            bytes.as_mut_ptr().cast(),
+            core::Mat_AUTO_STEP,
+        )?
+    };
+    cvt_color(frame, &mut target, COLOR_BGR2RGBA, 0)?;
+    Ok(())
+}
+
+// 缩小到 width 宽的 RGBA 图像, 用于界面上的小图.
+pub fn thumbnail(frame: &Mat, width: i32) -> Result<FrameBuffer> {
+    let scale = (width as f64 / frame.cols() as f64).min(1.0);
+    let mut small = Mat::default();
+    imgproc::resize(
+        frame,
+        &mut small,
+        core::Size::new(0, 0),
+        scale,
+        scale,
+        INTER_AREA,
+    )?;
+    let mut buffer = FrameBuffer::new(small.cols() as u32, small.rows() as u32);
+    convert(&small, &mut buffer)?;
+    Ok(buffer)
+}
+
+// 采集线程和界面之间的帧传递, 只保留最新一帧: 界面跟不上时丢弃旧帧而不是排队.
+// 缓冲区在两个线程之间循环使用, 采集线程把像素直接转换到缓冲区中, 界面不再复制.
+#[derive(Clone, Default)]
+pub struct FrameSlot(Arc<Mutex<Slots>>);
+
+impl FrameSlot {
+    // 把 BGR 帧转换为 RGBA 写入空闲的缓冲区, 替换还没有显示的上一帧.
+    pub fn publish(&self, frame: &Mat) -> Result<()> {
+        let mut buffer = self.buffer(frame.cols() as u32, frame.rows() as u32);
+        convert(frame, &mut buffer)?;
+        let mut slots = self.0.lock().unwrap();
+        if let Some(stale) = slots.latest.replace(buffer) {
+            DROPPED_FRAMES.fetch_add(1, Ordering::Relaxed);
+            slots.recycle(stale);
+        }
+        Ok(())
+    }
+
+    // 先用最早归还的缓冲区, 此时界面通常已经不再引用它. 尺寸改变时重新分配.
+    fn buffer(&self, width: u32, height: u32) -> FrameBuffer {
+        let mut slots = self.0.lock().unwrap();
+        while let Some(buffer) = slots.free.pop_front() {
+            if buffer.width() == width && buffer.height() == height {
+                return buffer;
+            }
+        }
+        FrameBuffer::new(width, height)
+    }
+
+    // 取走最新一帧, 没有新帧时返回 None.
+    pub fn take(&self) -> Option<FrameBuffer> {
+        self.0.lock().unwrap().latest.take()
+    }
+
+    // 不再显示的缓冲区还给采集线程.
+    pub fn recycle(&self, buffer: FrameBuffer) {
+        self.0.lock().unwrap().recycle(buffer);
+    }
+
+    // 空闲的缓冲区数量.
+    #[cfg(test)]
+    pub(crate) fn pooled(&self) -> usize {
+        self.0.lock().unwrap().free.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    // BGR (10, 20, 30).
+    fn frame(cols: i32, rows: i32) -> Mat {
+        Mat::new_rows_cols_with_default(rows, cols, CV_8UC3, Scalar::new(10., 20., 30., 0.))
+            .unwrap()
+    }
+
+    #[test]
+    fn converts_to_rgba() {
+        let slot = FrameSlot::default();
+        assert!(slot.take().is_none());
+        slot.publish(&frame(4, 2)).unwrap();
+        let buffer = slot.take().unwrap();
+        assert_eq!((buffer.width(), buffer.height()), (4, 2));
+        assert_eq!(&buffer.as_bytes()[..4], [30, 20, 10, 255]);
+        assert!(slot.take().is_none());
+    }
+
+    // 没有显示的旧帧被替换并计入丢帧, 它的缓冲区回到空闲列表.
+    #[test]
+    fn keeps_newest_frame() {
+        let slot = FrameSlot::default();
+        let dropped = DROPPED_FRAMES.load(Ordering::Relaxed);
+        slot.publish(&frame(4, 2)).unwrap();
+        let mut newer = frame(4, 2);
+        *newer.at_2d_mut::<Vec3b>(0, 0).unwrap() = Vec3b::all(200);
+        slot.publish(&newer).unwrap();
+        // 其他测试也会丢帧, 只检查增加了.
+        assert!(DROPPED_FRAMES.load(Ordering::Relaxed) > dropped);
+        assert_eq!(slot.pooled(), 1);
+        let buffer = slot.take().unwrap();
+        assert_eq!(&buffer.as_bytes()[..4], [200, 200, 200, 255]);
+    }
+
+    // 归还的缓冲区被复用, 尺寸改变时重新分配, 空闲列表不超过 POOL_SIZE.
+    #[test]
+    fn recycles_buffers() {
+        let slot = FrameSlot::default();
+        for _ in 0..POOL_SIZE + 2 {
+            slot.recycle(FrameBuffer::new(4, 2));
+        }
+        assert_eq!(slot.pooled(), POOL_SIZE);
+        slot.publish(&frame(4, 2)).unwrap();
+        assert_eq!(slot.pooled(), POOL_SIZE - 1);
+        slot.take().unwrap();
+        slot.publish(&frame(8, 4)).unwrap();
+        assert_eq!(slot.pooled(), 0);
+        assert_eq!(slot.take().unwrap().width(), 8);
+    }
+
+    #[test]
+    fn thumbnail_width() {
+        let small = thumbnail(&frame(64, 48), 16).unwrap();
+        assert_eq!((small.width(), small.height()), (16, 12));
+        // 不放大.
+        let same = thumbnail(&frame(64, 48), 128).unwrap();
+        assert_eq!((same.width(), same.height()), (64, 48));
+    }
+
+    // 保存照片后显示的小图也是 RGBA, 宽度为奇数时按比例取整.
+    #[test]
+    fn thumbnail_pixels() {
+        let small = thumbnail(&frame(64, 48), 33).unwrap();
+        assert_eq!((small.width(), small.height()), (33, 25));
+        assert!(small
+            .as_bytes()
+            .chunks(4)
+            .all(|pixel| pixel == [30, 20, 10, 255]));
+    }
+}
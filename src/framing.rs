@@ -0,0 +1,342 @@
+use std::{
+    mem,
+    path::PathBuf,
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{Mat, Rect, Scalar, Size},
+    imgproc::{self, INTER_LINEAR},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    detect::Detector,
+    pipeline::FrameProcessor,
+    threads::{self, ThreadConfig},
+};
+
+// 自动取景: 检测人脸或人物, 平滑地裁剪和缩放, 使主体保持在画面中央.
+// 多人讨论时可以把每个人裁剪到网格中的一格.
+// 在处理管线中执行, 预览, 录像和虚拟摄像头输出都是取景后的画面.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FramingConfig {
+    pub enabled: bool,
+    // 检测模型名称 (见 [models]), 不设置时使用 cascade 指定的 Haar 级联分类器.
+    pub model: Option<String>,
+    // 使用检测模型时只跟随该类别 ID, 例如 person.
+    pub class: Option<usize>,
+    pub cascade: PathBuf,
+    // 每秒最多检测的帧数, 检测在后台线程运行.
+    pub fps: f64,
+    pub min_score: f64,
+    // 主体高度占取景高度的比例, 越大放大越多.
+    pub size: f64,
+    // 主体上方留出的空间, 占取景高度的比例.
+    pub headroom: f64,
+    // 最大放大倍数.
+    pub max_zoom: f64,
+    // 取景跟随主体的时间常数 (秒), 越大越平缓.
+    pub smoothing: f64,
+    // 主体中心偏离取景中心不超过该比例时不移动, 避免小动作引起晃动.
+    pub deadzone: f64,
+    // 主体消失多少秒后缩回全画面.
+    pub lost: f64,
+    // grid: 多人时每人一格, 按从左到右的顺序排成网格.
+    pub layout: Layout,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    Single,
+    Grid,
+}
+
+impl Default for FramingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            class: None,
+            cascade: PathBuf::from("haarcascade_frontalface_default.xml"),
+            fps: 5.0,
+            min_score: 0.5,
+            size: 0.3,
+            headroom: 0.15,
+            max_zoom: 2.0,
+            smoothing: 0.8,
+            deadzone: 0.05,
+            lost: 3.0,
+            layout: Layout::Single,
+        }
+    }
+}
+
+// 后台线程最近一次的检测结果和检测时间, 取走后为 None.
+type Found = Option<(Vec<Rect>, Instant)>;
+
+// 平滑移动的取景 [x, y, 宽, 高], 像素.
+#[derive(Default)]
+struct View {
+    current: Option<[f64; 4]>,
+    target: Option<[f64; 4]>,
+}
+
+impl View {
+    // 目标取景变化不大时保持不动.
+    fn retarget(&mut self, target: [f64; 4], deadzone: f64) {
+        if let Some([x, y, w, h]) = self.target {
+            let deadzone = deadzone * w;
+            let moved = (target[0] + target[2] / 2.0 - x - w / 2.0).abs() > deadzone
+                || (target[1] + target[3] / 2.0 - y - h / 2.0).abs() > deadzone
+                || (target[3] - h).abs() > deadzone;
+            if !moved {
+                return;
+            }
+        }
+        self.target = Some(target);
+    }
+
+    // 指数平滑, 与帧率无关. 新的取景直接跳到目标.
+    fn step(&mut self, alpha: f64) -> Rect {
+        let target = self.target.unwrap_or_default();
+        let mut current = self.current.unwrap_or(target);
+        for (value, target) in current.iter_mut().zip(target) {
+            *value += (target - *value) * alpha;
+        }
+        self.current = Some(current);
+        let [x, y, w, h] = current;
+        Rect::new(x as i32, y as i32, w as i32, h as i32)
+    }
+}
+
+// 跨检测结果跟随的一个人, 网格中的格子随人移动.
+struct Face {
+    rect: Rect,
+    seen: Instant,
+    view: View,
+}
+
+pub struct AutoFraming {
+    config: FramingConfig,
+    jobs: SyncSender<Mat>,
+    found: Arc<Mutex<Found>>,
+    interval: Duration,
+    last_detect: Option<Instant>,
+    last_frame: Option<Instant>,
+    faces: Vec<Face>,
+    // 单人取景, 多人时包含所有人.
+    view: View,
+    scratch: Mat,
+}
+
+impl AutoFraming {
+    pub fn new(
+        config: &FramingConfig,
+        mut detector: Box<dyn Detector>,
+        workers: &ThreadConfig,
+    ) -> Result<Self> {
+        let found = Arc::new(Mutex::new(None));
+        let results = found.clone();
+        let (class, min_score) = (config.class, config.min_score);
+        let jobs = threads::worker("framing", 1, workers, move |image: Mat| {
+            let rects = detector
+                .detect(&image)?
+                .iter()
+                .filter(|d| d.score >= min_score)
+                .filter(|d| class.map_or(true, |class| d.class == class))
+                .map(|d| d.rect)
+                .collect();
+            *results.lock().unwrap() = Some((rects, Instant::now()));
+            Ok(())
+        })?;
+        Ok(Self {
+            config: config.clone(),
+            jobs,
+            found,
+            interval: Duration::from_secs_f64(1.0 / config.fps.max(0.1)),
+            last_detect: None,
+            last_frame: None,
+            faces: Vec::new(),
+            view: View::default(),
+            scratch: Mat::default(),
+        })
+    }
+
+    // 把新的检测框对应到已有的人: 中心距离小于人脸宽度时认为是同一个人.
+    fn update_faces(&mut self) {
+        if let Some((rects, time)) = self.found.lock().unwrap().take() {
+            let mut matched = vec![false; self.faces.len()];
+            for rect in rects {
+                let center = |r: &Rect| {
+                    (
+                        r.x as f64 + r.width as f64 / 2.0,
+                        r.y as f64 + r.height as f64 / 2.0,
+                    )
+                };
+                let (cx, cy) = center(&rect);
+                let nearest = self
+                    .faces
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| !matched[*index])
+                    .map(|(index, face)| {
+                        let (fx, fy) = center(&face.rect);
+                        (index, (fx - cx).hypot(fy - cy), face.rect.width)
+                    })
+                    .filter(|(_, distance, width)| *distance < (*width).max(rect.width) as f64)
+                    .min_by(|a, b| a.1.total_cmp(&b.1));
+                match nearest {
+                    Some((index, _, _)) => {
+                        matched[index] = true;
+                        self.faces[index].rect = rect;
+                        self.faces[index].seen = time;
+                    }
+                    None => {
+                        matched.push(true);
+                        self.faces.push(Face {
+                            rect,
+                            seen: time,
+                            view: View::default(),
+                        });
+                    }
+                }
+            }
+        }
+        let lost = self.config.lost;
+        self.faces
+            .retain(|face| face.seen.elapsed().as_secs_f64() < lost);
+        self.faces
+            .sort_by_key(|face| face.rect.x + face.rect.width / 2);
+    }
+
+    // 包含主体, 宽高比为 aspect, 不超出画面的取景.
+    fn frame_subject(&self, subject: Rect, aspect: f64, width: f64, height: f64) -> [f64; 4] {
+        let config = &self.config;
+        let min_height = height / config.max_zoom.max(1.0);
+        let max_height = height.min(width / aspect);
+        let h = (subject.height as f64 / config.size.clamp(0.05, 1.0))
+            .clamp(min_height.min(max_height), max_height);
+        let w = h * aspect;
+        let x = subject.x as f64 + subject.width as f64 / 2.0 - w / 2.0;
+        let y = subject.y as f64 - config.headroom * h;
+        [x.clamp(0.0, width - w), y.clamp(0.0, height - h), w, h]
+    }
+
+    // 每人一格, 最后一行不满时居中, 其余部分为黑色.
+    fn compose_grid(&mut self, frame: &mut Mat, alpha: f64) -> Result<()> {
+        let size = frame.size()?;
+        let count = self.faces.len() as i32;
+        let columns = (count as f64).sqrt().ceil() as i32;
+        let rows = (count + columns - 1) / columns;
+        let tile = Size::new(size.width / columns, size.height / rows);
+        let aspect = tile.width as f64 / tile.height as f64;
+        let (width, height) = (size.width as f64, size.height as f64);
+        let targets: Vec<[f64; 4]> = self
+            .faces
+            .iter()
+            .map(|face| self.frame_subject(face.rect, aspect, width, height))
+            .collect();
+        let bounds = Rect::new(0, 0, size.width, size.height);
+        self.scratch = Mat::new_size_with_default(size, frame.typ(), Scalar::all(0.0))?;
+        for (index, (face, target)) in self.faces.iter_mut().zip(targets).enumerate() {
+            let (row, column) = (index as i32 / columns, index as i32 % columns);
+            let in_row = (count - row * columns).min(columns);
+            let offset = (columns - in_row) * tile.width / 2;
+            face.view.retarget(target, self.config.deadzone);
+            let source = face.view.step(alpha) & bounds;
+            if source.width <= 0 || source.height <= 0 {
+                continue;
+            }
+            let roi = Mat::roi(frame, source)?;
+            let cell = Rect::new(
+                offset + column * tile.width,
+                row * tile.height,
+                tile.width,
+                tile.height,
+            );
+            let mut cell = Mat::roi_mut(&mut self.scratch, cell)?;
+            imgproc::resize(&roi, &mut cell, tile, 0.0, 0.0, INTER_LINEAR)?;
+        }
+        mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+}
+
+impl FrameProcessor for AutoFraming {
+    fn name(&self) -> &str {
+        "framing"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let size = frame.size()?;
+        if size.width <= 0 || size.height <= 0 {
+            return Ok(());
+        }
+        // 在取景前的完整画面上检测.
+        let due = self
+            .last_detect
+            .map_or(true, |last| last.elapsed() >= self.interval);
+        if due {
+            match self.jobs.try_send(frame.try_clone()?) {
+                Ok(()) => self.last_detect = Some(Instant::now()),
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => bail!("framing thread exited"),
+            }
+        }
+        self.update_faces();
+
+        let now = Instant::now();
+        let elapsed = self
+            .last_frame
+            .map_or(0.0, |last| now.duration_since(last).as_secs_f64());
+        self.last_frame = Some(now);
+        let alpha = if self.config.smoothing > 0.0 {
+            1.0 - (-elapsed / self.config.smoothing).exp()
+        } else {
+            1.0
+        };
+
+        if self.config.layout == Layout::Grid && self.faces.len() > 1 {
+            // 回到单人取景时从全画面开始.
+            self.view = View::default();
+            return self.compose_grid(frame, alpha);
+        }
+        let (width, height) = (size.width as f64, size.height as f64);
+        let full = [0.0, 0.0, width, height];
+        let subject = self.faces.iter().map(|face| face.rect).reduce(|a, b| a | b);
+        match subject {
+            Some(rect) => {
+                let target = self.frame_subject(rect, width / height, width, height);
+                self.view.retarget(target, self.config.deadzone);
+            }
+            None => self.view.target = Some(full),
+        }
+        if self.view.current.is_none() {
+            self.view.current = Some(full);
+        }
+        let rect = self.view.step(alpha) & Rect::new(0, 0, size.width, size.height);
+        if rect.width <= 0 || rect.height <= 0 || rect.size() == size {
+            return Ok(());
+        }
+        {
+            let roi = Mat::roi(frame, rect)?;
+            imgproc::resize(&roi, &mut self.scratch, size, 0.0, 0.0, INTER_LINEAR)?;
+        }
+        mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+
+    // 在场景裁剪之后, 分析之前, 检测和跟踪只看到取景后的画面.
+    fn order(&self) -> i32 {
+        -4
+    }
+}
@@ -0,0 +1,543 @@
+use std::{
+    f64::consts::PI,
+    fs::{File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{self, Mat, Point, Rect, Scalar},
+    imgproc::{self, COLOR_BGR2GRAY, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+// 仪表读数预设: 在标定好的区域中读取指针表的指针角度或七段数码管的数字,
+// 按间隔把读数追加到 CSV 文件, 用于用摄像头监控没有数据接口的旧设备.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GaugeConfig {
+    pub enabled: bool,
+    // 读数间隔 (秒).
+    pub interval: f64,
+    // 读数追加到这个文件, 每行 time,meter,value,unit.
+    pub csv: PathBuf,
+    pub meters: Vec<Meter>,
+}
+
+impl Default for GaugeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 1.0,
+            csv: PathBuf::from("gauges.csv"),
+            meters: Vec::new(),
+        }
+    }
+}
+
+// 一个仪表, analog 和 segments 只能设置一个.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Meter {
+    pub name: String,
+    // 仪表在画面中的区域 [x, y, w, h] (像素).
+    pub roi: [i32; 4],
+    #[serde(default)]
+    pub unit: String,
+    pub analog: Option<Analog>,
+    pub segments: Option<Segments>,
+}
+
+// 指针表. 角度从 12 点方向顺时针计算 (度), 刻度在 min_angle 和 max_angle 之间线性变化.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Analog {
+    pub min_angle: f64,
+    pub max_angle: f64,
+    pub min_value: f64,
+    pub max_value: f64,
+    // 表盘中心 (区域内的像素), 默认为区域中心.
+    pub center: Option<[f64; 2]>,
+    // 指针长度 (像素), 默认为区域较短边的一半.
+    pub radius: Option<f64>,
+    // 指针比表盘暗 (白底黑针), 否则比表盘亮.
+    #[serde(default = "default_dark")]
+    pub dark: bool,
+}
+
+fn default_dark() -> bool {
+    true
+}
+
+// 七段数码管, 区域按 digits 平均分为每位数字一格.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Segments {
+    pub digits: usize,
+    // 小数点后的位数, 小数点本身不识别.
+    #[serde(default)]
+    pub decimals: u32,
+    // 笔段比背景暗 (液晶屏), 否则比背景亮 (LED).
+    #[serde(default)]
+    pub dark: bool,
+    // 二值化阈值 (0..255), 默认自动 (Otsu).
+    pub threshold: Option<f64>,
+    // 笔段区域中点亮的像素超过这个比例时认为笔段点亮.
+    #[serde(default = "default_fill")]
+    pub fill: f64,
+}
+
+fn default_fill() -> f64 {
+    0.5
+}
+
+// 七个笔段 a..g 在一格中的位置 [x0, y0, x1, y1], 取值为格子宽高的比例.
+pub const SEGMENTS: [[f64; 4]; 7] = [
+    [0.25, 0.0, 0.75, 0.12],
+    [0.78, 0.1, 1.0, 0.42],
+    [0.78, 0.58, 1.0, 0.9],
+    [0.25, 0.88, 0.75, 1.0],
+    [0.0, 0.58, 0.22, 0.9],
+    [0.0, 0.1, 0.22, 0.42],
+    [0.25, 0.44, 0.75, 0.56],
+];
+
+// 数字 0..9 点亮的笔段, 第 i 位对应 SEGMENTS[i].
+pub const DIGITS: [u8; 10] = [
+    0b0111111, 0b0000110, 0b1011011, 0b1001111, 0b1100110, 0b1101101, 0b1111101, 0b0000111,
+    0b1111111, 0b1101111,
+];
+
+// 笔段组合对应的字符, 全灭为空格, 只有 g 为负号.
+pub fn decode(mask: u8) -> Option<char> {
+    match mask {
+        0 => Some(' '),
+        0b1000000 => Some('-'),
+        // 有的数码管 7 和 9 多点亮一段.
+        0b0100111 => Some('7'),
+        0b1100111 => Some('9'),
+        _ => DIGITS
+            .iter()
+            .position(|digits| *digits == mask)
+            .map(|digit| char::from(b'0' + digit as u8)),
+    }
+}
+
+// 读出的字符转换为数值, 前面的空位忽略, 数字中间有空位或无法识别时返回 None.
+pub fn number(text: &str, decimals: u32) -> Option<f64> {
+    let text = text.trim_start();
+    if text.is_empty() || text.contains(' ') {
+        return None;
+    }
+    let value: i64 = text.parse().ok()?;
+    Some(value as f64 / 10f64.powi(decimals as i32))
+}
+
+// 指针角度对应的刻度值.
+pub fn scale(analog: &Analog, angle: f64) -> f64 {
+    let span = analog.max_angle - analog.min_angle;
+    if span == 0.0 {
+        return analog.min_value;
+    }
+    let t = (angle - analog.min_angle) / span;
+    analog.min_value + t * (analog.max_value - analog.min_value)
+}
+
+// 灰度图中指针的角度: 沿刻度范围内每半度的射线累加明暗, 取最像指针的方向.
+pub fn needle(gray: &Mat, analog: &Analog) -> Result<f64> {
+    let (w, h) = (gray.cols(), gray.rows());
+    let [cx, cy] = analog.center.unwrap_or([w as f64 / 2.0, h as f64 / 2.0]);
+    let radius = analog.radius.unwrap_or(w.min(h) as f64 / 2.0).max(4.0);
+    let steps = ((analog.max_angle - analog.min_angle).abs() * 2.0)
+        .ceil()
+        .max(1.0) as usize;
+    let mut best = (f64::MIN, analog.min_angle);
+    for step in 0..=steps {
+        let angle =
+            analog.min_angle + (analog.max_angle - analog.min_angle) * step as f64 / steps as f64;
+        let (dx, dy) = ((angle * PI / 180.0).sin(), -(angle * PI / 180.0).cos());
+        let (mut sum, mut count) = (0.0, 0);
+        // 中心附近的轴和边缘的刻度不计入.
+        let mut r = radius * 0.25;
+        while r <= radius * 0.85 {
+            let (x, y) = ((cx + dx * r).round() as i32, (cy + dy * r).round() as i32);
+            if (0..w).contains(&x) && (0..h).contains(&y) {
+                let value = *gray.at_2d::<u8>(y, x)? as f64;
+                sum += if analog.dark { 255.0 - value } else { value };
+                count += 1;
+            }
+            r += 1.0;
+        }
+        if count > 0 && sum / count as f64 > best.0 {
+            best = (sum / count as f64, angle);
+        }
+    }
+    Ok(best.1)
+}
+
+// 灰度图中七段数码管的数值, 无法识别时返回 None.
+pub fn read_segments(gray: &Mat, segments: &Segments) -> Result<Option<f64>> {
+    let mut lit = Mat::default();
+    let kind = if segments.dark {
+        imgproc::THRESH_BINARY_INV
+    } else {
+        imgproc::THRESH_BINARY
+    };
+    match segments.threshold {
+        Some(threshold) => imgproc::threshold(gray, &mut lit, threshold, 255.0, kind)?,
+        None => imgproc::threshold(gray, &mut lit, 0.0, 255.0, kind | imgproc::THRESH_OTSU)?,
+    };
+    let digits = segments.digits.max(1);
+    let cell = gray.cols() as f64 / digits as f64;
+    let height = gray.rows() as f64;
+    let mut text = String::new();
+    for digit in 0..digits {
+        let mut mask = 0u8;
+        for (i, [x0, y0, x1, y1]) in SEGMENTS.iter().enumerate() {
+            let rect = Rect::new(
+                (cell * (digit as f64 + x0)) as i32,
+                (height * y0) as i32,
+                ((cell * (x1 - x0)) as i32).max(1),
+                ((height * (y1 - y0)) as i32).max(1),
+            );
+            let fill = core::mean(&Mat::roi(&lit, rect)?, &core::no_array())?[0] / 255.0;
+            if fill > segments.fill {
+                mask |= 1 << i;
+            }
+        }
+        match decode(mask) {
+            Some(c) => text.push(c),
+            None => return Ok(None),
+        }
+    }
+    Ok(number(&text, segments.decimals))
+}
+
+struct Reading {
+    value: Option<f64>,
+    // 指针表读出的角度, 用于画出指针.
+    angle: Option<f64>,
+}
+
+pub struct GaugeReading {
+    meters: Vec<Meter>,
+    interval: Duration,
+    last: Option<Instant>,
+    csv: File,
+    gray: Mat,
+    readings: Vec<Reading>,
+}
+
+impl GaugeReading {
+    pub fn new(config: &GaugeConfig) -> Result<Self> {
+        if config.meters.is_empty() {
+            bail!("add at least one [[gauges.meters]] with a calibrated roi");
+        }
+        for meter in &config.meters {
+            if meter.analog.is_some() == meter.segments.is_some() {
+                bail!(
+                    "gauge {:?} needs exactly one of analog or segments",
+                    meter.name
+                );
+            }
+            if meter.roi[2] <= 0 || meter.roi[3] <= 0 {
+                bail!("gauge {:?} has an empty roi", meter.name);
+            }
+        }
+        let mut csv = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&config.csv)
+            .with_context(|| format!("open {}", config.csv.display()))?;
+        if csv.metadata()?.len() == 0 {
+            writeln!(csv, "time,meter,value,unit")?;
+        }
+        Ok(Self {
+            meters: config.meters.clone(),
+            interval: Duration::from_secs_f64(config.interval.max(0.0)),
+            last: None,
+            csv,
+            gray: Mat::default(),
+            readings: Vec::new(),
+        })
+    }
+
+    fn read(&self, meter: &Meter, rect: Rect) -> Result<Reading> {
+        let gray = Mat::roi(&self.gray, rect)?;
+        if let Some(analog) = &meter.analog {
+            let angle = needle(&gray, analog)?;
+            return Ok(Reading {
+                value: Some(scale(analog, angle)),
+                angle: Some(angle),
+            });
+        }
+        let value = match &meter.segments {
+            Some(segments) => read_segments(&gray, segments)?,
+            None => None,
+        };
+        Ok(Reading { value, angle: None })
+    }
+}
+
+// 区域限制在画面内, 完全在画面外时返回 None.
+fn clip(roi: [i32; 4], frame: &Mat) -> Option<Rect> {
+    let [x, y, w, h] = roi;
+    let (x0, y0) = (x.max(0), y.max(0));
+    let (x1, y1) = ((x + w).min(frame.cols()), (y + h).min(frame.rows()));
+    (x1 > x0 && y1 > y0).then(|| Rect::new(x0, y0, x1 - x0, y1 - y0))
+}
+
+impl FrameProcessor for GaugeReading {
+    fn name(&self) -> &str {
+        "gauges"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        let time = Local::now().to_rfc3339();
+        let mut readings = Vec::with_capacity(self.meters.len());
+        for meter in &self.meters {
+            let reading = match clip(meter.roi, frame) {
+                Some(rect) => self.read(meter, rect)?,
+                None => Reading {
+                    value: None,
+                    angle: None,
+                },
+            };
+            if let Some(value) = reading.value {
+                writeln!(self.csv, "{},{},{},{}", time, meter.name, value, meter.unit)?;
+            }
+            readings.push(reading);
+        }
+        self.readings = readings;
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 读不出数值的仪表区域画成红色.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        for (meter, reading) in self.meters.iter().zip(&self.readings) {
+            let Some(rect) = clip(meter.roi, frame) else {
+                continue;
+            };
+            let color = match reading.value {
+                Some(_) => Scalar::new(0., 255., 0., 0.),
+                None => Scalar::new(0., 0., 255., 0.),
+            };
+            imgproc::rectangle(frame, rect, color, 2, LINE_AA, 0)?;
+            if let (Some(angle), Some(analog)) = (reading.angle, &meter.analog) {
+                let [cx, cy] = analog
+                    .center
+                    .unwrap_or([rect.width as f64 / 2.0, rect.height as f64 / 2.0]);
+                let radius = analog
+                    .radius
+                    .unwrap_or(rect.width.min(rect.height) as f64 / 2.0);
+                let center = Point::new(rect.x + cx as i32, rect.y + cy as i32);
+                let tip = Point::new(
+                    center.x + (radius * (angle * PI / 180.0).sin()) as i32,
+                    center.y - (radius * (angle * PI / 180.0).cos()) as i32,
+                );
+                imgproc::line(frame, center, tip, color, 2, LINE_AA, 0)?;
+            }
+            let label = match reading.value {
+                Some(value) => format!("{} {:.2} {}", meter.name, value, meter.unit),
+                None => format!("{} ?", meter.name),
+            };
+            imgproc::put_text(
+                frame,
+                label.trim_end(),
+                Point::new(rect.x, rect.y - 6),
+                FONT_HERSHEY_SIMPLEX,
+                0.5,
+                color,
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{CV_8U, CV_8UC3};
+
+    use super::*;
+
+    // 270 度刻度, 0 到 10 bar.
+    fn analog() -> Analog {
+        Analog {
+            min_angle: -135.0,
+            max_angle: 135.0,
+            min_value: 0.0,
+            max_value: 10.0,
+            center: None,
+            radius: None,
+            dark: true,
+        }
+    }
+
+    // 白底上从 (100, 100) 画出黑色指针.
+    fn dial(frame: &mut Mat, angle: f64) {
+        let radians = angle.to_radians();
+        let tip = Point::new(
+            (100.0 + 90.0 * radians.sin()).round() as i32,
+            (100.0 - 90.0 * radians.cos()).round() as i32,
+        );
+        let black = Scalar::all(0.0);
+        imgproc::line(
+            frame,
+            Point::new(100, 100),
+            tip,
+            black,
+            3,
+            imgproc::LINE_8,
+            0,
+        )
+        .unwrap();
+    }
+
+    // 按 SEGMENTS 画出的数码管, 每格 40x70.
+    fn display(text: &str) -> Mat {
+        let (cell, height) = (40, 70);
+        let width = cell * text.len() as i32;
+        let mut display =
+            Mat::new_rows_cols_with_default(height, width, CV_8U, Scalar::all(0.0)).unwrap();
+        for (i, c) in text.chars().enumerate() {
+            let mask = match c {
+                '-' => 0b1000000,
+                ' ' => 0,
+                c => DIGITS[c.to_digit(10).unwrap() as usize],
+            };
+            for (bit, [x0, y0, x1, y1]) in SEGMENTS.iter().enumerate() {
+                if mask & (1 << bit) != 0 {
+                    let rect = Rect::new(
+                        cell * i as i32 + (cell as f64 * x0) as i32,
+                        (height as f64 * y0) as i32,
+                        (cell as f64 * (x1 - x0)).ceil() as i32,
+                        (height as f64 * (y1 - y0)).ceil() as i32,
+                    );
+                    let white = Scalar::all(255.0);
+                    imgproc::rectangle(&mut display, rect, white, -1, imgproc::LINE_8, 0).unwrap();
+                }
+            }
+        }
+        display
+    }
+
+    #[test]
+    fn decode_characters() {
+        assert_eq!(decode(0), Some(' '));
+        assert_eq!(decode(0b1000000), Some('-'));
+        assert_eq!(decode(DIGITS[8]), Some('8'));
+        assert_eq!(decode(0b0100111), Some('7'));
+        assert_eq!(decode(0b0000001), None);
+        assert_eq!(number("  123", 1), Some(12.3));
+        assert_eq!(number("-42", 0), Some(-42.0));
+        assert_eq!(number("1 2", 0), None);
+        assert_eq!(number("   ", 0), None);
+    }
+
+    #[test]
+    fn analog_scale() {
+        let analog = analog();
+        assert_eq!(scale(&analog, -135.0), 0.0);
+        assert_eq!(scale(&analog, 0.0), 5.0);
+        assert_eq!(scale(&analog, 135.0), 10.0);
+        let flat = Analog {
+            max_angle: -135.0,
+            ..analog
+        };
+        assert_eq!(scale(&flat, 40.0), 0.0);
+    }
+
+    #[test]
+    fn read_needle() {
+        for angle in [-120.0, 0.0, 81.0] {
+            let mut gray =
+                Mat::new_rows_cols_with_default(200, 200, CV_8U, Scalar::all(255.0)).unwrap();
+            dial(&mut gray, angle);
+            let read = needle(&gray, &analog()).unwrap();
+            assert!((read - angle).abs() <= 2.0, "{} read as {}", angle, read);
+        }
+    }
+
+    #[test]
+    fn read_digits() {
+        let segments = |decimals| Segments {
+            digits: 4,
+            decimals,
+            dark: false,
+            threshold: Some(128.0),
+            fill: 0.5,
+        };
+        assert_eq!(
+            read_segments(&display("-125"), &segments(1)).unwrap(),
+            Some(-12.5)
+        );
+        assert_eq!(
+            read_segments(&display("  42"), &segments(0)).unwrap(),
+            Some(42.0)
+        );
+        assert_eq!(read_segments(&display("4 2 "), &segments(0)).unwrap(), None);
+    }
+
+    // 仪表配置检查, 读数按 time,meter,value,unit 追加到 CSV.
+    #[test]
+    fn csv_readings() {
+        let dir = std::env::temp_dir().join(format!("gauges-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let meter = Meter {
+            name: "boiler".to_string(),
+            roi: [0, 0, 200, 200],
+            unit: "bar".to_string(),
+            analog: Some(analog()),
+            segments: None,
+        };
+        let config = |meters: Vec<Meter>| GaugeConfig {
+            enabled: true,
+            csv: dir.join("gauges.csv"),
+            meters,
+            ..Default::default()
+        };
+        assert!(GaugeReading::new(&config(Vec::new())).is_err());
+        let empty = Meter {
+            roi: [0, 0, 0, 200],
+            ..meter.clone()
+        };
+        assert!(GaugeReading::new(&config(vec![empty])).is_err());
+        let neither = Meter {
+            analog: None,
+            ..meter.clone()
+        };
+        assert!(GaugeReading::new(&config(vec![neither])).is_err());
+
+        let mut stage = GaugeReading::new(&config(vec![meter])).unwrap();
+        let mut frame =
+            Mat::new_rows_cols_with_default(240, 320, CV_8UC3, Scalar::all(255.0)).unwrap();
+        dial(&mut frame, 0.0);
+        stage.process(&mut frame).unwrap();
+        drop(stage);
+        let csv = std::fs::read_to_string(dir.join("gauges.csv")).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "time,meter,value,unit");
+        let fields: Vec<&str> = lines[1].split(',').collect();
+        assert_eq!((fields[1], fields[3]), ("boiler", "bar"));
+        assert!((fields[2].parse::<f64>().unwrap() - 5.0).abs() < 0.1);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,225 @@
+// 回归测试: 每个内置处理阶段在固定的测试图像上运行, 输出与 tests/golden 下保存的结果比较.
+// 结果不存在或设置了 UPDATE_GOLDEN=1 时写入新的结果.
+use std::path::PathBuf;
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Point, Rect, Scalar, Vec3b, Vec3f, Vector, CV_32FC3, CV_8UC3},
+    imgcodecs,
+    imgproc::{self, LINE_8},
+    prelude::*,
+};
+
+use crate::{
+    change::ChangeDetection,
+    color::ColorCorrection,
+    effects::{Effect, Effects},
+    flat_field::FlatFieldCorrection,
+    hot_pixels::HotPixelCorrection,
+    lanes::{LaneConfig, LaneDetection},
+    pipeline::FrameProcessor,
+    white_balance::WhiteBalance,
+};
+
+const WIDTH: i32 = 320;
+const HEIGHT: i32 = 240;
+// 低于该 PSNR (dB) 认为输出发生了肉眼可见的变化.
+const MIN_PSNR: f64 = 45.0;
+
+// 彩色渐变加几何图形, 覆盖全部色调范围.
+fn gradient() -> Result<Mat> {
+    let mut image = Mat::new_rows_cols_with_default(HEIGHT, WIDTH, CV_8UC3, Scalar::all(0.))?;
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            *image.at_2d_mut::<Vec3b>(y, x)? = Vec3b::from([
+                (x * 255 / WIDTH) as u8,
+                (y * 255 / HEIGHT) as u8,
+                ((x + y) * 255 / (WIDTH + HEIGHT)) as u8,
+            ]);
+        }
+    }
+    imgproc::circle(
+        &mut image,
+        Point::new(100, 120),
+        40,
+        Scalar::new(255., 255., 255., 0.),
+        -1,
+        LINE_8,
+        0,
+    )?;
+    imgproc::rectangle(
+        &mut image,
+        Rect::new(200, 60, 80, 50),
+        Scalar::new(0., 0., 0., 0.),
+        -1,
+        LINE_8,
+        0,
+    )?;
+    Ok(image)
+}
+
+// 灰色路面和两条白色车道线.
+fn road() -> Result<Mat> {
+    let mut image = Mat::new_rows_cols_with_default(HEIGHT, WIDTH, CV_8UC3, Scalar::all(80.))?;
+    let white = Scalar::all(255.);
+    imgproc::line(
+        &mut image,
+        Point::new(40, HEIGHT),
+        Point::new(145, 150),
+        white,
+        4,
+        LINE_8,
+        0,
+    )?;
+    imgproc::line(
+        &mut image,
+        Point::new(280, HEIGHT),
+        Point::new(175, 150),
+        white,
+        4,
+        LINE_8,
+        0,
+    )?;
+    Ok(image)
+}
+
+// 中心亮、四周暗的平场参考.
+fn vignette() -> Result<Mat> {
+    let mut flat = Mat::new_rows_cols_with_default(HEIGHT, WIDTH, CV_32FC3, Scalar::all(0.))?;
+    let (cx, cy) = (WIDTH as f32 / 2.0, HEIGHT as f32 / 2.0);
+    let max = (cx * cx + cy * cy).sqrt();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let r = ((x as f32 - cx).powi(2) + (y as f32 - cy).powi(2)).sqrt() / max;
+            let v = 200.0 * (1.0 - 0.4 * r * r);
+            *flat.at_2d_mut::<Vec3f>(y, x)? = Vec3f::from([v, v, v]);
+        }
+    }
+    Ok(flat)
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+// FNV-1a, 只用于报告输出是否逐字节一致.
+fn hash(image: &Mat) -> Result<u64> {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in image.data_bytes()? {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    Ok(hash)
+}
+
+fn check(name: &str, stage: &mut dyn FrameProcessor, input: &Mat) -> Result<()> {
+    let mut output = input.try_clone()?;
+    stage.process(&mut output)?;
+    let path = golden_path(name);
+    let update = std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v == "1");
+    if update {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        imgcodecs::imwrite(&path.to_string_lossy(), &output, &Vector::new())?;
+        eprintln!("{}: golden written to {}", name, path.display());
+        return Ok(());
+    }
+    // 缺少 golden 时失败, 否则新增或改名的阶段不做比较也会通过.
+    assert!(
+        path.exists(),
+        "{}: missing golden {}, run with UPDATE_GOLDEN=1 and commit it",
+        name,
+        path.display()
+    );
+    let golden = imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_UNCHANGED)?;
+    assert_eq!(
+        (golden.size()?, golden.typ()),
+        (output.size()?, output.typ()),
+        "{}: output size or type changed",
+        name
+    );
+    if hash(&golden)? == hash(&output)? {
+        return Ok(());
+    }
+    let psnr = core::psnr(&golden, &output, 255.)?;
+    eprintln!("{}: output differs from golden, PSNR {:.1} dB", name, psnr);
+    assert!(
+        psnr >= MIN_PSNR,
+        "{}: PSNR {:.1} dB below {} dB, run with UPDATE_GOLDEN=1 if the change is intended",
+        name,
+        psnr,
+        MIN_PSNR
+    );
+    Ok(())
+}
+
+#[test]
+fn hot_pixels() -> Result<()> {
+    let mut input = gradient()?;
+    for [x, y] in [[10, 10], [150, 40], [300, 200]] {
+        *input.at_2d_mut::<Vec3b>(y, x)? = Vec3b::from([255, 255, 255]);
+    }
+    let mut stage = HotPixelCorrection::new(&[[10, 10], [150, 40], [300, 200]]);
+    check("hot-pixels", &mut stage, &input)
+}
+
+#[test]
+fn flat_field() -> Result<()> {
+    let mut stage = FlatFieldCorrection::new(&vignette()?)?;
+    check("flat-field", &mut stage, &gradient()?)
+}
+
+#[test]
+fn white_balance() -> Result<()> {
+    let mut stage = WhiteBalance::new(&[1.2, 1.0, 0.8])?;
+    check("white-balance", &mut stage, &gradient()?)
+}
+
+#[test]
+fn color_correction() -> Result<()> {
+    let matrix = [
+        [0.9, 0.1, 0.0, 5.0],
+        [0.05, 0.9, 0.05, 0.0],
+        [0.0, 0.1, 0.9, -5.0],
+    ];
+    let mut stage = ColorCorrection::new(&matrix)?;
+    check("color-correction", &mut stage, &gradient()?)
+}
+
+#[test]
+fn lanes() -> Result<()> {
+    let mut stage = LaneDetection::new(&LaneConfig::default());
+    check("lanes", &mut stage, &road()?)
+}
+
+#[test]
+fn change_detection() -> Result<()> {
+    let reference = gradient()?;
+    let mut input = reference.try_clone()?;
+    imgproc::rectangle(
+        &mut input,
+        Rect::new(30, 170, 60, 40),
+        Scalar::new(0., 0., 255., 0.),
+        -1,
+        LINE_8,
+        0,
+    )?;
+    let mut stage = ChangeDetection::new(&reference, 5, 30.0)?;
+    check("change-detection", &mut stage, &input)
+}
+
+#[test]
+fn effects() -> Result<()> {
+    let mut stage = Effects::new(&[Effect::Equalize, Effect::Blur { kernel: 5 }, Effect::Mirror]);
+    check("effects", &mut stage, &gradient()?)
+}
+
+#[test]
+fn edges() -> Result<()> {
+    let mut stage = Effects::new(&[Effect::Edges {
+        low: 50.0,
+        high: 150.0,
+    }]);
+    check("edges", &mut stage, &road()?)
+}
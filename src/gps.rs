@@ -0,0 +1,450 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Write},
+    net::TcpStream,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{
+    auth::Auth,
+    telemetry::{Sample, Telemetry, TelemetryConfig},
+};
+
+// 断开后重新打开设备或连接 gpsd 的间隔.
+const RETRY: Duration = Duration::from_secs(5);
+// gpsd 的读取超时, 用于检查是否已停止.
+const POLL: Duration = Duration::from_millis(500);
+// 节转换为米每秒.
+const KNOT: f64 = 0.514444;
+
+// 行车记录仪用的 GPS 输入, 位置和速度显示在 OSD 中, 录像时写入 GPX 轨迹.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GpsConfig {
+    // NMEA 0183 输入: 串口设备 (波特率需要先用 stty 设置) 或不断追加的日志文件.
+    pub nmea: Option<PathBuf>,
+    // gpsd 地址, 例如 127.0.0.1:2947.
+    pub gpsd: Option<String>,
+    // 与帧的时间相差超过该值 (秒) 的定位不显示, GPS 通常每秒一次.
+    pub tolerance: f64,
+    pub speed_unit: SpeedUnit,
+    // 录像时在文件旁写入 <录像>.gpx.
+    pub gpx: bool,
+}
+
+impl Default for GpsConfig {
+    fn default() -> Self {
+        Self {
+            nmea: None,
+            gpsd: None,
+            tolerance: 1.5,
+            speed_unit: SpeedUnit::Kmh,
+            gpx: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SpeedUnit {
+    Kmh,
+    Mph,
+    Knots,
+}
+
+impl SpeedUnit {
+    fn convert(self, meters_per_second: f64) -> (f64, &'static str) {
+        match self {
+            SpeedUnit::Kmh => (meters_per_second * 3.6, "km/h"),
+            SpeedUnit::Mph => (meters_per_second * 2.236936, "mph"),
+            SpeedUnit::Knots => (meters_per_second / KNOT, "kn"),
+        }
+    }
+}
+
+// 一次定位, 速度为米每秒, 方向为相对正北的度数.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Fix {
+    pub lat: f64,
+    pub lon: f64,
+    pub alt: Option<f64>,
+    pub speed: Option<f64>,
+    pub track: Option<f64>,
+}
+
+impl Fix {
+    fn sample(&self) -> Map<String, Value> {
+        let mut data = Map::new();
+        data.insert("lat".to_string(), self.lat.into());
+        data.insert("lon".to_string(), self.lon.into());
+        for (name, value) in [
+            ("alt", self.alt),
+            ("speed", self.speed),
+            ("track", self.track),
+        ] {
+            if let Some(value) = value {
+                data.insert(name.to_string(), value.into());
+            }
+        }
+        data
+    }
+
+    fn from_sample(sample: &Sample) -> Option<Self> {
+        let field = |name: &str| sample.data.get(name).and_then(Value::as_f64);
+        Some(Self {
+            lat: field("lat")?,
+            lon: field("lon")?,
+            alt: field("alt"),
+            speed: field("speed"),
+            track: field("track"),
+        })
+    }
+}
+
+// 最近的定位, 按收到的时间与帧对应 (与帧使用同一个时钟, 不受 GPS 时间和系统时间偏差影响).
+#[derive(Clone)]
+pub struct Gps {
+    fixes: Telemetry,
+    unit: SpeedUnit,
+    pub gpx: bool,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Gps {
+    // 没有配置输入时返回 None.
+    pub fn start(config: &GpsConfig, auth: &Auth) -> Result<Option<Self>> {
+        if config.nmea.is_none() && config.gpsd.is_none() {
+            return Ok(None);
+        }
+        let fixes = Telemetry::start(
+            &TelemetryConfig {
+                tolerance: config.tolerance,
+                sidecar: false,
+                ..Default::default()
+            },
+            auth,
+        )?;
+        let gps = Self {
+            fixes,
+            unit: config.speed_unit,
+            gpx: config.gpx,
+            stopped: Arc::default(),
+        };
+        if let Some(path) = &config.nmea {
+            let (gps, path) = (gps.clone(), path.clone());
+            spawn(move || {
+                while !gps.stopped.load(Ordering::SeqCst) {
+                    if let Err(err) = gps.read_nmea(&path) {
+                        eprintln!("gps {}: {:#}", path.display(), err);
+                    }
+                    sleep(RETRY);
+                }
+            });
+        }
+        if let Some(addr) = &config.gpsd {
+            let (gps, addr) = (gps.clone(), addr.clone());
+            spawn(move || {
+                while !gps.stopped.load(Ordering::SeqCst) {
+                    if let Err(err) = gps.read_gpsd(&addr) {
+                        eprintln!("gpsd {}: {:#}", addr, err);
+                    }
+                    sleep(RETRY);
+                }
+            });
+        }
+        Ok(Some(gps))
+    }
+
+    // 关闭 VideoFeed 时调用. 串口没有数据时读取线程在下一行到达后结束.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    // 时间最接近 time 的定位.
+    pub fn at(&self, time: DateTime<Local>) -> Option<(DateTime<Local>, Fix)> {
+        let sample = self.fixes.at(time)?;
+        Some((sample.time, Fix::from_sample(&sample)?))
+    }
+
+    // OSD 中显示的一行, 例如 "N52.37310 E4.89220 63 km/h".
+    pub fn describe(&self, fix: &Fix) -> String {
+        let mut text = format!(
+            "{}{:.5} {}{:.5}",
+            if fix.lat < 0.0 { 'S' } else { 'N' },
+            fix.lat.abs(),
+            if fix.lon < 0.0 { 'W' } else { 'E' },
+            fix.lon.abs()
+        );
+        if let Some(speed) = fix.speed {
+            let (speed, unit) = self.unit.convert(speed);
+            text.push_str(&format!(" {:.0} {}", speed, unit));
+        }
+        text
+    }
+
+    fn add(&self, fix: &Fix) {
+        self.fixes.add(Local::now(), fix.sample());
+    }
+
+    fn read_nmea(&self, path: &Path) -> Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut nmea = Nmea::default();
+        let mut line = String::new();
+        while !self.stopped.load(Ordering::SeqCst) {
+            line.clear();
+            // 日志文件读到结尾时等待新的内容, 串口没有数据时阻塞.
+            if reader.read_line(&mut line)? == 0 {
+                sleep(Duration::from_millis(100));
+                continue;
+            }
+            match nmea.parse(line.trim()) {
+                Ok(Some(fix)) => self.add(&fix),
+                Ok(None) => {}
+                Err(err) => eprintln!("gps {}: {:#}", path.display(), err),
+            }
+        }
+        Ok(())
+    }
+
+    fn read_gpsd(&self, addr: &str) -> Result<()> {
+        let mut stream = TcpStream::connect(addr)?;
+        stream.set_read_timeout(Some(POLL))?;
+        stream.write_all(b"?WATCH={\"enable\":true,\"json\":true};\n")?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while !self.stopped.load(Ordering::SeqCst) {
+            match reader.read_line(&mut line) {
+                Ok(0) => bail!("connection closed"),
+                Ok(_) => {}
+                Err(err)
+                    if matches!(
+                        err.kind(),
+                        std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    // 超时时已读到的部分留在 line 中, 下次继续读.
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            }
+            if let Some(fix) = gpsd_fix(&line)? {
+                self.add(&fix);
+            }
+            line.clear();
+        }
+        Ok(())
+    }
+}
+
+// gpsd 的 TPV 报告, mode 为 2 (2D) 或 3 (3D) 时有位置.
+fn gpsd_fix(line: &str) -> Result<Option<Fix>> {
+    let report: Value = serde_json::from_str(line).context("gpsd report")?;
+    if report["class"] != "TPV" || report["mode"].as_u64().unwrap_or(0) < 2 {
+        return Ok(None);
+    }
+    let (Some(lat), Some(lon)) = (report["lat"].as_f64(), report["lon"].as_f64()) else {
+        return Ok(None);
+    };
+    Ok(Some(Fix {
+        lat,
+        lon,
+        alt: report["altMSL"].as_f64().or(report["alt"].as_f64()),
+        speed: report["speed"].as_f64(),
+        track: report["track"].as_f64(),
+    }))
+}
+
+// NMEA 0183 语句的解析状态: RMC 给出位置, 速度和方向, GGA 给出高度, 每个有效的 RMC 产生一次定位.
+#[derive(Default)]
+pub struct Nmea {
+    alt: Option<f64>,
+}
+
+impl Nmea {
+    pub fn parse(&mut self, sentence: &str) -> Result<Option<Fix>> {
+        let Some(body) = sentence.strip_prefix('$') else {
+            return Ok(None);
+        };
+        let body = match body.split_once('*') {
+            Some((body, checksum)) => {
+                let expected = u8::from_str_radix(checksum.trim(), 16)
+                    .with_context(|| format!("invalid checksum in {}", sentence))?;
+                let actual = body.bytes().fold(0, |sum, byte| sum ^ byte);
+                if actual != expected {
+                    bail!("checksum mismatch in {}", sentence);
+                }
+                body
+            }
+            None => body,
+        };
+        let fields: Vec<&str> = body.split(',').collect();
+        // 前两个字符是设备类型 (GP, GN, GL 等).
+        let kind = fields[0].get(2..).unwrap_or_default();
+        let field = |index: usize| fields.get(index).copied().unwrap_or_default();
+        match kind {
+            "GGA" => {
+                // 定位质量为 0 时无效.
+                if !matches!(field(6), "" | "0") {
+                    self.alt = field(9).parse().ok();
+                }
+                Ok(None)
+            }
+            "RMC" => {
+                if field(2) != "A" {
+                    return Ok(None);
+                }
+                let (Some(lat), Some(lon)) = (
+                    coordinate(field(3), field(4)),
+                    coordinate(field(5), field(6)),
+                ) else {
+                    return Ok(None);
+                };
+                Ok(Some(Fix {
+                    lat,
+                    lon,
+                    alt: self.alt,
+                    speed: field(7).parse::<f64>().ok().map(|knots| knots * KNOT),
+                    track: field(8).parse().ok(),
+                }))
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+// ddmm.mmmm (纬度) 或 dddmm.mmmm (经度) 加半球.
+fn coordinate(value: &str, hemisphere: &str) -> Option<f64> {
+    let dot = value.find('.').unwrap_or(value.len());
+    let degrees: f64 = value.get(..dot.checked_sub(2)?)?.parse().ok()?;
+    let minutes: f64 = value.get(dot - 2..)?.parse().ok()?;
+    let value = degrees + minutes / 60.0;
+    match hemisphere {
+        "N" | "E" => Some(value),
+        "S" | "W" => Some(-value),
+        _ => None,
+    }
+}
+
+// 录像文件旁的 <录像>.gpx, 与 telemetry 的 sidecar 一样跟随当前的录像文件.
+// 点的时间与录像的 OSD 时间戳使用同一个时钟.
+#[derive(Default)]
+pub struct Tracks {
+    tracks: Vec<Track>,
+}
+
+struct Track {
+    recording: PathBuf,
+    writer: BufWriter<File>,
+    // 最近写入的定位的时间, 同一个定位对应多帧时只写一次.
+    last: Option<DateTime<Local>>,
+}
+
+pub fn gpx_path(recording: &Path) -> PathBuf {
+    recording.with_extension("gpx")
+}
+
+impl Track {
+    fn create(recording: &Path) -> Result<Self> {
+        let path = gpx_path(recording);
+        let mut writer = BufWriter::new(
+            File::create(&path).with_context(|| format!("create {}", path.display()))?,
+        );
+        let name = recording
+            .file_name()
+            .map(|name| escape(&name.to_string_lossy()))
+            .unwrap_or_default();
+        write!(
+            writer,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <gpx version=\"1.1\" creator=\"slint-opencv\" xmlns=\"http://www.topografix.com/GPX/1/1\">\n\
+             <trk><name>{}</name><trkseg>\n",
+            name
+        )?;
+        Ok(Self {
+            recording: recording.to_path_buf(),
+            writer,
+            last: None,
+        })
+    }
+
+    fn point(&mut self, time: DateTime<Local>, fix: &Fix) -> Result<()> {
+        if self.last == Some(time) {
+            return Ok(());
+        }
+        self.last = Some(time);
+        write!(
+            self.writer,
+            "<trkpt lat=\"{:.7}\" lon=\"{:.7}\">",
+            fix.lat, fix.lon
+        )?;
+        if let Some(alt) = fix.alt {
+            write!(self.writer, "<ele>{:.1}</ele>", alt)?;
+        }
+        writeln!(
+            self.writer,
+            "<time>{}</time></trkpt>",
+            time.with_timezone(&Utc).format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        )?;
+        Ok(())
+    }
+}
+
+impl Drop for Track {
+    fn drop(&mut self) {
+        let result =
+            writeln!(self.writer, "</trkseg></trk>\n</gpx>").and_then(|_| self.writer.flush());
+        if let Err(err) = result {
+            eprintln!("gpx for {}: {:?}", self.recording.display(), err);
+        }
+    }
+}
+
+impl Tracks {
+    // 每帧调用, recordings 为当前正在写入的录像文件, 没有录像时关闭所有轨迹.
+    pub fn write<'a>(
+        &mut self,
+        recordings: impl Iterator<Item = &'a Path>,
+        fix: Option<&(DateTime<Local>, Fix)>,
+    ) {
+        let recordings: Vec<&Path> = recordings.collect();
+        self.tracks
+            .retain(|track| recordings.contains(&track.recording.as_path()));
+        for recording in recordings {
+            if self.tracks.iter().any(|track| track.recording == recording) {
+                continue;
+            }
+            match Track::create(recording) {
+                Ok(track) => self.tracks.push(track),
+                Err(err) => eprintln!("gpx: {:?}", err),
+            }
+        }
+        let Some((time, fix)) = fix else {
+            return;
+        };
+        self.tracks
+            .retain_mut(|track| match track.point(*time, fix) {
+                Ok(()) => true,
+                Err(err) => {
+                    eprintln!("gpx for {}: {:?}", track.recording.display(), err);
+                    false
+                }
+            });
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
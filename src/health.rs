@@ -0,0 +1,211 @@
+use std::{collections::VecDeque, sync::atomic::Ordering, thread::spawn, time::Duration};
+
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{crash, events::EventStore, frames::DROPPED_FRAMES, metrics, threads::Shutdown};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HealthConfig {
+    pub enabled: bool,
+    // 采样间隔 (秒).
+    pub interval: f64,
+    // 用最近多少个采样计算增长趋势.
+    pub window: usize,
+    // 超过这些增长速度 (每小时) 时告警.
+    pub max_rss_growth_mb: f64,
+    pub max_fd_growth: f64,
+    // 一个采样间隔内界面来不及显示而丢弃的帧数超过该值时告警.
+    pub max_dropped_frames: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            interval: 10.0,
+            window: 360,
+            max_rss_growth_mb: 50.0,
+            max_fd_growth: 20.0,
+            max_dropped_frames: 100,
+        }
+    }
+}
+
+struct Sample {
+    rss_mb: f64,
+    fds: f64,
+}
+
+// 后台线程定期采样内存、文件描述符和丢弃的预览帧, 持续增长时写告警事件.
+pub fn start(config: &HealthConfig, events: EventStore, shutdown: &Shutdown) {
+    if !config.enabled {
+        return;
+    }
+    let interval = Duration::from_secs_f64(config.interval);
+    let hours_per_sample = config.interval / 3600.0;
+    let window = config.window.max(2);
+    let (max_rss, max_fds, max_dropped) = (
+        config.max_rss_growth_mb,
+        config.max_fd_growth,
+        config.max_dropped_frames,
+    );
+    let shutdown = shutdown.clone();
+    spawn(move || {
+        let mut samples: VecDeque<Sample> = VecDeque::new();
+        let mut last_dropped = DROPPED_FRAMES.load(Ordering::Relaxed);
+        loop {
+            if shutdown.wait(interval) {
+                return;
+            }
+            let sample = Sample {
+                rss_mb: rss_bytes().unwrap_or(0) as f64 / 1048576.0,
+                fds: open_fds().unwrap_or(0) as f64,
+            };
+            let total = DROPPED_FRAMES.load(Ordering::Relaxed);
+            let dropped = total - last_dropped;
+            last_dropped = total;
+            metrics::gauge("process_rss_megabytes", "Resident set size", sample.rss_mb);
+            metrics::gauge("process_open_fds", "Open file descriptors", sample.fds);
+            metrics::gauge(
+                "preview_dropped_frames",
+                "Preview frames dropped because the UI fell behind",
+                total as f64,
+            );
+            if dropped > max_dropped {
+                warn(&events, "dropped-frames", json!({"frames": dropped}));
+            }
+
+            samples.push_back(sample);
+            if samples.len() < window {
+                continue;
+            }
+            let rss = slope(samples.iter().map(|s| s.rss_mb)) / hours_per_sample;
+            let fds = slope(samples.iter().map(|s| s.fds)) / hours_per_sample;
+            metrics::gauge("process_rss_growth_mb_per_hour", "RSS trend", rss);
+            metrics::gauge(
+                "process_fd_growth_per_hour",
+                "Open file descriptor trend",
+                fds,
+            );
+            if rss > max_rss {
+                warn(&events, "memory", json!({"growth_mb_per_hour": rss}));
+            }
+            if fds > max_fds {
+                warn(&events, "file-descriptors", json!({"growth_per_hour": fds}));
+            }
+            // 告警后重新积累一个窗口, 避免每次采样都告警.
+            if rss > max_rss || fds > max_fds {
+                samples.clear();
+            } else {
+                samples.pop_front();
+            }
+        }
+    });
+}
+
+fn warn(events: &EventStore, resource: &str, data: serde_json::Value) {
+    let text = format!("health warning: {} {}", resource, data);
+    eprintln!("{}", text);
+    crash::log(&text);
+    events.log("health", json!({"resource": resource, "data": data}));
+}
+
+// 最小二乘直线拟合的斜率, 单位为每个采样.
+pub(crate) fn slope(values: impl Iterator<Item = f64>) -> f64 {
+    let values: Vec<f64> = values.collect();
+    let n = values.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = values.iter().sum::<f64>() / n;
+    let (mut num, mut den) = (0.0, 0.0);
+    for (i, y) in values.iter().enumerate() {
+        let dx = i as f64 - mean_x;
+        num += dx * (y - mean_y);
+        den += dx * dx;
+    }
+    if den == 0.0 {
+        0.0
+    } else {
+        num / den
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn rss_bytes() -> Option<u64> {
+    // statm 第二列为常驻页数.
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn rss_bytes() -> Option<u64> {
+    let output = std::process::Command::new("ps")
+        .args(["-o", "rss=", "-p", &std::process::id().to_string()])
+        .output()
+        .ok()?;
+    let kb: u64 = String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+pub(crate) fn open_fds() -> Option<usize> {
+    let dir = if cfg!(target_os = "linux") {
+        "/proc/self/fd"
+    } else {
+        "/dev/fd"
+    };
+    Some(std::fs::read_dir(dir).ok()?.count())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use super::*;
+    use crate::events;
+
+    #[test]
+    fn trend() {
+        assert_eq!(slope([1.0, 3.0, 5.0, 7.0].into_iter()), 2.0);
+        assert_eq!(slope([4.0, 4.0, 4.0].into_iter()), 0.0);
+        assert_eq!(slope([4.0].into_iter()), 0.0);
+        // 噪声不影响整体趋势.
+        assert_eq!(slope([0.0, 2.0, 1.0, 3.0].into_iter()), 0.8);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn process_usage() {
+        assert!(rss_bytes().unwrap() > 0);
+        // 至少有标准输入输出.
+        assert!(open_fds().unwrap() >= 3);
+    }
+
+    #[test]
+    fn warns_on_dropped_frames() {
+        let path = std::env::temp_dir().join(format!("health-test-{}.jsonl", std::process::id()));
+        let config = HealthConfig {
+            interval: 0.05,
+            window: 1000,
+            max_dropped_frames: 5,
+            ..Default::default()
+        };
+        let shutdown = Shutdown::default();
+        start(&config, EventStore::open(&path).unwrap(), &shutdown);
+        sleep(Duration::from_millis(20));
+        DROPPED_FRAMES.fetch_add(10, Ordering::Relaxed);
+        sleep(Duration::from_millis(150));
+        shutdown.stop();
+        let logged = events::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // 其他测试中的预览也会丢帧, 只检查这一次告警.
+        assert!(logged.iter().any(|event| {
+            event.data["resource"] == "dropped-frames"
+                && event.data["data"]["frames"].as_u64() >= Some(10)
+        }));
+    }
+}
@@ -0,0 +1,138 @@
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use opencv::{prelude::*, videoio};
+use serde::Deserialize;
+
+use crate::source::FrameSource;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HighSpeedConfig {
+    // 高速模式请求的帧率和分辨率, 摄像头通常只在较低分辨率下支持 60/120 帧/秒.
+    pub fps: f64,
+    pub width: i32,
+    pub height: i32,
+    // 录制文件的播放帧率, 例如 120 帧/秒采集, 30 帧/秒播放为 4 倍慢动作.
+    pub playback_fps: f64,
+    // 预览的最高帧率, 其余的帧只录制不显示.
+    pub preview_fps: f64,
+}
+
+impl Default for HighSpeedConfig {
+    fn default() -> Self {
+        Self {
+            fps: 120.0,
+            width: 640,
+            height: 480,
+            playback_fps: 30.0,
+            preview_fps: 30.0,
+        }
+    }
+}
+
+// 来源的分辨率和帧率.
+#[derive(Debug, Clone, Copy)]
+pub struct Mode {
+    pub width: f64,
+    pub height: f64,
+    pub fps: f64,
+}
+
+// 请求新的分辨率和帧率, 返回摄像头实际使用的值.
+pub fn apply(source: &mut dyn FrameSource, mode: Mode) -> Result<Mode> {
+    source.reconfigure(videoio::CAP_PROP_FRAME_WIDTH, mode.width)?;
+    source.reconfigure(videoio::CAP_PROP_FRAME_HEIGHT, mode.height)?;
+    source.reconfigure(videoio::CAP_PROP_FPS, mode.fps)?;
+    let camera = source
+        .capture()
+        .context("the source does not support changing the frame rate")?;
+    Ok(Mode {
+        width: camera.get(videoio::CAP_PROP_FRAME_WIDTH)?,
+        height: camera.get(videoio::CAP_PROP_FRAME_HEIGHT)?,
+        fps: camera.get(videoio::CAP_PROP_FPS)?,
+    })
+}
+
+// 限制预览帧率, 高速采集时不必把每一帧都转换并发送给界面.
+pub struct PreviewPacing {
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl PreviewPacing {
+    pub fn new(fps: Option<f64>) -> Self {
+        Self {
+            interval: fps.map_or(Duration::ZERO, |fps| {
+                Duration::from_secs_f64(1.0 / fps.max(1.0))
+            }),
+            last: None,
+        }
+    }
+
+    pub fn due(&mut self) -> bool {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return false;
+        }
+        self.last = Some(Instant::now());
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread::sleep};
+
+    use opencv::{
+        core::{Mat, Scalar, Size, CV_8UC3},
+        videoio::VideoWriter,
+    };
+
+    use super::*;
+    use crate::{config::Config, source};
+
+    #[test]
+    fn preview_pacing() {
+        let mut unlimited = PreviewPacing::new(None);
+        assert!(unlimited.due() && unlimited.due());
+        let mut pacing = PreviewPacing::new(Some(20.0));
+        assert!(pacing.due());
+        assert!(!pacing.due());
+        sleep(Duration::from_millis(60));
+        assert!(pacing.due());
+    }
+
+    // 文件不能改变分辨率和帧率, 返回实际的值.
+    #[test]
+    fn apply_reports_actual_mode() {
+        let dir = std::env::temp_dir().join(format!("high-speed-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.avi");
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        let frame = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(0.)).unwrap();
+        writer.write(&frame).unwrap();
+        writer.release().unwrap();
+
+        let mut source = source::open(&path.to_string_lossy(), &Config::default()).unwrap();
+        let config = HighSpeedConfig::default();
+        let mode = Mode {
+            width: f64::from(config.width),
+            height: f64::from(config.height),
+            fps: config.fps,
+        };
+        let actual = apply(source.as_mut(), mode).unwrap();
+        assert_eq!(
+            (actual.width, actual.height, actual.fps),
+            (64.0, 48.0, 10.0)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
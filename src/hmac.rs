@@ -0,0 +1,97 @@
+use sha2::{Digest, Sha256};
+
+const BLOCK: usize = 64;
+
+// HMAC-SHA256 (RFC 2104).
+pub fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+    Keyed::new(key).sign(data)
+}
+
+// 预先处理好密钥的 HMAC, 同一个密钥签名多次时 (PBKDF2) 不重复计算填充.
+#[derive(Clone)]
+pub struct Keyed {
+    inner: Sha256,
+    outer: Sha256,
+}
+
+impl Keyed {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block = [0u8; BLOCK];
+        if key.len() > BLOCK {
+            block[..32].copy_from_slice(&Sha256::digest(key));
+        } else {
+            block[..key.len()].copy_from_slice(key);
+        }
+        Self {
+            inner: Sha256::new_with_prefix(block.map(|b| b ^ 0x36)),
+            outer: Sha256::new_with_prefix(block.map(|b| b ^ 0x5c)),
+        }
+    }
+
+    pub fn sign(&self, data: &[u8]) -> [u8; 32] {
+        let inner = self.inner.clone().chain_update(data).finalize();
+        self.outer.clone().chain_update(inner).finalize().into()
+    }
+}
+
+// PBKDF2-HMAC-SHA256 (RFC 8018), 输出一个 32 字节的块.
+pub fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let keyed = Keyed::new(password);
+    let mut first = salt.to_vec();
+    first.extend_from_slice(&1u32.to_be_bytes());
+    let mut block = keyed.sign(&first);
+    let mut output = block;
+    for _ in 1..iterations {
+        block = keyed.sign(&block);
+        for (output, byte) in output.iter_mut().zip(block) {
+            *output ^= byte;
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    // RFC 4231 的 Test Case 1, 2 和 6 (密钥比块长).
+    #[test]
+    fn hmac_known_answer() {
+        assert_eq!(
+            hex(&hmac(&[0x0b; 20], b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            hex(&hmac(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert_eq!(
+            hex(&hmac(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    // PBKDF2-HMAC-SHA256 的常用测试向量 (RFC 6070 的输入).
+    #[test]
+    fn pbkdf2_known_answer() {
+        assert_eq!(
+            hex(&pbkdf2(b"password", b"salt", 1)),
+            "120fb6cffcf8b32c43e7225256c4f837a86548c92ccc35480805987cb70be17b"
+        );
+        assert_eq!(
+            hex(&pbkdf2(b"password", b"salt", 2)),
+            "ae4d0c95af6b46d32d0adff928f06dd02a303f8ef3c251dfd6e2d85a95474c43"
+        );
+        assert_eq!(
+            hex(&pbkdf2(b"password", b"salt", 4096)),
+            "c5e478d59288c841aa530db6845c4c8d962893a001ce4e11a4963873aa98134a"
+        );
+    }
+}
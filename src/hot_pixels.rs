@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Point, Scalar, Vec3b, Vector, CV_32FC1, CV_8U, CV_8UC3},
+    imgproc::{self, COLOR_BGR2GRAY, THRESH_BINARY},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HotPixelConfig {
+    // 校准时平均的暗帧数量.
+    pub dark_frames: u32,
+    // 比周围中值亮多少才认为是坏点.
+    pub threshold: f64,
+}
+
+impl Default for HotPixelConfig {
+    fn default() -> Self {
+        Self {
+            dark_frames: 16,
+            threshold: 30.0,
+        }
+    }
+}
+
+// 暗帧校准: 遮住镜头后累加若干帧, 平均后与中值滤波结果比较找出亮点.
+pub struct DarkFrames {
+    remaining: u32,
+    count: u32,
+    threshold: f64,
+    gray: Mat,
+    sum: Mat,
+}
+
+impl DarkFrames {
+    pub fn new(config: &HotPixelConfig) -> Self {
+        Self {
+            remaining: config.dark_frames.max(1),
+            count: 0,
+            threshold: config.threshold,
+            gray: Mat::default(),
+            sum: Mat::default(),
+        }
+    }
+
+    // 收集够帧数后返回坏点坐标.
+    pub fn add(&mut self, frame: &Mat) -> Result<Option<Vec<[i32; 2]>>> {
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        if self.sum.empty() || self.sum.size()? != self.gray.size()? {
+            self.sum = Mat::new_rows_cols_with_default(
+                self.gray.rows(),
+                self.gray.cols(),
+                CV_32FC1,
+                Scalar::all(0.),
+            )?;
+            self.count = 0;
+        }
+        imgproc::accumulate(&self.gray, &mut self.sum, &core::no_array())?;
+        self.count += 1;
+        self.remaining -= 1;
+        if self.remaining > 0 {
+            return Ok(None);
+        }
+
+        let mut mean = Mat::default();
+        self.sum
+            .convert_to(&mut mean, CV_8U, 1.0 / self.count as f64, 0.0)?;
+        if core::mean(&mean, &core::no_array())?[0] > 40.0 {
+            bail!("frames are not dark, cover the lens and retry");
+        }
+        let mut median = Mat::default();
+        imgproc::median_blur(&mean, &mut median, 5)?;
+        let mut diff = Mat::default();
+        core::subtract(&mean, &median, &mut diff, &core::no_array(), -1)?;
+        let mut mask = Mat::default();
+        imgproc::threshold(&diff, &mut mask, self.threshold, 255.0, THRESH_BINARY)?;
+        let mut points = Vector::<Point>::new();
+        core::find_non_zero(&mask, &mut points)?;
+        Ok(Some(points.iter().map(|p| [p.x, p.y]).collect()))
+    }
+}
+
+// 用周围正常像素的均值替换坏点.
+pub struct HotPixelCorrection {
+    pixels: Vec<[i32; 2]>,
+    defects: HashSet<[i32; 2]>,
+}
+
+impl HotPixelCorrection {
+    pub fn new(pixels: &[[i32; 2]]) -> Self {
+        Self {
+            pixels: pixels.to_vec(),
+            defects: pixels.iter().copied().collect(),
+        }
+    }
+}
+
+impl FrameProcessor for HotPixelCorrection {
+    fn name(&self) -> &str {
+        "hot-pixels"
+    }
+
+    fn order(&self) -> i32 {
+        -40
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        if frame.typ() != CV_8UC3 {
+            return Ok(());
+        }
+        let (cols, rows) = (frame.cols(), frame.rows());
+        for &[x, y] in &self.pixels {
+            if x >= cols || y >= rows {
+                continue;
+            }
+            let mut sum = [0u32; 3];
+            let mut n = 0;
+            for dy in -1..=1 {
+                for dx in -1..=1 {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if (dx, dy) == (0, 0)
+                        || nx < 0
+                        || ny < 0
+                        || nx >= cols
+                        || ny >= rows
+                        || self.defects.contains(&[nx, ny])
+                    {
+                        continue;
+                    }
+                    let p = frame.at_2d::<Vec3b>(ny, nx)?;
+                    for c in 0..3 {
+                        sum[c] += p[c] as u32;
+                    }
+                    n += 1;
+                }
+            }
+            if n > 0 {
+                let p = frame.at_2d_mut::<Vec3b>(y, x)?;
+                for c in 0..3 {
+                    p[c] = (sum[c] / n) as u8;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOT: [[i32; 2]; 4] = [[5, 7], [20, 20], [21, 20], [0, 0]];
+
+    // 暗帧上有几个亮点, 其中两个相邻, 一个在角上.
+    fn dark() -> Mat {
+        let mut frame =
+            Mat::new_rows_cols_with_default(30, 40, CV_8UC3, Scalar::all(10.0)).unwrap();
+        for [x, y] in HOT {
+            *frame.at_2d_mut::<Vec3b>(y, x).unwrap() = Vec3b::from([200, 190, 210]);
+        }
+        frame
+    }
+
+    #[test]
+    fn dark_frame_calibration() {
+        let config = HotPixelConfig {
+            dark_frames: 3,
+            ..HotPixelConfig::default()
+        };
+        let mut dark_frames = DarkFrames::new(&config);
+        assert_eq!(dark_frames.add(&dark()).unwrap(), None);
+        assert_eq!(dark_frames.add(&dark()).unwrap(), None);
+        let mut found = dark_frames.add(&dark()).unwrap().unwrap();
+        found.sort();
+        let mut expected = HOT.to_vec();
+        expected.sort();
+        assert_eq!(found, expected);
+
+        // 没有遮住镜头.
+        let bright = Mat::new_rows_cols_with_default(30, 40, CV_8UC3, Scalar::all(90.0)).unwrap();
+        let mut dark_frames = DarkFrames::new(&config);
+        dark_frames.add(&bright).unwrap();
+        dark_frames.add(&bright).unwrap();
+        assert!(dark_frames.add(&bright).is_err());
+    }
+
+    // 坏点换成周围正常像素的均值, 相邻的坏点互不参与.
+    #[test]
+    fn correction() {
+        let mut frame = dark();
+        *frame.at_2d_mut::<Vec3b>(6, 5).unwrap() = Vec3b::from([18, 18, 18]);
+        let mut correction = HotPixelCorrection::new(&[HOT.as_slice(), &[[100, 100]]].concat());
+        correction.process(&mut frame).unwrap();
+        // (5, 7) 周围 8 个像素中一个是 18, 其余是 10.
+        assert_eq!(
+            *frame.at_2d::<Vec3b>(7, 5).unwrap(),
+            Vec3b::from([11, 11, 11])
+        );
+        for [x, y] in &HOT[1..] {
+            assert_eq!(*frame.at_2d::<Vec3b>(*y, *x).unwrap(), Vec3b::all(10));
+        }
+    }
+}
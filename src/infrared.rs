@@ -0,0 +1,191 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use opencv::{
+    core::{Mat, Size},
+    imgproc::{self, COLOR_BGR2GRAY, INTER_AREA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{effects::ColorMap, occupancy::Debounce};
+
+// 测量使用的画面宽度.
+const WIDTH: i32 = 64;
+
+// 红外夜视: 很多摄像头天黑后移开 IR-cut 滤光片改用红外灯照明, 送来的仍是 BGR 帧,
+// 但三个通道几乎相同. 这时依赖颜色的阶段只会得到错误的结果, 检测到后暂停它们.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InfraredConfig {
+    pub enabled: bool,
+    // 检查间隔 (秒).
+    pub interval: f64,
+    // 每个像素最大和最小通道之差的平均值 (0..255) 低于 saturation 时认为是红外画面.
+    pub saturation: f64,
+    // 新的状态持续 after 秒才切换.
+    pub after: f64,
+    // 红外画面中暂停的处理阶段.
+    pub skip: Vec<String>,
+    // 红外画面的伪彩色, 不设置时保持灰度.
+    pub colormap: Option<ColorMap>,
+}
+
+impl Default for InfraredConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 2.0,
+            saturation: 3.0,
+            after: 10.0,
+            skip: [
+                "white-balance",
+                "color-correction",
+                "chroma-key",
+                "reaction",
+            ]
+            .iter()
+            .map(|name| name.to_string())
+            .collect(),
+            colormap: None,
+        }
+    }
+}
+
+// BGR 像素的平均饱和度, 灰度画面为 0.
+pub fn saturation(pixels: &[u8]) -> f64 {
+    let count = pixels.len() / 3;
+    if count == 0 {
+        return 0.0;
+    }
+    let total = pixels
+        .chunks_exact(3)
+        .map(|p| (p[0].max(p[1]).max(p[2]) - p[0].min(p[1]).min(p[2])) as u64)
+        .sum::<u64>();
+    total as f64 / count as f64
+}
+
+pub struct Infrared {
+    config: InfraredConfig,
+    debounce: Debounce,
+    started: Instant,
+    last: Option<Instant>,
+    active: bool,
+    small: Mat,
+    gray: Mat,
+}
+
+impl Infrared {
+    pub fn new(config: &InfraredConfig) -> Self {
+        Self {
+            config: config.clone(),
+            debounce: Debounce::default(),
+            started: Instant::now(),
+            last: None,
+            active: false,
+            small: Mat::default(),
+            gray: Mat::default(),
+        }
+    }
+
+    pub fn config(&self) -> &InfraredConfig {
+        &self.config
+    }
+
+    // 当前需要暂停的阶段.
+    pub fn skipped(&self) -> &[String] {
+        if self.active {
+            &self.config.skip
+        } else {
+            &[]
+        }
+    }
+
+    // 每帧调用, 进入或离开红外画面时返回新的状态.
+    pub fn update(&mut self, frame: &Mat) -> Result<Option<bool>> {
+        if !self.config.enabled || frame.channels() != 3 {
+            return Ok(None);
+        }
+        let interval = Duration::from_secs_f64(self.config.interval.max(0.1));
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(None);
+        }
+        self.last = Some(Instant::now());
+        let scale = (WIDTH as f64 / frame.cols().max(1) as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        let raw = saturation(self.small.data_bytes()?) < self.config.saturation;
+        let now = self.started.elapsed().as_secs_f64();
+        let after = self.config.after.max(0.0);
+        match self.debounce.update(raw, now, after, after) {
+            Some(active) if active != self.active => {
+                self.active = active;
+                Ok(Some(active))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // 红外画面换上伪彩色, 在处理阶段之后调用, 检测仍然使用灰度画面.
+    pub fn colorize(&mut self, frame: &mut Mat) -> Result<()> {
+        let Some(map) = self.config.colormap.filter(|_| self.active) else {
+            return Ok(());
+        };
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        imgproc::apply_color_map(&self.gray, frame, map.code())?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, CV_8UC3};
+
+    use super::*;
+
+    #[test]
+    fn pixel_saturation() {
+        assert_eq!(saturation(&[]), 0.0);
+        assert_eq!(saturation(&[80, 80, 80, 200, 200, 200]), 0.0);
+        assert_eq!(saturation(&[10, 20, 30, 100, 100, 104]), 12.0);
+        // 不完整的像素不计.
+        assert_eq!(saturation(&[0, 0, 255, 7]), 255.0);
+    }
+
+    fn frame(b: f64, g: f64, r: f64) -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::new(b, g, r, 0.0)).unwrap()
+    }
+
+    // 第一次检查直接采用, 红外画面时暂停依赖颜色的阶段.
+    #[test]
+    fn pauses_color_stages() {
+        let config = InfraredConfig {
+            enabled: true,
+            ..InfraredConfig::default()
+        };
+        let mut infrared = Infrared::new(&config);
+        assert_eq!(
+            infrared.update(&frame(90.0, 91.0, 92.0)).unwrap(),
+            Some(true)
+        );
+        assert_eq!(infrared.skipped(), config.skip.as_slice());
+        assert!(infrared
+            .skipped()
+            .iter()
+            .any(|stage| stage == "white-balance"));
+
+        let mut color = Infrared::new(&config);
+        assert_eq!(color.update(&frame(40.0, 90.0, 160.0)).unwrap(), None);
+        assert!(color.skipped().is_empty());
+
+        let mut disabled = Infrared::new(&InfraredConfig::default());
+        assert_eq!(disabled.update(&frame(90.0, 90.0, 90.0)).unwrap(), None);
+        assert!(disabled.skipped().is_empty());
+    }
+}
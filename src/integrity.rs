@@ -0,0 +1,415 @@
+use std::{
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::Mat,
+    prelude::*,
+    videoio::{self, VideoCapture, CAP_ANY},
+};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::{hmac::Keyed, sink::SinkInfo};
+
+// 录像旁边的完整性记录: <录像>.integrity.jsonl.
+pub const EXTENSION: &str = "integrity.jsonl";
+pub const KEY_FILE: &str = "integrity.key";
+// 版本 1 只用 SHA-256, 拿到录像的人可以重新生成一致的记录, 不再接受.
+const VERSION: u64 = 2;
+
+// HMAC 密钥, 文件中为 64 个十六进制字符. 没有密钥就不能为修改过的录像生成记录.
+#[derive(Clone)]
+pub struct Key(Keyed);
+
+impl Key {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("read integrity key {}", path.display()))?;
+        let key = unhex(text.trim()).ok().filter(|key| key.len() == 32);
+        let Some(key) = key else {
+            bail!("integrity key {} must be 64 hex characters", path.display());
+        };
+        Ok(Self(Keyed::new(&key)))
+    }
+}
+
+// 完整性记录的第一行是录像信息, 之后每帧一行 {"frame", "time", "pixels", "chain"},
+// 录制正常结束时最后一行为 {"frames", "chain", "file_sha256", "mac"}.
+// chain = HMAC(上一帧的 chain + 帧序号 + 时间 + pixels), 第一帧的上一个 chain 为第一行的 HMAC,
+// mac = HMAC(最后的 chain + 帧数 + 文件哈希). 删除, 插入或修改任何一行都会使之后的 chain 对不上.
+pub struct Chain {
+    writer: BufWriter<File>,
+    path: PathBuf,
+    key: Key,
+    frame: u64,
+    last: [u8; 32],
+}
+
+impl Chain {
+    pub fn create(recording: &Path, fourcc: &str, info: &SinkInfo, key: Key) -> Result<Self> {
+        let path = sidecar(recording);
+        let file = File::create(&path).with_context(|| format!("create {}", path.display()))?;
+        let mut writer = BufWriter::new(file);
+        let header = json!({
+            "version": VERSION,
+            "file": recording.file_name().map(|name| name.to_string_lossy()),
+            "fourcc": fourcc,
+            "fps": info.fps,
+            "width": info.size.width,
+            "height": info.size.height,
+            "start": Local::now().to_rfc3339(),
+        })
+        .to_string();
+        writeln!(writer, "{}", header)?;
+        Ok(Self {
+            writer,
+            path,
+            frame: 0,
+            last: key.0.sign(header.as_bytes()),
+            key,
+        })
+    }
+
+    // 记录写入录像的一帧 (编码之前的像素).
+    pub fn add(&mut self, frame: &Mat) -> Result<()> {
+        let pixels = pixels(frame)?;
+        let time = Local::now().to_rfc3339();
+        self.last = link(&self.key, &self.last, self.frame, &time, &pixels);
+        let line = json!({
+            "frame": self.frame,
+            "time": time,
+            "pixels": hex(&pixels),
+            "chain": hex(&self.last),
+        });
+        writeln!(self.writer, "{}", line)?;
+        self.frame += 1;
+        Ok(())
+    }
+
+    // 录像文件关闭之后调用, 写入帧数, 最后的 chain 和文件的 SHA-256.
+    pub fn finish(mut self, recording: &Path) -> Result<()> {
+        let file = file_hash(recording)?;
+        let footer = json!({
+            "frames": self.frame,
+            "chain": hex(&self.last),
+            "file_sha256": hex(&file),
+            "mac": hex(&seal(&self.key, &self.last, self.frame, &file)),
+        });
+        writeln!(self.writer, "{}", footer)?;
+        self.writer.flush()?;
+        self.writer.get_ref().sync_all()?;
+        println!("Integrity record {}", self.path.display());
+        Ok(())
+    }
+}
+
+pub fn sidecar(recording: &Path) -> PathBuf {
+    let mut name = recording.as_os_str().to_owned();
+    name.push(format!(".{}", EXTENSION));
+    PathBuf::from(name)
+}
+
+// 检查录像和它的完整性记录, 返回每项检查的结果. 任何一项不通过时返回错误.
+// frames 为 true 时解码录像逐帧比较像素, 只对无损编码 (例如 FFV1) 有意义.
+// 没有最后一行 (录制没有正常结束) 时失败, 除非 unfinished 为 true.
+pub fn verify(recording: &Path, key: &Key, frames: bool, unfinished: bool) -> Result<Vec<String>> {
+    let path = sidecar(recording);
+    let reader = BufReader::new(
+        File::open(&path).with_context(|| format!("open integrity record {}", path.display()))?,
+    );
+    let mut lines = reader.lines();
+    let header = lines.next().context("empty integrity record")??;
+    let info: Value = serde_json::from_str(&header)?;
+    if info["version"].as_u64() != Some(VERSION) {
+        bail!("unsupported integrity record version {}", info["version"]);
+    }
+    let mut report = Vec::new();
+    let mut last = key.0.sign(header.as_bytes());
+    let mut hashes = Vec::new();
+    let mut footer = None;
+    for (number, line) in lines.enumerate() {
+        let line = line?;
+        let entry: Value = serde_json::from_str(&line)
+            .with_context(|| format!("line {} is not JSON", number + 2))?;
+        if entry.get("file_sha256").is_some() {
+            footer = Some(entry);
+            break;
+        }
+        let frame = entry["frame"].as_u64().context("missing frame")?;
+        if frame != hashes.len() as u64 {
+            bail!(
+                "frame {} found where frame {} was expected, frames are missing or reordered",
+                frame,
+                hashes.len()
+            );
+        }
+        let hash = unhex(entry["pixels"].as_str().unwrap_or_default())?;
+        last = link(
+            key,
+            &last,
+            frame,
+            entry["time"].as_str().unwrap_or_default(),
+            &hash,
+        );
+        if entry["chain"].as_str() != Some(hex(&last).as_str()) {
+            bail!(
+                "hash chain broken at frame {}, the record was edited or the key is wrong",
+                frame
+            );
+        }
+        hashes.push(hash);
+    }
+    report.push(format!("hash chain intact over {} frames", hashes.len()));
+
+    match footer {
+        Some(footer) => {
+            if footer["frames"].as_u64() != Some(hashes.len() as u64)
+                || footer["chain"].as_str() != Some(hex(&last).as_str())
+            {
+                bail!("the final line does not match the frames, the record was truncated");
+            }
+            let expected = unhex(footer["file_sha256"].as_str().unwrap_or_default())?;
+            let mac = seal(key, &last, hashes.len() as u64, &expected);
+            if footer["mac"].as_str() != Some(hex(&mac).as_str()) {
+                bail!("the final line was edited");
+            }
+            if file_hash(recording)?[..] != expected[..] {
+                bail!("{} was modified after recording", recording.display());
+            }
+            report.push(format!("{} unchanged since recording", recording.display()));
+        }
+        None if unfinished => report.push(
+            "recording did not finish normally, no file hash to compare (crash or power loss)"
+                .to_string(),
+        ),
+        None => bail!(
+            "the record has no final line: the recording did not finish normally or was truncated, \
+             pass --allow-unfinished to check the frames only"
+        ),
+    }
+
+    let mut capture = VideoCapture::from_file(&recording.to_string_lossy(), CAP_ANY)?;
+    if !capture.is_opened()? {
+        bail!("open {}", recording.display());
+    }
+    let counted = capture.get(videoio::CAP_PROP_FRAME_COUNT)?;
+    if counted > 0.0 && counted as usize != hashes.len() {
+        report.push(format!(
+            "warning: the container reports {} frames, the record has {}",
+            counted,
+            hashes.len()
+        ));
+    }
+    if frames {
+        let mut frame = Mat::default();
+        let mut index = 0;
+        while capture.read(&mut frame)? && !frame.empty() {
+            let Some(expected) = hashes.get(index) else {
+                bail!("the video has more frames than the record");
+            };
+            if pixels(&frame)? != *expected {
+                bail!("frame {} differs from the recorded pixels", index);
+            }
+            index += 1;
+        }
+        if index != hashes.len() {
+            bail!(
+                "the video has {} frames, the record has {}",
+                index,
+                hashes.len()
+            );
+        }
+        report.push(format!("all {} decoded frames match", index));
+    }
+    Ok(report)
+}
+
+fn link(key: &Key, previous: &[u8; 32], frame: u64, time: &str, pixels: &[u8]) -> [u8; 32] {
+    let mut data = previous.to_vec();
+    data.extend_from_slice(&frame.to_be_bytes());
+    data.extend_from_slice(time.as_bytes());
+    data.extend_from_slice(pixels);
+    key.0.sign(&data)
+}
+
+// 最后一行的 HMAC, 覆盖最后的 chain, 帧数和文件哈希.
+fn seal(key: &Key, last: &[u8; 32], frames: u64, file: &[u8]) -> [u8; 32] {
+    let mut data = last.to_vec();
+    data.extend_from_slice(&frames.to_be_bytes());
+    data.extend_from_slice(file);
+    key.0.sign(&data)
+}
+
+// 帧像素的 SHA-256, 不连续的 Mat (例如 ROI) 先复制.
+fn pixels(frame: &Mat) -> Result<Vec<u8>> {
+    let hash = if frame.is_continuous() {
+        Sha256::digest(frame.data_bytes()?)
+    } else {
+        Sha256::digest(frame.try_clone()?.data_bytes()?)
+    };
+    Ok(hash.to_vec())
+}
+
+fn file_hash(path: &Path) -> Result<[u8; 32]> {
+    let mut file = File::open(path).with_context(|| format!("open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            return Ok(hasher.finalize().into());
+        }
+        hasher.update(&buffer[..n]);
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(text: &str) -> Result<Vec<u8>> {
+    if text.len() % 2 != 0 || !text.bytes().all(|b| b.is_ascii_hexdigit()) {
+        bail!("invalid hash {:?}", text);
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&text[i..i + 2], 16)?))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::{
+        core::{Scalar, Size, CV_8UC3},
+        videoio::VideoWriter,
+    };
+
+    use super::*;
+
+    fn key(dir: &Path, text: &str) -> Result<Key> {
+        let path = dir.join(KEY_FILE);
+        fs::write(&path, text)?;
+        Key::load(&path)
+    }
+
+    // 三帧 MJPG 录像和它的完整性记录.
+    fn record(dir: &Path, key: &Key) -> PathBuf {
+        let recording = dir.join("clip.avi");
+        let info = SinkInfo {
+            fps: 10.0,
+            size: Size::new(64, 48),
+        };
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer =
+            VideoWriter::new(&recording.to_string_lossy(), fourcc, 10.0, info.size, true).unwrap();
+        let mut chain = Chain::create(&recording, "MJPG", &info, key.clone()).unwrap();
+        for level in [0., 100., 200.] {
+            let frame =
+                Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(level)).unwrap();
+            writer.write(&frame).unwrap();
+            chain.add(&frame).unwrap();
+        }
+        writer.release().unwrap();
+        chain.finish(&recording).unwrap();
+        recording
+    }
+
+    // 改写记录的第 line 行 (从 0 开始), 返回 None 时删除这一行.
+    fn edit(recording: &Path, line: usize, change: impl Fn(&str) -> Option<String>) {
+        let path = sidecar(recording);
+        let text = fs::read_to_string(&path).unwrap();
+        let lines: Vec<String> = text
+            .lines()
+            .enumerate()
+            .filter_map(|(i, text)| {
+                if i == line {
+                    change(text)
+                } else {
+                    Some(text.to_string())
+                }
+            })
+            .collect();
+        fs::write(&path, lines.join("\n") + "\n").unwrap();
+    }
+
+    fn error(recording: &Path, key: &Key, unfinished: bool) -> String {
+        verify(recording, key, false, unfinished)
+            .unwrap_err()
+            .to_string()
+    }
+
+    #[test]
+    fn hex_and_keys() {
+        assert_eq!(hex(&[0, 15, 255]), "000fff");
+        assert_eq!(unhex("000fff").unwrap(), [0, 15, 255]);
+        assert!(unhex("abc").is_err());
+        assert!(unhex("zz").is_err());
+        assert_eq!(
+            sidecar(Path::new("out/clip.mp4")),
+            Path::new("out/clip.mp4.integrity.jsonl")
+        );
+
+        let dir = std::env::temp_dir().join(format!("integrity-keys-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert!(key(&dir, &format!("{}\n", "ab".repeat(32))).is_ok());
+        assert!(key(&dir, &"ab".repeat(16)).is_err());
+        assert!(key(&dir, &"zz".repeat(32)).is_err());
+        assert!(Key::load(&dir.join("missing.key")).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 未修改的录像通过检查, 修改录像或记录的任何部分都会被发现.
+    #[test]
+    fn detects_tampering() {
+        let dir = std::env::temp_dir().join(format!("integrity-verify-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let key = key(&dir, &"ab".repeat(32)).unwrap();
+        let recording = record(&dir, &key);
+        let report = verify(&recording, &key, false, false).unwrap();
+        assert_eq!(report[0], "hash chain intact over 3 frames");
+        assert!(report[1].ends_with("unchanged since recording"));
+
+        let other = Key(Keyed::new(&[1; 32]));
+        assert!(error(&recording, &other, false).contains("hash chain broken at frame 0"));
+
+        let mut bytes = fs::read(&recording).unwrap();
+        bytes.push(0);
+        fs::write(&recording, &bytes).unwrap();
+        assert!(error(&recording, &key, false).contains("was modified after recording"));
+
+        let recording = record(&dir, &key);
+        edit(&recording, 2, |line| {
+            Some(line.replace("\"time\":\"2", "\"time\":\"1"))
+        });
+        assert!(error(&recording, &key, false).contains("hash chain broken at frame 1"));
+
+        let recording = record(&dir, &key);
+        edit(&recording, 2, |_| None);
+        assert!(error(&recording, &key, false).contains("frame 2 found where frame 1"));
+
+        // 没有最后一行时只在 unfinished 时检查帧.
+        let recording = record(&dir, &key);
+        edit(&recording, 4, |_| None);
+        assert!(error(&recording, &key, false).contains("no final line"));
+        let report = verify(&recording, &key, false, true).unwrap();
+        assert!(report[1].starts_with("recording did not finish normally"));
+
+        let recording = record(&dir, &key);
+        edit(&recording, 4, |line| {
+            Some(line.replace("\"frames\":3", "\"frames\":2"))
+        });
+        assert!(error(&recording, &key, false).contains("the record was truncated"));
+
+        let recording = record(&dir, &key);
+        edit(&recording, 0, |line| {
+            Some(line.replace("\"version\":2", "\"version\":1"))
+        });
+        assert!(error(&recording, &key, false).contains("unsupported integrity record version 1"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
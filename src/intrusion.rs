@@ -0,0 +1,593 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::PathBuf,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Datelike, Local, NaiveDateTime, NaiveTime, Weekday};
+use opencv::{
+    core::{Mat, Point, Scalar, Vector},
+    imgcodecs,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    detect::Detector,
+    events::EventStore,
+    fall::TelegramConfig,
+    pipeline::FrameProcessor,
+    tracking::{Track, Tracker, TrackingConfig},
+    zones::Zone,
+};
+
+// 周界防范: 跟踪行人, 按方向越过警戒线或从外面进入区域时按规则报警.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IntrusionConfig {
+    pub enabled: bool,
+    // 每隔多少帧运行一次行人检测.
+    pub detect_every: u32,
+    // 规则的 webhook 动作以 JSON POST 到这些地址.
+    pub alert_urls: Vec<String>,
+    // 规则的 telegram 动作发送到这里.
+    pub telegram: Option<TelegramConfig>,
+    // snapshot 动作保存画面的目录.
+    pub dir: PathBuf,
+    pub rules: Vec<Rule>,
+}
+
+impl Default for IntrusionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detect_every: 3,
+            alert_urls: Vec::new(),
+            telegram: None,
+            dir: PathBuf::from("intrusions"),
+            rules: Vec::new(),
+        }
+    }
+}
+
+// 越线方向: 沿警戒线从第一个点看向第二个点, forward 为从左侧走到右侧.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Both,
+    Forward,
+    Backward,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Snapshot,
+    Webhook,
+    Telegram,
+}
+
+// 一条规则, line 和 zone 二选一. 每次触发都会记录 intrusion 事件.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    // 警戒线的两个端点, 坐标为画面宽高的比例.
+    #[serde(default)]
+    pub line: Option<[[f64; 2]; 2]>,
+    #[serde(default)]
+    pub direction: Direction,
+    // [[zones]] 中的区域名称.
+    #[serde(default)]
+    pub zone: Option<String>,
+    // 布防时段, 为空时一直布防.
+    #[serde(default)]
+    pub armed: Vec<Period>,
+    #[serde(default)]
+    pub actions: Vec<Action>,
+    // 同一规则两次报警的最短间隔 (秒).
+    #[serde(default = "default_cooldown")]
+    pub cooldown: f64,
+}
+
+fn default_cooldown() -> f64 {
+    30.0
+}
+
+// 每天的一个时段 (本地时间 HH:MM), to 早于 from 时跨过午夜.
+// days 为开始那天的星期, 例如 ["mon", "fri"], 为空时每天.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Period {
+    pub from: String,
+    pub to: String,
+    #[serde(default)]
+    pub days: Vec<String>,
+}
+
+// 解析后的时段.
+#[derive(Debug, Clone)]
+pub struct Armed {
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+    pub days: Vec<Weekday>,
+}
+
+impl Armed {
+    pub fn parse(period: &Period) -> Result<Self> {
+        let time = |text: &str| {
+            NaiveTime::parse_from_str(text, "%H:%M")
+                .with_context(|| format!("armed time {:?}, expected HH:MM", text))
+        };
+        let days = period
+            .days
+            .iter()
+            .map(|day| Weekday::from_str(day).map_err(|_| anyhow!("unknown day {:?}", day)))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            from: time(&period.from)?,
+            to: time(&period.to)?,
+            days,
+        })
+    }
+
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        let day = |weekday: Weekday| self.days.is_empty() || self.days.contains(&weekday);
+        let now = time.time();
+        if self.from <= self.to {
+            return day(time.weekday()) && now >= self.from && now < self.to;
+        }
+        // 跨午夜: 午夜前算当天, 午夜后算前一天开始的时段.
+        (now >= self.from && day(time.weekday())) || (now < self.to && day(time.weekday().pred()))
+    }
+}
+
+// 点在有向线段的哪一侧: 画面坐标 y 向下, 正数为右侧.
+fn side(line: &[[f64; 2]; 2], point: [f64; 2]) -> f64 {
+    let [[ax, ay], [bx, by]] = *line;
+    (bx - ax) * (point[1] - ay) - (by - ay) * (point[0] - ax)
+}
+
+// 从 from 走到 to 是否穿过警戒线, 返回穿过的方向 (Forward 或 Backward).
+pub fn crossing(line: &[[f64; 2]; 2], from: [f64; 2], to: [f64; 2]) -> Option<Direction> {
+    let (before, after) = (side(line, from), side(line, to));
+    // 两段互相跨立时才相交, 刚好落在线上的一端不算, 等下一次更新越过后再判断.
+    if before * after >= 0.0 {
+        return None;
+    }
+    let step = [from, to];
+    if side(&step, line[0]) * side(&step, line[1]) > 0.0 {
+        return None;
+    }
+    Some(if before < 0.0 {
+        Direction::Forward
+    } else {
+        Direction::Backward
+    })
+}
+
+// 规则的触发条件.
+enum Trigger {
+    Line([[f64; 2]; 2], Direction),
+    Zone(Zone),
+}
+
+impl Trigger {
+    // 触发时返回方式: forward, backward 或 enter.
+    fn hit(&self, from: [f64; 2], to: [f64; 2]) -> Option<&'static str> {
+        match self {
+            Trigger::Line(line, direction) => match crossing(line, from, to)? {
+                crossed if *direction != Direction::Both && *direction != crossed => None,
+                Direction::Backward => Some("backward"),
+                _ => Some("forward"),
+            },
+            Trigger::Zone(zone) => {
+                (!zone.contains(from[0], from[1]) && zone.contains(to[0], to[1])).then_some("enter")
+            }
+        }
+    }
+}
+
+struct Prepared {
+    rule: Rule,
+    trigger: Trigger,
+    armed: Vec<Armed>,
+    last: Option<Instant>,
+}
+
+impl Prepared {
+    fn armed(&self, time: NaiveDateTime) -> bool {
+        self.armed.is_empty() || self.armed.iter().any(|armed| armed.contains(time))
+    }
+}
+
+pub struct Intrusion {
+    config: IntrusionConfig,
+    rules: Vec<Prepared>,
+    detector: Box<dyn Detector>,
+    tracker: Tracker,
+    tracks: Vec<Track>,
+    events: EventStore,
+    frames: u64,
+    // 每个目标上一次的位置, 比例坐标.
+    positions: HashMap<u64, [f64; 2]>,
+    // 最近报警的目标, 画面上高亮几秒.
+    alarms: Vec<(u64, Instant)>,
+}
+
+impl Intrusion {
+    pub fn new(
+        config: &IntrusionConfig,
+        zones: &[Zone],
+        tracking: &TrackingConfig,
+        detector: Box<dyn Detector>,
+        events: EventStore,
+    ) -> Result<Self> {
+        if config.rules.is_empty() {
+            bail!("add [[intrusion.rules]] with a line or a zone");
+        }
+        let mut rules = Vec::new();
+        for rule in &config.rules {
+            let trigger = match (&rule.line, &rule.zone) {
+                (Some(line), None) => Trigger::Line(*line, rule.direction),
+                (None, Some(name)) => {
+                    let zone = zones
+                        .iter()
+                        .find(|zone| &zone.name == name)
+                        .with_context(|| {
+                            format!("rule {} uses unknown zone {:?}", rule.name, name)
+                        })?;
+                    if zone.points.len() < 3 {
+                        bail!("zone {} needs at least 3 points", zone.name);
+                    }
+                    Trigger::Zone(zone.clone())
+                }
+                _ => bail!("rule {} needs either a line or a zone", rule.name),
+            };
+            if rule.actions.contains(&Action::Webhook) && config.alert_urls.is_empty() {
+                bail!(
+                    "rule {} uses webhook but intrusion.alert_urls is empty",
+                    rule.name
+                );
+            }
+            if rule.actions.contains(&Action::Telegram) && config.telegram.is_none() {
+                bail!(
+                    "rule {} uses telegram but [intrusion.telegram] is not set",
+                    rule.name
+                );
+            }
+            if rule.actions.contains(&Action::Snapshot) {
+                fs::create_dir_all(&config.dir)
+                    .with_context(|| format!("create {}", config.dir.display()))?;
+            }
+            let armed = rule
+                .armed
+                .iter()
+                .map(Armed::parse)
+                .collect::<Result<_>>()
+                .with_context(|| format!("rule {}", rule.name))?;
+            rules.push(Prepared {
+                rule: rule.clone(),
+                trigger,
+                armed,
+                last: None,
+            });
+        }
+        Ok(Self {
+            config: config.clone(),
+            rules,
+            detector,
+            tracker: Tracker::new(tracking),
+            tracks: Vec::new(),
+            events,
+            frames: 0,
+            positions: HashMap::new(),
+            alarms: Vec::new(),
+        })
+    }
+
+    // 记录事件并执行规则的动作, 发送提醒不阻塞采集.
+    fn fire(&mut self, index: usize, track: u64, how: &str, frame: &Mat) {
+        let rule = &self.rules[index].rule;
+        let time = Local::now();
+        let mut snapshot = None;
+        if rule.actions.contains(&Action::Snapshot) {
+            let path = self.config.dir.join(format!(
+                "{}-{}.jpg",
+                rule.name,
+                time.format("%Y-%m-%dT%H-%M-%S")
+            ));
+            match imgcodecs::imwrite(&path.to_string_lossy(), frame, &Vector::new()) {
+                Ok(true) => snapshot = Some(path.display().to_string()),
+                _ => eprintln!("intrusion: save {}", path.display()),
+            }
+        }
+        self.events.log(
+            "intrusion",
+            json!({
+                "rule": rule.name,
+                "track": track,
+                "how": how,
+                "snapshot": snapshot,
+            }),
+        );
+        self.alarms.push((track, Instant::now()));
+        let message = match how {
+            "enter" => format!("{}: person #{} entered", rule.name, track),
+            _ => format!("{}: person #{} crossed {}", rule.name, track, how),
+        };
+        let urls = if rule.actions.contains(&Action::Webhook) {
+            self.config.alert_urls.clone()
+        } else {
+            Vec::new()
+        };
+        let telegram = self
+            .config
+            .telegram
+            .clone()
+            .filter(|_| rule.actions.contains(&Action::Telegram));
+        if urls.is_empty() && telegram.is_none() {
+            return;
+        }
+        let body = json!({
+            "title": rule.name,
+            "message": message,
+            "time": time.to_rfc3339(),
+            "track": track,
+            "how": how,
+        })
+        .to_string();
+        let _ = thread::Builder::new()
+            .name("intrusion-alert".to_string())
+            .spawn(move || {
+                for url in urls {
+                    if let Err(err) = ureq::post(&url)
+                        .set("Content-Type", "application/json")
+                        .send_string(&body)
+                    {
+                        eprintln!("intrusion alert to {}: {:?}", url, err);
+                    }
+                }
+                if let Some(telegram) = telegram {
+                    if let Err(err) = telegram.send(&message) {
+                        eprintln!("intrusion: {:?}", err);
+                    }
+                }
+            });
+    }
+}
+
+impl FrameProcessor for Intrusion {
+    fn name(&self) -> &str {
+        "intrusion"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols() as f64, frame.rows() as f64);
+        if self.frames % self.config.detect_every.max(1) as u64 != 0 {
+            self.frames += 1;
+            return Ok(());
+        }
+        self.frames += 1;
+        let detections = self.detector.detect(frame)?;
+        self.tracker.update(&detections);
+        self.tracks = self
+            .tracker
+            .tracks()
+            .filter(|t| t.missed == 0)
+            .cloned()
+            .collect();
+        // 用底边中点 (脚的位置) 判断越线和进入.
+        let now = Local::now().naive_local();
+        let mut hits = Vec::new();
+        for track in &self.tracks {
+            let foot = track.foot();
+            let point = [foot.x as f64 / cols, foot.y as f64 / rows];
+            let Some(previous) = self.positions.insert(track.id, point) else {
+                continue;
+            };
+            for (index, rule) in self.rules.iter().enumerate() {
+                if !rule.armed(now) {
+                    continue;
+                }
+                if let Some(how) = rule.trigger.hit(previous, point) {
+                    hits.push((index, track.id, how));
+                }
+            }
+        }
+        let alive: Vec<u64> = self.tracker.tracks().map(|t| t.id).collect();
+        self.positions.retain(|id, _| alive.contains(id));
+        self.alarms
+            .retain(|(_, at)| at.elapsed() < Duration::from_secs(3));
+        for (index, track, how) in hits {
+            let cooldown = Duration::from_secs_f64(self.rules[index].rule.cooldown.max(0.0));
+            if self.rules[index]
+                .last
+                .is_some_and(|last| last.elapsed() < cooldown)
+            {
+                continue;
+            }
+            self.rules[index].last = Some(Instant::now());
+            self.fire(index, track, how, frame);
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 布防的规则为红色, 撤防为灰色; 警戒线上的箭头为报警的方向. 刚报警的目标为红框.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        let now = Local::now().naive_local();
+        let red = Scalar::new(0., 0., 255., 0.);
+        for rule in &self.rules {
+            let armed = rule.armed(now);
+            let color = if armed { red } else { Scalar::all(128.0) };
+            let origin = match &rule.trigger {
+                Trigger::Line(line, direction) => {
+                    let [a, b] = line.map(|[x, y]| {
+                        Point::new((x * cols as f64) as i32, (y * rows as f64) as i32)
+                    });
+                    imgproc::line(frame, a, b, color, 2, LINE_AA, 0)?;
+                    // 右侧的法线, 长 30 像素.
+                    let (dx, dy) = ((b.x - a.x) as f64, (b.y - a.y) as f64);
+                    let length = dx.hypot(dy).max(1.0);
+                    let normal =
+                        Point::new((-dy / length * 30.0) as i32, (dx / length * 30.0) as i32);
+                    let middle = Point::new((a.x + b.x) / 2, (a.y + b.y) / 2);
+                    if *direction != Direction::Backward {
+                        imgproc::arrowed_line(
+                            frame,
+                            middle,
+                            middle + normal,
+                            color,
+                            2,
+                            LINE_AA,
+                            0,
+                            0.3,
+                        )?;
+                    }
+                    if *direction != Direction::Forward {
+                        imgproc::arrowed_line(
+                            frame,
+                            middle,
+                            middle - normal,
+                            color,
+                            2,
+                            LINE_AA,
+                            0,
+                            0.3,
+                        )?;
+                    }
+                    a
+                }
+                Trigger::Zone(zone) => {
+                    let points = zone.pixels(cols, rows);
+                    let mut polygons = Vector::<Vector<Point>>::new();
+                    polygons.push(points.clone());
+                    imgproc::polylines(frame, &polygons, true, color, 2, LINE_AA, 0)?;
+                    points.get(0)?
+                }
+            };
+            let label = if armed {
+                rule.rule.name.clone()
+            } else {
+                format!("{} (disarmed)", rule.rule.name)
+            };
+            imgproc::put_text(
+                frame,
+                &label,
+                Point::new(origin.x + 4, origin.y - 8),
+                FONT_HERSHEY_SIMPLEX,
+                0.6,
+                color,
+                2,
+                LINE_AA,
+                false,
+            )?;
+        }
+        for track in &self.tracks {
+            let alarm = self.alarms.iter().any(|(id, _)| *id == track.id);
+            let color = if alarm {
+                red
+            } else {
+                Scalar::new(0., 200., 0., 0.)
+            };
+            imgproc::rectangle(
+                frame,
+                track.rect,
+                color,
+                if alarm { 3 } else { 2 },
+                LINE_AA,
+                0,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.tracks.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    // 从左往右的水平线, 画面 y 向下, 所以从上往下走是 forward.
+    const LINE: [[f64; 2]; 2] = [[0.2, 0.5], [0.8, 0.5]];
+
+    #[test]
+    fn line_crossing() {
+        assert_eq!(
+            crossing(&LINE, [0.5, 0.3], [0.5, 0.7]),
+            Some(Direction::Forward)
+        );
+        assert_eq!(
+            crossing(&LINE, [0.5, 0.7], [0.4, 0.3]),
+            Some(Direction::Backward)
+        );
+        // 从线段端点外绕过, 停在线上, 没有越过.
+        assert_eq!(crossing(&LINE, [0.9, 0.3], [0.9, 0.7]), None);
+        assert_eq!(crossing(&LINE, [0.5, 0.3], [0.5, 0.5]), None);
+        assert_eq!(crossing(&LINE, [0.5, 0.3], [0.6, 0.4]), None);
+    }
+
+    #[test]
+    fn triggers() {
+        let forward = Trigger::Line(LINE, Direction::Forward);
+        assert_eq!(forward.hit([0.5, 0.3], [0.5, 0.7]), Some("forward"));
+        assert_eq!(forward.hit([0.5, 0.7], [0.5, 0.3]), None);
+        let both = Trigger::Line(LINE, Direction::Both);
+        assert_eq!(both.hit([0.5, 0.7], [0.5, 0.3]), Some("backward"));
+        let zone = Trigger::Zone(Zone {
+            name: "yard".to_string(),
+            points: vec![[0.0, 0.0], [0.5, 0.0], [0.5, 0.5], [0.0, 0.5]],
+        });
+        assert_eq!(zone.hit([0.7, 0.2], [0.3, 0.2]), Some("enter"));
+        assert_eq!(zone.hit([0.1, 0.2], [0.3, 0.2]), None);
+        assert_eq!(zone.hit([0.3, 0.2], [0.7, 0.2]), None);
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> NaiveDateTime {
+        // 2024-03-01 是星期五.
+        NaiveDate::from_ymd_opt(2024, 3, day)
+            .unwrap()
+            .and_hms_opt(hour, minute, 0)
+            .unwrap()
+    }
+
+    fn period(from: &str, to: &str, days: &[&str]) -> Period {
+        Period {
+            from: from.to_string(),
+            to: to.to_string(),
+            days: days.iter().map(|day| day.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn armed_periods() {
+        let office = Armed::parse(&period("09:00", "17:00", &[])).unwrap();
+        assert!(office.contains(at(1, 9, 0)));
+        assert!(!office.contains(at(1, 17, 0)));
+        // 星期五晚上开始的时段持续到星期六早上.
+        let night = Armed::parse(&period("22:00", "06:00", &["fri"])).unwrap();
+        assert!(night.contains(at(1, 23, 0)));
+        assert!(night.contains(at(2, 5, 59)));
+        assert!(!night.contains(at(1, 5, 0)));
+        assert!(!night.contains(at(2, 23, 0)));
+        assert!(Armed::parse(&period("25:00", "06:00", &[])).is_err());
+        assert!(Armed::parse(&period("22:00", "06:00", &["someday"])).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use anyhow::{bail, Result};
+use slint::Weak;
+
+use crate::{capture::set_status, threads::ThreadConfig, Main};
+
+// 面板中保留的已结束任务数, 更早的自动移除.
+const KEEP_FINISHED: usize = 10;
+
+// 后台任务面板: 全景拼接, 高质量静态图合成, 定格动画导出, 统计导出, 崩溃报告上传和模型下载.
+// 执行任务的线程更新这张表, 界面每秒读取一次. 任务结束时在状态栏通知.
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Running,
+    Done,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct Job {
+    pub id: u64,
+    pub name: String,
+    pub state: State,
+    // 0~1 的进度, 不报告进度或进度未知时为 None.
+    pub progress: Option<f64>,
+    // 报告过进度的任务在每次报告时检查取消, 只有它们可以取消.
+    reporting: bool,
+    started: Instant,
+    // 结束时的耗时 (秒).
+    elapsed: Option<f64>,
+    // 结果或错误.
+    pub message: Option<String>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Job {
+    pub fn cancellable(&self) -> bool {
+        self.state == State::Running && self.reporting && !self.cancel.load(Ordering::Relaxed)
+    }
+
+    // 面板中显示的一行.
+    pub fn describe(&self) -> String {
+        let elapsed = self
+            .elapsed
+            .unwrap_or_else(|| self.started.elapsed().as_secs_f64());
+        let mut text = match self.state {
+            State::Running if self.cancel.load(Ordering::Relaxed) => {
+                format!("{}: cancelling", self.name)
+            }
+            State::Running => match self.progress {
+                Some(progress) => {
+                    format!("{}: {:.0}%, {:.0} s", self.name, progress * 100.0, elapsed)
+                }
+                None => format!("{}: running {:.0} s", self.name, elapsed),
+            },
+            State::Done => format!("{}: done in {:.0} s", self.name, elapsed),
+            State::Failed => format!("{}: failed after {:.0} s", self.name, elapsed),
+            State::Cancelled => format!("{}: cancelled", self.name),
+        };
+        if let Some(message) = &self.message {
+            text += &format!("\n{}", message);
+        }
+        text
+    }
+}
+
+static JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+// 在当前线程中执行的任务, 例如每天定时生成的汇总视频.
+pub struct Handle {
+    id: u64,
+    name: String,
+    cancel: Arc<AtomicBool>,
+}
+
+impl Handle {
+    // 报告进度, 任务被取消时返回错误, 调用者应停止并清理.
+    pub fn progress(&self, progress: Option<f64>) -> Result<()> {
+        update(self.id, |job| {
+            job.progress = progress.map(|progress| progress.clamp(0.0, 1.0));
+            job.reporting = true;
+        });
+        if self.cancel.load(Ordering::Relaxed) {
+            bail!("cancelled");
+        }
+        Ok(())
+    }
+
+    // 成功结束, 返回状态栏中的通知.
+    pub fn done(self, message: String) -> String {
+        self.finish(State::Done, message.clone());
+        message
+    }
+
+    pub fn failed(self, err: &anyhow::Error) -> String {
+        if self.cancel.load(Ordering::Relaxed) {
+            self.finish(State::Cancelled, String::new());
+            return format!("{} cancelled", self.name);
+        }
+        let text = format!("{} failed: {}", self.name, err);
+        self.finish(State::Failed, err.to_string());
+        text
+    }
+
+    fn finish(&self, state: State, message: String) {
+        update(self.id, |job| {
+            job.state = state;
+            job.elapsed = Some(job.started.elapsed().as_secs_f64());
+            job.message = (!message.is_empty()).then_some(message);
+        });
+        let mut jobs = JOBS.lock().unwrap();
+        let finished = jobs
+            .iter()
+            .filter(|job| job.state != State::Running)
+            .count();
+        let mut excess = finished.saturating_sub(KEEP_FINISHED);
+        jobs.retain(|job| {
+            let remove = excess > 0 && job.state != State::Running;
+            excess -= remove as usize;
+            !remove
+        });
+    }
+}
+
+impl Drop for Handle {
+    // 没有调用 done 或 failed 就结束 (panic) 的任务记为失败.
+    fn drop(&mut self) {
+        let running = JOBS
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|job| job.id == self.id && job.state == State::Running);
+        if running {
+            self.finish(State::Failed, "interrupted".to_string());
+        }
+    }
+}
+
+// 登记一个新任务.
+pub fn start(name: impl Into<String>) -> Handle {
+    let name = name.into();
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    let cancel = Arc::new(AtomicBool::new(false));
+    JOBS.lock().unwrap().push(Job {
+        id,
+        name: name.clone(),
+        state: State::Running,
+        progress: None,
+        reporting: false,
+        started: Instant::now(),
+        elapsed: None,
+        message: None,
+        cancel: cancel.clone(),
+    });
+    Handle { id, name, cancel }
+}
+
+// 在后台线程中执行任务, 结束时在状态栏显示 work 返回的结果或错误.
+pub fn spawn<F>(window: &Weak<Main>, name: &str, workers: &ThreadConfig, work: F)
+where
+    F: FnOnce(&Handle) -> Result<String> + Send + 'static,
+{
+    let job = start(name);
+    let (window, workers) = (window.clone(), workers.clone());
+    thread::spawn(move || {
+        workers.apply(&job.name);
+        let text = match work(&job) {
+            Ok(message) => job.done(message),
+            Err(err) => job.failed(&err),
+        };
+        set_status(&window, text);
+    });
+}
+
+fn update(id: u64, update: impl FnOnce(&mut Job)) {
+    if let Some(job) = JOBS.lock().unwrap().iter_mut().find(|job| job.id == id) {
+        update(job);
+    }
+}
+
+// 按开始顺序排列的所有任务.
+pub fn list() -> Vec<Job> {
+    JOBS.lock().unwrap().clone()
+}
+
+pub fn cancel(id: u64) {
+    if let Some(job) = JOBS.lock().unwrap().iter().find(|job| job.id == id) {
+        job.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+// 移除已结束的任务.
+pub fn clear() {
+    JOBS.lock()
+        .unwrap()
+        .retain(|job| job.state == State::Running);
+}
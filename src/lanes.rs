@@ -0,0 +1,328 @@
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size, Vec4i, Vector, CV_8UC1},
+    imgproc::{self, COLOR_BGR2GRAY, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LaneConfig {
+    pub enabled: bool,
+    // 梯形 ROI, 取值为画面宽高的比例: 顶边的 y、顶边左右 x、底边左右 x.
+    pub roi_top: f64,
+    pub roi_top_left: f64,
+    pub roi_top_right: f64,
+    pub roi_bottom_left: f64,
+    pub roi_bottom_right: f64,
+    pub canny_low: f64,
+    pub canny_high: f64,
+    pub hough_threshold: i32,
+    pub min_line_length: f64,
+    pub max_line_gap: f64,
+    // 斜率绝对值小于该值的线段 (接近水平) 被忽略.
+    pub min_slope: f64,
+    // 拟合结果的时间平滑系数, 越大越平滑.
+    pub smoothing: f64,
+}
+
+impl Default for LaneConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            roi_top: 0.6,
+            roi_top_left: 0.42,
+            roi_top_right: 0.58,
+            roi_bottom_left: 0.05,
+            roi_bottom_right: 0.95,
+            canny_low: 50.0,
+            canny_high: 150.0,
+            hough_threshold: 30,
+            min_line_length: 20.0,
+            max_line_gap: 100.0,
+            min_slope: 0.3,
+            smoothing: 0.8,
+        }
+    }
+}
+
+// 车道线检测预设: 梯形 ROI + Canny + 概率霍夫变换 + 二次多项式拟合 x = f(y).
+pub struct LaneDetection {
+    config: LaneConfig,
+    gray: Mat,
+    blurred: Mat,
+    edges: Mat,
+    masked: Mat,
+    left: Option<[f64; 3]>,
+    right: Option<[f64; 3]>,
+}
+
+impl LaneDetection {
+    pub fn new(config: &LaneConfig) -> Self {
+        Self {
+            config: config.clone(),
+            gray: Mat::default(),
+            blurred: Mat::default(),
+            edges: Mat::default(),
+            masked: Mat::default(),
+            left: None,
+            right: None,
+        }
+    }
+
+    fn roi(&self, cols: i32, rows: i32) -> Vector<Point> {
+        let c = &self.config;
+        let (w, h) = (cols as f64, rows as f64);
+        Vector::from_slice(&[
+            Point::new((c.roi_bottom_left * w) as i32, rows),
+            Point::new((c.roi_top_left * w) as i32, (c.roi_top * h) as i32),
+            Point::new((c.roi_top_right * w) as i32, (c.roi_top * h) as i32),
+            Point::new((c.roi_bottom_right * w) as i32, rows),
+        ])
+    }
+}
+
+impl FrameProcessor for LaneDetection {
+    fn name(&self) -> &str {
+        "lanes"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        imgproc::gaussian_blur(
+            &self.gray,
+            &mut self.blurred,
+            Size::new(5, 5),
+            0.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )?;
+        imgproc::canny(
+            &self.blurred,
+            &mut self.edges,
+            self.config.canny_low,
+            self.config.canny_high,
+            3,
+            false,
+        )?;
+
+        let roi = self.roi(cols, rows);
+        let mut mask = Mat::new_rows_cols_with_default(rows, cols, CV_8UC1, Scalar::all(0.))?;
+        let mut polygons = Vector::<Vector<Point>>::new();
+        polygons.push(roi.clone());
+        imgproc::fill_poly(
+            &mut mask,
+            &polygons,
+            Scalar::all(255.),
+            imgproc::LINE_8,
+            0,
+            Point::new(0, 0),
+        )?;
+        core::bitwise_and(&self.edges, &mask, &mut self.masked, &core::no_array())?;
+
+        let mut lines = Vector::<Vec4i>::new();
+        imgproc::hough_lines_p(
+            &self.masked,
+            &mut lines,
+            1.0,
+            std::f64::consts::PI / 180.0,
+            self.config.hough_threshold,
+            self.config.min_line_length,
+            self.config.max_line_gap,
+        )?;
+
+        // 按斜率和所在半边分成左右两组端点.
+        let center = cols as f64 / 2.0;
+        let (mut left, mut right) = (Vec::new(), Vec::new());
+        for l in lines.iter() {
+            let (x1, y1, x2, y2) = (l[0] as f64, l[1] as f64, l[2] as f64, l[3] as f64);
+            if x1 == x2 {
+                continue;
+            }
+            let slope = (y2 - y1) / (x2 - x1);
+            if slope.abs() < self.config.min_slope {
+                continue;
+            }
+            if slope < 0.0 && x1 < center && x2 < center {
+                left.extend([(y1, x1), (y2, x2)]);
+            } else if slope > 0.0 && x1 > center && x2 > center {
+                right.extend([(y1, x1), (y2, x2)]);
+            }
+        }
+        let smoothing = self.config.smoothing;
+        self.left = smooth(self.left, polyfit(&left), smoothing);
+        self.right = smooth(self.right, polyfit(&right), smoothing);
+
+        let top = (self.config.roi_top * rows as f64) as i32;
+        let curve = |coeffs: &[f64; 3]| -> Vec<Point> {
+            (top..=rows)
+                .step_by(10)
+                .map(|y| {
+                    let yf = y as f64;
+                    Point::new((coeffs[0] * yf * yf + coeffs[1] * yf + coeffs[2]) as i32, y)
+                })
+                .collect()
+        };
+        let (left, right) = (
+            self.left.as_ref().map(curve),
+            self.right.as_ref().map(curve),
+        );
+
+        // 两条线都检测到时填充车道区域.
+        if let (Some(l), Some(r)) = (&left, &right) {
+            let mut area: Vec<Point> = l.clone();
+            area.extend(r.iter().rev());
+            let mut polygons = Vector::<Vector<Point>>::new();
+            polygons.push(Vector::from_slice(&area));
+            let mut overlay = frame.try_clone()?;
+            imgproc::fill_poly(
+                &mut overlay,
+                &polygons,
+                Scalar::new(0., 200., 0., 0.),
+                LINE_AA,
+                0,
+                Point::new(0, 0),
+            )?;
+            let mut blended = Mat::default();
+            core::add_weighted(frame, 0.7, &overlay, 0.3, 0.0, &mut blended, -1)?;
+            blended.copy_to(frame)?;
+        }
+        for points in left.iter().chain(right.iter()) {
+            let mut curves = Vector::<Vector<Point>>::new();
+            curves.push(Vector::from_slice(points));
+            imgproc::polylines(
+                frame,
+                &curves,
+                false,
+                Scalar::new(0., 255., 255., 0.),
+                4,
+                LINE_AA,
+                0,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn smooth(previous: Option<[f64; 3]>, current: Option<[f64; 3]>, alpha: f64) -> Option<[f64; 3]> {
+    match (previous, current) {
+        (Some(p), Some(c)) => Some([
+            p[0] * alpha + c[0] * (1.0 - alpha),
+            p[1] * alpha + c[1] * (1.0 - alpha),
+            p[2] * alpha + c[2] * (1.0 - alpha),
+        ]),
+        (_, current) => current,
+    }
+}
+
+// 最小二乘拟合 x = a*y^2 + b*y + c, 点太少时退化为直线.
+fn polyfit(points: &[(f64, f64)]) -> Option<[f64; 3]> {
+    if points.len() < 2 {
+        return None;
+    }
+    let degree = if points.len() >= 6 { 2 } else { 1 };
+    let n = degree + 1;
+    let mut ata = [[0.0; 3]; 3];
+    let mut atb = [0.0; 3];
+    for &(y, x) in points {
+        let powers = [1.0, y, y * y];
+        for i in 0..n {
+            for j in 0..n {
+                ata[i][j] += powers[i] * powers[j];
+            }
+            atb[i] += powers[i] * x;
+        }
+    }
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&a, &b| ata[a][col].abs().total_cmp(&ata[b][col].abs()))?;
+        if ata[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        ata.swap(col, pivot);
+        atb.swap(col, pivot);
+        for row in 0..n {
+            if row != col {
+                let factor = ata[row][col] / ata[col][col];
+                for k in 0..n {
+                    ata[row][k] -= factor * ata[col][k];
+                }
+                atb[row] -= factor * atb[col];
+            }
+        }
+    }
+    let solved: Vec<f64> = (0..n).map(|i| atb[i] / ata[i][i]).collect();
+    // 返回 [a, b, c] 形式.
+    Some(match degree {
+        2 => [solved[2], solved[1], solved[0]],
+        _ => [0.0, solved[1], solved[0]],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::CV_8UC3;
+
+    use super::*;
+
+    fn x_at(coeffs: [f64; 3], y: f64) -> f64 {
+        coeffs[0] * y * y + coeffs[1] * y + coeffs[2]
+    }
+
+    // 点够多时拟合弯道, 少于 6 个点时拟合直线, 同一高度的点无法拟合.
+    #[test]
+    fn fit_curves() {
+        let curve: Vec<(f64, f64)> = (0..8)
+            .map(|i| {
+                let y = 300.0 + 25.0 * i as f64;
+                (y, 0.002 * y * y - 1.5 * y + 400.0)
+            })
+            .collect();
+        let [a, b, c] = polyfit(&curve).unwrap();
+        assert!((a - 0.002).abs() < 1e-9 && (b + 1.5).abs() < 1e-6 && (c - 400.0).abs() < 1e-3);
+        let line = polyfit(&[(300.0, 280.0), (480.0, 100.0)]).unwrap();
+        assert_eq!(line[0], 0.0);
+        assert!((x_at(line, 390.0) - 190.0).abs() < 1e-9);
+        assert_eq!(polyfit(&[(300.0, 280.0)]), None);
+        assert_eq!(polyfit(&[(300.0, 280.0), (300.0, 200.0)]), None);
+    }
+
+    #[test]
+    fn smoothing() {
+        let previous = Some([0.0, 1.0, 100.0]);
+        assert_eq!(
+            smooth(previous, Some([0.0, 0.0, 200.0]), 0.75),
+            Some([0.0, 0.75, 125.0])
+        );
+        // 这一帧没有检测到时不再显示, 重新检测到时直接采用.
+        assert_eq!(smooth(previous, None, 0.75), None);
+        assert_eq!(smooth(None, previous, 0.75), previous);
+    }
+
+    // 路面上左右两条白线, 斜率相反, 分别落在画面两侧.
+    #[test]
+    fn finds_both_lanes() {
+        let mut frame =
+            Mat::new_rows_cols_with_default(480, 640, CV_8UC3, Scalar::all(40.)).unwrap();
+        for (from, to) in [((100, 480), (280, 300)), ((540, 480), (360, 300))] {
+            imgproc::line(
+                &mut frame,
+                Point::new(from.0, from.1),
+                Point::new(to.0, to.1),
+                Scalar::all(255.),
+                6,
+                imgproc::LINE_8,
+                0,
+            )
+            .unwrap();
+        }
+        let mut lanes = LaneDetection::new(&LaneConfig::default());
+        lanes.process(&mut frame).unwrap();
+        let (left, right) = (lanes.left.unwrap(), lanes.right.unwrap());
+        assert!((x_at(left, 470.0) - 110.0).abs() < 8.0, "{:?}", left);
+        assert!((x_at(right, 470.0) - 530.0).abs() < 8.0, "{:?}", right);
+    }
+}
@@ -0,0 +1,162 @@
+// 模块都是内部实现, 库接口见下面的 pub use. doc(hidden) 的模块只供命令行程序 (main.rs) 使用.
+pub(crate) mod api;
+pub(crate) mod app;
+pub(crate) mod ar;
+pub(crate) mod audio;
+#[doc(hidden)]
+pub mod auth;
+pub(crate) mod backlight;
+#[doc(hidden)]
+pub mod batch;
+pub(crate) mod best_shot;
+pub(crate) mod bitrate;
+#[doc(hidden)]
+pub mod camera;
+pub(crate) mod captions;
+pub(crate) mod capture;
+pub(crate) mod change;
+pub(crate) mod chroma_key;
+pub(crate) mod classify;
+pub(crate) mod clips;
+pub(crate) mod clock;
+pub(crate) mod color;
+pub(crate) mod color_match;
+#[doc(hidden)]
+pub mod compare;
+pub(crate) mod config;
+pub(crate) mod connections;
+pub(crate) mod controls;
+#[doc(hidden)]
+pub mod crash;
+pub(crate) mod daily_summary;
+pub(crate) mod day_night;
+pub(crate) mod denoise;
+pub(crate) mod detect;
+pub(crate) mod detection;
+#[cfg(feature = "dnn")]
+pub(crate) mod dnn;
+#[doc(hidden)]
+pub mod doctor;
+pub(crate) mod effects;
+#[doc(hidden)]
+pub mod encryption;
+pub(crate) mod events;
+pub(crate) mod exclusions;
+pub(crate) mod exif;
+pub(crate) mod fall;
+pub(crate) mod faults;
+pub(crate) mod feed;
+pub(crate) mod flat_field;
+pub(crate) mod frames;
+pub(crate) mod framing;
+pub(crate) mod gauges;
+#[cfg(test)]
+mod golden;
+pub(crate) mod gps;
+pub(crate) mod health;
+pub(crate) mod high_speed;
+pub(crate) mod hmac;
+pub(crate) mod hot_pixels;
+pub(crate) mod infrared;
+#[doc(hidden)]
+pub mod integrity;
+pub(crate) mod intrusion;
+pub(crate) mod jobs;
+pub(crate) mod lanes;
+pub(crate) mod libcamera;
+#[doc(hidden)]
+pub mod mdns;
+pub(crate) mod measure;
+pub(crate) mod meteor;
+pub(crate) mod metrics;
+pub(crate) mod migrate;
+pub(crate) mod models;
+pub(crate) mod monitor;
+pub(crate) mod motion_filter;
+pub(crate) mod mqtt;
+pub(crate) mod occupancy;
+pub(crate) mod ocr;
+pub(crate) mod onion;
+#[cfg(feature = "onnxruntime")]
+pub(crate) mod onnx;
+pub(crate) mod osd;
+pub(crate) mod palette;
+pub(crate) mod panorama;
+pub(crate) mod parking;
+pub(crate) mod photo;
+pub(crate) mod pipeline;
+pub(crate) mod playback;
+#[doc(hidden)]
+pub mod portable;
+pub(crate) mod pose;
+pub(crate) mod ppe;
+pub(crate) mod presentation;
+pub(crate) mod printer;
+pub(crate) mod privacy;
+pub(crate) mod profile;
+pub(crate) mod projection;
+#[cfg(test)]
+mod properties;
+pub(crate) mod queue;
+pub(crate) mod raw;
+pub(crate) mod reaction;
+#[cfg(feature = "record")]
+pub(crate) mod recorder;
+pub(crate) mod remote;
+pub(crate) mod restore;
+pub(crate) mod retention;
+pub(crate) mod review;
+pub(crate) mod routing;
+pub(crate) mod scenes;
+pub(crate) mod segment;
+#[doc(hidden)]
+pub mod session;
+pub(crate) mod sink;
+pub(crate) mod slate;
+#[doc(hidden)]
+pub mod soak;
+pub(crate) mod source;
+pub(crate) mod speed;
+pub(crate) mod spotlight;
+pub(crate) mod stages;
+pub(crate) mod stats;
+pub(crate) mod still;
+pub(crate) mod stop_motion;
+pub(crate) mod storage;
+pub(crate) mod stream;
+pub(crate) mod summary;
+pub(crate) mod supervisor;
+pub(crate) mod sync;
+pub(crate) mod synthetic;
+pub(crate) mod tamper;
+pub(crate) mod telemetry;
+pub(crate) mod threads;
+pub(crate) mod throttle;
+pub(crate) mod thumbnail;
+pub(crate) mod timeshift;
+pub(crate) mod tracking;
+pub(crate) mod trajectory;
+pub(crate) mod update;
+pub(crate) mod waveform;
+pub(crate) mod white_balance;
+pub(crate) mod wildlife;
+#[doc(hidden)]
+pub mod wizard;
+pub(crate) mod zones;
+
+// 嵌入到其他 Slint 应用时使用的接口, 见 README 的 "Using as a library".
+pub use app::App;
+pub use config::{Config, CONFIG_FILE};
+pub use effects::{ColorMap, Effect};
+pub use events::Event;
+pub use feed::{Active, Frame, VideoFeed};
+#[cfg(feature = "record")]
+pub use recorder::{Recorder, RecordingOptions, RecordingReport};
+pub use sink::{register as register_sink, FrameSink, SinkConfig, SinkInfo};
+pub use source::{register as register_source, FrameSource, Metadata};
+pub use telemetry::Telemetry;
+
+#[cfg(all(feature = "directml", not(target_os = "windows")))]
+compile_error!("the directml feature is only available on Windows");
+
+slint::include_modules!();
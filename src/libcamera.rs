@@ -0,0 +1,201 @@
+use std::{
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+};
+
+use anyhow::{Context, Result};
+use opencv::videoio::{self, VideoCapture};
+use serde::Deserialize;
+
+// Raspberry Pi 摄像头 (libcamera) 的读取方式.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Method {
+    // GStreamer libcamerasrc 元素.
+    #[default]
+    Gstreamer,
+    // 启动 rpicam-vid, 从它的标准输出读取 H.264 码流.
+    RpicamVid,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LibcameraConfig {
+    pub enabled: bool,
+    pub method: Method,
+    pub width: i32,
+    pub height: i32,
+    pub framerate: i32,
+    // 传感器模式 "宽:高:位深:打包", 例如 "2304:1296:10:P", 只适用于 rpicam-vid.
+    pub mode: Option<String>,
+    // 把硬件编码的 H.264 原样保存到该文件 (Matroska), 不需要重新编码.
+    pub h264_file: Option<PathBuf>,
+    // H.264 码率 (bit/s), 不设置时使用 rpicam-vid 的默认值.
+    pub bitrate: Option<u32>,
+}
+
+impl Default for LibcameraConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: Method::default(),
+            width: 1280,
+            height: 720,
+            framerate: 30,
+            mode: None,
+            h264_file: None,
+            bitrate: None,
+        }
+    }
+}
+
+// 转换为 BGR 交给 OpenCV, drop=true 避免处理慢时帧堆积.
+pub const APPSINK: &str = "videoconvert ! video/x-raw,format=BGR ! appsink drop=true max-buffers=2";
+
+// 持有 rpicam-vid 子进程, drop 时结束它.
+pub struct Libcamera {
+    child: Option<Child>,
+}
+
+impl Libcamera {
+    pub fn open(config: &LibcameraConfig) -> Result<(VideoCapture, Self)> {
+        let (pipeline, child) = match config.method {
+            Method::Gstreamer => {
+                if config.mode.is_some() {
+                    eprintln!("libcamera: sensor mode is only supported with rpicam-vid");
+                }
+                (Self::gstreamer(config), None)
+            }
+            Method::RpicamVid => {
+                let child = Self::rpicam_vid(config)?;
+                let fd = stdout_fd(&child)?;
+                (Self::pipe(config, fd), Some(child))
+            }
+        };
+        let libcamera = Self { child };
+        let camera = VideoCapture::from_file(&pipeline, videoio::CAP_GSTREAMER)
+            .with_context(|| format!("open GStreamer pipeline {}", pipeline))?;
+        Ok((camera, libcamera))
+    }
+
+    fn caps(config: &LibcameraConfig) -> String {
+        format!(
+            "video/x-raw,width={},height={},framerate={}/1",
+            config.width, config.height, config.framerate
+        )
+    }
+
+    fn gstreamer(config: &LibcameraConfig) -> String {
+        let source = format!("libcamerasrc ! {}", Self::caps(config));
+        match &config.h264_file {
+            // 用 tee 分出一路交给硬件编码器 v4l2h264enc 保存.
+            Some(file) => format!(
+                "{} ! tee name=t t. ! queue ! {} t. ! queue ! v4l2h264enc ! \
+                 video/x-h264,level=(string)4 ! h264parse ! matroskamux ! filesink location={}",
+                source,
+                APPSINK,
+                file.display()
+            ),
+            None => format!("{} ! {}", source, APPSINK),
+        }
+    }
+
+    // rpicam-vid 已经输出硬件编码的 H.264, 解码一路用于处理, 另一路直接写文件.
+    fn pipe(config: &LibcameraConfig, fd: i32) -> String {
+        let source = format!("fdsrc fd={} ! h264parse", fd);
+        let decode = format!("avdec_h264 ! {}", APPSINK);
+        match &config.h264_file {
+            // Matroska 即使没有正常结束也可以播放.
+            Some(file) => format!(
+                "{} ! tee name=t t. ! queue ! {} t. ! queue ! matroskamux ! filesink location={}",
+                source,
+                decode,
+                file.display()
+            ),
+            None => format!("{} ! {}", source, decode),
+        }
+    }
+
+    fn rpicam_vid(config: &LibcameraConfig) -> Result<Child> {
+        let mut command = Command::new("rpicam-vid");
+        command
+            .args(["-t", "0", "--nopreview", "--codec", "h264", "--inline"])
+            .args(["--width", &config.width.to_string()])
+            .args(["--height", &config.height.to_string()])
+            .args(["--framerate", &config.framerate.to_string()]);
+        if let Some(mode) = &config.mode {
+            command.args(["--mode", mode]);
+        }
+        if let Some(bitrate) = config.bitrate {
+            command.args(["--bitrate", &bitrate.to_string()]);
+        }
+        command
+            .args(["-o", "-"])
+            .stdout(Stdio::piped())
+            .spawn()
+            .context("start rpicam-vid")
+    }
+}
+
+#[cfg(unix)]
+fn stdout_fd(child: &Child) -> Result<i32> {
+    use std::os::fd::AsRawFd;
+    Ok(child
+        .stdout
+        .as_ref()
+        .context("rpicam-vid stdout")?
+        .as_raw_fd())
+}
+
+#[cfg(not(unix))]
+fn stdout_fd(_child: &Child) -> Result<i32> {
+    anyhow::bail!("rpicam-vid is only supported on Linux")
+}
+
+impl Drop for Libcamera {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gstreamer_pipeline() {
+        let mut config = LibcameraConfig::default();
+        assert_eq!(
+            Libcamera::gstreamer(&config),
+            format!(
+                "libcamerasrc ! video/x-raw,width=1280,height=720,framerate=30/1 ! {}",
+                APPSINK
+            )
+        );
+        config.h264_file = Some(PathBuf::from("out.mkv"));
+        let pipeline = Libcamera::gstreamer(&config);
+        assert!(pipeline.contains("tee name=t"));
+        assert!(pipeline.contains("v4l2h264enc"));
+        assert!(pipeline.ends_with("matroskamux ! filesink location=out.mkv"));
+    }
+
+    // rpicam-vid 已经编码, 保存时不再经过编码器.
+    #[test]
+    fn rpicam_vid_pipeline() {
+        let mut config: LibcameraConfig =
+            toml::from_str("method = \"rpicam-vid\"\nmode = \"2304:1296:10:P\"").unwrap();
+        assert_eq!(config.method, Method::RpicamVid);
+        assert_eq!(
+            Libcamera::pipe(&config, 7),
+            format!("fdsrc fd=7 ! h264parse ! avdec_h264 ! {}", APPSINK)
+        );
+        config.h264_file = Some(PathBuf::from("out.mkv"));
+        let pipeline = Libcamera::pipe(&config, 7);
+        assert!(pipeline.starts_with("fdsrc fd=7 ! h264parse ! tee name=t"));
+        assert!(!pipeline.contains("v4l2h264enc"));
+        assert!(pipeline.ends_with("matroskamux ! filesink location=out.mkv"));
+    }
+}
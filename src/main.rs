@@ -1,161 +1,302 @@
-use std::{
-    sync::mpsc::{channel, Receiver, Sender},
-    thread::{spawn, JoinHandle},
-};
-
-use anyhow::Result;
-use opencv::{
-    core::{self, MatTraitConst},
-    imgproc::{cvt_color, COLOR_BGR2RGBA},
-    prelude::*,
-    videoio::{self, VideoCapture, VideoCaptureTrait},
-};
+use std::path::{Path, PathBuf};
 
-use slint::{Image, Timer, TimerMode};
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 
-const CAMERA_INDEX: i32 = 0;
-
-use slint::slint;
-slint! {
-    import {VerticalBox, HorizontalBox} from "std-widgets.slint";
+use slint_opencv::{
+    auth, batch, camera, compare, crash, doctor,
+    encryption::{self, Key},
+    integrity, mdns, portable,
+    session::Replay,
+    soak, wizard, App, Config, CONFIG_FILE,
+};
 
-export component Main inherits Window {
-    title: "slint";
-    icon: @image-url("");
-    width: 1152px;
-    height: 648px;
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Video source for the viewer: camera://1, a video file, rtsp:// or http(s):// stream
+    #[arg(long)]
+    source: Option<String>,
+    /// Serve the live feed as an MJPEG stream on this address, e.g. 0.0.0.0:8080
+    #[arg(long)]
+    serve: Option<String>,
+    /// Capture, process and record without the GUI for this many hours and write a soak test report.
+    /// Uses the synthetic test source unless --source is given
+    #[arg(long, value_name = "HOURS")]
+    soak: Option<f64>,
+    /// Keep the config, models, recordings and events next to the executable, e.g. on a USB stick.
+    /// Also enabled by a file named "portable" next to the executable
+    #[arg(long)]
+    portable: bool,
+    #[command(subcommand)]
+    action: Option<Action>,
+}
 
-    pure callback render-image(int) -> image;
-    in-out property <int> frame;
+#[derive(Subcommand)]
+enum Action {
+    /// Run the configured pipeline over a video file or a folder of videos without the GUI
+    Process {
+        /// Input video file or folder
+        input: PathBuf,
+        /// Output folder for processed videos, events and trajectories
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// Camera profile to use for sensor corrections
+        #[arg(long, default_value = "camera-0")]
+        camera: String,
+    },
+    /// Process new video files dropped into a folder, moving them to done/ or failed/ afterwards
+    Watch {
+        /// Folder to watch
+        dir: PathBuf,
+        /// Output folder for processed videos, events and trajectories
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// Camera profile to use for sensor corrections
+        #[arg(long, default_value = "camera-0")]
+        camera: String,
+        /// Polling interval in seconds
+        #[arg(long, default_value_t = 2.0)]
+        interval: f64,
+    },
+    /// Run two pipeline configurations over the same video and compare them side by side
+    Compare {
+        /// Input video file
+        input: PathBuf,
+        /// Config file of variant A
+        a: PathBuf,
+        /// Config file of variant B
+        b: PathBuf,
+        /// Output folder for the side-by-side video and events
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+        /// Camera profile to use for sensor corrections
+        #[arg(long, default_value = "camera-0")]
+        camera: String,
+        /// Show the side-by-side output in a window while processing
+        #[arg(long)]
+        show: bool,
+    },
+    /// List cameras with their index, name and the backend used to open them
+    Devices,
+    /// Check OpenCV video support, cameras, output folders and codecs and print a report
+    Doctor,
+    /// Re-run a recorded session with the same commands at the same frames
+    Replay {
+        /// Session log (sessions/session-*.jsonl)
+        log: PathBuf,
+    },
+    /// Generate a key for encrypted recordings
+    Keygen {
+        /// Key file to create; an existing file is never overwritten
+        #[arg(default_value = encryption::KEY_FILE)]
+        key: PathBuf,
+    },
+    /// Read a password from stdin and print its password_hash for [[auth.users]]
+    HashPassword,
+    /// List instances advertised on the local network with mDNS
+    Discover {
+        /// Seconds to wait for answers
+        #[arg(long, default_value_t = 3.0)]
+        timeout: f64,
+    },
+    /// Decrypt encrypted recordings (*.enc) for playback or export
+    Decrypt {
+        /// Encrypted files
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Key file used when recording
+        #[arg(long, default_value = encryption::KEY_FILE)]
+        key: PathBuf,
+        /// Output folder, next to each input by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Check a recording against its integrity record (<recording>.integrity.jsonl)
+    Verify {
+        /// Recording, decrypted first if it was encrypted
+        input: PathBuf,
+        /// Key file used when recording
+        #[arg(long, default_value = integrity::KEY_FILE)]
+        key: PathBuf,
+        /// Also decode the video and compare every frame, only for lossless codecs
+        #[arg(long)]
+        frames: bool,
+        /// Accept a record without its final line, left by a crash or power loss
+        #[arg(long)]
+        allow_unfinished: bool,
+    },
+}
 
-    VerticalLayout {
-        HorizontalLayout {
-            alignment: center;
-            Rectangle {
-                border-color: white;
-                border-width: 1px;
-                width: 1152px;
-                height: 648px;
-                Image {
-                    width: 100%;
-                    height: 100%;
-                    source: render-image(frame);
-                }
+impl Action {
+    // 命令行中的路径.
+    fn paths(&mut self) -> Vec<&mut PathBuf> {
+        match self {
+            Action::Process { input, output, .. } => vec![input, output],
+            Action::Watch { dir, output, .. } => vec![dir, output],
+            Action::Compare {
+                input,
+                a,
+                b,
+                output,
+                ..
+            } => vec![input, a, b, output],
+            Action::Replay { log } => vec![log],
+            Action::Keygen { key } => vec![key],
+            Action::Decrypt {
+                inputs,
+                key,
+                output,
+            } => inputs
+                .iter_mut()
+                .chain([key])
+                .chain(output.as_mut())
+                .collect(),
+            Action::Verify { input, key, .. } => vec![input, key],
+            Action::Devices | Action::Doctor | Action::HashPassword | Action::Discover { .. } => {
+                Vec::new()
             }
         }
     }
 }
 
-}
-
 fn main() -> Result<()> {
-    // 打开摄像头
-    let camera = VideoCapture::new(CAMERA_INDEX, videoio::CAP_ANY)?;
-    let opened = VideoCapture::is_opened(&camera)?;
-    if !opened {
-        panic!("Unable to open default camera!");
-    }
-    // 获得摄像头参数
-    let frame_width = camera.get(videoio::CAP_PROP_FRAME_WIDTH).unwrap();
-    let frame_height = camera.get(videoio::CAP_PROP_FRAME_HEIGHT).unwrap();
-    let fps = camera.get(videoio::CAP_PROP_FPS).unwrap();
-    println!(
-        "camera: width {}, height {}, FPS: {}",
-        frame_width, frame_height, fps
-    );
-
-    let window = Main::new().unwrap();
-    let window_clone = window.as_weak();
-
-    let timer = Timer::default();
-    timer.start(
-        TimerMode::Repeated,
-        std::time::Duration::from_secs_f32(1. / (fps + 10.0) as f32), // fps + 10  是加快 slint 显示图片的频率, 显示的视频更流畅
-        move || {
-            if let Some(window) = window_clone.upgrade() {
-                window.set_frame(window.get_frame() + 1);
+    let mut cli = Cli::parse();
+    // 便携模式下工作目录切换到程序所在的目录, 命令行中的路径仍按启动时的目录解析.
+    let portable = portable::detect(cli.portable)?;
+    if let Some(dir) = &portable {
+        let cwd = portable::enter(dir)?;
+        if let Some(action) = &mut cli.action {
+            for path in action.paths() {
+                *path = portable::resolve(&cwd, path);
             }
-        },
-    );
-
-    // 创建 Sline 和 Camera image 之间的数据通道
-    let (frame_sender, frame_receiver) = channel();
-    // 优雅退出 channel, 确保文件和 camera 对象被正常关闭, 否则 mp4 文件不完整
-    let (exit_sender, exit_receiver) = channel();
-
-    let task = start(
-        frame_sender,
-        exit_receiver,
-        camera,
-        frame_width,
-        frame_height,
-        fps,
-    );
-
-    // 需要确保 frame_data 的大小和从摄像头的分辨率一致, 否则后续 copy_from_slice() 会报错.
-    let mut frame_data = vec![0; (frame_width * frame_height * 4.0) as usize];
-    let mut render = move || -> Result<Image> {
-        if let Ok(frame_rgba) = frame_receiver.try_recv() {
-            frame_data.copy_from_slice(&frame_rgba);
         }
-        let v = slint::Image::from_rgba8(slint::SharedPixelBuffer::clone_from_slice(
-            frame_data.as_slice(),
-            frame_width as u32,
-            frame_height as u32,
-        ));
-        Ok(v)
+        if let Some(source) = cli.source.as_mut().filter(|source| !source.contains("://")) {
+            *source = portable::resolve(&cwd, Path::new(source))
+                .to_string_lossy()
+                .into_owned();
+        }
+        println!("Portable mode: data is kept in {}", dir.display());
+    }
+    // 第一次启动界面时先显示设置向导.
+    let setup = if cli.action.is_none() && cli.soak.is_none() && !Path::new(CONFIG_FILE).exists() {
+        wizard::run(Path::new(CONFIG_FILE))?
+    } else {
+        None
     };
-
-    window.on_render_image(move |_frame| render().map_err(|err| eprintln!("{:?}", err)).unwrap());
-    // 阻塞, 直到窗口被关闭.
-    window.run().unwrap();
-
-    // 关闭摄像头和文件.
-    exit_sender.send(())?;
-    let result = task.join().unwrap();
-    println!("Camera Stopped And File Closed {:?}", result);
-    Ok(())
-}
-
-fn start(
-    frame_sender: Sender<Vec<u8>>,
-    exit_receiver: Receiver<()>,
-    mut camera: VideoCapture,
-    frame_width: f64,
-    frame_height: f64,
-    fps: f64,
-) -> JoinHandle<Result<()>> {
-    spawn(move || -> Result<()> {
-        let fourcc = videoio::VideoWriter::fourcc('m', 'p', '4', 'v').unwrap();
-        let mut out = videoio::VideoWriter::new(
-            "test.mp4",
-            fourcc,
-            fps, // 需要和 camera FPS 一致, 播放保存的 mp4 视频才正常速度
-            core::Size2i::new(frame_width as i32, frame_height as i32),
-            true,
-        )
-        .expect("Can not open video writer");
-
-        let mut frame_bgr = Mat::default();
-        let mut frame_rgba = Mat::default();
-        loop {
-            if let Ok(()) = exit_receiver.try_recv() {
-                break;
-            } else {
-                camera.read(&mut frame_bgr)?;
-
-                // 需要转换称 Slint 显示的 RGBA 像素格式.
-                cvt_color(&frame_bgr, &mut frame_rgba, COLOR_BGR2RGBA, 0)?;
-
-                frame_sender.send(frame_rgba.data_bytes()?.to_vec())?;
-
-                if frame_bgr.size().unwrap().width > 0 {
-                    let _ = out.write(&frame_bgr);
+    let mut config = Config::load()?;
+    if let Some(dir) = &portable {
+        for path in portable::outside(&config, dir) {
+            eprintln!(
+                "Portable mode: {} is outside {} and is written to this machine",
+                path.display(),
+                dir.display()
+            );
+        }
+    }
+    if let Some(source) = &cli.source {
+        config.source = Some(source.clone());
+    }
+    if let Some(addr) = &cli.serve {
+        config.stream.addr = Some(addr.clone());
+    }
+    crash::install(&config.crash.dir);
+    if let Some(hours) = cli.soak {
+        return soak::run(&config, hours);
+    }
+    config.threads.ui.apply("ui");
+    let mut replay = None;
+    match &cli.action {
+        Some(Action::Process {
+            input,
+            output,
+            camera,
+        }) => return batch::run(&config, input, output, camera),
+        Some(Action::Watch {
+            dir,
+            output,
+            camera,
+            interval,
+        }) => return batch::watch(&config, dir, output, camera, *interval),
+        Some(Action::Compare {
+            input,
+            a,
+            b,
+            output,
+            camera,
+            show,
+        }) => return compare::run(input, a, b, output, camera, *show),
+        Some(Action::Devices) => {
+            for device in camera::devices(&config.camera) {
+                println!(
+                    "{}\t{:?}\t{}",
+                    device.index,
+                    device.backend,
+                    device.name.as_deref().unwrap_or("<unknown>")
+                );
+            }
+            return Ok(());
+        }
+        Some(Action::Doctor) => return doctor::run(&config),
+        Some(Action::Keygen { key }) => {
+            Key::generate(key)?;
+            println!(
+                "Key written to {}; keep a copy, recordings can't be recovered without it",
+                key.display()
+            );
+            return Ok(());
+        }
+        Some(Action::HashPassword) => {
+            let mut password = String::new();
+            std::io::stdin()
+                .read_line(&mut password)
+                .context("read password")?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            if password.is_empty() {
+                anyhow::bail!("empty password");
+            }
+            println!("{}", auth::hash_password(password));
+            return Ok(());
+        }
+        Some(Action::Discover { timeout }) => {
+            return mdns::discover(std::time::Duration::from_secs_f64(*timeout))
+        }
+        Some(Action::Decrypt {
+            inputs,
+            key,
+            output,
+        }) => {
+            let key = Key::load(key)?;
+            for input in inputs {
+                let mut decrypted = encryption::decrypted_path(input);
+                if let Some(dir) = output {
+                    decrypted = dir.join(decrypted.file_name().unwrap_or_default());
                 }
-
-                //std::thread::sleep(Duration::from_millis(10));
+                encryption::decrypt_file(input, &decrypted, &key)
+                    .with_context(|| format!("decrypt {}", input.display()))?;
+                println!("{} -> {}", input.display(), decrypted.display());
             }
+            return Ok(());
         }
-        Ok(())
-    })
+        Some(Action::Verify {
+            input,
+            key,
+            frames,
+            allow_unfinished,
+        }) => {
+            let key = integrity::Key::load(key)?;
+            for line in integrity::verify(input, &key, *frames, *allow_unfinished)? {
+                println!("{}", line);
+            }
+            return Ok(());
+        }
+        Some(Action::Replay { log }) => replay = Some(Replay::load(log)?),
+        None => {}
+    }
+
+    let mut app = App::new(config).calibrate(setup.is_some_and(|setup| setup.calibrate));
+    if let Some(session) = replay {
+        app = app.replay(session);
+    }
+    app.run()
 }
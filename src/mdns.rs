@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    fs,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+// 控制接口的服务类型, 配套应用和其他实例按这个类型查找相机.
+pub const APP_SERVICE: &str = "_slint-opencv._tcp.local.";
+const APP: &str = "slint-opencv";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MdnsConfig {
+    pub enabled: bool,
+    // 发现时显示的相机名称, 不设置时使用主机名.
+    pub name: Option<String>,
+    // 额外公布的服务, 例如 ffmpeg sink 推流到的本机 RTSP 服务器.
+    pub services: Vec<ServiceConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServiceConfig {
+    // 服务类型, 例如 "_rtsp._tcp".
+    pub kind: String,
+    pub port: u16,
+    // 写入 TXT 记录的 path, 例如 "/camera".
+    pub path: Option<String>,
+}
+
+// 要公布的一个服务.
+#[cfg_attr(not(feature = "mdns"), allow(dead_code))]
+struct Service {
+    kind: String,
+    port: u16,
+    // 监听所有网卡时为 None, 公布本机的所有地址.
+    ip: Option<IpAddr>,
+    properties: HashMap<String, String>,
+}
+
+// 在局域网中用 mDNS 公布控制接口, MJPEG 推流和配置的额外服务. 被丢弃时发送下线通知.
+pub struct Advertiser {
+    #[cfg(feature = "mdns")]
+    daemon: mdns_sd::ServiceDaemon,
+}
+
+impl Advertiser {
+    // api, stream 和 remote 为监听地址, secure 表示使用 TLS.
+    pub fn start(
+        config: &MdnsConfig,
+        api: Option<&str>,
+        stream: Option<&str>,
+        remote: Option<&str>,
+        secure: bool,
+    ) -> Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        let name = config.name.clone().unwrap_or_else(hostname);
+        let services = services(config, api, stream, remote, secure);
+        if services.is_empty() {
+            eprintln!("mdns: nothing to advertise, set api, [stream] addr or [[mdns.services]]");
+            return Ok(None);
+        }
+        Self::register(&name, services).map(Some)
+    }
+
+    #[cfg(feature = "mdns")]
+    fn register(name: &str, services: Vec<Service>) -> Result<Self> {
+        let daemon = mdns_sd::ServiceDaemon::new()?;
+        let host = format!("{}.local.", host_label(name));
+        for service in services {
+            let ips: Vec<IpAddr> = service.ip.into_iter().collect();
+            let mut info = mdns_sd::ServiceInfo::new(
+                &service.kind,
+                name,
+                &host,
+                &ips[..],
+                service.port,
+                service.properties,
+            )?;
+            if ips.is_empty() {
+                info = info.enable_addr_auto();
+            }
+            println!("Advertising {} as {:?}", service.kind, name);
+            daemon.register(info)?;
+        }
+        Ok(Self { daemon })
+    }
+
+    #[cfg(not(feature = "mdns"))]
+    fn register(_name: &str, _services: Vec<Service>) -> Result<Self> {
+        bail!("mDNS advertisement requires the mdns build feature")
+    }
+}
+
+#[cfg(feature = "mdns")]
+impl Drop for Advertiser {
+    fn drop(&mut self) {
+        // 关闭时注销所有服务, 其他设备马上看到相机下线.
+        let _ = self.daemon.shutdown();
+    }
+}
+
+// 查找局域网中运行的实例, 打印名称, 地址和服务.
+#[cfg(feature = "mdns")]
+pub fn discover(timeout: Duration) -> Result<()> {
+    use std::time::Instant;
+
+    let daemon = mdns_sd::ServiceDaemon::new()?;
+    let kinds = [APP_SERVICE, "_http._tcp.local.", "_https._tcp.local."];
+    let receivers = kinds
+        .iter()
+        .map(|kind| daemon.browse(kind))
+        .collect::<Result<Vec<_>, _>>()?;
+    let start = Instant::now();
+    let mut found = 0;
+    while start.elapsed() < timeout {
+        for receiver in &receivers {
+            while let Ok(event) = receiver.try_recv() {
+                let mdns_sd::ServiceEvent::ServiceResolved(info) = event else {
+                    continue;
+                };
+                // 其他 HTTP 服务 (打印机, 路由器) 不是本应用的实例.
+                if info.get_property_val_str("app") != Some(APP) {
+                    continue;
+                }
+                let addresses: Vec<String> = info
+                    .get_addresses()
+                    .iter()
+                    .map(|ip| ip.to_string())
+                    .collect();
+                println!(
+                    "{}\t{}:{}\t{}",
+                    info.get_fullname(),
+                    addresses.join(","),
+                    info.get_port(),
+                    info.get_property_val_str("path").unwrap_or("/")
+                );
+                found += 1;
+            }
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    let _ = daemon.shutdown();
+    if found == 0 {
+        println!("No instances found");
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "mdns"))]
+pub fn discover(_timeout: Duration) -> Result<()> {
+    bail!("discovery requires the mdns build feature")
+}
+
+// 按监听地址和配置列出要公布的服务.
+fn services(
+    config: &MdnsConfig,
+    api: Option<&str>,
+    stream: Option<&str>,
+    remote: Option<&str>,
+    secure: bool,
+) -> Vec<Service> {
+    let mut services = Vec::new();
+    let api = api.and_then(parse);
+    let stream = stream.and_then(parse);
+    let remote = remote.and_then(parse);
+    // 应用的服务指向控制接口, 没有时指向远程控制. TXT 中列出各接口的端口.
+    if let Some((ip, port)) = api.or(remote) {
+        let mut properties = properties(secure);
+        if api.is_some() {
+            properties.insert("path".to_string(), "/scenes".to_string());
+        }
+        for (key, endpoint) in [("api", api), ("stream", stream), ("remote", remote)] {
+            if let Some((_, port)) = endpoint {
+                properties.insert(key.to_string(), port.to_string());
+            }
+        }
+        services.push(Service {
+            kind: APP_SERVICE.to_string(),
+            port,
+            ip,
+            properties,
+        });
+    }
+    if let Some((ip, port)) = stream {
+        let mut properties = properties(secure);
+        properties.insert("path".to_string(), "/".to_string());
+        properties.insert("kind".to_string(), "mjpeg".to_string());
+        let kind = if secure { "_https._tcp" } else { "_http._tcp" };
+        services.push(Service {
+            kind: format!("{}.local.", kind),
+            port,
+            ip,
+            properties,
+        });
+    }
+    for service in &config.services {
+        let mut properties = properties(false);
+        if let Some(path) = &service.path {
+            properties.insert("path".to_string(), path.clone());
+        }
+        services.push(Service {
+            kind: format!("{}.local.", service.kind.trim_end_matches('.')),
+            port: service.port,
+            ip: None,
+            properties,
+        });
+    }
+    services
+}
+
+fn properties(secure: bool) -> HashMap<String, String> {
+    HashMap::from([
+        ("app".to_string(), APP.to_string()),
+        ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+        ("tls".to_string(), (secure as u8).to_string()),
+    ])
+}
+
+// 监听地址中的 IP 和端口, 0.0.0.0 和 [::] 时 IP 为 None. 其他设备连不上的地址不公布.
+fn parse(addr: &str) -> Option<(Option<IpAddr>, u16)> {
+    let Ok(socket) = addr.parse::<SocketAddr>() else {
+        eprintln!("mdns: not advertising {}, use an IP address and port", addr);
+        return None;
+    };
+    if socket.ip().is_loopback() {
+        eprintln!(
+            "mdns: not advertising {}, it only listens on this machine",
+            addr
+        );
+        return None;
+    }
+    let ip = (!socket.ip().is_unspecified()).then_some(socket.ip());
+    Some((ip, socket.port()))
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .ok()
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| APP.to_string())
+}
+
+// mDNS 主机名只用字母, 数字和连字符.
+#[cfg_attr(not(feature = "mdns"), allow(dead_code))]
+fn host_label(name: &str) -> String {
+    let label: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let label = label.trim_matches('-');
+    if label.is_empty() {
+        APP.to_string()
+    } else {
+        label.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(services: Vec<ServiceConfig>) -> MdnsConfig {
+        MdnsConfig {
+            enabled: true,
+            name: Some("Bird Feeder".to_string()),
+            services,
+        }
+    }
+
+    #[test]
+    fn listen_addresses() {
+        assert_eq!(parse("0.0.0.0:8080"), Some((None, 8080)));
+        assert_eq!(parse("[::]:8080"), Some((None, 8080)));
+        let ip: IpAddr = "192.168.1.20".parse().unwrap();
+        assert_eq!(parse("192.168.1.20:9000"), Some((Some(ip), 9000)));
+        assert_eq!(parse("127.0.0.1:8080"), None);
+        assert_eq!(parse("camera.local:8080"), None);
+    }
+
+    #[test]
+    fn host_labels() {
+        assert_eq!(host_label("Bird Feeder #2"), "bird-feeder--2");
+        assert_eq!(host_label("  "), APP);
+        assert!(!hostname().is_empty());
+    }
+
+    // 应用的服务在 TXT 中列出各接口的端口, 推流和额外服务各公布一次.
+    #[test]
+    fn lists_services() {
+        let extra = ServiceConfig {
+            kind: "_rtsp._tcp.".to_string(),
+            port: 8554,
+            path: Some("/camera".to_string()),
+        };
+        let listed = services(
+            &config(vec![extra]),
+            Some("0.0.0.0:8080"),
+            Some("192.168.1.20:8081"),
+            Some("0.0.0.0:8082"),
+            true,
+        );
+        let kinds: Vec<_> = listed.iter().map(|service| service.kind.as_str()).collect();
+        assert_eq!(
+            kinds,
+            [APP_SERVICE, "_https._tcp.local.", "_rtsp._tcp.local."]
+        );
+
+        let app = &listed[0];
+        assert_eq!((app.ip, app.port), (None, 8080));
+        assert_eq!(app.properties["path"], "/scenes");
+        assert_eq!(app.properties["stream"], "8081");
+        assert_eq!(app.properties["remote"], "8082");
+        assert_eq!(app.properties["tls"], "1");
+        assert_eq!(listed[1].properties["kind"], "mjpeg");
+        assert_eq!(listed[1].ip, Some("192.168.1.20".parse().unwrap()));
+        assert_eq!(listed[2].properties["path"], "/camera");
+        assert_eq!(listed[2].properties["tls"], "0");
+
+        // 没有控制接口时应用的服务指向远程控制.
+        let listed = services(&config(Vec::new()), None, None, Some("0.0.0.0:8082"), false);
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].port, 8082);
+        assert!(!listed[0].properties.contains_key("path"));
+    }
+
+    // 没有启用或没有可公布的服务时不启动.
+    #[test]
+    fn nothing_to_advertise() {
+        let disabled = MdnsConfig::default();
+        assert!(
+            Advertiser::start(&disabled, Some("0.0.0.0:8080"), None, None, false)
+                .unwrap()
+                .is_none()
+        );
+        let local = Advertiser::start(
+            &config(Vec::new()),
+            Some("127.0.0.1:8080"),
+            None,
+            None,
+            false,
+        );
+        assert!(local.unwrap().is_none());
+    }
+}
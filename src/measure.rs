@@ -0,0 +1,331 @@
+use std::{fs::File, io::Write, path::Path};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{Mat, Point, Scalar, Vector},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Tool {
+    Off,
+    // 在标准测微尺上点两点, 根据已知长度得到 um/px.
+    Calibrate,
+    Distance,
+    Area,
+}
+
+impl Tool {
+    pub fn parse(name: &str) -> Self {
+        match name {
+            "Calibrate" => Tool::Calibrate,
+            "Distance" => Tool::Distance,
+            "Area" => Tool::Area,
+            _ => Tool::Off,
+        }
+    }
+}
+
+struct Record {
+    time: DateTime<Local>,
+    kind: &'static str,
+    pixels: f64,
+    micrometers: Option<f64>,
+    points: Vec<Point>,
+}
+
+pub enum Outcome {
+    Calibrated(f64),
+    Measured(String),
+}
+
+pub struct Measure {
+    tool: Tool,
+    reference_um: f64,
+    points: Vec<Point>,
+    pub um_per_px: Option<f64>,
+    records: Vec<Record>,
+}
+
+impl Measure {
+    pub fn new(um_per_px: Option<f64>) -> Self {
+        Self {
+            tool: Tool::Off,
+            reference_um: 100.0,
+            points: Vec::new(),
+            um_per_px,
+            records: Vec::new(),
+        }
+    }
+
+    pub fn set_tool(&mut self, tool: Tool, reference_um: f64) {
+        if tool != self.tool {
+            self.points.clear();
+        }
+        self.tool = tool;
+        if reference_um > 0.0 {
+            self.reference_um = reference_um;
+        }
+    }
+
+    pub fn click(&mut self, p: Point) -> Option<Outcome> {
+        self.points.push(p);
+        match self.tool {
+            Tool::Off => {
+                self.points.clear();
+                None
+            }
+            Tool::Area => None,
+            Tool::Calibrate | Tool::Distance if self.points.len() < 2 => None,
+            Tool::Calibrate => {
+                let pixels = distance(self.points[0], self.points[1]);
+                self.points.clear();
+                if pixels < 1.0 {
+                    return None;
+                }
+                let scale = self.reference_um / pixels;
+                self.um_per_px = Some(scale);
+                Some(Outcome::Calibrated(scale))
+            }
+            Tool::Distance => {
+                let points = std::mem::take(&mut self.points);
+                let pixels = distance(points[0], points[1]);
+                let micrometers = self.um_per_px.map(|s| pixels * s);
+                let text = match micrometers {
+                    Some(um) => format!("Distance {:.2} um", um),
+                    None => format!("Distance {:.1} px (not calibrated)", pixels),
+                };
+                self.record("distance", pixels, micrometers, points);
+                Some(Outcome::Measured(text))
+            }
+        }
+    }
+
+    // 结束多边形, 用鞋带公式计算面积.
+    pub fn close_area(&mut self) -> Option<String> {
+        if self.tool != Tool::Area || self.points.len() < 3 {
+            return None;
+        }
+        let points = std::mem::take(&mut self.points);
+        let twice: i64 = points
+            .iter()
+            .zip(points.iter().cycle().skip(1))
+            .map(|(a, b)| a.x as i64 * b.y as i64 - b.x as i64 * a.y as i64)
+            .sum();
+        let pixels = twice.abs() as f64 / 2.0;
+        let micrometers = self.um_per_px.map(|s| pixels * s * s);
+        let text = match micrometers {
+            Some(um2) => format!("Area {:.2} um^2", um2),
+            None => format!("Area {:.0} px^2 (not calibrated)", pixels),
+        };
+        self.record("area", pixels, micrometers, points);
+        Some(text)
+    }
+
+    fn record(
+        &mut self,
+        kind: &'static str,
+        pixels: f64,
+        micrometers: Option<f64>,
+        points: Vec<Point>,
+    ) {
+        self.records.push(Record {
+            time: Local::now(),
+            kind,
+            pixels,
+            micrometers,
+            points,
+        });
+    }
+
+    pub fn has_overlay(&self) -> bool {
+        self.um_per_px.is_some() || !self.points.is_empty() || !self.records.is_empty()
+    }
+
+    // 绘制比例尺、正在进行的测量以及最近一次测量结果.
+    pub fn draw(&self, frame: &mut Mat) -> Result<()> {
+        let yellow = Scalar::new(0., 255., 255., 0.);
+        let cyan = Scalar::new(255., 255., 0., 0.);
+        if let Some(last) = self.records.last() {
+            polyline(frame, &last.points, last.kind == "area", cyan)?;
+        }
+        polyline(frame, &self.points, false, yellow)?;
+        for p in &self.points {
+            imgproc::circle(frame, *p, 3, yellow, -1, LINE_AA, 0)?;
+        }
+        if let Some(scale) = self.um_per_px {
+            scale_bar(frame, scale)?;
+        }
+        Ok(())
+    }
+
+    pub fn export_csv(&self, path: &Path) -> Result<usize> {
+        let mut file = File::create(path)?;
+        writeln!(file, "time,kind,pixels,micrometers,points")?;
+        for r in &self.records {
+            let points = r
+                .points
+                .iter()
+                .map(|p| format!("{} {}", p.x, p.y))
+                .collect::<Vec<_>>()
+                .join(";");
+            let micrometers = r
+                .micrometers
+                .map(|v| format!("{:.3}", v))
+                .unwrap_or_default();
+            writeln!(
+                file,
+                "{},{},{:.3},{},{}",
+                r.time.to_rfc3339(),
+                r.kind,
+                r.pixels,
+                micrometers,
+                points
+            )?;
+        }
+        Ok(self.records.len())
+    }
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    (((a.x - b.x) as f64).powi(2) + ((a.y - b.y) as f64).powi(2)).sqrt()
+}
+
+fn polyline(frame: &mut Mat, points: &[Point], closed: bool, color: Scalar) -> Result<()> {
+    if points.len() < 2 {
+        return Ok(());
+    }
+    let mut contours = Vector::<Vector<Point>>::new();
+    contours.push(Vector::from_slice(points));
+    imgproc::polylines(frame, &contours, closed, color, 2, LINE_AA, 0)?;
+    Ok(())
+}
+
+// 右下角比例尺, 长度取 1/2/5 x 10^n um 中不超过画面宽度 20% 的最大值.
+fn scale_bar(frame: &mut Mat, um_per_px: f64) -> Result<()> {
+    let target = frame.cols() as f64 * 0.2 * um_per_px;
+    let magnitude = 10f64.powf(target.log10().floor());
+    let length = [5.0, 2.0, 1.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|l| *l <= target)
+        .unwrap_or(magnitude);
+    let bar = (length / um_per_px).round() as i32;
+    let x = frame.cols() - bar - 20;
+    let y = frame.rows() - 30;
+    let white = Scalar::new(255., 255., 255., 0.);
+    imgproc::rectangle(
+        frame,
+        opencv::core::Rect::new(x, y, bar, 6),
+        white,
+        -1,
+        LINE_AA,
+        0,
+    )?;
+    let label = if length >= 1000.0 {
+        format!("{} mm", length / 1000.0)
+    } else {
+        format!("{} um", length)
+    };
+    imgproc::put_text(
+        frame,
+        &label,
+        Point::new(x, y - 8),
+        FONT_HERSHEY_SIMPLEX,
+        0.6,
+        white,
+        1,
+        LINE_AA,
+        false,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn measured(outcome: Option<Outcome>) -> String {
+        match outcome {
+            Some(Outcome::Measured(text)) => text,
+            _ => panic!("expected a measurement"),
+        }
+    }
+
+    #[test]
+    fn calibrate_then_measure() {
+        let mut measure = Measure::new(None);
+        measure.set_tool(Tool::parse("Distance"), 0.0);
+        assert!(measure.click(Point::new(0, 0)).is_none());
+        assert_eq!(
+            measured(measure.click(Point::new(30, 40))),
+            "Distance 50.0 px (not calibrated)"
+        );
+
+        // 测微尺上 100 um 的刻度相距 200 像素.
+        measure.set_tool(Tool::parse("Calibrate"), 100.0);
+        assert!(measure.click(Point::new(10, 10)).is_none());
+        assert!(matches!(
+            measure.click(Point::new(210, 10)),
+            Some(Outcome::Calibrated(scale)) if scale == 0.5
+        ));
+        assert_eq!(measure.um_per_px, Some(0.5));
+
+        measure.set_tool(Tool::Distance, 0.0);
+        measure.click(Point::new(0, 0));
+        assert_eq!(
+            measured(measure.click(Point::new(0, 81))),
+            "Distance 40.50 um"
+        );
+    }
+
+    #[test]
+    fn polygon_area() {
+        let mut measure = Measure::new(Some(0.5));
+        measure.set_tool(Tool::Area, 0.0);
+        for (x, y) in [(0, 0), (100, 0)] {
+            assert!(measure.click(Point::new(x, y)).is_none());
+        }
+        assert_eq!(measure.close_area(), None);
+        // 换工具时丢弃没完成的多边形.
+        measure.set_tool(Tool::Distance, 0.0);
+        measure.set_tool(Tool::Area, 0.0);
+        for (x, y) in [(0, 0), (100, 0), (100, 100), (0, 100)] {
+            measure.click(Point::new(x, y));
+        }
+        assert_eq!(measure.close_area().as_deref(), Some("Area 2500.00 um^2"));
+        measure.um_per_px = None;
+        for (x, y) in [(0, 0), (0, 60), (80, 0)] {
+            measure.click(Point::new(x, y));
+        }
+        assert_eq!(
+            measure.close_area().as_deref(),
+            Some("Area 2400 px^2 (not calibrated)")
+        );
+    }
+
+    #[test]
+    fn csv_export() {
+        let mut measure = Measure::new(Some(2.0));
+        measure.set_tool(Tool::Distance, 0.0);
+        measure.click(Point::new(1, 2));
+        measure.click(Point::new(4, 6));
+        measure.set_tool(Tool::Off, 0.0);
+        assert!(measure.click(Point::new(5, 5)).is_none());
+        let path = std::env::temp_dir().join(format!("measure-test-{}.csv", std::process::id()));
+        assert_eq!(measure.export_csv(&path).unwrap(), 1);
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "time,kind,pixels,micrometers,points");
+        assert!(
+            lines[1].ends_with(",distance,5.000,10.000,1 2;4 6"),
+            "{}",
+            lines[1]
+        );
+        assert_eq!(lines.len(), 2);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,673 @@
+use std::{
+    collections::VecDeque,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Timelike};
+use opencv::{
+    core::{self, Mat, Point, Point2f, Scalar, Size, Vector, CV_32F, CV_8U},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc::{self, CHAIN_APPROX_SIMPLE, COLOR_BGR2GRAY, INTER_AREA, LINE_AA, RETR_EXTERNAL},
+    prelude::*,
+    videoio::VideoWriter,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{events::EventStore, photo, pipeline::FrameProcessor, sink, threads::ThreadConfig};
+
+// 检测时把画面缩小到该宽度, min_length 按这个宽度计算.
+const DETECT_WIDTH: i32 = 640;
+// 背景更新速度, 星空随地球自转缓慢移动.
+const BACKGROUND_ALPHA: f64 = 0.05;
+// 检测到的轨迹在预览中显示的时长.
+const SHOW_STREAK: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MeteorConfig {
+    pub enabled: bool,
+    // 比背景亮多少 (0..255) 的像素算作变化.
+    pub threshold: f64,
+    // 阈值至少为差值噪声的 sigma 倍, 高增益的暗场噪声较大.
+    pub sigma: f64,
+    // 轨迹的最短长度 (检测画面像素) 和最小长宽比, 星星和噪点是圆点.
+    pub min_length: f64,
+    pub min_elongation: f64,
+    // 画面平均亮度高于该值时 (白天, 黎明) 不检测.
+    pub max_brightness: f64,
+    // 变化像素超过该比例时认为是闪电, 车灯或云, 忽略这一帧.
+    pub max_changed: f64,
+    // 片段在第一次和最后一次检测前后保留的时长 (秒).
+    pub pre_seconds: f64,
+    pub post_seconds: f64,
+    // 持续更久的目标 (飞机, 卫星) 不保存.
+    pub max_duration: f64,
+    // 缓存帧的 JPEG 质量.
+    pub quality: i32,
+    pub fourcc: String,
+    // 片段, 叠加图和每晚汇总的保存目录.
+    pub dir: PathBuf,
+}
+
+impl Default for MeteorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 30.0,
+            sigma: 5.0,
+            min_length: 20.0,
+            min_elongation: 4.0,
+            max_brightness: 80.0,
+            max_changed: 0.01,
+            pre_seconds: 2.0,
+            post_seconds: 2.0,
+            max_duration: 5.0,
+            quality: 90,
+            fourcc: "mp4v".to_string(),
+            dir: PathBuf::from("meteors"),
+        }
+    }
+}
+
+struct Buffered {
+    time: DateTime<Local>,
+    data: Vector<u8>,
+}
+
+// 一次正在进行的瞬变事件.
+struct Transient {
+    started: Instant,
+    last_seen: Instant,
+    frames: Vec<Buffered>,
+    // 原始画面坐标下的轨迹端点.
+    streaks: Vec<(Point, Point)>,
+}
+
+// 结束的事件, 由后台线程写成片段.
+struct Clip {
+    frames: Vec<Buffered>,
+    fps: f64,
+    duration: f64,
+    streaks: Vec<(Point, Point)>,
+}
+
+// 流星和瞬变检测: 与缓慢更新的背景相减, 只保留细长的亮线, 每个事件连同前后几秒保存为片段,
+// 按夜汇总. 用于全天空相机.
+pub struct MeteorDetection {
+    config: MeteorConfig,
+    params: Vector<i32>,
+    background: Mat,
+    small: Mat,
+    gray: Mat,
+    reference: Mat,
+    diff: Mat,
+    mask: Mat,
+    // 预录缓存, 只保留 pre_seconds.
+    buffer: VecDeque<Buffered>,
+    transient: Option<Transient>,
+    // 平滑后的帧间隔 (秒), 用于计算缓存长度和片段帧率.
+    frame_interval: f64,
+    last_frame: Option<Instant>,
+    recent: Vec<(Point, Point, Instant)>,
+    clips: Sender<Clip>,
+}
+
+impl MeteorDetection {
+    pub fn new(config: &MeteorConfig, events: EventStore, workers: &ThreadConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let (clips, receiver) = channel::<Clip>();
+        let saving = config.clone();
+        let workers = workers.clone();
+        // 阶段移除时 clips 被丢弃, 写完剩下的片段后线程退出.
+        thread::Builder::new()
+            .name("meteor".to_string())
+            .spawn(move || {
+                workers.apply("meteor");
+                for clip in receiver {
+                    if let Err(err) = save(&saving, &events, &clip) {
+                        eprintln!("meteor: {:?}", err);
+                    }
+                }
+            })?;
+        Ok(Self {
+            config: config.clone(),
+            params: photo::write_params("jpg", Some(config.quality)),
+            background: Mat::default(),
+            small: Mat::default(),
+            gray: Mat::default(),
+            reference: Mat::default(),
+            diff: Mat::default(),
+            mask: Mat::default(),
+            buffer: VecDeque::new(),
+            transient: None,
+            frame_interval: 1.0 / 25.0,
+            last_frame: None,
+            recent: Vec::new(),
+            clips,
+        })
+    }
+
+    // 当前帧中的细长亮线, 端点为原始画面坐标.
+    fn find_streaks(&mut self, frame: &Mat) -> Result<Vec<(Point, Point)>> {
+        let scale = (DETECT_WIDTH as f64 / frame.cols() as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        imgproc::cvt_color(&self.small, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        if self.background.empty() || self.background.size()? != self.gray.size()? {
+            self.gray
+                .convert_to(&mut self.background, CV_32F, 1.0, 0.0)?;
+            return Ok(Vec::new());
+        }
+        let brightness = core::mean(&self.gray, &core::no_array())?[0];
+        self.background
+            .convert_to(&mut self.reference, CV_8U, 1.0, 0.0)?;
+        imgproc::accumulate_weighted(
+            &self.gray,
+            &mut self.background,
+            BACKGROUND_ALPHA,
+            &core::no_array(),
+        )?;
+        if brightness > self.config.max_brightness {
+            return Ok(Vec::new());
+        }
+
+        // 只关心变亮的像素, subtract 对 8 位图像截断负值.
+        core::subtract(
+            &self.gray,
+            &self.reference,
+            &mut self.diff,
+            &core::no_array(),
+            -1,
+        )?;
+        let mut mean = Mat::default();
+        let mut deviation = Mat::default();
+        core::mean_std_dev(&self.diff, &mut mean, &mut deviation, &core::no_array())?;
+        let noise = *mean.at::<f64>(0)? + self.config.sigma * *deviation.at::<f64>(0)?;
+        let threshold = self.config.threshold.max(noise);
+        imgproc::threshold(
+            &self.diff,
+            &mut self.mask,
+            threshold,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+        let total = (self.mask.cols() * self.mask.rows()) as f64;
+        if core::count_non_zero(&self.mask)? as f64 > self.config.max_changed * total {
+            return Ok(Vec::new());
+        }
+
+        let mut contours = Vector::<Vector<Point>>::new();
+        imgproc::find_contours(
+            &self.mask,
+            &mut contours,
+            RETR_EXTERNAL,
+            CHAIN_APPROX_SIMPLE,
+            Point::new(0, 0),
+        )?;
+        let mut streaks = Vec::new();
+        for contour in contours.iter() {
+            let rect = imgproc::min_area_rect(&contour)?;
+            let long = rect.size.width.max(rect.size.height) as f64;
+            let short = rect.size.width.min(rect.size.height).max(1.0) as f64;
+            if long < self.config.min_length || long / short < self.config.min_elongation {
+                continue;
+            }
+            // 轨迹端点为两条短边的中点.
+            let mut corners = Mat::default();
+            imgproc::box_points(rect, &mut corners)?;
+            let mut p = [Point2f::default(); 4];
+            for (i, point) in p.iter_mut().enumerate() {
+                *point = Point2f::new(
+                    *corners.at_2d::<f32>(i as i32, 0)?,
+                    *corners.at_2d::<f32>(i as i32, 1)?,
+                );
+            }
+            let length =
+                |a: Point2f, b: Point2f| ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt();
+            let (a, b, c, d) = if length(p[0], p[1]) < length(p[1], p[2]) {
+                (p[0], p[1], p[2], p[3])
+            } else {
+                (p[1], p[2], p[3], p[0])
+            };
+            let end = |a: Point2f, b: Point2f| {
+                Point::new(
+                    ((a.x + b.x) as f64 / 2.0 / scale) as i32,
+                    ((a.y + b.y) as f64 / 2.0 / scale) as i32,
+                )
+            };
+            streaks.push((end(a, b), end(c, d)));
+        }
+        Ok(streaks)
+    }
+
+    // 结束当前事件, 持续时间合理时交给后台线程保存.
+    fn finish(&mut self) {
+        let Some(transient) = self.transient.take() else {
+            return;
+        };
+        let duration = transient
+            .last_seen
+            .duration_since(transient.started)
+            .as_secs_f64();
+        if duration > self.config.max_duration {
+            println!("meteor: ignored a transient lasting {:.1}s", duration);
+            return;
+        }
+        let _ = self.clips.send(Clip {
+            frames: transient.frames,
+            fps: 1.0 / self.frame_interval,
+            duration,
+            streaks: transient.streaks,
+        });
+    }
+}
+
+impl FrameProcessor for MeteorDetection {
+    fn name(&self) -> &str {
+        "meteor"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let now = Instant::now();
+        if let Some(last) = self.last_frame {
+            let elapsed = now.duration_since(last).as_secs_f64();
+            self.frame_interval = self.frame_interval * 0.95 + elapsed * 0.05;
+        }
+        self.last_frame = Some(now);
+
+        let mut data = Vector::new();
+        imgcodecs::imencode(".jpg", frame, &mut data, &self.params)?;
+        let buffered = Buffered {
+            time: Local::now(),
+            data,
+        };
+        let streaks = self.find_streaks(frame)?;
+        self.recent.retain(|(_, _, at)| at.elapsed() < SHOW_STREAK);
+        for &(a, b) in &streaks {
+            self.recent.push((a, b, now));
+        }
+
+        if !streaks.is_empty() && self.transient.is_none() {
+            self.transient = Some(Transient {
+                started: now,
+                last_seen: now,
+                frames: self.buffer.drain(..).collect(),
+                streaks: Vec::new(),
+            });
+        }
+        let post = Duration::from_secs_f64(self.config.post_seconds.max(0.0));
+        let limit = Duration::from_secs_f64(self.config.max_duration.max(0.0)) + post;
+        match &mut self.transient {
+            Some(transient) => {
+                transient.frames.push(buffered);
+                if !streaks.is_empty() {
+                    transient.last_seen = now;
+                    transient.streaks.extend(streaks);
+                }
+                if transient.last_seen.elapsed() > post || transient.started.elapsed() > limit {
+                    self.finish();
+                }
+            }
+            None => {
+                self.buffer.push_back(buffered);
+                let capacity = (self.config.pre_seconds.max(0.0) / self.frame_interval).ceil();
+                while self.buffer.len() > capacity as usize {
+                    self.buffer.pop_front();
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        !self.recent.is_empty()
+    }
+
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        for &(a, b, _) in &self.recent {
+            imgproc::line(frame, a, b, Scalar::new(0., 255., 255., 0.), 2, LINE_AA, 0)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for MeteorDetection {
+    // 关闭检测时保存进行中的事件.
+    fn drop(&mut self) {
+        self.finish();
+    }
+}
+
+// 每晚汇总中的一次流星.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Sighting {
+    time: String,
+    clip: PathBuf,
+    image: PathBuf,
+    duration: f64,
+    streaks: usize,
+}
+
+// <dir>/<夜晚>/summary.json, 每次保存片段后重写.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Night {
+    night: String,
+    count: u64,
+    hours: [u64; 24],
+    meteors: Vec<Sighting>,
+}
+
+// 午夜前后属于同一夜, 按中午切分日期.
+fn night(time: DateTime<Local>) -> String {
+    (time - chrono::Duration::hours(12))
+        .format("%Y-%m-%d")
+        .to_string()
+}
+
+// 写片段和叠加图, 更新当晚的叠加图和汇总.
+fn save(config: &MeteorConfig, events: &EventStore, clip: &Clip) -> Result<()> {
+    let Some(first) = clip.frames.first() else {
+        bail!("empty clip");
+    };
+    let dir = config.dir.join(night(first.time));
+    fs::create_dir_all(&dir)?;
+    let stem = format!("meteor-{}", first.time.format("%Y-%m-%dT%H-%M-%S"));
+    let video = dir.join(format!("{}.mp4", stem));
+    let image = dir.join(format!("{}.jpg", stem));
+
+    // 片段的最大值叠加图显示完整的轨迹.
+    let mut stack = Mat::default();
+    let mut writer: Option<VideoWriter> = None;
+    for buffered in &clip.frames {
+        let frame = imgcodecs::imdecode(&buffered.data, IMREAD_COLOR)?;
+        if writer.is_none() {
+            let opened = VideoWriter::new(
+                &video.to_string_lossy(),
+                sink::fourcc(&config.fourcc)?,
+                clip.fps.clamp(1.0, 120.0),
+                frame.size()?,
+                true,
+            )?;
+            if !opened.is_opened()? {
+                bail!("open video writer {}", video.display());
+            }
+            writer = Some(opened);
+        }
+        if let Some(writer) = &mut writer {
+            writer.write(&frame)?;
+        }
+        if stack.empty() {
+            stack = frame;
+        } else {
+            let mut max = Mat::default();
+            core::max(&stack, &frame, &mut max)?;
+            stack = max;
+        }
+    }
+    if let Some(mut writer) = writer {
+        writer.release()?;
+    }
+    write(&image, &stack)?;
+
+    // 当晚所有流星叠加在一张图上.
+    let composite = dir.join("stack.jpg");
+    let previous = imgcodecs::imread(&composite.to_string_lossy(), IMREAD_COLOR)?;
+    if !previous.empty() && previous.size()? == stack.size()? {
+        let mut max = Mat::default();
+        core::max(&previous, &stack, &mut max)?;
+        write(&composite, &max)?;
+    } else {
+        write(&composite, &stack)?;
+    }
+
+    let summary = dir.join("summary.json");
+    let mut tonight: Night = fs::read_to_string(&summary)
+        .ok()
+        .and_then(|text| serde_json::from_str(&text).ok())
+        .unwrap_or_else(|| Night {
+            night: night(first.time),
+            ..Night::default()
+        });
+    tonight.count += 1;
+    tonight.hours[first.time.hour() as usize] += 1;
+    tonight.meteors.push(Sighting {
+        time: first.time.to_rfc3339(),
+        clip: video.clone(),
+        image: image.clone(),
+        duration: clip.duration,
+        streaks: clip.streaks.len(),
+    });
+    fs::write(&summary, serde_json::to_string_pretty(&tonight)?)?;
+
+    let (start, end) = clip.streaks[0];
+    events.log(
+        "meteor",
+        json!({
+            "clip": video,
+            "image": image,
+            "duration": clip.duration,
+            "x1": start.x,
+            "y1": start.y,
+            "x2": end.x,
+            "y2": end.y,
+        }),
+    );
+    Ok(())
+}
+
+fn write(path: &Path, image: &Mat) -> Result<()> {
+    if !imgcodecs::imwrite(&path.to_string_lossy(), image, &Vector::new())? {
+        bail!("write {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+
+    use chrono::TimeZone;
+    use opencv::core::CV_8UC3;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("meteor-{}-{}", name, std::process::id()))
+    }
+
+    fn sky(level: f64) -> Mat {
+        Mat::new_rows_cols_with_default(150, 200, CV_8UC3, Scalar::all(level)).unwrap()
+    }
+
+    fn streak() -> Mat {
+        let mut frame = sky(10.);
+        imgproc::line(
+            &mut frame,
+            Point::new(50, 50),
+            Point::new(110, 70),
+            Scalar::all(255.),
+            3,
+            imgproc::LINE_8,
+            0,
+        )
+        .unwrap();
+        frame
+    }
+
+    fn detection(name: &str, config: MeteorConfig) -> (PathBuf, MeteorDetection) {
+        let dir = temp_dir(name);
+        let config = MeteorConfig {
+            dir: dir.clone(),
+            ..config
+        };
+        let events = EventStore::open(dir.with_extension("jsonl")).unwrap();
+        let detection = MeteorDetection::new(&config, events, &ThreadConfig::default()).unwrap();
+        (dir, detection)
+    }
+
+    fn near(a: Point, b: Point) -> bool {
+        (a.x - b.x).abs() <= 4 && (a.y - b.y).abs() <= 4
+    }
+
+    #[test]
+    fn night_starts_at_noon() {
+        let at = |day, hour| Local.with_ymd_and_hms(2026, 5, day, hour, 0, 0).unwrap();
+        assert_eq!(night(at(1, 23)), "2026-05-01");
+        assert_eq!(night(at(2, 3)), "2026-05-01");
+        assert_eq!(night(at(2, 13)), "2026-05-02");
+    }
+
+    // 细长的亮线是轨迹, 圆点, 大面积变化和明亮的画面都不是.
+    #[test]
+    fn finds_streaks() {
+        let (dir, mut meteor) = detection("streaks", MeteorConfig::default());
+        assert!(meteor.find_streaks(&sky(10.)).unwrap().is_empty());
+        assert!(meteor.find_streaks(&sky(10.)).unwrap().is_empty());
+        let streaks = meteor.find_streaks(&streak()).unwrap();
+        assert_eq!(streaks.len(), 1);
+        let (a, b) = streaks[0];
+        let (start, end) = (Point::new(50, 50), Point::new(110, 70));
+        assert!(
+            near(a, start) && near(b, end) || near(a, end) && near(b, start),
+            "{a:?} {b:?}"
+        );
+
+        let mut star = sky(10.);
+        imgproc::circle(
+            &mut star,
+            Point::new(100, 75),
+            6,
+            Scalar::all(255.),
+            -1,
+            imgproc::LINE_8,
+            0,
+        )
+        .unwrap();
+        assert!(meteor.find_streaks(&star).unwrap().is_empty());
+        assert!(meteor.find_streaks(&sky(60.)).unwrap().is_empty());
+        drop(meteor);
+
+        let (_, mut meteor) = detection("streaks", MeteorConfig::default());
+        meteor.find_streaks(&sky(200.)).unwrap();
+        let mut day = sky(200.);
+        imgproc::line(
+            &mut day,
+            Point::new(50, 50),
+            Point::new(110, 70),
+            Scalar::all(255.),
+            3,
+            imgproc::LINE_8,
+            0,
+        )
+        .unwrap();
+        assert!(meteor.find_streaks(&day).unwrap().is_empty());
+        drop(meteor);
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(dir.with_extension("jsonl")).unwrap();
+    }
+
+    fn clip(hour: u32, minute: u32) -> Clip {
+        let time = Local.with_ymd_and_hms(2026, 5, 2, hour, minute, 0).unwrap();
+        let frames = [sky(10.), streak(), sky(10.)]
+            .iter()
+            .map(|frame| {
+                let mut data = Vector::new();
+                imgcodecs::imencode(".jpg", frame, &mut data, &Vector::new()).unwrap();
+                Buffered { time, data }
+            })
+            .collect();
+        Clip {
+            frames,
+            fps: 10.0,
+            duration: 0.1,
+            streaks: vec![(Point::new(50, 50), Point::new(110, 70))],
+        }
+    }
+
+    // 片段, 叠加图和当晚的汇总按夜保存.
+    #[test]
+    fn saves_clips() {
+        let dir = temp_dir("save");
+        let config = MeteorConfig {
+            dir: dir.clone(),
+            ..Default::default()
+        };
+        let path = dir.with_extension("jsonl");
+        let events = EventStore::open(&path).unwrap();
+        save(&config, &events, &clip(1, 30)).unwrap();
+        save(&config, &events, &clip(2, 15)).unwrap();
+
+        let night = dir.join("2026-05-01");
+        assert!(night.join("meteor-2026-05-02T01-30-00.mp4").exists());
+        assert!(night.join("meteor-2026-05-02T02-15-00.jpg").exists());
+        let stack =
+            imgcodecs::imread(&night.join("stack.jpg").to_string_lossy(), IMREAD_COLOR).unwrap();
+        assert_eq!((stack.cols(), stack.rows()), (200, 150));
+        let summary: Night =
+            serde_json::from_str(&fs::read_to_string(night.join("summary.json")).unwrap()).unwrap();
+        assert_eq!((summary.night.as_str(), summary.count), ("2026-05-01", 2));
+        assert_eq!((summary.hours[1], summary.hours[2]), (1, 1));
+        assert_eq!(summary.meteors[0].streaks, 1);
+
+        let logged = crate::events::read(&path).unwrap();
+        assert_eq!(logged.len(), 2);
+        assert_eq!(logged[0].kind, "meteor");
+        assert_eq!(logged[0].data["x1"], 50);
+        assert_eq!(logged[0].data["x2"], 110);
+        let empty = Clip {
+            frames: Vec::new(),
+            ..clip(3, 0)
+        };
+        assert!(save(&config, &events, &empty).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+
+    // 检测到轨迹时带上预录的帧保存片段.
+    #[test]
+    fn records_transient() {
+        let (dir, mut meteor) = detection(
+            "process",
+            MeteorConfig {
+                post_seconds: 0.0,
+                ..Default::default()
+            },
+        );
+        for _ in 0..3 {
+            meteor.process(&mut sky(10.)).unwrap();
+        }
+        assert!(!meteor.has_overlay());
+        meteor.process(&mut streak()).unwrap();
+        assert!(meteor.has_overlay());
+        sleep(Duration::from_millis(5));
+        meteor.process(&mut sky(10.)).unwrap();
+        assert!(meteor.transient.is_none());
+        drop(meteor);
+
+        let path = dir.with_extension("jsonl");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut logged = Vec::new();
+        while logged.is_empty() {
+            assert!(Instant::now() < deadline, "timed out");
+            sleep(Duration::from_millis(20));
+            logged = crate::events::read(&path).unwrap();
+        }
+        assert_eq!(logged[0].kind, "meteor");
+        let clip = PathBuf::from(logged[0].data["clip"].as_str().unwrap());
+        assert!(clip.exists());
+        fs::remove_dir_all(&dir).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
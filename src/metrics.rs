@@ -0,0 +1,55 @@
+use std::{collections::BTreeMap, fmt::Write as _, io::Write, sync::Mutex};
+
+use anyhow::Result;
+
+use crate::auth::{Auth, Connection, Listener, Request, Role};
+
+// 当前进程的指标: 名称 -> (说明, 数值).
+static METRICS: Mutex<BTreeMap<&'static str, (&'static str, f64)>> = Mutex::new(BTreeMap::new());
+
+pub fn gauge(name: &'static str, help: &'static str, value: f64) {
+    METRICS.lock().unwrap().insert(name, (help, value));
+}
+
+// Prometheus 文本格式.
+pub fn render() -> String {
+    let metrics = METRICS.lock().unwrap();
+    let mut text = String::new();
+    for (name, (help, value)) in metrics.iter() {
+        let _ = writeln!(text, "# HELP {} {}", name, help);
+        let _ = writeln!(text, "# TYPE {} gauge", name);
+        let _ = writeln!(text, "{} {}", name, value);
+    }
+    text
+}
+
+// 在 addr 上提供 GET /metrics, 需要 viewer 权限.
+pub fn serve(addr: &str, auth: &Auth) -> Result<Listener> {
+    let checker = auth.clone();
+    auth.listen(addr, "metrics endpoint", move |stream| {
+        respond(stream, &checker)
+    })
+}
+
+fn respond(mut stream: Connection, auth: &Auth) -> Result<()> {
+    let request = Request::read(&mut stream)?;
+    if auth
+        .authorize(&mut stream, &request, Role::Viewer)?
+        .is_none()
+    {
+        return Ok(());
+    }
+    let (status, body) = if request.path == "/metrics" {
+        ("200 OK", render())
+    } else {
+        ("404 Not Found", "not found\n".to_string())
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    Ok(())
+}
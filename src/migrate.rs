@@ -0,0 +1,180 @@
+use std::{fs, path::Path};
+
+use anyhow::{bail, Context, Result};
+use toml::{Table, Value};
+
+// 配置文件的当前版本. 不兼容的修改 (改名, 移动, 改变含义) 时加一, 并在 MIGRATIONS 中增加一步.
+pub const CONFIG_VERSION: i64 = 1;
+
+// 一步迁移, 原地修改并返回每项修改的说明.
+type Migration = fn(&mut Table) -> Vec<String>;
+
+// 第 i 步把版本 i 升级到 i + 1.
+const MIGRATIONS: [Migration; CONFIG_VERSION as usize] = [photo_metadata];
+
+// 没有 version 的文件是加入版本之前的格式.
+pub fn version(table: &Table) -> Result<i64> {
+    match table.get("version") {
+        None => Ok(0),
+        Some(Value::Integer(version)) if *version >= 0 => Ok(*version),
+        Some(value) => bail!("config version {} is not a version number", value),
+    }
+}
+
+// 迁移到当前版本, 返回修改的说明. 没有需要修改的内容时不改动 table.
+pub fn migrate(table: &mut Table) -> Result<Vec<String>> {
+    let version = version(table)?;
+    if version > CONFIG_VERSION {
+        bail!(
+            "config version {} is newer than this build understands ({}), upgrade the app",
+            version,
+            CONFIG_VERSION
+        );
+    }
+    let mut notes = Vec::new();
+    for migration in &MIGRATIONS[version as usize..] {
+        notes.extend(migration(table));
+    }
+    if !notes.is_empty() {
+        table.insert("version".to_string(), CONFIG_VERSION.into());
+    }
+    Ok(notes)
+}
+
+// 需要迁移时把原文件备份为 <name>.v<version>.bak, 写入迁移后的内容并返回它.
+// 迁移后的文件由 toml 重新生成, 原来的注释只保留在备份中.
+pub fn upgrade(path: &Path, text: &str) -> Result<Option<String>> {
+    let mut table: Table =
+        toml::from_str(text).with_context(|| format!("parse config {}", path.display()))?;
+    let version = version(&table)?;
+    let notes = migrate(&mut table)?;
+    if notes.is_empty() {
+        return Ok(None);
+    }
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let backup = path.with_file_name(format!("{}.v{}.bak", name, version));
+    fs::write(&backup, text).with_context(|| format!("back up config to {}", backup.display()))?;
+    let migrated = toml::to_string_pretty(&table)?;
+    fs::write(path, &migrated).with_context(|| format!("write config {}", path.display()))?;
+    println!(
+        "Migrated {} from version {} to {}, the original is kept as {}:",
+        path.display(),
+        version,
+        CONFIG_VERSION,
+        backup.display()
+    );
+    for note in notes {
+        println!("  {}", note);
+    }
+    Ok(Some(migrated))
+}
+
+fn table<'a>(table: &'a mut Table, key: &str) -> Option<&'a mut Table> {
+    table.get_mut(key).and_then(Value::as_table_mut)
+}
+
+// 0 -> 1: [photo.process] exif 改为 [photo] metadata, 同时作用于照片处理前后; 去掉顶层的 max_frame_queue,
+// 预览只保留最新的一帧.
+fn photo_metadata(config: &mut Table) -> Vec<String> {
+    let mut notes = Vec::new();
+    let exif = table(config, "photo")
+        .and_then(|photo| table(photo, "process"))
+        .and_then(|process| process.remove("exif"));
+    if let (Some(exif), Some(photo)) = (exif, table(config, "photo")) {
+        if photo.contains_key("metadata") {
+            notes.push("removed [photo.process] exif, [photo] metadata is already set".to_string());
+        } else {
+            notes.push(format!(
+                "moved [photo.process] exif = {} to [photo] metadata",
+                exif
+            ));
+            photo.insert("metadata".to_string(), exif);
+        }
+    }
+    if config.remove("max_frame_queue").is_some() {
+        notes
+            .push("removed max_frame_queue, the preview always shows the newest frame".to_string());
+    }
+    notes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    const OLD: &str = "max_frame_queue = 30
+
+[photo.process]
+enabled = true
+exif = false
+";
+
+    // 旧的照片设置移到新位置, 迁移后可以正常加载, 再次迁移没有修改.
+    #[test]
+    fn photo_metadata_moved() {
+        let mut table: Table = toml::from_str(OLD).unwrap();
+        let notes = migrate(&mut table).unwrap();
+        assert_eq!(
+            notes,
+            [
+                "moved [photo.process] exif = false to [photo] metadata",
+                "removed max_frame_queue, the preview always shows the newest frame",
+            ]
+        );
+        assert_eq!(version(&table).unwrap(), CONFIG_VERSION);
+        assert!(!table.contains_key("max_frame_queue"));
+        let config: Config = Value::Table(table.clone()).try_into().unwrap();
+        assert!(!config.photo.metadata);
+        assert!(config.photo.process.enabled);
+        assert!(migrate(&mut table).unwrap().is_empty());
+    }
+
+    // 已经设置的新值优先.
+    #[test]
+    fn metadata_already_set() {
+        let text = "[photo]\nmetadata = true\n[photo.process]\nexif = false\n";
+        let mut table: Table = toml::from_str(text).unwrap();
+        let notes = migrate(&mut table).unwrap();
+        assert_eq!(
+            notes,
+            ["removed [photo.process] exif, [photo] metadata is already set"]
+        );
+        assert_eq!(table["photo"]["metadata"].as_bool(), Some(true));
+        assert!(table["photo"]["process"].get("exif").is_none());
+    }
+
+    #[test]
+    fn versions() {
+        let mut current: Table = toml::from_str("version = 1\nmax_frame_queue = 30\n").unwrap();
+        assert!(migrate(&mut current).unwrap().is_empty());
+        assert!(current.contains_key("max_frame_queue"));
+        let mut newer: Table = toml::from_str("version = 2\n").unwrap();
+        let err = migrate(&mut newer).unwrap_err().to_string();
+        assert!(err.contains("upgrade the app"), "{}", err);
+        for text in ["version = -1\n", "version = \"1\"\n"] {
+            assert!(version(&toml::from_str(text).unwrap()).is_err(), "{}", text);
+        }
+    }
+
+    // 原文件备份为 <name>.v0.bak, 不需要迁移时不写文件.
+    #[test]
+    fn upgrade_backs_up() {
+        let dir = std::env::temp_dir().join(format!("migrate-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        fs::write(&path, OLD).unwrap();
+        let migrated = upgrade(&path, OLD).unwrap().unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.join("config.toml.v0.bak")).unwrap(),
+            OLD
+        );
+        assert_eq!(fs::read_to_string(&path).unwrap(), migrated);
+        assert!(migrated.contains("version = 1"));
+        assert_eq!(upgrade(&path, &migrated).unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
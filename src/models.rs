@@ -0,0 +1,333 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+// 推理后端, onnxruntime 需要启用 onnxruntime feature.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    #[default]
+    Opencv,
+    Onnxruntime,
+}
+
+// onnxruntime 的执行设备, 按顺序尝试, 都不可用时使用 CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Provider {
+    Cuda,
+    Directml,
+    Cpu,
+}
+
+// OpenCV DNN 的计算后端和目标设备.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DnnBackend {
+    #[default]
+    Default,
+    Opencv,
+    Cuda,
+    Openvino,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DnnTarget {
+    #[default]
+    Cpu,
+    Opencl,
+    OpenclFp16,
+    Cuda,
+    CudaFp16,
+    Myriad,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Task {
+    Detection,
+    Classification,
+    Segmentation,
+    // 姿态估计, labels 为关键点名称.
+    Pose,
+    // 拍照后处理中的超分辨率放大.
+    SuperResolution,
+}
+
+// 配置文件中声明的模型, 第一次使用时下载到 models_dir.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelConfig {
+    pub url: String,
+    pub sha256: Option<String>,
+    pub task: Task,
+    // 缓存文件名, 默认取 URL 的最后一段.
+    pub file: Option<String>,
+    // 网络输入尺寸 (宽, 高).
+    #[serde(default = "default_input_size")]
+    pub input_size: [i32; 2],
+    // 预处理: 像素值乘以 scale, 减去 BGR 均值, 是否交换 R/B 通道.
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub mean: [f64; 3],
+    #[serde(default = "default_swap_rb")]
+    pub swap_rb: bool,
+    // 类别名称, 下标为模型输出的类别 ID.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub backend: Backend,
+    #[serde(default = "default_providers")]
+    pub providers: Vec<Provider>,
+    #[serde(default)]
+    pub dnn_backend: DnnBackend,
+    #[serde(default)]
+    pub dnn_target: DnnTarget,
+}
+
+fn default_input_size() -> [i32; 2] {
+    [300, 300]
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+fn default_swap_rb() -> bool {
+    true
+}
+
+fn default_providers() -> Vec<Provider> {
+    vec![Provider::Cuda, Provider::Directml, Provider::Cpu]
+}
+
+impl ModelConfig {
+    pub fn label(&self, class: usize) -> String {
+        self.labels
+            .get(class)
+            .cloned()
+            .unwrap_or_else(|| class.to_string())
+    }
+
+    fn file_name(&self) -> &str {
+        self.file
+            .as_deref()
+            .unwrap_or_else(|| self.url.rsplit('/').next().unwrap_or(&self.url))
+    }
+}
+
+// 实时处理时模型在后台下载, 需要它的阶段等下载结束后再创建.
+#[derive(Debug)]
+pub struct Downloading(pub String);
+
+impl fmt::Display for Downloading {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "model {} is downloading", self.0)
+    }
+}
+
+impl std::error::Error for Downloading {}
+
+pub struct Models {
+    dir: PathBuf,
+    models: BTreeMap<String, ModelConfig>,
+}
+
+impl Models {
+    pub fn new(dir: &Path, models: &BTreeMap<String, ModelConfig>) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            models: models.clone(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Result<&ModelConfig> {
+        self.models
+            .get(name)
+            .with_context(|| format!("unknown model {:?}", name))
+    }
+
+    // 已下载的模型文件, 还没有下载时返回 None.
+    pub fn cached(&self, name: &str) -> Result<Option<PathBuf>> {
+        let model = self.get(name)?;
+        let path = self.dir.join(name).join(model.file_name());
+        Ok(path.exists().then_some(path))
+    }
+
+    // 返回模型文件的本地路径, 不存在时先下载. progress 接收 0~1 的下载进度,
+    // 服务器没有返回长度时为 None, 返回错误时停止下载.
+    pub fn fetch(
+        &self,
+        name: &str,
+        mut progress: impl FnMut(Option<f64>) -> Result<()>,
+    ) -> Result<PathBuf> {
+        let model = self.get(name)?;
+        let path = self.dir.join(name).join(model.file_name());
+        if path.exists() {
+            return Ok(path);
+        }
+        fs::create_dir_all(path.parent().unwrap())?;
+
+        let response = ureq::get(&model.url)
+            .call()
+            .with_context(|| format!("download model {} from {}", name, model.url))?;
+        let total: Option<u64> = response
+            .header("Content-Length")
+            .and_then(|len| len.parse().ok());
+        let partial = path.with_extension("part");
+        let mut file = File::create(&partial)?;
+        let mut reader = response.into_reader();
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        let mut received = 0u64;
+        loop {
+            let n = reader.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            file.write_all(&buf[..n])?;
+            hasher.update(&buf[..n]);
+            received += n as u64;
+            if let Err(err) = progress(total.map(|total| received as f64 / total as f64)) {
+                drop(file);
+                let _ = fs::remove_file(&partial);
+                return Err(err);
+            }
+        }
+        drop(file);
+
+        let digest = format!("{:x}", hasher.finalize());
+        if let Some(expected) = &model.sha256 {
+            if !digest.eq_ignore_ascii_case(expected) {
+                let _ = fs::remove_file(&partial);
+                bail!(
+                    "model {}: SHA256 mismatch, expected {} got {}",
+                    name,
+                    expected,
+                    digest
+                );
+            }
+        }
+        fs::rename(&partial, &path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        net::TcpListener,
+        thread,
+    };
+
+    use super::*;
+
+    const BODY: &[u8] = b"not really a model";
+
+    // 只响应一次请求的 HTTP 服务器.
+    fn serve_once() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            let mut stream = reader.into_inner();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                BODY.len()
+            )
+            .unwrap();
+            stream.write_all(BODY).unwrap();
+        });
+        format!("http://{}/files/detector.onnx", addr)
+    }
+
+    fn models(dir: &Path, url: String, sha256: Option<String>) -> Models {
+        let config: ModelConfig = toml::from_str(&format!(
+            "url = {:?}\ntask = \"detection\"\nlabels = [\"person\"]",
+            url
+        ))
+        .unwrap();
+        assert_eq!(config.input_size, [300, 300]);
+        assert!(config.swap_rb);
+        assert_eq!(config.label(0), "person");
+        assert_eq!(config.label(7), "7");
+        let config = ModelConfig { sha256, ..config };
+        Models::new(dir, &BTreeMap::from([("people".to_string(), config)]))
+    }
+
+    #[test]
+    fn backends() {
+        let config: ModelConfig =
+            toml::from_str("url = \"https://example.com/a.onnx\"\ntask = \"detection\"").unwrap();
+        assert_eq!(config.backend, Backend::Opencv);
+        assert_eq!(
+            config.providers,
+            [Provider::Cuda, Provider::Directml, Provider::Cpu]
+        );
+        let config: ModelConfig = toml::from_str(
+            "url = \"https://example.com/a.onnx\"\ntask = \"detection\"\n\
+             backend = \"onnxruntime\"\nproviders = [\"directml\", \"cpu\"]",
+        )
+        .unwrap();
+        assert_eq!(config.backend, Backend::Onnxruntime);
+        assert_eq!(config.providers, [Provider::Directml, Provider::Cpu]);
+        assert!(toml::from_str::<ModelConfig>(
+            "url = \"a\"\ntask = \"detection\"\nbackend = \"tensorrt\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn downloads_once() {
+        let dir = std::env::temp_dir().join(format!("models-test-{}", std::process::id()));
+        let models = models(
+            &dir,
+            serve_once(),
+            Some(format!("{:X}", Sha256::digest(BODY))),
+        );
+        assert!(models.get("cars").is_err());
+        assert_eq!(models.cached("people").unwrap(), None);
+        let mut progress = Vec::new();
+        let path = models
+            .fetch("people", |p| {
+                progress.push(p);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(path, dir.join("people").join("detector.onnx"));
+        assert_eq!(fs::read(&path).unwrap(), BODY);
+        assert_eq!(progress.last(), Some(&Some(1.0)));
+        // 服务器已经退出, 第二次直接使用缓存.
+        assert_eq!(models.fetch("people", |_| Ok(())).unwrap(), path);
+        assert_eq!(models.cached("people").unwrap(), Some(path));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_checksum_mismatch() {
+        let dir = std::env::temp_dir().join(format!("models-sha-{}", std::process::id()));
+        let models = models(&dir, serve_once(), Some("00".repeat(32)));
+        let err = models.fetch("people", |_| Ok(())).unwrap_err();
+        assert!(err.to_string().contains("SHA256 mismatch"));
+        let cached = dir.join("people");
+        assert_eq!(fs::read_dir(&cached).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
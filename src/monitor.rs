@@ -0,0 +1,384 @@
+use std::{
+    fs, mem,
+    path::PathBuf,
+    sync::mpsc::SyncSender,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{self, Mat, Size, Vector},
+    imgcodecs,
+    imgproc::{self, COLOR_BGR2GRAY, INTER_AREA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    audio::{AudioConfig, AudioInput},
+    events::EventStore,
+    exclusions::ExclusionMask,
+    motion_filter::{MotionFilter, MotionFilterConfig},
+    photo,
+    pipeline::FrameProcessor,
+    threads::{self, ThreadConfig},
+};
+
+// 运动检测使用的画面宽度, 监护只需要知道有没有动.
+const MOTION_WIDTH: i32 = 160;
+// 运动或声音停止后继续显示 "Motion" 或 "Sound" 的时长, 避免来回闪烁.
+const MOTION_HOLD: Duration = Duration::from_secs(3);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MonitorConfig {
+    pub enabled: bool,
+    // 像素亮度变化超过 threshold (0..255), 且变化像素占画面的比例超过 motion 时认为有运动.
+    pub threshold: f64,
+    pub motion: f64,
+    // 两次提醒的最短间隔 (秒), 运动和声音分别计算.
+    pub cooldown: f64,
+    // 声音提醒: 音量超过 sound_level (dBFS) 并持续 sound_duration 秒时提醒, 从 [audio] 输入读取.
+    pub sound: bool,
+    pub sound_level: f64,
+    pub sound_duration: f64,
+    // 提醒以 JSON POST 到该地址, 例如 ntfy 或 Home Assistant 的 webhook.
+    pub alert_url: Option<String>,
+    // 监护模式下的预览帧率, 降低以省电.
+    pub preview_fps: f64,
+    // 定时快照的目录 (例如共享文件夹), 不设置时不保存.
+    pub snapshot_dir: Option<PathBuf>,
+    // 快照间隔 (秒).
+    pub snapshot_interval: f64,
+    pub quality: i32,
+    // 室外摄像头的误报过滤.
+    pub filter: MotionFilterConfig,
+}
+
+impl Default for MonitorConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            threshold: 25.0,
+            motion: 0.01,
+            cooldown: 120.0,
+            sound: false,
+            sound_level: -30.0,
+            sound_duration: 1.0,
+            alert_url: None,
+            preview_fps: 5.0,
+            snapshot_dir: None,
+            snapshot_interval: 300.0,
+            quality: 85,
+            filter: MotionFilterConfig::default(),
+        }
+    }
+}
+
+// 提醒的原因: 变化的面积比例, 或声音的 dBFS.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Trigger {
+    Motion(f64),
+    Sound(f64),
+}
+
+enum Job {
+    Alert(Trigger),
+    Snapshot(Mat),
+}
+
+type ActivityCallback = Box<dyn Fn(bool, bool) + Send>;
+
+// 婴儿和宠物监护预设: 低分辨率帧差检测运动, 可选监听声音, 有运动或声音时发出提醒,
+// 定时保存快照. 提醒和快照在后台线程中处理, 不阻塞采集.
+pub struct Monitor {
+    config: MonitorConfig,
+    jobs: SyncSender<Job>,
+    on_activity: ActivityCallback,
+    small: Mat,
+    blurred: Mat,
+    gray: Mat,
+    previous: Mat,
+    diff: Mat,
+    mask: Mat,
+    exclusions: ExclusionMask,
+    filter: MotionFilter,
+    motion: bool,
+    last_motion: Option<Instant>,
+    last_alert: Option<Instant>,
+    last_snapshot: Option<Instant>,
+    // 声音提醒关闭或音频输入出错后为 None.
+    audio: Option<AudioInput>,
+    sound: Sound,
+}
+
+// 音量超过门限的持续时间, 单次的响动 (关门, 咳嗽) 不提醒.
+#[derive(Default)]
+struct Sound {
+    loud_since: Option<Instant>,
+    last_sound: Option<Instant>,
+    last_alert: Option<Instant>,
+    shown: bool,
+}
+
+impl Sound {
+    // 返回是否持续有声音.
+    fn update(&mut self, loud: bool, duration: Duration, now: Instant) -> bool {
+        if !loud {
+            self.loud_since = None;
+            return false;
+        }
+        let since = *self.loud_since.get_or_insert(now);
+        let sustained = now - since >= duration;
+        if sustained {
+            self.last_sound = Some(now);
+        }
+        sustained
+    }
+
+    fn heard(&self, now: Instant) -> bool {
+        self.last_sound.is_some_and(|last| now - last < MOTION_HOLD)
+    }
+}
+
+impl Monitor {
+    // on_activity 在运动或声音开始和停止时以 (运动, 声音) 调用, 用于更新界面.
+    pub fn new(
+        config: &MonitorConfig,
+        audio: &AudioConfig,
+        events: EventStore,
+        workers: &ThreadConfig,
+        on_activity: impl Fn(bool, bool) + Send + 'static,
+    ) -> Result<Self> {
+        if let Some(dir) = &config.snapshot_dir {
+            fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        }
+        let audio = config
+            .sound
+            .then(|| AudioInput::open(audio, workers).context("sound alerts"))
+            .transpose()?;
+        let saving = config.clone();
+        let jobs = threads::worker("monitor", 2, workers, move |job: Job| match job {
+            Job::Alert(trigger) => alert(&saving, &events, trigger),
+            Job::Snapshot(frame) => snapshot(&saving, &frame),
+        })?;
+        Ok(Self {
+            config: config.clone(),
+            jobs,
+            on_activity: Box::new(on_activity),
+            small: Mat::default(),
+            blurred: Mat::default(),
+            gray: Mat::default(),
+            previous: Mat::default(),
+            diff: Mat::default(),
+            mask: Mat::default(),
+            exclusions: ExclusionMask::default(),
+            filter: MotionFilter::new(&config.filter)?,
+            motion: false,
+            last_motion: None,
+            last_alert: None,
+            last_snapshot: None,
+            audio,
+            sound: Sound::default(),
+        })
+    }
+
+    // 持续有声音时提醒. 音频输入出错时报告一次, 之后只检测运动.
+    fn listen(&mut self, now: Instant) -> Result<()> {
+        let Some(audio) = &self.audio else {
+            return Ok(());
+        };
+        let levels = audio.levels();
+        if let Some(error) = levels.error {
+            self.audio = None;
+            bail!("sound alerts stopped: {}", error);
+        }
+        let level = levels.dbfs();
+        let loud = levels.is_fresh() && level >= self.config.sound_level;
+        let duration = Duration::from_secs_f64(self.config.sound_duration.max(0.0));
+        let cooldown = Duration::from_secs_f64(self.config.cooldown.max(0.0));
+        if self.sound.update(loud, duration, now)
+            && !self
+                .sound
+                .last_alert
+                .is_some_and(|last| now - last < cooldown)
+            && self
+                .jobs
+                .try_send(Job::Alert(Trigger::Sound(level)))
+                .is_ok()
+        {
+            self.sound.last_alert = Some(now);
+        }
+        Ok(())
+    }
+
+    // 与上一帧相比变化的像素比例, 经过误报过滤.
+    fn changed(&mut self, frame: &Mat) -> Result<f64> {
+        let scale = (MOTION_WIDTH as f64 / frame.cols() as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        // 模糊去掉传感器噪声, 暗处的高增益画面噪声很大.
+        imgproc::gaussian_blur(
+            &self.small,
+            &mut self.blurred,
+            Size::new(5, 5),
+            0.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )?;
+        imgproc::cvt_color(&self.blurred, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        if self.previous.empty() || self.previous.size()? != self.gray.size()? {
+            self.gray.copy_to(&mut self.previous)?;
+            return Ok(0.0);
+        }
+        core::absdiff(&self.gray, &self.previous, &mut self.diff)?;
+        self.gray.copy_to(&mut self.previous)?;
+        imgproc::threshold(
+            &self.diff,
+            &mut self.mask,
+            self.config.threshold,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+        if let Some(exclusions) = self.exclusions.get(self.mask.size()?)? {
+            core::bitwise_and(&self.mask, exclusions, &mut self.diff, &core::no_array())?;
+            mem::swap(&mut self.mask, &mut self.diff);
+        }
+        self.filter.apply(&self.mask)
+    }
+}
+
+impl FrameProcessor for Monitor {
+    fn name(&self) -> &str {
+        "monitor"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let area = self.changed(frame)?;
+        let moving = self.filter.sustained(area > self.config.motion);
+        if moving {
+            self.last_motion = Some(Instant::now());
+        }
+        let now = Instant::now();
+        let listened = self.listen(now);
+        let motion = self
+            .last_motion
+            .is_some_and(|last| last.elapsed() < MOTION_HOLD);
+        let sound = self.sound.heard(now);
+        if motion != self.motion || sound != self.sound.shown {
+            (self.motion, self.sound.shown) = (motion, sound);
+            (self.on_activity)(motion, sound);
+        }
+
+        let cooldown = Duration::from_secs_f64(self.config.cooldown.max(0.0));
+        if moving
+            && !self
+                .last_alert
+                .is_some_and(|last| last.elapsed() < cooldown)
+        {
+            // 后台线程还在发送上一个提醒时不重复提醒.
+            if self
+                .jobs
+                .try_send(Job::Alert(Trigger::Motion(area)))
+                .is_ok()
+            {
+                self.last_alert = Some(Instant::now());
+            }
+        }
+
+        let interval = Duration::from_secs_f64(self.config.snapshot_interval.max(1.0));
+        if self.config.snapshot_dir.is_some()
+            && !self
+                .last_snapshot
+                .is_some_and(|last| last.elapsed() < interval)
+            && self
+                .jobs
+                .try_send(Job::Snapshot(frame.try_clone()?))
+                .is_ok()
+        {
+            self.last_snapshot = Some(Instant::now());
+        }
+        listened
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        if self.motion || self.sound.shown {
+            (self.on_activity)(false, false);
+        }
+    }
+}
+
+// 记录 motion 或 sound 事件, 设置了 alert_url 时发送提醒.
+fn alert(config: &MonitorConfig, events: &EventStore, trigger: Trigger) -> Result<()> {
+    let time = Local::now();
+    let (kind, title, mut body) = match trigger {
+        Trigger::Motion(area) => ("motion", "Motion detected", json!({ "area": area })),
+        Trigger::Sound(level) => ("sound", "Sound detected", json!({ "level": level })),
+    };
+    events.log(kind, body.clone());
+    let Some(url) = &config.alert_url else {
+        return Ok(());
+    };
+    body["title"] = json!(title);
+    body["message"] = json!(format!("{} at {}", title, time.format("%H:%M:%S")));
+    body["time"] = json!(time.to_rfc3339());
+    ureq::post(url)
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+        .with_context(|| format!("send alert to {}", url))?;
+    Ok(())
+}
+
+// 快照按时间命名, 另外覆盖 latest.jpg, 方便其他设备总是打开同一个文件.
+fn snapshot(config: &MonitorConfig, frame: &Mat) -> Result<()> {
+    let Some(dir) = &config.snapshot_dir else {
+        return Ok(());
+    };
+    let params = photo::write_params("jpg", Some(config.quality));
+    let mut data = Vector::<u8>::new();
+    if !imgcodecs::imencode(".jpg", frame, &mut data, &params)? {
+        bail!("encode snapshot");
+    }
+    let name = format!("snapshot-{}.jpg", Local::now().format("%Y-%m-%dT%H-%M-%S"));
+    fs::write(dir.join(name), data.as_slice())?;
+    // 先写临时文件再改名, 其他设备不会读到写了一半的文件.
+    let latest = dir.join("latest.jpg");
+    let partial = dir.join("latest.jpg.part");
+    fs::write(&partial, data.as_slice())?;
+    fs::rename(&partial, &latest)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 音量要持续 duration 才算有声音, 中间安静一次就重新计时; 声音停止后保持 MOTION_HOLD.
+    #[test]
+    fn sustained_sound() {
+        let start = Instant::now();
+        let at = |ms: u64| start + Duration::from_millis(ms);
+        let duration = Duration::from_secs(1);
+        let mut sound = Sound::default();
+        assert!(!sound.update(true, duration, at(0)));
+        assert!(!sound.update(true, duration, at(900)));
+        assert!(!sound.update(false, duration, at(950)));
+        assert!(!sound.update(true, duration, at(1000)));
+        assert!(!sound.heard(at(1500)));
+        assert!(sound.update(true, duration, at(2000)));
+        assert!(sound.heard(at(2000)));
+        assert!(!sound.update(false, duration, at(2100)));
+        assert!(sound.heard(at(2000) + MOTION_HOLD - Duration::from_millis(1)));
+        assert!(!sound.heard(at(2000) + MOTION_HOLD));
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size, Vector, CV_32F},
+    imgproc::{self, CHAIN_APPROX_SIMPLE, MORPH_ELLIPSE, MORPH_OPEN, RETR_EXTERNAL},
+    prelude::*,
+};
+use serde::Deserialize;
+
+// 闪烁统计的滑动平均系数, 约为最近 20 帧.
+const FLICKER_ALPHA: f64 = 0.05;
+
+// 室外误报过滤的强度.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Level {
+    #[default]
+    Off,
+    Low,
+    Medium,
+    High,
+}
+
+// 室外摄像头的误报过滤: 雨雪和飞虫是又小又快的斑点, 红外灯下的反光会来回闪烁,
+// 云和路灯会让整个画面变亮变暗. level 选择一组设置, 也可以单独覆盖其中几项.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MotionFilterConfig {
+    pub level: Level,
+    // 小于画面这个比例的斑点忽略.
+    pub min_blob: Option<f64>,
+    // 连续这么多帧有运动才算.
+    pub frames: Option<u32>,
+    // 超过画面这个比例同时变化时认为是光照变化.
+    pub global: Option<f64>,
+    // 最近的帧中变化比例超过 flicker 的像素认为在闪烁, 不参与判断.
+    pub flicker: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Settings {
+    pub min_blob: f64,
+    pub frames: u32,
+    pub global: f64,
+    pub flicker: f64,
+}
+
+impl Settings {
+    pub fn level(level: Level) -> Self {
+        let (min_blob, frames, global, flicker) = match level {
+            Level::Off => (0.0, 1, 1.0, 1.0),
+            Level::Low => (0.0005, 2, 0.6, 0.8),
+            Level::Medium => (0.001, 3, 0.4, 0.6),
+            Level::High => (0.002, 5, 0.25, 0.4),
+        };
+        Self {
+            min_blob,
+            frames,
+            global,
+            flicker,
+        }
+    }
+}
+
+impl MotionFilterConfig {
+    pub fn settings(&self) -> Settings {
+        let level = Settings::level(self.level);
+        Settings {
+            min_blob: self.min_blob.unwrap_or(level.min_blob),
+            frames: self.frames.unwrap_or(level.frames).max(1),
+            global: self.global.unwrap_or(level.global),
+            flicker: self.flicker.unwrap_or(level.flicker),
+        }
+    }
+}
+
+// 保留面积不小于 min_blob 的斑点, 返回保留的总面积. 面积都是画面的比例.
+pub fn kept(blobs: &[f64], settings: &Settings) -> f64 {
+    blobs
+        .iter()
+        .filter(|&&area| area >= settings.min_blob)
+        .sum()
+}
+
+pub struct MotionFilter {
+    settings: Settings,
+    kernel: Mat,
+    opened: Mat,
+    // 每个像素最近变化的频率 (0..255).
+    changes: Mat,
+    steady: Mat,
+    filtered: Mat,
+    // 连续有运动的帧数.
+    streak: u32,
+}
+
+impl MotionFilter {
+    pub fn new(config: &MotionFilterConfig) -> Result<Self> {
+        Ok(Self {
+            settings: config.settings(),
+            kernel: imgproc::get_structuring_element(
+                MORPH_ELLIPSE,
+                Size::new(3, 3),
+                Point::new(-1, -1),
+            )?,
+            opened: Mat::default(),
+            changes: Mat::default(),
+            steady: Mat::default(),
+            filtered: Mat::default(),
+            streak: 0,
+        })
+    }
+
+    // mask 为帧差的二值图, 返回过滤后变化的面积比例.
+    pub fn apply(&mut self, mask: &Mat) -> Result<f64> {
+        let total = mask.total().max(1) as f64;
+        // 关闭并且没有覆盖任何一项时和原来一样只统计变化的像素.
+        if self.settings == Settings::level(Level::Off) {
+            return Ok(core::count_non_zero(mask)? as f64 / total);
+        }
+        if self.changes.size()? != mask.size()? {
+            self.changes = Mat::zeros_size(mask.size()?, CV_32F)?.to_mat()?;
+        }
+        imgproc::accumulate_weighted(mask, &mut self.changes, FLICKER_ALPHA, &core::no_array())?;
+        // 整个画面一起变化是光照, 不是运动.
+        if core::count_non_zero(mask)? as f64 / total > self.settings.global {
+            return Ok(0.0);
+        }
+        // 去掉经常闪烁的像素.
+        core::compare(
+            &self.changes,
+            &Scalar::all(self.settings.flicker * 255.0),
+            &mut self.steady,
+            core::CMP_LE,
+        )?;
+        core::bitwise_and(mask, &self.steady, &mut self.filtered, &core::no_array())?;
+        // 开运算去掉雨雪留下的单个像素.
+        imgproc::morphology_ex(
+            &self.filtered,
+            &mut self.opened,
+            MORPH_OPEN,
+            &self.kernel,
+            Point::new(-1, -1),
+            1,
+            core::BORDER_CONSTANT,
+            Scalar::all(0.0),
+        )?;
+        let mut contours = Vector::<Vector<Point>>::new();
+        imgproc::find_contours(
+            &self.opened,
+            &mut contours,
+            RETR_EXTERNAL,
+            CHAIN_APPROX_SIMPLE,
+            Point::new(0, 0),
+        )?;
+        let blobs = contours
+            .iter()
+            .map(|contour| Ok(imgproc::contour_area(&contour, false)? / total))
+            .collect::<Result<Vec<f64>>>()?;
+        Ok(kept(&blobs, &self.settings))
+    }
+
+    // 飞过镜头的虫子只持续一两帧, 运动要连续 frames 帧才算.
+    pub fn sustained(&mut self, moving: bool) -> bool {
+        self.streak = if moving {
+            self.streak.saturating_add(1)
+        } else {
+            0
+        };
+        self.streak >= self.settings.frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Rect, CV_8UC1};
+
+    use super::*;
+
+    #[test]
+    fn overrides() {
+        let config = MotionFilterConfig {
+            level: Level::High,
+            min_blob: Some(0.01),
+            frames: Some(0),
+            ..MotionFilterConfig::default()
+        };
+        assert_eq!(
+            config.settings(),
+            Settings {
+                min_blob: 0.01,
+                frames: 1,
+                global: 0.25,
+                flicker: 0.4,
+            }
+        );
+        let low = Settings::level(Level::Low);
+        assert!((kept(&[0.0001, 0.002, 0.0005], &low) - 0.0025).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sustained_frames() {
+        let config = MotionFilterConfig {
+            level: Level::Medium,
+            ..MotionFilterConfig::default()
+        };
+        let mut filter = MotionFilter::new(&config).unwrap();
+        let moving = [true, true, true, true, false, true, true];
+        let sustained: Vec<bool> = moving.iter().map(|&m| filter.sustained(m)).collect();
+        assert_eq!(sustained, [false, false, true, true, false, false, false]);
+    }
+
+    // 100x100 的帧差里有一个 20x20 的物体和三个雨点.
+    fn mask() -> Mat {
+        let mut mask = Mat::zeros(100, 100, CV_8UC1).unwrap().to_mat().unwrap();
+        imgproc::rectangle(
+            &mut mask,
+            Rect::new(10, 10, 20, 20),
+            Scalar::all(255.0),
+            -1,
+            imgproc::LINE_8,
+            0,
+        )
+        .unwrap();
+        for (row, col) in [(70, 70), (20, 80), (90, 90)] {
+            *mask.at_2d_mut::<u8>(row, col).unwrap() = 255;
+        }
+        mask
+    }
+
+    fn filter(level: Level) -> MotionFilter {
+        MotionFilter::new(&MotionFilterConfig {
+            level,
+            ..MotionFilterConfig::default()
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn drops_rain_and_light() {
+        let area = filter(Level::Off).apply(&mask()).unwrap();
+        assert!((area - 0.0403).abs() < 1e-9, "{}", area);
+        // 雨点被开运算去掉, 物体只少了边缘.
+        let area = filter(Level::Medium).apply(&mask()).unwrap();
+        assert!(area > 0.03 && area < 0.04, "{}", area);
+        let light = Mat::new_rows_cols_with_default(100, 100, CV_8UC1, Scalar::all(255.0)).unwrap();
+        assert_eq!(filter(Level::Medium).apply(&light).unwrap(), 0.0);
+        assert_eq!(filter(Level::Off).apply(&light).unwrap(), 1.0);
+    }
+}
@@ -0,0 +1,219 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    path::PathBuf,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+// MQTT broker 和发布的主题前缀.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    // host:port, 例如 "127.0.0.1:1883".
+    pub broker: String,
+    pub topic: String,
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    // 只包含密码的文件.
+    #[serde(default)]
+    pub password_file: Option<PathBuf>,
+}
+
+fn default_client_id() -> String {
+    "slint-opencv".to_string()
+}
+
+// MQTT 的剩余长度: 每字节 7 位, 最高位表示后面还有.
+pub fn remaining_length(mut length: usize) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        bytes.push(byte);
+        if length == 0 {
+            return bytes;
+        }
+    }
+}
+
+fn string(data: &mut Vec<u8>, text: &[u8]) {
+    data.extend_from_slice(&(text.len() as u16).to_be_bytes());
+    data.extend_from_slice(text);
+}
+
+fn packet(kind: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    packet.extend(remaining_length(body.len()));
+    packet.extend_from_slice(body);
+    packet
+}
+
+// 以 QoS 0 发布一组 (主题, 内容): 连接, 发布, 断开. 只实现了发布需要的 MQTT 3.1.1 报文,
+// 状态更新间隔较长, 不保持连接.
+pub fn publish(config: &MqttConfig, messages: &[(String, String)], retain: bool) -> Result<()> {
+    let addr = config
+        .broker
+        .to_socket_addrs()
+        .with_context(|| format!("resolve {}", config.broker))?
+        .next()
+        .with_context(|| format!("resolve {}", config.broker))?;
+    let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(5))
+        .with_context(|| format!("connect to {}", config.broker))?;
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(5)))?;
+
+    let password = config
+        .password_file
+        .as_ref()
+        .map(|file| {
+            fs::read_to_string(file)
+                .with_context(|| format!("read {}", file.display()))
+                .map(|password| password.trim().to_string())
+        })
+        .transpose()?;
+    // 协议名, 版本 4, clean session, keep alive 60 秒.
+    let mut flags = 0x02;
+    if config.username.is_some() {
+        flags |= 0x80;
+    }
+    if password.is_some() {
+        flags |= 0x40;
+    }
+    let mut connect = Vec::new();
+    string(&mut connect, b"MQTT");
+    connect.extend_from_slice(&[4, flags, 0, 60]);
+    string(&mut connect, config.client_id.as_bytes());
+    if let Some(username) = &config.username {
+        string(&mut connect, username.as_bytes());
+    }
+    if let Some(password) = &password {
+        string(&mut connect, password.as_bytes());
+    }
+    stream.write_all(&packet(0x10, &connect))?;
+    let mut connack = [0u8; 4];
+    stream
+        .read_exact(&mut connack)
+        .context("read MQTT CONNACK")?;
+    if connack[0] != 0x20 {
+        bail!("unexpected MQTT reply {:#04x}", connack[0]);
+    }
+    if connack[3] != 0 {
+        bail!("MQTT broker refused the connection (code {})", connack[3]);
+    }
+    for (topic, payload) in messages {
+        let mut publish = Vec::new();
+        string(&mut publish, topic.as_bytes());
+        publish.extend_from_slice(payload.as_bytes());
+        stream.write_all(&packet(0x30 | retain as u8, &publish))?;
+    }
+    stream.write_all(&[0xe0, 0])?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use super::*;
+
+    // MQTT 3.1.1 规范中的例子.
+    #[test]
+    fn remaining_lengths() {
+        assert_eq!(remaining_length(0), [0x00]);
+        assert_eq!(remaining_length(127), [0x7f]);
+        assert_eq!(remaining_length(128), [0x80, 0x01]);
+        assert_eq!(remaining_length(16_383), [0xff, 0x7f]);
+        assert_eq!(remaining_length(16_384), [0x80, 0x80, 0x01]);
+        assert_eq!(remaining_length(268_435_455), [0xff, 0xff, 0xff, 0x7f]);
+    }
+
+    // 用本地 TCP 端口假装 broker, 回复 reply 作为 CONNACK, 返回收到的所有字节.
+    fn broker(config: &MqttConfig, reply: [u8; 4], retain: bool) -> (Result<()>, Vec<u8>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = MqttConfig {
+            broker: listener.local_addr().unwrap().to_string(),
+            ..config.clone()
+        };
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut fixed = [0u8; 2];
+            stream.read_exact(&mut fixed).unwrap();
+            let mut received = fixed.to_vec();
+            let mut connect = vec![0u8; fixed[1] as usize];
+            stream.read_exact(&mut connect).unwrap();
+            received.extend(connect);
+            stream.write_all(&reply).unwrap();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+        let messages = [
+            ("parking/a1".to_string(), "occupied".to_string()),
+            ("parking/free".to_string(), "3".to_string()),
+        ];
+        let result = publish(&config, &messages, retain);
+        (result, server.join().unwrap())
+    }
+
+    fn config() -> MqttConfig {
+        MqttConfig {
+            broker: String::new(),
+            topic: "parking".to_string(),
+            client_id: default_client_id(),
+            username: None,
+            password_file: None,
+        }
+    }
+
+    #[test]
+    fn publish_packets() {
+        let (result, received) = broker(&config(), [0x20, 2, 0, 0], true);
+        result.unwrap();
+        let mut expected = vec![0x10, 24, 0, 4];
+        expected.extend(b"MQTT");
+        expected.extend([4, 0x02, 0, 60, 0, 12]);
+        expected.extend(b"slint-opencv");
+        expected.extend([0x31, 20, 0, 10]);
+        expected.extend(b"parking/a1occupied");
+        expected.extend([0x31, 15, 0, 12]);
+        expected.extend(b"parking/free3");
+        expected.extend([0xe0, 0]);
+        assert_eq!(received, expected);
+    }
+
+    // 密码从文件读取, 去掉末尾的换行.
+    #[test]
+    fn credentials() {
+        let dir = std::env::temp_dir().join(format!("mqtt-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let password_file = dir.join("password");
+        fs::write(&password_file, "secret\n").unwrap();
+        let config = MqttConfig {
+            username: Some("cam".to_string()),
+            password_file: Some(password_file),
+            ..config()
+        };
+        let (result, received) = broker(&config, [0x20, 2, 0, 0], false);
+        result.unwrap();
+        assert_eq!(received[9], 0xc2);
+        let connect = &received[..2 + received[1] as usize];
+        assert!(connect.ends_with(b"\x00\x03cam\x00\x06secret"));
+        assert_eq!(received[connect.len()], 0x30);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn refused() {
+        let (result, _) = broker(&config(), [0x20, 2, 0, 5], false);
+        let err = result.unwrap_err().to_string();
+        assert!(err.contains("refused"), "{}", err);
+    }
+}
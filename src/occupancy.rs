@@ -0,0 +1,645 @@
+use std::{thread, time::Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size, Vector, CV_8U},
+    imgproc::{self, COLOR_BGR2GRAY, FONT_HERSHEY_SIMPLEX, LINE_8, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    detect::Detector,
+    events::{Event, EventStore},
+    pipeline::FrameProcessor,
+    zones::Zone,
+};
+
+// 状态机: 在 [[zones]] 上判断门开着还是关着, 工位有人还是空着, 状态持续一段时间后才切换,
+// 每次切换记录 state 事件并发送提醒, 控制面板中显示每个状态机的时间线.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OccupancyConfig {
+    pub enabled: bool,
+    // 检测信号每隔多少帧运行一次检测.
+    pub detect_every: u32,
+    // 状态切换以 JSON POST 到这些地址, 例如 ntfy 或 Home Assistant 的 webhook.
+    pub alert_urls: Vec<String>,
+    // 时间线显示的时长 (秒).
+    pub timeline: f64,
+    pub machines: Vec<Machine>,
+}
+
+impl Default for OccupancyConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detect_every: 3,
+            alert_urls: Vec::new(),
+            timeline: 3600.0,
+            machines: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Signal {
+    // 区域和开始时的画面 (门关着, 工位空着) 相比变化的像素比例超过 fraction.
+    Change,
+    // 检测到的目标 (tracking.model, 默认为行人) 的底边中点在区域内.
+    Detection,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Machine {
+    pub name: String,
+    // [[zones]] 中的区域名称.
+    pub zone: String,
+    pub signal: Signal,
+    // 两个状态的名称.
+    #[serde(default = "default_on")]
+    pub on: String,
+    #[serde(default = "default_off")]
+    pub off: String,
+    // 新的状态持续这么多秒后才切换 (防抖).
+    #[serde(default = "default_on_after")]
+    pub on_after: f64,
+    #[serde(default = "default_off_after")]
+    pub off_after: f64,
+    // change 信号: 像素亮度变化超过 threshold (0..255) 算作变化.
+    #[serde(default = "default_threshold")]
+    pub threshold: f64,
+    #[serde(default = "default_fraction")]
+    pub fraction: f64,
+}
+
+fn default_on() -> String {
+    "on".to_string()
+}
+
+fn default_off() -> String {
+    "off".to_string()
+}
+
+fn default_on_after() -> f64 {
+    1.0
+}
+
+fn default_off_after() -> f64 {
+    3.0
+}
+
+fn default_threshold() -> f64 {
+    30.0
+}
+
+fn default_fraction() -> f64 {
+    0.2
+}
+
+// 防抖: 原始信号和当前状态不同并且持续 on_after 或 off_after 秒后才切换.
+#[derive(Debug, Clone, Default)]
+pub struct Debounce {
+    state: Option<bool>,
+    // 和当前状态不同的原始信号开始的时间 (秒).
+    pending: Option<f64>,
+}
+
+impl Debounce {
+    pub fn state(&self) -> Option<bool> {
+        self.state
+    }
+
+    // 第一次更新直接采用原始信号. 返回切换后的状态.
+    pub fn update(&mut self, raw: bool, now: f64, on_after: f64, off_after: f64) -> Option<bool> {
+        let Some(state) = self.state else {
+            self.state = Some(raw);
+            return Some(raw);
+        };
+        if raw == state {
+            self.pending = None;
+            return None;
+        }
+        let since = *self.pending.get_or_insert(now);
+        let delay = if raw { on_after } else { off_after };
+        if now - since >= delay {
+            self.state = Some(raw);
+            self.pending = None;
+            return Some(raw);
+        }
+        None
+    }
+}
+
+// 一次状态切换, 来自 state 事件.
+#[derive(Debug, Clone)]
+pub struct Transition {
+    pub machine: String,
+    pub time: DateTime<Local>,
+    pub on: bool,
+    pub state: String,
+}
+
+impl Transition {
+    pub fn from_event(event: &Event) -> Option<Self> {
+        if event.kind != "state" {
+            return None;
+        }
+        Some(Self {
+            machine: event.data.get("machine")?.as_str()?.to_string(),
+            time: event.time,
+            on: event.data.get("on")?.as_bool()?,
+            state: event.data.get("state")?.as_str()?.to_string(),
+        })
+    }
+}
+
+// 时间线上状态为 on 的时段, 取值为时间线宽度的比例. changes 为 (距时间线开始的秒数, 状态),
+// 按时间排序, 时间线开始之前的切换决定开始时的状态.
+pub fn spans(changes: &[(f64, bool)], window: f64) -> Vec<(f64, f64)> {
+    let mut spans = Vec::new();
+    let mut start: Option<f64> = None;
+    for &(time, on) in changes {
+        let time = time.clamp(0.0, window);
+        match (start, on) {
+            (None, true) => start = Some(time),
+            (Some(from), false) => {
+                if time > from {
+                    spans.push((from / window, time / window));
+                }
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(from) = start {
+        if window > from {
+            spans.push((from / window, 1.0));
+        }
+    }
+    spans
+}
+
+// 时间线之前的切换只保留每个状态机最后一次, 它决定时间线开始时的状态.
+pub fn prune(transitions: &mut Vec<Transition>, cutoff: DateTime<Local>) {
+    let mut keep = vec![true; transitions.len()];
+    for (i, transition) in transitions.iter().enumerate() {
+        if transition.time < cutoff {
+            keep[i] = !transitions[i + 1..]
+                .iter()
+                .any(|later| later.machine == transition.machine && later.time < cutoff);
+        }
+    }
+    let mut keep = keep.into_iter();
+    transitions.retain(|_| keep.next().unwrap_or(true));
+}
+
+// 控制面板的时间线: 每个状态机一行 "名称: 状态 since 时间", 以及 on 时段 (行号, 开始, 结束).
+pub fn timeline(
+    transitions: &[Transition],
+    machines: &[Machine],
+    now: DateTime<Local>,
+    window: f64,
+) -> (Vec<String>, Vec<(usize, f64, f64)>) {
+    let start = now - chrono::TimeDelta::milliseconds((window * 1000.0) as i64);
+    let mut rows = Vec::new();
+    let mut all = Vec::new();
+    for (row, machine) in machines.iter().enumerate() {
+        let changes: Vec<&Transition> = transitions
+            .iter()
+            .filter(|transition| transition.machine == machine.name)
+            .collect();
+        rows.push(match changes.last() {
+            Some(last) => format!(
+                "{}: {} since {}",
+                machine.name,
+                last.state,
+                last.time.format("%H:%M:%S")
+            ),
+            None => format!("{}: unknown", machine.name),
+        });
+        let offsets: Vec<(f64, bool)> = changes
+            .iter()
+            .map(|transition| {
+                let offset = (transition.time - start).num_milliseconds() as f64 / 1000.0;
+                (offset, transition.on)
+            })
+            .collect();
+        all.extend(
+            spans(&offsets, window)
+                .into_iter()
+                .map(|(from, to)| (row, from, to)),
+        );
+    }
+    (rows, all)
+}
+
+struct Watch {
+    machine: Machine,
+    zone: Zone,
+    debounce: Debounce,
+    since: Option<DateTime<Local>>,
+    // 当前的原始信号, 用于显示.
+    raw: bool,
+}
+
+pub struct Occupancy {
+    config: OccupancyConfig,
+    watches: Vec<Watch>,
+    detector: Option<Box<dyn Detector>>,
+    events: EventStore,
+    started: Instant,
+    frames: u64,
+    // 检测信号最近一次的目标底边中点 (比例坐标).
+    feet: Vec<(f64, f64)>,
+    gray: Mat,
+    reference: Option<Mat>,
+    // 每个区域的掩码, 画面大小变化时重新生成.
+    masks: Vec<Mat>,
+}
+
+impl Occupancy {
+    pub fn new(
+        config: &OccupancyConfig,
+        zones: &[Zone],
+        detector: Option<Box<dyn Detector>>,
+        events: EventStore,
+    ) -> Result<Self> {
+        if config.machines.is_empty() {
+            bail!("add at least one [[occupancy.machines]]");
+        }
+        let mut watches = Vec::new();
+        for machine in &config.machines {
+            let zone = zones
+                .iter()
+                .find(|zone| zone.name == machine.zone)
+                .with_context(|| {
+                    format!(
+                        "state {:?} uses unknown zone {:?}",
+                        machine.name, machine.zone
+                    )
+                })?;
+            if machine.signal == Signal::Detection && detector.is_none() {
+                bail!("state {:?} needs a detector", machine.name);
+            }
+            watches.push(Watch {
+                machine: machine.clone(),
+                zone: zone.clone(),
+                debounce: Debounce::default(),
+                since: None,
+                raw: false,
+            });
+        }
+        Ok(Self {
+            config: config.clone(),
+            watches,
+            detector,
+            events,
+            started: Instant::now(),
+            frames: 0,
+            feet: Vec::new(),
+            gray: Mat::default(),
+            reference: None,
+            masks: Vec::new(),
+        })
+    }
+
+    fn masks(&mut self, size: Size) -> Result<()> {
+        if self
+            .masks
+            .first()
+            .is_some_and(|mask| mask.size().ok() == Some(size))
+        {
+            return Ok(());
+        }
+        self.masks.clear();
+        for watch in &self.watches {
+            let mut mask = Mat::new_size_with_default(size, CV_8U, Scalar::all(0.0))?;
+            let mut polygons = Vector::<Vector<Point>>::new();
+            polygons.push(watch.zone.pixels(size.width, size.height));
+            imgproc::fill_poly(
+                &mut mask,
+                &polygons,
+                Scalar::all(255.0),
+                LINE_8,
+                0,
+                Point::new(0, 0),
+            )?;
+            self.masks.push(mask);
+        }
+        // 画面大小变化后重新取参考画面.
+        self.reference = None;
+        Ok(())
+    }
+
+    // 区域中和参考画面相比变化的像素比例.
+    fn changed(&self, index: usize, threshold: f64) -> Result<f64> {
+        let Some(reference) = &self.reference else {
+            return Ok(0.0);
+        };
+        let mask = &self.masks[index];
+        let area = core::count_non_zero(mask)?;
+        if area == 0 {
+            return Ok(0.0);
+        }
+        let mut diff = Mat::default();
+        core::absdiff(&self.gray, reference, &mut diff)?;
+        let mut changed = Mat::default();
+        imgproc::threshold(
+            &diff,
+            &mut changed,
+            threshold,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+        let mut inside = Mat::default();
+        core::bitwise_and(&changed, mask, &mut inside, &core::no_array())?;
+        Ok(core::count_non_zero(&inside)? as f64 / area as f64)
+    }
+
+    fn notify(&self, watch: &Watch, previous: Option<&str>, seconds: Option<f64>) {
+        let state = if watch.debounce.state() == Some(true) {
+            &watch.machine.on
+        } else {
+            &watch.machine.off
+        };
+        self.events.log(
+            "state",
+            json!({
+                "machine": watch.machine.name,
+                "zone": watch.machine.zone,
+                "state": state,
+                "on": watch.debounce.state() == Some(true),
+                "previous": previous,
+                "seconds": seconds,
+            }),
+        );
+        if self.config.alert_urls.is_empty() || previous.is_none() {
+            return;
+        }
+        let body = json!({
+            "title": format!("{} {}", watch.machine.name, state),
+            "message": format!(
+                "{} is {} (was {} for {:.0} s)",
+                watch.machine.name,
+                state,
+                previous.unwrap_or_default(),
+                seconds.unwrap_or_default()
+            ),
+            "time": Local::now().to_rfc3339(),
+            "machine": watch.machine.name,
+            "state": state,
+        })
+        .to_string();
+        let urls = self.config.alert_urls.clone();
+        // 发送提醒不阻塞采集.
+        let _ = thread::Builder::new()
+            .name("state-alert".to_string())
+            .spawn(move || {
+                for url in urls {
+                    if let Err(err) = ureq::post(&url)
+                        .set("Content-Type", "application/json")
+                        .send_string(&body)
+                    {
+                        eprintln!("state alert to {}: {:?}", url, err);
+                    }
+                }
+            });
+    }
+}
+
+impl FrameProcessor for Occupancy {
+    fn name(&self) -> &str {
+        "occupancy"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        self.masks(frame.size()?)?;
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        let blurred = self.gray.try_clone()?;
+        imgproc::gaussian_blur(
+            &blurred,
+            &mut self.gray,
+            Size::new(5, 5),
+            0.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )?;
+        // 开始时的画面作为参考: 门关着, 工位空着.
+        if self.reference.is_none() {
+            self.reference = Some(self.gray.try_clone()?);
+        }
+        if let Some(detector) = &mut self.detector {
+            if self.frames % self.config.detect_every.max(1) as u64 == 0 {
+                self.feet = detector
+                    .detect(frame)?
+                    .iter()
+                    .map(|d| {
+                        let x = (d.rect.x + d.rect.width / 2) as f64 / cols as f64;
+                        let y = (d.rect.y + d.rect.height) as f64 / rows as f64;
+                        (x, y)
+                    })
+                    .collect();
+            }
+        }
+        self.frames += 1;
+
+        let now = self.started.elapsed().as_secs_f64();
+        for index in 0..self.watches.len() {
+            let machine = &self.watches[index].machine;
+            let raw = match machine.signal {
+                Signal::Change => self.changed(index, machine.threshold)? > machine.fraction,
+                Signal::Detection => {
+                    let zone = &self.watches[index].zone;
+                    self.feet.iter().any(|&(x, y)| zone.contains(x, y))
+                }
+            };
+            let watch = &mut self.watches[index];
+            watch.raw = raw;
+            let previous = watch.debounce.state();
+            let (on_after, off_after) = (watch.machine.on_after, watch.machine.off_after);
+            if watch
+                .debounce
+                .update(raw, now, on_after, off_after)
+                .is_none()
+            {
+                continue;
+            }
+            let time = Local::now();
+            let seconds = watch
+                .since
+                .map(|since| (time - since).num_milliseconds() as f64 / 1000.0);
+            watch.since = Some(time);
+            let previous = previous.map(|on| {
+                if on {
+                    watch.machine.on.clone()
+                } else {
+                    watch.machine.off.clone()
+                }
+            });
+            let watch = &self.watches[index];
+            self.notify(watch, previous.as_deref(), seconds);
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 区域轮廓: on 为橙色, off 为绿色; 原始信号和状态不同 (正在防抖) 时为细线.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        for watch in &self.watches {
+            if watch.zone.points.len() < 3 {
+                continue;
+            }
+            let on = watch.debounce.state() == Some(true);
+            let color = if on {
+                Scalar::new(0., 140., 255., 0.)
+            } else {
+                Scalar::new(0., 200., 0., 0.)
+            };
+            let thickness = if Some(watch.raw) == watch.debounce.state() {
+                3
+            } else {
+                1
+            };
+            let points = watch.zone.pixels(cols, rows);
+            let mut polygons = Vector::<Vector<Point>>::new();
+            polygons.push(points.clone());
+            imgproc::polylines(frame, &polygons, true, color, thickness, LINE_AA, 0)?;
+            let state = if on {
+                &watch.machine.on
+            } else {
+                &watch.machine.off
+            };
+            let origin = points.get(0)?;
+            imgproc::put_text(
+                frame,
+                &format!("{}: {}", watch.machine.name, state),
+                Point::new(origin.x + 4, origin.y + 20),
+                FONT_HERSHEY_SIMPLEX,
+                0.6,
+                color,
+                2,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeDelta, TimeZone};
+
+    use super::*;
+
+    fn transition(machine: &str, time: DateTime<Local>, on: bool) -> Transition {
+        Transition {
+            machine: machine.to_string(),
+            time,
+            on,
+            state: if on { "open" } else { "closed" }.to_string(),
+        }
+    }
+
+    fn machine(name: &str) -> Machine {
+        Machine {
+            name: name.to_string(),
+            zone: name.to_string(),
+            signal: Signal::Change,
+            on: "open".to_string(),
+            off: "closed".to_string(),
+            on_after: 1.0,
+            off_after: 3.0,
+            threshold: default_threshold(),
+            fraction: default_fraction(),
+        }
+    }
+
+    // 第一次直接采用原始信号, 之后新状态持续 on_after 或 off_after 秒才切换, 中途恢复时重新计时.
+    #[test]
+    fn debounce() {
+        let mut debounce = Debounce::default();
+        let steps = [
+            (false, 0.0, Some(false)),
+            (true, 0.5, None),
+            (false, 0.8, None),
+            (true, 1.0, None),
+            (true, 1.9, None),
+            (true, 2.0, Some(true)),
+            (false, 2.5, None),
+            (false, 5.4, None),
+            (false, 5.5, Some(false)),
+        ];
+        for (raw, now, expected) in steps {
+            assert_eq!(debounce.update(raw, now, 1.0, 3.0), expected, "{}s", now);
+        }
+        assert_eq!(debounce.state(), Some(false));
+    }
+
+    // 时间线开始之前打开的状态从 0 开始, 没有关闭的延续到结尾.
+    #[test]
+    fn on_spans() {
+        let changes = [(-10.0, true), (20.0, false), (50.0, true)];
+        assert_eq!(spans(&changes, 100.0), vec![(0.0, 0.2), (0.5, 1.0)]);
+        assert_eq!(spans(&[(30.0, false)], 100.0), vec![]);
+    }
+
+    // 时间线之前的切换只保留每个状态机的最后一次.
+    #[test]
+    fn prune_before_window() {
+        let now = Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let hours = |hours: i64| now - TimeDelta::minutes(hours * 60);
+        let mut transitions = vec![
+            transition("door", hours(3), false),
+            transition("desk", hours(2), true),
+            transition("door", hours(2), true),
+            transition("door", now - TimeDelta::minutes(10), false),
+        ];
+        prune(&mut transitions, hours(1));
+        let kept: Vec<(&str, bool)> = transitions
+            .iter()
+            .map(|transition| (transition.machine.as_str(), transition.on))
+            .collect();
+        assert_eq!(kept, vec![("desk", true), ("door", true), ("door", false)]);
+    }
+
+    // 每个状态机一行当前状态, 以及一小时内打开的时段.
+    #[test]
+    fn timeline_rows() {
+        let now = Local.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+        let transitions = [
+            transition("door", now - TimeDelta::minutes(45), true),
+            transition("door", now - TimeDelta::minutes(15), false),
+        ];
+        let machines = [machine("door"), machine("desk")];
+        let (rows, spans) = timeline(&transitions, &machines, now, 3600.0);
+        assert_eq!(rows, vec!["door: closed since 11:45:00", "desk: unknown"]);
+        assert_eq!(spans, vec![(0, 0.25, 0.75)]);
+    }
+
+    #[test]
+    fn transition_from_event() {
+        let event = Event {
+            time: Local::now(),
+            kind: "state".to_string(),
+            data: json!({ "machine": "door", "on": true, "state": "open" }),
+        };
+        let transition = Transition::from_event(&event).unwrap();
+        assert_eq!((transition.machine.as_str(), transition.on), ("door", true));
+        let motion = Event {
+            kind: "motion".to_string(),
+            ..event
+        };
+        assert!(Transition::from_event(&motion).is_none());
+    }
+}
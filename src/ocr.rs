@@ -0,0 +1,546 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    process::{Command, Stdio},
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use opencv::{
+    core::{self, Mat, Point, Rect, Scalar, Vector},
+    imgcodecs,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    events::EventStore,
+    pipeline::FrameProcessor,
+    threads::{self, ThreadConfig},
+};
+
+// 文字识别: 按间隔把画面交给 tesseract 程序识别, 画出每个词和它的置信度, 识别到的文字行记为 ocr 事件.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OcrConfig {
+    pub enabled: bool,
+    pub program: String,
+    // tesseract 语言包, 例如 ["eng", "deu", "chi_sim"], 需要已经安装.
+    pub languages: Vec<String>,
+    // 识别间隔 (秒), 识别在后台线程运行, 不会拖慢采集.
+    pub interval: f64,
+    // 先检测文字方向 (需要 osd 语言包), 旋转后再识别, 例如倒放或竖放的标签.
+    pub orientation: bool,
+    // 低于这个置信度 (0..100) 的文字行不记录事件, 词仍然画出.
+    pub min_confidence: f64,
+    // 同一行文字在这段时间 (秒) 内只记录一次, 连续识别时不会重复记录同一个标签.
+    pub dedupe: f64,
+    // 识别区域 [x, y, w, h], 取值为画面宽高的比例, 不设置时为整个画面.
+    pub region: Option<[f64; 4]>,
+    // 词框上显示识别出的文字, 否则只显示置信度.
+    pub show_text: bool,
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            program: "tesseract".to_string(),
+            languages: vec!["eng".to_string()],
+            interval: 1.0,
+            orientation: true,
+            min_confidence: 60.0,
+            dedupe: 60.0,
+            region: None,
+            show_text: true,
+        }
+    }
+}
+
+// 一个识别出的词, 坐标为原始画面中的像素.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Word {
+    pub rect: Rect,
+    pub confidence: f64,
+    pub text: String,
+    // 所在的文字行 (block, paragraph, line).
+    pub line: (u32, u32, u32),
+}
+
+// tesseract 的 tsv 输出中的词 (level 5), 坐标为识别图像中的像素.
+pub fn parse_tsv(tsv: &str) -> Vec<Word> {
+    tsv.lines()
+        .skip(1)
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.splitn(12, '\t').collect();
+            let [level, _page, block, paragraph, line, _word, left, top, width, height, confidence, text] =
+                fields[..]
+            else {
+                return None;
+            };
+            let confidence: f64 = confidence.parse().ok()?;
+            let text = text.trim();
+            if level != "5" || confidence < 0.0 || text.is_empty() {
+                return None;
+            }
+            Some(Word {
+                rect: Rect::new(
+                    left.parse().ok()?,
+                    top.parse().ok()?,
+                    width.parse().ok()?,
+                    height.parse().ok()?,
+                ),
+                confidence,
+                text: text.to_string(),
+                line: (block.parse().ok()?, paragraph.parse().ok()?, line.parse().ok()?),
+            })
+        })
+        .collect()
+}
+
+// 按行合并词, 返回每行的文字和平均置信度.
+pub fn lines(words: &[Word]) -> Vec<(String, f64)> {
+    let mut lines: Vec<((u32, u32, u32), Vec<&Word>)> = Vec::new();
+    for word in words {
+        match lines.iter_mut().find(|(line, _)| *line == word.line) {
+            Some((_, line)) => line.push(word),
+            None => lines.push((word.line, vec![word])),
+        }
+    }
+    lines
+        .into_iter()
+        .map(|(_, words)| {
+            let text = words
+                .iter()
+                .map(|word| word.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let confidence =
+                words.iter().map(|word| word.confidence).sum::<f64>() / words.len() as f64;
+            (text, confidence)
+        })
+        .collect()
+}
+
+// 连续识别时去掉重复的文字行, 大小写, 空白和标点不同的算同一行.
+pub struct Dedupe {
+    window: Duration,
+    seen: HashMap<String, Instant>,
+}
+
+impl Dedupe {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            seen: HashMap::new(),
+        }
+    }
+
+    // 这一行在 window 内没有出现过时返回 true. 一直出现的行每过 window 记录一次.
+    pub fn fresh(&mut self, text: &str, now: Instant) -> bool {
+        let key = normalize(text);
+        if key.is_empty() {
+            return false;
+        }
+        let window = self.window;
+        self.seen
+            .retain(|_, seen| now.saturating_duration_since(*seen) < window);
+        if self.seen.contains_key(&key) {
+            return false;
+        }
+        self.seen.insert(key, now);
+        true
+    }
+}
+
+pub fn normalize(text: &str) -> String {
+    text.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// tesseract 的 Rotate: 需要顺时针旋转的角度.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Rotate180,
+    Clockwise270,
+}
+
+impl Rotation {
+    fn from_degrees(degrees: i32) -> Self {
+        match degrees.rem_euclid(360) {
+            90 => Rotation::Clockwise90,
+            180 => Rotation::Rotate180,
+            270 => Rotation::Clockwise270,
+            _ => Rotation::None,
+        }
+    }
+
+    fn degrees(self) -> i32 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Rotate180 => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+
+    // 旋转后图像中的框对应到旋转前 (width x height) 的图像中.
+    pub fn unrotate(self, rect: Rect, width: i32, height: i32) -> Rect {
+        let (x0, y0, x1, y1) = (rect.x, rect.y, rect.x + rect.width, rect.y + rect.height);
+        let (a, b) = match self {
+            Rotation::None => return rect,
+            Rotation::Clockwise90 => ((y0, height - x1), (y1, height - x0)),
+            Rotation::Rotate180 => ((width - x1, height - y1), (width - x0, height - y0)),
+            Rotation::Clockwise270 => ((width - y1, x0), (width - y0, x1)),
+        };
+        Rect::new(a.0, a.1, b.0 - a.0, b.1 - a.1)
+    }
+}
+
+struct Job {
+    image: Mat,
+    // 识别区域在画面中的位置.
+    offset: Point,
+}
+
+pub struct Ocr {
+    jobs: SyncSender<Job>,
+    latest: Arc<Mutex<Vec<Word>>>,
+    interval: Duration,
+    last: Option<Instant>,
+    region: Option<[f64; 4]>,
+    show_text: bool,
+}
+
+impl Ocr {
+    pub fn new(config: &OcrConfig, events: EventStore, workers: &ThreadConfig) -> Result<Self> {
+        if config.languages.is_empty() {
+            bail!("set at least one language in [ocr] languages");
+        }
+        let installed = installed(&config.program)?;
+        let missing: Vec<&String> = config
+            .languages
+            .iter()
+            .filter(|language| !installed.contains(language))
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "tesseract language packs {:?} are not installed, available: {}",
+                missing,
+                installed.join(", ")
+            );
+        }
+        let mut orientation = config.orientation;
+        if orientation && !installed.iter().any(|language| language == "osd") {
+            eprintln!("ocr: the osd language pack is not installed, orientation is not corrected");
+            orientation = false;
+        }
+        let latest = Arc::new(Mutex::new(Vec::new()));
+        let results = latest.clone();
+        let recognizer = Recognizer {
+            program: config.program.clone(),
+            languages: config.languages.join("+"),
+            orientation,
+        };
+        let (min_confidence, languages) = (config.min_confidence, config.languages.clone());
+        let mut dedupe = Dedupe::new(Duration::from_secs_f64(config.dedupe.max(0.0)));
+        let jobs = threads::worker("ocr", 1, workers, move |job: Job| {
+            let (words, rotation) = recognizer.recognize(&job.image)?;
+            let now = Instant::now();
+            for (text, confidence) in lines(&words) {
+                if confidence >= min_confidence && dedupe.fresh(&text, now) {
+                    events.log(
+                        "ocr",
+                        json!({
+                            "text": text,
+                            "confidence": confidence,
+                            "rotation": rotation.degrees(),
+                            "languages": languages,
+                        }),
+                    );
+                }
+            }
+            *results.lock().unwrap() = words
+                .into_iter()
+                .map(|mut word| {
+                    word.rect.x += job.offset.x;
+                    word.rect.y += job.offset.y;
+                    word
+                })
+                .collect();
+            Ok(())
+        })?;
+        Ok(Self {
+            jobs,
+            latest,
+            interval: Duration::from_secs_f64(config.interval.max(0.1)),
+            last: None,
+            region: config.region,
+            show_text: config.show_text,
+        })
+    }
+
+    fn region(&self, frame: &Mat) -> Rect {
+        let (w, h) = (frame.cols(), frame.rows());
+        let Some([x, y, rw, rh]) = self.region else {
+            return Rect::new(0, 0, w, h);
+        };
+        let scale = |value: f64, length: i32| ((value.clamp(0.0, 1.0) * length as f64) as i32);
+        let rect = Rect::new(scale(x, w), scale(y, h), scale(rw, w), scale(rh, h));
+        let (x, y) = (rect.x.min(w - 1), rect.y.min(h - 1));
+        Rect::new(
+            x,
+            y,
+            rect.width.min(w - x).max(1),
+            rect.height.min(h - y).max(1),
+        )
+    }
+}
+
+// 已安装的语言包.
+fn installed(program: &str) -> Result<Vec<String>> {
+    let output = Command::new(program)
+        .arg("--list-langs")
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("run {}", program))?;
+    // 旧版本把列表输出到 stderr.
+    let text = String::from_utf8_lossy(&output.stdout).to_string()
+        + &String::from_utf8_lossy(&output.stderr);
+    Ok(text
+        .lines()
+        .skip_while(|line| !line.starts_with("List of available languages"))
+        .skip(1)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+struct Recognizer {
+    program: String,
+    languages: String,
+    orientation: bool,
+}
+
+impl Recognizer {
+    // 识别出的词 (识别区域中的坐标) 和检测到的方向.
+    fn recognize(&self, image: &Mat) -> Result<(Vec<Word>, Rotation)> {
+        let rotation = if self.orientation {
+            self.orientation(image).unwrap_or_else(|err| {
+                eprintln!("ocr orientation: {:?}", err);
+                Rotation::None
+            })
+        } else {
+            Rotation::None
+        };
+        let mut rotated = Mat::default();
+        let input = match rotation {
+            Rotation::None => image,
+            Rotation::Clockwise90 => {
+                core::rotate(image, &mut rotated, core::ROTATE_90_CLOCKWISE)?;
+                &rotated
+            }
+            Rotation::Rotate180 => {
+                core::rotate(image, &mut rotated, core::ROTATE_180)?;
+                &rotated
+            }
+            Rotation::Clockwise270 => {
+                core::rotate(image, &mut rotated, core::ROTATE_90_COUNTERCLOCKWISE)?;
+                &rotated
+            }
+        };
+        let tsv = self.run(input, &["-l", &self.languages, "tsv"])?;
+        let (width, height) = (image.cols(), image.rows());
+        let words = parse_tsv(&tsv)
+            .into_iter()
+            .map(|mut word| {
+                word.rect = rotation.unrotate(word.rect, width, height);
+                word
+            })
+            .collect();
+        Ok((words, rotation))
+    }
+
+    fn orientation(&self, image: &Mat) -> Result<Rotation> {
+        let osd = self.run(image, &["--psm", "0", "-l", "osd"])?;
+        let degrees = osd
+            .lines()
+            .find_map(|line| line.strip_prefix("Rotate:"))
+            .and_then(|value| value.trim().parse().ok())
+            .context("no orientation in tesseract output")?;
+        Ok(Rotation::from_degrees(degrees))
+    }
+
+    // 图像以 PNG 通过 stdin 传给 tesseract, 结果从 stdout 读取.
+    fn run(&self, image: &Mat, args: &[&str]) -> Result<String> {
+        let mut png = Vector::<u8>::new();
+        imgcodecs::imencode(".png", image, &mut png, &Vector::new())?;
+        let mut child = Command::new(&self.program)
+            .args(["stdin", "stdout"])
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("run {}", self.program))?;
+        child
+            .stdin
+            .take()
+            .context("tesseract stdin")?
+            .write_all(png.as_slice())?;
+        let output = child.wait_with_output()?;
+        if !output.status.success() {
+            bail!(
+                "{}: {}",
+                self.program,
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+impl FrameProcessor for Ocr {
+    fn name(&self) -> &str {
+        "ocr"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let due = self
+            .last
+            .map_or(true, |last| last.elapsed() >= self.interval);
+        if due {
+            let region = self.region(frame);
+            let job = Job {
+                image: Mat::roi(frame, region)?.try_clone()?,
+                offset: region.tl(),
+            };
+            match self.jobs.try_send(job) {
+                Ok(()) => self.last = Some(Instant::now()),
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => bail!("ocr thread exited"),
+            }
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 词框的颜色按置信度从红色 (0) 渐变到绿色 (100).
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        if self.region.is_some() {
+            let region = self.region(frame);
+            imgproc::rectangle(
+                frame,
+                region,
+                Scalar::new(255., 200., 0., 0.),
+                1,
+                LINE_AA,
+                0,
+            )?;
+        }
+        for word in self.latest.lock().unwrap().iter() {
+            let confidence = word.confidence.clamp(0.0, 100.0) / 100.0;
+            let color = Scalar::new(0., 255. * confidence, 255. * (1.0 - confidence), 0.);
+            imgproc::rectangle(frame, word.rect, color, 2, LINE_AA, 0)?;
+            let label = if self.show_text {
+                format!("{} {:.0}", word.text, word.confidence)
+            } else {
+                format!("{:.0}", word.confidence)
+            };
+            imgproc::put_text(
+                frame,
+                &label,
+                Point::new(word.rect.x, word.rect.y - 4),
+                FONT_HERSHEY_SIMPLEX,
+                0.45,
+                color,
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.latest.lock().unwrap().len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSV: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+4\t1\t1\t1\t1\t0\t10\t20\t200\t30\t-1\t
+5\t1\t1\t1\t1\t1\t10\t20\t80\t30\t91.5\tGate
+5\t1\t1\t1\t1\t2\t100\t20\t40\t30\t88.5\t4
+5\t1\t1\t1\t2\t1\t10\t60\t90\t30\t-1\t
+5\t1\t1\t1\t2\t2\t10\t60\t90\t30\t75\tOPEN";
+
+    // 只取有置信度的词, 同一行的词合并, 置信度取平均.
+    #[test]
+    fn words_and_lines() {
+        let words = parse_tsv(TSV);
+        assert_eq!(words.len(), 3);
+        assert_eq!(
+            words[0],
+            Word {
+                rect: Rect::new(10, 20, 80, 30),
+                confidence: 91.5,
+                text: "Gate".to_string(),
+                line: (1, 1, 1),
+            }
+        );
+        assert_eq!(
+            lines(&words),
+            vec![("Gate 4".to_string(), 90.0), ("OPEN".to_string(), 75.0)]
+        );
+    }
+
+    // 大小写, 空白和标点不同的算同一行, window 之后再记录一次.
+    #[test]
+    fn dedupe() {
+        let start = Instant::now();
+        let mut dedupe = Dedupe::new(Duration::from_secs(10));
+        assert!(dedupe.fresh("Gate 4 OPEN", start));
+        assert!(!dedupe.fresh("gate 4, open.", start + Duration::from_secs(5)));
+        assert!(dedupe.fresh("Gate 5 OPEN", start + Duration::from_secs(5)));
+        assert!(dedupe.fresh("GATE 4 OPEN", start + Duration::from_secs(10)));
+        assert!(!dedupe.fresh("...", start));
+    }
+
+    // 旋转后图像中的框对应回 100x50 的原图.
+    #[test]
+    fn unrotate() {
+        let rect = Rect::new(10, 20, 5, 30);
+        assert_eq!(Rotation::None.unrotate(rect, 100, 50), rect);
+        assert_eq!(
+            Rotation::Clockwise90.unrotate(rect, 100, 50),
+            Rect::new(20, 35, 30, 5)
+        );
+        assert_eq!(
+            Rotation::Rotate180.unrotate(Rect::new(0, 0, 10, 5), 100, 50),
+            Rect::new(90, 45, 10, 5)
+        );
+        assert_eq!(
+            Rotation::Clockwise270.unrotate(rect, 100, 50),
+            Rect::new(50, 10, 30, 5)
+        );
+        assert_eq!(Rotation::from_degrees(-90), Rotation::Clockwise270);
+        assert_eq!(Rotation::from_degrees(450).degrees(), 90);
+    }
+}
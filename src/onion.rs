@@ -0,0 +1,127 @@
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc::{self, INTER_AREA},
+    prelude::*,
+};
+
+// 在预览画面上半透明叠加参考图像或之前的一帧, 用于定格动画和重复拍摄时对齐.
+pub struct OnionSkin {
+    image: Option<Mat>,
+    // 叠加图像的不透明度 0..1.
+    pub opacity: f64,
+    blended: Mat,
+}
+
+impl Default for OnionSkin {
+    fn default() -> Self {
+        Self {
+            image: None,
+            opacity: 0.4,
+            blended: Mat::default(),
+        }
+    }
+}
+
+impl OnionSkin {
+    pub fn set(&mut self, frame: &Mat) -> Result<()> {
+        self.image = Some(frame.try_clone()?);
+        Ok(())
+    }
+
+    // 使用已经读取的图像, 例如删除定格动画最后一帧后退回到上一帧.
+    pub fn image_from(&mut self, image: Mat) {
+        self.image = Some(image);
+    }
+
+    pub fn load(&mut self, path: &str) -> Result<()> {
+        let image = imgcodecs::imread(path, IMREAD_COLOR)?;
+        if image.empty() {
+            bail!("read {}", path);
+        }
+        self.image = Some(image);
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.image = None;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.image.is_some() && self.opacity > 0.0
+    }
+
+    pub fn draw(&mut self, frame: &mut Mat) -> Result<()> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let Some(image) = &mut self.image else {
+            return Ok(());
+        };
+        // 尺寸不同 (载入的图像或切换了来源) 时缩放一次并保留结果.
+        if image.size()? != frame.size()? {
+            let mut scaled = Mat::default();
+            imgproc::resize(image, &mut scaled, frame.size()?, 0.0, 0.0, INTER_AREA)?;
+            *image = scaled;
+        }
+        let opacity = self.opacity.clamp(0.0, 1.0);
+        core::add_weighted(
+            &*frame,
+            1.0 - opacity,
+            image,
+            opacity,
+            0.0,
+            &mut self.blended,
+            -1,
+        )?;
+        self.blended.copy_to(frame)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    fn solid(rows: i32, cols: i32, level: f64) -> Mat {
+        Mat::new_rows_cols_with_default(rows, cols, CV_8UC3, Scalar::all(level)).unwrap()
+    }
+
+    #[test]
+    fn blends_reference() {
+        let mut onion = OnionSkin::default();
+        let mut frame = solid(48, 64, 100.);
+        onion.draw(&mut frame).unwrap();
+        assert_eq!(*frame.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::all(100));
+
+        onion.opacity = 0.5;
+        onion.set(&solid(48, 64, 200.)).unwrap();
+        assert!(onion.is_active());
+        onion.draw(&mut frame).unwrap();
+        assert_eq!(*frame.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::all(150));
+
+        onion.opacity = 0.0;
+        assert!(!onion.is_active());
+        onion.clear();
+        onion.opacity = 0.5;
+        assert!(!onion.is_active());
+    }
+
+    // 尺寸不同的参考图像缩放到帧的尺寸.
+    #[test]
+    fn scales_reference() {
+        let mut onion = OnionSkin {
+            opacity: 1.0,
+            ..Default::default()
+        };
+        onion.image_from(solid(96, 128, 40.));
+        let mut frame = solid(48, 64, 200.);
+        onion.draw(&mut frame).unwrap();
+        assert_eq!((frame.cols(), frame.rows()), (64, 48));
+        assert_eq!(*frame.at_2d::<Vec3b>(20, 30).unwrap(), Vec3b::all(40));
+        assert!(onion.load("missing-onion-skin.png").is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::Path;
+
+use anyhow::Result;
+use opencv::{core::Mat, prelude::*};
+use ort::{
+    CPUExecutionProvider, CUDAExecutionProvider, DirectMLExecutionProvider,
+    ExecutionProviderDispatch, Session, Tensor,
+};
+
+use crate::{
+    classify::{self, Classifier},
+    detect::Detector,
+    dnn,
+    models::{ModelConfig, Provider},
+    pose::{self, Keypoint, PoseEstimator},
+    segment::{self, Segmenter},
+    tracking::Detection,
+};
+
+// ONNX Runtime 推理的 SSD 类检测模型, 输入输出格式与 DnnDetector 相同.
+pub struct OnnxDetector {
+    session: Session,
+    config: ModelConfig,
+    class: Option<usize>,
+}
+
+impl OnnxDetector {
+    pub fn new(path: &Path, config: &ModelConfig, class: Option<usize>) -> Result<Self> {
+        Ok(Self {
+            session: session(path, config)?,
+            config: config.clone(),
+            class,
+        })
+    }
+}
+
+fn session(path: &Path, config: &ModelConfig) -> Result<Session> {
+    let providers: Vec<ExecutionProviderDispatch> = config
+        .providers
+        .iter()
+        .map(|provider| match provider {
+            Provider::Cuda => CUDAExecutionProvider::default().build(),
+            Provider::Directml => DirectMLExecutionProvider::default().build(),
+            Provider::Cpu => CPUExecutionProvider::default().build(),
+        })
+        .collect();
+    // 不可用的执行设备会被跳过.
+    Ok(Session::builder()?
+        .with_execution_providers(providers)?
+        .commit_from_file(path)?)
+}
+
+// 按模型配置预处理后推理, 返回第一个输出.
+fn run(session: &mut Session, frame: &Mat, config: &ModelConfig) -> Result<Vec<f32>> {
+    Ok(run_shaped(session, frame, config)?.1)
+}
+
+// 同 run, 另外返回输出的形状.
+fn run_shaped(
+    session: &mut Session,
+    frame: &Mat,
+    config: &ModelConfig,
+) -> Result<(Vec<usize>, Vec<f32>)> {
+    let blob = dnn::blob(frame, config)?;
+    let [width, height] = config.input_size;
+    let input = Tensor::from_array((
+        [1, 3, height as usize, width as usize],
+        blob.data_typed::<f32>()?.to_vec(),
+    ))?;
+    let outputs = session.run(ort::inputs![input]?)?;
+    let (shape, data) = outputs[0].try_extract_raw_tensor::<f32>()?;
+    Ok((shape.iter().map(|&d| d as usize).collect(), data.to_vec()))
+}
+
+impl Detector for OnnxDetector {
+    fn detect(&mut self, frame: &Mat) -> Result<Vec<Detection>> {
+        let data = run(&mut self.session, frame, &self.config)?;
+        Ok(dnn::parse_ssd(&data, frame, self.class))
+    }
+
+    fn label(&self, class: usize) -> String {
+        self.config.label(class)
+    }
+
+    // onnxruntime 不报告最终选中的执行设备, 这里显示配置的候选列表.
+    fn device(&self) -> String {
+        format!("onnxruntime {:?}", self.config.providers).to_lowercase()
+    }
+}
+
+// ONNX Runtime 推理的分类模型, 输入输出格式与 DnnClassifier 相同.
+pub struct OnnxClassifier {
+    session: Session,
+    config: ModelConfig,
+}
+
+impl OnnxClassifier {
+    pub fn new(path: &Path, config: &ModelConfig) -> Result<Self> {
+        Ok(Self {
+            session: session(path, config)?,
+            config: config.clone(),
+        })
+    }
+}
+
+impl Classifier for OnnxClassifier {
+    fn classify(&mut self, image: &Mat) -> Result<(usize, f64)> {
+        let data = run(&mut self.session, image, &self.config)?;
+        Ok(classify::best(&data))
+    }
+
+    fn label(&self, class: usize) -> String {
+        self.config.label(class)
+    }
+
+    fn device(&self) -> String {
+        format!("onnxruntime {:?}", self.config.providers).to_lowercase()
+    }
+}
+
+// ONNX Runtime 推理的分割模型, 输入输出格式与 DnnSegmenter 相同.
+pub struct OnnxSegmenter {
+    session: Session,
+    config: ModelConfig,
+    class: usize,
+}
+
+impl OnnxSegmenter {
+    pub fn new(path: &Path, config: &ModelConfig, class: usize) -> Result<Self> {
+        Ok(Self {
+            session: session(path, config)?,
+            config: config.clone(),
+            class,
+        })
+    }
+}
+
+impl Segmenter for OnnxSegmenter {
+    fn segment(&mut self, frame: &Mat) -> Result<Mat> {
+        let data = run(&mut self.session, frame, &self.config)?;
+        segment::foreground(&data, self.config.input_size, self.class)
+    }
+
+    fn device(&self) -> String {
+        format!("onnxruntime {:?}", self.config.providers).to_lowercase()
+    }
+}
+
+// ONNX Runtime 推理的姿态估计模型, 输入输出格式与 DnnPose 相同.
+pub struct OnnxPose {
+    session: Session,
+    config: ModelConfig,
+}
+
+impl OnnxPose {
+    pub fn new(path: &Path, config: &ModelConfig) -> Result<Self> {
+        Ok(Self {
+            session: session(path, config)?,
+            config: config.clone(),
+        })
+    }
+}
+
+impl PoseEstimator for OnnxPose {
+    fn estimate(&mut self, frame: &Mat) -> Result<Vec<Keypoint>> {
+        let (shape, data) = run_shaped(&mut self.session, frame, &self.config)?;
+        let count = (!self.config.labels.is_empty()).then_some(self.config.labels.len());
+        pose::keypoints(&data, &shape, count, frame.cols(), frame.rows())
+    }
+
+    fn device(&self) -> String {
+        format!("onnxruntime {:?}", self.config.providers).to_lowercase()
+    }
+}
@@ -0,0 +1,411 @@
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{Mat, Point, Rect, Scalar},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_8},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::clock::ClockState;
+
+const FONT_SCALE: f64 = 0.6;
+const THICKNESS: i32 = 1;
+const MARGIN: i32 = 8;
+const LINE_GAP: i32 = 6;
+const WHITE: [u8; 3] = [255, 255, 255];
+const WARNING: [u8; 3] = [255, 80, 80];
+
+// 叠加内容在画面中的位置.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    TopCenter,
+    TopRight,
+    BottomLeft,
+    BottomCenter,
+    BottomRight,
+}
+
+const ANCHORS: usize = 6;
+
+impl Anchor {
+    fn is_top(self) -> bool {
+        matches!(self, Anchor::TopLeft | Anchor::TopCenter | Anchor::TopRight)
+    }
+}
+
+// 字幕样式, color 为 RGB.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CaptionStyle {
+    pub font_scale: f64,
+    pub thickness: i32,
+    pub color: [u8; 3],
+    // 字幕最大宽度占画面宽度的比例, 超过后自动换行.
+    pub max_width: f64,
+}
+
+impl Default for CaptionStyle {
+    fn default() -> Self {
+        Self {
+            font_scale: 0.9,
+            thickness: 2,
+            color: [255, 255, 0],
+            max_width: 0.8,
+        }
+    }
+}
+
+// 一个 OSD 布局: 每种叠加内容显示在哪里, None 表示不显示.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OsdProfile {
+    pub timestamp: Option<Anchor>,
+    // 采集的帧序号, 画面上的序号不连续说明录像被剪辑过.
+    pub frame: Option<Anchor>,
+    pub fps: Option<Anchor>,
+    pub detections: Option<Anchor>,
+    pub captions: Option<Anchor>,
+    pub caption_style: CaptionStyle,
+    // 与帧时间对应的外部数据, 见 [telemetry].
+    pub telemetry: Option<Anchor>,
+    // GPS 坐标和速度, 见 [gps].
+    pub gps: Option<Anchor>,
+}
+
+// 绘制 OSD 时需要的每帧信息.
+pub struct OsdInfo {
+    pub time: DateTime<Local>,
+    pub frame: u64,
+    pub fps: f64,
+    pub detections: usize,
+    pub caption: Option<String>,
+    pub telemetry: Option<String>,
+    pub gps: Option<String>,
+    // 时钟检查的结果, 标注在时间戳旁.
+    pub clock: ClockState,
+}
+
+// 一行待绘制的文字.
+struct Line {
+    anchor: Anchor,
+    text: String,
+    font_scale: f64,
+    thickness: i32,
+    color: [u8; 3],
+}
+
+impl Line {
+    fn plain(anchor: Anchor, text: String) -> Self {
+        Self {
+            anchor,
+            text,
+            font_scale: FONT_SCALE,
+            thickness: THICKNESS,
+            color: WHITE,
+        }
+    }
+}
+
+impl OsdProfile {
+    pub fn full() -> Self {
+        Self {
+            timestamp: Some(Anchor::TopLeft),
+            fps: Some(Anchor::TopRight),
+            captions: Some(Anchor::BottomCenter),
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.timestamp.is_none()
+            && self.frame.is_none()
+            && self.fps.is_none()
+            && self.detections.is_none()
+            && self.captions.is_none()
+            && self.telemetry.is_none()
+            && self.gps.is_none()
+    }
+
+    fn lines(&self, info: &OsdInfo, frame_width: i32) -> Result<Vec<Line>> {
+        let mut lines = Vec::new();
+        if let Some(anchor) = self.timestamp {
+            let mut line = Line::plain(anchor, info.time.format("%Y-%m-%d %H:%M:%S").to_string());
+            // 录像中的时间戳可能作为证据, 不能确认准确时要在画面上标出.
+            match info.clock {
+                ClockState::Off | ClockState::Verified(_) => {}
+                ClockState::Unverified => line.text.push_str(" (unverified)"),
+                ClockState::Drift(offset) => {
+                    line.text.push_str(&format!(" (clock off {:+.1}s)", offset));
+                    line.color = WARNING;
+                }
+            }
+            lines.push(line);
+        }
+        if let Some(anchor) = self.frame {
+            lines.push(Line::plain(anchor, format!("#{}", info.frame)));
+        }
+        if let Some(anchor) = self.fps {
+            lines.push(Line::plain(anchor, format!("FPS {:.1}", info.fps)));
+        }
+        if let Some(anchor) = self.detections {
+            lines.push(Line::plain(anchor, format!("objects {}", info.detections)));
+        }
+        if let (Some(anchor), Some(gps)) = (self.gps, &info.gps) {
+            lines.push(Line::plain(anchor, gps.clone()));
+        }
+        if let (Some(anchor), Some(telemetry)) = (self.telemetry, &info.telemetry) {
+            lines.push(Line::plain(anchor, telemetry.clone()));
+        }
+        if let (Some(anchor), Some(caption)) = (self.captions, &info.caption) {
+            let style = &self.caption_style;
+            let max_width = (frame_width as f64 * style.max_width) as i32;
+            let mut wrapped = wrap(caption, style.font_scale, style.thickness, max_width)?;
+            // 底部位置的内容是自下而上堆叠的, 需要倒序才能保持阅读顺序.
+            if !anchor.is_top() {
+                wrapped.reverse();
+            }
+            lines.extend(wrapped.into_iter().map(|text| Line {
+                anchor,
+                text,
+                font_scale: style.font_scale,
+                thickness: style.thickness,
+                color: style.color,
+            }));
+        }
+        Ok(lines)
+    }
+
+    pub fn draw(&self, frame: &mut Mat, info: &OsdInfo) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        // 同一位置的多条内容依次堆叠, 记录每个位置已占用的高度.
+        let mut used = [0; ANCHORS];
+        for line in self.lines(info, cols)? {
+            let mut baseline = 0;
+            let size = imgproc::get_text_size(
+                &line.text,
+                FONT_HERSHEY_SIMPLEX,
+                line.font_scale,
+                line.thickness,
+                &mut baseline,
+            )?;
+            let x = match line.anchor {
+                Anchor::TopLeft | Anchor::BottomLeft => MARGIN,
+                Anchor::TopCenter | Anchor::BottomCenter => (cols - size.width) / 2,
+                Anchor::TopRight | Anchor::BottomRight => cols - size.width - MARGIN,
+            };
+            let offset = &mut used[line.anchor as usize];
+            let y = if line.anchor.is_top() {
+                MARGIN + *offset + size.height
+            } else {
+                rows - MARGIN - *offset - baseline
+            };
+            *offset += size.height + baseline + LINE_GAP;
+
+            // 半透明效果代价较高, 这里用实心黑底保证文字可读.
+            imgproc::rectangle(
+                frame,
+                Rect::new(
+                    x - 2,
+                    y - size.height - 2,
+                    size.width + 4,
+                    size.height + baseline + 4,
+                ),
+                Scalar::new(0., 0., 0., 0.),
+                imgproc::FILLED,
+                LINE_8,
+                0,
+            )?;
+            let [r, g, b] = line.color;
+            imgproc::put_text(
+                frame,
+                &line.text,
+                Point::new(x, y),
+                FONT_HERSHEY_SIMPLEX,
+                line.font_scale,
+                Scalar::new(b as f64, g as f64, r as f64, 0.),
+                line.thickness,
+                LINE_8,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// 按单词换行, 单个超长单词独占一行.
+fn wrap(text: &str, font_scale: f64, thickness: i32, max_width: i32) -> Result<Vec<String>> {
+    let mut lines = Vec::new();
+    for paragraph in text.lines() {
+        let mut current = String::new();
+        for word in paragraph.split_whitespace() {
+            let candidate = if current.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", current, word)
+            };
+            let mut baseline = 0;
+            let size = imgproc::get_text_size(
+                &candidate,
+                FONT_HERSHEY_SIMPLEX,
+                font_scale,
+                thickness,
+                &mut baseline,
+            )?;
+            if size.width > max_width && !current.is_empty() {
+                lines.push(std::mem::replace(&mut current, word.to_string()));
+            } else {
+                current = candidate;
+            }
+        }
+        if !current.is_empty() {
+            lines.push(current);
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use opencv::core::{self, CV_8UC3};
+
+    use super::*;
+    use crate::config::OsdConfig;
+
+    fn info() -> OsdInfo {
+        OsdInfo {
+            time: Local.with_ymd_and_hms(2024, 5, 1, 9, 30, 0).unwrap(),
+            frame: 1234,
+            fps: 29.97,
+            detections: 2,
+            caption: None,
+            telemetry: None,
+            gps: None,
+            clock: ClockState::Off,
+        }
+    }
+
+    fn texts(profile: &OsdProfile, info: &OsdInfo) -> Vec<(Anchor, String)> {
+        profile
+            .lines(info, 640)
+            .unwrap()
+            .into_iter()
+            .map(|line| (line.anchor, line.text))
+            .collect()
+    }
+
+    // 预览和录制按名称选择 profile, 没有设置的内容不显示.
+    #[test]
+    fn named_profiles() {
+        let config: OsdConfig = toml::from_str(
+            r#"
+            preview = "full"
+            recording = "evidence"
+
+            [profiles.full]
+            timestamp = "top-left"
+            fps = "top-right"
+
+            [profiles.evidence]
+            timestamp = "bottom-right"
+            frame = "bottom-right"
+            "#,
+        )
+        .unwrap();
+        let recording = config.profile(&config.recording).unwrap();
+        assert_eq!(
+            texts(&recording, &info()),
+            [
+                (Anchor::BottomRight, "2024-05-01 09:30:00".to_string()),
+                (Anchor::BottomRight, "#1234".to_string()),
+            ]
+        );
+        assert_eq!(
+            texts(&config.profile("full").unwrap(), &info()),
+            [
+                (Anchor::TopLeft, "2024-05-01 09:30:00".to_string()),
+                (Anchor::TopRight, "FPS 30.0".to_string()),
+            ]
+        );
+        assert!(config.profile("minimal").is_err());
+        let default = OsdConfig::default();
+        assert!(default.profile(&default.recording).unwrap().is_empty());
+        assert!(!default.profile(&default.preview).unwrap().is_empty());
+    }
+
+    fn black() -> Mat {
+        Mat::zeros(240, 320, CV_8UC3).unwrap().to_mat().unwrap()
+    }
+
+    fn drawn(frame: &Mat, rect: Rect) -> bool {
+        core::sum_elems(&Mat::roi(frame, rect).unwrap()).unwrap()[0] > 0.0
+    }
+
+    // 文字画在对应的角落, 空的 profile 不改动画面.
+    #[test]
+    fn draw_in_corners() {
+        let mut frame = black();
+        OsdProfile::default().draw(&mut frame, &info()).unwrap();
+        assert!(!drawn(&frame, Rect::new(0, 0, 320, 240)));
+
+        let profile = OsdProfile {
+            timestamp: Some(Anchor::TopLeft),
+            detections: Some(Anchor::BottomRight),
+            ..OsdProfile::default()
+        };
+        let mut frame = black();
+        profile.draw(&mut frame, &info()).unwrap();
+        assert!(drawn(&frame, Rect::new(0, 0, 160, 40)));
+        assert!(drawn(&frame, Rect::new(160, 200, 160, 40)));
+        assert!(!drawn(&frame, Rect::new(0, 200, 100, 40)));
+        assert!(!drawn(&frame, Rect::new(0, 60, 320, 120)));
+    }
+
+    // 长字幕按单词换行; 底部的字幕自下而上堆叠, 所以按倒序排列.
+    #[test]
+    fn caption_wrapping() {
+        let caption = "The quick brown fox jumps over the lazy dog while the camera keeps rolling\nsecond paragraph";
+        let info = OsdInfo {
+            caption: Some(caption.to_string()),
+            ..info()
+        };
+        let profile = OsdProfile {
+            captions: Some(Anchor::TopCenter),
+            ..OsdProfile::default()
+        };
+        let lines = profile.lines(&info, 640).unwrap();
+        assert!(lines.len() >= 3, "{}", lines.len());
+        assert_eq!(lines.last().unwrap().text, "second paragraph");
+        let words: Vec<&str> = lines.iter().flat_map(|line| line.text.split(' ')).collect();
+        assert_eq!(words, caption.split_whitespace().collect::<Vec<_>>());
+        for line in &lines {
+            assert_eq!(line.color, [255, 255, 0]);
+            let mut baseline = 0;
+            let size =
+                imgproc::get_text_size(&line.text, FONT_HERSHEY_SIMPLEX, 0.9, 2, &mut baseline)
+                    .unwrap();
+            assert!(size.width <= 512, "{:?}", line.text);
+        }
+
+        let bottom = OsdProfile {
+            captions: Some(Anchor::BottomCenter),
+            ..OsdProfile::default()
+        };
+        let reversed: Vec<String> = bottom
+            .lines(&info, 640)
+            .unwrap()
+            .into_iter()
+            .rev()
+            .map(|line| line.text)
+            .collect();
+        let top: Vec<String> = lines.into_iter().map(|line| line.text).collect();
+        assert_eq!(reversed, top);
+        assert_eq!(
+            wrap("supercalifragilistic", 0.9, 2, 10).unwrap(),
+            ["supercalifragilistic"]
+        );
+    }
+}
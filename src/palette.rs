@@ -0,0 +1,79 @@
+// 命令面板 (Ctrl+K) 的模糊搜索: 输入的字符按顺序出现在名称中即匹配,
+// 连续的字符和单词开头的字符得分更高, 结果按得分排列.
+
+// 不区分大小写. 不匹配时返回 None, 空查询得 0 分.
+pub fn score(query: &str, title: &str) -> Option<i32> {
+    let title: Vec<char> = title.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter(|c| !c.is_whitespace())
+    {
+        let found = position + title[position..].iter().position(|&c| c == wanted)?;
+        let word_start = found == 0 || !title[found - 1].is_alphanumeric();
+        score += match previous {
+            Some(previous) if found == previous + 1 => 8,
+            _ if word_start => 6,
+            // 跳过的字符越多得分越低.
+            Some(previous) => 1 - (found - previous - 1).min(5) as i32,
+            None => 1 - found.min(5) as i32,
+        };
+        previous = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+// 匹配的名称在 titles 中的位置, 得分高的在前, 同分时保持原来的顺序.
+pub fn search(query: &str, titles: &[String]) -> Vec<usize> {
+    let mut matches: Vec<(usize, i32)> = titles
+        .iter()
+        .enumerate()
+        .filter_map(|(index, title)| Some((index, score(query, title)?)))
+        .collect();
+    matches.sort_by_key(|(_, score)| -score);
+    matches.into_iter().map(|(index, _)| index).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn titles() -> Vec<String> {
+        [
+            "Toggle recording",
+            "Start stream",
+            "Snapshot",
+            "Restore layout",
+            "Record settings",
+        ]
+        .map(String::from)
+        .to_vec()
+    }
+
+    // 单词开头 6 分, 连续的字符 8 分, 跳过的字符扣分.
+    #[test]
+    fn scores() {
+        assert_eq!(score("ab", "ab"), Some(14));
+        assert_eq!(score("ST", "Start"), Some(14));
+        assert_eq!(score("tr", "Toggle recording"), Some(12));
+        assert_eq!(score("tr", "Restore layout"), Some(-2));
+        assert_eq!(score("", "Snapshot"), Some(0));
+        assert_eq!(score("rec", "Start stream"), None);
+    }
+
+    // 得分高的在前, 同分时保持原来的顺序, 空查询返回全部.
+    #[test]
+    fn ranking() {
+        let titles = titles();
+        assert_eq!(search("st", &titles), vec![1, 3, 4, 2]);
+        assert_eq!(search("rec", &titles), vec![0, 4]);
+        assert_eq!(search("s s", &titles), vec![1, 2, 4]);
+        assert_eq!(search("snap", &titles), vec![2]);
+        assert_eq!(search("", &titles), vec![0, 1, 2, 3, 4]);
+        assert!(search("xyz", &titles).is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Point, Point2d, Rect, Scalar, Size, Vector, CV_32F},
+    imgcodecs,
+    imgproc::{self, COLOR_BGR2GRAY, INTER_AREA, LINE_8},
+    prelude::*,
+    stitching::{Stitcher, Stitcher_Mode, Stitcher_Status},
+};
+
+// 估计位移时使用的缩略图宽度.
+const SMALL_WIDTH: i32 = 320;
+// 相对上一张保留帧移动超过画面的这个比例时保留新的一帧.
+const STEP: f64 = 0.3;
+// 相位相关的置信度低于该值视为跟踪丢失.
+const MIN_RESPONSE: f64 = 0.05;
+// 覆盖范围小地图的缩放比例.
+const MAP_SCALE: f64 = 0.08;
+
+// 平移摄像头时按位移间隔收集帧, 结束后用 OpenCV Stitcher 拼接.
+pub struct Panorama {
+    frames: Vector<Mat>,
+    // 每张保留帧相对第一帧的位移 (缩略图坐标).
+    offsets: Vec<Point2d>,
+    position: Point2d,
+    kept: Mat,
+    small: Mat,
+    gray: Mat,
+    lost: bool,
+}
+
+impl Panorama {
+    pub fn new() -> Self {
+        Self {
+            frames: Vector::new(),
+            offsets: Vec::new(),
+            position: Point2d::new(0., 0.),
+            kept: Mat::default(),
+            small: Mat::default(),
+            gray: Mat::default(),
+            lost: false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn add(&mut self, frame: &Mat) -> Result<()> {
+        let scale = SMALL_WIDTH as f64 / frame.cols() as f64;
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        imgproc::cvt_color(&self.small, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        let mut current = Mat::default();
+        self.gray.convert_to(&mut current, CV_32F, 1.0, 0.0)?;
+
+        if self.kept.empty() {
+            self.keep(frame, current, Point2d::new(0., 0.))?;
+            return Ok(());
+        }
+        let mut response = 0.0;
+        let shift =
+            imgproc::phase_correlate(&self.kept, &current, &core::no_array(), &mut response)?;
+        self.lost = response < MIN_RESPONSE;
+        if self.lost {
+            return Ok(());
+        }
+        let last = *self.offsets.last().unwrap();
+        self.position = Point2d::new(last.x - shift.x, last.y - shift.y);
+        let (w, h) = (current.cols() as f64, current.rows() as f64);
+        if shift.x.abs() > w * STEP || shift.y.abs() > h * STEP {
+            self.keep(frame, current, self.position)?;
+        }
+        Ok(())
+    }
+
+    fn keep(&mut self, frame: &Mat, small: Mat, offset: Point2d) -> Result<()> {
+        self.frames.push(frame.try_clone()?);
+        self.offsets.push(offset);
+        self.position = offset;
+        self.kept = small;
+        Ok(())
+    }
+
+    // 左上角绘制覆盖范围小地图: 灰框为已保留帧, 绿框为当前视野, 跟踪丢失时为红框.
+    pub fn draw(&self, frame: &mut Mat) -> Result<()> {
+        if self.offsets.is_empty() {
+            return Ok(());
+        }
+        let (w, h) = (self.kept.cols() as f64, self.kept.rows() as f64);
+        let all = self.offsets.iter().chain(std::iter::once(&self.position));
+        let min_x = all.clone().map(|p| p.x).fold(f64::MAX, f64::min);
+        let min_y = all.map(|p| p.y).fold(f64::MAX, f64::min);
+        let origin = Point::new(16, 48);
+        let scale = MAP_SCALE * frame.cols() as f64 / w;
+        let to_map = |p: &Point2d| {
+            Rect::new(
+                origin.x + ((p.x - min_x) * scale) as i32,
+                origin.y + ((p.y - min_y) * scale) as i32,
+                (w * scale) as i32,
+                (h * scale) as i32,
+            )
+        };
+        for offset in &self.offsets {
+            let rect = to_map(offset);
+            imgproc::rectangle(frame, rect, Scalar::new(160., 160., 160., 0.), 1, LINE_8, 0)?;
+        }
+        let color = if self.lost {
+            Scalar::new(0., 0., 255., 0.)
+        } else {
+            Scalar::new(0., 255., 0., 0.)
+        };
+        let rect = to_map(&self.position);
+        imgproc::rectangle(frame, rect, color, 2, LINE_8, 0)?;
+        imgproc::put_text(
+            frame,
+            &format!("panorama {} frames", self.frames.len()),
+            Point::new(origin.x, origin.y - 8),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.6,
+            color,
+            1,
+            LINE_8,
+            false,
+        )?;
+        Ok(())
+    }
+
+    // 拼接比较耗时, 调用者应在单独的线程中执行.
+    pub fn stitch(self) -> Result<PathBuf> {
+        if self.frames.len() < 2 {
+            bail!("pan the camera to capture at least 2 frames");
+        }
+        let mut stitcher = Stitcher::create(Stitcher_Mode::PANORAMA)?;
+        let mut pano = Mat::default();
+        let status = stitcher.stitch(&self.frames, &mut pano)?;
+        if status != Stitcher_Status::OK {
+            bail!("stitching failed: {:?}", status);
+        }
+        let path = PathBuf::from(format!(
+            "panorama-{}.jpg",
+            chrono::Local::now().format("%Y-%m-%dT%H-%M-%S")
+        ));
+        if !imgcodecs::imwrite(&path.to_string_lossy(), &pano, &Vector::new())? {
+            bail!("write {}", path.display());
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::CV_8UC3;
+
+    use super::*;
+
+    // 有纹理的宽场景, 模拟平移摄像头时截取其中 640x480 的一部分.
+    fn scene() -> Mat {
+        let mut noise =
+            Mat::new_rows_cols_with_default(480, 1400, CV_8UC3, Scalar::all(0.)).unwrap();
+        core::randu(&mut noise, &Scalar::all(0.), &Scalar::all(255.)).unwrap();
+        let mut scene = Mat::default();
+        imgproc::gaussian_blur(
+            &noise,
+            &mut scene,
+            Size::new(5, 5),
+            0.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )
+        .unwrap();
+        scene
+    }
+
+    fn view(scene: &Mat, x: i32) -> Mat {
+        Mat::roi(scene, Rect::new(x, 0, 640, 480))
+            .unwrap()
+            .try_clone()
+            .unwrap()
+    }
+
+    // 移动超过画面宽度的 30% 才保留新的一帧.
+    #[test]
+    fn keeps_frames_by_step() {
+        let wide = scene();
+        let mut panorama = Panorama::new();
+        assert!(Panorama::new().stitch().is_err());
+        panorama.add(&view(&wide, 0)).unwrap();
+        assert_eq!(panorama.len(), 1);
+        panorama.add(&view(&wide, 100)).unwrap();
+        assert_eq!(panorama.len(), 1);
+        assert!(!panorama.lost);
+        panorama.add(&view(&wide, 220)).unwrap();
+        assert_eq!(panorama.len(), 2);
+        panorama.add(&view(&wide, 320)).unwrap();
+        assert_eq!(panorama.len(), 2);
+        // 画面向左移动, 视野向右.
+        assert!(panorama.position.x > panorama.offsets[1].x);
+        assert!(panorama.offsets[1].x > 100.0 && panorama.offsets[1].x < 120.0);
+
+        // 和之前毫无关系的画面认为跟踪丢失, 不保留.
+        panorama.add(&view(&scene(), 700)).unwrap();
+        assert!(panorama.lost);
+        assert_eq!(panorama.len(), 2);
+    }
+}
@@ -0,0 +1,466 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        mpsc::{sync_channel, SyncSender},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::Local;
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size, Vector, CV_8U},
+    imgcodecs,
+    imgproc::{self, COLOR_BGR2GRAY, FONT_HERSHEY_SIMPLEX, LINE_8, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    detect::Detector,
+    events::EventStore,
+    metrics,
+    mqtt::{self, MqttConfig},
+    occupancy::Debounce,
+    pipeline::FrameProcessor,
+    zones::Zone,
+};
+
+// 最近一次的车位状态, 供控制接口 GET /parking 读取.
+static LATEST: Mutex<Option<Value>> = Mutex::new(None);
+
+pub fn latest() -> Option<Value> {
+    LATEST.lock().unwrap().clone()
+}
+
+// 停车位预设: 判断每个车位是否有车, 在画面上画出占用图,
+// 空位数写入 metrics, 发布到 MQTT 和控制接口.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ParkingConfig {
+    pub enabled: bool,
+    pub method: Method,
+    // 车位, 顶点坐标为画面宽高的比例.
+    pub spaces: Vec<Zone>,
+    // background: 和空车场参考画面相比, 亮度变化超过 threshold 的像素占车位的比例超过 fraction 时有车.
+    // 参考画面保存在 reference, 文件不存在时取启用时的画面.
+    pub threshold: f64,
+    pub fraction: f64,
+    pub reference: PathBuf,
+    // detection: 车辆检测模型 (见 [models]) 和车辆的类别, 检测框中心在车位内时有车.
+    pub model: Option<String>,
+    pub classes: Vec<usize>,
+    pub min_score: f64,
+    pub detect_every: u32,
+    // 状态持续这么多秒才切换, 避免行人和路过的车.
+    pub debounce: f64,
+    // 状态没有变化时也每隔这么多秒发布一次.
+    pub interval: f64,
+    pub mqtt: Option<MqttConfig>,
+}
+
+impl Default for ParkingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            method: Method::Background,
+            spaces: Vec::new(),
+            threshold: 30.0,
+            fraction: 0.3,
+            reference: PathBuf::from("parking-empty.jpg"),
+            model: None,
+            classes: Vec::new(),
+            min_score: 0.5,
+            detect_every: 10,
+            debounce: 5.0,
+            interval: 60.0,
+            mqtt: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Method {
+    Background,
+    Detection,
+}
+
+// 发布的 JSON: 总数, 空位数和每个车位的状态.
+pub fn summary(spaces: &[Zone], occupied: &[bool]) -> Value {
+    let taken = occupied.iter().filter(|&&occupied| occupied).count();
+    let states: serde_json::Map<String, Value> = spaces
+        .iter()
+        .zip(occupied)
+        .map(|(space, &occupied)| (space.name.clone(), Value::Bool(occupied)))
+        .collect();
+    json!({
+        "time": Local::now().to_rfc3339(),
+        "total": spaces.len(),
+        "occupied": taken,
+        "free": spaces.len() - taken,
+        "spaces": states,
+    })
+}
+
+pub struct Parking {
+    config: ParkingConfig,
+    detector: Option<Box<dyn Detector>>,
+    events: EventStore,
+    publisher: SyncSender<Value>,
+    started: Instant,
+    frames: u64,
+    debounces: Vec<Debounce>,
+    // 最近一次的原始判断, 和防抖后的状态不同时画细线.
+    raw: Vec<bool>,
+    gray: Mat,
+    saved: Option<Mat>,
+    // 模糊后的空车场灰度画面.
+    reference: Option<Mat>,
+    masks: Vec<Mat>,
+    last_publish: Option<Instant>,
+}
+
+impl Parking {
+    pub fn new(
+        config: &ParkingConfig,
+        detector: Option<Box<dyn Detector>>,
+        events: EventStore,
+    ) -> Result<Self> {
+        if config.spaces.is_empty() {
+            bail!("add at least one [[parking.spaces]]");
+        }
+        if let Some(space) = config.spaces.iter().find(|space| space.points.len() < 3) {
+            bail!("parking space {:?} needs at least 3 points", space.name);
+        }
+        if config.method == Method::Detection && detector.is_none() {
+            bail!("parking detection needs a detector");
+        }
+        let (publisher, receiver) = sync_channel::<Value>(1);
+        let mqtt = config.mqtt.clone();
+        // 阶段移除时 publisher 被丢弃, 线程随之退出.
+        thread::Builder::new()
+            .name("parking".to_string())
+            .spawn(move || {
+                for status in receiver {
+                    let Some(mqtt) = &mqtt else {
+                        continue;
+                    };
+                    let mut messages = vec![(mqtt.topic.clone(), status.to_string())];
+                    if let Some(spaces) = status["spaces"].as_object() {
+                        for (name, occupied) in spaces {
+                            let state = if occupied.as_bool() == Some(true) {
+                                "occupied"
+                            } else {
+                                "free"
+                            };
+                            messages.push((format!("{}/{}", mqtt.topic, name), state.to_string()));
+                        }
+                    }
+                    if let Err(err) = mqtt::publish(mqtt, &messages, true) {
+                        eprintln!("parking: {:?}", err);
+                    }
+                }
+            })?;
+        // 已保存的空车场画面, 第一帧时转换成参考画面.
+        let saved = match config.method {
+            Method::Background if config.reference.exists() => {
+                let image = imgcodecs::imread(
+                    &config.reference.to_string_lossy(),
+                    imgcodecs::IMREAD_COLOR,
+                )?;
+                (!image.empty()).then_some(image)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            config: config.clone(),
+            detector,
+            events,
+            publisher,
+            started: Instant::now(),
+            frames: 0,
+            debounces: vec![Debounce::default(); config.spaces.len()],
+            raw: vec![false; config.spaces.len()],
+            gray: Mat::default(),
+            saved,
+            reference: None,
+            masks: Vec::new(),
+            last_publish: None,
+        })
+    }
+
+    fn occupied(&self) -> Vec<bool> {
+        self.debounces
+            .iter()
+            .map(|debounce| debounce.state() == Some(true))
+            .collect()
+    }
+
+    fn blur(frame: &Mat) -> Result<Mat> {
+        let mut gray = Mat::default();
+        imgproc::cvt_color(frame, &mut gray, COLOR_BGR2GRAY, 0)?;
+        let mut blurred = Mat::default();
+        imgproc::gaussian_blur(
+            &gray,
+            &mut blurred,
+            Size::new(5, 5),
+            0.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )?;
+        Ok(blurred)
+    }
+
+    // 画面大小变化时重新生成车位掩码和参考画面. 没有保存的空车场画面时保存当前画面.
+    fn prepare(&mut self, frame: &Mat) -> Result<()> {
+        let size = frame.size()?;
+        if self
+            .masks
+            .first()
+            .map_or(true, |mask| mask.size().ok() != Some(size))
+        {
+            self.masks.clear();
+            for space in &self.config.spaces {
+                let mut mask = Mat::new_size_with_default(size, CV_8U, Scalar::all(0.0))?;
+                let mut polygons = Vector::<Vector<Point>>::new();
+                polygons.push(space.pixels(size.width, size.height));
+                imgproc::fill_poly(
+                    &mut mask,
+                    &polygons,
+                    Scalar::all(255.0),
+                    LINE_8,
+                    0,
+                    Point::new(0, 0),
+                )?;
+                self.masks.push(mask);
+            }
+        }
+        if self.config.method != Method::Background
+            || self
+                .reference
+                .as_ref()
+                .is_some_and(|reference| reference.size().ok() == Some(size))
+        {
+            return Ok(());
+        }
+        let image = match self.saved.take() {
+            Some(saved) if saved.size()? == size => saved,
+            _ => {
+                let path = self.config.reference.to_string_lossy().to_string();
+                if !imgcodecs::imwrite(&path, frame, &Vector::new())? {
+                    eprintln!("parking: write {}", path);
+                }
+                frame.try_clone()?
+            }
+        };
+        self.reference = Some(Self::blur(&image)?);
+        Ok(())
+    }
+
+    fn changed(&self, index: usize) -> Result<f64> {
+        let Some(reference) = &self.reference else {
+            return Ok(0.0);
+        };
+        let mask = &self.masks[index];
+        let area = core::count_non_zero(mask)?;
+        if area == 0 {
+            return Ok(0.0);
+        }
+        let mut diff = Mat::default();
+        core::absdiff(&self.gray, reference, &mut diff)?;
+        let mut changed = Mat::default();
+        imgproc::threshold(
+            &diff,
+            &mut changed,
+            self.config.threshold,
+            255.0,
+            imgproc::THRESH_BINARY,
+        )?;
+        let mut inside = Mat::default();
+        core::bitwise_and(&changed, mask, &mut inside, &core::no_array())?;
+        Ok(core::count_non_zero(&inside)? as f64 / area as f64)
+    }
+
+    fn publish(&mut self) {
+        let occupied = self.occupied();
+        let status = summary(&self.config.spaces, &occupied);
+        let free = status["free"].as_f64().unwrap_or_default();
+        metrics::gauge("parking_free", "Free parking spaces", free);
+        metrics::gauge(
+            "parking_occupied",
+            "Occupied parking spaces",
+            occupied.len() as f64 - free,
+        );
+        *LATEST.lock().unwrap() = Some(status.clone());
+        // 上一次还没有发布完时跳过, 下次会发布最新的状态.
+        let _ = self.publisher.try_send(status);
+        self.last_publish = Some(Instant::now());
+    }
+}
+
+impl Drop for Parking {
+    fn drop(&mut self) {
+        *LATEST.lock().unwrap() = None;
+    }
+}
+
+impl FrameProcessor for Parking {
+    fn name(&self) -> &str {
+        "parking"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let every = match self.config.method {
+            Method::Background => 1,
+            Method::Detection => self.config.detect_every.max(1) as u64,
+        };
+        let detect = self.frames % every == 0;
+        self.frames += 1;
+        if !detect {
+            return Ok(());
+        }
+        self.prepare(frame)?;
+        let (cols, rows) = (frame.cols(), frame.rows());
+        self.raw = match (&mut self.detector, self.config.method) {
+            (Some(detector), Method::Detection) => {
+                let centers: Vec<(f64, f64)> = detector
+                    .detect(frame)?
+                    .iter()
+                    .filter(|d| d.score >= self.config.min_score)
+                    .filter(|d| {
+                        self.config.classes.is_empty() || self.config.classes.contains(&d.class)
+                    })
+                    .map(|d| {
+                        let x = (d.rect.x + d.rect.width / 2) as f64 / cols as f64;
+                        let y = (d.rect.y + d.rect.height / 2) as f64 / rows as f64;
+                        (x, y)
+                    })
+                    .collect();
+                self.config
+                    .spaces
+                    .iter()
+                    .map(|space| centers.iter().any(|&(x, y)| space.contains(x, y)))
+                    .collect()
+            }
+            _ => {
+                self.gray = Self::blur(frame)?;
+                (0..self.config.spaces.len())
+                    .map(|index| Ok(self.changed(index)? > self.config.fraction))
+                    .collect::<Result<_>>()?
+            }
+        };
+        let now = self.started.elapsed().as_secs_f64();
+        let mut changed = false;
+        for index in 0..self.debounces.len() {
+            let debounce = &mut self.debounces[index];
+            let first = debounce.state().is_none();
+            let delay = self.config.debounce;
+            if let Some(occupied) = debounce.update(self.raw[index], now, delay, delay) {
+                changed = true;
+                if !first {
+                    self.events.log(
+                        "parking",
+                        json!({
+                            "space": self.config.spaces[index].name,
+                            "occupied": occupied,
+                        }),
+                    );
+                }
+            }
+        }
+        let interval = Duration::from_secs_f64(self.config.interval.max(1.0));
+        if changed
+            || self
+                .last_publish
+                .map_or(true, |last| last.elapsed() >= interval)
+        {
+            self.publish();
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 占用图: 空位绿色, 有车红色, 半透明填充; 原始判断和状态不同时只画细线.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        let occupied = self.occupied();
+        let color = |occupied: bool| {
+            if occupied {
+                Scalar::new(0., 0., 230., 0.)
+            } else {
+                Scalar::new(0., 200., 0., 0.)
+            }
+        };
+        let mut filled = frame.try_clone()?;
+        for (space, &occupied) in self.config.spaces.iter().zip(&occupied) {
+            let mut polygons = Vector::<Vector<Point>>::new();
+            polygons.push(space.pixels(cols, rows));
+            imgproc::fill_poly(
+                &mut filled,
+                &polygons,
+                color(occupied),
+                LINE_AA,
+                0,
+                Point::new(0, 0),
+            )?;
+        }
+        let mut blended = Mat::default();
+        core::add_weighted(&filled, 0.35, &*frame, 0.65, 0.0, &mut blended, -1)?;
+        blended.copy_to(frame)?;
+        for (index, space) in self.config.spaces.iter().enumerate() {
+            let points = space.pixels(cols, rows);
+            let thickness = if self.raw[index] == occupied[index] {
+                2
+            } else {
+                1
+            };
+            let mut polygons = Vector::<Vector<Point>>::new();
+            polygons.push(points.clone());
+            imgproc::polylines(
+                frame,
+                &polygons,
+                true,
+                color(occupied[index]),
+                thickness,
+                LINE_AA,
+                0,
+            )?;
+            let origin = points.get(0)?;
+            imgproc::put_text(
+                frame,
+                &space.name,
+                Point::new(origin.x + 4, origin.y + 16),
+                FONT_HERSHEY_SIMPLEX,
+                0.45,
+                Scalar::all(255.0),
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+        let free = occupied.iter().filter(|&&occupied| !occupied).count();
+        imgproc::put_text(
+            frame,
+            &format!("Free {}/{}", free, occupied.len()),
+            Point::new(20, 40),
+            FONT_HERSHEY_SIMPLEX,
+            1.0,
+            Scalar::all(255.0),
+            2,
+            LINE_AA,
+            false,
+        )?;
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.occupied().iter().filter(|&&occupied| occupied).count())
+    }
+}
@@ -0,0 +1,482 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{self, Mat, Point, Scalar, Size, Vector, BORDER_DEFAULT},
+    imgcodecs,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, INTER_LANCZOS4, LINE_AA},
+    photo::fast_nl_means_denoising_colored,
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    best_shot::BestShotConfig,
+    exif::Exif,
+    jobs::Handle,
+    models::{ModelConfig, Models, Task},
+    stats,
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhotoConfig {
+    pub dir: PathBuf,
+    // 文件扩展名, 决定图像格式: jpg, png, webp 或 tiff.
+    pub format: String,
+    // jpg 和 webp 的质量 0..100, png 和 tiff 为无损格式, 忽略该设置.
+    pub quality: i32,
+    // 连拍张数, 每帧保存一张.
+    pub burst: u32,
+    // 自拍定时 (秒).
+    pub timer: f64,
+    // 拍照快捷键, 按 Still 组中的连拍和自拍选项拍摄. 为空时不启用.
+    pub hotkey: String,
+    // jpg 和 png 照片写入 EXIF 和 XMP 元数据: 拍摄时间, 摄像头名称和设置, GPS 位置和滤镜列表.
+    pub metadata: bool,
+    // 拍照后的处理, 与视频的处理链分开.
+    pub process: ProcessConfig,
+    // 连拍后挑选最好的一张再保存.
+    pub best_shot: BestShotConfig,
+}
+
+impl Default for PhotoConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("photos"),
+            format: "jpg".to_string(),
+            quality: 95,
+            burst: 5,
+            timer: 10.0,
+            hotkey: "p".to_string(),
+            metadata: true,
+            process: ProcessConfig::default(),
+            best_shot: BestShotConfig::default(),
+        }
+    }
+}
+
+// 照片在后台任务中按顺序降噪, 放大, 锐化后保存, 可以使用比视频重得多的处理.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProcessConfig {
+    // 关闭时拍到的帧直接保存.
+    pub enabled: bool,
+    // 非局部均值降噪的强度, 0 为不降噪.
+    pub denoise: f64,
+    // 放大倍数, 1 为不放大.
+    pub upscale: f64,
+    // [models] 中 task = "super-resolution" 的模型, 设置后按模型的倍数放大, 忽略 upscale.
+    pub model: Option<String>,
+    // 锐化 (unsharp mask) 的强度, 0 为不锐化.
+    pub sharpen: f64,
+}
+
+impl Default for ProcessConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            denoise: 3.0,
+            upscale: 1.0,
+            model: None,
+            sharpen: 0.3,
+        }
+    }
+}
+
+// imwrite 按格式使用的质量参数.
+pub fn write_params(format: &str, quality: Option<i32>) -> Vector<i32> {
+    let mut params = Vector::new();
+    let Some(quality) = quality else {
+        return params;
+    };
+    let quality = quality.clamp(0, 100);
+    match format {
+        "jpg" | "jpeg" => {
+            params.push(imgcodecs::IMWRITE_JPEG_QUALITY);
+            params.push(quality);
+        }
+        "webp" => {
+            params.push(imgcodecs::IMWRITE_WEBP_QUALITY);
+            params.push(quality.max(1));
+        }
+        _ => {}
+    }
+    params
+}
+
+// 一次拍摄: 可选的倒计时之后连续保存 shots 帧.
+pub struct Shoot {
+    config: PhotoConfig,
+    shots: u32,
+    due: Instant,
+    saved: Vec<PathBuf>,
+    // 等待后台处理的帧和元数据, 与 saved 一一对应.
+    frames: Vec<(Mat, Exif)>,
+}
+
+impl Shoot {
+    pub fn new(config: &PhotoConfig, burst: bool, timer: bool) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("create {}", config.dir.display()))?;
+        let delay = if timer { config.timer.max(0.0) } else { 0.0 };
+        Ok(Self {
+            config: config.clone(),
+            shots: if burst { config.burst.max(1) } else { 1 },
+            due: Instant::now() + Duration::from_secs_f64(delay),
+            saved: Vec::new(),
+            frames: Vec::new(),
+        })
+    }
+
+    // 倒计时剩余的整秒数, 不在倒计时中时返回 None.
+    pub fn countdown(&self) -> Option<u64> {
+        let left = self.due.checked_duration_since(Instant::now())?;
+        Some(left.as_secs() + 1)
+    }
+
+    // 倒计时结束后保存该帧, 全部拍完时返回保存的文件. metadata 只在保存时调用.
+    pub fn add(
+        &mut self,
+        frame: &Mat,
+        metadata: impl FnOnce() -> Exif,
+    ) -> Result<Option<&[PathBuf]>> {
+        if self.countdown().is_some() {
+            return Ok(None);
+        }
+        let name = format!(
+            "photo-{}.{}",
+            Local::now().format("%Y-%m-%dT%H-%M-%S%.3f"),
+            self.config.format
+        );
+        let path = self.config.dir.join(name);
+        if self.deferred() {
+            self.frames.push((frame.try_clone()?, metadata()));
+        } else {
+            let metadata = self.config.metadata.then(metadata);
+            let bytes = encode(
+                &self.config.format,
+                Some(self.config.quality),
+                frame,
+                metadata.as_ref(),
+            )?;
+            fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))?;
+            stats::file(&path);
+        }
+        self.saved.push(path);
+        if self.saved.len() < self.shots as usize {
+            return Ok(None);
+        }
+        Ok(Some(&self.saved))
+    }
+
+    // 照片在后台处理或连拍选择之后才保存.
+    pub fn deferred(&self) -> bool {
+        self.config.process.enabled || self.best_of_burst()
+    }
+
+    pub fn best_of_burst(&self) -> bool {
+        self.config.best_shot.enabled && self.shots > 1
+    }
+
+    // 拍完后等待处理或选择的照片, 直接保存时为空.
+    pub fn into_pending(self) -> Vec<(PathBuf, Mat, Exif)> {
+        self.saved
+            .into_iter()
+            .zip(self.frames)
+            .map(|(path, (frame, metadata))| (path, frame, metadata))
+            .collect()
+    }
+
+    // 在预览画面中央显示倒计时.
+    pub fn draw(&self, frame: &mut Mat) -> Result<()> {
+        let Some(seconds) = self.countdown() else {
+            return Ok(());
+        };
+        let text = seconds.to_string();
+        let scale = frame.rows() as f64 / 120.0;
+        let thickness = (scale * 3.0) as i32;
+        let mut baseline = 0;
+        let size =
+            imgproc::get_text_size(&text, FONT_HERSHEY_SIMPLEX, scale, thickness, &mut baseline)?;
+        let origin = Point::new(
+            (frame.cols() - size.width) / 2,
+            (frame.rows() + size.height) / 2,
+        );
+        for (color, extra) in [(Scalar::all(0.), 4), (Scalar::all(255.), 0)] {
+            imgproc::put_text(
+                frame,
+                &text,
+                origin,
+                FONT_HERSHEY_SIMPLEX,
+                scale,
+                color,
+                thickness + extra,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+// 保存的照片数量较多时只显示第一张和张数.
+pub fn describe(saved: &[PathBuf]) -> String {
+    match saved {
+        [] => "No photo saved".to_string(),
+        [path] => format!("Photo saved to {}", path.display()),
+        [first, ..] => format!(
+            "{} photos saved to {}",
+            saved.len(),
+            first.parent().unwrap_or(first).display()
+        ),
+    }
+}
+
+// 按 format 编码, jpg 和 png 写入元数据, 其他格式不写.
+pub fn encode(
+    format: &str,
+    quality: Option<i32>,
+    image: &Mat,
+    metadata: Option<&Exif>,
+) -> Result<Vec<u8>> {
+    let params = write_params(format, quality);
+    let mut buffer = Vector::<u8>::new();
+    if !imgcodecs::imencode(&format!(".{}", format), image, &mut buffer, &params)? {
+        bail!("encode {} image", format);
+    }
+    let bytes = buffer.to_vec();
+    match (format, metadata) {
+        ("jpg" | "jpeg", Some(metadata)) => metadata.insert(&bytes),
+        ("png", Some(metadata)) => metadata.insert_png(&bytes),
+        _ => Ok(bytes),
+    }
+}
+
+// 保存推迟的照片: 启用处理时先处理, 否则直接编码. 在后台任务中执行.
+pub fn save(
+    config: &PhotoConfig,
+    models: &Models,
+    photos: Vec<(PathBuf, Mat, Exif)>,
+    job: &Handle,
+) -> Result<String> {
+    if config.process.enabled {
+        return process(config, models, photos, job);
+    }
+    let mut saved = Vec::new();
+    for (path, frame, metadata) in &photos {
+        let bytes = encode(
+            &config.format,
+            Some(config.quality),
+            frame,
+            config.metadata.then_some(metadata),
+        )?;
+        fs::write(path, bytes).with_context(|| format!("write {}", path.display()))?;
+        stats::file(path);
+        saved.push(path.clone());
+    }
+    Ok(describe(&saved))
+}
+
+// 处理并保存照片, 在后台任务中执行. 超分辨率模型不存在时先下载.
+pub fn process(
+    config: &PhotoConfig,
+    models: &Models,
+    photos: Vec<(PathBuf, Mat, Exif)>,
+    job: &Handle,
+) -> Result<String> {
+    let model = match &config.process.model {
+        Some(name) => {
+            let model = models.get(name)?.clone();
+            if model.task != Task::SuperResolution {
+                bail!("model {} is not a super-resolution model", name);
+            }
+            let path = models.fetch(name, |_| job.progress(Some(0.0)))?;
+            Some((model, path))
+        }
+        None => None,
+    };
+    let mut saved = Vec::new();
+    for (index, (path, frame, metadata)) in photos.iter().enumerate() {
+        job.progress(Some(index as f64 / photos.len() as f64))?;
+        let image = develop(&config.process, model.as_ref(), frame)?;
+        let bytes = encode(
+            &config.format,
+            Some(config.quality),
+            &image,
+            config.metadata.then_some(metadata),
+        )?;
+        fs::write(path, bytes).with_context(|| format!("write {}", path.display()))?;
+        stats::file(path);
+        saved.push(path.clone());
+    }
+    Ok(describe(&saved))
+}
+
+fn develop(
+    config: &ProcessConfig,
+    model: Option<&(ModelConfig, PathBuf)>,
+    frame: &Mat,
+) -> Result<Mat> {
+    let mut image = frame.try_clone()?;
+    let mut scratch = Mat::default();
+    if config.denoise > 0.0 {
+        let strength = config.denoise as f32;
+        fast_nl_means_denoising_colored(&image, &mut scratch, strength, strength, 7, 21)?;
+        std::mem::swap(&mut image, &mut scratch);
+    }
+    match model {
+        Some((model, path)) => image = super_resolve(path, model, &image)?,
+        None if config.upscale > 1.0 => {
+            imgproc::resize(
+                &image,
+                &mut scratch,
+                Size::new(0, 0),
+                config.upscale,
+                config.upscale,
+                INTER_LANCZOS4,
+            )?;
+            std::mem::swap(&mut image, &mut scratch);
+        }
+        None => {}
+    }
+    if config.sharpen > 0.0 {
+        imgproc::gaussian_blur(
+            &image,
+            &mut scratch,
+            Size::new(0, 0),
+            2.0,
+            0.0,
+            BORDER_DEFAULT,
+        )?;
+        let mut sharpened = Mat::default();
+        core::add_weighted(
+            &image,
+            1.0 + config.sharpen,
+            &scratch,
+            -config.sharpen,
+            0.0,
+            &mut sharpened,
+            -1,
+        )?;
+        image = sharpened;
+    }
+    Ok(image)
+}
+
+#[cfg(feature = "dnn")]
+fn super_resolve(path: &Path, model: &ModelConfig, image: &Mat) -> Result<Mat> {
+    use crate::models::Backend;
+
+    match model.backend {
+        Backend::Opencv => crate::dnn::super_resolve(path, model, image),
+        Backend::Onnxruntime => bail!("super-resolution models need the opencv backend"),
+    }
+}
+
+#[cfg(not(feature = "dnn"))]
+fn super_resolve(_path: &Path, _model: &ModelConfig, _image: &Mat) -> Result<Mat> {
+    bail!("super-resolution needs OpenCV DNN, rebuild with --features dnn")
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::CV_8UC3;
+
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}", name, std::process::id()))
+    }
+
+    fn frame() -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(120.)).unwrap()
+    }
+
+    #[test]
+    fn quality_params() {
+        assert_eq!(
+            write_params("jpg", Some(150)).to_vec(),
+            [imgcodecs::IMWRITE_JPEG_QUALITY, 100]
+        );
+        assert_eq!(
+            write_params("webp", Some(0)).to_vec(),
+            [imgcodecs::IMWRITE_WEBP_QUALITY, 1]
+        );
+        assert!(write_params("png", Some(90)).is_empty());
+        assert!(write_params("jpg", None).is_empty());
+    }
+
+    #[test]
+    fn describe_saved() {
+        assert_eq!(describe(&[]), "No photo saved");
+        let first = PathBuf::from("photos").join("a.jpg");
+        assert_eq!(
+            describe(&[first.clone()]),
+            format!("Photo saved to {}", first.display())
+        );
+        assert_eq!(
+            describe(&[first, PathBuf::from("photos").join("b.jpg")]),
+            "2 photos saved to photos"
+        );
+    }
+
+    #[test]
+    fn burst_saves_each_frame() {
+        let dir = temp_dir("photo-burst");
+        let config = PhotoConfig {
+            dir: dir.clone(),
+            format: "png".to_string(),
+            burst: 3,
+            metadata: false,
+            ..Default::default()
+        };
+        let mut shoot = Shoot::new(&config, true, false).unwrap();
+        assert!(shoot.countdown().is_none());
+        assert!(!shoot.deferred());
+        for _ in 0..2 {
+            assert!(shoot.add(&frame(), Exif::default).unwrap().is_none());
+        }
+        let saved = shoot
+            .add(&frame(), Exif::default)
+            .unwrap()
+            .unwrap()
+            .to_vec();
+        assert_eq!(saved.len(), 3);
+        for path in &saved {
+            let image =
+                imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_COLOR).unwrap();
+            assert_eq!((image.cols(), image.rows()), (64, 48));
+        }
+        assert!(shoot.into_pending().is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 倒计时中的帧不保存, 画面中央显示剩余秒数.
+    #[test]
+    fn timer_counts_down() {
+        let dir = temp_dir("photo-timer");
+        let config = PhotoConfig {
+            dir: dir.clone(),
+            timer: 3.0,
+            ..Default::default()
+        };
+        let mut shoot = Shoot::new(&config, false, true).unwrap();
+        assert_eq!(shoot.countdown(), Some(3));
+        assert!(shoot
+            .add(&frame(), || panic!("metadata before the countdown ends"))
+            .unwrap()
+            .is_none());
+        let mut preview = frame();
+        shoot.draw(&mut preview).unwrap();
+        let changed = core::norm2(&preview, &frame(), core::NORM_INF, &core::no_array()).unwrap();
+        assert!(changed > 0.0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use opencv::core::Mat;
+
+// 处理阶段, 在摄像头读取之后、OSD 和像素格式转换之前对 BGR 帧进行原地处理.
+pub trait FrameProcessor: Send {
+    fn name(&self) -> &str;
+    fn process(&mut self, frame: &mut Mat) -> Result<()>;
+
+    // 执行顺序, 数值小的先执行; 传感器校正需要排在其他处理之前.
+    fn order(&self) -> i32 {
+        0
+    }
+
+    // 只画在预览上的标注, 例如不写入录像的检测框.
+    fn has_overlay(&self) -> bool {
+        false
+    }
+
+    fn draw_overlay(&self, _frame: &mut Mat) -> Result<()> {
+        Ok(())
+    }
+
+    // 最近一次检测到的目标数, 不做检测的阶段返回 None.
+    fn detections(&self) -> Option<usize> {
+        None
+    }
+
+    // 写入照片元数据的名称, 包含多个效果的阶段分别列出.
+    fn filters(&self) -> Vec<String> {
+        vec![self.name().to_string()]
+    }
+}
+
+// 连续出错这么多帧后隔离该阶段.
+const QUARANTINE_AFTER: u32 = 3;
+
+// 被隔离的阶段和它最后一次的错误.
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub stage: String,
+    pub error: String,
+}
+
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn FrameProcessor>>,
+    // 暂停的阶段, 例如红外画面中依赖颜色的阶段. 仍在管线中, 只是不处理也不画标注.
+    skipped: Vec<String>,
+    // 每个阶段连续出错的帧数.
+    errors: BTreeMap<String, u32>,
+    // 隔离中的阶段, 和暂停一样不再处理. 重新设置或移除同名阶段时解除.
+    quarantined: Vec<Failure>,
+    // 新隔离的阶段, 由 take_failures 取走.
+    failures: Vec<Failure>,
+    // 等待后台下载模型的阶段, 下载结束后由采集线程创建. 设置或移除同名阶段时不再等待.
+    waiting: Vec<String>,
+}
+
+impl Pipeline {
+    // 同名阶段已存在时原位替换, 否则按 order 插入到同序号阶段之后.
+    pub fn set(&mut self, stage: Box<dyn FrameProcessor>) {
+        self.release(stage.name());
+        if let Some(i) = self.stages.iter().position(|s| s.name() == stage.name()) {
+            self.stages[i] = stage;
+            return;
+        }
+        let i = self
+            .stages
+            .iter()
+            .position(|s| s.order() > stage.order())
+            .unwrap_or(self.stages.len());
+        self.stages.insert(i, stage);
+    }
+
+    pub fn skip(&mut self, names: &[String]) {
+        if self.skipped != names {
+            self.skipped = names.to_vec();
+        }
+    }
+
+    fn runs(&self, name: &str) -> bool {
+        !self.skipped.iter().any(|skipped| skipped == name)
+            && !self.quarantined.iter().any(|failure| failure.stage == name)
+    }
+
+    fn running(&self) -> impl Iterator<Item = &Box<dyn FrameProcessor>> {
+        self.stages.iter().filter(move |s| self.runs(s.name()))
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.stages.iter().map(|s| s.name()).collect()
+    }
+
+    pub fn filters(&self) -> Vec<String> {
+        self.running().flat_map(|s| s.filters()).collect()
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.release(name);
+        self.stages.retain(|s| s.name() != name);
+    }
+
+    fn release(&mut self, name: &str) {
+        self.errors.remove(name);
+        self.quarantined.retain(|failure| failure.stage != name);
+        self.waiting.retain(|waiting| waiting != name);
+    }
+
+    pub fn wait(&mut self, name: &str) {
+        if !self.waiting.iter().any(|waiting| waiting == name) {
+            self.waiting.push(name.to_string());
+        }
+    }
+
+    pub fn is_waiting(&self) -> bool {
+        !self.waiting.is_empty()
+    }
+
+    pub fn take_waiting(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.waiting)
+    }
+
+    pub fn quarantined(&self) -> &[Failure] {
+        &self.quarantined
+    }
+
+    // 自上次调用以来新隔离的阶段.
+    pub fn take_failures(&mut self) -> Vec<Failure> {
+        std::mem::take(&mut self.failures)
+    }
+
+    pub fn has_overlay(&self) -> bool {
+        self.running().any(|s| s.has_overlay())
+    }
+
+    pub fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        for stage in self.running() {
+            stage.draw_overlay(frame)?;
+        }
+        Ok(())
+    }
+
+    // 所有检测阶段的目标数之和, 用于 OSD 和界面.
+    pub fn detections(&self) -> Option<usize> {
+        self.running()
+            .filter_map(|s| s.detections())
+            .reduce(|a, b| a + b)
+    }
+
+    // 单个阶段出错时跳过它继续处理后面的阶段, 连续出错 QUARANTINE_AFTER 帧后隔离,
+    // 一个坏掉的模型或参数不会让整个画面停下来.
+    pub fn process(&mut self, frame: &mut Mat) {
+        for i in 0..self.stages.len() {
+            if self.runs(self.stages[i].name()) {
+                let result = self.stages[i].process(frame);
+                self.check(i, result);
+            }
+        }
+    }
+
+    // 与 process 相同, 同时按阶段名称累计耗时.
+    pub fn process_timed(&mut self, frame: &mut Mat, timings: &mut Vec<(String, Duration)>) {
+        for i in 0..self.stages.len() {
+            if !self.runs(self.stages[i].name()) {
+                continue;
+            }
+            let start = Instant::now();
+            let result = self.stages[i].process(frame);
+            let elapsed = start.elapsed();
+            let name = self.stages[i].name();
+            match timings.iter_mut().find(|(timed, _)| timed == name) {
+                Some((_, total)) => *total += elapsed,
+                None => timings.push((name.to_string(), elapsed)),
+            }
+            self.check(i, result);
+        }
+    }
+
+    fn check(&mut self, i: usize, result: Result<()>) {
+        let name = self.stages[i].name().to_string();
+        let Err(err) = result else {
+            self.errors.remove(&name);
+            return;
+        };
+        let errors = self.errors.entry(name.clone()).or_default();
+        *errors += 1;
+        if *errors < QUARANTINE_AFTER {
+            eprintln!("stage {}: {:?}", name, err);
+            return;
+        }
+        self.errors.remove(&name);
+        let failure = Failure {
+            stage: name,
+            error: format!("{:#}", err),
+        };
+        self.quarantined.push(failure.clone());
+        self.failures.push(failure);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use anyhow::bail;
+
+    use super::*;
+
+    // 按给定的顺序成功或出错的阶段.
+    struct Flaky(std::vec::IntoIter<bool>);
+
+    impl FrameProcessor for Flaky {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        fn process(&mut self, _frame: &mut Mat) -> Result<()> {
+            if self.0.next().unwrap_or(false) {
+                bail!("broken");
+            }
+            Ok(())
+        }
+    }
+
+    // 统计处理过的帧数.
+    struct Counter(Arc<AtomicUsize>);
+
+    impl FrameProcessor for Counter {
+        fn name(&self) -> &str {
+            "counter"
+        }
+
+        fn process(&mut self, _frame: &mut Mat) -> Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    // 等待模型的阶段只取走一次, 在下载结束前被设置或移除时不再等待.
+    #[test]
+    fn waiting_stages() {
+        let mut pipeline = Pipeline::default();
+        pipeline.wait("counter");
+        pipeline.wait("counter");
+        pipeline.wait("flaky");
+        assert!(pipeline.is_waiting());
+        assert_eq!(pipeline.take_waiting(), vec!["counter", "flaky"]);
+        assert!(!pipeline.is_waiting());
+
+        pipeline.wait("counter");
+        pipeline.wait("flaky");
+        pipeline.set(Box::new(Counter(Arc::default())));
+        pipeline.remove("flaky");
+        assert!(!pipeline.is_waiting());
+    }
+
+    fn pipeline(fails: &[bool], count: &Arc<AtomicUsize>) -> Pipeline {
+        let mut pipeline = Pipeline::default();
+        pipeline.set(Box::new(Flaky(fails.to_vec().into_iter())));
+        pipeline.set(Box::new(Counter(count.clone())));
+        pipeline
+    }
+
+    // 偶尔出错的阶段只跳过出错的那一帧, 后面的阶段照常处理.
+    #[test]
+    fn occasional_errors() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let fails = [true, true, false, true, true, false, true];
+        let mut pipeline = pipeline(&fails, &count);
+        let mut frame = Mat::default();
+        for _ in fails {
+            pipeline.process(&mut frame);
+        }
+        assert!(pipeline.quarantined().is_empty());
+        assert!(pipeline.take_failures().is_empty());
+        assert_eq!(count.load(Ordering::Relaxed), fails.len());
+    }
+
+    // 连续出错 3 帧后隔离, 不再处理; 重新设置同名阶段时解除.
+    #[test]
+    fn quarantine() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut pipeline = pipeline(&[false, true, true, true], &count);
+        let mut frame = Mat::default();
+        for _ in 0..4 {
+            pipeline.process(&mut frame);
+        }
+        let failures = pipeline.take_failures();
+        assert_eq!(failures.len(), 1);
+        assert_eq!(failures[0].stage, "flaky");
+        assert_eq!(failures[0].error, "broken");
+        assert!(pipeline.take_failures().is_empty());
+        assert_eq!(pipeline.quarantined().len(), 1);
+
+        let mut timings = Vec::new();
+        pipeline.process_timed(&mut frame, &mut timings);
+        let timed: Vec<&str> = timings.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(timed, ["counter"]);
+        assert_eq!(count.load(Ordering::Relaxed), 5);
+        assert_eq!(pipeline.names(), ["flaky", "counter"]);
+
+        pipeline.set(Box::new(Flaky(Vec::new().into_iter())));
+        assert!(pipeline.quarantined().is_empty());
+        pipeline.process_timed(&mut frame, &mut timings);
+        assert_eq!(timings.len(), 2);
+    }
+}
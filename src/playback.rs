@@ -0,0 +1,227 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use opencv::{
+    core::Mat,
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+
+// 播放位置更新到界面的最小间隔.
+const REPORT_INTERVAL: Duration = Duration::from_millis(250);
+
+// 文件来源的播放控制: 按文件的帧率播放, 暂停或播放结束时重复处理同一帧, 可以跳到任意位置.
+pub struct Playback {
+    interval: Duration,
+    last: Option<Instant>,
+    paused: bool,
+    ended: bool,
+    // 暂停时显示的原始帧, 暂停后读到的第一帧.
+    held: Option<Mat>,
+    reported: Option<Instant>,
+}
+
+impl Playback {
+    pub fn new(fps: f64) -> Self {
+        Self {
+            interval: Duration::from_secs_f64(1.0 / fps.max(1.0)),
+            last: None,
+            paused: false,
+            ended: false,
+            held: None,
+            reported: None,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // 等到下一帧的时间, 文件不会比原始速度播放得更快.
+    pub fn pace(&mut self) {
+        if let Some(wait) = self
+            .last
+            .and_then(|last| self.interval.checked_sub(last.elapsed()))
+        {
+            sleep(wait);
+        }
+        self.last = Some(Instant::now());
+    }
+
+    // 暂停时重复处理的帧, 还没有读到时返回 None.
+    pub fn held(&self) -> Option<&Mat> {
+        self.held.as_ref()
+    }
+
+    // 读到新帧之后调用, 暂停时保存这一帧.
+    pub fn read(&mut self, frame: &Mat) -> Result<()> {
+        if self.paused && self.held.is_none() {
+            self.held = Some(frame.try_clone()?);
+        }
+        Ok(())
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    // 播放结束后继续播放时从头开始.
+    pub fn resume(&mut self, camera: &mut VideoCapture) -> Result<()> {
+        if self.ended {
+            self.seek(camera, 0.0)?;
+        }
+        self.paused = false;
+        self.held = None;
+        Ok(())
+    }
+
+    // 跳到 0..1 的位置, 暂停时停在跳转后的第一帧. 不能跳转时返回 false.
+    pub fn seek(&mut self, camera: &mut VideoCapture, fraction: f64) -> Result<bool> {
+        let count = camera.get(videoio::CAP_PROP_FRAME_COUNT)?;
+        if count <= 0.0 {
+            return Ok(false);
+        }
+        let frame = (fraction.clamp(0.0, 1.0) * (count - 1.0)).round();
+        if !camera.set(videoio::CAP_PROP_POS_FRAMES, frame)? {
+            return Ok(false);
+        }
+        self.ended = false;
+        self.held = None;
+        self.reported = None;
+        Ok(true)
+    }
+
+    // 读到文件结尾时停在最后一帧, 已经停下时返回 false.
+    pub fn end(&mut self, camera: &mut VideoCapture) -> Result<bool> {
+        if self.ended {
+            return Ok(false);
+        }
+        self.seek(camera, 1.0)?;
+        self.ended = true;
+        self.paused = true;
+        Ok(true)
+    }
+
+    // 当前位置 (0..1) 和已播放, 总时长 (秒), 距离上次更新不足 REPORT_INTERVAL 时返回 None.
+    pub fn report(&mut self, camera: &VideoCapture) -> Result<Option<(f64, f64, f64)>> {
+        if self
+            .reported
+            .is_some_and(|reported| reported.elapsed() < REPORT_INTERVAL)
+        {
+            return Ok(None);
+        }
+        self.reported = Some(Instant::now());
+        let Some((played, total)) = self.seconds(camera)? else {
+            return Ok(None);
+        };
+        Ok(Some(((played / total).clamp(0.0, 1.0), played, total)))
+    }
+
+    // 已播放和总时长 (秒), 不知道帧数时返回 None.
+    pub fn seconds(&self, camera: &VideoCapture) -> Result<Option<(f64, f64)>> {
+        let count = camera.get(videoio::CAP_PROP_FRAME_COUNT)?;
+        let position = camera.get(videoio::CAP_PROP_POS_FRAMES)?;
+        if count <= 0.0 {
+            return Ok(None);
+        }
+        let seconds = self.interval.as_secs_f64();
+        Ok(Some((position * seconds, count * seconds)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use opencv::{
+        core::{Scalar, Size, Vec3b, CV_8UC3},
+        videoio::VideoWriter,
+    };
+
+    use super::*;
+
+    // 10 FPS 的 10 帧视频, 共 1 秒.
+    fn video(name: &str) -> (PathBuf, VideoCapture) {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.avi");
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        for i in 0..10 {
+            let frame =
+                Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(i as f64 * 20.))
+                    .unwrap();
+            writer.write(&frame).unwrap();
+        }
+        writer.release().unwrap();
+        let camera = VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).unwrap();
+        (dir, camera)
+    }
+
+    #[test]
+    fn pace_limits_speed() {
+        let mut playback = Playback::new(20.0);
+        let start = Instant::now();
+        for _ in 0..3 {
+            playback.pace();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    // 暂停后保存读到的第一帧, 继续播放时丢弃.
+    #[test]
+    fn pause_holds_frame() {
+        let (dir, mut camera) = video("playback-pause");
+        let mut playback = Playback::new(10.0);
+        let mut frame = Mat::default();
+        camera.read(&mut frame).unwrap();
+        playback.read(&frame).unwrap();
+        assert!(playback.held().is_none());
+        playback.pause();
+        assert!(playback.is_paused());
+        camera.read(&mut frame).unwrap();
+        playback.read(&frame).unwrap();
+        camera.read(&mut frame).unwrap();
+        playback.read(&frame).unwrap();
+        let held = playback.held().unwrap();
+        assert!(held.at_2d::<Vec3b>(0, 0).unwrap()[0].abs_diff(20) <= 3);
+        playback.resume(&mut camera).unwrap();
+        assert!(!playback.is_paused());
+        assert!(playback.held().is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 结尾时停在最后一帧, 继续播放时从头开始.
+    #[test]
+    fn seek_and_end() {
+        let (dir, mut camera) = video("playback-seek");
+        let mut playback = Playback::new(10.0);
+        assert_eq!(playback.seconds(&camera).unwrap(), Some((0.0, 1.0)));
+        let (fraction, played, total) = playback.report(&camera).unwrap().unwrap();
+        assert_eq!((fraction, played, total), (0.0, 0.0, 1.0));
+        assert!(playback.report(&camera).unwrap().is_none());
+
+        assert!(playback.seek(&mut camera, 0.5).unwrap());
+        assert_eq!(camera.get(videoio::CAP_PROP_POS_FRAMES).unwrap(), 5.0);
+        // 跳转后立即更新位置.
+        assert!(playback.report(&camera).unwrap().is_some());
+
+        assert!(playback.end(&mut camera).unwrap());
+        assert!(playback.is_paused());
+        assert_eq!(camera.get(videoio::CAP_PROP_POS_FRAMES).unwrap(), 9.0);
+        assert!(!playback.end(&mut camera).unwrap());
+        playback.resume(&mut camera).unwrap();
+        assert_eq!(camera.get(videoio::CAP_PROP_POS_FRAMES).unwrap(), 0.0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
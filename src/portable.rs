@@ -0,0 +1,99 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+
+use crate::config::Config;
+
+// 程序旁边有这个文件时启用便携模式, 不需要 --portable.
+pub const MARKER: &str = "portable";
+
+// 程序所在的目录.
+pub fn dir() -> Result<PathBuf> {
+    let exe = env::current_exe().context("locate the executable")?;
+    let exe = exe.canonicalize().unwrap_or(exe);
+    exe.parent()
+        .map(Path::to_path_buf)
+        .context("the executable has no parent directory")
+}
+
+// 便携模式: 配置, 模型, 录像和事件都放在程序所在的目录中, 从 U 盘运行时不写入主机的目录.
+// 启用时返回程序所在的目录.
+pub fn detect(flag: bool) -> Result<Option<PathBuf>> {
+    let dir = dir()?;
+    Ok((flag || dir.join(MARKER).exists()).then_some(dir))
+}
+
+// 切换工作目录到 dir, 配置中的相对路径 (包括配置文件本身) 都相对于它. 返回原来的工作目录.
+pub fn enter(dir: &Path) -> Result<PathBuf> {
+    let previous = env::current_dir().context("read the working directory")?;
+    env::set_current_dir(dir).with_context(|| format!("change directory to {}", dir.display()))?;
+    Ok(previous)
+}
+
+// 相对路径按 base 解析, 绝对路径不变.
+pub fn resolve(base: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base.join(path)
+    }
+}
+
+// 配置中指向程序目录之外的主要路径, 便携模式下这些文件会写到主机上.
+pub fn outside(config: &Config, dir: &Path) -> Vec<PathBuf> {
+    [
+        &config.events_file,
+        &config.audit_file,
+        &config.profiles_dir,
+        &config.models_dir,
+        &config.crash.dir,
+        &config.photo.dir,
+        &config.update.dir,
+    ]
+    .into_iter()
+    .filter(|path| path.is_absolute() && !path.starts_with(dir))
+    .cloned()
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_paths() {
+        let stick = env::temp_dir().join("stick");
+        assert_eq!(
+            resolve(&stick, Path::new("recordings")),
+            stick.join("recordings")
+        );
+        let host = env::temp_dir().join("host").join("events.jsonl");
+        assert_eq!(resolve(&stick, &host), host);
+    }
+
+    // 默认配置都是相对路径, 不会写到主机上; 只提示程序目录之外的绝对路径.
+    #[test]
+    fn outside_paths() {
+        let stick = env::temp_dir().join("stick");
+        let config = Config::default();
+        assert!(outside(&config, &stick).is_empty());
+
+        let host = env::temp_dir().join("host");
+        let mut config = Config::default();
+        config.events_file = host.join("events.jsonl");
+        config.models_dir = stick.join("models");
+        config.photo.dir = host.join("photos");
+        assert_eq!(
+            outside(&config, &stick),
+            [host.join("events.jsonl"), host.join("photos")]
+        );
+    }
+
+    #[test]
+    fn flag_enables() {
+        assert_eq!(detect(true).unwrap(), Some(dir().unwrap()));
+    }
+}
@@ -0,0 +1,75 @@
+use anyhow::{bail, Result};
+use opencv::core::Mat;
+
+// 人体关键点, 坐标为原始画面的像素, score 为模型给出的置信度.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keypoint {
+    pub x: f32,
+    pub y: f32,
+    pub score: f32,
+}
+
+// 姿态估计模型, 返回画面中一个人的关键点, 下标为模型的关键点编号.
+pub trait PoseEstimator: Send {
+    fn estimate(&mut self, frame: &Mat) -> Result<Vec<Keypoint>>;
+
+    // 实际使用的计算设备, 用于显示.
+    fn device(&self) -> String {
+        "cpu".to_string()
+    }
+}
+
+// 支持两种输出格式:
+// [1, 1, K, 3] 为 MoveNet 的 (y, x, score), 坐标为相对值;
+// [1, C, H, W] 为 OpenPose, HRNet 等模型的热图, 每个关键点取热图最大值的位置.
+// 热图模型还会输出背景和肢体向量通道, count 为前面的关键点通道数, None 时全部使用.
+pub fn keypoints(
+    data: &[f32],
+    shape: &[usize],
+    count: Option<usize>,
+    width: i32,
+    height: i32,
+) -> Result<Vec<Keypoint>> {
+    if shape.len() != 4 || shape.iter().product::<usize>() != data.len() {
+        bail!("pose output of shape {:?} is not supported", shape);
+    }
+    let (width, height) = (width as f32, height as f32);
+    if shape[1] == 1 && shape[3] == 3 {
+        return Ok(data
+            .chunks_exact(3)
+            .map(|point| Keypoint {
+                x: point[1] * width,
+                y: point[0] * height,
+                score: point[2],
+            })
+            .collect());
+    }
+    let (rows, cols) = (shape[2], shape[3]);
+    let pixels = rows * cols;
+    if pixels == 0 {
+        bail!("pose output of shape {:?} is empty", shape);
+    }
+    let channels = count.map_or(shape[1], |count| count.min(shape[1]));
+    Ok(data
+        .chunks_exact(pixels)
+        .take(channels)
+        .map(|heatmap| {
+            let (best, score) =
+                heatmap
+                    .iter()
+                    .enumerate()
+                    .fold((0, f32::NEG_INFINITY), |best, (i, &value)| {
+                        if value > best.1 {
+                            (i, value)
+                        } else {
+                            best
+                        }
+                    });
+            Keypoint {
+                x: ((best % cols) as f32 + 0.5) / cols as f32 * width,
+                y: ((best / cols) as f32 + 0.5) / rows as f32 * height,
+                score,
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,358 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, Vector},
+    imgcodecs,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    detect::Detector,
+    events::EventStore,
+    pipeline::FrameProcessor,
+    threads::{self, ThreadConfig},
+    tracking::Detection,
+};
+
+// 劳保用品检测预设: 用检测模型找出没有戴安全帽或没有穿反光背心的人,
+// 在画面上标出并记录带快照的事件.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PpeConfig {
+    pub enabled: bool,
+    // 检测模型名称 (见 [models]), 需要能检测人和劳保用品.
+    pub model: Option<String>,
+    pub min_score: f64,
+    // 检测间隔 (秒).
+    pub interval: f64,
+    // 模型中人, 安全帽和反光背心的类别, 不设置的用品不检查.
+    pub person: usize,
+    pub helmet: Option<usize>,
+    pub vest: Option<usize>,
+    // 本身就表示违规的类别, 例如模型直接输出的 "no-helmet".
+    pub violations: Vec<usize>,
+    // 两次记录违规事件的最短间隔 (秒).
+    pub cooldown: f64,
+    // 违规时的画面.
+    pub dir: PathBuf,
+}
+
+impl Default for PpeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            min_score: 0.5,
+            interval: 1.0,
+            person: 0,
+            helmet: None,
+            vest: None,
+            violations: Vec::new(),
+            cooldown: 30.0,
+            dir: PathBuf::from("ppe"),
+        }
+    }
+}
+
+// 一处违规: 缺少用品的人, 或违规类别的检测框.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub rect: Rect,
+    pub missing: Vec<String>,
+}
+
+// 用品框的中心落在人框的对应部位时认为穿戴了: 安全帽在上面四分之一 (允许超出人框上沿),
+// 背心在中间一半.
+fn worn(person: &Rect, item: &Rect, top: f64, bottom: f64) -> bool {
+    let x = item.x as f64 + item.width as f64 / 2.0;
+    let y = item.y as f64 + item.height as f64 / 2.0;
+    let height = person.height as f64;
+    x >= person.x as f64
+        && x <= (person.x + person.width) as f64
+        && y >= person.y as f64 + top * height
+        && y <= person.y as f64 + bottom * height
+}
+
+// 按配置检查检测结果, 返回所有违规.
+pub fn check(detections: &[Detection], config: &PpeConfig) -> (Vec<Rect>, Vec<Violation>) {
+    let detections: Vec<&Detection> = detections
+        .iter()
+        .filter(|d| d.score >= config.min_score)
+        .collect();
+    let boxes = |class: usize| -> Vec<Rect> {
+        detections
+            .iter()
+            .filter(|d| d.class == class)
+            .map(|d| d.rect)
+            .collect()
+    };
+    let helmets = config.helmet.map(boxes);
+    let vests = config.vest.map(boxes);
+    let mut compliant = Vec::new();
+    let mut violations = Vec::new();
+    for person in boxes(config.person) {
+        let mut missing = Vec::new();
+        if let Some(helmets) = &helmets {
+            if !helmets.iter().any(|h| worn(&person, h, -0.1, 0.25)) {
+                missing.push("helmet".to_string());
+            }
+        }
+        if let Some(vests) = &vests {
+            if !vests.iter().any(|v| worn(&person, v, 0.2, 0.7)) {
+                missing.push("vest".to_string());
+            }
+        }
+        if missing.is_empty() {
+            compliant.push(person);
+        } else {
+            violations.push(Violation {
+                rect: person,
+                missing,
+            });
+        }
+    }
+    for detection in &detections {
+        if config.violations.contains(&detection.class) {
+            violations.push(Violation {
+                rect: detection.rect,
+                missing: Vec::new(),
+            });
+        }
+    }
+    (compliant, violations)
+}
+
+struct Job {
+    image: Mat,
+}
+
+#[derive(Default)]
+struct Status {
+    compliant: Vec<Rect>,
+    violations: Vec<(Violation, String)>,
+}
+
+pub struct PpeMonitor {
+    jobs: SyncSender<Job>,
+    status: Arc<Mutex<Status>>,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl PpeMonitor {
+    pub fn new(
+        config: &PpeConfig,
+        mut detector: Box<dyn Detector>,
+        events: EventStore,
+        workers: &ThreadConfig,
+    ) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("create {}", config.dir.display()))?;
+        let status = Arc::new(Mutex::new(Status::default()));
+        let shared = status.clone();
+        let worker_config = config.clone();
+        let cooldown = Duration::from_secs_f64(config.cooldown.max(0.0));
+        let mut logged: Option<Instant> = None;
+        let jobs = threads::worker("ppe", 1, workers, move |job: Job| {
+            let config = &worker_config;
+            let detections = detector.detect(&job.image)?;
+            let (compliant, violations) = check(&detections, config);
+            let labels: Vec<String> = violations
+                .iter()
+                .map(|violation| label(violation, &detections, detector.as_ref()))
+                .collect();
+            {
+                let mut status = shared.lock().unwrap();
+                status.compliant = compliant;
+                status.violations = violations.iter().cloned().zip(labels.clone()).collect();
+            }
+            if violations.is_empty() || logged.is_some_and(|logged| logged.elapsed() < cooldown) {
+                return Ok(());
+            }
+            logged = Some(Instant::now());
+            let snapshot = config.dir.join(format!(
+                "violation-{}.jpg",
+                Local::now().format("%Y-%m-%dT%H-%M-%S")
+            ));
+            let snapshot =
+                match imgcodecs::imwrite(&snapshot.to_string_lossy(), &job.image, &Vector::new()) {
+                    Ok(true) => Some(snapshot.display().to_string()),
+                    _ => None,
+                };
+            events.log(
+                "ppe-violation",
+                json!({
+                    "count": violations.len(),
+                    "violations": labels,
+                    "snapshot": snapshot,
+                }),
+            );
+            Ok(())
+        })?;
+        Ok(Self {
+            jobs,
+            status,
+            interval: Duration::from_secs_f64(config.interval.max(0.1)),
+            last: None,
+        })
+    }
+}
+
+// 人框写出缺少的用品, 违规类别的框写出类别名称.
+fn label(violation: &Violation, detections: &[Detection], detector: &dyn Detector) -> String {
+    if !violation.missing.is_empty() {
+        return format!("no {}", violation.missing.join(", no "));
+    }
+    detections
+        .iter()
+        .find(|d| d.rect == violation.rect)
+        .map_or_else(String::new, |d| detector.label(d.class))
+}
+
+impl FrameProcessor for PpeMonitor {
+    fn name(&self) -> &str {
+        "ppe"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        match self.jobs.try_send(Job {
+            image: frame.try_clone()?,
+        }) {
+            Ok(()) => self.last = Some(Instant::now()),
+            Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => bail!("ppe thread exited"),
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 合规的人为绿框, 违规为红框并写出缺少的用品.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let status = self.status.lock().unwrap();
+        for rect in &status.compliant {
+            imgproc::rectangle(frame, *rect, Scalar::new(0., 200., 0., 0.), 2, LINE_AA, 0)?;
+        }
+        let red = Scalar::new(0., 0., 255., 0.);
+        for (violation, label) in &status.violations {
+            imgproc::rectangle(frame, violation.rect, red, 3, LINE_AA, 0)?;
+            imgproc::put_text(
+                frame,
+                label,
+                Point::new(violation.rect.x, (violation.rect.y - 8).max(16)),
+                FONT_HERSHEY_SIMPLEX,
+                0.6,
+                red,
+                2,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.status.lock().unwrap().violations.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(class: usize, x: i32, y: i32, width: i32, height: i32) -> Detection {
+        Detection {
+            rect: Rect::new(x, y, width, height),
+            score: 0.9,
+            class,
+        }
+    }
+
+    fn config() -> PpeConfig {
+        PpeConfig {
+            person: 0,
+            helmet: Some(1),
+            vest: Some(2),
+            violations: vec![3],
+            ..PpeConfig::default()
+        }
+    }
+
+    fn missing(items: &[&str]) -> Vec<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    // 安全帽在头部 (可以略高出人框), 背心在躯干; 腿上的背心和低置信度的框不算.
+    #[test]
+    fn gear_positions() {
+        let detections = [
+            detection(0, 0, 0, 100, 300),
+            detection(1, 30, -40, 40, 40),
+            detection(2, 20, 100, 60, 60),
+            detection(0, 400, 0, 100, 300),
+            detection(1, 430, 10, 40, 30),
+            detection(2, 420, 260, 60, 40),
+            detection(0, 800, 0, 100, 300),
+            Detection {
+                score: 0.3,
+                ..detection(1, 830, 10, 40, 30)
+            },
+        ];
+        let (compliant, violations) = check(&detections, &config());
+        assert_eq!(compliant, vec![Rect::new(0, 0, 100, 300)]);
+        assert_eq!(
+            violations,
+            vec![
+                Violation {
+                    rect: Rect::new(400, 0, 100, 300),
+                    missing: missing(&["vest"]),
+                },
+                Violation {
+                    rect: Rect::new(800, 0, 100, 300),
+                    missing: missing(&["helmet", "vest"]),
+                },
+            ]
+        );
+    }
+
+    // 不检查没有配置的用品, 违规类别本身就是违规.
+    #[test]
+    fn violation_classes() {
+        let config = PpeConfig {
+            vest: None,
+            ..config()
+        };
+        let detections = [
+            detection(0, 0, 0, 100, 300),
+            detection(1, 30, 10, 40, 30),
+            detection(3, 500, 50, 40, 40),
+        ];
+        let (compliant, violations) = check(&detections, &config);
+        assert_eq!(compliant.len(), 1);
+        assert_eq!(
+            violations,
+            vec![Violation {
+                rect: Rect::new(500, 50, 40, 40),
+                missing: Vec::new(),
+            }]
+        );
+    }
+}
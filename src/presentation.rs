@@ -0,0 +1,306 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::Result;
+use opencv::{
+    core::{self, Mat, Point, Rect, Scalar, Size, BORDER_CONSTANT, CV_8UC1},
+    imgproc::{self, FILLED, INTER_AREA, LINE_AA, MORPH_ELLIPSE},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    source::{self, FrameSource, Metadata},
+};
+
+// 演示模式: 屏幕采集为底, 摄像头画面作为角落里的圆形或圆角气泡, 合成为一个来源 "presentation://".
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PresentationConfig {
+    // 底层画面和气泡的来源 URI.
+    pub screen: String,
+    pub camera: String,
+    // 气泡中心的位置, 相对于画面宽高, 可以在预览中拖动.
+    pub x: f64,
+    pub y: f64,
+    // 气泡宽度占画面宽度的比例, 可以在预览中用滚轮调整.
+    pub size: f64,
+    pub shape: Shape,
+    // 圆角半径占气泡宽度的比例.
+    pub radius: f64,
+    // 气泡边框宽度 (像素), 0 为不画.
+    pub border: i32,
+}
+
+impl Default for PresentationConfig {
+    fn default() -> Self {
+        Self {
+            screen: "screen://".to_string(),
+            camera: "camera://".to_string(),
+            x: 0.85,
+            y: 0.8,
+            size: 0.22,
+            shape: Shape::Circle,
+            radius: 0.15,
+            border: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Shape {
+    Circle,
+    Rounded,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bubble {
+    x: f64,
+    y: f64,
+    size: f64,
+}
+
+// 当前气泡的位置和大小, 切换来源或重新连接后保持.
+static BUBBLE: Mutex<Option<Bubble>> = Mutex::new(None);
+
+// 把气泡中心移到 (x, y), 相对于画面宽高. 预览中拖动时调用.
+pub fn move_bubble(x: f32, y: f32) {
+    if let Some(bubble) = BUBBLE.lock().unwrap().as_mut() {
+        bubble.x = (x as f64).clamp(0.0, 1.0);
+        bubble.y = (y as f64).clamp(0.0, 1.0);
+    }
+}
+
+// 按比例缩放气泡, 预览中滚动滚轮时调用.
+pub fn resize_bubble(factor: f32) {
+    if let Some(bubble) = BUBBLE.lock().unwrap().as_mut() {
+        bubble.size = (bubble.size * factor as f64).clamp(0.05, 0.9);
+    }
+}
+
+// 参数不使用, 来源由 [presentation] 配置.
+pub fn open(_: &str, config: &Config) -> Result<Box<dyn FrameSource>> {
+    let presentation = &config.presentation;
+    let screen = source::open(&presentation.screen, config)?;
+    let mut camera = source::open(&presentation.camera, config)?;
+    let mut metadata = screen.metadata().clone();
+    metadata.description = format!(
+        "presentation ({} + {})",
+        metadata.description,
+        camera.metadata().description
+    );
+    metadata.profile = "presentation".to_string();
+    BUBBLE.lock().unwrap().get_or_insert(Bubble {
+        x: presentation.x,
+        y: presentation.y,
+        size: presentation.size,
+    });
+
+    // 摄像头在自己的线程中读取, 帧率不同也不会拖慢屏幕采集.
+    let latest = Arc::new(Mutex::new(Mat::default()));
+    let stopped = Arc::new(AtomicBool::new(false));
+    {
+        let (latest, stopped) = (latest.clone(), stopped.clone());
+        spawn(move || {
+            let mut frame = Mat::default();
+            while !stopped.load(Ordering::SeqCst) {
+                match camera.read(&mut frame) {
+                    Ok(true) if !frame.empty() => {
+                        std::mem::swap(&mut *latest.lock().unwrap(), &mut frame);
+                    }
+                    Ok(_) => sleep(Duration::from_millis(10)),
+                    Err(err) => {
+                        eprintln!("presentation camera: {:?}", err);
+                        break;
+                    }
+                }
+            }
+        });
+    }
+    Ok(Box::new(PresentationSource {
+        screen,
+        latest,
+        stopped,
+        metadata,
+        shape: presentation.shape,
+        radius: presentation.radius,
+        border: presentation.border,
+        bubble: Mat::default(),
+        mask: Mat::default(),
+        outline: Mat::default(),
+    }))
+}
+
+pub struct PresentationSource {
+    screen: Box<dyn FrameSource>,
+    latest: Arc<Mutex<Mat>>,
+    stopped: Arc<AtomicBool>,
+    metadata: Metadata,
+    shape: Shape,
+    radius: f64,
+    border: i32,
+    bubble: Mat,
+    mask: Mat,
+    // 遮罩内侧 border 像素宽的一圈, 画边框用.
+    outline: Mat,
+}
+
+impl PresentationSource {
+    // 气泡在画面中的位置, 不超出画面.
+    fn placement(&self, frame: Size, camera: Size) -> Rect {
+        let bubble = BUBBLE.lock().unwrap().unwrap_or(Bubble {
+            x: 0.85,
+            y: 0.8,
+            size: 0.22,
+        });
+        let width = ((bubble.size * frame.width as f64) as i32).clamp(2, frame.width);
+        let height = match self.shape {
+            Shape::Circle => width,
+            Shape::Rounded => width * camera.height / camera.width.max(1),
+        }
+        .clamp(2, frame.height);
+        let x = (bubble.x * frame.width as f64) as i32 - width / 2;
+        let y = (bubble.y * frame.height as f64) as i32 - height / 2;
+        Rect::new(
+            x.clamp(0, frame.width - width),
+            y.clamp(0, frame.height - height),
+            width,
+            height,
+        )
+    }
+
+    // 气泡形状的遮罩和边框, 尺寸不变时复用.
+    fn update_mask(&mut self, size: Size) -> Result<()> {
+        if self.mask.size()? == size {
+            return Ok(());
+        }
+        self.mask = Mat::new_size_with_default(size, CV_8UC1, Scalar::all(0.0))?;
+        let white = Scalar::all(255.0);
+        match self.shape {
+            Shape::Circle => {
+                let center = Point::new(size.width / 2, size.height / 2);
+                imgproc::circle(
+                    &mut self.mask,
+                    center,
+                    size.width / 2,
+                    white,
+                    FILLED,
+                    LINE_AA,
+                    0,
+                )?;
+            }
+            Shape::Rounded => {
+                let r = ((self.radius * size.width as f64) as i32)
+                    .clamp(0, size.width.min(size.height) / 2);
+                let (w, h) = (size.width, size.height);
+                imgproc::rectangle(
+                    &mut self.mask,
+                    Rect::new(r, 0, w - 2 * r, h),
+                    white,
+                    FILLED,
+                    LINE_AA,
+                    0,
+                )?;
+                imgproc::rectangle(
+                    &mut self.mask,
+                    Rect::new(0, r, w, h - 2 * r),
+                    white,
+                    FILLED,
+                    LINE_AA,
+                    0,
+                )?;
+                for center in [
+                    Point::new(r, r),
+                    Point::new(w - r - 1, r),
+                    Point::new(r, h - r - 1),
+                    Point::new(w - r - 1, h - r - 1),
+                ] {
+                    imgproc::circle(&mut self.mask, center, r, white, FILLED, LINE_AA, 0)?;
+                }
+            }
+        }
+        if self.border > 0 {
+            let side = 2 * self.border + 1;
+            let kernel = imgproc::get_structuring_element(
+                MORPH_ELLIPSE,
+                Size::new(side, side),
+                Point::new(-1, -1),
+            )?;
+            let mut inner = Mat::default();
+            // 边界外按 0 处理, 贴着边界的部分也有边框.
+            imgproc::erode(
+                &self.mask,
+                &mut inner,
+                &kernel,
+                Point::new(-1, -1),
+                1,
+                BORDER_CONSTANT,
+                Scalar::all(0.0),
+            )?;
+            core::subtract(&self.mask, &inner, &mut self.outline, &core::no_array(), -1)?;
+        }
+        Ok(())
+    }
+
+    fn composite(&mut self, frame: &mut Mat) -> Result<()> {
+        let camera = self.latest.lock().unwrap();
+        if camera.empty() {
+            return Ok(());
+        }
+        let camera_size = camera.size()?;
+        let place = self.placement(frame.size()?, camera_size);
+        // 圆形气泡取摄像头画面中间的正方形.
+        let source = match self.shape {
+            Shape::Circle => {
+                let side = camera_size.width.min(camera_size.height);
+                Rect::new(
+                    (camera_size.width - side) / 2,
+                    (camera_size.height - side) / 2,
+                    side,
+                    side,
+                )
+            }
+            Shape::Rounded => Rect::new(0, 0, camera_size.width, camera_size.height),
+        };
+        {
+            let roi = Mat::roi(&*camera, source)?;
+            imgproc::resize(&roi, &mut self.bubble, place.size(), 0.0, 0.0, INTER_AREA)?;
+        }
+        drop(camera);
+        self.update_mask(place.size())?;
+        let mut target = Mat::roi_mut(frame, place)?;
+        self.bubble.copy_to_masked(&mut target, &self.mask)?;
+        if self.border > 0 {
+            target.set_to(&Scalar::all(255.0), &self.outline)?;
+        }
+        Ok(())
+    }
+}
+
+impl FrameSource for PresentationSource {
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        if !self.screen.read(frame)? || frame.empty() {
+            return Ok(false);
+        }
+        self.composite(frame)?;
+        Ok(true)
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+impl Drop for PresentationSource {
+    fn drop(&mut self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, Vector},
+    imgcodecs,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    detect::Detector,
+    events::EventStore,
+    pipeline::FrameProcessor,
+    threads::{self, ThreadConfig},
+};
+
+// 3D 打印监控预设: 按层拍摄延时摄影, 用检测模型发现打印失败 (炒面),
+// 连续几次检测到时通过 OctoPrint 或 Moonraker 暂停打印.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrinterConfig {
+    pub enabled: bool,
+    // 打印机的接口, 不设置时只记录事件, 不暂停.
+    pub api: Option<PrinterApi>,
+    // 打印失败检测模型名称 (见 [models]) 和使用的类别.
+    pub model: Option<String>,
+    pub class: Option<usize>,
+    pub min_score: f64,
+    // 检测间隔 (秒).
+    pub interval: f64,
+    // 连续检测到这么多次才认为打印失败, 避免一次误检就暂停.
+    pub consecutive: u32,
+    pub pause: bool,
+    // 每次打印的延时摄影帧和视频, 以及失败时的画面.
+    pub dir: PathBuf,
+    pub fps: f64,
+    pub fourcc: String,
+}
+
+impl Default for PrinterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            api: None,
+            model: None,
+            class: None,
+            min_score: 0.6,
+            interval: 5.0,
+            consecutive: 3,
+            pause: true,
+            dir: PathBuf::from("prints"),
+            fps: 30.0,
+            fourcc: "mp4v".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase", deny_unknown_fields)]
+pub enum PrinterApi {
+    Octoprint {
+        url: String,
+        // 只包含 API key 的文件.
+        api_key_file: PathBuf,
+    },
+    Moonraker {
+        url: String,
+        api_key_file: Option<PathBuf>,
+    },
+}
+
+impl PrinterApi {
+    pub fn pause(&self) -> Result<()> {
+        let key = |file: &Path| -> Result<String> {
+            let key =
+                fs::read_to_string(file).with_context(|| format!("read {}", file.display()))?;
+            Ok(key.trim().to_string())
+        };
+        let (request, body) = match self {
+            PrinterApi::Octoprint { url, api_key_file } => (
+                ureq::post(&format!("{}/api/job", url.trim_end_matches('/')))
+                    .set("X-Api-Key", &key(api_key_file)?),
+                json!({ "command": "pause", "action": "pause" }).to_string(),
+            ),
+            PrinterApi::Moonraker { url, api_key_file } => {
+                let request = ureq::post(&format!(
+                    "{}/printer/print/pause",
+                    url.trim_end_matches('/')
+                ));
+                let request = match api_key_file {
+                    Some(file) => request.set("X-Api-Key", &key(file)?),
+                    None => request,
+                };
+                (request, "{}".to_string())
+            }
+        };
+        request
+            .set("Content-Type", "application/json")
+            .timeout(Duration::from_secs(10))
+            .send_string(&body)
+            .context("pause the print")?;
+        Ok(())
+    }
+}
+
+// 打印机通过控制接口的 webhook 发来的通知, 见 api.rs.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PrintEvent {
+    Started(Option<String>),
+    Layer(Option<u32>),
+    Finished,
+}
+
+// 一次打印的延时摄影: 每层一帧, 保存在 dir/<开始时间>-<名称>/.
+pub struct PrintTimelapse {
+    dir: PathBuf,
+    frames: Vec<PathBuf>,
+}
+
+impl PrintTimelapse {
+    pub fn start(config: &PrinterConfig, name: Option<&str>) -> Result<Self> {
+        let mut folder = Local::now().format("%Y-%m-%dT%H-%M-%S").to_string();
+        if let Some(name) = name {
+            let name: String = name
+                .chars()
+                .map(|c| {
+                    if c.is_alphanumeric() || c == '-' {
+                        c
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            folder = format!("{}-{}", folder, name);
+        }
+        let dir = config.dir.join(folder);
+        fs::create_dir_all(&dir).with_context(|| format!("create {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            frames: Vec::new(),
+        })
+    }
+
+    // 保存一层的画面, 返回已有的帧数. 没有层号时按顺序编号.
+    pub fn layer(&mut self, frame: &Mat, layer: Option<u32>) -> Result<usize> {
+        let number = layer.unwrap_or(self.frames.len() as u32 + 1);
+        let path = self.dir.join(format!("layer-{:05}.jpg", number));
+        if !imgcodecs::imwrite(&path.to_string_lossy(), frame, &Vector::new())? {
+            bail!("write {}", path.display());
+        }
+        self.frames.push(path);
+        Ok(self.frames.len())
+    }
+
+    pub fn frames(&self) -> Vec<PathBuf> {
+        self.frames.clone()
+    }
+
+    pub fn output(&self) -> PathBuf {
+        self.dir.join("timelapse.mp4")
+    }
+}
+
+struct Job {
+    image: Mat,
+}
+
+#[derive(Default)]
+struct Status {
+    boxes: Vec<(Rect, f64, String)>,
+    // 连续检测到失败的次数.
+    strikes: u32,
+    // 已经判定失败, 检测正常之前不再重复暂停.
+    failed: bool,
+    paused: bool,
+}
+
+pub struct PrinterMonitor {
+    jobs: SyncSender<Job>,
+    status: Arc<Mutex<Status>>,
+    interval: Duration,
+    last: Option<Instant>,
+    consecutive: u32,
+}
+
+impl PrinterMonitor {
+    pub fn new(
+        config: &PrinterConfig,
+        mut detector: Box<dyn Detector>,
+        events: EventStore,
+        workers: &ThreadConfig,
+    ) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("create {}", config.dir.display()))?;
+        let status = Arc::new(Mutex::new(Status::default()));
+        let shared = status.clone();
+        let worker_config = config.clone();
+        let jobs = threads::worker("printer", 1, workers, move |job: Job| {
+            let config = &worker_config;
+            let detections = detector.detect(&job.image)?;
+            let boxes: Vec<(Rect, f64, String)> = detections
+                .iter()
+                .filter(|d| d.score >= config.min_score)
+                .filter(|d| config.class.map_or(true, |class| d.class == class))
+                .map(|d| (d.rect, d.score, detector.label(d.class)))
+                .collect();
+            let failure = {
+                let mut status = shared.lock().unwrap();
+                status.strikes = if boxes.is_empty() {
+                    0
+                } else {
+                    status.strikes + 1
+                };
+                if status.strikes == 0 {
+                    status.failed = false;
+                    status.paused = false;
+                }
+                let failure = !status.failed && status.strikes >= config.consecutive;
+                if failure {
+                    status.failed = true;
+                }
+                let best = boxes
+                    .iter()
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .map(|(_, score, label)| (*score, label.clone()));
+                status.boxes = boxes;
+                failure.then_some(best).flatten()
+            };
+            let Some((score, label)) = failure else {
+                return Ok(());
+            };
+            let snapshot = config.dir.join(format!(
+                "failure-{}.jpg",
+                Local::now().format("%Y-%m-%dT%H-%M-%S")
+            ));
+            let snapshot =
+                match imgcodecs::imwrite(&snapshot.to_string_lossy(), &job.image, &Vector::new()) {
+                    Ok(true) => Some(snapshot.display().to_string()),
+                    _ => None,
+                };
+            let paused = match (&config.api, config.pause) {
+                (Some(api), true) => match api.pause() {
+                    Ok(()) => true,
+                    Err(err) => {
+                        eprintln!("printer: {:?}", err);
+                        false
+                    }
+                },
+                _ => false,
+            };
+            shared.lock().unwrap().paused = paused;
+            events.log(
+                "print-failure",
+                json!({
+                    "label": label,
+                    "score": score,
+                    "paused": paused,
+                    "snapshot": snapshot,
+                }),
+            );
+            Ok(())
+        })?;
+        Ok(Self {
+            jobs,
+            status,
+            interval: Duration::from_secs_f64(config.interval.max(0.1)),
+            last: None,
+            consecutive: config.consecutive,
+        })
+    }
+}
+
+impl FrameProcessor for PrinterMonitor {
+    fn name(&self) -> &str {
+        "printer"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        match self.jobs.try_send(Job {
+            image: frame.try_clone()?,
+        }) {
+            Ok(()) => self.last = Some(Instant::now()),
+            Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => bail!("printer thread exited"),
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 检测框和状态: 怀疑失败时显示次数, 判定失败后显示是否已经暂停.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let status = self.status.lock().unwrap();
+        let red = Scalar::new(0., 0., 255., 0.);
+        for (rect, score, label) in &status.boxes {
+            imgproc::rectangle(frame, *rect, red, 2, LINE_AA, 0)?;
+            imgproc::put_text(
+                frame,
+                &format!("{} {:.2}", label, score),
+                Point::new(rect.x, rect.y - 6),
+                FONT_HERSHEY_SIMPLEX,
+                0.5,
+                red,
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+        let text = match (status.failed, status.paused) {
+            (true, true) => "Print failure: paused".to_string(),
+            (true, false) => "Print failure".to_string(),
+            _ if status.strikes > 0 => {
+                format!("Possible failure {}/{}", status.strikes, self.consecutive)
+            }
+            _ => return Ok(()),
+        };
+        imgproc::put_text(
+            frame,
+            &text,
+            Point::new(20, 40),
+            FONT_HERSHEY_SIMPLEX,
+            1.0,
+            red,
+            2,
+            LINE_AA,
+            false,
+        )?;
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.status.lock().unwrap().boxes.len())
+    }
+}
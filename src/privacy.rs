@@ -0,0 +1,128 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{Mat, Scalar},
+    prelude::*,
+};
+use serde_json::json;
+
+pub const AUDIT_FILE: &str = "audit.jsonl";
+
+// 隐私快门: 关闭时所有输出 (录制, 路由, 推流, 缩略图) 只收到黑帧, 本机预览不受影响.
+static SHUTTER: AtomicBool = AtomicBool::new(false);
+// 审计日志, 打开之前不记录.
+static AUDIT: Mutex<Option<File>> = Mutex::new(None);
+
+// 审计日志每行一个 JSON 对象 {"time", "activity", "active", "detail"}, 只追加写入.
+pub fn open_audit(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("open audit log {}", path.display()))?;
+    *AUDIT.lock().unwrap() = Some(file);
+    Ok(())
+}
+
+// activity 为 "capture", "recording", "stream" 或 "shutter".
+pub fn audit(activity: &str, active: bool, detail: &str) {
+    let Some(file) = &mut *AUDIT.lock().unwrap() else {
+        return;
+    };
+    let line = json!({
+        "time": Local::now().to_rfc3339(),
+        "activity": activity,
+        "active": active,
+        "detail": detail,
+    });
+    if let Err(err) = writeln!(file, "{}", line) {
+        eprintln!("write audit log: {:?}", err);
+    }
+}
+
+pub fn shutter() -> bool {
+    SHUTTER.load(Ordering::SeqCst)
+}
+
+pub fn set_shutter(closed: bool) {
+    if SHUTTER.swap(closed, Ordering::SeqCst) != closed {
+        audit("shutter", closed, "");
+    }
+}
+
+// 输出使用的帧: 快门关闭时为同样大小的黑帧, blank 在调用之间复用.
+pub fn output<'a>(frame: &'a Mat, blank: &'a mut Mat) -> Result<&'a Mat> {
+    masked(frame, blank, shutter())
+}
+
+fn masked<'a>(frame: &'a Mat, blank: &'a mut Mat, closed: bool) -> Result<&'a Mat> {
+    if !closed {
+        return Ok(frame);
+    }
+    if blank.size()? != frame.size()? || blank.typ() != frame.typ() {
+        *blank = Mat::new_size_with_default(frame.size()?, frame.typ(), Scalar::all(0.))?;
+    }
+    Ok(blank)
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{self, CV_8UC3, CV_8UC4};
+
+    use super::*;
+
+    fn solid(typ: i32) -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, typ, Scalar::all(90.)).unwrap()
+    }
+
+    // 快门关闭时输出同样大小和类型的黑帧, 尺寸或类型改变时重新创建.
+    #[test]
+    fn masks_frames() {
+        let frame = solid(CV_8UC3);
+        let mut blank = Mat::default();
+        let output = masked(&frame, &mut blank, false).unwrap();
+        assert_eq!(output.data(), frame.data());
+
+        let output = masked(&frame, &mut blank, true).unwrap();
+        assert_eq!(
+            (output.size().unwrap(), output.typ()),
+            (frame.size().unwrap(), CV_8UC3)
+        );
+        let level = core::norm(output, core::NORM_INF, &core::no_array()).unwrap();
+        assert_eq!(level, 0.0);
+
+        let frame = solid(CV_8UC4);
+        let output = masked(&frame, &mut blank, true).unwrap();
+        assert_eq!(output.typ(), CV_8UC4);
+    }
+
+    #[test]
+    fn writes_audit_log() {
+        let path = std::env::temp_dir().join(format!("privacy-audit-{}.jsonl", std::process::id()));
+        open_audit(&path).unwrap();
+        audit("recording", true, "privacy-test");
+        // 其他测试也可能写入审计日志, 关闭后只检查这一行.
+        *AUDIT.lock().unwrap() = None;
+        let text = std::fs::read_to_string(&path).unwrap();
+        let line: serde_json::Value = text
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .find(|line: &serde_json::Value| line["detail"] == "privacy-test")
+            .unwrap();
+        assert_eq!(line["activity"], "recording");
+        assert_eq!(line["active"], true);
+        std::fs::remove_file(&path).unwrap();
+        assert!(open_audit(path.join("missing")).is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::ar::Intrinsics;
+
+const PROFILE_FILE: &str = "profile.toml";
+
+// 每个摄像头各自的校准数据, 保存在 <profiles_dir>/<name>/ 目录下.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CameraProfile {
+    // BGR 仿射校色矩阵 (3x4), 由色卡校准得到.
+    pub color_matrix: Option<[[f64; 4]; 3]>,
+    // 点击中性灰得到的 BGR 白平衡增益.
+    pub white_balance: Option<[f64; 3]>,
+    // 暗帧校准找到的坏点坐标 (x, y).
+    pub hot_pixels: Vec<[i32; 2]>,
+    // 是否启用平场校正, 参考帧单独保存为 flat-field.png.
+    pub flat_field: bool,
+    // 显微测量的比例, 由标准测微尺标定.
+    pub um_per_px: Option<f64>,
+    // 速度估计用的场景比例 (米/像素).
+    pub meters_per_px: Option<f64>,
+    // 相机内参, AR 叠加用来求标记的位姿.
+    pub intrinsics: Option<Intrinsics>,
+}
+
+impl CameraProfile {
+    pub fn dir(profiles_dir: &Path, camera: &str) -> PathBuf {
+        profiles_dir.join(camera)
+    }
+
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(PROFILE_FILE);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("read camera profile {}", path.display()))?;
+        toml::from_str(&text).with_context(|| format!("parse camera profile {}", path.display()))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<()> {
+        fs::create_dir_all(dir)?;
+        let path = dir.join(PROFILE_FILE);
+        fs::write(&path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("write camera profile {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每个摄像头一个目录, 没有保存过时使用默认值.
+    #[test]
+    fn save_and_load() {
+        let profiles = std::env::temp_dir().join(format!("profile-test-{}", std::process::id()));
+        let dir = CameraProfile::dir(&profiles, "usb-0");
+        assert!(CameraProfile::load(&dir).unwrap().color_matrix.is_none());
+        let matrix = [
+            [1.1, 0.0, 0.0, -5.0],
+            [0.0, 0.95, 0.0, 3.0],
+            [0.0, 0.0, 0.9, 8.5],
+        ];
+        let profile = CameraProfile {
+            color_matrix: Some(matrix),
+            ..CameraProfile::default()
+        };
+        profile.save(&dir).unwrap();
+        assert_eq!(
+            CameraProfile::load(&dir).unwrap().color_matrix,
+            Some(matrix)
+        );
+        assert!(CameraProfile::load(&CameraProfile::dir(&profiles, "usb-1"))
+            .unwrap()
+            .color_matrix
+            .is_none());
+        fs::write(dir.join(PROFILE_FILE), "color_matrix = 3").unwrap();
+        assert!(CameraProfile::load(&dir).is_err());
+        fs::remove_dir_all(profiles).unwrap();
+    }
+}
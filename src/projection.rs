@@ -0,0 +1,257 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use opencv::{
+    calib3d,
+    core::{self, Mat, Point, Point2f, Scalar, Size, TermCriteria, Vector, CV_8UC1},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc::{self, COLOR_BGR2GRAY, INTER_LINEAR, LINE_8},
+    prelude::*,
+    video,
+    videoio::{self, VideoCapture},
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+// 跟踪的特征点少于这个数时在平面内重新检测.
+const MIN_FEATURES: usize = 20;
+
+// 平面投影: 把图片或视频按单应性变换贴到画面中的一个平面上, 例如预览招牌效果或替换录像中显示器的内容.
+// 平面由四个角点确定, 跟踪平面内的特征点, 摄像头移动时角点随之移动.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectionConfig {
+    pub enabled: bool,
+    // 投影的图片或视频, 视频循环播放.
+    pub content: Option<PathBuf>,
+    // 平面的四个角, 取值为画面宽高的比例, 顺序为内容的左上, 右上, 右下, 左下.
+    // 为空时在界面上点选.
+    pub corners: Vec<[f64; 2]>,
+    // 跟踪平面的移动, 关闭时角点固定在画面上.
+    pub track: bool,
+    // 内容的不透明度 0..1.
+    pub opacity: f64,
+    // 平面内跟踪的最多特征点数.
+    pub features: i32,
+}
+
+impl Default for ProjectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            content: None,
+            corners: Vec::new(),
+            track: true,
+            opacity: 1.0,
+            features: 200,
+        }
+    }
+}
+
+enum Content {
+    Image(Mat),
+    // 每处理一帧画面播放一帧, 播放到结尾后从头循环.
+    Clip(VideoCapture, Mat),
+}
+
+impl Content {
+    fn open(path: &Path) -> Result<Self> {
+        let path = path.to_string_lossy();
+        let image = imgcodecs::imread(&path, IMREAD_COLOR)?;
+        if !image.empty() {
+            return Ok(Content::Image(image));
+        }
+        let clip = VideoCapture::from_file(&path, videoio::CAP_ANY)?;
+        if !clip.is_opened()? {
+            bail!("Unable to open projection content {}", path);
+        }
+        Ok(Content::Clip(clip, Mat::default()))
+    }
+
+    fn next(&mut self) -> Result<&Mat> {
+        Ok(match self {
+            Content::Image(image) => image,
+            Content::Clip(clip, frame) => {
+                if !clip.read(frame)? || frame.empty() {
+                    clip.set(videoio::CAP_PROP_POS_FRAMES, 0.0)?;
+                    clip.read(frame)?;
+                }
+                frame
+            }
+        })
+    }
+}
+
+pub struct PlanarProjection {
+    content: Content,
+    // 比例坐标的角点, 第一帧时换算为像素.
+    initial: [Point2f; 4],
+    corners: Option<Vector<Point2f>>,
+    track: bool,
+    opacity: f64,
+    features: i32,
+    // 上一帧的灰度图和其中平面内的特征点.
+    previous: Mat,
+    points: Vector<Point2f>,
+    gray: Mat,
+    warped: Mat,
+    blended: Mat,
+    mask: Mat,
+}
+
+impl PlanarProjection {
+    pub fn new(config: &ProjectionConfig, content: &Path, corners: &[[f64; 2]]) -> Result<Self> {
+        let [a, b, c, d] = corners else {
+            bail!(
+                "the projection surface needs 4 corners, got {}",
+                corners.len()
+            );
+        };
+        let point = |[x, y]: [f64; 2]| Point2f::new(x as f32, y as f32);
+        Ok(Self {
+            content: Content::open(content)?,
+            initial: [point(*a), point(*b), point(*c), point(*d)],
+            corners: None,
+            track: config.track,
+            opacity: config.opacity.clamp(0.0, 1.0),
+            features: config.features.max(MIN_FEATURES as i32),
+            previous: Mat::default(),
+            points: Vector::new(),
+            gray: Mat::default(),
+            warped: Mat::default(),
+            blended: Mat::default(),
+            mask: Mat::default(),
+        })
+    }
+
+    // 画面中平面区域的掩码.
+    fn fill_mask(&mut self, size: Size, corners: &Vector<Point2f>) -> Result<()> {
+        self.mask = Mat::zeros_size(size, CV_8UC1)?.to_mat()?;
+        let polygon: Vector<Point> = corners
+            .iter()
+            .map(|p| Point::new(p.x.round() as i32, p.y.round() as i32))
+            .collect();
+        imgproc::fill_convex_poly(&mut self.mask, &polygon, Scalar::all(255.0), LINE_8, 0)?;
+        Ok(())
+    }
+
+    // 用前后两帧特征点的单应性变换移动角点. 特征点不够时角点不动.
+    fn track(&mut self, frame: &Mat, corners: &mut Vector<Point2f>) -> Result<()> {
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        if !self.previous.empty() && !self.points.is_empty() {
+            let mut next = Vector::<Point2f>::new();
+            let mut status = Vector::<u8>::new();
+            let mut error = Vector::<f32>::new();
+            video::calc_optical_flow_pyr_lk(
+                &self.previous,
+                &self.gray,
+                &self.points,
+                &mut next,
+                &mut status,
+                &mut error,
+                Size::new(21, 21),
+                3,
+                TermCriteria::new(core::TermCriteria_COUNT + core::TermCriteria_EPS, 30, 0.01)?,
+                0,
+                1e-4,
+            )?;
+            let (mut from, mut to) = (Vector::<Point2f>::new(), Vector::<Point2f>::new());
+            for ((found, a), b) in status.iter().zip(self.points.iter()).zip(next.iter()) {
+                if found == 1 {
+                    from.push(a);
+                    to.push(b);
+                }
+            }
+            self.points = to.clone();
+            if from.len() >= MIN_FEATURES / 2 {
+                let mut inliers = Mat::default();
+                let homography =
+                    calib3d::find_homography(&from, &to, &mut inliers, calib3d::RANSAC, 3.0)?;
+                if !homography.empty() {
+                    let mut moved = Vector::<Point2f>::new();
+                    core::perspective_transform(&*corners, &mut moved, &homography)?;
+                    *corners = moved;
+                }
+            }
+        }
+        if self.points.len() < MIN_FEATURES {
+            self.fill_mask(self.gray.size()?, corners)?;
+            imgproc::good_features_to_track(
+                &self.gray,
+                &mut self.points,
+                self.features,
+                0.01,
+                8.0,
+                &self.mask,
+                3,
+                false,
+                0.04,
+            )?;
+        }
+        std::mem::swap(&mut self.previous, &mut self.gray);
+        Ok(())
+    }
+}
+
+impl FrameProcessor for PlanarProjection {
+    fn name(&self) -> &str {
+        "projection"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let mut corners = match self.corners.take() {
+            Some(corners) => corners,
+            None => {
+                let (w, h) = (frame.cols() as f32, frame.rows() as f32);
+                self.initial
+                    .iter()
+                    .map(|p| Point2f::new(p.x * w, p.y * h))
+                    .collect()
+            }
+        };
+        if self.track {
+            self.track(frame, &mut corners)?;
+        }
+        let size = frame.size()?;
+        let content = self.content.next()?;
+        if content.empty() {
+            self.corners = Some(corners);
+            return Ok(());
+        }
+        let (w, h) = (content.cols() as f32, content.rows() as f32);
+        let source = Vector::from_slice(&[
+            Point2f::new(0.0, 0.0),
+            Point2f::new(w, 0.0),
+            Point2f::new(w, h),
+            Point2f::new(0.0, h),
+        ]);
+        let homography = imgproc::get_perspective_transform(&source, &corners, core::DECOMP_LU)?;
+        imgproc::warp_perspective(
+            content,
+            &mut self.warped,
+            &homography,
+            size,
+            INTER_LINEAR,
+            core::BORDER_CONSTANT,
+            Scalar::default(),
+        )?;
+        self.fill_mask(size, &corners)?;
+        if self.opacity < 1.0 {
+            core::add_weighted(
+                &*frame,
+                1.0 - self.opacity,
+                &self.warped,
+                self.opacity,
+                0.0,
+                &mut self.blended,
+                -1,
+            )?;
+            self.blended.copy_to_masked(frame, &self.mask)?;
+        } else {
+            self.warped.copy_to_masked(frame, &self.mask)?;
+        }
+        self.corners = Some(corners);
+        Ok(())
+    }
+}
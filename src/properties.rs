@@ -0,0 +1,268 @@
+// 性质测试: 用随机的帧尺寸, 操作顺序, 消费者停顿和关闭顺序检查帧缓冲区和线程间的帧传递,
+// 不能 panic, 缓冲区数量有上限, 线程不能互相等待而卡住.
+use std::{
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use opencv::{
+    core::{Mat, Scalar, CV_8UC3},
+    prelude::*,
+};
+use proptest::{collection::vec, prelude::*};
+
+use crate::{
+    feed::Frame,
+    frames::{FrameBuffer, FrameSlot, POOL_SIZE},
+    stream::{JpegFeed, StreamConfig},
+    timeshift::{TimeShift, TimeShiftConfig},
+};
+
+// 超过该时间还没有结束的线程认为已死锁.
+const DEADLINE: Duration = Duration::from_secs(10);
+
+fn image(width: i32, height: i32, value: u8) -> Mat {
+    Mat::new_rows_cols_with_default(height, width, CV_8UC3, Scalar::all(value as f64)).unwrap()
+}
+
+fn size() -> impl Strategy<Value = (i32, i32)> {
+    (1..96i32, 1..72i32)
+}
+
+#[derive(Debug, Clone)]
+enum SlotOp {
+    Publish(i32, i32),
+    Take,
+    Recycle,
+}
+
+fn slot_op() -> impl Strategy<Value = SlotOp> {
+    prop_oneof![
+        size().prop_map(|(width, height)| SlotOp::Publish(width, height)),
+        Just(SlotOp::Take),
+        Just(SlotOp::Recycle),
+    ]
+}
+
+#[derive(Debug, Clone)]
+enum ShiftOp {
+    Push(i32, i32),
+    Pause,
+    Live,
+    Step(i64),
+    Seek(f64),
+    Mark,
+    ClearLoop,
+    Speed(f64),
+    Show,
+}
+
+fn shift_op() -> impl Strategy<Value = ShiftOp> {
+    prop_oneof![
+        4 => size().prop_map(|(width, height)| ShiftOp::Push(width, height)),
+        1 => Just(ShiftOp::Pause),
+        1 => Just(ShiftOp::Live),
+        1 => (-20..20i64).prop_map(ShiftOp::Step),
+        1 => (-0.5..1.5f64).prop_map(ShiftOp::Seek),
+        1 => Just(ShiftOp::Mark),
+        1 => Just(ShiftOp::ClearLoop),
+        1 => (0.0..8.0f64).prop_map(ShiftOp::Speed),
+        2 => Just(ShiftOp::Show),
+    ]
+}
+
+// 等待线程结束, 超时时返回 false.
+fn finishes(handle: thread::JoinHandle<()>) -> bool {
+    let (done, finished) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = done.send(handle.join().is_ok());
+    });
+    finished.recv_timeout(DEADLINE).unwrap_or(false)
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    // 单线程中任意顺序的发布, 取帧和归还: 取到的总是最新发布的尺寸, 空闲缓冲区不超过上限.
+    #[test]
+    fn frame_slot_operations(ops in vec(slot_op(), 1..60)) {
+        let slot = FrameSlot::default();
+        let mut pending: Option<(u32, u32)> = None;
+        let mut shown: Vec<FrameBuffer> = Vec::new();
+        for op in ops {
+            match op {
+                SlotOp::Publish(width, height) => {
+                    slot.publish(&image(width, height, 128)).unwrap();
+                    pending = Some((width as u32, height as u32));
+                }
+                SlotOp::Take => {
+                    let taken = slot.take();
+                    prop_assert_eq!(
+                        taken.as_ref().map(|buffer| (buffer.width(), buffer.height())),
+                        pending.take()
+                    );
+                    shown.extend(taken);
+                }
+                SlotOp::Recycle => {
+                    if let Some(buffer) = shown.pop() {
+                        slot.recycle(buffer);
+                    }
+                }
+            }
+            prop_assert!(slot.pooled() <= POOL_SIZE);
+        }
+    }
+
+    // 采集线程和界面线程同时运行, 界面随机停顿. 任意一方先结束, 另一方都能继续直到结束.
+    #[test]
+    fn frame_slot_threads(
+        sizes in vec(size(), 1..40),
+        stalls in vec(0..4u64, 1..40),
+        consumer_first in any::<bool>(),
+    ) {
+        let slot = FrameSlot::default();
+        let frames = sizes.len();
+        let producer = {
+            let slot = slot.clone();
+            thread::spawn(move || {
+                for (width, height) in sizes {
+                    slot.publish(&image(width, height, 64)).unwrap();
+                }
+            })
+        };
+        let consumer = {
+            let slot = slot.clone();
+            thread::spawn(move || {
+                // 先结束的界面线程只取一部分帧.
+                let takes = if consumer_first { stalls.len() / 2 } else { frames + stalls.len() };
+                for stall in stalls.iter().cycle().take(takes) {
+                    if let Some(buffer) = slot.take() {
+                        assert!(buffer.width() > 0 && buffer.height() > 0);
+                        thread::sleep(Duration::from_millis(*stall));
+                        slot.recycle(buffer);
+                    }
+                }
+            })
+        };
+        let (first, second) = if consumer_first { (consumer, producer) } else { (producer, consumer) };
+        prop_assert!(finishes(first));
+        prop_assert!(finishes(second));
+        prop_assert!(slot.pooled() <= POOL_SIZE);
+        // 两个线程都结束后最多还剩一帧没有显示.
+        slot.take();
+        prop_assert!(slot.take().is_none());
+    }
+
+    // 时移环形缓存: 帧数不超过容量, 暂停时总能取到帧, 时间线位置在范围内.
+    #[test]
+    fn timeshift_ring(
+        seconds in 0.0..2.0f64,
+        fps in 1.0..30.0f64,
+        ops in vec(shift_op(), 1..80),
+    ) {
+        let config = TimeShiftConfig { seconds, quality: 50 };
+        let capacity = (seconds * fps).round() as usize;
+        let mut shift = TimeShift::new(&config, fps);
+        for op in ops {
+            match op {
+                ShiftOp::Push(width, height) => shift.push(&image(width, height, 200)).unwrap(),
+                ShiftOp::Pause => shift.pause(),
+                ShiftOp::Live => shift.live(),
+                ShiftOp::Step(frames) => shift.step(frames),
+                ShiftOp::Seek(fraction) => shift.seek(fraction),
+                ShiftOp::Mark => {
+                    shift.mark();
+                }
+                ShiftOp::ClearLoop => shift.clear_loop(),
+                ShiftOp::Speed(speed) => shift.set_speed(speed),
+                ShiftOp::Show => {
+                    let paused = shift.is_paused();
+                    let shown = shift.frame().unwrap();
+                    prop_assert_eq!(shown.is_some(), paused);
+                    if let Some(shown) = shown {
+                        prop_assert!(!shown.empty());
+                    }
+                }
+            }
+            prop_assert!(shift.len() <= capacity);
+            let (fraction, behind) = shift.timeline();
+            prop_assert!((0.0..=1.0).contains(&fraction));
+            prop_assert!(behind >= 0.0);
+            let (a, b) = shift.marks();
+            prop_assert!((-1.0..=1.0).contains(&a) && (-1.0..=1.0).contains(&b));
+        }
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    // 编码线程通过容量为 1 的通道接收帧: 客户端随机停顿和断开, 推流随时关闭,
+    // 等待不超过超时, 断开后不残留客户端计数, 订阅期间推送的帧一定能收到.
+    #[test]
+    fn jpeg_feed_channel(
+        (width, height) in size(),
+        scale in proptest::option::of(8..64i32),
+        clients in vec(vec(0..4u64, 1..10), 0..4),
+        pushes in 1..20usize,
+        feed_first in any::<bool>(),
+    ) {
+        let config = StreamConfig {
+            addr: None,
+            quality: 50,
+            fps: 1000.0,
+            width: scale,
+            ..StreamConfig::default()
+        };
+        let mut feed = JpegFeed::new("test-stream", &config).unwrap();
+        let subscriber = feed.subscriber();
+
+        // 订阅之后推送的第一帧一定被编码.
+        let mut probe = subscriber.subscribe();
+        let picture = image(width, height, 32);
+        feed.push(&Frame { index: 0, time: Local::now(), image: &picture });
+        prop_assert!(matches!(probe.next(DEADLINE), Ok(Some(_))));
+        drop(probe);
+
+        let timeout = Duration::from_millis(20);
+        let handles: Vec<_> = clients
+            .into_iter()
+            .map(|stalls| {
+                let mut subscription = subscriber.subscribe();
+                thread::spawn(move || {
+                    for stall in stalls {
+                        let start = Instant::now();
+                        let _ = subscription.next(timeout);
+                        assert!(start.elapsed() < timeout + Duration::from_secs(1));
+                        thread::sleep(Duration::from_millis(stall));
+                    }
+                })
+            })
+            .collect();
+        for index in 0..pushes {
+            let picture = image(width, height, index as u8);
+            feed.push(&Frame { index: index as u64, time: Local::now(), image: &picture });
+        }
+        // 关闭推流时编码线程结束, 等待中的客户端在超时后返回.
+        if feed_first {
+            drop(feed);
+            for handle in handles {
+                prop_assert!(finishes(handle));
+            }
+        } else {
+            for handle in handles {
+                prop_assert!(finishes(handle));
+            }
+            prop_assert_eq!(feed.clients(), 0);
+            drop(feed);
+        }
+        // 推流关闭后, 客户端取完最后一帧就收到关闭, 不会一直等待新帧.
+        let mut late = subscriber.subscribe();
+        let closed = (0..2).any(|_| late.next(DEADLINE).is_err());
+        prop_assert!(closed);
+        drop(late);
+        prop_assert_eq!(subscriber.clients(), 0);
+    }
+}
@@ -0,0 +1,385 @@
+use std::{
+    collections::VecDeque,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, Vector},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{detect::Detector, events::EventStore, metrics, pipeline::FrameProcessor, zones::Zone};
+
+// 排队长度预设: 统计区域内的人数, 定时写入 CSV 和 metrics,
+// 人数持续超过阈值一段时间时提醒.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    pub enabled: bool,
+    // 排队区域, 为 [[zones]] 中的名称. 也可以在控制面板中画出.
+    pub zone: Option<String>,
+    // 每隔多少帧运行一次行人检测.
+    pub detect_every: u32,
+    // 每隔多少秒写入一行 CSV, 为这段时间的平均和最大人数.
+    pub interval: f64,
+    pub csv: PathBuf,
+    // 提醒以 JSON POST 到这些地址, 例如 ntfy 或 Home Assistant 的 webhook.
+    pub alert_urls: Vec<String>,
+    pub alerts: Vec<QueueAlert>,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            zone: None,
+            detect_every: 3,
+            interval: 10.0,
+            csv: PathBuf::from("queue.csv"),
+            alert_urls: Vec::new(),
+            alerts: Vec::new(),
+        }
+    }
+}
+
+// 排队人数超过 above 持续 seconds 秒时提醒一次, 人数回落后重新待命.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct QueueAlert {
+    pub name: String,
+    pub above: usize,
+    pub seconds: f64,
+}
+
+// 一个提醒的状态, 时间为秒.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Waiting {
+    since: Option<f64>,
+    fired: bool,
+}
+
+impl Waiting {
+    // 返回 true 表示这次需要提醒.
+    pub fn update(&mut self, length: usize, now: f64, alert: &QueueAlert) -> bool {
+        if length <= alert.above {
+            *self = Waiting::default();
+            return false;
+        }
+        let since = *self.since.get_or_insert(now);
+        if self.fired || now - since < alert.seconds {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+
+    pub fn active(&self) -> bool {
+        self.fired
+    }
+}
+
+pub struct QueueLength {
+    config: QueueConfig,
+    zone: Zone,
+    detector: Box<dyn Detector>,
+    events: EventStore,
+    started: Instant,
+    frames: u64,
+    // 最近一次检测到的区域内的人.
+    people: Vec<Rect>,
+    waiting: Vec<Waiting>,
+    // 当前 CSV 间隔内的采样.
+    samples: Vec<usize>,
+    last_row: Instant,
+    // 画面上曲线的历史, 每行 CSV 一个点.
+    history: VecDeque<f64>,
+}
+
+impl QueueLength {
+    pub fn new(
+        config: &QueueConfig,
+        zone: Zone,
+        detector: Box<dyn Detector>,
+        events: EventStore,
+    ) -> Result<Self> {
+        if zone.points.len() < 3 {
+            bail!("the queue zone needs at least 3 points");
+        }
+        Ok(Self {
+            config: config.clone(),
+            zone,
+            detector,
+            events,
+            started: Instant::now(),
+            frames: 0,
+            people: Vec::new(),
+            waiting: vec![Waiting::default(); config.alerts.len()],
+            samples: Vec::new(),
+            last_row: Instant::now(),
+            history: VecDeque::new(),
+        })
+    }
+
+    // 按 [queue] zone 在 [[zones]] 中查找区域.
+    pub fn zone(config: &QueueConfig, zones: &[Zone]) -> Result<Zone> {
+        let Some(name) = &config.zone else {
+            bail!("draw the queue zone or set queue.zone to one of [[zones]]");
+        };
+        zones
+            .iter()
+            .find(|zone| &zone.name == name)
+            .cloned()
+            .with_context(|| format!("queue uses unknown zone {:?}", name))
+    }
+
+    fn write_row(&mut self) -> Result<()> {
+        if self.samples.is_empty() {
+            return Ok(());
+        }
+        let average = self.samples.iter().sum::<usize>() as f64 / self.samples.len() as f64;
+        let max = self.samples.iter().copied().max().unwrap_or(0);
+        self.samples.clear();
+        metrics::gauge("queue_length", "People waiting in the queue zone", average);
+        self.history.push_back(average);
+        while self.history.len() > 60 {
+            self.history.pop_front();
+        }
+        let path = &self.config.csv;
+        let new = !path.exists();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("open {}", path.display()))?;
+        if new {
+            writeln!(file, "time,zone,average,max")?;
+        }
+        writeln!(
+            file,
+            "{},{},{:.2},{}",
+            Local::now().to_rfc3339(),
+            self.zone.name,
+            average,
+            max
+        )?;
+        Ok(())
+    }
+
+    fn alert(&self, alert: &QueueAlert, length: usize) {
+        self.events.log(
+            "queue",
+            json!({
+                "alert": alert.name,
+                "zone": self.zone.name,
+                "length": length,
+                "above": alert.above,
+                "seconds": alert.seconds,
+            }),
+        );
+        if self.config.alert_urls.is_empty() {
+            return;
+        }
+        let body = json!({
+            "title": alert.name,
+            "message": format!(
+                "{} people waiting in {} for more than {:.0} s",
+                length, self.zone.name, alert.seconds
+            ),
+            "time": Local::now().to_rfc3339(),
+            "length": length,
+        })
+        .to_string();
+        let urls = self.config.alert_urls.clone();
+        // 发送提醒不阻塞采集.
+        let _ = thread::Builder::new()
+            .name("queue-alert".to_string())
+            .spawn(move || {
+                for url in urls {
+                    if let Err(err) = ureq::post(&url)
+                        .set("Content-Type", "application/json")
+                        .send_string(&body)
+                    {
+                        eprintln!("queue alert to {}: {:?}", url, err);
+                    }
+                }
+            });
+    }
+}
+
+impl Drop for QueueLength {
+    fn drop(&mut self) {
+        if let Err(err) = self.write_row() {
+            eprintln!("queue: {:?}", err);
+        }
+    }
+}
+
+impl FrameProcessor for QueueLength {
+    fn name(&self) -> &str {
+        "queue"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        if self.frames % self.config.detect_every.max(1) as u64 != 0 {
+            self.frames += 1;
+            return Ok(());
+        }
+        self.frames += 1;
+        // 用目标底边中点判断是否在区域内, 站在区域边上的人按脚的位置算.
+        self.people = self
+            .detector
+            .detect(frame)?
+            .into_iter()
+            .map(|d| d.rect)
+            .filter(|rect| {
+                let x = (rect.x + rect.width / 2) as f64 / cols as f64;
+                let y = (rect.y + rect.height) as f64 / rows as f64;
+                self.zone.contains(x, y)
+            })
+            .collect();
+        let length = self.people.len();
+        self.samples.push(length);
+        let now = self.started.elapsed().as_secs_f64();
+        for index in 0..self.config.alerts.len() {
+            let alert = &self.config.alerts[index];
+            if self.waiting[index].update(length, now, alert) {
+                self.alert(alert, length);
+            }
+        }
+        if self.last_row.elapsed() >= Duration::from_secs_f64(self.config.interval.max(1.0)) {
+            self.last_row = Instant::now();
+            self.write_row()?;
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 区域轮廓和人数, 有提醒处于触发状态时为红色; 左下角为平均人数的曲线.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        let color = if self.waiting.iter().any(Waiting::active) {
+            Scalar::new(0., 0., 255., 0.)
+        } else {
+            Scalar::new(255., 128., 0., 0.)
+        };
+        let points = self.zone.pixels(cols, rows);
+        let mut polygons = Vector::<Vector<Point>>::new();
+        polygons.push(points.clone());
+        imgproc::polylines(frame, &polygons, true, color, 2, LINE_AA, 0)?;
+        for rect in &self.people {
+            imgproc::rectangle(frame, *rect, color, 2, LINE_AA, 0)?;
+        }
+        let origin = points.get(0)?;
+        imgproc::put_text(
+            frame,
+            &format!("{}: {} waiting", self.zone.name, self.people.len()),
+            Point::new(origin.x + 4, origin.y + 20),
+            FONT_HERSHEY_SIMPLEX,
+            0.6,
+            color,
+            2,
+            LINE_AA,
+            false,
+        )?;
+        if self.history.len() < 2 {
+            return Ok(());
+        }
+        let (width, height) = (cols / 4, rows / 6);
+        let top = rows - height - 10;
+        let max = self.history.iter().copied().fold(1.0, f64::max);
+        let line: Vector<Point> = self
+            .history
+            .iter()
+            .enumerate()
+            .map(|(i, value)| {
+                let x = 10 + i as i32 * width / (self.history.len() as i32 - 1);
+                let y = top + height - (value / max * height as f64) as i32;
+                Point::new(x, y)
+            })
+            .collect();
+        let mut lines = Vector::<Vector<Point>>::new();
+        lines.push(line);
+        imgproc::rectangle(
+            frame,
+            Rect::new(10, top, width, height),
+            Scalar::all(200.0),
+            1,
+            LINE_AA,
+            0,
+        )?;
+        imgproc::polylines(frame, &lines, false, color, 2, LINE_AA, 0)?;
+        Ok(())
+    }
+
+    fn detections(&self) -> Option<usize> {
+        Some(self.people.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 人数超过阈值持续足够久才提醒, 只提醒一次, 回落后重新计时.
+    #[test]
+    fn alert_after_waiting() {
+        let alert = QueueAlert {
+            name: "long queue".to_string(),
+            above: 3,
+            seconds: 10.0,
+        };
+        let mut waiting = Waiting::default();
+        let steps = [
+            (4, 0.0, false),
+            (5, 9.0, false),
+            (4, 10.0, true),
+            (6, 20.0, false),
+            (3, 21.0, false),
+            (4, 22.0, false),
+            (4, 31.0, false),
+            (4, 32.0, true),
+        ];
+        for (length, now, fire) in steps {
+            assert_eq!(waiting.update(length, now, &alert), fire, "at {}", now);
+            assert_eq!(waiting.active(), (10.0..21.0).contains(&now) || now >= 32.0);
+        }
+    }
+
+    #[test]
+    fn zone_by_name() {
+        let zones = vec![
+            Zone {
+                name: "door".to_string(),
+                points: vec![[0.0, 0.0], [1.0, 0.0], [1.0, 1.0]],
+            },
+            Zone {
+                name: "counter".to_string(),
+                points: vec![[0.5, 0.5], [1.0, 0.5], [1.0, 1.0], [0.5, 1.0]],
+            },
+        ];
+        let config = QueueConfig {
+            zone: Some("counter".to_string()),
+            ..QueueConfig::default()
+        };
+        assert_eq!(QueueLength::zone(&config, &zones).unwrap().points.len(), 4);
+        let config = QueueConfig {
+            zone: Some("exit".to_string()),
+            ..QueueConfig::default()
+        };
+        assert!(QueueLength::zone(&config, &zones).is_err());
+        assert!(QueueLength::zone(&QueueConfig::default(), &zones).is_err());
+    }
+}
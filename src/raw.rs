@@ -0,0 +1,201 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{Mat, Scalar, Vector, CV_16U, CV_8UC1, CV_8UC3},
+    imgcodecs,
+    imgproc::{self, COLOR_GRAY2BGR},
+    prelude::*,
+    videoio::{self, VideoCapture, VideoWriter},
+};
+use serde::Deserialize;
+
+// 高位深采集 (V4L2 Y16 等), 帧以 16 位原样保留, 显示和录制使用映射后的 8 位图像.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RawConfig {
+    pub enabled: bool,
+    // 请求的像素格式 FourCC.
+    pub fourcc: String,
+    // 传感器满量程对应的数值, 低位对齐的 10/12 位数据需要相应调小.
+    pub white_level: f64,
+    // 显示映射的曝光补偿 (EV) 和 gamma.
+    pub exposure: f64,
+    pub gamma: f64,
+    // 16 位照片格式: tiff 或 png.
+    pub format: String,
+}
+
+impl Default for RawConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            fourcc: "Y16 ".to_string(),
+            white_level: 65535.0,
+            exposure: 0.0,
+            gamma: 2.2,
+            format: "tiff".to_string(),
+        }
+    }
+}
+
+// 要求后端输出未经转换的高位深数据, 返回是否成功.
+pub fn configure(camera: &mut VideoCapture, config: &RawConfig) -> Result<bool> {
+    let mut code = config.fourcc.chars().chain(std::iter::repeat(' '));
+    let mut next = || code.next().unwrap();
+    let fourcc = VideoWriter::fourcc(next(), next(), next(), next())?;
+    let ok = camera.set(videoio::CAP_PROP_FOURCC, fourcc as f64)?
+        && camera.set(videoio::CAP_PROP_CONVERT_RGB, 0.0)?;
+    Ok(ok)
+}
+
+// 16 位到 8 位的查找表映射: ((v / white) * 2^ev) ^ (1 / gamma).
+pub struct DepthMapping {
+    white_level: f64,
+    lut: Vec<u8>,
+    mapped: Mat,
+}
+
+impl DepthMapping {
+    pub fn new(config: &RawConfig) -> Self {
+        let mut mapping = Self {
+            white_level: config.white_level.max(1.0),
+            lut: vec![0; 1 << 16],
+            mapped: Mat::default(),
+        };
+        mapping.set(config.exposure, config.gamma);
+        mapping
+    }
+
+    pub fn set(&mut self, exposure: f64, gamma: f64) {
+        let gain = 2f64.powf(exposure) / self.white_level;
+        let inverse = 1.0 / gamma.max(0.1);
+        for (v, out) in self.lut.iter_mut().enumerate() {
+            let linear = (v as f64 * gain).min(1.0);
+            *out = (linear.powf(inverse) * 255.0).round() as u8;
+        }
+    }
+
+    // 输出始终是 8 位 BGR.
+    pub fn apply(&mut self, raw: &Mat, out: &mut Mat) -> Result<()> {
+        if raw.depth() != CV_16U {
+            bail!("expected 16-bit frame, got depth {}", raw.depth());
+        }
+        let typ = match raw.channels() {
+            1 => CV_8UC1,
+            3 => CV_8UC3,
+            n => bail!("unsupported {} channel high bit-depth frame", n),
+        };
+        if self.mapped.size()? != raw.size()? || self.mapped.typ() != typ {
+            self.mapped = Mat::new_size_with_default(raw.size()?, typ, Scalar::all(0.))?;
+        }
+        // data_typed 只接受单通道, 这里按字节读取以同时支持多通道.
+        let src = raw.data_bytes()?;
+        let dst = self.mapped.data_bytes_mut()?;
+        for (d, s) in dst.iter_mut().zip(src.chunks_exact(2)) {
+            *d = self.lut[u16::from_ne_bytes([s[0], s[1]]) as usize];
+        }
+        if typ == CV_8UC1 {
+            imgproc::cvt_color(&self.mapped, out, COLOR_GRAY2BGR, 0)?;
+        } else {
+            self.mapped.copy_to(out)?;
+        }
+        Ok(())
+    }
+}
+
+// 保存未经映射的 16 位照片.
+pub fn save_still(raw: &Mat, config: &RawConfig) -> Result<PathBuf> {
+    let extension = if config.format == "png" {
+        "png"
+    } else {
+        "tiff"
+    };
+    let path = PathBuf::from(format!(
+        "raw-{}.{}",
+        chrono::Local::now().format("%Y-%m-%dT%H-%M-%S"),
+        extension
+    ));
+    if !imgcodecs::imwrite(&path.to_string_lossy(), raw, &Vector::new())? {
+        bail!("write {}", path.display());
+    }
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Vec3b, CV_16UC1, CV_16UC3};
+
+    use super::*;
+
+    // 低位对齐的 12 位数据, 线性映射.
+    fn config() -> RawConfig {
+        RawConfig {
+            white_level: 4095.0,
+            gamma: 1.0,
+            ..RawConfig::default()
+        }
+    }
+
+    fn raw(values: [u16; 4]) -> Mat {
+        let mut raw = Mat::new_rows_cols_with_default(2, 2, CV_16UC1, Scalar::all(0.)).unwrap();
+        raw.data_typed_mut::<u16>()
+            .unwrap()
+            .copy_from_slice(&values);
+        raw
+    }
+
+    fn mapped(mapping: &mut DepthMapping, raw: &Mat) -> Vec<u8> {
+        let mut out = Mat::default();
+        mapping.apply(raw, &mut out).unwrap();
+        assert_eq!(out.typ(), CV_8UC3);
+        (0..4)
+            .map(|i| {
+                let pixel = *out.at_2d::<Vec3b>(i / 2, i % 2).unwrap();
+                assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+                pixel[0]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn exposure_and_gamma() {
+        let mut mapping = DepthMapping::new(&config());
+        let frame = raw([0, 1024, 4095, 60000]);
+        assert_eq!(mapped(&mut mapping, &frame), [0, 64, 255, 255]);
+        // +1 EV 亮一倍.
+        mapping.set(1.0, 1.0);
+        assert_eq!(mapped(&mut mapping, &frame), [0, 128, 255, 255]);
+        // gamma 2 时四分之一亮度映射到一半.
+        mapping.set(0.0, 2.0);
+        assert_eq!(mapped(&mut mapping, &frame), [0, 128, 255, 255]);
+    }
+
+    #[test]
+    fn frame_types() {
+        let mut mapping = DepthMapping::new(&config());
+        let color =
+            Mat::new_rows_cols_with_default(2, 2, CV_16UC3, Scalar::new(4095., 0., 1024., 0.))
+                .unwrap();
+        let mut out = Mat::default();
+        mapping.apply(&color, &mut out).unwrap();
+        assert_eq!(
+            *out.at_2d::<Vec3b>(1, 1).unwrap(),
+            Vec3b::from([255, 0, 64])
+        );
+        let eight = Mat::new_rows_cols_with_default(2, 2, CV_8UC3, Scalar::all(0.)).unwrap();
+        assert!(mapping.apply(&eight, &mut out).is_err());
+    }
+
+    // 照片保存原始的 16 位数据, 不经过映射.
+    #[test]
+    fn still_keeps_16_bits() {
+        let frame = raw([0, 1024, 4095, 60000]);
+        let path = save_still(&frame, &config()).unwrap();
+        assert_eq!(path.extension().unwrap(), "tiff");
+        let saved =
+            imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_UNCHANGED).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(saved.data_typed::<u16>().unwrap(), [0, 1024, 4095, 60000]);
+    }
+}
@@ -0,0 +1,351 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Point, Rect, Scalar, Vector},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{events::EventStore, pipeline::FrameProcessor};
+
+// 颜色变化监测: 跟踪选定区域的平均颜色, 在画面上画出曲线, 越过阈值时记录事件,
+// 用于观察化学反应, 3D 打印机热床和指示灯.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReactionConfig {
+    pub enabled: bool,
+    // 监测区域 [x, y, w, h], 取值为画面宽高的比例, 也可以在控制面板中点选两个角.
+    pub roi: [f64; 4],
+    // 采样间隔 (秒) 和曲线保留的采样数.
+    pub interval: f64,
+    pub history: usize,
+    // 回到阈值另一侧超过这个值后才能再次触发, 避免在阈值附近反复记录.
+    pub hysteresis: f64,
+    pub thresholds: Vec<Threshold>,
+}
+
+impl Default for ReactionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            roi: [0.4, 0.4, 0.2, 0.2],
+            interval: 0.5,
+            history: 240,
+            hysteresis: 3.0,
+            thresholds: Vec::new(),
+        }
+    }
+}
+
+// 区域颜色的一个量. red, green, blue, saturation 和 value 为 0..255, hue 为 0..360 度,
+// delta 为和开始监测时颜色的距离 (RGB 空间).
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Hue,
+    Saturation,
+    Value,
+    Delta,
+}
+
+impl Channel {
+    // 曲线的纵轴范围.
+    fn range(self) -> f64 {
+        match self {
+            Channel::Hue => 360.0,
+            Channel::Delta => 255.0 * 3f64.sqrt(),
+            _ => 255.0,
+        }
+    }
+}
+
+// 数值从阈值一侧越到另一侧时记录 reaction 事件, above 和 below 可以同时设置.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub name: String,
+    pub channel: Channel,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+}
+
+impl Threshold {
+    fn channel_name(&self) -> String {
+        format!("{:?}", self.channel).to_lowercase()
+    }
+}
+
+// 平均颜色 (RGB) 中的一个量, baseline 为开始监测时的颜色.
+pub fn measure(channel: Channel, rgb: [f64; 3], baseline: [f64; 3]) -> f64 {
+    let [r, g, b] = rgb;
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    match channel {
+        Channel::Red => r,
+        Channel::Green => g,
+        Channel::Blue => b,
+        Channel::Value => max,
+        Channel::Saturation => {
+            if max > 0.0 {
+                (max - min) / max * 255.0
+            } else {
+                0.0
+            }
+        }
+        Channel::Hue => {
+            let chroma = max - min;
+            if chroma == 0.0 {
+                return 0.0;
+            }
+            let hue = if max == r {
+                ((g - b) / chroma).rem_euclid(6.0)
+            } else if max == g {
+                (b - r) / chroma + 2.0
+            } else {
+                (r - g) / chroma + 4.0
+            };
+            hue * 60.0
+        }
+        Channel::Delta => rgb
+            .iter()
+            .zip(baseline)
+            .map(|(a, b)| (a - b).powi(2))
+            .sum::<f64>()
+            .sqrt(),
+    }
+}
+
+// 一个阈值的触发状态: 越过阈值时触发一次, 回到另一侧超过 hysteresis 后重新待命.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Crossing {
+    above: bool,
+    below: bool,
+}
+
+impl Crossing {
+    // 返回这次越过的方向 "above" 或 "below".
+    pub fn update(
+        &mut self,
+        threshold: &Threshold,
+        value: f64,
+        hysteresis: f64,
+    ) -> Option<&'static str> {
+        let mut crossed = None;
+        if let Some(above) = threshold.above {
+            if !self.above && value > above {
+                self.above = true;
+                crossed = Some("above");
+            } else if self.above && value < above - hysteresis {
+                self.above = false;
+            }
+        }
+        if let Some(below) = threshold.below {
+            if !self.below && value < below {
+                self.below = true;
+                crossed = Some("below");
+            } else if self.below && value > below + hysteresis {
+                self.below = false;
+            }
+        }
+        crossed
+    }
+
+    pub fn active(&self) -> bool {
+        self.above || self.below
+    }
+}
+
+pub struct ReactionMonitor {
+    config: ReactionConfig,
+    roi: [f64; 4],
+    events: EventStore,
+    last: Option<Instant>,
+    baseline: Option<[f64; 3]>,
+    // 采样的平均颜色 (RGB).
+    history: VecDeque<[f64; 3]>,
+    crossings: Vec<Crossing>,
+}
+
+impl ReactionMonitor {
+    pub fn new(config: &ReactionConfig, roi: [f64; 4], events: EventStore) -> Result<Self> {
+        if roi[2] <= 0.0 || roi[3] <= 0.0 {
+            bail!("the reaction region is empty");
+        }
+        for threshold in &config.thresholds {
+            if threshold.above.is_none() && threshold.below.is_none() {
+                bail!(
+                    "reaction threshold {:?} needs above or below",
+                    threshold.name
+                );
+            }
+        }
+        Ok(Self {
+            config: config.clone(),
+            roi,
+            events,
+            last: None,
+            baseline: None,
+            history: VecDeque::new(),
+            crossings: vec![Crossing::default(); config.thresholds.len()],
+        })
+    }
+
+    fn region(&self, frame: &Mat) -> Rect {
+        let (w, h) = (frame.cols(), frame.rows());
+        let [x, y, rw, rh] = self.roi.map(|value| value.clamp(0.0, 1.0));
+        let x = ((x * w as f64) as i32).min(w - 1);
+        let y = ((y * h as f64) as i32).min(h - 1);
+        Rect::new(
+            x,
+            y,
+            ((rw * w as f64) as i32).clamp(1, w - x),
+            ((rh * h as f64) as i32).clamp(1, h - y),
+        )
+    }
+
+    // 画面左下角的曲线: 红绿蓝三条平均值曲线, 阈值用到的其他量为白色, 阈值为黄色虚线.
+    fn draw_plot(&self, frame: &mut Mat) -> Result<()> {
+        let width = (frame.cols() / 3).max(120);
+        let height = (frame.rows() / 5).max(60);
+        let plot = Rect::new(10, frame.rows() - height - 10, width, height);
+        if plot.y < 0 || plot.x + width > frame.cols() {
+            return Ok(());
+        }
+        {
+            let mut area = Mat::roi_mut(frame, plot)?;
+            let mut dimmed = Mat::default();
+            area.convert_to(&mut dimmed, -1, 0.35, 0.0)?;
+            dimmed.copy_to(&mut *area)?;
+        }
+        let capacity = self.config.history.max(2);
+        let x = |i: usize| plot.x + (i * (width - 1) as usize / (capacity - 1)) as i32;
+        let y = |value: f64, range: f64| {
+            plot.y + height - 1 - ((value / range).clamp(0.0, 1.0) * (height - 1) as f64) as i32
+        };
+        let baseline = self.baseline.unwrap_or_default();
+        let mut channels = vec![
+            (Channel::Red, Scalar::new(60., 60., 255., 0.)),
+            (Channel::Green, Scalar::new(60., 255., 60., 0.)),
+            (Channel::Blue, Scalar::new(255., 120., 60., 0.)),
+        ];
+        for threshold in &self.config.thresholds {
+            if !channels
+                .iter()
+                .any(|(channel, _)| *channel == threshold.channel)
+            {
+                channels.push((threshold.channel, Scalar::all(255.0)));
+            }
+        }
+        for (channel, color) in channels {
+            let points: Vector<Point> = self
+                .history
+                .iter()
+                .enumerate()
+                .map(|(i, rgb)| {
+                    Point::new(x(i), y(measure(channel, *rgb, baseline), channel.range()))
+                })
+                .collect();
+            let mut lines = Vector::<Vector<Point>>::new();
+            lines.push(points);
+            imgproc::polylines(frame, &lines, false, color, 1, LINE_AA, 0)?;
+        }
+        for threshold in &self.config.thresholds {
+            for level in [threshold.above, threshold.below].into_iter().flatten() {
+                let y = y(level, threshold.channel.range());
+                let mut dash = plot.x;
+                while dash < plot.x + width {
+                    let end = (dash + 6).min(plot.x + width - 1);
+                    imgproc::line(
+                        frame,
+                        Point::new(dash, y),
+                        Point::new(end, y),
+                        Scalar::new(0., 220., 255., 0.),
+                        1,
+                        LINE_AA,
+                        0,
+                    )?;
+                    dash += 12;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FrameProcessor for ReactionMonitor {
+    fn name(&self) -> &str {
+        "reaction"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let interval = Duration::from_secs_f64(self.config.interval.max(0.0));
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        let mean = core::mean(&Mat::roi(frame, self.region(frame))?, &core::no_array())?;
+        let rgb = [mean[2], mean[1], mean[0]];
+        let baseline = *self.baseline.get_or_insert(rgb);
+        self.history.push_back(rgb);
+        while self.history.len() > self.config.history.max(2) {
+            self.history.pop_front();
+        }
+        for (threshold, crossing) in self.config.thresholds.iter().zip(&mut self.crossings) {
+            let value = measure(threshold.channel, rgb, baseline);
+            if let Some(direction) = crossing.update(threshold, value, self.config.hysteresis) {
+                self.events.log(
+                    "reaction",
+                    json!({
+                        "name": threshold.name,
+                        "channel": threshold.channel_name(),
+                        "direction": direction,
+                        "value": value,
+                        "color": rgb.map(|c| c.round() as u8),
+                    }),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 有阈值处于触发状态时区域框为红色.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let region = self.region(frame);
+        let alert = self.crossings.iter().any(Crossing::active);
+        let color = if alert {
+            Scalar::new(0., 0., 255., 0.)
+        } else {
+            Scalar::new(255., 255., 255., 0.)
+        };
+        imgproc::rectangle(frame, region, color, 2, LINE_AA, 0)?;
+        if let Some([r, g, b]) = self.history.back() {
+            let swatch = Rect::new(region.x, (region.y - 22).max(0), 18, 18);
+            imgproc::rectangle(frame, swatch, Scalar::new(*b, *g, *r, 0.), -1, LINE_AA, 0)?;
+            imgproc::put_text(
+                frame,
+                &format!("{:.0} {:.0} {:.0}", r, g, b),
+                Point::new(swatch.x + 24, swatch.y + 14),
+                FONT_HERSHEY_SIMPLEX,
+                0.45,
+                color,
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+        self.draw_plot(frame)
+    }
+}
@@ -0,0 +1,263 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use opencv::{core::Size, prelude::*};
+use serde_json::json;
+
+use crate::{
+    events::EventStore,
+    feed::Frame,
+    privacy,
+    sink::{SinkConfig, SinkInfo, Sinks},
+};
+
+// Recorder::start 的选项, 与 [[sinks]] 中 file sink 的设置相同.
+#[derive(Debug, Clone)]
+pub struct RecordingOptions {
+    pub fourcc: String,
+    pub quality: Option<f64>,
+    // 输出帧率和尺寸, 不设置时与来源相同.
+    pub fps: Option<f64>,
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    // 写入完整性记录, 见 integrity 模块.
+    pub integrity: bool,
+    // 等待来源第一帧的最长时间, 需要帧的尺寸才能打开文件.
+    pub timeout: Duration,
+}
+
+impl Default for RecordingOptions {
+    fn default() -> Self {
+        Self {
+            fourcc: "mp4v".to_string(),
+            quality: None,
+            fps: None,
+            width: None,
+            height: None,
+            integrity: false,
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+// 一次录制的结果, Recorder::stop 返回.
+#[derive(Debug, Clone)]
+pub struct RecordingReport {
+    // 实际写入的文件, 路径中的时间格式已展开.
+    pub path: PathBuf,
+    pub started: DateTime<Local>,
+    pub stopped: DateTime<Local>,
+    pub duration: Duration,
+    // 交给录制的帧数, 输出帧率不同时写入文件的帧数会按比例增减.
+    pub frames: u64,
+    // 第一帧和最后一帧在 VideoFeed 中的序号, 没有帧时为 None.
+    pub first_frame: Option<u64>,
+    pub last_frame: Option<u64>,
+    // 录制期间序号不连续的帧数, 例如切换来源时.
+    pub skipped: u64,
+    // 关闭后的文件大小.
+    pub bytes: Option<u64>,
+    // 实际设置与选项不同时的提示, 例如编码不可用时换用了其他编码.
+    pub notice: Option<String>,
+    // 录制中途写入失败时的原因, 之后的帧没有写入.
+    pub error: Option<String>,
+}
+
+struct Recording {
+    sinks: Sinks,
+    path: PathBuf,
+    notice: Option<String>,
+    started: DateTime<Local>,
+    start: Instant,
+    frames: u64,
+    first: Option<u64>,
+    last: Option<u64>,
+    skipped: u64,
+    error: Option<String>,
+}
+
+impl Recording {
+    fn write(&mut self, frame: &Frame) {
+        if self.error.is_some() {
+            return;
+        }
+        self.sinks.write(frame.image);
+        if self.sinks.is_empty() {
+            self.error = Some(format!("writing {} failed", self.path.display()));
+            return;
+        }
+        if let Some(last) = self.last {
+            self.skipped += frame.index.saturating_sub(last + 1);
+        }
+        self.first.get_or_insert(frame.index);
+        self.last = Some(frame.index);
+        self.frames += 1;
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // 最近一帧的尺寸.
+    size: Option<Size>,
+    recording: Option<Recording>,
+}
+
+// 库使用者自己管理的录制, 与界面上的录制按钮和配置的 sinks 互不影响, 可以同时使用多个.
+// 由 VideoFeed::recorder 创建, 写入处理之后, 绘制 OSD 之前的帧. 被丢弃时停止录制.
+pub struct Recorder {
+    shared: Arc<(Mutex<State>, Condvar)>,
+    fps: f64,
+    events: EventStore,
+}
+
+impl Recorder {
+    // 返回 Recorder 和需要注册为帧回调的函数.
+    pub(crate) fn new(fps: f64, events: EventStore) -> (Self, impl FnMut(&Frame) + Send) {
+        let shared = Arc::new((Mutex::new(State::default()), Condvar::new()));
+        let callback = {
+            let shared = shared.clone();
+            move |frame: &Frame| {
+                let (state, updated) = &*shared;
+                let mut state = state.lock().unwrap();
+                if state.size.is_none() {
+                    updated.notify_all();
+                }
+                state.size = frame.image.size().ok();
+                if let Some(recording) = &mut state.recording {
+                    recording.write(frame);
+                }
+            }
+        };
+        (
+            Self {
+                shared,
+                fps,
+                events,
+            },
+            callback,
+        )
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.shared.0.lock().unwrap().recording.is_some()
+    }
+
+    // 开始录制到 path (可以使用 strftime 格式), 文件打开后返回, 之后的每一帧都写入.
+    pub fn start(&self, path: impl AsRef<Path>, options: &RecordingOptions) -> Result<()> {
+        let path = path.as_ref();
+        let (state, updated) = &*self.shared;
+        let size = {
+            let state = state.lock().unwrap();
+            if let Some(recording) = &state.recording {
+                bail!("already recording to {}", recording.path.display());
+            }
+            let (state, _) = updated
+                .wait_timeout_while(state, options.timeout, |state| state.size.is_none())
+                .unwrap();
+            match state.size {
+                Some(size) => size,
+                None => bail!("no frames from the source"),
+            }
+        };
+        // 打开文件需要时间, 不持有锁, 避免阻塞采集线程.
+        let mut table = toml::Table::new();
+        table.insert("path".to_string(), path.to_string_lossy().as_ref().into());
+        table.insert("fourcc".to_string(), options.fourcc.as_str().into());
+        if let Some(quality) = options.quality {
+            table.insert("quality".to_string(), quality.into());
+        }
+        table.insert("integrity".to_string(), options.integrity.into());
+        let config = SinkConfig {
+            kind: "file".to_string(),
+            name: None,
+            fps: options.fps,
+            width: options.width,
+            height: options.height,
+            options: table,
+        };
+        let info = SinkInfo {
+            fps: self.fps,
+            size,
+        };
+        let mut sinks = Sinks::single(&config, &info)?;
+        let path = sinks.paths().next().unwrap_or(path).to_path_buf();
+        let notice = sinks.messages.drain(..).next();
+
+        let mut state = state.lock().unwrap();
+        if state.recording.is_some() {
+            bail!("already recording");
+        }
+        state.recording = Some(Recording {
+            sinks,
+            path: path.clone(),
+            notice,
+            started: Local::now(),
+            start: Instant::now(),
+            frames: 0,
+            first: None,
+            last: None,
+            skipped: 0,
+            error: None,
+        });
+        drop(state);
+        privacy::audit("recording", true, &path.to_string_lossy());
+        self.events.log(
+            "recording",
+            json!({ "change": "started", "path": path.to_string_lossy() }),
+        );
+        Ok(())
+    }
+
+    // 停止录制并关闭文件, 返回这次录制的结果.
+    pub fn stop(&self) -> Result<RecordingReport> {
+        let Some(recording) = self.shared.0.lock().unwrap().recording.take() else {
+            bail!("not recording");
+        };
+        let duration = recording.start.elapsed();
+        let mut error = recording.error;
+        if let Err(err) = recording.sinks.close() {
+            error.get_or_insert(format!("{:#}", err));
+        }
+        let report = RecordingReport {
+            bytes: fs::metadata(&recording.path).map(|meta| meta.len()).ok(),
+            path: recording.path,
+            started: recording.started,
+            stopped: Local::now(),
+            duration,
+            frames: recording.frames,
+            first_frame: recording.first,
+            last_frame: recording.last,
+            skipped: recording.skipped,
+            notice: recording.notice,
+            error,
+        };
+        privacy::audit("recording", false, &report.path.to_string_lossy());
+        self.events.log(
+            "recording",
+            json!({
+                "change": "stopped",
+                "path": report.path.to_string_lossy(),
+                "frames": report.frames,
+                "seconds": report.duration.as_secs_f64(),
+                "error": report.error,
+            }),
+        );
+        Ok(report)
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.is_recording() {
+            if let Err(err) = self.stop() {
+                eprintln!("stop recording: {:?}", err);
+            }
+        }
+    }
+}
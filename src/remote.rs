@@ -0,0 +1,422 @@
+use std::sync::{mpsc::Sender, Arc, Mutex};
+#[cfg(feature = "streaming")]
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+#[cfg(feature = "streaming")]
+use serde_json::{json, Value};
+
+use crate::{
+    auth::Auth,
+    capture::Command,
+    feed::{Active, Frame},
+};
+#[cfg(feature = "streaming")]
+use crate::{
+    auth::{Listener, Role},
+    privacy,
+    stream::{JpegFeed, StreamConfig, Subscriber, Subscription},
+};
+
+// 等待客户端消息的间隔, 也是检查状态变化和发送预览的间隔.
+#[cfg(feature = "streaming")]
+const POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemoteConfig {
+    // WebSocket 监听地址, 例如 "0.0.0.0:9300", 不设置时不启动.
+    pub addr: Option<String>,
+    // 预览的宽度, 帧率和 JPEG 质量, 手机上看够用即可.
+    pub preview_width: i32,
+    pub preview_fps: f64,
+    pub quality: i32,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        Self {
+            addr: None,
+            preview_width: 320,
+            preview_fps: 5.0,
+            quality: 60,
+        }
+    }
+}
+
+// 客户端发送的 JSON 文本消息, id 原样放在回复中.
+#[cfg(feature = "streaming")]
+#[derive(Deserialize)]
+struct ClientMessage {
+    #[serde(default)]
+    id: Value,
+    #[serde(flatten)]
+    request: Request,
+}
+
+#[cfg(feature = "streaming")]
+#[derive(Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+enum Request {
+    // 开始或暂停接收预览, 只需要 viewer 权限.
+    Preview { enabled: bool },
+    Record { enabled: bool },
+    // 在本机拍一张照片, 与界面上的拍照按钮相同.
+    Snapshot,
+    Scene { name: String },
+}
+
+// 连接共享的状态.
+#[cfg(feature = "streaming")]
+#[derive(Clone)]
+struct Context {
+    scenes: Arc<Vec<String>>,
+    commands: Sender<Command>,
+    active: Arc<Mutex<Active>>,
+    connections: Arc<AtomicUsize>,
+}
+
+// 手机应用或网页使用的远程控制: WebSocket 上的 JSON 命令, 状态推送和低分辨率预览.
+#[cfg(feature = "streaming")]
+pub struct RemoteServer {
+    preview: JpegFeed,
+    _listener: Listener,
+}
+
+#[cfg(feature = "streaming")]
+impl RemoteServer {
+    // on_clients 在客户端连接和断开时以当前连接数调用. 连接需要 viewer 权限, 控制需要 operator 权限.
+    pub fn start(
+        addr: &str,
+        config: &RemoteConfig,
+        auth: &Auth,
+        scenes: Vec<String>,
+        commands: Sender<Command>,
+        active: Arc<Mutex<Active>>,
+        on_clients: impl Fn(usize) + Clone + Send + 'static,
+    ) -> Result<Self> {
+        let preview = JpegFeed::new(
+            "remote",
+            &StreamConfig {
+                addr: None,
+                quality: config.quality,
+                fps: config.preview_fps,
+                width: Some(config.preview_width),
+                ..StreamConfig::default()
+            },
+        )?;
+        let context = Context {
+            scenes: Arc::new(scenes),
+            commands,
+            active,
+            connections: Arc::new(AtomicUsize::new(0)),
+        };
+        let listener = listen(addr, auth, context, preview.subscriber(), on_clients)?;
+        Ok(Self {
+            preview,
+            _listener: listener,
+        })
+    }
+
+    // 在帧回调中调用, 没有客户端接收预览时不编码.
+    pub fn push(&mut self, frame: &Frame) {
+        self.preview.push(frame);
+    }
+}
+
+#[cfg(feature = "streaming")]
+fn listen(
+    addr: &str,
+    auth: &Auth,
+    context: Context,
+    preview: Subscriber,
+    on_clients: impl Fn(usize) + Clone + Send + 'static,
+) -> Result<Listener> {
+    let checker = auth.clone();
+    auth.listen(addr, "remote control", move |stream| {
+        let peer = stream.peer();
+        let (socket, user, role) = checker.accept_websocket(stream, Role::Viewer)?;
+        let peer = if user.is_empty() {
+            peer
+        } else {
+            format!("{} ({})", peer, user)
+        };
+        privacy::audit("remote", true, &peer);
+        on_clients(context.connections.fetch_add(1, Ordering::SeqCst) + 1);
+        let result = session(socket, &user, role, &context, &preview);
+        on_clients(context.connections.fetch_sub(1, Ordering::SeqCst) - 1);
+        privacy::audit("remote", false, &peer);
+        result
+    })
+}
+
+#[cfg(not(feature = "streaming"))]
+pub struct RemoteServer;
+
+#[cfg(not(feature = "streaming"))]
+impl RemoteServer {
+    pub fn start(
+        addr: &str,
+        _config: &RemoteConfig,
+        _auth: &Auth,
+        _scenes: Vec<String>,
+        _commands: Sender<Command>,
+        _active: Arc<Mutex<Active>>,
+        _on_clients: impl Fn(usize) + Clone + Send + 'static,
+    ) -> Result<Self> {
+        anyhow::bail!(
+            "remote control {} needs the streaming feature, rebuild with --features streaming",
+            addr
+        )
+    }
+
+    pub fn push(&mut self, _frame: &Frame) {}
+}
+
+// 一个客户端的会话: 先发送 hello 和 state, 之后处理命令, 在状态变化时推送 state,
+// 启用预览时把新的 JPEG 作为二进制消息发送.
+#[cfg(feature = "streaming")]
+fn session(
+    mut socket: tungstenite::WebSocket<crate::auth::Connection>,
+    user: &str,
+    role: Role,
+    context: &Context,
+    preview: &Subscriber,
+) -> Result<()> {
+    use std::io::ErrorKind;
+    use tungstenite::{Error, Message};
+
+    // 握手之后才设置超时, 用超时轮询命令, 状态和预览.
+    socket.get_ref().set_read_timeout(Some(POLL))?;
+    let hello = json!({
+        "type": "hello",
+        "version": env!("CARGO_PKG_VERSION"),
+        "user": user,
+        "role": if role == Role::Operator { "operator" } else { "viewer" },
+        "scenes": *context.scenes,
+    });
+    socket.send(Message::Text(hello.to_string()))?;
+    let mut subscription = Some(preview.subscribe());
+    let mut last_state = Value::Null;
+    loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => {
+                let reply = handle(&text, role, context, &mut subscription, preview);
+                socket.send(Message::Text(reply.to_string()))?;
+            }
+            Ok(Message::Close(_)) | Err(Error::ConnectionClosed) => return Ok(()),
+            Ok(_) => {}
+            Err(Error::Io(err))
+                if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {}
+            Err(err) => return Err(err.into()),
+        }
+        let state = state(context);
+        if state != last_state {
+            socket.send(Message::Text(state.to_string()))?;
+            last_state = state;
+        }
+        // 预览已关闭 (程序退出) 时结束会话.
+        if let Some(subscription) = &mut subscription {
+            if let Some(jpeg) = subscription.next(Duration::ZERO)? {
+                socket.send(Message::Binary(jpeg.to_vec()))?;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "streaming")]
+fn state(context: &Context) -> Value {
+    let active = context.active.lock().unwrap();
+    json!({
+        "type": "state",
+        "recording": active.recording,
+        "scene": active.scene,
+        "source": active.source,
+        "shutter": privacy::shutter(),
+    })
+}
+
+// 执行一条命令, 返回 result 消息.
+#[cfg(feature = "streaming")]
+fn handle(
+    text: &str,
+    role: Role,
+    context: &Context,
+    subscription: &mut Option<Subscription>,
+    preview: &Subscriber,
+) -> Value {
+    let message: ClientMessage = match serde_json::from_str(text) {
+        Ok(message) => message,
+        Err(err) => return reply(Value::Null, Err(format!("invalid message: {}", err))),
+    };
+    let needed = match message.request {
+        Request::Preview { .. } => Role::Viewer,
+        _ => Role::Operator,
+    };
+    if role < needed {
+        return reply(message.id, Err("forbidden".to_string()));
+    }
+    let command = match message.request {
+        Request::Preview { enabled } => {
+            if !enabled {
+                *subscription = None;
+            } else if subscription.is_none() {
+                *subscription = Some(preview.subscribe());
+            }
+            return reply(message.id, Ok(()));
+        }
+        Request::Record { enabled } => Command::Record(enabled),
+        Request::Snapshot => Command::TakePhoto(false, false),
+        Request::Scene { name } => {
+            if !context.scenes.contains(&name) {
+                return reply(message.id, Err(format!("unknown scene {}", name)));
+            }
+            Command::Scene(name)
+        }
+    };
+    let result = context
+        .commands
+        .send(command)
+        .map_err(|_| "capture stopped".to_string());
+    reply(message.id, result)
+}
+
+#[cfg(feature = "streaming")]
+fn reply(id: Value, result: Result<(), String>) -> Value {
+    match result {
+        Ok(()) => json!({ "type": "result", "id": id, "ok": true }),
+        Err(error) => json!({ "type": "result", "id": id, "ok": false, "error": error }),
+    }
+}
+
+#[cfg(all(test, feature = "streaming"))]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::mpsc::{channel, Receiver},
+    };
+
+    use tungstenite::Message;
+
+    use super::*;
+    use crate::auth::AuthConfig;
+
+    fn context() -> (Context, Receiver<Command>) {
+        let (commands, receiver) = channel();
+        let context = Context {
+            scenes: Arc::new(vec!["Day".to_string()]),
+            commands,
+            active: Arc::default(),
+            connections: Arc::default(),
+        };
+        (context, receiver)
+    }
+
+    // 回复中带上请求的 id, 失败时带上原因.
+    #[test]
+    fn handles_commands() {
+        let (context, receiver) = context();
+        let feed = JpegFeed::new("remote-test", &StreamConfig::default()).unwrap();
+        let preview = feed.subscriber();
+        let mut subscription = None;
+        let mut send = |text: &str, role| handle(text, role, &context, &mut subscription, &preview);
+
+        let result = send(
+            r#"{"id": 1, "command": "record", "enabled": true}"#,
+            Role::Operator,
+        );
+        assert_eq!(result, json!({"type": "result", "id": 1, "ok": true}));
+        assert!(matches!(receiver.try_recv(), Ok(Command::Record(true))));
+
+        let result = send(r#"{"id": 2, "command": "snapshot"}"#, Role::Viewer);
+        assert_eq!(result["error"], "forbidden");
+        let result = send(
+            r#"{"id": "a", "command": "scene", "name": "Night"}"#,
+            Role::Operator,
+        );
+        assert_eq!(
+            (&result["id"], &result["error"]),
+            (&json!("a"), &json!("unknown scene Night"))
+        );
+        send(r#"{"command": "scene", "name": "Day"}"#, Role::Operator);
+        assert!(matches!(receiver.try_recv(), Ok(Command::Scene(name)) if name == "Day"));
+        let result = send(r#"{"command": "zoom"}"#, Role::Operator);
+        assert!(result["error"]
+            .as_str()
+            .unwrap()
+            .starts_with("invalid message"));
+        assert!(receiver.try_recv().is_err());
+
+        // 预览只需要 viewer 权限.
+        let result = send(r#"{"command": "preview", "enabled": true}"#, Role::Viewer);
+        assert_eq!(result["ok"], true);
+        assert_eq!(preview.clients(), 1);
+        send(r#"{"command": "preview", "enabled": false}"#, Role::Viewer);
+        assert_eq!(preview.clients(), 0);
+
+        drop(receiver);
+        let result = send(r#"{"command": "snapshot"}"#, Role::Operator);
+        assert_eq!(result["error"], "capture stopped");
+    }
+
+    fn text(socket: &mut tungstenite::WebSocket<impl std::io::Read + std::io::Write>) -> Value {
+        loop {
+            if let Message::Text(text) = socket.read().unwrap() {
+                return serde_json::from_str(&text).unwrap();
+            }
+        }
+    }
+
+    // 连接后收到 hello 和 state, 命令交给采集线程, 状态变化时推送 state.
+    #[test]
+    fn websocket_session() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (commands, receiver) = channel();
+        let active = Arc::new(Mutex::new(Active {
+            source: "synthetic://".to_string(),
+            ..Active::default()
+        }));
+        let clients = Arc::new(AtomicUsize::new(0));
+        let counted = clients.clone();
+        let _server = RemoteServer::start(
+            &format!("127.0.0.1:{}", port),
+            &RemoteConfig::default(),
+            &Auth::new(&AuthConfig::default()).unwrap(),
+            vec!["Day".to_string()],
+            commands,
+            active.clone(),
+            move |count| counted.store(count, Ordering::SeqCst),
+        )
+        .unwrap();
+        let (mut socket, _) = tungstenite::connect(format!("ws://127.0.0.1:{}/", port)).unwrap();
+        let hello = text(&mut socket);
+        assert_eq!(hello["type"], "hello");
+        assert_eq!(hello["scenes"], json!(["Day"]));
+        let state = text(&mut socket);
+        assert_eq!(
+            (&state["type"], &state["source"]),
+            (&json!("state"), &json!("synthetic://"))
+        );
+        assert_eq!(clients.load(Ordering::SeqCst), 1);
+
+        socket
+            .send(Message::Text(
+                r#"{"id": 7, "command": "record", "enabled": true}"#.to_string(),
+            ))
+            .unwrap();
+        assert_eq!(text(&mut socket)["id"], 7);
+        assert!(matches!(receiver.recv(), Ok(Command::Record(true))));
+
+        active.lock().unwrap().recording = true;
+        let state = text(&mut socket);
+        assert_eq!(state["recording"], true);
+    }
+}
@@ -0,0 +1,138 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RestoreConfig {
+    // 退出时保存运行状态, 下次启动时提示恢复.
+    pub enabled: bool,
+    pub file: PathBuf,
+}
+
+impl Default for RestoreConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            file: PathBuf::from("last-session.json"),
+        }
+    }
+}
+
+// 退出时的运行状态.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedState {
+    // 来源 URI.
+    pub source: String,
+    // 处理阶段名称, 按执行顺序.
+    pub pipeline: Vec<String>,
+    // 场景名称, 没有切换过场景时为 None.
+    pub scene: Option<String>,
+    // 窗口位置和大小 (物理像素), 不支持时为 None.
+    pub position: Option<(i32, i32)>,
+    pub size: Option<(u32, u32)>,
+}
+
+impl SavedState {
+    // 恢复提示中显示的摘要.
+    pub fn summary(&self) -> String {
+        let pipeline = if self.pipeline.is_empty() {
+            "no processing".to_string()
+        } else {
+            self.pipeline.join(", ")
+        };
+        match &self.scene {
+            Some(scene) => format!("scene {}: {} with {}", scene, self.source, pipeline),
+            None => format!("{} with {}", self.source, pipeline),
+        }
+    }
+}
+
+// 没有保存的状态时返回 None.
+pub fn load(config: &RestoreConfig) -> Option<SavedState> {
+    if !config.enabled || !config.file.exists() {
+        return None;
+    }
+    match read(&config.file) {
+        Ok(state) => Some(state),
+        Err(err) => {
+            eprintln!("{:?}", err);
+            None
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<SavedState> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("read session {}", path.display()))?;
+    serde_json::from_str(&text).with_context(|| format!("parse session {}", path.display()))
+}
+
+pub fn save(config: &RestoreConfig, state: &SavedState) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let text = serde_json::to_string_pretty(state)?;
+    fs::write(&config.file, text)
+        .with_context(|| format!("write session {}", config.file.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary() {
+        let mut state = SavedState {
+            source: "camera://".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(state.summary(), "camera:// with no processing");
+        state.pipeline = vec!["speed".to_string(), "tracking".to_string()];
+        state.scene = Some("night".to_string());
+        assert_eq!(
+            state.summary(),
+            "scene night: camera:// with speed, tracking"
+        );
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = std::env::temp_dir().join(format!("restore-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let mut config = RestoreConfig {
+            enabled: true,
+            file: dir.join("last-session.json"),
+        };
+        assert!(load(&config).is_none());
+        let state = SavedState {
+            source: "synthetic://64x48@30".to_string(),
+            pipeline: vec!["speed".to_string()],
+            scene: None,
+            position: Some((10, 20)),
+            size: Some((800, 600)),
+        };
+        save(&config, &state).unwrap();
+        let loaded = load(&config).unwrap();
+        assert_eq!(loaded.source, state.source);
+        assert_eq!(loaded.pipeline, state.pipeline);
+        assert_eq!(loaded.position, Some((10, 20)));
+        assert_eq!(loaded.size, Some((800, 600)));
+
+        // 损坏的文件不恢复.
+        fs::write(&config.file, "{").unwrap();
+        assert!(load(&config).is_none());
+
+        // 关闭时不读也不写.
+        fs::remove_file(&config.file).unwrap();
+        config.enabled = false;
+        save(&config, &state).unwrap();
+        assert!(!config.file.exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{
+    events::EventStore,
+    threads::{Shutdown, ThreadConfig},
+    throttle::{self, Budget},
+};
+
+// 最近修改过的文件可能还在写入 (正在录制), 不处理.
+const IN_USE: Duration = Duration::from_secs(120);
+// 没有 max_days 的规则在超出容量时按一年计算先后.
+const DEFAULT_DAYS: f64 = 365.0;
+const DAY: f64 = 86400.0;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionConfig {
+    pub enabled: bool,
+    // 检查间隔 (分钟).
+    pub interval: f64,
+    // 所有规则匹配的文件总大小上限 (GB), 超出时先处理最接近期限的文件.
+    pub max_size_gb: Option<f64>,
+    // 设置时移动到该目录而不是删除, 保持相对于规则目录的路径.
+    pub archive: Option<PathBuf>,
+    pub rules: Vec<RetentionRule>,
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 10.0,
+            max_size_gb: None,
+            archive: None,
+            rules: vec![RetentionRule::default()],
+        }
+    }
+}
+
+// 一类文件的保留期限, 例如连续录像保留 7 天, 运动片段保留 30 天.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetentionRule {
+    pub name: String,
+    pub dir: PathBuf,
+    // 文件名模式, 支持 * 和 ?.
+    pub patterns: Vec<String>,
+    // 是否包括子目录, 例如按日期分目录保存的片段.
+    pub recursive: bool,
+    // 超过该天数的文件被删除或归档, 不设置时只受容量上限影响.
+    pub max_days: Option<f64>,
+}
+
+impl Default for RetentionRule {
+    fn default() -> Self {
+        Self {
+            name: "recordings".to_string(),
+            dir: PathBuf::from("."),
+            patterns: vec!["capture-*.mp4".to_string(), "capture-*.mp4.enc".to_string()],
+            recursive: false,
+            max_days: Some(30.0),
+        }
+    }
+}
+
+struct Entry {
+    path: PathBuf,
+    rule: usize,
+    size: u64,
+    age: f64,
+}
+
+// 在后台线程中定期清理. 每次清理的结果记录为 retention 事件.
+pub fn start(
+    config: &RetentionConfig,
+    events: EventStore,
+    workers: &ThreadConfig,
+    shutdown: &Shutdown,
+) -> Result<()> {
+    if !config.enabled || config.rules.is_empty() {
+        return Ok(());
+    }
+    let config = config.clone();
+    let workers = workers.clone();
+    let shutdown = shutdown.clone();
+    thread::Builder::new()
+        .name("retention".to_string())
+        .spawn(move || {
+            workers.apply("retention");
+            loop {
+                match clean(&config) {
+                    Ok((0, _)) => {}
+                    Ok((files, bytes)) => events.log(
+                        "retention",
+                        json!({
+                            "files": files,
+                            "bytes": bytes,
+                            "archived": config.archive.is_some(),
+                        }),
+                    ),
+                    Err(err) => eprintln!("retention: {:?}", err),
+                }
+                if shutdown.wait(Duration::from_secs_f64(config.interval.max(1.0) * 60.0)) {
+                    return;
+                }
+            }
+        })?;
+    Ok(())
+}
+
+// 处理过期和超出容量的文件, 返回处理的文件数和释放的字节数.
+pub fn clean(config: &RetentionConfig) -> Result<(usize, u64)> {
+    let mut entries = Vec::new();
+    for (index, rule) in config.rules.iter().enumerate() {
+        scan(
+            rule,
+            index,
+            &rule.dir,
+            config.archive.as_deref(),
+            &mut entries,
+        )?;
+    }
+    let days = |entry: &Entry| config.rules[entry.rule].max_days.unwrap_or(DEFAULT_DAYS);
+    // 按已用掉的保留期限比例排序, 期限短的连续录像先于期限长的运动片段被清理.
+    entries.sort_by(|a, b| (b.age / days(b)).total_cmp(&(a.age / days(a))));
+
+    let mut total: u64 = entries.iter().map(|entry| entry.size).sum();
+    let cap = config.max_size_gb.map(|gb| (gb * 1e9) as u64);
+    let (mut files, mut bytes) = (0, 0);
+    for entry in &entries {
+        let rule = &config.rules[entry.rule];
+        let expired = rule.max_days.is_some_and(|max| entry.age > max * DAY);
+        let over = cap.is_some_and(|cap| total > cap);
+        if !expired && !over {
+            continue;
+        }
+        if let Err(err) = remove(entry, rule, config.archive.as_deref()) {
+            eprintln!("retention: {:?}", err);
+            continue;
+        }
+        total -= entry.size;
+        files += 1;
+        bytes += entry.size;
+    }
+    Ok((files, bytes))
+}
+
+fn scan(
+    rule: &RetentionRule,
+    index: usize,
+    dir: &Path,
+    archive: Option<&Path>,
+    entries: &mut Vec<Entry>,
+) -> Result<()> {
+    let Ok(read) = fs::read_dir(dir) else {
+        // 目录还没有创建.
+        return Ok(());
+    };
+    let now = SystemTime::now();
+    for entry in read.flatten() {
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            if rule.recursive && archive.map_or(true, |archive| !same(&path, archive)) {
+                scan(rule, index, &path, archive, entries)?;
+            }
+            continue;
+        }
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !rule.patterns.iter().any(|pattern| matches(pattern, &name)) {
+            continue;
+        }
+        let age = now.duration_since(metadata.modified()?).unwrap_or_default();
+        if age < IN_USE {
+            continue;
+        }
+        entries.push(Entry {
+            path,
+            rule: index,
+            size: metadata.len(),
+            age: age.as_secs_f64(),
+        });
+    }
+    Ok(())
+}
+
+// 删除或移动到归档目录. 按日期分的子目录空了之后一并删除.
+fn remove(entry: &Entry, rule: &RetentionRule, archive: Option<&Path>) -> Result<()> {
+    match archive {
+        Some(archive) => {
+            let relative = entry.path.strip_prefix(&rule.dir).unwrap_or(&entry.path);
+            let target = archive.join(&rule.name).join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            // 归档目录在其他磁盘上时 rename 失败, 改为复制后删除.
+            if fs::rename(&entry.path, &target).is_err() {
+                throttle::copy(&entry.path, &target, Budget::Disk)
+                    .with_context(|| format!("archive {}", entry.path.display()))?;
+                fs::remove_file(&entry.path)?;
+            }
+        }
+        None => fs::remove_file(&entry.path)
+            .with_context(|| format!("delete {}", entry.path.display()))?,
+    }
+    let mut parent = entry.path.parent();
+    while let Some(dir) = parent {
+        if same(dir, &rule.dir) || fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+    Ok(())
+}
+
+fn same(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+// 文件名匹配, * 匹配任意个字符, ? 匹配一个字符.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut p, mut n) = (0, 0);
+    // 最近一个 * 的位置和它匹配到的名字位置, 失配时回溯.
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use super::*;
+
+    // 每个测试使用自己的临时目录.
+    fn temp(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("retention-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    // 写入 size 字节, 修改时间为 days 天前.
+    fn file(path: &Path, size: usize, days: f64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![0u8; size]).unwrap();
+        let modified = SystemTime::now() - Duration::from_secs_f64(days * DAY);
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    fn rules(dir: &Path) -> Vec<RetentionRule> {
+        vec![
+            RetentionRule {
+                name: "recordings".to_string(),
+                dir: dir.to_path_buf(),
+                patterns: vec!["capture-*.mp4".to_string()],
+                recursive: false,
+                max_days: Some(7.0),
+            },
+            RetentionRule {
+                name: "clips".to_string(),
+                dir: dir.join("clips"),
+                patterns: vec!["*.mp4".to_string()],
+                recursive: true,
+                max_days: Some(30.0),
+            },
+        ]
+    }
+
+    #[test]
+    fn patterns() {
+        for (pattern, name, expected) in [
+            ("capture-*.mp4", "capture-2024-01-01.mp4", true),
+            ("capture-*.mp4", "capture-.mp4", true),
+            ("capture-*.mp4", "capture-1.mp4.enc", false),
+            ("capture-*.mp4", "clip-1.mp4", false),
+            ("*.mp4", "a.mp4", true),
+            ("*", "", true),
+            ("", "a", false),
+            ("?.jpg", "a.jpg", true),
+            ("?.jpg", "ab.jpg", false),
+            ("*a*b", "xaxxab", true),
+            ("*a*b", "xaxxa", false),
+            ("a**b", "ab", true),
+        ] {
+            assert_eq!(matches(pattern, name), expected, "{} {}", pattern, name);
+        }
+    }
+
+    // 过期的文件被删除; 还没过期, 正在写入和不匹配的文件保留; 空了的日期目录一并删除.
+    #[test]
+    fn clean_expired() {
+        let dir = temp("expired");
+        file(&dir.join("capture-old.mp4"), 100, 10.0);
+        file(&dir.join("capture-new.mp4"), 100, 1.0);
+        file(&dir.join("capture-live.mp4"), 100, 0.0);
+        file(&dir.join("notes.txt"), 100, 100.0);
+        file(&dir.join("clips/2024-01-01/a.mp4"), 50, 40.0);
+        file(&dir.join("clips/2024-02-01/b.mp4"), 50, 10.0);
+        let config = RetentionConfig {
+            enabled: true,
+            rules: rules(&dir),
+            ..RetentionConfig::default()
+        };
+        assert_eq!(clean(&config).unwrap(), (2, 150));
+        assert!(!dir.join("capture-old.mp4").exists());
+        assert!(!dir.join("clips/2024-01-01").exists());
+        for kept in [
+            "capture-new.mp4",
+            "capture-live.mp4",
+            "notes.txt",
+            "clips/2024-02-01/b.mp4",
+        ] {
+            assert!(dir.join(kept).exists(), "{}", kept);
+        }
+        assert_eq!(clean(&config).unwrap(), (0, 0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 超出容量时先处理用掉保留期限比例最大的文件, 归档时保持相对路径.
+    #[test]
+    fn clean_over_size() {
+        let dir = temp("size");
+        let archive = dir.join("archive");
+        file(&dir.join("capture-a.mp4"), 100, 2.0);
+        file(&dir.join("clips/x/b.mp4"), 100, 12.0);
+        file(&dir.join("clips/x/c.mp4"), 100, 3.0);
+        let config = RetentionConfig {
+            enabled: true,
+            max_size_gb: Some(150e-9),
+            archive: Some(archive.clone()),
+            rules: rules(&dir),
+            ..RetentionConfig::default()
+        };
+        // capture-a 用掉 2/7, b 用掉 12/30, c 用掉 3/30: 先处理 b, 再处理 capture-a.
+        assert_eq!(clean(&config).unwrap(), (2, 200));
+        assert!(archive.join("clips/x/b.mp4").exists());
+        assert!(archive.join("recordings/capture-a.mp4").exists());
+        assert!(dir.join("clips/x/c.mp4").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
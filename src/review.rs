@@ -0,0 +1,238 @@
+use std::{fs, path::Path};
+
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDateTime, TimeDelta, TimeZone};
+use serde::Deserialize;
+
+use crate::{events, sync};
+
+// 跳转到下一个事件时, 当前位置之后至少这么多秒的事件才算下一个, 避免停在同一个事件上.
+const NEXT_MARGIN: f64 = 0.5;
+// 跳转到上一个事件时跳过刚刚开始的这一个.
+const PREVIOUS_MARGIN: f64 = 1.0;
+
+// 回放录像时按事件存储跳转: 上一个, 下一个事件, 以及自动跳过没有事件的片段.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReviewConfig {
+    // 事件前后保留的秒数, 跳转到事件时从事件前 padding 秒开始.
+    pub padding: f64,
+    // 只使用这些类型的事件, 例如 ["motion", "speed"], 为空时使用全部.
+    pub kinds: Vec<String>,
+}
+
+impl Default for ReviewConfig {
+    fn default() -> Self {
+        Self {
+            padding: 3.0,
+            kinds: Vec::new(),
+        }
+    }
+}
+
+// 一个录像中的事件, 时间为距录像开始的秒数.
+#[derive(Debug, Clone, Default)]
+pub struct Review {
+    events: Vec<f64>,
+    padding: f64,
+    total: f64,
+}
+
+impl Review {
+    pub fn new(mut events: Vec<f64>, padding: f64, total: f64) -> Self {
+        events.retain(|time| (0.0..=total).contains(time));
+        events.sort_by(f64::total_cmp);
+        Self {
+            events,
+            padding: padding.max(0.0),
+            total,
+        }
+    }
+
+    // 按录像开始时间和时长从事件存储中取出录像期间的事件.
+    pub fn load(
+        config: &ReviewConfig,
+        recording: &Path,
+        events_file: &Path,
+        total: f64,
+    ) -> Result<Self> {
+        let Some(started) = started(recording, total) else {
+            return Ok(Self::default());
+        };
+        let events = events::read(events_file)?
+            .into_iter()
+            .filter(|event| config.kinds.is_empty() || config.kinds.contains(&event.kind))
+            .map(|event| (event.time - started).num_milliseconds() as f64 / 1000.0)
+            .collect();
+        Ok(Self::new(events, config.padding, total))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    // 事件在时间线上的位置 (0..1).
+    pub fn marks(&self) -> Vec<f64> {
+        self.events
+            .iter()
+            .map(|time| time / self.total.max(f64::EPSILON))
+            .collect()
+    }
+
+    // 下一个事件的开始位置 (秒).
+    pub fn next(&self, position: f64) -> Option<f64> {
+        self.events
+            .iter()
+            .map(|time| (time - self.padding).max(0.0))
+            .find(|start| *start > position + NEXT_MARGIN)
+    }
+
+    pub fn previous(&self, position: f64) -> Option<f64> {
+        self.events
+            .iter()
+            .map(|time| (time - self.padding).max(0.0))
+            .rfind(|start| *start < position - PREVIOUS_MARGIN)
+    }
+
+    // 不在任何事件前后 padding 秒内时, 返回要跳到的位置: 下一个事件的开始, 没有时为录像结尾.
+    pub fn skip(&self, position: f64) -> Option<f64> {
+        if self.events.is_empty() {
+            return None;
+        }
+        let inside = self
+            .events
+            .iter()
+            .any(|time| (time - self.padding..=time + self.padding).contains(&position));
+        if inside {
+            return None;
+        }
+        Some(
+            self.events
+                .iter()
+                .map(|time| (time - self.padding).max(0.0))
+                .find(|start| *start > position)
+                .unwrap_or(self.total),
+        )
+    }
+}
+
+// 录像的开始时间: 优先使用同步 manifest, 然后是文件名中的时间 (默认文件名为 capture-%Y-%m-%dT%H-%M-%S.mp4),
+// 最后用修改时间 (录像结束时) 减去时长.
+pub fn started(recording: &Path, total: f64) -> Option<DateTime<Local>> {
+    let manifest = fs::read_to_string(sync::manifest_path(recording))
+        .ok()
+        .and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok());
+    if let Some(Ok(time)) = manifest
+        .as_ref()
+        .and_then(|manifest| manifest["started"].as_str())
+        .map(DateTime::parse_from_rfc3339)
+    {
+        return Some(time.with_timezone(&Local));
+    }
+    let stem = recording.file_stem()?.to_string_lossy().to_string();
+    // 从数字开始, 否则 "capture-2024-..." 中的 "-2024" 会被当作公元前的年份.
+    let named = (0..stem.len()).find_map(|start| {
+        let text = stem
+            .get(start..start + 19)
+            .filter(|text| text.starts_with(|c: char| c.is_ascii_digit()))?;
+        let time = NaiveDateTime::parse_from_str(text, "%Y-%m-%dT%H-%M-%S").ok()?;
+        Local.from_local_datetime(&time).earliest()
+    });
+    if named.is_some() {
+        return named;
+    }
+    let modified: DateTime<Local> = fs::metadata(recording).ok()?.modified().ok()?.into();
+    Some(modified - TimeDelta::milliseconds((total * 1000.0) as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn review() -> Review {
+        // 录像之外的事件被去掉, 其余按时间排列.
+        Review::new(vec![100.0, 30.0, 700.0, 2.0], 3.0, 600.0)
+    }
+
+    // 跳到事件前 padding 秒, 下一个不停在当前的事件上, 上一个跳过刚开始的事件.
+    #[test]
+    fn jumps() {
+        let review = review();
+        assert_eq!(
+            review.marks(),
+            vec![2.0 / 600.0, 30.0 / 600.0, 100.0 / 600.0]
+        );
+        assert_eq!(review.next(0.0), Some(27.0));
+        assert_eq!(review.next(27.0), Some(97.0));
+        assert_eq!(review.next(97.0), None);
+        assert_eq!(review.previous(97.5), Some(27.0));
+        assert_eq!(review.previous(27.5), Some(0.0));
+        assert_eq!(review.previous(0.5), None);
+    }
+
+    // 事件附近不跳, 其他位置跳到下一个事件, 最后一个事件之后跳到结尾.
+    #[test]
+    fn skip_quiet_parts() {
+        let review = review();
+        assert_eq!(review.skip(31.0), None);
+        assert_eq!(review.skip(40.0), Some(97.0));
+        assert_eq!(review.skip(200.0), Some(600.0));
+        assert_eq!(Review::new(Vec::new(), 3.0, 600.0).skip(40.0), None);
+    }
+
+    // 开始时间优先取同步 manifest, 然后是文件名, 最后是修改时间减去时长.
+    #[test]
+    fn start_time() {
+        let dir = std::env::temp_dir().join(format!("review-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let named = dir.join("capture-2024-05-01T12-30-00.mp4");
+        let expected = Local.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+        assert_eq!(started(&named, 60.0), Some(expected));
+
+        let manifest = Local.with_ymd_and_hms(2024, 5, 1, 12, 29, 58).unwrap();
+        let started_json = json!({ "started": manifest.to_rfc3339() });
+        fs::write(sync::manifest_path(&named), started_json.to_string()).unwrap();
+        assert_eq!(started(&named, 60.0), Some(manifest));
+
+        let plain = dir.join("clip.mp4");
+        fs::write(&plain, b"").unwrap();
+        let modified: DateTime<Local> = fs::metadata(&plain).unwrap().modified().unwrap().into();
+        assert_eq!(
+            started(&plain, 60.0),
+            Some(modified - TimeDelta::seconds(60))
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 从事件存储中取出录像期间所选类型的事件.
+    #[test]
+    fn load_events() {
+        let dir = std::env::temp_dir().join(format!("review-load-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("capture-2024-05-01T12-30-00.mp4");
+        let start = Local.with_ymd_and_hms(2024, 5, 1, 12, 30, 0).unwrap();
+        let lines: Vec<String> = [
+            (10, "motion"),
+            (20, "speed"),
+            (-5, "motion"),
+            (45, "motion"),
+        ]
+        .iter()
+        .map(|(seconds, kind)| {
+            let time = start + TimeDelta::seconds(*seconds);
+            json!({ "time": time.to_rfc3339(), "kind": kind, "data": {} }).to_string()
+        })
+        .collect();
+        let events_file = dir.join("events.jsonl");
+        fs::write(&events_file, lines.join("\n")).unwrap();
+        let config = ReviewConfig {
+            padding: 0.0,
+            kinds: vec!["motion".to_string()],
+        };
+        let review = Review::load(&config, &recording, &events_file, 60.0).unwrap();
+        assert_eq!(review.events, vec![10.0, 45.0]);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,487 @@
+use std::{
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, sleep, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{self, Mat},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    config::Config,
+    connections::{self, Jitter, Kind, Status},
+    crash,
+    pipeline::Pipeline,
+    profile::CameraProfile,
+    sink::{SinkConfig, SinkInfo, Sinks},
+    source::{self, FrameSource},
+    stages::Stages,
+    trajectory::Trajectories,
+};
+
+// 预览使用的来源.
+pub const MAIN: &str = "main";
+
+// 一条路由: 来源 -> 处理阶段 -> sink. 预览和默认录制是内置的路由, 不需要配置.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RouteConfig {
+    pub name: String,
+    // 来源 URI, "main" 为预览使用的来源, 此时在采集线程中处理.
+    pub source: String,
+    // 处理阶段名称, 为空时输出原始帧.
+    pub stages: Vec<String>,
+    // [[sinks]] 中的 name. 被路由引用的 sink 不再接收预览的录制帧.
+    pub sinks: Vec<String>,
+}
+
+impl Default for RouteConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            source: MAIN.to_string(),
+            stages: Vec::new(),
+            sinks: Vec::new(),
+        }
+    }
+}
+
+impl RouteConfig {
+    // 路由面板中显示的文字.
+    pub fn describe(&self) -> String {
+        let stages = if self.stages.is_empty() {
+            "raw".to_string()
+        } else {
+            self.stages.join(" + ")
+        };
+        format!(
+            "{}: {} → {} → {}",
+            self.name,
+            self.source,
+            stages,
+            self.sinks.join(", ")
+        )
+    }
+}
+
+// 没有被路由引用的 sink, 接收预览的录制帧.
+pub fn main_sinks(config: &Config) -> impl Iterator<Item = &SinkConfig> {
+    config.sinks.iter().filter(|sink| {
+        !config.routes.iter().any(|route| {
+            sink.name
+                .as_ref()
+                .is_some_and(|name| route.sinks.contains(name))
+        })
+    })
+}
+
+struct Route {
+    name: String,
+    pipeline: Pipeline,
+    sinks: Sinks,
+    frame: Mat,
+}
+
+impl Route {
+    fn open(config: &RouteConfig, stages: &Stages, info: &SinkInfo) -> Result<Self> {
+        let names = config.stages.iter().map(String::as_str).collect::<Vec<_>>();
+        let pipeline = stages.build(&names, &Trajectories::default())?;
+        let sinks = config
+            .sinks
+            .iter()
+            .map(|name| {
+                stages
+                    .config
+                    .sinks
+                    .iter()
+                    .find(|sink| sink.name.as_ref() == Some(name))
+                    .with_context(|| format!("unknown sink {:?}", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let sinks = Sinks::open(sinks, info);
+        for message in &sinks.messages {
+            stages.status(format!("Route {}: {}", config.name, message));
+        }
+        Ok(Self {
+            name: config.name.clone(),
+            pipeline,
+            sinks,
+            frame: Mat::default(),
+        })
+    }
+
+    // 每条路由在帧的副本上处理, 互不影响.
+    fn process(&mut self, frame: &Mat) -> Result<()> {
+        frame.copy_to(&mut self.frame)?;
+        self.pipeline.process(&mut self.frame);
+        self.sinks.write(&self.frame);
+        Ok(())
+    }
+}
+
+// 处理一组路由, 出错的路由被停止.
+fn process(routes: &mut Vec<Route>, frame: &Mat) {
+    routes.retain_mut(|route| match route.process(frame) {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("route {}: {:?}", route.name, err);
+            false
+        }
+    });
+}
+
+// 配置中的所有路由. 来源为 main 的在采集线程中处理, 其他来源各自在一个线程中读取.
+#[derive(Default)]
+pub struct Router {
+    main: Vec<Route>,
+    // 有路由使用 color-match, 需要发布主摄像头的直方图.
+    pub color_match: bool,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    // 在连接面板中显示的网络来源.
+    network: Vec<String>,
+    // 有路由的阶段在等待后台下载的模型, 下载结束后需要重新打开.
+    waiting: bool,
+}
+
+impl Router {
+    // stages 和 info 对应预览来源. 打开失败的路由在状态栏提示后跳过.
+    pub fn open(stages: &Stages, info: &SinkInfo) -> Self {
+        let mut router = Self::default();
+        let mut sources: Vec<(&str, Vec<&RouteConfig>)> = Vec::new();
+        for route in &stages.config.routes {
+            router.color_match |= route.stages.iter().any(|stage| stage == "color-match");
+            if route.source != MAIN {
+                match sources.iter_mut().find(|(uri, _)| *uri == route.source) {
+                    Some((_, routes)) => routes.push(route),
+                    None => sources.push((&route.source, vec![route])),
+                }
+                continue;
+            }
+            match Route::open(route, stages, info) {
+                Ok(route) => {
+                    router.waiting |= route.pipeline.is_waiting();
+                    router.main.push(route);
+                }
+                Err(err) => stages.status(format!("Route {} failed: {}", route.name, err)),
+            }
+        }
+        // 同一来源只打开一次, 帧分发给使用它的所有路由.
+        for (uri, routes) in sources {
+            if connections::is_network(uri) {
+                router.network.push(uri.to_string());
+            }
+            match router.spawn(uri, &routes, stages) {
+                Ok(thread) => router.threads.push(thread),
+                Err(err) => {
+                    stages.status(format!("Route source {} failed: {}", uri, err));
+                    if connections::is_network(uri) {
+                        connections::update(uri, Kind::Route, |connection| {
+                            connection.set_status(Status::Failed);
+                            connection.error(format!("{:#}", err));
+                        });
+                    }
+                }
+            }
+        }
+        router
+    }
+
+    fn spawn(
+        &mut self,
+        uri: &str,
+        routes: &[&RouteConfig],
+        stages: &Stages,
+    ) -> Result<JoinHandle<()>> {
+        let config = stages.config;
+        let source = source::open(uri, config)?;
+        let metadata = source.metadata();
+        let profile_dir = CameraProfile::dir(&config.profiles_dir, &metadata.profile);
+        let profile = CameraProfile::load(&profile_dir)?;
+        let stages = Stages {
+            profile: &profile,
+            profile_dir: &profile_dir,
+            frame_size: (metadata.width as i32, metadata.height as i32),
+            ..*stages
+        };
+        let info = SinkInfo {
+            fps: metadata.fps,
+            size: core::Size2i::new(metadata.width as i32, metadata.height as i32),
+        };
+        let mut opened = Vec::new();
+        for route in routes {
+            match Route::open(route, &stages, &info) {
+                Ok(route) => {
+                    self.waiting |= route.pipeline.is_waiting();
+                    opened.push(route);
+                }
+                Err(err) => stages.status(format!("Route {} failed: {}", route.name, err)),
+            }
+        }
+        let sync = config.sync.enabled.then_some(config.sync.window);
+        if sync.is_some() {
+            let files = opened
+                .iter()
+                .flat_map(|route| route.sinks.paths().map(Path::to_path_buf))
+                .collect();
+            crate::sync::register(uri, files);
+        }
+        let stop = self.stop.clone();
+        let uri = uri.to_string();
+        Ok(thread::Builder::new()
+            .name(format!("route {}", uri))
+            .spawn(move || run(source, opened, stop, &uri, sync))?)
+    }
+
+    // 有正在输出的路由, 用于界面上的输出提示.
+    pub fn is_active(&self) -> bool {
+        !self.main.is_empty() || !self.threads.is_empty()
+    }
+
+    // 有路由在等待后台下载的模型.
+    pub fn is_waiting(&self) -> bool {
+        self.waiting
+    }
+
+    // 采集线程中调用, frame 为预览来源未经处理的帧.
+    pub fn process(&mut self, frame: &Mat) {
+        process(&mut self.main, frame);
+    }
+}
+
+// sync 为 [sync] 启用时的时间窗口, 此时记录每帧的亮度用于估计时间偏差.
+fn run(
+    mut source: Box<dyn FrameSource>,
+    mut routes: Vec<Route>,
+    stop: Arc<AtomicBool>,
+    uri: &str,
+    sync: Option<f64>,
+) {
+    let mut frame = Mat::default();
+    let network = connections::is_network(uri);
+    let mut jitter = Jitter::default();
+    while !routes.is_empty() && !stop.load(Ordering::Relaxed) && !crash::stopping() {
+        match source.read(&mut frame) {
+            Ok(true) if !frame.empty() => {
+                if network {
+                    let jitter = jitter.frame();
+                    connections::update(uri, Kind::Route, |connection| {
+                        connection.set_status(Status::Connected);
+                        connection.jitter = Some(jitter);
+                    });
+                }
+                if let Some(window) = sync {
+                    if let Err(err) = crate::sync::add(uri, Local::now(), &frame, window) {
+                        eprintln!("sync {}: {:?}", uri, err);
+                    }
+                }
+                process(&mut routes, &frame);
+            }
+            // 文件读完后结束.
+            Ok(_) if !source.metadata().live => break,
+            Ok(_) => sleep(Duration::from_millis(10)),
+            Err(err) => {
+                eprintln!("{}: {:?}", source.metadata().description, err);
+                if network {
+                    connections::update(uri, Kind::Route, |connection| {
+                        connection.set_status(Status::Failed);
+                        connection.error(format!("{:#}", err));
+                    });
+                }
+                break;
+            }
+        }
+    }
+}
+
+impl Drop for Router {
+    // 等待各线程的 sink 关闭, 确保文件完整.
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+        for uri in &self.network {
+            connections::remove(uri);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, sync::atomic::AtomicUsize};
+
+    use opencv::{
+        core::{Scalar, Size, CV_8UC3},
+        videoio::{self, VideoCapture, VideoWriter},
+    };
+
+    use super::*;
+    use crate::{
+        events::EventStore,
+        sink::{self, FrameSink},
+    };
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl FrameSink for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn write(&mut self, _frame: &Mat) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn frame() -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(80.)).unwrap()
+    }
+
+    fn write_video(path: &Path, frames: u32) {
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        for _ in 0..frames {
+            writer.write(&frame()).unwrap();
+        }
+    }
+
+    fn count_frames(path: &Path) -> u32 {
+        let mut video = VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).unwrap();
+        let mut frame = Mat::default();
+        let mut frames = 0;
+        while video.read(&mut frame).unwrap() && !frame.empty() {
+            frames += 1;
+        }
+        frames
+    }
+
+    #[test]
+    fn describe() {
+        let mut route = RouteConfig {
+            name: "wide".to_string(),
+            sinks: vec!["a".to_string(), "b".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(route.describe(), "wide: main → raw → a, b");
+        route.stages = vec!["speed".to_string(), "tracking".to_string()];
+        assert_eq!(route.describe(), "wide: main → speed + tracking → a, b");
+    }
+
+    // 被路由引用的 sink 不接收预览的录制帧.
+    #[test]
+    fn main_sinks_skip_routed() {
+        let config: Config = toml::from_str(
+            r#"
+            [[sinks]]
+            kind = "file"
+
+            [[sinks]]
+            kind = "file"
+            name = "side"
+
+            [[sinks]]
+            kind = "snapshot"
+            name = "stills"
+
+            [[routes]]
+            name = "r"
+            sinks = ["side"]
+            "#,
+        )
+        .unwrap();
+        let names: Vec<_> = main_sinks(&config).map(|sink| sink.name.clone()).collect();
+        assert_eq!(names, [None, Some("stills".to_string())]);
+    }
+
+    // 文件来源在自己的线程中读完, 预览来源的路由由 process 驱动, 引用不存在的 sink 的路由被跳过.
+    #[cfg(feature = "record")]
+    #[test]
+    fn routes() {
+        let dir = std::env::temp_dir().join(format!("routing-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("input.avi");
+        let output = dir.join("output.avi");
+        write_video(&input, 6);
+        let written = Arc::new(AtomicUsize::new(0));
+        let counter = written.clone();
+        sink::register("routing-test", move |_, _| {
+            Ok(Box::new(Counting(counter.clone())))
+        });
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [[sinks]]
+            kind = "file"
+            name = "clip"
+            path = {:?}
+            fourcc = "MJPG"
+
+            [[sinks]]
+            kind = "routing-test"
+            name = "count"
+
+            [[routes]]
+            name = "file"
+            source = {:?}
+            sinks = ["clip"]
+
+            [[routes]]
+            name = "preview"
+            sinks = ["count"]
+
+            [[routes]]
+            name = "broken"
+            sinks = ["missing"]
+            "#,
+            output.display().to_string(),
+            input.display().to_string(),
+        ))
+        .unwrap();
+        let config = Config {
+            profiles_dir: dir.join("profiles"),
+            ..config
+        };
+        let profile = CameraProfile::default();
+        let events = EventStore::open(dir.join("events.jsonl")).unwrap();
+        let stages = Stages {
+            config: &config,
+            profile: &profile,
+            profile_dir: &dir,
+            events: &events,
+            frame_size: (64, 48),
+            window: None,
+            models_ready: None,
+        };
+        let info = SinkInfo {
+            fps: 10.0,
+            size: Size::new(64, 48),
+        };
+        let mut router = Router::open(&stages, &info);
+        assert!(router.is_active());
+        assert!(!router.is_waiting());
+        assert!(!router.color_match);
+        router.process(&frame());
+        router.process(&frame());
+        assert_eq!(written.load(Ordering::SeqCst), 2);
+        drop(router);
+        assert_eq!(count_frames(&output), 6);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use std::mem;
+
+use anyhow::Result;
+use opencv::{
+    core::{Mat, Rect},
+    imgproc::{self, INTER_LINEAR},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{effects::Effect, pipeline::FrameProcessor};
+
+// 场景: 来源, 裁剪, 效果和 OSD 的组合, 用热键, 控制面板或控制接口一次切换.
+// 配置中为 [[scenes]], 没有设置的部分恢复为默认值.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SceneConfig {
+    pub name: String,
+    // 来源 URI, 不设置时保持当前来源.
+    #[serde(default)]
+    pub source: Option<String>,
+    // 裁剪区域 [x, y, 宽, 高], 取值 0..1, 相对于画面宽高, 裁剪后缩放回原来的尺寸.
+    #[serde(default)]
+    pub crop: Option<[f64; 4]>,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    // 预览和录制使用的 OSD profile, 不设置时使用 [osd] 中的设置.
+    #[serde(default)]
+    pub preview_osd: Option<String>,
+    #[serde(default)]
+    pub recording_osd: Option<String>,
+    // 切换热键, 例如 "1", 输入框没有焦点时有效.
+    #[serde(default)]
+    pub hotkey: Option<String>,
+}
+
+// 裁剪后缩放回原尺寸, 录像文件和路由的分辨率不变.
+pub struct Crop {
+    rect: [f64; 4],
+    scratch: Mat,
+}
+
+impl Crop {
+    pub fn new(rect: [f64; 4]) -> Self {
+        Self {
+            rect,
+            scratch: Mat::default(),
+        }
+    }
+
+    fn rect(&self, width: i32, height: i32) -> Rect {
+        let [x, y, w, h] = self.rect.map(|v| v.clamp(0.0, 1.0));
+        let x = ((x * width as f64) as i32).min(width - 1);
+        let y = ((y * height as f64) as i32).min(height - 1);
+        let w = ((w * width as f64) as i32).clamp(1, width - x);
+        let h = ((h * height as f64) as i32).clamp(1, height - y);
+        Rect::new(x, y, w, h)
+    }
+}
+
+impl FrameProcessor for Crop {
+    fn name(&self) -> &str {
+        "crop"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let size = frame.size()?;
+        if size.width <= 0 || size.height <= 0 {
+            return Ok(());
+        }
+        {
+            let roi = Mat::roi(frame, self.rect(size.width, size.height))?;
+            imgproc::resize(&roi, &mut self.scratch, size, 0.0, 0.0, INTER_LINEAR)?;
+        }
+        mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+
+    // 在传感器和颜色校正之后, 分析之前, 检测和跟踪只看到裁剪后的画面.
+    fn order(&self) -> i32 {
+        -5
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{self, Scalar, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    #[test]
+    fn parse_scene() {
+        let scene: SceneConfig = toml::from_str(
+            r#"
+            name = "Night"
+            crop = [0.25, 0.25, 0.5, 0.5]
+            hotkey = "2"
+            effects = [{ kind = "colormap" }]
+            "#,
+        )
+        .unwrap();
+        assert!(scene.source.is_none());
+        assert_eq!(scene.crop, Some([0.25, 0.25, 0.5, 0.5]));
+        assert_eq!(scene.effects.len(), 1);
+        assert!(toml::from_str::<SceneConfig>("crop = [0.0, 0.0, 1.0, 1.0]").is_err());
+    }
+
+    // 超出画面的部分被截掉, 至少保留 1 个像素.
+    #[test]
+    fn crop_rect() {
+        assert_eq!(
+            Crop::new([0.25, 0.5, 0.5, 0.5]).rect(64, 48),
+            Rect::new(16, 24, 32, 24)
+        );
+        assert_eq!(
+            Crop::new([0.75, -1.0, 1.0, 2.0]).rect(64, 48),
+            Rect::new(48, 0, 16, 48)
+        );
+        assert_eq!(
+            Crop::new([1.0, 1.0, 0.0, 0.0]).rect(64, 48),
+            Rect::new(63, 47, 1, 1)
+        );
+    }
+
+    // 右下角的白色区域放大到整个画面, 尺寸不变.
+    #[test]
+    fn crop_keeps_size() {
+        let mut frame = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(0.)).unwrap();
+        {
+            let mut corner = Mat::roi_mut(&mut frame, Rect::new(32, 24, 32, 24)).unwrap();
+            corner
+                .set_to(&Scalar::all(255.), &core::no_array())
+                .unwrap();
+        }
+        let mut crop = Crop::new([0.5, 0.5, 0.5, 0.5]);
+        crop.process(&mut frame).unwrap();
+        assert_eq!((frame.cols(), frame.rows()), (64, 48));
+        assert_eq!(*frame.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::all(255));
+        assert_eq!(*frame.at_2d::<Vec3b>(47, 63).unwrap(), Vec3b::all(255));
+    }
+}
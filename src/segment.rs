@@ -0,0 +1,52 @@
+use anyhow::{bail, Result};
+use opencv::{core::Mat, prelude::*};
+
+// 语义分割模型, 给出每个像素属于前景 (通常是人) 的概率.
+pub trait Segmenter: Send {
+    // 返回 CV_32F 单通道的前景概率 (0~1), 尺寸为模型的输出尺寸.
+    fn segment(&mut self, frame: &Mat) -> Result<Mat>;
+
+    // 实际使用的计算设备, 用于显示.
+    fn device(&self) -> String {
+        "cpu".to_string()
+    }
+}
+
+// 模型输出为 NCHW, 宽高与输入相同. 单通道时为前景分数, 不在 0~1 之间时按 logits 做 sigmoid;
+// 多通道时为各类别的分数, 取 class 通道的 softmax 概率.
+pub fn foreground(data: &[f32], size: [i32; 2], class: usize) -> Result<Mat> {
+    let [width, height] = size;
+    let pixels = (width * height) as usize;
+    if pixels == 0 || data.len() % pixels != 0 {
+        bail!(
+            "segmentation output of {} values does not match {}x{}",
+            data.len(),
+            width,
+            height
+        );
+    }
+    let channels = data.len() / pixels;
+    let values: Vec<f32> = if channels == 1 {
+        let logits = data.iter().any(|v| !(0.0..=1.0).contains(v));
+        data.iter()
+            .map(|&v| if logits { 1.0 / (1.0 + (-v).exp()) } else { v })
+            .collect()
+    } else {
+        if class >= channels {
+            bail!(
+                "class {} is not among the {} model outputs",
+                class,
+                channels
+            );
+        }
+        (0..pixels)
+            .map(|pixel| {
+                let score = |c: usize| data[c * pixels + pixel];
+                let max = (0..channels).map(score).fold(f32::NEG_INFINITY, f32::max);
+                let total: f32 = (0..channels).map(|c| (score(c) - max).exp()).sum();
+                (score(class) - max).exp() / total
+            })
+            .collect()
+    };
+    Ok(Mat::from_slice(&values)?.reshape(1, height)?.try_clone()?)
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{Mat, Size},
+    prelude::*,
+    videoio::VideoWriter,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    capture::Command,
+    config::{Config, CONFIG_FILE},
+    profile::CameraProfile,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    // 录制可回放的会话: 未经处理的视频加上带帧号的命令日志.
+    pub enabled: bool,
+    pub dir: PathBuf,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            dir: PathBuf::from("sessions"),
+        }
+    }
+}
+
+// 日志第一行, 记录回放所需的全部初始状态.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    time: String,
+    // 相对于日志文件所在目录.
+    video: String,
+    fps: f64,
+    // 启动时的配置文件原文.
+    config: String,
+    profile: CameraProfile,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    frame: u64,
+    command: Command,
+}
+
+// 录制中的会话.
+pub struct SessionLog {
+    log: BufWriter<File>,
+    video: VideoWriter,
+}
+
+impl SessionLog {
+    pub fn create(
+        config: &SessionConfig,
+        fps: f64,
+        size: Size,
+        profile: &CameraProfile,
+    ) -> Result<(Self, PathBuf)> {
+        fs::create_dir_all(&config.dir)?;
+        let name = format!("session-{}", Local::now().format("%Y-%m-%dT%H-%M-%S"));
+        let video_name = format!("{}.mp4", name);
+        let log_path = config.dir.join(format!("{}.jsonl", name));
+        let video = VideoWriter::new(
+            &config.dir.join(&video_name).to_string_lossy(),
+            VideoWriter::fourcc('m', 'p', '4', 'v')?,
+            fps,
+            size,
+            true,
+        )?;
+        let header = Header {
+            time: Local::now().to_rfc3339(),
+            video: video_name,
+            fps,
+            config: fs::read_to_string(CONFIG_FILE).unwrap_or_default(),
+            profile: profile.clone(),
+        };
+        let mut log = BufWriter::new(File::create(&log_path)?);
+        writeln!(log, "{}", serde_json::to_string(&header)?)?;
+        Ok((Self { log, video }, log_path))
+    }
+
+    // 命令在第 frame 帧处理之前执行.
+    pub fn command(&mut self, frame: u64, command: &Command) -> Result<()> {
+        let line = serde_json::to_string(&Entry {
+            frame,
+            command: command.clone(),
+        })?;
+        writeln!(self.log, "{}", line)?;
+        self.log.flush()?;
+        Ok(())
+    }
+
+    pub fn frame(&mut self, frame: &Mat) -> Result<()> {
+        self.video.write(frame)?;
+        Ok(())
+    }
+}
+
+// 待回放的会话.
+pub struct Replay {
+    pub video: PathBuf,
+    pub fps: f64,
+    pub config: Config,
+    pub profile: CameraProfile,
+    commands: VecDeque<Entry>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("open session {}", path.display()))?;
+        let mut lines = BufReader::new(file).lines();
+        let Some(first) = lines.next() else {
+            bail!("empty session log {}", path.display());
+        };
+        let header: Header = serde_json::from_str(&first?)?;
+        let mut config: Config = toml::from_str(&header.config)?;
+        // 会话视频已经是映射后的 8 位帧, 回放时不再录制新的会话.
+        config.raw.enabled = false;
+        config.session.enabled = false;
+        let mut commands = VecDeque::new();
+        for line in lines {
+            let line = line?;
+            if !line.trim().is_empty() {
+                commands.push_back(serde_json::from_str(&line)?);
+            }
+        }
+        Ok(Self {
+            video: path.parent().unwrap_or(Path::new(".")).join(header.video),
+            fps: header.fps,
+            config,
+            profile: header.profile,
+            commands,
+        })
+    }
+
+    // 取出第 frame 帧之前需要执行的命令.
+    pub fn commands(&mut self, frame: u64) -> Vec<Command> {
+        let mut due = Vec::new();
+        while self.commands.front().is_some_and(|e| e.frame <= frame) {
+            due.push(self.commands.pop_front().unwrap().command);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, CV_8UC3};
+
+    use super::*;
+
+    #[test]
+    fn record_and_replay() {
+        let dir = std::env::temp_dir().join(format!("session-test-{}", std::process::id()));
+        let config = SessionConfig {
+            enabled: true,
+            dir: dir.clone(),
+        };
+        let (mut session, path) =
+            SessionLog::create(&config, 15.0, Size::new(64, 48), &CameraProfile::default())
+                .unwrap();
+        let frame = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(0.)).unwrap();
+        session.command(0, &Command::ToggleFlatField).unwrap();
+        session.frame(&frame).unwrap();
+        session
+            .command(3, &Command::WhiteBalance(0.25, 0.5))
+            .unwrap();
+        session.command(3, &Command::OnionHold).unwrap();
+        session.frame(&frame).unwrap();
+        drop(session);
+
+        let mut replay = Replay::load(&path).unwrap();
+        assert_eq!(replay.fps, 15.0);
+        assert_eq!(replay.video, path.with_extension("mp4"));
+        assert!(replay.video.exists());
+        assert!(!replay.config.session.enabled);
+        assert!(matches!(replay.commands(0)[..], [Command::ToggleFlatField]));
+        assert!(replay.commands(2).is_empty());
+        // 跳过的帧号上的命令在下一次取出时一起执行.
+        assert!(matches!(
+            replay.commands(5)[..],
+            [Command::WhiteBalance(x, y), Command::OnionHold] if (x, y) == (0.25, 0.5)
+        ));
+        assert!(replay.commands(100).is_empty());
+
+        fs::write(&path, "").unwrap();
+        assert!(Replay::load(&path).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,1320 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::Mutex,
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{self, Mat, Vector},
+    imgproc::{self, INTER_AREA},
+    prelude::*,
+    videoio::{self, VideoWriter},
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use crate::{
+    connections::{self, Kind, Status},
+    encryption::{self, Key},
+    exif::Exif,
+    integrity::{self, Chain},
+    photo, privacy, storage,
+};
+
+// 录制输出, 接收绘制录制 OSD 之后的 BGR 帧.
+pub trait FrameSink: Send {
+    fn name(&self) -> &str;
+    fn write(&mut self, frame: &Mat) -> Result<()>;
+
+    // 停止录制时调用, 用于写入文件尾. 之后 sink 会被 drop.
+    fn finish(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    // 实际使用的设置与配置不同时的提示, 例如编码不可用时换用了其他编码.
+    fn notice(&self) -> Option<&str> {
+        None
+    }
+
+    // 写入的文件, 路径中的时间格式已展开.
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+}
+
+// 每次开始录制时按本地时间展开, 生成新的文件.
+pub const DEFAULT_PATH: &str = "capture-%Y-%m-%dT%H-%M-%S.mp4";
+
+// 一个输出, kind 选择 sink 类型, 其余字段交给对应的 sink 解析.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SinkConfig {
+    pub kind: String,
+    // 供 [[routes]] 引用的名称.
+    pub name: Option<String>,
+    // 输出帧率, 不设置时与输入相同. 低于输入时丢帧, 高于输入时重复上一帧.
+    pub fps: Option<f64>,
+    // 输出尺寸, 只设置一项时按输入的宽高比计算另一项. 不设置时与输入相同.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    #[serde(flatten)]
+    pub options: toml::Table,
+}
+
+impl SinkConfig {
+    // 没有配置 [[sinks]] 时使用的默认输出.
+    pub fn defaults() -> Vec<Self> {
+        let mut options = toml::Table::new();
+        options.insert("path".to_string(), DEFAULT_PATH.into());
+        vec![Self {
+            kind: "file".to_string(),
+            name: None,
+            fps: None,
+            width: None,
+            height: None,
+            options,
+        }]
+    }
+
+    // 按 sink 自己的帧率和尺寸打开, 视频文件的时间轴与输出的帧数一致.
+    pub fn info(&self, info: &SinkInfo) -> SinkInfo {
+        let (width, height) = (info.size.width, info.size.height);
+        // 多数编码要求宽高为偶数.
+        let scaled = |length: i32, from: i32, to: i32| {
+            ((length as f64 * to as f64 / from.max(1) as f64 / 2.0).round() as i32 * 2).max(2)
+        };
+        let size = match (self.width, self.height) {
+            (Some(w), Some(h)) => core::Size::new(w, h),
+            (Some(w), None) => core::Size::new(w, scaled(height, width, w)),
+            (None, Some(h)) => core::Size::new(scaled(width, height, h), h),
+            (None, None) => info.size,
+        };
+        SinkInfo {
+            fps: self.fps.unwrap_or(info.fps),
+            size,
+        }
+    }
+
+    fn parse<T: DeserializeOwned>(&self) -> Result<T> {
+        toml::Value::Table(self.options.clone())
+            .try_into()
+            .with_context(|| format!("parse {} sink options", self.kind))
+    }
+}
+
+// 创建 sink 时可用的输入参数.
+#[derive(Debug, Clone, Copy)]
+pub struct SinkInfo {
+    pub fps: f64,
+    pub size: core::Size2i,
+}
+
+pub type SinkFactory =
+    Box<dyn Fn(&SinkConfig, &SinkInfo) -> Result<Box<dyn FrameSink>> + Send + 'static>;
+
+// 库使用者和插件注册的 sink 类型: kind -> 构造函数.
+static FACTORIES: Mutex<BTreeMap<String, SinkFactory>> = Mutex::new(BTreeMap::new());
+
+// 注册自定义 sink, 之后可以在 [[sinks]] 中用 kind 引用. 同名时覆盖内置类型.
+pub fn register(
+    kind: &str,
+    factory: impl Fn(&SinkConfig, &SinkInfo) -> Result<Box<dyn FrameSink>> + Send + 'static,
+) {
+    FACTORIES
+        .lock()
+        .unwrap()
+        .insert(kind.to_string(), Box::new(factory));
+}
+
+pub fn build(config: &SinkConfig, info: &SinkInfo) -> Result<Box<dyn FrameSink>> {
+    if let Some(factory) = FACTORIES.lock().unwrap().get(&config.kind) {
+        return factory(config, info);
+    }
+    Ok(match config.kind.as_str() {
+        #[cfg(feature = "record")]
+        "file" => Box::new(FileSink::new(config.parse()?, info)?),
+        #[cfg(feature = "record")]
+        "ffmpeg" => Box::new(FfmpegSink::new(config.parse()?, info)?),
+        #[cfg(not(feature = "record"))]
+        kind @ ("file" | "ffmpeg") => {
+            bail!("sink kind {:?} requires the record build feature", kind)
+        }
+        "snapshot" => Box::new(SnapshotSink::new(config.parse()?)?),
+        kind => bail!("unknown sink kind {:?}", kind),
+    })
+}
+
+// 帧率转换: 按输入的帧数计算输出应有的帧数, 而不是按墙上时间,
+// 这样高速模式的慢动作和文件回放也能得到正确的时间轴.
+#[derive(Debug, Clone)]
+pub struct FrameRate {
+    ratio: f64,
+    frames: u64,
+    written: u64,
+}
+
+impl FrameRate {
+    // 帧率相同时不需要转换, 返回 None.
+    pub fn new(input: f64, output: f64) -> Option<Self> {
+        if input <= 0.0 || output <= 0.0 || (input - output).abs() < 1e-3 {
+            return None;
+        }
+        Some(Self {
+            ratio: output / input,
+            frames: 0,
+            written: 0,
+        })
+    }
+
+    // 当前输入帧需要写入的次数: 0 为丢弃, 大于 1 时重复.
+    pub fn next(&mut self) -> u64 {
+        self.frames += 1;
+        let due = (self.frames as f64 * self.ratio).round() as u64;
+        let count = due.saturating_sub(self.written);
+        self.written += count;
+        count
+    }
+}
+
+struct Scaled {
+    size: core::Size,
+    frame: Mat,
+    // 结果对应的帧序号.
+    index: u64,
+}
+
+// 同一帧缩放到同一尺寸只计算一次, 尺寸相同的 sink 共用结果. 缓冲区跨帧复用.
+#[derive(Default)]
+struct ScaleCache {
+    index: u64,
+    entries: Vec<Scaled>,
+}
+
+impl ScaleCache {
+    // 每个新帧之前调用, 使上一帧的结果失效.
+    fn next_frame(&mut self) {
+        self.index += 1;
+    }
+
+    fn get<'a>(&'a mut self, frame: &'a Mat, size: core::Size) -> Result<&'a Mat> {
+        if frame.size()? == size {
+            return Ok(frame);
+        }
+        let i = match self.entries.iter().position(|e| e.size == size) {
+            Some(i) => i,
+            None => {
+                self.entries.push(Scaled {
+                    size,
+                    frame: Mat::default(),
+                    index: 0,
+                });
+                self.entries.len() - 1
+            }
+        };
+        let entry = &mut self.entries[i];
+        if entry.index != self.index {
+            imgproc::resize(frame, &mut entry.frame, size, 0.0, 0.0, INTER_AREA)?;
+            entry.index = self.index;
+        }
+        Ok(&entry.frame)
+    }
+}
+
+struct Output {
+    sink: Box<dyn FrameSink>,
+    rate: Option<FrameRate>,
+    // 与输入不同的输出尺寸.
+    size: Option<core::Size>,
+}
+
+impl Output {
+    // 按 sink 自己的帧率和尺寸打开.
+    fn open(config: &SinkConfig, info: &SinkInfo) -> Result<Self> {
+        let sink_info = config.info(info);
+        let sink = build(config, &sink_info)?;
+        Ok(Self {
+            sink,
+            rate: FrameRate::new(info.fps, sink_info.fps),
+            size: (sink_info.size != info.size).then_some(sink_info.size),
+        })
+    }
+
+    // 丢弃的帧不缩放.
+    fn write(&mut self, frame: &Mat, cache: &mut ScaleCache) -> Result<()> {
+        let count = self.rate.as_mut().map_or(1, FrameRate::next);
+        if count == 0 {
+            return Ok(());
+        }
+        let frame = match self.size {
+            Some(size) => cache.get(frame, size)?,
+            None => frame,
+        };
+        for _ in 0..count {
+            self.sink.write(frame)?;
+        }
+        Ok(())
+    }
+}
+
+// 同一帧依次交给所有 sink, 每个 sink 按自己的帧率和尺寸输出. 出错的 sink 被关闭, 不影响其他输出.
+#[derive(Default)]
+pub struct Sinks {
+    sinks: Vec<Output>,
+    cache: ScaleCache,
+    // 隐私快门关闭时写入的黑帧.
+    blank: Mat,
+    // 打开时的错误和提示, 由调用者显示在界面上.
+    pub messages: Vec<String>,
+}
+
+impl Sinks {
+    pub fn open<'a>(configs: impl IntoIterator<Item = &'a SinkConfig>, info: &SinkInfo) -> Self {
+        let mut sinks = Self::default();
+        for config in configs {
+            match Output::open(config, info) {
+                Ok(output) => {
+                    sinks
+                        .messages
+                        .extend(output.sink.notice().map(str::to_string));
+                    sinks.sinks.push(output);
+                }
+                Err(err) => {
+                    eprintln!("open {} sink: {:?}", config.kind, err);
+                    sinks
+                        .messages
+                        .push(format!("Recording to {} failed: {:#}", config.kind, err));
+                }
+            }
+        }
+        sinks
+    }
+
+    // 只打开一个输出, 打开失败时返回错误.
+    pub fn single(config: &SinkConfig, info: &SinkInfo) -> Result<Self> {
+        let output = Output::open(config, info)?;
+        Ok(Self {
+            messages: output
+                .sink
+                .notice()
+                .map(str::to_string)
+                .into_iter()
+                .collect(),
+            sinks: vec![output],
+            ..Default::default()
+        })
+    }
+
+    pub fn add(&mut self, sink: Box<dyn FrameSink>) {
+        self.sinks.push(Output {
+            sink,
+            rate: None,
+            size: None,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sinks.is_empty()
+    }
+
+    // 仍在写入的输出数, 出错的 sink 会被关闭.
+    pub fn len(&self) -> usize {
+        self.sinks.len()
+    }
+
+    pub fn write(&mut self, frame: &Mat) {
+        let frame = match privacy::output(frame, &mut self.blank) {
+            Ok(frame) => frame,
+            Err(err) => {
+                eprintln!("privacy shutter: {:?}", err);
+                return;
+            }
+        };
+        let cache = &mut self.cache;
+        cache.next_frame();
+        self.sinks
+            .retain_mut(|output| match output.write(frame, cache) {
+                Ok(()) => true,
+                Err(err) => {
+                    eprintln!("{} sink: {:?}", output.sink.name(), err);
+                    false
+                }
+            });
+    }
+
+    // 输出写入的文件, 不写文件的 sink 不包括在内.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.sinks.iter().filter_map(|output| output.sink.path())
+    }
+
+    // 关闭所有输出, 返回第一个错误. drop 时同样会关闭, 但只打印错误.
+    pub fn close(mut self) -> Result<()> {
+        let mut result = Ok(());
+        for Output { mut sink, .. } in std::mem::take(&mut self.sinks) {
+            if let Err(err) = sink.finish() {
+                eprintln!("finish {} sink: {:?}", sink.name(), err);
+                if result.is_ok() {
+                    result = Err(err.context(format!("finish {} sink", sink.name())));
+                }
+            }
+        }
+        result
+    }
+}
+
+impl Drop for Sinks {
+    fn drop(&mut self) {
+        for Output { sink, .. } in &mut self.sinks {
+            if let Err(err) = sink.finish() {
+                eprintln!("finish {} sink: {:?}", sink.name(), err);
+            }
+        }
+    }
+}
+
+// 展开输出路径中的 strftime 格式 (例如 %Y-%m-%d), 格式无效时原样返回.
+pub fn timestamped(path: &Path) -> PathBuf {
+    let text = path.to_string_lossy();
+    let mut expanded = String::new();
+    match write!(expanded, "{}", Local::now().format(&text)) {
+        Ok(()) => PathBuf::from(expanded),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// 按优先顺序尝试的编码, 以及对应的文件扩展名.
+pub const CODECS: [(&str, &str); 3] = [("avc1", "mp4"), ("mp4v", "mp4"), ("MJPG", "avi")];
+
+pub fn fourcc(code: &str) -> Result<i32> {
+    let chars: Vec<char> = code.chars().collect();
+    let [a, b, c, d] = chars[..] else {
+        bail!("fourcc {:?} must have 4 characters", code);
+    };
+    Ok(VideoWriter::fourcc(a, b, c, d)?)
+}
+
+// 控制面板中显示的一行, 包括按当前设置估算的每小时文件大小.
+pub fn describe(config: &SinkConfig, info: &SinkInfo) -> String {
+    let name = config.name.as_deref().unwrap_or(&config.kind);
+    match estimate(config, info) {
+        Some(bytes) => format!("{}: about {} per hour", name, format_size(bytes)),
+        None => format!("{}: size unknown", name),
+    }
+}
+
+// 粗略估算每小时写入的字节数, 无法估算时 (例如自定义 sink) 返回 None.
+pub fn estimate(config: &SinkConfig, info: &SinkInfo) -> Option<f64> {
+    let info = config.info(info);
+    let pixels = f64::from(info.size.width) * f64::from(info.size.height);
+    let bits_per_second = |bits_per_pixel: f64| pixels * info.fps * bits_per_pixel;
+    let bits = match config.kind.as_str() {
+        "file" => {
+            let config: FileSinkConfig = config.parse().ok()?;
+            bits_per_second(bits_per_pixel(&config.fourcc, config.quality)?)
+        }
+        "ffmpeg" => {
+            let config: FfmpegSinkConfig = config.parse().ok()?;
+            let arg = |name: &str| {
+                let index = config.args.iter().position(|arg| arg == name)?;
+                config.args.get(index + 1).cloned()
+            };
+            match config.bitrate.clone().or_else(|| arg("-b:v")) {
+                Some(bitrate) => parse_bitrate(&bitrate)?,
+                None if config.codec.is_some() => {
+                    let (_, _, bits_per_pixel) = ffmpeg_codec(config.codec.as_deref()?).ok()?;
+                    bits_per_second(bits_per_pixel)
+                }
+                None => {
+                    // x264 默认 CRF 23, CRF 每增加 6 码率约减半.
+                    let crf = match config.crf {
+                        Some(crf) => f64::from(crf),
+                        None => arg("-crf").and_then(|crf| crf.parse().ok()).unwrap_or(23.0),
+                    };
+                    bits_per_second(0.1 * 2f64.powf((23.0 - crf) / 6.0))
+                }
+            }
+        }
+        "snapshot" => {
+            let config: SnapshotSinkConfig = config.parse().ok()?;
+            let bits_per_pixel = if config.format == "png" { 12.0 } else { 2.0 };
+            pixels * bits_per_pixel / config.interval
+        }
+        _ => return None,
+    };
+    Some(bits / 8.0 * 3600.0)
+}
+
+// 常见编码在默认设置下大致的每像素比特数.
+fn bits_per_pixel(fourcc: &str, quality: Option<f64>) -> Option<f64> {
+    match fourcc {
+        "avc1" | "H264" | "X264" => Some(0.1),
+        "mp4v" | "XVID" | "DIVX" => Some(0.2),
+        "MJPG" => {
+            let quality = quality.unwrap_or(95.0) / 100.0;
+            Some(0.5 + 2.5 * quality * quality)
+        }
+        // 无损编码, 与画面内容有关, 按原始 24 位的一半左右估算.
+        "FFV1" => Some(12.0),
+        "ULRG" | "ULY0" | "ULY2" | "ULY4" | "ULH0" | "ULH2" | "ULH4" => Some(14.0),
+        _ => None,
+    }
+}
+
+// ffmpeg 码率格式, 例如 "8M", "800k" 或 "8000000", 返回 bit/s.
+fn parse_bitrate(text: &str) -> Option<f64> {
+    let (number, scale) = if let Some(number) = text.strip_suffix(['k', 'K']) {
+        (number, 1e3)
+    } else if let Some(number) = text.strip_suffix(['m', 'M']) {
+        (number, 1e6)
+    } else {
+        (text, 1.0)
+    };
+    Some(number.parse::<f64>().ok()? * scale)
+}
+
+fn format_size(bytes: f64) -> String {
+    if bytes >= 1e9 {
+        format!("{:.1} GB", bytes / 1e9)
+    } else {
+        format!("{:.0} MB", bytes / 1e6)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileSinkConfig {
+    pub path: PathBuf,
+    pub fourcc: String,
+    // 编码质量 0..100 (VIDEOWRITER_PROP_QUALITY), 只有部分编码和后端支持, 例如 MJPG.
+    pub quality: Option<f64>,
+    // 设置时录制结束后用该密钥文件加密为 <path>.enc 并删除明文.
+    pub key_file: Option<PathBuf>,
+    // 在 <path>.integrity.jsonl 中记录每帧的序号, 时间和哈希链, 用 verify 命令检查.
+    pub integrity: bool,
+    // 哈希链的 HMAC 密钥, 启用 integrity 时必须存在.
+    pub integrity_key_file: PathBuf,
+}
+
+impl Default for FileSinkConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from(DEFAULT_PATH),
+            fourcc: "mp4v".to_string(),
+            quality: None,
+            key_file: None,
+            integrity: false,
+            integrity_key_file: PathBuf::from(integrity::KEY_FILE),
+        }
+    }
+}
+
+// OpenCV VideoWriter.
+#[cfg_attr(not(feature = "record"), allow(dead_code))]
+pub struct FileSink {
+    writer: VideoWriter,
+    notice: Option<String>,
+    path: PathBuf,
+    key: Option<Key>,
+    chain: Option<Chain>,
+}
+
+#[cfg_attr(not(feature = "record"), allow(dead_code))]
+impl FileSink {
+    pub fn new(config: FileSinkConfig, info: &SinkInfo) -> Result<Self> {
+        // 密钥有问题时不开始录制, 避免留下未加密的文件.
+        let key = config.key_file.as_deref().map(Key::load).transpose()?;
+        let integrity_key = config
+            .integrity
+            .then(|| integrity::Key::load(&config.integrity_key_file))
+            .transpose()?;
+        let path = &storage::resolve(&timestamped(&config.path));
+        // 编码不可用时 VideoWriter 可能照常打开, 但只生成空文件.
+        let mut notices = Vec::new();
+        let mut code = config.fourcc.as_str();
+        if !probe(path, fourcc(code)?, info) {
+            let fallback = CODECS
+                .iter()
+                .map(|(code, _)| *code)
+                .filter(|fallback| *fallback != code)
+                .find(|fallback| fourcc(fallback).is_ok_and(|fourcc| probe(path, fourcc, info)));
+            let Some(fallback) = fallback else {
+                bail!("no working codec for {}", path.display());
+            };
+            notices.push(format!(
+                "Codec {} does not work for {}, recording with {}",
+                code,
+                path.display(),
+                fallback
+            ));
+            code = fallback;
+        }
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc(code)?,
+            info.fps, // 需要和 camera FPS 一致, 播放保存的 mp4 视频才正常速度
+            info.size,
+            true,
+        )?;
+        if !writer.is_opened()? {
+            bail!("open video writer {}", path.display());
+        }
+        if let Some(quality) = config.quality {
+            if !writer.set(videoio::VIDEOWRITER_PROP_QUALITY, quality)? {
+                notices.push(format!("Codec {} ignores the quality setting", code));
+            }
+        }
+        let chain = integrity_key
+            .map(|key| Chain::create(path, code, info, key))
+            .transpose()?;
+        let notice = (!notices.is_empty()).then(|| notices.join("; "));
+        Ok(Self {
+            writer,
+            notice,
+            path: path.clone(),
+            key,
+            chain,
+        })
+    }
+}
+
+// 在 path 旁边写几帧黑色画面, 检查编码和容器在当前 OpenCV 构建中是否可用.
+pub fn probe(path: &Path, fourcc: i32, info: &SinkInfo) -> bool {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let probe = path.with_file_name(format!("{}.probe.{}", stem, extension));
+    let written = (|| -> Result<bool> {
+        let mut writer =
+            VideoWriter::new(&probe.to_string_lossy(), fourcc, info.fps, info.size, true)?;
+        if !writer.is_opened()? {
+            return Ok(false);
+        }
+        let frame = Mat::zeros(info.size.height, info.size.width, core::CV_8UC3)?.to_mat()?;
+        for _ in 0..3 {
+            writer.write(&frame)?;
+        }
+        writer.release()?;
+        Ok(fs::metadata(&probe)?.len() > 0)
+    })()
+    .unwrap_or(false);
+    let _ = fs::remove_file(&probe);
+    written
+}
+
+impl FrameSink for FileSink {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    fn notice(&self) -> Option<&str> {
+        self.notice.as_deref()
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn write(&mut self, frame: &Mat) -> Result<()> {
+        self.writer.write(frame)?;
+        if let Some(chain) = &mut self.chain {
+            chain.add(frame)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        self.writer.release()?;
+        let (key, chain) = (self.key.take(), self.chain.take());
+        if key.is_none() && chain.is_none() {
+            return Ok(());
+        }
+        let path = self.path.clone();
+        // 处理完之前不上传 outbox 中的录像.
+        let pending = storage::Pending::create(&path)
+            .inspect_err(|err| eprintln!("{:?}", err))
+            .ok();
+        // 计算文件哈希和加密大文件需要时间, 不阻塞采集线程. 哈希针对加密之前的文件.
+        let handle = thread::Builder::new()
+            .name("finish".to_string())
+            .spawn(move || {
+                let _pending = pending;
+                if let Some(chain) = chain {
+                    if let Err(err) = chain.finish(&path) {
+                        eprintln!("integrity {}: {:?}", path.display(), err);
+                    }
+                }
+                if let Some(key) = key {
+                    if let Err(err) = encryption::encrypt_file(&path, &key) {
+                        eprintln!("encrypt {}: {:?}", path.display(), err);
+                    }
+                }
+            })?;
+        let mut finishing = FINISHING.lock().unwrap();
+        finishing.retain(|handle| !handle.is_finished());
+        finishing.push(handle);
+        Ok(())
+    }
+}
+
+// 后台写完整性记录和加密的线程. 退出前要等它们结束, 否则会留下未加密的明文.
+static FINISHING: Mutex<Vec<JoinHandle<()>>> = Mutex::new(Vec::new());
+
+// 等待所有已关闭的文件处理完成. 停止采集和命令行处理结束时调用.
+pub fn wait_finished() {
+    let handles = std::mem::take(&mut *FINISHING.lock().unwrap());
+    for handle in handles {
+        let _ = handle.join();
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FfmpegSinkConfig {
+    // 输出参数, 放在输入参数之后, 例如 ["-c:v", "libx264", "out.mkv"].
+    pub args: Vec<String>,
+    pub program: String,
+    // 分别对应 -crf 和 -b:v (例如 "8M"), 放在 args 之前.
+    pub crf: Option<u32>,
+    pub bitrate: Option<String>,
+    // 无损或剪辑用的中间编码, 见 FFMPEG_CODECS. 参数放在输出文件名之前, 覆盖 args 中的编码.
+    pub codec: Option<String>,
+}
+
+impl Default for FfmpegSinkConfig {
+    fn default() -> Self {
+        Self {
+            args: vec![
+                "-c:v".into(),
+                "libx264".into(),
+                "-y".into(),
+                "out.mkv".into(),
+            ],
+            program: "ffmpeg".to_string(),
+            crf: None,
+            bitrate: None,
+            codec: None,
+        }
+    }
+}
+
+// 把原始 BGR 帧通过标准输入写给 ffmpeg, 可以使用 OpenCV 不支持的编码器和格式.
+#[cfg_attr(not(feature = "record"), allow(dead_code))]
+pub struct FfmpegSink {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    // 输出为网络地址 (rtmp, srt 等) 时在连接面板中显示.
+    url: Option<String>,
+}
+
+// 编码名称, ffmpeg 输出参数, 估算文件大小用的每像素比特数.
+pub const FFMPEG_CODECS: [(&str, &[&str], f64); 5] = [
+    (
+        "ffv1",
+        &["-c:v", "ffv1", "-level", "3", "-g", "1", "-slices", "4"],
+        12.0,
+    ),
+    ("utvideo", &["-c:v", "utvideo", "-pix_fmt", "gbrp"], 14.0),
+    (
+        "prores",
+        &[
+            "-c:v",
+            "prores_ks",
+            "-profile:v",
+            "3",
+            "-pix_fmt",
+            "yuv422p10le",
+        ],
+        3.5,
+    ),
+    (
+        "prores-4444",
+        &[
+            "-c:v",
+            "prores_ks",
+            "-profile:v",
+            "4",
+            "-pix_fmt",
+            "yuv444p10le",
+        ],
+        5.5,
+    ),
+    (
+        "mjpeg",
+        &["-c:v", "mjpeg", "-q:v", "1", "-pix_fmt", "yuvj444p"],
+        3.0,
+    ),
+];
+
+fn ffmpeg_codec(name: &str) -> Result<(&'static str, &'static [&'static str], f64)> {
+    FFMPEG_CODECS
+        .into_iter()
+        .find(|(codec, _, _)| *codec == name)
+        .with_context(|| format!("unknown ffmpeg codec {:?}", name))
+}
+
+#[cfg_attr(not(feature = "record"), allow(dead_code))]
+impl FfmpegSink {
+    pub fn new(config: FfmpegSinkConfig, info: &SinkInfo) -> Result<Self> {
+        let args = output_args(&config)?;
+        let mut child = Command::new(&config.program)
+            .args(["-loglevel", "error", "-f", "rawvideo", "-pix_fmt", "bgr24"])
+            .args([
+                "-video_size",
+                &format!("{}x{}", info.size.width, info.size.height),
+            ])
+            .args(["-framerate", &info.fps.to_string(), "-i", "-"])
+            .args(
+                config
+                    .crf
+                    .iter()
+                    .flat_map(|crf| ["-crf".to_string(), crf.to_string()]),
+            )
+            .args(
+                config
+                    .bitrate
+                    .iter()
+                    .flat_map(|bitrate| ["-b:v", bitrate.as_str()]),
+            )
+            .args(&args)
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("start {}", config.program))?;
+        let stdin = child.stdin.take();
+        let url = args
+            .last()
+            .filter(|output| connections::is_network(output))
+            .cloned();
+        if let Some(url) = &url {
+            connections::update(url, Kind::Sink, |connection| {
+                connection.set_status(Status::Connected);
+            });
+        }
+        Ok(Self { child, stdin, url })
+    }
+}
+
+// config.args 中的输出文件按存储设置解析, 编码参数放在输出文件之前.
+fn output_args(config: &FfmpegSinkConfig) -> Result<Vec<String>> {
+    let mut args = config.args.clone();
+    // 最后一个参数是输出文件, 网络地址不经过存储.
+    if let Some(output) = args.last_mut() {
+        let path = timestamped(Path::new(output));
+        let path = if connections::is_network(output) {
+            path
+        } else {
+            storage::resolve(&path)
+        };
+        *output = path.to_string_lossy().into_owned();
+    }
+    if let Some(codec) = &config.codec {
+        let (_, codec_args, _) = ffmpeg_codec(codec)?;
+        let output = args.len().saturating_sub(1);
+        args.splice(output..output, codec_args.iter().map(|arg| arg.to_string()));
+    }
+    Ok(args)
+}
+
+impl FrameSink for FfmpegSink {
+    fn name(&self) -> &str {
+        "ffmpeg"
+    }
+
+    fn write(&mut self, frame: &Mat) -> Result<()> {
+        let stdin = self.stdin.as_mut().context("ffmpeg stdin closed")?;
+        // 非连续的 Mat 先复制一份.
+        let result = if frame.is_continuous() {
+            stdin.write_all(frame.data_bytes()?)
+        } else {
+            stdin.write_all(frame.try_clone()?.data_bytes()?)
+        };
+        // ffmpeg 连不上或断开时退出, 写入失败.
+        if let (Err(err), Some(url)) = (&result, &self.url) {
+            connections::update(url, Kind::Sink, |connection| {
+                connection.set_status(Status::Failed);
+                connection.error(err);
+            });
+        }
+        Ok(result?)
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        // 关闭标准输入, 等待 ffmpeg 写完文件.
+        drop(self.stdin.take());
+        let status = self.child.wait()?;
+        if !status.success() {
+            bail!("ffmpeg exited with {}", status);
+        }
+        // 正常结束的网络输出不再显示, 失败的保留到重新连接.
+        if let Some(url) = &self.url {
+            connections::remove(url);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SnapshotSinkConfig {
+    pub dir: PathBuf,
+    // 保存间隔 (秒).
+    pub interval: f64,
+    // 文件扩展名, 决定图像格式.
+    pub format: String,
+    // jpg 和 webp 的质量 0..100, 不设置时使用 OpenCV 默认值.
+    pub quality: Option<i32>,
+    // jpg 和 png 写入保存时间的 EXIF 和 XMP.
+    pub metadata: bool,
+}
+
+impl Default for SnapshotSinkConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("snapshots"),
+            interval: 60.0,
+            format: "jpg".to_string(),
+            quality: None,
+            metadata: true,
+        }
+    }
+}
+
+// 按固定间隔把帧保存为图片, 文件名为本地时间.
+pub struct SnapshotSink {
+    config: SnapshotSinkConfig,
+    interval: Duration,
+    last: Option<Instant>,
+}
+
+impl SnapshotSink {
+    pub fn new(config: SnapshotSinkConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("create {}", config.dir.display()))?;
+        Ok(Self {
+            interval: Duration::from_secs_f64(config.interval.max(0.0)),
+            config,
+            last: None,
+        })
+    }
+}
+
+impl FrameSink for SnapshotSink {
+    fn name(&self) -> &str {
+        "snapshot"
+    }
+
+    fn write(&mut self, frame: &Mat) -> Result<()> {
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        let name = format!(
+            "{}.{}",
+            Local::now().format("%Y%m%d-%H%M%S%.3f"),
+            self.config.format
+        );
+        let path = storage::resolve(&self.config.dir.join(name));
+        let metadata = self.config.metadata.then(|| Exif {
+            time: Some(Local::now()),
+            ..Exif::default()
+        });
+        let bytes = photo::encode(
+            &self.config.format,
+            self.config.quality,
+            frame,
+            metadata.as_ref(),
+        )?;
+        fs::write(&path, bytes).with_context(|| format!("write {}", path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use opencv::{
+        core::{Scalar, Size, CV_8UC3},
+        imgcodecs,
+        videoio::VideoCapture,
+    };
+
+    use super::*;
+
+    fn info() -> SinkInfo {
+        SinkInfo {
+            fps: 10.0,
+            size: Size::new(64, 48),
+        }
+    }
+
+    fn frame(level: f64) -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(level)).unwrap()
+    }
+
+    fn sink_config(text: &str) -> SinkConfig {
+        toml::from_str(text).unwrap()
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn count_frames(path: &Path) -> usize {
+        let mut video = VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).unwrap();
+        let mut frame = Mat::default();
+        let mut frames = 0;
+        while video.read(&mut frame).unwrap() && !frame.empty() {
+            frames += 1;
+        }
+        frames
+    }
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl FrameSink for Counting {
+        fn name(&self) -> &str {
+            "counting"
+        }
+
+        fn write(&mut self, _frame: &Mat) -> Result<()> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    // 注册的类型可以在配置中使用, 打不开的 sink 只记录错误.
+    #[test]
+    fn registry() {
+        let written = Arc::new(AtomicUsize::new(0));
+        let counter = written.clone();
+        register("counting-test", move |_, _| {
+            Ok(Box::new(Counting(counter.clone())))
+        });
+        let configs = [
+            sink_config("kind = \"counting-test\""),
+            sink_config("kind = \"carrier-pigeon\""),
+        ];
+        let mut sinks = Sinks::open(&configs, &info());
+        assert_eq!(sinks.len(), 1);
+        assert_eq!(
+            sinks.messages,
+            ["Recording to carrier-pigeon failed: unknown sink kind \"carrier-pigeon\""]
+        );
+        sinks.write(&frame(0.));
+        sinks.write(&frame(0.));
+        assert_eq!(written.load(Ordering::SeqCst), 2);
+        assert_eq!(sinks.paths().count(), 0);
+        sinks.close().unwrap();
+    }
+
+    #[cfg(feature = "record")]
+    #[test]
+    fn file_sink() {
+        let dir = temp_dir("sink-file");
+        let config = sink_config(&format!(
+            "kind = \"file\"\npath = {:?}\nfourcc = \"MJPG\"",
+            dir.join("clip-%Y.avi").display().to_string()
+        ));
+        let mut sinks = Sinks::single(&config, &info()).unwrap();
+        assert!(sinks.messages.is_empty());
+        let path = sinks.paths().next().unwrap().to_path_buf();
+        assert_eq!(
+            path,
+            dir.join(format!("clip-{}.avi", Local::now().format("%Y")))
+        );
+        for i in 0..4 {
+            sinks.write(&frame(i as f64 * 50.));
+        }
+        sinks.close().unwrap();
+        assert_eq!(count_frames(&path), 4);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn snapshot_interval() {
+        let dir = temp_dir("sink-snapshot");
+        let mut sink = SnapshotSink::new(SnapshotSinkConfig {
+            dir: dir.clone(),
+            format: "png".to_string(),
+            ..Default::default()
+        })
+        .unwrap();
+        sink.write(&frame(10.)).unwrap();
+        // 间隔内的帧不保存.
+        sink.write(&frame(20.)).unwrap();
+        let files: Vec<PathBuf> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files.len(), 1);
+        let image =
+            imgcodecs::imread(&files[0].to_string_lossy(), imgcodecs::IMREAD_COLOR).unwrap();
+        assert_eq!((image.cols(), image.rows()), (64, 48));
+        assert_eq!(
+            *image.at_2d::<core::Vec3b>(0, 0).unwrap(),
+            core::Vec3b::all(10)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 只有能写出内容的编码才算可用, 探测文件不保留.
+    #[test]
+    fn probe_codecs() {
+        let dir = temp_dir("sink-probe");
+        let path = dir.join("clip.avi");
+        assert!(probe(&path, fourcc("MJPG").unwrap(), &info()));
+        assert!(!probe(&path, fourcc("ZZZZ").unwrap(), &info()));
+        assert!(fourcc("MJPEG").is_err());
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(feature = "record")]
+    #[test]
+    fn file_sink_falls_back() {
+        let dir = temp_dir("sink-fallback");
+        let config = sink_config(&format!(
+            "kind = \"file\"\npath = {:?}\nfourcc = \"ZZZZ\"",
+            dir.join("clip.avi").display().to_string()
+        ));
+        let mut sinks = Sinks::single(&config, &info()).unwrap();
+        assert_eq!(sinks.messages.len(), 1);
+        assert!(sinks.messages[0].starts_with("Codec ZZZZ does not work for "));
+        for _ in 0..3 {
+            sinks.write(&frame(100.));
+        }
+        sinks.close().unwrap();
+        assert_eq!(count_frames(&dir.join("clip.avi")), 3);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn bitrate_and_size() {
+        assert_eq!(parse_bitrate("8M"), Some(8e6));
+        assert_eq!(parse_bitrate("800k"), Some(8e5));
+        assert_eq!(parse_bitrate("8000000"), Some(8e6));
+        assert_eq!(parse_bitrate("fast"), None);
+        assert_eq!(format_size(3.6e9), "3.6 GB");
+        assert_eq!(format_size(1.4e6), "1 MB");
+    }
+
+    // 64x48 10 FPS 每秒 30720 像素.
+    #[test]
+    fn estimate_per_hour() {
+        let estimate = |text: &str| estimate(&sink_config(text), &info()).unwrap();
+        assert_eq!(
+            estimate("kind = \"file\"\nfourcc = \"avc1\""),
+            30720.0 * 0.1 / 8.0 * 3600.0
+        );
+        // MJPG 随 quality 变化, 输出帧率和尺寸按 sink 的设置计算.
+        assert!(
+            estimate("kind = \"file\"\nfourcc = \"MJPG\"\nquality = 50.0")
+                < estimate("kind = \"file\"\nfourcc = \"MJPG\"")
+        );
+        assert!(
+            (estimate("kind = \"file\"\nfourcc = \"avc1\"\nfps = 5.0") * 2.0
+                - estimate("kind = \"file\"\nfourcc = \"avc1\""))
+            .abs()
+                < 1e-6
+        );
+        assert_eq!(
+            estimate("kind = \"ffmpeg\"\nbitrate = \"8M\""),
+            8e6 / 8.0 * 3600.0
+        );
+        assert_eq!(
+            estimate("kind = \"ffmpeg\"\nargs = [\"-b:v\", \"800k\", \"out.mkv\"]"),
+            8e5 / 8.0 * 3600.0
+        );
+        // CRF 每增加 6 码率减半.
+        assert!(
+            (estimate("kind = \"ffmpeg\"\ncrf = 29") * 2.0 - estimate("kind = \"ffmpeg\"")).abs()
+                < 1e-6
+        );
+        assert!(
+            (estimate("kind = \"ffmpeg\"\nargs = [\"-crf\", \"17\", \"out.mkv\"]")
+                - estimate("kind = \"ffmpeg\"") * 2.0)
+                .abs()
+                < 1e-6
+        );
+        assert_eq!(
+            estimate("kind = \"snapshot\"\nformat = \"png\"\ninterval = 2.0"),
+            3072.0 * 12.0 / 2.0 / 8.0 * 3600.0
+        );
+    }
+
+    #[test]
+    fn describe_unknown_size() {
+        assert!(estimate(&sink_config("kind = \"file\"\nfourcc = \"ZZZZ\""), &info()).is_none());
+        let config = sink_config("kind = \"carrier-pigeon\"\nname = \"birds\"");
+        assert_eq!(describe(&config, &info()), "birds: size unknown");
+        let config = sink_config("kind = \"ffmpeg\"\nbitrate = \"8M\"");
+        assert_eq!(describe(&config, &info()), "ffmpeg: about 3.6 GB per hour");
+    }
+
+    #[test]
+    fn ffmpeg_codecs() {
+        let config = FfmpegSinkConfig {
+            args: vec!["-f".into(), "mpegts".into(), "srt://127.0.0.1:9000".into()],
+            codec: Some("utvideo".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            output_args(&config).unwrap(),
+            [
+                "-f",
+                "mpegts",
+                "-c:v",
+                "utvideo",
+                "-pix_fmt",
+                "gbrp",
+                "srt://127.0.0.1:9000"
+            ]
+        );
+        let config = FfmpegSinkConfig {
+            codec: Some("huffyuv".to_string()),
+            ..config
+        };
+        assert!(output_args(&config).is_err());
+
+        let estimate = |text: &str| estimate(&sink_config(text), &info()).unwrap();
+        assert_eq!(
+            estimate("kind = \"ffmpeg\"\ncodec = \"ffv1\""),
+            30720.0 * 12.0 / 8.0 * 3600.0
+        );
+        assert_eq!(
+            estimate("kind = \"file\"\nfourcc = \"ULRG\""),
+            30720.0 * 14.0 / 8.0 * 3600.0
+        );
+    }
+
+    // 每次录制按当前时间生成新的文件名, 无效的格式原样保留.
+    #[test]
+    fn timestamped_paths() {
+        let year = Local::now().format("%Y").to_string();
+        assert_eq!(
+            timestamped(Path::new("clips/%Y/capture.mp4")),
+            Path::new("clips").join(&year).join("capture.mp4")
+        );
+        assert_eq!(timestamped(Path::new("plain.mp4")), Path::new("plain.mp4"));
+        assert_eq!(
+            timestamped(Path::new("bad-%Q.mp4")),
+            Path::new("bad-%Q.mp4")
+        );
+        let defaults = SinkConfig::defaults();
+        assert_eq!(defaults[0].options["path"].as_str(), Some(DEFAULT_PATH));
+        assert!(!timestamped(Path::new(DEFAULT_PATH))
+            .to_string_lossy()
+            .contains('%'));
+        assert!(Sinks::default().is_empty());
+    }
+
+    // 按输入帧数换算, 低于输入时丢帧, 高于输入时重复.
+    #[test]
+    fn frame_rate() {
+        assert!(FrameRate::new(30.0, 30.0).is_none());
+        assert!(FrameRate::new(0.0, 30.0).is_none());
+        let mut half = FrameRate::new(10.0, 5.0).unwrap();
+        let counts: Vec<u64> = (0..6).map(|_| half.next()).collect();
+        assert_eq!(counts, [1, 0, 1, 0, 1, 0]);
+        let mut faster = FrameRate::new(10.0, 25.0).unwrap();
+        let counts: Vec<u64> = (0..4).map(|_| faster.next()).collect();
+        assert_eq!(counts, [3, 2, 3, 2]);
+    }
+
+    #[test]
+    fn sink_frame_rates() {
+        let written = Arc::new(AtomicUsize::new(0));
+        let counter = written.clone();
+        register("counting-rate", move |_, info| {
+            assert_eq!(info.fps, 5.0);
+            Ok(Box::new(Counting(counter.clone())))
+        });
+        let config = sink_config("kind = \"counting-rate\"\nfps = 5.0");
+        assert_eq!(config.info(&info()).fps, 5.0);
+        let mut sinks = Sinks::single(&config, &info()).unwrap();
+        for _ in 0..10 {
+            sinks.write(&frame(0.));
+        }
+        assert_eq!(written.load(Ordering::SeqCst), 5);
+        sinks.close().unwrap();
+    }
+
+    // 只设置一项时按宽高比计算另一项, 结果取偶数.
+    #[test]
+    fn sink_sizes() {
+        let size = |text: &str| sink_config(text).info(&info()).size;
+        assert_eq!(size("kind = \"file\""), Size::new(64, 48));
+        assert_eq!(size("kind = \"file\"\nwidth = 32"), Size::new(32, 24));
+        assert_eq!(size("kind = \"file\"\nheight = 25"), Size::new(34, 25));
+        assert_eq!(
+            size("kind = \"file\"\nwidth = 20\nheight = 20"),
+            Size::new(20, 20)
+        );
+    }
+
+    // 同一帧缩放到同一尺寸只计算一次, 下一帧重新计算.
+    #[test]
+    fn scale_cache() {
+        let mut cache = ScaleCache::default();
+        let original = frame(10.);
+        let small = Size::new(32, 24);
+        cache.next_frame();
+        assert_eq!(
+            cache.get(&original, info().size).unwrap().data(),
+            original.data()
+        );
+        let first = cache.get(&original, small).unwrap().data();
+        assert_eq!(cache.get(&original, small).unwrap().data(), first);
+        assert_eq!(cache.entries.len(), 1);
+
+        cache.next_frame();
+        let next = frame(200.);
+        let scaled = cache.get(&next, small).unwrap();
+        assert_eq!((scaled.cols(), scaled.rows()), (32, 24));
+        assert_eq!(
+            *scaled.at_2d::<core::Vec3b>(0, 0).unwrap(),
+            core::Vec3b::all(200)
+        );
+        assert_eq!(cache.entries.len(), 1);
+    }
+
+    struct Sizes(Arc<Mutex<Vec<Size>>>);
+
+    impl FrameSink for Sizes {
+        fn name(&self) -> &str {
+            "sizes"
+        }
+
+        fn write(&mut self, frame: &Mat) -> Result<()> {
+            self.0.lock().unwrap().push(frame.size()?);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sinks_scale_frames() {
+        let sizes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = sizes.clone();
+        register("sizes-test", move |_, info| {
+            assert_eq!(info.size, Size::new(32, 24));
+            Ok(Box::new(Sizes(recorded.clone())))
+        });
+        let configs = [
+            sink_config("kind = \"sizes-test\"\nwidth = 32"),
+            sink_config("kind = \"sizes-test\"\nheight = 24"),
+        ];
+        let mut sinks = Sinks::open(&configs, &info());
+        sinks.write(&frame(0.));
+        assert_eq!(*sizes.lock().unwrap(), [Size::new(32, 24); 2]);
+        sinks.close().unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+use std::{
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{Mat, Size},
+    imgcodecs::{self, IMREAD_COLOR},
+    imgproc::{self, INTER_AREA},
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+use serde::Deserialize;
+
+use crate::{
+    clock,
+    config::Config,
+    osd::{Anchor, OsdInfo, OsdProfile},
+    source::{FrameSource, Metadata},
+};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlateConfig {
+    // 输出尺寸, 不设置时使用 [camera] 的分辨率, 再没有时使用图片本身的尺寸.
+    // 与摄像头相同时, 切换前后的录像和路由分辨率一致.
+    pub width: Option<i32>,
+    pub height: Option<i32>,
+    pub fps: f64,
+    // 显示当前时间的位置, 不设置时不显示.
+    pub clock: Option<Anchor>,
+}
+
+impl Default for SlateConfig {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            fps: 30.0,
+            clock: None,
+        }
+    }
+}
+
+enum Content {
+    Image(Mat),
+    // 短片播放到结尾后从头循环.
+    Clip(VideoCapture),
+}
+
+// 静态图片或循环播放的短片, 例如摄像头需要隐藏时显示的 "马上回来" 画面.
+// URI 为 slate://<图片或视频路径>.
+pub struct Slate {
+    content: Content,
+    size: Size,
+    clock: OsdProfile,
+    interval: Duration,
+    last: Option<Instant>,
+    scratch: Mat,
+    metadata: Metadata,
+}
+
+pub fn open(path: &str, config: &Config) -> Result<Box<dyn FrameSource>> {
+    let slate = &config.slate;
+    let image = imgcodecs::imread(path, IMREAD_COLOR)?;
+    let (content, native) = if !image.empty() {
+        let size = image.size()?;
+        (Content::Image(image), size)
+    } else {
+        let clip = VideoCapture::from_file(path, videoio::CAP_ANY)?;
+        if !clip.is_opened()? {
+            bail!("Unable to open slate {}", path);
+        }
+        let size = Size::new(
+            clip.get(videoio::CAP_PROP_FRAME_WIDTH)? as i32,
+            clip.get(videoio::CAP_PROP_FRAME_HEIGHT)? as i32,
+        );
+        (Content::Clip(clip), size)
+    };
+    let size = Size::new(
+        slate.width.or(config.camera.width).unwrap_or(native.width),
+        slate
+            .height
+            .or(config.camera.height)
+            .unwrap_or(native.height),
+    );
+    let fps = slate.fps.max(1.0);
+    Ok(Box::new(Slate {
+        content,
+        size,
+        clock: OsdProfile {
+            timestamp: slate.clock,
+            ..Default::default()
+        },
+        interval: Duration::from_secs_f64(1.0 / fps),
+        last: None,
+        scratch: Mat::default(),
+        metadata: Metadata {
+            description: format!("slate {}", path),
+            profile: "slate".to_string(),
+            width: size.width as f64,
+            height: size.height as f64,
+            fps,
+            live: true,
+        },
+    }))
+}
+
+impl FrameSource for Slate {
+    // 按配置的帧率输出, 和摄像头一样经过处理管线并写入所有输出.
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        if let Some(wait) = self
+            .last
+            .and_then(|last| self.interval.checked_sub(last.elapsed()))
+        {
+            sleep(wait);
+        }
+        self.last = Some(Instant::now());
+
+        let source = match &mut self.content {
+            Content::Image(image) => &*image,
+            Content::Clip(clip) => {
+                if !clip.read(&mut self.scratch)? || self.scratch.empty() {
+                    clip.set(videoio::CAP_PROP_POS_FRAMES, 0.0)?;
+                    clip.read(&mut self.scratch)?;
+                }
+                &self.scratch
+            }
+        };
+        if source.empty() {
+            return Ok(false);
+        }
+        if source.size()? == self.size {
+            source.copy_to(frame)?;
+        } else {
+            imgproc::resize(source, frame, self.size, 0.0, 0.0, INTER_AREA)?;
+        }
+        if !self.clock.is_empty() {
+            let info = OsdInfo {
+                time: chrono::Local::now(),
+                frame: 0,
+                fps: self.metadata.fps,
+                detections: 0,
+                caption: None,
+                telemetry: None,
+                gps: None,
+                clock: clock::state(),
+            };
+            self.clock.draw(frame, &info)?;
+        }
+        Ok(true)
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use opencv::{
+        core::{self, Scalar, Vec3b, Vector, CV_8UC3},
+        videoio::VideoWriter,
+    };
+
+    use super::*;
+    use crate::source;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn solid(level: f64) -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(level)).unwrap()
+    }
+
+    // 图片缩放到 [camera] 的分辨率, 按配置的帧率重复输出.
+    #[test]
+    fn image_slate() {
+        let dir = temp_dir("slate-image");
+        let path = dir.join("brb.png");
+        imgcodecs::imwrite(&path.to_string_lossy(), &solid(90.), &Vector::new()).unwrap();
+        let mut config = Config::default();
+        config.camera.width = Some(128);
+        config.camera.height = Some(96);
+        config.slate.fps = 20.0;
+        let mut slate = source::open(&format!("slate://{}", path.display()), &config).unwrap();
+        let metadata = slate.metadata().clone();
+        assert_eq!(
+            (metadata.width, metadata.height, metadata.fps),
+            (128.0, 96.0, 20.0)
+        );
+        assert!(metadata.live);
+        let mut frame = Mat::default();
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(slate.read(&mut frame).unwrap());
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert_eq!((frame.cols(), frame.rows()), (128, 96));
+        assert_eq!(*frame.at_2d::<Vec3b>(50, 50).unwrap(), Vec3b::all(90));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    // 短片播放到结尾后从头循环, 设置 clock 时叠加时间.
+    #[test]
+    fn clip_slate_loops() {
+        let dir = temp_dir("slate-clip");
+        let path = dir.join("loop.avi");
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        for level in [0., 100., 200.] {
+            writer.write(&solid(level)).unwrap();
+        }
+        writer.release().unwrap();
+
+        let mut config = Config::default();
+        config.slate.fps = 100.0;
+        let mut slate = source::open(&format!("slate://{}", path.display()), &config).unwrap();
+        let mut frame = Mat::default();
+        let mut levels = Vec::new();
+        for _ in 0..5 {
+            assert!(slate.read(&mut frame).unwrap());
+            levels.push(frame.at_2d::<Vec3b>(24, 32).unwrap()[0]);
+        }
+        let expected = [0u8, 100, 200, 0, 100];
+        assert!(levels
+            .iter()
+            .zip(expected)
+            .all(|(level, expected)| level.abs_diff(expected) <= 3));
+
+        config.slate.clock = Some(Anchor::TopLeft);
+        let mut slate = source::open(&format!("slate://{}", path.display()), &config).unwrap();
+        slate.read(&mut frame).unwrap();
+        let plain = solid(0.);
+        let changed = core::norm2(&frame, &plain, core::NORM_INF, &core::no_array()).unwrap();
+        assert!(changed > 50.0);
+
+        assert!(source::open("slate://missing-slate.png", &config).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,426 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{Mat, Size},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    config::Config,
+    events::EventStore,
+    health,
+    profile::CameraProfile,
+    routing,
+    sink::{self, SinkInfo, Sinks},
+    source::{self, Metadata},
+    stages::Stages,
+    synthetic::SKIPPED_FRAMES,
+    trajectory::Trajectories,
+};
+
+// 直方图每格 0.1 ms, 1 s 及以上都计入最后一格.
+const BUCKET_US: u64 = 100;
+const BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SoakConfig {
+    // 报告文件, 可以使用 strftime 格式. 测试过程中定期更新, 中途退出也留有结果.
+    pub report: PathBuf,
+    // 每隔多少分钟换一个录像文件, 检验反复打开和关闭输出.
+    pub segment: f64,
+    // 采样内存和更新报告的间隔 (秒).
+    pub interval: f64,
+    // 超过这些值时测试不通过.
+    pub max_drop_rate: f64,
+    pub max_rss_growth_mb: f64,
+    pub max_fd_growth: f64,
+    // 每帧从读取到录制完成的 p99 耗时 (毫秒), 不设置时不检查.
+    pub max_p99_ms: Option<f64>,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            report: PathBuf::from("soak-%Y-%m-%dT%H-%M-%S.json"),
+            segment: 60.0,
+            interval: 60.0,
+            max_drop_rate: 0.001,
+            max_rss_growth_mb: 10.0,
+            max_fd_growth: 1.0,
+            max_p99_ms: None,
+        }
+    }
+}
+
+// 一个阶段的耗时分布.
+struct Timings {
+    buckets: Vec<u64>,
+    count: u64,
+    total: Duration,
+    max: Duration,
+}
+
+impl Timings {
+    fn new() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            total: Duration::ZERO,
+            max: Duration::ZERO,
+        }
+    }
+
+    fn add(&mut self, time: Duration) {
+        let bucket = (time.as_micros() as u64 / BUCKET_US).min(BUCKETS as u64 - 1);
+        self.buckets[bucket as usize] += 1;
+        self.count += 1;
+        self.total += time;
+        self.max = self.max.max(time);
+    }
+
+    // 百分位数 (毫秒), 取所在格的上限.
+    fn percentile(&self, fraction: f64) -> f64 {
+        let rank = ((self.count as f64 * fraction).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return ((bucket as u64 + 1) * BUCKET_US) as f64 / 1000.0;
+            }
+        }
+        ms(self.max)
+    }
+
+    fn summary(&self) -> Value {
+        if self.count == 0 {
+            return Value::Null;
+        }
+        json!({
+            "mean": ms(self.total) / self.count as f64,
+            "p50": self.percentile(0.5),
+            "p90": self.percentile(0.9),
+            "p99": self.percentile(0.99),
+            "p999": self.percentile(0.999),
+            "max": ms(self.max),
+        })
+    }
+}
+
+struct Sample {
+    elapsed: f64,
+    rss_mb: f64,
+    fds: f64,
+    frames: u64,
+    dropped: u64,
+}
+
+// 无界面地连续采集, 处理和录制 hours 小时, 统计丢帧, 内存增长和各阶段耗时, 写出 JSON 报告.
+// 没有设置来源时使用合成画面, 结果不受摄像头影响. 不通过时返回错误, 进程以非零状态退出.
+pub fn run(config: &Config, hours: f64) -> Result<()> {
+    let settings = &config.soak;
+    let uri = config
+        .source
+        .clone()
+        .unwrap_or_else(|| "synthetic://".to_string());
+    let mut input = source::open(&uri, config)?;
+    let metadata = input.metadata().clone();
+    let info = SinkInfo {
+        fps: metadata.fps,
+        size: Size::new(metadata.width as i32, metadata.height as i32),
+    };
+    let profile_dir = CameraProfile::dir(&config.profiles_dir, &metadata.profile);
+    let profile = CameraProfile::load(&profile_dir)?;
+    let events = EventStore::open(&config.events_file)?;
+    let trajectories = Trajectories::default();
+    let stages = Stages {
+        config,
+        profile: &profile,
+        profile_dir: &profile_dir,
+        events: &events,
+        frame_size: (info.size.width, info.size.height),
+        window: None,
+        models_ready: None,
+    };
+    let mut pipeline = stages.pipeline(&trajectories)?;
+    let configured = routing::main_sinks(config).count();
+    let report_path = sink::timestamped(&settings.report);
+
+    let duration = Duration::from_secs_f64(hours.max(0.0) * 3600.0);
+    let segment = Duration::from_secs_f64(settings.segment.max(1.0) * 60.0);
+    let interval = Duration::from_secs_f64(settings.interval.max(1.0));
+    println!(
+        "Soak test: {} for {:.1} hours, {} outputs, report {}",
+        metadata.description,
+        hours,
+        configured,
+        report_path.display()
+    );
+
+    let started = Local::now();
+    let start = Instant::now();
+    let skipped = SKIPPED_FRAMES.load(Ordering::Relaxed);
+    let mut soak = Soak {
+        read: Timings::new(),
+        process: Timings::new(),
+        record: Timings::new(),
+        frame: Timings::new(),
+        frames: 0,
+        failures: 0,
+        segments: 0,
+        lost_sinks: 0,
+        sink_errors: Vec::new(),
+        samples: vec![sample(0.0, 0, 0)],
+    };
+    let mut sinks = soak.open(config, &info);
+    let mut segment_start = Instant::now();
+    let mut last_sample = Instant::now();
+    let mut frame = Mat::default();
+    while start.elapsed() < duration {
+        let begin = Instant::now();
+        let ok = input.read(&mut frame).unwrap_or_else(|err| {
+            eprintln!("soak: read {}: {:?}", uri, err);
+            false
+        });
+        if !ok || frame.empty() {
+            soak.failures += 1;
+            std::thread::sleep(Duration::from_millis(10));
+            continue;
+        }
+        let read = Instant::now();
+        pipeline.process(&mut frame);
+        for failure in pipeline.take_failures() {
+            eprintln!(
+                "{} bypassed after repeated errors: {}",
+                failure.stage, failure.error
+            );
+        }
+        let processed = Instant::now();
+        let open = sinks.len();
+        sinks.write(&frame);
+        soak.lost_sinks += open - sinks.len();
+        let recorded = Instant::now();
+        soak.read.add(read - begin);
+        soak.process.add(processed - read);
+        soak.record.add(recorded - processed);
+        soak.frame.add(recorded - begin);
+        soak.frames += 1;
+
+        if segment_start.elapsed() >= segment {
+            // 先关闭旧文件再打开新文件, 与停止后重新录制相同.
+            drop(sinks);
+            sinks = soak.open(config, &info);
+            segment_start = Instant::now();
+        }
+        if last_sample.elapsed() >= interval {
+            last_sample = Instant::now();
+            let dropped = SKIPPED_FRAMES.load(Ordering::Relaxed) - skipped;
+            soak.samples
+                .push(sample(start.elapsed().as_secs_f64(), soak.frames, dropped));
+            let report = soak.report(config, &metadata, hours, started, dropped, false);
+            write_report(&report_path, &report)?;
+            println!(
+                "soak: {:.2}h, {} frames, {} dropped, RSS {:.0} MB",
+                start.elapsed().as_secs_f64() / 3600.0,
+                soak.frames,
+                dropped,
+                soak.samples.last().map_or(0.0, |sample| sample.rss_mb)
+            );
+        }
+    }
+    drop(sinks);
+    sink::wait_finished();
+    let dropped = SKIPPED_FRAMES.load(Ordering::Relaxed) - skipped;
+    soak.samples
+        .push(sample(start.elapsed().as_secs_f64(), soak.frames, dropped));
+    let report = soak.report(config, &metadata, hours, started, dropped, true);
+    write_report(&report_path, &report)?;
+    println!("Soak report written to {}", report_path.display());
+    let failures: Vec<String> = report["result"]["failures"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|failure| failure.as_str().map(str::to_string))
+        .collect();
+    if !failures.is_empty() {
+        bail!("soak test failed: {}", failures.join("; "));
+    }
+    println!("Soak test passed");
+    Ok(())
+}
+
+struct Soak {
+    read: Timings,
+    process: Timings,
+    record: Timings,
+    frame: Timings,
+    frames: u64,
+    // 读不到帧的次数, 合成来源不应该出现.
+    failures: u64,
+    segments: u64,
+    // 写入出错而被关闭的输出.
+    lost_sinks: usize,
+    sink_errors: Vec<String>,
+    samples: Vec<Sample>,
+}
+
+impl Soak {
+    fn open(&mut self, config: &Config, info: &SinkInfo) -> Sinks {
+        let mut sinks = Sinks::open(routing::main_sinks(config), info);
+        self.segments += 1;
+        self.sink_errors.append(&mut sinks.messages);
+        self.lost_sinks += routing::main_sinks(config).count() - sinks.len();
+        sinks
+    }
+
+    fn report(
+        &self,
+        config: &Config,
+        metadata: &Metadata,
+        hours: f64,
+        started: DateTime<Local>,
+        dropped: u64,
+        finished: bool,
+    ) -> Value {
+        let settings = &config.soak;
+        let (first, last) = (&self.samples[0], &self.samples[self.samples.len() - 1]);
+        let peak = self
+            .samples
+            .iter()
+            .map(|sample| sample.rss_mb)
+            .fold(0.0, f64::max);
+        // 运行超过两小时时跳过第一个小时的采样, 启动时分配的缓存和模型不算作增长.
+        let settled: Vec<&Sample> = self
+            .samples
+            .iter()
+            .filter(|sample| sample.elapsed >= 3600.0 || last.elapsed < 2.0 * 3600.0)
+            .collect();
+        let hours_per_sample = settings.interval.max(1.0) / 3600.0;
+        let rss_growth =
+            health::slope(settled.iter().map(|sample| sample.rss_mb)) / hours_per_sample;
+        let fd_growth = health::slope(settled.iter().map(|sample| sample.fds)) / hours_per_sample;
+        let expected = self.frames + dropped;
+        let drop_rate = if expected > 0 {
+            dropped as f64 / expected as f64
+        } else {
+            0.0
+        };
+
+        let mut failures = Vec::new();
+        if drop_rate > settings.max_drop_rate {
+            failures.push(format!(
+                "dropped {:.3}% of frames, limit {:.3}%",
+                drop_rate * 100.0,
+                settings.max_drop_rate * 100.0
+            ));
+        }
+        if self.failures > 0 {
+            failures.push(format!("{} failed reads", self.failures));
+        }
+        if self.lost_sinks > 0 {
+            failures.push(format!(
+                "{} outputs failed to open or closed on errors",
+                self.lost_sinks
+            ));
+        }
+        // 采样太少时趋势没有意义.
+        if settled.len() >= 10 {
+            if rss_growth > settings.max_rss_growth_mb {
+                failures.push(format!("memory grows {:.1} MB per hour", rss_growth));
+            }
+            if fd_growth > settings.max_fd_growth {
+                failures.push(format!("open files grow {:.1} per hour", fd_growth));
+            }
+        }
+        let p99 = self.frame.percentile(0.99);
+        if settings
+            .max_p99_ms
+            .is_some_and(|max| self.frame.count > 0 && p99 > max)
+        {
+            failures.push(format!("p99 frame time {:.1} ms", p99));
+        }
+
+        json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "source": metadata.description,
+            "width": metadata.width,
+            "height": metadata.height,
+            "fps": metadata.fps,
+            "hours": hours,
+            "started": started.to_rfc3339(),
+            "updated": Local::now().to_rfc3339(),
+            "elapsed_seconds": last.elapsed,
+            "finished": finished,
+            "frames": {
+                "captured": self.frames,
+                "dropped": dropped,
+                "drop_rate": drop_rate,
+                "read_failures": self.failures,
+            },
+            "outputs": {
+                "configured": routing::main_sinks(config).count(),
+                "segments": self.segments,
+                "lost": self.lost_sinks,
+                "errors": self.sink_errors,
+            },
+            "memory": {
+                "start_mb": first.rss_mb,
+                "end_mb": last.rss_mb,
+                "peak_mb": peak,
+                "growth_mb_per_hour": rss_growth,
+                "fds_start": first.fds,
+                "fds_end": last.fds,
+                "fd_growth_per_hour": fd_growth,
+            },
+            "timings_ms": {
+                "read": self.read.summary(),
+                "process": self.process.summary(),
+                "record": self.record.summary(),
+                "frame": self.frame.summary(),
+            },
+            "samples": self.samples.iter().map(|sample| json!({
+                "elapsed": sample.elapsed,
+                "rss_mb": sample.rss_mb,
+                "fds": sample.fds,
+                "frames": sample.frames,
+                "dropped": sample.dropped,
+            })).collect::<Vec<_>>(),
+            "result": {
+                "passed": finished && failures.is_empty(),
+                "failures": failures,
+            },
+        })
+    }
+}
+
+fn sample(elapsed: f64, frames: u64, dropped: u64) -> Sample {
+    Sample {
+        elapsed,
+        rss_mb: health::rss_bytes().unwrap_or(0) as f64 / 1048576.0,
+        fds: health::open_fds().unwrap_or(0) as f64,
+        frames,
+        dropped,
+    }
+}
+
+// 先写临时文件再改名, 读取报告的工具不会看到写了一半的文件.
+fn write_report(path: &Path, report: &Value) -> Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    fs::write(&temp, serde_json::to_string_pretty(report)?)?;
+    fs::rename(&temp, path)?;
+    Ok(())
+}
+
+fn ms(time: Duration) -> f64 {
+    time.as_secs_f64() * 1000.0
+}
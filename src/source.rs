@@ -0,0 +1,357 @@
+use std::{collections::BTreeMap, sync::Mutex};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::Mat,
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+
+use crate::{
+    camera,
+    config::Config,
+    crash, faults,
+    libcamera::{self, Libcamera},
+    presentation, raw, slate, synthetic,
+};
+
+// 来源的基本参数. profile 为对应的 camera profile 名称.
+#[derive(Debug, Clone)]
+pub struct Metadata {
+    pub description: String,
+    pub profile: String,
+    pub width: f64,
+    pub height: f64,
+    pub fps: f64,
+    // 实时来源 (摄像头, 网络流, 屏幕), 文件为 false.
+    pub live: bool,
+}
+
+// 视频输入, 由采集线程读取 BGR 帧. 通过 register 注册的构造函数打开.
+pub trait FrameSource: Send {
+    // 没有新帧时返回 false 或空帧.
+    fn read(&mut self, frame: &mut Mat) -> Result<bool>;
+    fn metadata(&self) -> &Metadata;
+
+    // 设置 OpenCV 属性 (CAP_PROP_*), 不支持时返回 false.
+    fn reconfigure(&mut self, property: i32, value: f64) -> Result<bool> {
+        match self.capture() {
+            Some(camera) => Ok(camera.set(property, value)?),
+            None => Ok(false),
+        }
+    }
+
+    // 基于 VideoCapture 的来源返回它, 用于硬件白平衡等直接控制摄像头的功能.
+    fn capture(&mut self) -> Option<&mut VideoCapture> {
+        None
+    }
+}
+
+// 参数为 URI 中 "scheme://" 之后的部分.
+pub type SourceFactory = Box<dyn Fn(&str, &Config) -> Result<Box<dyn FrameSource>> + Send>;
+
+// 库使用者和插件注册的来源: scheme -> 构造函数.
+static FACTORIES: Mutex<BTreeMap<String, SourceFactory>> = Mutex::new(BTreeMap::new());
+
+// 注册自定义来源, 之后可以用 "scheme://..." 打开. 同名时覆盖内置来源.
+pub fn register(
+    scheme: &str,
+    factory: impl Fn(&str, &Config) -> Result<Box<dyn FrameSource>> + Send + 'static,
+) {
+    FACTORIES
+        .lock()
+        .unwrap()
+        .insert(scheme.to_string(), Box::new(factory));
+}
+
+// 没有指定来源时按 [camera] 和 [libcamera] 配置选择.
+pub fn default_uri(config: &Config) -> String {
+    match &config.source {
+        Some(uri) => uri.clone(),
+        None if config.libcamera.enabled => "libcamera://".to_string(),
+        None => "camera://".to_string(),
+    }
+}
+
+// 打开 URI 指定的来源, 没有 scheme 时作为文件路径.
+pub fn open(uri: &str, config: &Config) -> Result<Box<dyn FrameSource>> {
+    open_source(uri, config, true)
+}
+
+// 断开后重新打开来源. 摄像头打不开时直接返回错误, 不弹窗询问.
+pub fn reopen(uri: &str, config: &Config) -> Result<Box<dyn FrameSource>> {
+    open_source(uri, config, false)
+}
+
+fn open_source(uri: &str, config: &Config, interactive: bool) -> Result<Box<dyn FrameSource>> {
+    faults::check_open(&config.faults)?;
+    let (scheme, rest) = uri.split_once("://").unwrap_or(("file", uri));
+    let custom = FACTORIES
+        .lock()
+        .unwrap()
+        .get(scheme)
+        .map(|factory| factory(rest, config));
+    let source = match custom {
+        Some(source) => source?,
+        None => match scheme {
+            "camera" => open_camera(rest, config, interactive)?,
+            "libcamera" => open_libcamera(config)?,
+            "file" => open_file(rest, config)?,
+            "rtsp" | "rtsps" | "http" | "https" => open_stream(uri, config)?,
+            "screen" => open_screen(rest)?,
+            "presentation" => presentation::open(rest, config)?,
+            "slate" => slate::open(rest, config)?,
+            "synthetic" => synthetic::open(rest, config)?,
+            _ => bail!("unknown source {:?}", uri),
+        },
+    };
+    let source = faults::wrap(source, &config.faults);
+    let metadata = source.metadata();
+    crash::set_state("source", metadata.description.clone());
+    println!(
+        "{}: width {}, height {}, FPS: {}",
+        metadata.description, metadata.width, metadata.height, metadata.fps
+    );
+    Ok(source)
+}
+
+// 为空时按 [camera] 配置选择, 否则为编号或设备名称.
+fn open_camera(selector: &str, config: &Config, interactive: bool) -> Result<Box<dyn FrameSource>> {
+    let device = match selector {
+        "" => camera::select(&config.camera)?,
+        selector => camera::find(&config.camera, selector)?,
+    };
+    let mut camera = if interactive {
+        camera::open(&device)?
+    } else {
+        camera::try_open(&device)?
+    };
+    if let (Some(width), Some(height)) = (config.camera.width, config.camera.height) {
+        camera::set_resolution(&mut camera, width, height)?;
+    }
+    if config.raw.enabled && !raw::configure(&mut camera, &config.raw)? {
+        eprintln!(
+            "camera does not accept {:?}, using 8-bit frames",
+            config.raw.fourcc
+        );
+    }
+    let profile = format!("camera-{}", device.index);
+    let source = CaptureSource::new(camera, device.to_string(), profile, true)?;
+    source.warm_up(config)
+}
+
+fn open_libcamera(config: &Config) -> Result<Box<dyn FrameSource>> {
+    let (camera, libcamera) = Libcamera::open(&config.libcamera)?;
+    let description = format!("libcamera ({:?})", config.libcamera.method);
+    let mut source = CaptureSource::new(camera, description, "libcamera".to_string(), true)?;
+    source.libcamera = Some(libcamera);
+    source.warm_up(config)
+}
+
+// 文件使用 [camera] index 对应的 camera profile, 与录制时一致.
+fn open_file(path: &str, config: &Config) -> Result<Box<dyn FrameSource>> {
+    let camera = VideoCapture::from_file(path, videoio::CAP_ANY)?;
+    let profile = format!("camera-{}", config.camera.index);
+    Ok(Box::new(CaptureSource::new(
+        camera,
+        path.to_string(),
+        profile,
+        false,
+    )?))
+}
+
+// 断开后由采集线程重新打开.
+fn open_stream(uri: &str, config: &Config) -> Result<Box<dyn FrameSource>> {
+    let camera = VideoCapture::from_file(uri, videoio::CAP_FFMPEG)?;
+    let profile = format!("camera-{}", config.camera.index);
+    Ok(Box::new(CaptureSource::new(
+        camera,
+        uri.to_string(),
+        profile,
+        true,
+    )?))
+}
+
+// 通过 GStreamer 采集屏幕, 参数为显示器编号 (Linux 上为 X display 编号).
+fn open_screen(screen: &str) -> Result<Box<dyn FrameSource>> {
+    let index: u32 = match screen {
+        "" => 0,
+        screen => screen.parse()?,
+    };
+    let element = if cfg!(target_os = "windows") {
+        format!("d3d11screencapturesrc monitor-index={}", index)
+    } else if cfg!(target_os = "macos") {
+        format!("avfvideosrc capture-screen=true device-index={}", index)
+    } else {
+        format!("ximagesrc display-name=:{} use-damage=false", index)
+    };
+    let pipeline = format!("{} ! {}", element, libcamera::APPSINK);
+    let camera = VideoCapture::from_file(&pipeline, videoio::CAP_GSTREAMER)?;
+    Ok(Box::new(CaptureSource::new(
+        camera,
+        format!("screen {}", index),
+        format!("screen-{}", index),
+        true,
+    )?))
+}
+
+// OpenCV VideoCapture 来源, 内置的来源都使用它.
+pub struct CaptureSource {
+    camera: VideoCapture,
+    // rpicam-vid 子进程需要一直运行到 camera 关闭.
+    libcamera: Option<Libcamera>,
+    metadata: Metadata,
+}
+
+impl CaptureSource {
+    pub fn new(
+        camera: VideoCapture,
+        description: String,
+        profile: String,
+        live: bool,
+    ) -> Result<Self> {
+        if !camera.is_opened()? {
+            bail!("Unable to open {}", description);
+        }
+        let metadata = Metadata {
+            description,
+            profile,
+            width: camera.get(videoio::CAP_PROP_FRAME_WIDTH)?,
+            height: camera.get(videoio::CAP_PROP_FRAME_HEIGHT)?,
+            fps: camera.get(videoio::CAP_PROP_FPS)?,
+            live,
+        };
+        Ok(Self {
+            camera,
+            libcamera: None,
+            metadata,
+        })
+    }
+
+    fn warm_up(mut self, config: &Config) -> Result<Box<dyn FrameSource>> {
+        camera::warm_up(&mut self.camera, config.camera.warmup)?;
+        Ok(Box::new(self))
+    }
+}
+
+impl FrameSource for CaptureSource {
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        Ok(self.camera.read(frame)?)
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn capture(&mut self) -> Option<&mut VideoCapture> {
+        Some(&mut self.camera)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use opencv::core::{Scalar, Size, CV_8UC3};
+    use videoio::VideoWriter;
+
+    use super::*;
+
+    // 只产生纯色帧的来源.
+    struct Solid {
+        metadata: Metadata,
+    }
+
+    impl FrameSource for Solid {
+        fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+            *frame = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(128.))?;
+            Ok(true)
+        }
+
+        fn metadata(&self) -> &Metadata {
+            &self.metadata
+        }
+    }
+
+    fn write_video(path: &Path, frames: u32) {
+        let fourcc = VideoWriter::fourcc('M', 'J', 'P', 'G').unwrap();
+        let mut writer = VideoWriter::new(
+            &path.to_string_lossy(),
+            fourcc,
+            10.0,
+            Size::new(64, 48),
+            true,
+        )
+        .unwrap();
+        for _ in 0..frames {
+            let frame = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(50.)).unwrap();
+            writer.write(&frame).unwrap();
+        }
+    }
+
+    #[test]
+    fn default_uri_by_config() {
+        let mut config = Config::default();
+        assert_eq!(default_uri(&config), "camera://");
+        config.libcamera.enabled = true;
+        assert_eq!(default_uri(&config), "libcamera://");
+        config.source = Some("synthetic://".to_string());
+        assert_eq!(default_uri(&config), "synthetic://");
+    }
+
+    #[test]
+    fn custom_scheme() {
+        register("solid-test", |params, _| {
+            Ok(Box::new(Solid {
+                metadata: Metadata {
+                    description: format!("solid {}", params),
+                    profile: "solid".to_string(),
+                    width: 64.,
+                    height: 48.,
+                    fps: 10.,
+                    live: true,
+                },
+            }))
+        });
+        let mut source = open("solid-test://grey", &Config::default()).unwrap();
+        assert_eq!(source.metadata().description, "solid grey");
+        assert!(source.capture().is_none());
+        assert!(!source.reconfigure(videoio::CAP_PROP_FPS, 30.).unwrap());
+        let mut frame = Mat::default();
+        assert!(source.read(&mut frame).unwrap());
+        assert_eq!(frame.cols(), 64);
+    }
+
+    #[test]
+    fn unknown_scheme() {
+        let error = open("pigeon://coop", &Config::default()).err().unwrap();
+        assert!(error.to_string().contains("unknown source"));
+    }
+
+    // 没有 scheme 时作为文件打开, 使用 [camera] index 对应的 profile.
+    #[test]
+    fn file_source() {
+        let dir = std::env::temp_dir().join(format!("source-file-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("clip.avi");
+        write_video(&path, 3);
+        let mut config = Config::default();
+        config.camera.index = 2;
+        let mut source = open(&path.to_string_lossy(), &config).unwrap();
+        let metadata = source.metadata().clone();
+        assert_eq!(metadata.profile, "camera-2");
+        assert_eq!((metadata.width, metadata.height), (64., 48.));
+        assert_eq!(metadata.fps, 10.);
+        assert!(!metadata.live);
+        assert!(source.capture().is_some());
+        let mut frame = Mat::default();
+        let mut frames = 0;
+        while source.read(&mut frame).unwrap() && !frame.empty() {
+            frames += 1;
+        }
+        assert_eq!(frames, 3);
+
+        assert!(open(&dir.join("missing.avi").to_string_lossy(), &config).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,299 @@
+use std::time::Instant;
+
+use anyhow::Result;
+use opencv::{
+    core::{Mat, Point, Point2d, Ptr, Scalar, Vector},
+    imgproc::{self, CHAIN_APPROX_SIMPLE, LINE_AA, RETR_EXTERNAL},
+    prelude::*,
+    video::{self, BackgroundSubtractorMOG2},
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{events::EventStore, pipeline::FrameProcessor};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpeedConfig {
+    pub enabled: bool,
+    // 运动目标的最小面积 (像素), 过滤噪声.
+    pub min_area: f64,
+    // 相邻两帧位移超过该值视为新的目标.
+    pub max_jump: f64,
+    // 目标消失多少帧后结束本次测量并记录.
+    pub lost_frames: u32,
+    // km/h 或 m/s.
+    pub unit: String,
+}
+
+impl Default for SpeedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_area: 100.0,
+            max_jump: 300.0,
+            lost_frames: 10,
+            unit: "km/h".to_string(),
+        }
+    }
+}
+
+impl SpeedConfig {
+    fn convert(&self, meters_per_second: f64) -> f64 {
+        if self.unit == "m/s" {
+            meters_per_second
+        } else {
+            meters_per_second * 3.6
+        }
+    }
+}
+
+// 一次连续跟踪.
+struct Track {
+    last: Point2d,
+    at: Instant,
+    points: usize,
+    speed: f64,
+    max_speed: f64,
+    total_speed: f64,
+}
+
+// 用背景建模找出最大的运动目标, 按 m/px 标定把位移换算成速度.
+pub struct SpeedEstimation {
+    config: SpeedConfig,
+    meters_per_px: Option<f64>,
+    events: EventStore,
+    subtractor: Ptr<BackgroundSubtractorMOG2>,
+    mask: Mat,
+    track: Option<Track>,
+    missing: u32,
+}
+
+impl SpeedEstimation {
+    pub fn new(
+        config: &SpeedConfig,
+        meters_per_px: Option<f64>,
+        events: EventStore,
+    ) -> Result<Self> {
+        Ok(Self {
+            config: config.clone(),
+            meters_per_px,
+            events,
+            subtractor: video::create_background_subtractor_mog2(300, 32.0, false)?,
+            mask: Mat::default(),
+            track: None,
+            missing: 0,
+        })
+    }
+
+    fn finish(&mut self) {
+        if let Some(track) = self.track.take() {
+            // 太短的轨迹多半是噪声, 不记录.
+            if track.points >= 5 && self.meters_per_px.is_some() {
+                let samples = (track.points - 1) as f64;
+                self.events.log(
+                    "speed",
+                    json!({
+                        "unit": self.config.unit,
+                        "max": self.config.convert(track.max_speed),
+                        "average": self.config.convert(track.total_speed / samples),
+                        "frames": track.points,
+                    }),
+                );
+            }
+        }
+    }
+}
+
+impl FrameProcessor for SpeedEstimation {
+    fn name(&self) -> &str {
+        "speed"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        self.subtractor.apply(frame, &mut self.mask, -1.0)?;
+        let mut contours = Vector::<Vector<Point>>::new();
+        imgproc::find_contours(
+            &self.mask,
+            &mut contours,
+            RETR_EXTERNAL,
+            CHAIN_APPROX_SIMPLE,
+            Point::new(0, 0),
+        )?;
+        let mut largest = None;
+        let mut largest_area = self.config.min_area;
+        for contour in contours.iter() {
+            let area = imgproc::contour_area(&contour, false)?;
+            if area > largest_area {
+                largest_area = area;
+                largest = Some(imgproc::bounding_rect(&contour)?);
+            }
+        }
+
+        let Some(rect) = largest else {
+            self.missing += 1;
+            if self.missing > self.config.lost_frames {
+                self.finish();
+            }
+            return Ok(());
+        };
+        self.missing = 0;
+        let center = Point2d::new(
+            rect.x as f64 + rect.width as f64 / 2.0,
+            rect.y as f64 + rect.height as f64 / 2.0,
+        );
+        let now = Instant::now();
+        let jumped = self.track.as_ref().map_or(false, |t| {
+            ((center.x - t.last.x).powi(2) + (center.y - t.last.y).powi(2)).sqrt()
+                > self.config.max_jump
+        });
+        if jumped {
+            self.finish();
+        }
+        match &mut self.track {
+            Some(track) => {
+                let dt = now.duration_since(track.at).as_secs_f64();
+                let px =
+                    ((center.x - track.last.x).powi(2) + (center.y - track.last.y).powi(2)).sqrt();
+                if let (Some(scale), true) = (self.meters_per_px, dt > 0.0) {
+                    let speed = px * scale / dt;
+                    // 平滑瞬时速度, 减小检测框抖动的影响.
+                    track.speed = if track.points == 1 {
+                        speed
+                    } else {
+                        track.speed * 0.6 + speed * 0.4
+                    };
+                    track.max_speed = track.max_speed.max(track.speed);
+                    track.total_speed += track.speed;
+                }
+                track.last = center;
+                track.at = now;
+                track.points += 1;
+            }
+            None => {
+                self.track = Some(Track {
+                    last: center,
+                    at: now,
+                    points: 1,
+                    speed: 0.0,
+                    max_speed: 0.0,
+                    total_speed: 0.0,
+                })
+            }
+        }
+
+        let color = Scalar::new(0., 165., 255., 0.);
+        imgproc::rectangle(frame, rect, color, 2, LINE_AA, 0)?;
+        let label = match (&self.track, self.meters_per_px) {
+            (Some(track), Some(_)) => {
+                format!(
+                    "{:.1} {}",
+                    self.config.convert(track.speed),
+                    self.config.unit
+                )
+            }
+            _ => "not calibrated".to_string(),
+        };
+        imgproc::put_text(
+            frame,
+            &label,
+            Point::new(rect.x, (rect.y - 8).max(16)),
+            imgproc::FONT_HERSHEY_SIMPLEX,
+            0.7,
+            color,
+            2,
+            LINE_AA,
+            false,
+        )?;
+        Ok(())
+    }
+}
+
+// 在画面上点击两点并给出实际距离 (米), 得到 m/px.
+pub struct SpeedCalibration {
+    meters: f64,
+    first: Option<Point>,
+}
+
+impl SpeedCalibration {
+    pub fn new(meters: f64) -> Self {
+        Self {
+            meters,
+            first: None,
+        }
+    }
+
+    pub fn click(&mut self, p: Point) -> Option<f64> {
+        let Some(first) = self.first.replace(p) else {
+            return None;
+        };
+        let px = (((p.x - first.x) as f64).powi(2) + ((p.y - first.y) as f64).powi(2)).sqrt();
+        if px < 1.0 || self.meters <= 0.0 {
+            self.first = None;
+            return None;
+        }
+        Some(self.meters / px)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events;
+
+    #[test]
+    fn units() {
+        let config = SpeedConfig::default();
+        assert_eq!(config.convert(10.0), 36.0);
+        let config = SpeedConfig {
+            unit: "m/s".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.convert(10.0), 10.0);
+    }
+
+    #[test]
+    fn calibration() {
+        let mut calibration = SpeedCalibration::new(5.0);
+        assert_eq!(calibration.click(Point::new(10, 10)), None);
+        assert_eq!(calibration.click(Point::new(13, 14)), Some(1.0));
+        // 两次点在同一处无法标定, 重新开始.
+        let mut calibration = SpeedCalibration::new(5.0);
+        calibration.click(Point::new(10, 10));
+        assert_eq!(calibration.click(Point::new(10, 10)), None);
+        assert_eq!(calibration.click(Point::new(20, 10)), None);
+        assert_eq!(calibration.click(Point::new(30, 10)), Some(0.5));
+    }
+
+    fn track(points: usize) -> Track {
+        Track {
+            last: Point2d::new(0.0, 0.0),
+            at: Instant::now(),
+            points,
+            speed: 4.0,
+            max_speed: 10.0,
+            total_speed: 20.0,
+        }
+    }
+
+    #[test]
+    fn logs_finished_tracks() {
+        let path = std::env::temp_dir().join(format!("speed-test-{}.jsonl", std::process::id()));
+        let store = EventStore::open(&path).unwrap();
+        let mut speed = SpeedEstimation::new(&SpeedConfig::default(), Some(0.05), store).unwrap();
+        // 太短的轨迹不记录.
+        speed.track = Some(track(3));
+        speed.finish();
+        speed.track = Some(track(5));
+        speed.finish();
+        assert!(speed.track.is_none());
+        let logged = events::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].kind, "speed");
+        assert_eq!(logged[0].data["unit"], "km/h");
+        assert_eq!(logged[0].data["max"], 36.0);
+        assert_eq!(logged[0].data["average"], 18.0);
+        assert_eq!(logged[0].data["frames"], 5);
+    }
+}
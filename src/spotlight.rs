@@ -0,0 +1,176 @@
+use std::{
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Size},
+    imgproc::{self, COLOR_BGR2GRAY, COLOR_GRAY2BGR, INTER_LINEAR},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    pipeline::FrameProcessor,
+    segment::Segmenter,
+    threads::{self, ThreadConfig},
+};
+
+// 聚光灯效果: 用人像分割保持主体原样, 压暗并降低背景的饱和度, 比替换背景更自然.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SpotlightConfig {
+    pub enabled: bool,
+    // 分割模型名称 (见 [models]), task 为 segmentation.
+    pub model: Option<String>,
+    // 多类别模型中人的类别 ID, 单通道的人像模型忽略.
+    pub class: usize,
+    // 每秒最多分割的帧数, 分割在后台线程运行.
+    pub fps: f64,
+    // 背景压暗的程度, 0 为不变, 1 为全黑.
+    pub strength: f64,
+    // 背景去饱和的程度, 0 为不变, 1 为灰度.
+    pub desaturate: f64,
+    // 主体边缘的羽化宽度 (像素), 取奇数.
+    pub feather: i32,
+}
+
+impl Default for SpotlightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            class: 15,
+            fps: 10.0,
+            strength: 0.5,
+            desaturate: 0.6,
+            feather: 21,
+        }
+    }
+}
+
+pub struct Spotlight {
+    jobs: SyncSender<Mat>,
+    // 最近一次分割的前景概率, 模型输出尺寸.
+    latest: Arc<Mutex<Mat>>,
+    interval: Duration,
+    last: Option<Instant>,
+    strength: f64,
+    desaturate: f64,
+    feather: i32,
+    mask: Mat,
+    inverse: Mat,
+    gray: Mat,
+    background: Mat,
+    scratch: Mat,
+}
+
+impl Spotlight {
+    pub fn new(
+        config: &SpotlightConfig,
+        mut segmenter: Box<dyn Segmenter>,
+        workers: &ThreadConfig,
+    ) -> Result<Self> {
+        let latest = Arc::new(Mutex::new(Mat::default()));
+        let results = latest.clone();
+        let jobs = threads::worker("spotlight", 1, workers, move |image: Mat| {
+            *results.lock().unwrap() = segmenter.segment(&image)?;
+            Ok(())
+        })?;
+        Ok(Self {
+            jobs,
+            latest,
+            interval: Duration::from_secs_f64(1.0 / config.fps.max(0.1)),
+            last: None,
+            strength: config.strength.clamp(0.0, 1.0),
+            desaturate: config.desaturate.clamp(0.0, 1.0),
+            feather: config.feather.max(1) | 1,
+            mask: Mat::default(),
+            inverse: Mat::default(),
+            gray: Mat::default(),
+            background: Mat::default(),
+            scratch: Mat::default(),
+        })
+    }
+
+    // 压暗和去饱和后的背景.
+    fn background(&mut self, frame: &Mat) -> Result<()> {
+        imgproc::cvt_color(frame, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        imgproc::cvt_color(&self.gray, &mut self.scratch, COLOR_GRAY2BGR, 0)?;
+        let brightness = 1.0 - self.strength;
+        core::add_weighted(
+            frame,
+            (1.0 - self.desaturate) * brightness,
+            &self.scratch,
+            self.desaturate * brightness,
+            0.0,
+            &mut self.background,
+            -1,
+        )?;
+        Ok(())
+    }
+}
+
+impl FrameProcessor for Spotlight {
+    fn name(&self) -> &str {
+        "spotlight"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let due = self
+            .last
+            .map_or(true, |last| last.elapsed() >= self.interval);
+        if due {
+            match self.jobs.try_send(frame.try_clone()?) {
+                Ok(()) => self.last = Some(Instant::now()),
+                Err(TrySendError::Full(_)) => {}
+                Err(TrySendError::Disconnected(_)) => bail!("spotlight thread exited"),
+            }
+        }
+        // 第一次分割完成前画面不变.
+        {
+            let latest = self.latest.lock().unwrap();
+            if latest.empty() {
+                return Ok(());
+            }
+            imgproc::resize(
+                &*latest,
+                &mut self.scratch,
+                frame.size()?,
+                0.0,
+                0.0,
+                INTER_LINEAR,
+            )?;
+        }
+        let kernel = Size::new(self.feather, self.feather);
+        imgproc::gaussian_blur(
+            &self.scratch,
+            &mut self.mask,
+            kernel,
+            0.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )?;
+        // inverse = 1 - mask.
+        self.mask.convert_to(&mut self.inverse, -1, -1.0, 1.0)?;
+        self.background(frame)?;
+        imgproc::blend_linear(
+            &*frame,
+            &self.background,
+            &self.mask,
+            &self.inverse,
+            &mut self.scratch,
+        )?;
+        std::mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+
+    // 在分析之后, 其他效果之前.
+    fn order(&self) -> i32 {
+        45
+    }
+}
@@ -0,0 +1,592 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{bail, Result};
+use slint::Weak;
+
+use crate::{
+    ar::ArOverlay,
+    audio::AudioOverlay,
+    backlight::BacklightCompensation,
+    capture::set_status,
+    chroma_key::ChromaKey,
+    classify::Classifier,
+    color::ColorCorrection,
+    color_match::ColorMatch,
+    config::Config,
+    detect::{self, CascadeDetector, Detector, PersonDetector},
+    detection::ObjectDetection,
+    effects::Effects,
+    events::EventStore,
+    exclusions,
+    fall::FallDetection,
+    flat_field::{self, FlatFieldCorrection},
+    framing::AutoFraming,
+    gauges::GaugeReading,
+    hot_pixels::HotPixelCorrection,
+    intrusion::Intrusion,
+    jobs,
+    lanes::LaneDetection,
+    meteor::MeteorDetection,
+    models::{Backend, Downloading, ModelConfig, Models, Task},
+    monitor::Monitor,
+    occupancy::{Occupancy, Signal},
+    ocr::Ocr,
+    parking::{self, Parking},
+    pipeline::{FrameProcessor, Pipeline},
+    pose::PoseEstimator,
+    ppe::PpeMonitor,
+    printer::PrinterMonitor,
+    profile::CameraProfile,
+    projection::PlanarProjection,
+    queue::QueueLength,
+    reaction::ReactionMonitor,
+    segment::Segmenter,
+    speed::SpeedEstimation,
+    spotlight::Spotlight,
+    tamper::TamperDetection,
+    tracking::ObjectTracking,
+    trajectory::Trajectories,
+    white_balance::WhiteBalance,
+    wildlife::Wildlife,
+    zones::{DwellAnalytics, Zone},
+    Main,
+};
+
+// 根据配置和 camera profile 创建处理阶段, 实时预览和离线处理共用.
+pub struct Stages<'a> {
+    pub config: &'a Config,
+    pub profile: &'a CameraProfile,
+    pub profile_dir: &'a Path,
+    pub events: &'a EventStore,
+    pub frame_size: (i32, i32),
+    // 没有界面时只打印到终端.
+    pub window: Option<&'a Weak<Main>>,
+    // 实时处理时模型在后台任务中下载, 结束时置位, 由采集线程创建等待的阶段.
+    // None 时在当前线程中下载, 用于离线处理.
+    pub models_ready: Option<&'a Arc<AtomicBool>>,
+}
+
+// 后台下载中的模型为 None, 下载失败的为错误信息. 失败只报告一次, 再次启用时重新下载.
+static DOWNLOADS: Mutex<BTreeMap<String, Option<String>>> = Mutex::new(BTreeMap::new());
+
+impl Stages<'_> {
+    // 按 profile 和配置中启用的功能创建完整的处理管线.
+    pub fn pipeline(&self, trajectories: &Trajectories) -> Result<Pipeline> {
+        let profile = self.profile;
+        let config = self.config;
+        let enabled = [
+            ("hot-pixels", !profile.hot_pixels.is_empty()),
+            ("flat-field", profile.flat_field),
+            ("white-balance", profile.white_balance.is_some()),
+            ("color-correction", profile.color_matrix.is_some()),
+            ("tamper", config.tamper.enabled),
+            ("framing", config.framing.enabled),
+            ("chroma-key", config.chroma_key.enabled),
+            ("backlight", config.backlight.enabled),
+            ("lanes", config.lanes.enabled),
+            ("speed", config.speed.enabled),
+            ("tracking", config.tracking.enabled),
+            ("dwell", config.dwell.enabled),
+            ("occupancy", config.occupancy.enabled),
+            ("queue", config.queue.enabled),
+            ("parking", config.parking.enabled),
+            ("intrusion", config.intrusion.enabled),
+            ("detection", config.detection.enabled),
+            ("wildlife", config.wildlife.enabled),
+            ("meteor", config.meteor.enabled),
+            ("gauges", config.gauges.enabled),
+            ("reaction", config.reaction.enabled),
+            ("printer", config.printer.enabled),
+            ("ppe", config.ppe.enabled),
+            ("ocr", config.ocr.enabled),
+            ("fall", config.fall.enabled),
+            ("monitor", config.monitor.enabled),
+            ("spotlight", config.spotlight.enabled),
+            ("ar", config.ar.enabled),
+            ("projection", config.projection.enabled),
+            ("effects", !config.effects.is_empty()),
+            ("audio-overlay", config.audio.overlay.enabled),
+        ];
+        let names = enabled
+            .iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        // 换来源时重建管线, 之前画出的排除区域不再适用.
+        exclusions::set(config.exclusions.clone());
+        self.build(&names, trajectories)
+    }
+
+    pub fn build(&self, names: &[&str], trajectories: &Trajectories) -> Result<Pipeline> {
+        let mut pipeline = Pipeline::default();
+        for name in names {
+            match self.stage(name, trajectories) {
+                Ok(stage) => pipeline.set(stage),
+                Err(err) if err.is::<Downloading>() => pipeline.wait(name),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(pipeline)
+    }
+
+    // 按名称创建单个处理阶段, 传感器校正需要 profile 中已有校准数据.
+    pub fn stage(
+        &self,
+        name: &str,
+        trajectories: &Trajectories,
+    ) -> Result<Box<dyn FrameProcessor>> {
+        let profile = self.profile;
+        let config = self.config;
+        Ok(match name {
+            "hot-pixels" => {
+                if profile.hot_pixels.is_empty() {
+                    bail!("hot pixels are not mapped");
+                }
+                Box::new(HotPixelCorrection::new(&profile.hot_pixels))
+            }
+            "flat-field" => {
+                let flat = flat_field::load(self.profile_dir)?;
+                Box::new(FlatFieldCorrection::new(&flat)?)
+            }
+            "white-balance" => match &profile.white_balance {
+                Some(gains) => Box::new(WhiteBalance::new(gains)?),
+                None => bail!("white balance is not calibrated"),
+            },
+            "color-correction" => match &profile.color_matrix {
+                Some(matrix) => Box::new(ColorCorrection::new(matrix)?),
+                None => bail!("colors are not calibrated"),
+            },
+            "tamper" => Box::new(TamperDetection::new(&config.tamper, self.events.clone())?),
+            "framing" => Box::new(self.auto_framing()?),
+            "chroma-key" => Box::new(ChromaKey::new(&config.chroma_key)?),
+            "color-match" => Box::new(ColorMatch::new(&config.color_match)),
+            "backlight" => Box::new(BacklightCompensation::new(
+                &config.backlight,
+                self.events.clone(),
+            )?),
+            "lanes" => Box::new(LaneDetection::new(&config.lanes)),
+            "speed" => Box::new(self.speed_estimation()?),
+            "tracking" => Box::new(self.object_tracking(trajectories)?),
+            "dwell" => Box::new(self.dwell_analytics()?),
+            "occupancy" => Box::new(self.occupancy()?),
+            "queue" => {
+                Box::new(self.queue_length(QueueLength::zone(&config.queue, &config.zones)?)?)
+            }
+            "parking" => Box::new(self.parking()?),
+            "intrusion" => Box::new(self.intrusion()?),
+            "detection" => Box::new(self.object_detection()?),
+            "wildlife" => Box::new(self.wildlife()?),
+            "meteor" => Box::new(self.meteor_detection()?),
+            "gauges" => Box::new(GaugeReading::new(&config.gauges)?),
+            "reaction" => Box::new(ReactionMonitor::new(
+                &config.reaction,
+                config.reaction.roi,
+                self.events.clone(),
+            )?),
+            "printer" => Box::new(self.printer()?),
+            "ppe" => Box::new(self.ppe()?),
+            "ocr" => Box::new(Ocr::new(
+                &config.ocr,
+                self.events.clone(),
+                &config.threads.workers,
+            )?),
+            "fall" => Box::new(self.fall_detection()?),
+            "monitor" => Box::new(self.monitor()?),
+            "spotlight" => Box::new(self.spotlight()?),
+            "ar" => Box::new(ArOverlay::new(&config.ar, profile.intrinsics.as_ref())?),
+            "projection" => Box::new(self.projection()?),
+            "effects" => Box::new(Effects::new(&config.effects)),
+            "audio-overlay" => Box::new(AudioOverlay::new(&config.audio, &config.threads.workers)?),
+            _ => bail!("unknown stage {:?}", name),
+        })
+    }
+
+    pub fn status(&self, text: String) {
+        match self.window {
+            Some(window) => set_status(window, text),
+            None => println!("{}", text),
+        }
+    }
+
+    // 按 tracking.model 创建检测器, 不设置时使用 HOG 行人检测器.
+    pub fn detector(&self) -> Result<Box<dyn Detector>> {
+        let tracking = &self.config.tracking;
+        match &tracking.model {
+            Some(name) => self.model_detector(name, tracking.class),
+            None => Ok(Box::new(PersonDetector::new()?)),
+        }
+    }
+
+    // 模型文件不存在时先下载. 实时处理时在后台任务中下载并返回 Downloading,
+    // 离线处理时在当前线程中下载并在终端显示进度.
+    fn fetch_model(&self, name: &str, task: Task) -> Result<(ModelConfig, PathBuf)> {
+        let models = Models::new(&self.config.models_dir, &self.config.models);
+        let model = models.get(name)?.clone();
+        if model.task != task {
+            let task = format!("{:?}", task).to_lowercase();
+            bail!("model {} is not a {} model", name, task);
+        }
+        if let Some(path) = models.cached(name)? {
+            return Ok((model, path));
+        }
+        let (Some(window), Some(ready)) = (self.window, self.models_ready) else {
+            let mut last = -1;
+            let path = models.fetch(name, |progress| {
+                // 只在百分比变化时打印.
+                let percent = progress.map_or(0, |p| (p * 100.0) as i32);
+                if percent != last {
+                    last = percent;
+                    self.status(format!("Downloading model {}: {}%", name, percent));
+                }
+                Ok(())
+            })?;
+            return Ok((model, path));
+        };
+
+        let mut downloads = DOWNLOADS.lock().unwrap();
+        match downloads.remove(name) {
+            Some(Some(error)) => bail!("download model {}: {}", name, error),
+            Some(None) => {
+                downloads.insert(name.to_string(), None);
+                return Err(Downloading(name.to_string()).into());
+            }
+            None => {}
+        }
+        downloads.insert(name.to_string(), None);
+        drop(downloads);
+        let (model_name, ready) = (name.to_string(), ready.clone());
+        let job = format!("Model {} download", name);
+        jobs::spawn(window, &job, &self.config.threads.workers, move |job| {
+            let fetched = models.fetch(&model_name, |progress| job.progress(progress));
+            let error = fetched.as_ref().err().map(|err| format!("{:#}", err));
+            let mut downloads = DOWNLOADS.lock().unwrap();
+            match error {
+                Some(error) => downloads.insert(model_name.clone(), Some(error)),
+                None => downloads.remove(&model_name),
+            };
+            drop(downloads);
+            ready.store(true, Ordering::Relaxed);
+            fetched.map(|_| format!("Model {} downloaded", model_name))
+        });
+        self.status(format!(
+            "Downloading model {}, the stage starts when it is ready",
+            name
+        ));
+        Err(Downloading(name.to_string()).into())
+    }
+
+    pub fn model_detector(&self, name: &str, class: Option<usize>) -> Result<Box<dyn Detector>> {
+        let (model, path) = self.fetch_model(name, Task::Detection)?;
+        let mut detector: Box<dyn Detector> = match model.backend {
+            #[cfg(feature = "dnn")]
+            Backend::Opencv => Box::new(crate::dnn::DnnDetector::new(&path, &model, class)?),
+            #[cfg(not(feature = "dnn"))]
+            Backend::Opencv => bail!(
+                "model {} needs OpenCV DNN, rebuild with --features dnn",
+                name
+            ),
+            #[cfg(feature = "onnxruntime")]
+            Backend::Onnxruntime => Box::new(crate::onnx::OnnxDetector::new(&path, &model, class)?),
+            #[cfg(not(feature = "onnxruntime"))]
+            Backend::Onnxruntime => bail!(
+                "model {} needs the onnxruntime backend, rebuild with --features onnxruntime",
+                name
+            ),
+        };
+        let (width, height) = self.frame_size;
+        let fps = detect::benchmark(detector.as_mut(), width, height)?;
+        self.status(format!(
+            "Model {} on {}: {:.1} inference FPS",
+            name,
+            detector.device(),
+            fps
+        ));
+        Ok(detector)
+    }
+
+    pub fn model_classifier(&self, name: &str) -> Result<Box<dyn Classifier>> {
+        let (model, path) = self.fetch_model(name, Task::Classification)?;
+        let classifier: Box<dyn Classifier> = match model.backend {
+            #[cfg(feature = "dnn")]
+            Backend::Opencv => Box::new(crate::dnn::DnnClassifier::new(&path, &model)?),
+            #[cfg(not(feature = "dnn"))]
+            Backend::Opencv => bail!(
+                "model {} needs OpenCV DNN, rebuild with --features dnn",
+                name
+            ),
+            #[cfg(feature = "onnxruntime")]
+            Backend::Onnxruntime => Box::new(crate::onnx::OnnxClassifier::new(&path, &model)?),
+            #[cfg(not(feature = "onnxruntime"))]
+            Backend::Onnxruntime => bail!(
+                "model {} needs the onnxruntime backend, rebuild with --features onnxruntime",
+                name
+            ),
+        };
+        self.status(format!("Model {} on {}", name, classifier.device()));
+        Ok(classifier)
+    }
+
+    pub fn model_segmenter(&self, name: &str, class: usize) -> Result<Box<dyn Segmenter>> {
+        let (model, path) = self.fetch_model(name, Task::Segmentation)?;
+        let segmenter: Box<dyn Segmenter> = match model.backend {
+            #[cfg(feature = "dnn")]
+            Backend::Opencv => Box::new(crate::dnn::DnnSegmenter::new(&path, &model, class)?),
+            #[cfg(not(feature = "dnn"))]
+            Backend::Opencv => bail!(
+                "model {} needs OpenCV DNN, rebuild with --features dnn",
+                name
+            ),
+            #[cfg(feature = "onnxruntime")]
+            Backend::Onnxruntime => {
+                Box::new(crate::onnx::OnnxSegmenter::new(&path, &model, class)?)
+            }
+            #[cfg(not(feature = "onnxruntime"))]
+            Backend::Onnxruntime => bail!(
+                "model {} needs the onnxruntime backend, rebuild with --features onnxruntime",
+                name
+            ),
+        };
+        self.status(format!("Model {} on {}", name, segmenter.device()));
+        Ok(segmenter)
+    }
+
+    pub fn model_pose(&self, name: &str) -> Result<Box<dyn PoseEstimator>> {
+        let (model, path) = self.fetch_model(name, Task::Pose)?;
+        let estimator: Box<dyn PoseEstimator> = match model.backend {
+            #[cfg(feature = "dnn")]
+            Backend::Opencv => Box::new(crate::dnn::DnnPose::new(&path, &model)?),
+            #[cfg(not(feature = "dnn"))]
+            Backend::Opencv => bail!(
+                "model {} needs OpenCV DNN, rebuild with --features dnn",
+                name
+            ),
+            #[cfg(feature = "onnxruntime")]
+            Backend::Onnxruntime => Box::new(crate::onnx::OnnxPose::new(&path, &model)?),
+            #[cfg(not(feature = "onnxruntime"))]
+            Backend::Onnxruntime => bail!(
+                "model {} needs the onnxruntime backend, rebuild with --features onnxruntime",
+                name
+            ),
+        };
+        self.status(format!("Model {} on {}", name, estimator.device()));
+        Ok(estimator)
+    }
+
+    pub fn object_tracking(&self, trajectories: &Trajectories) -> Result<ObjectTracking> {
+        let detector = self.detector()?;
+        Ok(ObjectTracking::new(
+            &self.config.tracking,
+            detector,
+            trajectories.clone(),
+        ))
+    }
+
+    // 按 detection.model 创建检测器, 不设置时使用 Haar 级联分类器.
+    pub fn object_detection(&self) -> Result<ObjectDetection> {
+        let config = &self.config;
+        let detection = &config.detection;
+        let detector: Box<dyn Detector> = match &detection.model {
+            Some(name) => self.model_detector(name, detection.class)?,
+            None => Box::new(CascadeDetector::new(&detection.cascade, &detection.label)?),
+        };
+        ObjectDetection::new(detection, detector, &config.threads.workers)
+    }
+
+    // 自动取景与目标检测一样, 不设置模型时使用级联分类器检测人脸.
+    pub fn auto_framing(&self) -> Result<AutoFraming> {
+        let config = &self.config;
+        let framing = &config.framing;
+        let detector: Box<dyn Detector> = match &framing.model {
+            Some(name) => self.model_detector(name, framing.class)?,
+            None => Box::new(CascadeDetector::new(&framing.cascade, "face")?),
+        };
+        AutoFraming::new(framing, detector, &config.threads.workers)
+    }
+
+    // 野生动物预设需要 wildlife.model 指定的分类模型.
+    pub fn wildlife(&self) -> Result<Wildlife> {
+        let config = &self.config;
+        let Some(model) = &config.wildlife.model else {
+            bail!("set model in [wildlife] to a classification model");
+        };
+        let classifier = self.model_classifier(model)?;
+        Wildlife::new(
+            &config.wildlife,
+            classifier,
+            self.events.clone(),
+            &config.threads.workers,
+        )
+    }
+
+    // 聚光灯效果需要 spotlight.model 指定的分割模型.
+    pub fn spotlight(&self) -> Result<Spotlight> {
+        let config = &self.config;
+        let Some(model) = &config.spotlight.model else {
+            bail!("set model in [spotlight] to a segmentation model");
+        };
+        let segmenter = self.model_segmenter(model, config.spotlight.class)?;
+        Spotlight::new(&config.spotlight, segmenter, &config.threads.workers)
+    }
+
+    pub fn projection(&self) -> Result<PlanarProjection> {
+        let config = &self.config.projection;
+        let Some(content) = &config.content else {
+            bail!("set content in [projection] to an image or video");
+        };
+        PlanarProjection::new(config, content, &config.corners)
+    }
+
+    pub fn meteor_detection(&self) -> Result<MeteorDetection> {
+        let config = &self.config;
+        MeteorDetection::new(&config.meteor, self.events.clone(), &config.threads.workers)
+    }
+
+    pub fn printer(&self) -> Result<PrinterMonitor> {
+        let config = &self.config;
+        let Some(model) = &config.printer.model else {
+            bail!("set printer.model to a print failure detection model from [models]");
+        };
+        let detector = self.model_detector(model, config.printer.class)?;
+        PrinterMonitor::new(
+            &config.printer,
+            detector,
+            self.events.clone(),
+            &config.threads.workers,
+        )
+    }
+
+    // 检测模型保留全部类别, 人和用品的类别由 [ppe] 配置.
+    pub fn ppe(&self) -> Result<PpeMonitor> {
+        let config = &self.config;
+        let Some(model) = &config.ppe.model else {
+            bail!("set ppe.model to a person and safety gear detection model from [models]");
+        };
+        let detector = self.model_detector(model, None)?;
+        PpeMonitor::new(
+            &config.ppe,
+            detector,
+            self.events.clone(),
+            &config.threads.workers,
+        )
+    }
+
+    // 报警显示在窗口上, 直到确认.
+    pub fn fall_detection(&self) -> Result<FallDetection> {
+        let config = &self.config;
+        let Some(model) = &config.fall.model else {
+            bail!("set fall.model to a pose estimation model from [models]");
+        };
+        let estimator = self.model_pose(model)?;
+        let window = self.window.cloned();
+        FallDetection::new(
+            &config.fall,
+            estimator,
+            self.events.clone(),
+            &config.threads.workers,
+            move |alarm| {
+                if let Some(window) = &window {
+                    let _ =
+                        window.upgrade_in_event_loop(move |window| window.set_fall_alarm(alarm));
+                }
+            },
+        )
+    }
+
+    // 运动状态显示在监护界面上.
+    pub fn monitor(&self) -> Result<Monitor> {
+        let config = &self.config;
+        let window = self.window.cloned();
+        Monitor::new(
+            &config.monitor,
+            &config.audio,
+            self.events.clone(),
+            &config.threads.workers,
+            move |motion, sound| {
+                if let Some(window) = &window {
+                    let _ = window.upgrade_in_event_loop(move |window| {
+                        window.set_monitor_motion(motion);
+                        window.set_monitor_sound(sound);
+                    });
+                }
+            },
+        )
+    }
+
+    pub fn dwell_analytics(&self) -> Result<DwellAnalytics> {
+        let config = &self.config;
+        let detector = self.detector()?;
+        Ok(DwellAnalytics::new(
+            &config.dwell,
+            &config.zones,
+            &config.tracking,
+            detector,
+        ))
+    }
+
+    // 只有用到检测信号时才创建检测器.
+    pub fn occupancy(&self) -> Result<Occupancy> {
+        let config = &self.config;
+        let detection = config
+            .occupancy
+            .machines
+            .iter()
+            .any(|machine| machine.signal == Signal::Detection);
+        let detector = if detection {
+            Some(self.detector()?)
+        } else {
+            None
+        };
+        Occupancy::new(
+            &config.occupancy,
+            &config.zones,
+            detector,
+            self.events.clone(),
+        )
+    }
+
+    // 区域来自 [[zones]] 或在控制面板中画出.
+    pub fn queue_length(&self, zone: Zone) -> Result<QueueLength> {
+        let detector = self.detector()?;
+        QueueLength::new(&self.config.queue, zone, detector, self.events.clone())
+    }
+
+    // 只有按检测判断车位时才加载车辆检测模型.
+    pub fn parking(&self) -> Result<Parking> {
+        let config = &self.config.parking;
+        let detector = match (config.method, &config.model) {
+            (parking::Method::Background, _) => None,
+            (parking::Method::Detection, Some(model)) => Some(self.model_detector(model, None)?),
+            (parking::Method::Detection, None) => {
+                bail!("set parking.model to a vehicle detection model from [models]")
+            }
+        };
+        Parking::new(config, detector, self.events.clone())
+    }
+
+    pub fn intrusion(&self) -> Result<Intrusion> {
+        let config = &self.config;
+        let detector = self.detector()?;
+        Intrusion::new(
+            &config.intrusion,
+            &config.zones,
+            &config.tracking,
+            detector,
+            self.events.clone(),
+        )
+    }
+
+    pub fn speed_estimation(&self) -> Result<SpeedEstimation> {
+        SpeedEstimation::new(
+            &self.config.speed,
+            self.profile.meters_per_px,
+            self.events.clone(),
+        )
+    }
+}
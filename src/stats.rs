@@ -0,0 +1,259 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Write as _,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{atomic::Ordering, Mutex},
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::Deserialize;
+
+use crate::frames::DROPPED_FRAMES;
+
+// 运行统计: 程序退出和停止录制时生成可读的总结, 追加到 log, 退出时还显示在对话框中.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    pub enabled: bool,
+    pub log: PathBuf,
+    // 退出时显示总结对话框.
+    pub dialog: bool,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            log: PathBuf::from("sessions.log"),
+            dialog: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Stats {
+    pub started: DateTime<Local>,
+    pub frames: u64,
+    // 实时来源断开或读到空帧期间按帧率估算少的帧数.
+    pub dropped: u64,
+    // 界面来不及显示而丢弃的帧数, 结束时由 DROPPED_FRAMES 计算.
+    pub not_shown: u64,
+    pub events: BTreeMap<String, u64>,
+    pub files: BTreeSet<PathBuf>,
+    // 开始时的 DROPPED_FRAMES.
+    shown_base: u64,
+}
+
+impl Stats {
+    pub fn new(started: DateTime<Local>) -> Self {
+        Self {
+            started,
+            frames: 0,
+            dropped: 0,
+            not_shown: 0,
+            events: BTreeMap::new(),
+            files: BTreeSet::new(),
+            shown_base: DROPPED_FRAMES.load(Ordering::Relaxed),
+        }
+    }
+
+    fn close(mut self) -> Self {
+        self.not_shown = DROPPED_FRAMES
+            .load(Ordering::Relaxed)
+            .saturating_sub(self.shown_base);
+        self
+    }
+
+    // 可读的总结, 文件大小在生成时读取.
+    pub fn report(&self, title: &str, ended: DateTime<Local>) -> String {
+        let seconds = (ended - self.started).num_milliseconds().max(0) as f64 / 1000.0;
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "{} {} - {} ({})",
+            title,
+            self.started.format("%Y-%m-%d %H:%M:%S"),
+            ended.format("%H:%M:%S"),
+            duration(seconds)
+        );
+        let fps = if seconds > 0.0 {
+            self.frames as f64 / seconds
+        } else {
+            0.0
+        };
+        let _ = writeln!(
+            text,
+            "Frames: {} captured, {} dropped ({} lost from the source, {} not shown), {:.1} fps average",
+            self.frames,
+            self.dropped + self.not_shown,
+            self.dropped,
+            self.not_shown,
+            fps
+        );
+        if self.events.is_empty() {
+            let _ = writeln!(text, "Events: none");
+        } else {
+            let events = self
+                .events
+                .iter()
+                .map(|(kind, count)| format!("{} {}", kind, count))
+                .collect::<Vec<_>>();
+            let _ = writeln!(text, "Events: {}", events.join(", "));
+        }
+        if self.files.is_empty() {
+            let _ = writeln!(text, "Files: none");
+        } else {
+            let _ = writeln!(text, "Files:");
+            for path in &self.files {
+                let size = fs::metadata(path)
+                    .map(|metadata| size(metadata.len()))
+                    .unwrap_or_else(|_| "missing".to_string());
+                let _ = writeln!(text, "  {}  {}", path.display(), size);
+            }
+        }
+        text
+    }
+}
+
+// 例如 "1h 02m 03s".
+pub fn duration(seconds: f64) -> String {
+    let seconds = seconds.max(0.0).round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}h {:02}m {:02}s", hours, minutes, seconds)
+    } else if minutes > 0 {
+        format!("{}m {:02}s", minutes, seconds)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+pub fn size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    if value < 1000.0 {
+        return format!("{} B", bytes);
+    }
+    let mut unit = 0;
+    value /= 1000.0;
+    while value >= 1000.0 && unit + 1 < UNITS.len() {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+// 整个运行和当前录制的统计. 采集线程, 事件回调和界面线程都会更新, 所以放在全局.
+static APP: Mutex<Option<Stats>> = Mutex::new(None);
+static RECORDING: Mutex<Option<Stats>> = Mutex::new(None);
+
+fn update(change: impl Fn(&mut Stats)) {
+    for stats in [&APP, &RECORDING] {
+        if let Some(stats) = stats.lock().unwrap().as_mut() {
+            change(stats);
+        }
+    }
+}
+
+pub fn frame() {
+    update(|stats| stats.frames += 1);
+}
+
+pub fn dropped(count: u64) {
+    if count > 0 {
+        update(|stats| stats.dropped += count);
+    }
+}
+
+pub fn event(kind: &str) {
+    update(|stats| *stats.events.entry(kind.to_string()).or_default() += 1);
+}
+
+pub fn file(path: &Path) {
+    update(|stats| {
+        stats.files.insert(path.to_path_buf());
+    });
+}
+
+pub fn begin() {
+    *APP.lock().unwrap() = Some(Stats::new(Local::now()));
+}
+
+pub fn end() -> Option<Stats> {
+    RECORDING.lock().unwrap().take();
+    APP.lock().unwrap().take().map(Stats::close)
+}
+
+pub fn begin_recording() {
+    *RECORDING.lock().unwrap() = Some(Stats::new(Local::now()));
+}
+
+pub fn end_recording() -> Option<Stats> {
+    RECORDING.lock().unwrap().take().map(Stats::close)
+}
+
+// 追加到 log, 每段之间空一行.
+pub fn append(config: &StatsConfig, report: &str) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&config.log)
+        .with_context(|| format!("open {}", config.log.display()))?;
+    writeln!(file, "{}", report).with_context(|| format!("write {}", config.log.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn formats() {
+        assert_eq!(duration(0.4), "0s");
+        assert_eq!(duration(59.6), "1m 00s");
+        assert_eq!(duration(3723.0), "1h 02m 03s");
+        assert_eq!(size(999), "999 B");
+        assert_eq!(size(1_500), "1.5 KB");
+        assert_eq!(size(2_300_000_000), "2.3 GB");
+        assert_eq!(size(5_000_000_000_000_000), "5000.0 TB");
+    }
+
+    #[test]
+    fn session_report() {
+        let started = Local.with_ymd_and_hms(2024, 5, 1, 9, 0, 0).unwrap();
+        let ended = Local.with_ymd_and_hms(2024, 5, 1, 9, 1, 40).unwrap();
+        let mut session = Stats::new(started);
+        session.frames = 2_500;
+        session.dropped = 30;
+        session.not_shown = 12;
+        assert_eq!(
+            session.report("Session", ended),
+            "Session 2024-05-01 09:00:00 - 09:01:40 (1m 40s)\n\
+             Frames: 2500 captured, 42 dropped (30 lost from the source, 12 not shown), 25.0 fps average\n\
+             Events: none\n\
+             Files: none\n"
+        );
+
+        let dir = std::env::temp_dir().join(format!("stats-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let clip = dir.join("clip.mp4");
+        fs::write(&clip, vec![0u8; 2_048]).unwrap();
+        session.events.insert("motion".to_string(), 3);
+        session.events.insert("face".to_string(), 1);
+        session.files.insert(clip.clone());
+        session.files.insert(dir.join("gone.mp4"));
+        let report = session.report("Recording", ended);
+        assert!(
+            report.contains("\nEvents: face 1, motion 3\n"),
+            "{}",
+            report
+        );
+        assert!(report.contains(&format!("  {}  2.0 KB\n", clip.display())));
+        assert!(report.ends_with("gone.mp4  missing\n"), "{}", report);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Scalar, Size, TermCriteria, Vector, CV_16U, CV_32F, CV_32FC3, CV_8U},
+    imgcodecs,
+    imgproc::{self, COLOR_BGR2GRAY, INTER_CUBIC, INTER_LINEAR, WARP_INVERSE_MAP},
+    prelude::*,
+    video::{self, MOTION_EUCLIDEAN},
+};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StillConfig {
+    // 高质量照片合成使用的连拍帧数.
+    pub frames: usize,
+    // 输出相对原始分辨率的放大倍数.
+    pub scale: f64,
+    // png (8 位) 或 tiff (16 位).
+    pub format: String,
+}
+
+impl Default for StillConfig {
+    fn default() -> Self {
+        Self {
+            frames: 8,
+            scale: 2.0,
+            format: "png".to_string(),
+        }
+    }
+}
+
+// 收集连拍帧.
+pub struct Burst {
+    config: StillConfig,
+    frames: Vec<Mat>,
+}
+
+impl Burst {
+    pub fn new(config: &StillConfig) -> Self {
+        Self {
+            config: config.clone(),
+            frames: Vec::with_capacity(config.frames),
+        }
+    }
+
+    // 收集够帧数后返回 true.
+    pub fn add(&mut self, frame: &Mat) -> Result<bool> {
+        self.frames.push(frame.try_clone()?);
+        Ok(self.frames.len() >= self.config.frames.max(1))
+    }
+
+    // 以第一帧为基准用 ECC 对齐, 放大后平均, 降低噪声并恢复亚像素细节.
+    // 比较耗时, 调用者应在单独的线程中执行.
+    pub fn merge(self) -> Result<PathBuf> {
+        let scale = self.config.scale.max(1.0);
+        let reference = &self.frames[0];
+        let mut reference_gray = Mat::default();
+        imgproc::cvt_color(reference, &mut reference_gray, COLOR_BGR2GRAY, 0)?;
+        let size = Size::new(
+            (reference.cols() as f64 * scale) as i32,
+            (reference.rows() as f64 * scale) as i32,
+        );
+        let criteria =
+            TermCriteria::new(core::TermCriteria_COUNT + core::TermCriteria_EPS, 50, 1e-4)?;
+
+        let mut sum = Mat::new_size_with_default(size, CV_32FC3, Scalar::all(0.))?;
+        let mut gray = Mat::default();
+        let mut upscaled = Mat::default();
+        let mut aligned = Mat::default();
+        let mut merged = 0;
+        for frame in &self.frames {
+            imgproc::cvt_color(frame, &mut gray, COLOR_BGR2GRAY, 0)?;
+            let mut warp = Mat::eye(2, 3, CV_32F)?.to_mat()?;
+            // 对齐失败 (例如画面中有大幅运动) 的帧直接丢弃.
+            if video::find_transform_ecc(
+                &reference_gray,
+                &gray,
+                &mut warp,
+                MOTION_EUCLIDEAN,
+                criteria,
+                &core::no_array(),
+                5,
+            )
+            .is_err()
+            {
+                continue;
+            }
+            // 平移量随放大倍数缩放, 旋转部分不变.
+            *warp.at_2d_mut::<f32>(0, 2)? *= scale as f32;
+            *warp.at_2d_mut::<f32>(1, 2)? *= scale as f32;
+            imgproc::resize(frame, &mut upscaled, size, 0.0, 0.0, INTER_CUBIC)?;
+            imgproc::warp_affine(
+                &upscaled,
+                &mut aligned,
+                &warp,
+                size,
+                INTER_LINEAR | WARP_INVERSE_MAP,
+                core::BORDER_REPLICATE,
+                Scalar::all(0.),
+            )?;
+            imgproc::accumulate(&aligned, &mut sum, &core::no_array())?;
+            merged += 1;
+        }
+        if merged == 0 {
+            bail!("no frame could be aligned");
+        }
+
+        let (extension, depth, factor) = match self.config.format.as_str() {
+            "tiff" | "tif" => ("tiff", CV_16U, 257.0),
+            _ => ("png", CV_8U, 1.0),
+        };
+        let mut still = Mat::default();
+        sum.convert_to(&mut still, depth, factor / merged as f64, 0.0)?;
+        let path = PathBuf::from(format!(
+            "still-{}.{}",
+            chrono::Local::now().format("%Y-%m-%dT%H-%M-%S"),
+            extension
+        ));
+        if !imgcodecs::imwrite(&path.to_string_lossy(), &still, &Vector::new())? {
+            bail!("write {}", path.display());
+        }
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Rect, CV_8UC3};
+
+    use super::*;
+
+    // 手持连拍: 同一场景每帧错开几个像素.
+    fn burst(config: &StillConfig) -> Burst {
+        let mut noise = Mat::new_rows_cols_with_default(70, 90, CV_8UC3, Scalar::all(0.)).unwrap();
+        core::randu(&mut noise, &Scalar::all(0.), &Scalar::all(255.)).unwrap();
+        let mut scene = Mat::default();
+        imgproc::gaussian_blur(
+            &noise,
+            &mut scene,
+            Size::new(7, 7),
+            0.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )
+        .unwrap();
+        let mut burst = Burst::new(config);
+        for (i, shift) in [0, 2, 1].into_iter().enumerate() {
+            let frame = Mat::roi(&scene, Rect::new(5 + shift, 5, 80, 60)).unwrap();
+            assert_eq!(burst.add(&frame).unwrap(), i == 2);
+        }
+        burst
+    }
+
+    fn merge(config: &StillConfig) -> Mat {
+        let path = burst(config).merge().unwrap();
+        let still =
+            imgcodecs::imread(&path.to_string_lossy(), imgcodecs::IMREAD_UNCHANGED).unwrap();
+        std::fs::remove_file(path).unwrap();
+        still
+    }
+
+    // 输出按 scale 放大, png 为 8 位, tiff 为 16 位.
+    #[test]
+    fn merged_still() {
+        let config = StillConfig {
+            frames: 3,
+            ..StillConfig::default()
+        };
+        let still = merge(&config);
+        assert_eq!(still.size().unwrap(), Size::new(160, 120));
+        assert_eq!(still.depth(), CV_8U);
+        let tiff = merge(&StillConfig {
+            format: "tiff".to_string(),
+            scale: 1.0,
+            ..config
+        });
+        assert_eq!(tiff.size().unwrap(), Size::new(80, 60));
+        assert_eq!(tiff.depth(), CV_16U);
+    }
+}
@@ -0,0 +1,291 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{Mat, Vector},
+    imgcodecs::{self, IMREAD_COLOR},
+    prelude::*,
+    videoio::VideoWriter,
+};
+use serde::Deserialize;
+
+use crate::sink;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StopMotionConfig {
+    // 每帧保存为 frame-0001.png, 重新启动后继续在已有序列后面添加.
+    pub dir: PathBuf,
+    // 导出视频的编码.
+    pub fourcc: String,
+}
+
+impl Default for StopMotionConfig {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("stop-motion"),
+            fourcc: "mp4v".to_string(),
+        }
+    }
+}
+
+// 按顺序拍摄的定格动画帧.
+pub struct StopMotion {
+    config: StopMotionConfig,
+    frames: Vec<PathBuf>,
+    playback: Option<Playback>,
+}
+
+struct Playback {
+    frames: Vec<Mat>,
+    fps: f64,
+    start: Instant,
+}
+
+impl StopMotion {
+    pub fn open(config: &StopMotionConfig) -> Result<Self> {
+        fs::create_dir_all(&config.dir)
+            .with_context(|| format!("create {}", config.dir.display()))?;
+        let mut frames: Vec<PathBuf> = fs::read_dir(&config.dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("frame-") && name.ends_with(".png"))
+            })
+            .collect();
+        frames.sort();
+        Ok(Self {
+            config: config.clone(),
+            frames,
+            playback: None,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn capture(&mut self, frame: &Mat) -> Result<()> {
+        let path = self
+            .config
+            .dir
+            .join(format!("frame-{:04}.png", self.frames.len() + 1));
+        if !imgcodecs::imwrite(&path.to_string_lossy(), frame, &Vector::new())? {
+            bail!("write {}", path.display());
+        }
+        self.frames.push(path);
+        Ok(())
+    }
+
+    // 删除最后一帧, 没有帧时返回 false.
+    pub fn delete_last(&mut self) -> Result<bool> {
+        let Some(path) = self.frames.pop() else {
+            return Ok(false);
+        };
+        fs::remove_file(&path).with_context(|| format!("delete {}", path.display()))?;
+        Ok(true)
+    }
+
+    // 最后一帧, 用于洋葱皮叠加.
+    pub fn last(&self) -> Result<Option<Mat>> {
+        self.frames.last().map(|path| read(path)).transpose()
+    }
+
+    pub fn play(&mut self, fps: f64) -> Result<()> {
+        if self.frames.is_empty() {
+            bail!("no frames captured");
+        }
+        let frames: Vec<Mat> = self
+            .frames
+            .iter()
+            .map(|path| read(path))
+            .collect::<Result<_>>()?;
+        self.playback = Some(Playback {
+            frames,
+            fps: fps.max(1.0),
+            start: Instant::now(),
+        });
+        Ok(())
+    }
+
+    pub fn stop(&mut self) {
+        self.playback = None;
+    }
+
+    // 回放中按帧率循环返回的帧, 代替实时画面显示.
+    pub fn playback(&self) -> Option<&Mat> {
+        let playback = self.playback.as_ref()?;
+        let index = (playback.start.elapsed().as_secs_f64() * playback.fps) as usize;
+        playback.frames.get(index % playback.frames.len())
+    }
+
+    pub fn frames(&self) -> Vec<PathBuf> {
+        self.frames.clone()
+    }
+
+    pub fn export_path(&self) -> PathBuf {
+        self.config.dir.join(format!(
+            "stop-motion-{}.mp4",
+            Local::now().format("%Y-%m-%dT%H-%M-%S")
+        ))
+    }
+}
+
+fn read(path: &Path) -> Result<Mat> {
+    let image = imgcodecs::imread(&path.to_string_lossy(), IMREAD_COLOR)?;
+    if image.empty() {
+        bail!("read {}", path.display());
+    }
+    Ok(image)
+}
+
+// 按帧率写成视频, 帧较多时比较耗时, 调用者应在单独的线程中执行.
+// progress 接收 0~1 的进度, 返回错误时停止并删除写了一半的文件.
+pub fn export(
+    frames: &[PathBuf],
+    path: &Path,
+    fps: f64,
+    fourcc: &str,
+    mut progress: impl FnMut(f64) -> Result<()>,
+) -> Result<()> {
+    let Some(first) = frames.first() else {
+        bail!("no frames captured");
+    };
+    let size = read(first)?.size()?;
+    let mut writer = VideoWriter::new(
+        &path.to_string_lossy(),
+        sink::fourcc(fourcc)?,
+        fps.max(1.0),
+        size,
+        true,
+    )?;
+    if !writer.is_opened()? {
+        bail!("open video writer {}", path.display());
+    }
+    for (index, frame) in frames.iter().enumerate() {
+        if let Err(err) = progress(index as f64 / frames.len() as f64) {
+            writer.release()?;
+            let _ = fs::remove_file(path);
+            return Err(err);
+        }
+        writer.write(&read(frame)?)?;
+    }
+    writer.release()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::{
+        core::{Scalar, Vec3b, CV_8UC3},
+        videoio::{self, VideoCapture},
+    };
+
+    use super::*;
+
+    fn frame(level: f64) -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(level)).unwrap()
+    }
+
+    fn temp_config(name: &str) -> StopMotionConfig {
+        StopMotionConfig {
+            dir: std::env::temp_dir().join(format!("{}-{}", name, std::process::id())),
+            fourcc: "MJPG".to_string(),
+        }
+    }
+
+    // 重新打开时继续已有的序列, 删除最后一帧后编号可以复用.
+    #[test]
+    fn capture_and_resume() {
+        let config = temp_config("stop-motion-resume");
+        let mut stop_motion = StopMotion::open(&config).unwrap();
+        assert!(stop_motion.is_empty());
+        assert!(stop_motion.last().unwrap().is_none());
+        assert!(!stop_motion.delete_last().unwrap());
+        for level in [10., 20., 30.] {
+            stop_motion.capture(&frame(level)).unwrap();
+        }
+        assert!(stop_motion.delete_last().unwrap());
+        fs::write(config.dir.join("notes.txt"), "not a frame").unwrap();
+
+        let mut stop_motion = StopMotion::open(&config).unwrap();
+        assert_eq!(stop_motion.len(), 2);
+        let last = stop_motion.last().unwrap().unwrap();
+        assert_eq!(*last.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::all(20));
+        stop_motion.capture(&frame(40.)).unwrap();
+        assert_eq!(
+            stop_motion.frames().last().unwrap(),
+            &config.dir.join("frame-0003.png")
+        );
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    #[test]
+    fn playback_loops() {
+        let config = temp_config("stop-motion-play");
+        let mut stop_motion = StopMotion::open(&config).unwrap();
+        assert!(stop_motion.play(12.0).is_err());
+        stop_motion.capture(&frame(10.)).unwrap();
+        stop_motion.capture(&frame(20.)).unwrap();
+        assert!(stop_motion.playback().is_none());
+        stop_motion.play(12.0).unwrap();
+        let first = stop_motion.playback().unwrap();
+        assert_eq!(*first.at_2d::<Vec3b>(0, 0).unwrap(), Vec3b::all(10));
+        stop_motion.stop();
+        assert!(stop_motion.playback().is_none());
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+
+    // 进度回调出错时停止并删除写了一半的文件.
+    #[test]
+    fn export_video() {
+        let config = temp_config("stop-motion-export");
+        let mut stop_motion = StopMotion::open(&config).unwrap();
+        for level in [10., 20., 30., 40.] {
+            stop_motion.capture(&frame(level)).unwrap();
+        }
+        let path = config.dir.join("export.avi");
+        let mut progress = Vec::new();
+        export(
+            &stop_motion.frames(),
+            &path,
+            12.0,
+            &config.fourcc,
+            |value| {
+                progress.push(value);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(progress, [0.0, 0.25, 0.5, 0.75]);
+        let video = VideoCapture::from_file(&path.to_string_lossy(), videoio::CAP_ANY).unwrap();
+        assert_eq!(video.get(videoio::CAP_PROP_FRAME_COUNT).unwrap(), 4.0);
+
+        let result = export(
+            &stop_motion.frames(),
+            &path,
+            12.0,
+            &config.fourcc,
+            |value| {
+                if value > 0.4 {
+                    bail!("cancelled");
+                }
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+        assert!(!path.exists());
+        assert!(export(&[], &path, 12.0, &config.fourcc, |_| Ok(())).is_err());
+        fs::remove_dir_all(&config.dir).unwrap();
+    }
+}
@@ -0,0 +1,692 @@
+use std::{
+    io::Write,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{Mat, Size, Vector},
+    imgcodecs,
+    imgproc::{self, INTER_AREA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{Auth, Connection, Listener, Request, Role, WRITE_TIMEOUT},
+    bitrate::BitrateMeter,
+    connections::{self, Kind, Status},
+    feed::Frame,
+    photo, privacy,
+};
+
+const BOUNDARY: &str = "frame";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StreamConfig {
+    // 监听地址, 例如 "0.0.0.0:8080", 不设置时不启动. 命令行 --serve 优先.
+    pub addr: Option<String>,
+    pub quality: i32,
+    // 每秒最多发送的帧数.
+    pub fps: f64,
+    // 缩小到该宽度, 不设置时使用原始分辨率.
+    pub width: Option<i32>,
+    // 客户端发送不及时 (网络拥塞) 时按 ladder 逐档降低画质, 恢复后逐档回升.
+    pub adaptive: bool,
+    // quality 和 width 之下的各档, 从高到低.
+    pub ladder: Vec<Rung>,
+}
+
+impl Default for StreamConfig {
+    fn default() -> Self {
+        Self {
+            addr: None,
+            quality: 80,
+            fps: 15.0,
+            width: None,
+            adaptive: false,
+            ladder: vec![
+                Rung::new(1280, 70),
+                Rung::new(960, 60),
+                Rung::new(640, 50),
+                Rung::new(480, 40),
+            ],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rung {
+    pub width: Option<i32>,
+    pub quality: i32,
+}
+
+impl Rung {
+    fn new(width: i32, quality: i32) -> Self {
+        Self {
+            width: Some(width),
+            quality,
+        }
+    }
+}
+
+impl StreamConfig {
+    // 第 0 档为 quality 和 width, 自适应关闭时只有这一档.
+    pub fn rungs(&self) -> Vec<Rung> {
+        let top = Rung {
+            width: self.width,
+            quality: self.quality,
+        };
+        let lower = self.ladder.iter().filter(|_| self.adaptive).cloned();
+        std::iter::once(top).chain(lower).collect()
+    }
+}
+
+// 拥塞后至少保持该时间再降档, 给客户端消化积压的时间.
+const STEP_DOWN: Duration = Duration::from_secs(2);
+// 持续这么久没有拥塞才升一档, 避免在两档之间来回切换.
+const STEP_UP: Duration = Duration::from_secs(10);
+
+// 客户端在统计窗口内发送的帧数, 跳过的帧数和最长的一次发送时间.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Feedback {
+    pub sent: u64,
+    pub skipped: u64,
+    pub slowest: Duration,
+}
+
+impl Feedback {
+    // 跳过超过五分之一的帧, 或一帧的发送时间超过两帧的间隔时认为拥塞.
+    pub fn congested(&self, interval: Duration) -> bool {
+        self.skipped * 4 > self.sent || self.slowest > interval * 2
+    }
+}
+
+// 按客户端的反馈选择档位, 0 为最高画质. 多个客户端共用一次编码, 由最慢的客户端决定.
+pub struct Adaptive {
+    rungs: usize,
+    interval: Duration,
+    level: usize,
+    changed: Instant,
+    clear_since: Option<Instant>,
+}
+
+impl Adaptive {
+    pub fn new(rungs: usize, interval: Duration) -> Self {
+        Self {
+            rungs: rungs.max(1),
+            interval,
+            level: 0,
+            changed: Instant::now(),
+            clear_since: None,
+        }
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    // 每个统计窗口调用一次, now 为窗口结束的时间, 返回是否换档.
+    pub fn update(&mut self, feedback: Feedback, now: Instant) -> bool {
+        let since_change = now.duration_since(self.changed);
+        let previous = self.level;
+        if feedback.congested(self.interval) {
+            self.clear_since = None;
+            if since_change >= STEP_DOWN && self.level + 1 < self.rungs {
+                self.level += 1;
+            }
+        } else if feedback.sent > 0 {
+            let clear_since = *self.clear_since.get_or_insert(now);
+            if now.duration_since(clear_since) >= STEP_UP
+                && since_change >= STEP_UP
+                && self.level > 0
+            {
+                self.level -= 1;
+                self.clear_since = Some(now);
+            }
+        }
+        if self.level == previous {
+            return false;
+        }
+        self.changed = now;
+        true
+    }
+}
+
+// 最新一帧 JPEG 和它的序号, 编码线程写入后唤醒所有客户端.
+#[derive(Default)]
+struct Latest {
+    sequence: u64,
+    jpeg: Option<Arc<Vec<u8>>>,
+    // JpegFeed 被丢弃, 编码线程已退出, 不会再有新帧.
+    closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+    latest: Mutex<Latest>,
+    updated: Condvar,
+    clients: AtomicUsize,
+    // 编码后每帧 JPEG 的大小.
+    bitrate: Mutex<BitrateMeter>,
+    // 客户端的发送情况, 编码线程每个统计窗口取走一次.
+    feedback: Mutex<Feedback>,
+    // 当前使用的档位.
+    level: AtomicUsize,
+}
+
+// 按需编码的 JPEG 帧: 采集线程通过帧回调把帧交给编码线程, 编码后的 JPEG 交给所有订阅者.
+// 不另外读取摄像头, 没有订阅者时不编码. MJPEG 推流和远程控制的预览都使用它.
+pub struct JpegFeed {
+    shared: Arc<Shared>,
+    frames: SyncSender<Mat>,
+    interval: Duration,
+    last: Option<Instant>,
+    blank: Mat,
+    rungs: Vec<Rung>,
+}
+
+impl JpegFeed {
+    // 编码线程以 name 命名, 按 config 中的 fps, width 和 quality 编码.
+    pub fn new(name: &str, config: &StreamConfig) -> Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let (frames, receiver) = sync_channel(1);
+        let encoder = shared.clone();
+        let encoding = config.clone();
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || encode(receiver, &encoding, &encoder))?;
+        Ok(Self {
+            shared,
+            frames,
+            interval: Duration::from_secs_f64(1.0 / config.fps.max(0.1)),
+            last: None,
+            blank: Mat::default(),
+            rungs: config.rungs(),
+        })
+    }
+
+    // 在其他线程中订阅编码后的帧.
+    pub fn subscriber(&self) -> Subscriber {
+        Subscriber(self.shared.clone())
+    }
+
+    pub fn clients(&self) -> usize {
+        self.shared.clients.load(Ordering::SeqCst)
+    }
+
+    // 码率和每帧大小的迷你图, 没有订阅者 (不编码) 时为 None.
+    pub fn bitrate(&self) -> Option<String> {
+        let mut bitrate = self.shared.bitrate.lock().unwrap();
+        if self.clients() == 0 {
+            bitrate.reset();
+            return None;
+        }
+        let mut text = bitrate.describe(0.0);
+        if self.rungs.len() > 1 {
+            let level = self.shared.level.load(Ordering::SeqCst);
+            let rung = &self.rungs[level.min(self.rungs.len() - 1)];
+            text += &format!(
+                ", level {}/{} ({})",
+                level + 1,
+                self.rungs.len(),
+                describe(rung)
+            );
+        }
+        Some(format!("{}\n{}", text, bitrate.sparkline()))
+    }
+
+    // 在帧回调中调用. 编码线程还在处理上一帧时跳过这一帧.
+    pub fn push(&mut self, frame: &Frame) {
+        if self.clients() == 0 {
+            return;
+        }
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return;
+        }
+        let Ok(image) = privacy::output(frame.image, &mut self.blank) else {
+            return;
+        };
+        let Ok(image) = image.try_clone() else {
+            return;
+        };
+        if self.frames.try_send(image).is_ok() {
+            self.last = Some(Instant::now());
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Subscriber(Arc<Shared>);
+
+impl Subscriber {
+    // 开始接收帧, 返回的 Subscription 被丢弃时停止.
+    pub fn subscribe(&self) -> Subscription {
+        self.0.clients.fetch_add(1, Ordering::SeqCst);
+        Subscription {
+            shared: self.0.clone(),
+            sent: 0,
+            skipped: 0,
+        }
+    }
+
+    pub fn clients(&self) -> usize {
+        self.0.clients.load(Ordering::SeqCst)
+    }
+}
+
+pub struct Subscription {
+    shared: Arc<Shared>,
+    sent: u64,
+    // 取到的帧之前没有取到的帧数, 发送完成时报告.
+    skipped: u64,
+}
+
+impl Subscription {
+    // 等待还没有取过的最新一帧, timeout 内没有新帧时返回 None. 推流已关闭时返回错误.
+    pub fn next(&mut self, timeout: Duration) -> Result<Option<Arc<Vec<u8>>>> {
+        let latest = self.shared.latest.lock().unwrap();
+        let (latest, _) = self
+            .shared
+            .updated
+            .wait_timeout_while(latest, timeout, |latest| {
+                latest.sequence == self.sent && !latest.closed
+            })
+            .unwrap();
+        if latest.sequence == self.sent {
+            if latest.closed {
+                bail!("stream closed");
+            }
+            return Ok(None);
+        }
+        if self.sent > 0 {
+            self.skipped = latest.sequence - self.sent - 1;
+        }
+        self.sent = latest.sequence;
+        Ok(latest.jpeg.clone())
+    }
+
+    // 上一次 next 取到的帧发送完成, elapsed 为写入连接花费的时间. 自适应画质据此判断拥塞.
+    pub fn delivered(&mut self, elapsed: Duration) {
+        let mut feedback = self.shared.feedback.lock().unwrap();
+        feedback.sent += 1;
+        feedback.skipped += std::mem::take(&mut self.skipped);
+        feedback.slowest = feedback.slowest.max(elapsed);
+    }
+}
+
+impl Drop for Subscription {
+    fn drop(&mut self) {
+        self.shared.clients.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// MJPEG 推流: 编码后的 JPEG 发给所有连接的客户端.
+pub struct MjpegServer {
+    feed: JpegFeed,
+    _listener: Listener,
+}
+
+impl MjpegServer {
+    // on_clients 在客户端连接和断开时以当前连接数调用. 观看需要 viewer 权限.
+    pub fn start(
+        addr: &str,
+        config: &StreamConfig,
+        auth: &Auth,
+        on_clients: impl Fn(usize) + Clone + Send + 'static,
+    ) -> Result<Self> {
+        let feed = JpegFeed::new("mjpeg", config)?;
+        let subscriber = feed.subscriber();
+        let checker = auth.clone();
+        let name = addr.to_string();
+        let listener = auth.listen(addr, "MJPEG stream", move |mut stream| {
+            let request = Request::read(&mut stream)?;
+            let Some(user) = checker.authorize(&mut stream, &request, Role::Viewer)? else {
+                return Ok(());
+            };
+            let mut peer = stream.peer();
+            if !user.is_empty() {
+                peer = format!("{} ({})", peer, user);
+            }
+            privacy::audit("stream", true, &peer);
+            let subscription = subscriber.subscribe();
+            let clients = |count| {
+                on_clients(count);
+                connections::update(&name, Kind::Stream, |connection| {
+                    connection.clients = Some(count)
+                });
+            };
+            clients(subscriber.clients());
+            // 客户端断开时写入失败, 不是错误.
+            let _ = respond(stream, subscription);
+            clients(subscriber.clients());
+            privacy::audit("stream", false, &peer);
+            Ok(())
+        })?;
+        connections::update(addr, Kind::Stream, |connection| {
+            connection.set_status(Status::Listening);
+            connection.clients = Some(0);
+        });
+        Ok(Self {
+            feed,
+            _listener: listener,
+        })
+    }
+
+    pub fn clients(&self) -> usize {
+        self.feed.clients()
+    }
+
+    pub fn bitrate(&self) -> Option<String> {
+        self.feed.bitrate()
+    }
+
+    // 在帧回调中调用.
+    pub fn push(&mut self, frame: &Frame) {
+        self.feed.push(frame);
+    }
+}
+
+// 其他设备打开推流的地址, scheme 为 http 或 https. 监听所有网卡时用本机的局域网地址代替 0.0.0.0.
+pub fn link(scheme: &str, addr: &str) -> String {
+    match addr.parse::<SocketAddr>() {
+        Ok(socket) if socket.ip().is_unspecified() => match local_ip() {
+            Some(ip) => format!("{}://{}/", scheme, SocketAddr::new(ip, socket.port())),
+            None => format!("{}://{}/", scheme, addr),
+        },
+        _ => format!("{}://{}/", scheme, addr),
+    }
+}
+
+// 向外连接的 UDP 套接字使用的本机地址, connect 不会发送数据.
+fn local_ip() -> Option<IpAddr> {
+    let socket = UdpSocket::bind("0.0.0.0:0").ok()?;
+    socket.connect("192.0.2.1:80").ok()?;
+    Some(socket.local_addr().ok()?.ip())
+}
+
+fn describe(rung: &Rung) -> String {
+    match rung.width {
+        Some(width) => format!("{} px, quality {}", width, rung.quality),
+        None => format!("full size, quality {}", rung.quality),
+    }
+}
+
+fn encode(frames: Receiver<Mat>, config: &StreamConfig, shared: &Shared) {
+    let rungs = config.rungs();
+    let interval = Duration::from_secs_f64(1.0 / config.fps.max(0.1));
+    let mut adaptive = Adaptive::new(rungs.len(), interval);
+    let mut window = Instant::now();
+    let mut small = Mat::default();
+    for frame in frames {
+        if rungs.len() > 1 && window.elapsed() >= STEP_DOWN {
+            window = Instant::now();
+            let feedback = std::mem::take(&mut *shared.feedback.lock().unwrap());
+            if adaptive.update(feedback, window) {
+                let level = adaptive.level();
+                shared.level.store(level, Ordering::SeqCst);
+                println!(
+                    "MJPEG stream: level {} ({})",
+                    level + 1,
+                    describe(&rungs[level])
+                );
+            }
+        }
+        let rung = &rungs[adaptive.level()];
+        let params = photo::write_params("jpg", Some(rung.quality));
+        let frame = match rung.width {
+            Some(width) if width > 0 && width < frame.cols() => {
+                let height = (frame.rows() as f64 * width as f64 / frame.cols() as f64) as i32;
+                let size = Size::new(width, height.max(1));
+                if let Err(err) = imgproc::resize(&frame, &mut small, size, 0.0, 0.0, INTER_AREA) {
+                    eprintln!("MJPEG stream: {:?}", err);
+                    continue;
+                }
+                &small
+            }
+            _ => &frame,
+        };
+        let mut data = Vector::new();
+        if let Err(err) = imgcodecs::imencode(".jpg", frame, &mut data, &params) {
+            eprintln!("MJPEG stream: {:?}", err);
+            continue;
+        }
+        shared.bitrate.lock().unwrap().add(data.len() as u64);
+        let mut latest = shared.latest.lock().unwrap();
+        latest.sequence += 1;
+        latest.jpeg = Some(Arc::new(data.to_vec()));
+        shared.updated.notify_all();
+    }
+    // JpegFeed 被丢弃后通道关闭, 唤醒还在等待的客户端让它们断开.
+    shared.latest.lock().unwrap().closed = true;
+    shared.updated.notify_all();
+}
+
+// 任意路径都返回 multipart/x-mixed-replace 流, 浏览器和 VLC 都可以直接打开.
+// 客户端停止读取时写入在 WRITE_TIMEOUT 后出错, 返回错误断开这个客户端.
+fn respond(mut stream: Connection, mut subscription: Subscription) -> Result<()> {
+    stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: multipart/x-mixed-replace; boundary={}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+        BOUNDARY
+    )?;
+    loop {
+        // 采集暂停时没有新帧, 继续等待. 推流关闭时结束.
+        let Some(jpeg) = subscription.next(Duration::from_secs(1))? else {
+            continue;
+        };
+        write!(
+            stream,
+            "--{}\r\nContent-Type: image/jpeg\r\nContent-Length: {}\r\n\r\n",
+            BOUNDARY,
+            jpeg.len()
+        )?;
+        let start = Instant::now();
+        stream.write_all(&jpeg)?;
+        stream.write_all(b"\r\n")?;
+        subscription.delivered(start.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Read,
+        net::{TcpListener, TcpStream},
+    };
+
+    use chrono::Local;
+    use opencv::core::{Scalar, CV_8UC3};
+
+    use super::*;
+    use crate::auth::AuthConfig;
+
+    const INTERVAL: Duration = Duration::from_millis(66);
+
+    fn feedback(sent: u64, skipped: u64, slowest: u64) -> Feedback {
+        Feedback {
+            sent,
+            skipped,
+            slowest: Duration::from_millis(slowest),
+        }
+    }
+
+    // 跳过超过五分之一的帧或一帧发送超过两帧间隔时拥塞.
+    #[test]
+    fn congestion() {
+        assert!(!feedback(20, 5, 10).congested(INTERVAL));
+        assert!(feedback(20, 6, 10).congested(INTERVAL));
+        assert!(!feedback(20, 0, 132).congested(INTERVAL));
+        assert!(feedback(20, 0, 133).congested(INTERVAL));
+    }
+
+    // 自适应关闭时只有 quality 和 width 一档, 打开时接上 ladder.
+    #[test]
+    fn rungs() {
+        let mut config = StreamConfig {
+            quality: 90,
+            ..Default::default()
+        };
+        assert_eq!(
+            config.rungs(),
+            vec![Rung {
+                width: None,
+                quality: 90
+            }]
+        );
+        config.adaptive = true;
+        let rungs = config.rungs();
+        assert_eq!(rungs.len(), 5);
+        assert_eq!(rungs[1], Rung::new(1280, 70));
+        assert_eq!(rungs[4], Rung::new(480, 40));
+    }
+
+    // 拥塞时每 2 秒最多降一档, 不低于最后一档; 通畅 10 秒后升一档.
+    #[test]
+    fn ladder_steps() {
+        let start = Instant::now();
+        let at = |seconds: u64| start + Duration::from_secs(seconds);
+        let (bad, good) = (feedback(10, 5, 0), feedback(30, 0, 5));
+        let mut adaptive = Adaptive::new(3, INTERVAL);
+        let steps = [
+            (bad, 1, false, 0),
+            (bad, 2, true, 1),
+            (bad, 3, false, 1),
+            (bad, 4, true, 2),
+            (bad, 6, false, 2),
+            // 没有客户端的窗口不算通畅.
+            (Feedback::default(), 7, false, 2),
+            (good, 8, false, 2),
+            (good, 17, false, 2),
+            (good, 18, true, 1),
+            (good, 20, false, 1),
+            (good, 28, true, 0),
+            (good, 40, false, 0),
+        ];
+        for (feedback, seconds, changed, level) in steps {
+            assert_eq!(
+                adaptive.update(feedback, at(seconds)),
+                changed,
+                "{}s",
+                seconds
+            );
+            assert_eq!(adaptive.level(), level, "{}s", seconds);
+        }
+    }
+
+    fn push(feed: &mut JpegFeed, image: &Mat) {
+        feed.push(&Frame {
+            index: 0,
+            time: Local::now(),
+            image,
+        });
+    }
+
+    fn fast() -> StreamConfig {
+        StreamConfig {
+            fps: 1000.0,
+            ..Default::default()
+        }
+    }
+
+    // 没有订阅者时不编码, 订阅后取到 JPEG; 推流关闭后 next 返回错误.
+    #[test]
+    fn feed_subscription() {
+        let image = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(90.)).unwrap();
+        let mut feed = JpegFeed::new("mjpeg-test", &fast()).unwrap();
+        let subscriber = feed.subscriber();
+        push(&mut feed, &image);
+        assert_eq!(feed.bitrate(), None);
+
+        let mut subscription = subscriber.subscribe();
+        assert_eq!(feed.clients(), 1);
+        let jpeg = loop {
+            push(&mut feed, &image);
+            if let Some(jpeg) = subscription.next(Duration::from_millis(100)).unwrap() {
+                break jpeg;
+            }
+        };
+        assert_eq!(&jpeg[..2], [0xff, 0xd8]);
+        assert!(feed.bitrate().is_some());
+        subscription.delivered(Duration::from_millis(3));
+
+        drop(feed);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while subscription.next(Duration::from_millis(100)).is_ok() {
+            assert!(Instant::now() < deadline, "timed out");
+        }
+        drop(subscription);
+        assert_eq!(subscriber.clients(), 0);
+    }
+
+    // 客户端收到 multipart 响应和 JPEG 帧, 连接数通过回调报告.
+    #[test]
+    fn serves_mjpeg() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let clients = Arc::new(AtomicUsize::new(0));
+        let counted = clients.clone();
+        let mut server = MjpegServer::start(
+            &format!("127.0.0.1:{}", port),
+            &fast(),
+            &Auth::new(&AuthConfig::default()).unwrap(),
+            move |count| counted.store(count, Ordering::SeqCst),
+        )
+        .unwrap();
+        let image = Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(90.)).unwrap();
+
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.write_all(b"GET / HTTP/1.1\r\n\r\n").unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut received = Vec::new();
+        let part = b"Content-Type: image/jpeg\r\n";
+        while !received.windows(part.len()).any(|window| window == part) {
+            assert!(Instant::now() < deadline, "timed out");
+            server.push(&Frame {
+                index: 0,
+                time: Local::now(),
+                image: &image,
+            });
+            let mut buffer = [0; 4096];
+            if let Ok(read) = stream.read(&mut buffer) {
+                received.extend_from_slice(&buffer[..read]);
+            }
+        }
+        let text = String::from_utf8_lossy(&received);
+        assert!(text.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(text.contains("multipart/x-mixed-replace; boundary=frame"));
+        assert!(text.contains("--frame\r\n"));
+        assert_eq!(server.clients(), 1);
+        assert_eq!(clients.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn links() {
+        assert_eq!(link("http", "192.168.1.2:8080"), "http://192.168.1.2:8080/");
+        assert_eq!(
+            link("https", "camera.local:8443"),
+            "https://camera.local:8443/"
+        );
+        let link = link("http", "0.0.0.0:8080");
+        assert!(
+            link.starts_with("http://") && link.ends_with(":8080/"),
+            "{link}"
+        );
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Timelike};
+use opencv::{
+    core::{self, Mat, Point, Rect, Scalar, Size, CV_32F, CV_32FC3, CV_8U, CV_8UC3},
+    imgcodecs,
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, INTER_AREA, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SummaryConfig {
+    pub enabled: bool,
+    // 统计使用的画面宽度, 高度按宽高比计算. 24 个小时的平均图都按该尺寸保存在内存中.
+    pub width: i32,
+    // 采样间隔 (秒), 长时间统计不需要每一帧.
+    pub interval: f64,
+    // 与上一次采样的灰度差超过该值的像素计为运动.
+    pub motion_threshold: f64,
+    pub dir: PathBuf,
+    // 自动导出的间隔 (小时), 不设置时只在点击按钮时导出.
+    pub export_every: Option<f64>,
+}
+
+impl Default for SummaryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 640,
+            interval: 1.0,
+            motion_threshold: 25.0,
+            dir: PathBuf::from("summaries"),
+            export_every: None,
+        }
+    }
+}
+
+// 一个时间段内的逐像素平均值, 用 accumulate_weighted 计算滑动平均, 不需要 64 位的累加和.
+#[derive(Clone)]
+struct Mean {
+    mean: Mat,
+    samples: u64,
+}
+
+impl Mean {
+    fn new(size: Size) -> Result<Self> {
+        Ok(Self {
+            mean: Mat::zeros(size.height, size.width, CV_32FC3)?.to_mat()?,
+            samples: 0,
+        })
+    }
+
+    fn add(&mut self, frame: &Mat) -> Result<()> {
+        self.samples += 1;
+        let alpha = 1.0 / self.samples as f64;
+        imgproc::accumulate_weighted(frame, &mut self.mean, alpha, &core::no_array())?;
+        Ok(())
+    }
+
+    fn image(&self) -> Result<Mat> {
+        let mut image = Mat::default();
+        self.mean.convert_to(&mut image, CV_8UC3, 1.0, 0.0)?;
+        Ok(image)
+    }
+}
+
+// 长时间的逐像素统计: 平均值, 最大值, 运动频率, 以及按一天中的小时分开的平均值.
+// 用于生态观察和交通观测, 例如 "平均的一天" 合成图.
+pub struct Summary {
+    config: SummaryConfig,
+    started: DateTime<Local>,
+    last: Option<Instant>,
+    exported: Instant,
+    size: Option<Size>,
+    mean: Option<Mean>,
+    max: Mat,
+    // 每个像素被判定为运动的次数.
+    motion: Mat,
+    hours: Vec<Option<Mean>>,
+    small: Mat,
+    gray: Mat,
+    previous: Mat,
+    diff: Mat,
+    mask: Mat,
+}
+
+// 导出需要的数据, 在后台线程中写文件.
+pub struct Snapshot {
+    dir: PathBuf,
+    stem: String,
+    samples: u64,
+    mean: Mean,
+    max: Mat,
+    motion: Mat,
+    hours: Vec<Option<Mean>>,
+}
+
+impl Summary {
+    pub fn new(config: &SummaryConfig) -> Self {
+        Self {
+            config: config.clone(),
+            started: Local::now(),
+            last: None,
+            exported: Instant::now(),
+            size: None,
+            mean: None,
+            max: Mat::default(),
+            motion: Mat::default(),
+            hours: vec![None; 24],
+            small: Mat::default(),
+            gray: Mat::default(),
+            previous: Mat::default(),
+            diff: Mat::default(),
+            mask: Mat::default(),
+        }
+    }
+
+    pub fn samples(&self) -> u64 {
+        self.mean.as_ref().map_or(0, |mean| mean.samples)
+    }
+
+    // 按采样间隔加入一帧. 画面尺寸改变 (切换来源) 时重新开始统计.
+    pub fn add(&mut self, frame: &Mat) -> Result<()> {
+        let interval = Duration::from_secs_f64(self.config.interval.max(0.0));
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        let width = self.config.width.clamp(16, frame.cols().max(16));
+        let height = (frame.rows() as f64 * width as f64 / frame.cols() as f64).round() as i32;
+        let size = Size::new(width, height.max(1));
+        if self.size != Some(size) {
+            *self = Self {
+                size: Some(size),
+                ..Self::new(&self.config)
+            };
+            self.last = Some(Instant::now());
+        }
+        imgproc::resize(frame, &mut self.small, size, 0.0, 0.0, INTER_AREA)?;
+
+        match &mut self.mean {
+            Some(mean) => mean.add(&self.small)?,
+            None => {
+                let mut mean = Mean::new(size)?;
+                mean.add(&self.small)?;
+                self.mean = Some(mean);
+            }
+        }
+        let hour = &mut self.hours[Local::now().hour() as usize];
+        if hour.is_none() {
+            *hour = Some(Mean::new(size)?);
+        }
+        if let Some(hour) = hour {
+            hour.add(&self.small)?;
+        }
+        if self.max.empty() {
+            self.small.copy_to(&mut self.max)?;
+        } else {
+            let mut max = Mat::default();
+            core::max(&self.max, &self.small, &mut max)?;
+            self.max = max;
+        }
+
+        imgproc::cvt_color(&self.small, &mut self.gray, imgproc::COLOR_BGR2GRAY, 0)?;
+        if self.motion.empty() {
+            self.motion = Mat::zeros(size.height, size.width, CV_32F)?.to_mat()?;
+        }
+        if !self.previous.empty() {
+            core::absdiff(&self.gray, &self.previous, &mut self.diff)?;
+            imgproc::threshold(
+                &self.diff,
+                &mut self.mask,
+                self.config.motion_threshold,
+                1.0,
+                imgproc::THRESH_BINARY,
+            )?;
+            imgproc::accumulate(&self.mask, &mut self.motion, &core::no_array())?;
+        }
+        std::mem::swap(&mut self.gray, &mut self.previous);
+        Ok(())
+    }
+
+    // 距离上次导出超过 export_every 时返回 true.
+    pub fn export_due(&self) -> bool {
+        self.config
+            .export_every
+            .is_some_and(|hours| self.exported.elapsed().as_secs_f64() >= hours * 3600.0)
+    }
+
+    // 复制当前的统计结果, 统计继续进行.
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        self.exported = Instant::now();
+        let Some(mean) = &self.mean else {
+            bail!("no samples yet");
+        };
+        Ok(Snapshot {
+            dir: self.config.dir.clone(),
+            stem: format!(
+                "summary-{}-{}",
+                self.started.format("%Y-%m-%dT%H-%M-%S"),
+                Local::now().format("%Y-%m-%dT%H-%M-%S")
+            ),
+            samples: mean.samples,
+            mean: mean.clone(),
+            max: self.max.try_clone()?,
+            motion: self.motion.try_clone()?,
+            hours: self.hours.clone(),
+        })
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(&self.config);
+    }
+}
+
+impl Snapshot {
+    // 写出平均图, 最大值图, 运动频率热力图和 24 小时的平均图拼接, 返回文件名前缀.
+    pub fn export(&self) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)?;
+        let path = |kind: &str| self.dir.join(format!("{}-{}.png", self.stem, kind));
+        write(&path("mean"), &self.mean.image()?)?;
+        write(&path("max"), &self.max)?;
+
+        // 运动频率: 运动次数 / 采样次数, 映射为热力图.
+        let mut frequency = Mat::default();
+        let scale = 255.0 / self.samples.max(1) as f64;
+        self.motion.convert_to(&mut frequency, CV_8U, scale, 0.0)?;
+        let mut heat = Mat::default();
+        imgproc::apply_color_map(&frequency, &mut heat, imgproc::COLORMAP_JET)?;
+        write(&path("motion"), &heat)?;
+
+        write(&path("day"), &self.day()?)?;
+        Ok(self.dir.join(&self.stem))
+    }
+
+    // 6 x 4 的拼接图, 每格为一个小时的平均画面, 没有采样的小时为黑色.
+    fn day(&self) -> Result<Mat> {
+        const COLUMNS: i32 = 6;
+        let size = self.mean.mean.size()?;
+        let rows = 24 / COLUMNS;
+        let mut day = Mat::zeros(size.height * rows, size.width * COLUMNS, CV_8UC3)?.to_mat()?;
+        for (hour, mean) in self.hours.iter().enumerate() {
+            let hour = hour as i32;
+            let rect = Rect::new(
+                (hour % COLUMNS) * size.width,
+                (hour / COLUMNS) * size.height,
+                size.width,
+                size.height,
+            );
+            let mut cell = Mat::roi_mut(&mut day, rect)?;
+            if let Some(mean) = mean {
+                mean.image()?.copy_to(&mut *cell)?;
+            }
+            imgproc::put_text(
+                &mut *cell,
+                &format!("{:02}:00", hour),
+                Point::new(8, 24),
+                FONT_HERSHEY_SIMPLEX,
+                0.7,
+                Scalar::new(255., 255., 255., 0.),
+                2,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(day)
+    }
+}
+
+fn write(path: &Path, image: &Mat) -> Result<()> {
+    if !imgcodecs::imwrite(&path.to_string_lossy(), image, &core::Vector::new())? {
+        bail!("write {}", path.display());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::Vec3b;
+
+    use super::*;
+
+    fn solid(rows: i32, cols: i32, level: f64) -> Mat {
+        Mat::new_rows_cols_with_default(rows, cols, CV_8UC3, Scalar::all(level)).unwrap()
+    }
+
+    fn config(dir: &Path) -> SummaryConfig {
+        SummaryConfig {
+            enabled: true,
+            width: 32,
+            interval: 0.0,
+            dir: dir.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn mean_max_and_motion() {
+        let mut summary = Summary::new(&config(Path::new("summaries")));
+        assert_eq!(summary.samples(), 0);
+        summary.add(&solid(48, 64, 100.)).unwrap();
+        summary.add(&solid(48, 64, 200.)).unwrap();
+        assert_eq!(summary.samples(), 2);
+
+        let mean = summary.mean.as_ref().unwrap().image().unwrap();
+        assert_eq!((mean.cols(), mean.rows()), (32, 24));
+        assert_eq!(*mean.at_2d::<Vec3b>(10, 10).unwrap(), Vec3b::all(150));
+        assert_eq!(
+            *summary.max.at_2d::<Vec3b>(10, 10).unwrap(),
+            Vec3b::all(200)
+        );
+        // 两次采样之间每个像素都变化了一次.
+        assert_eq!(*summary.motion.at_2d::<f32>(10, 10).unwrap(), 1.0);
+        let hours = summary.hours.iter().filter(|hour| hour.is_some()).count();
+        assert_eq!(hours, 1);
+
+        summary.reset();
+        assert_eq!(summary.samples(), 0);
+    }
+
+    // 采样间隔内的帧被跳过, 画面尺寸改变时重新开始统计.
+    #[test]
+    fn interval_and_size_change() {
+        let mut summary = Summary::new(&SummaryConfig {
+            interval: 3600.0,
+            ..config(Path::new("summaries"))
+        });
+        summary.add(&solid(48, 64, 100.)).unwrap();
+        summary.add(&solid(48, 64, 100.)).unwrap();
+        assert_eq!(summary.samples(), 1);
+
+        let mut summary = Summary::new(&config(Path::new("summaries")));
+        summary.add(&solid(48, 64, 100.)).unwrap();
+        summary.add(&solid(48, 64, 100.)).unwrap();
+        summary.add(&solid(30, 20, 100.)).unwrap();
+        assert_eq!(summary.samples(), 1);
+        assert_eq!(summary.size, Some(Size::new(20, 30)));
+    }
+
+    #[test]
+    fn export_writes_images() {
+        let dir = std::env::temp_dir().join(format!("summary-export-{}", std::process::id()));
+        let mut summary = Summary::new(&config(&dir));
+        assert!(!summary.export_due());
+        assert!(summary.snapshot().is_err());
+
+        summary.add(&solid(48, 64, 100.)).unwrap();
+        summary.add(&solid(48, 64, 200.)).unwrap();
+        let stem = summary.snapshot().unwrap().export().unwrap();
+        for kind in ["mean", "max", "motion", "day"] {
+            let path = PathBuf::from(format!("{}-{}.png", stem.display(), kind));
+            assert!(path.exists(), "{}", path.display());
+        }
+        let day = imgcodecs::imread(
+            &format!("{}-day.png", stem.display()),
+            imgcodecs::IMREAD_COLOR,
+        )
+        .unwrap();
+        assert_eq!((day.cols(), day.rows()), (32 * 6, 24 * 4));
+
+        summary.config.export_every = Some(0.0);
+        assert!(summary.export_due());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,190 @@
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ReconnectConfig {
+    pub enabled: bool,
+    // 实时来源连续多久 (秒) 读不到帧时认为已断开.
+    pub timeout: f64,
+    // 第一次重新打开前的等待 (秒), 之后每次失败加倍, 最长 max_backoff.
+    pub backoff: f64,
+    pub max_backoff: f64,
+    // 断开前正在录制时, 重新连接后录制到新文件.
+    pub resume_recording: bool,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout: 2.0,
+            backoff: 1.0,
+            max_backoff: 30.0,
+            resume_recording: true,
+        }
+    }
+}
+
+pub enum Event {
+    None,
+    // 刚判定为断开.
+    Disconnected,
+    // 到了重新打开来源的时间, 参数为第几次尝试.
+    Retry(u32),
+}
+
+// 监视实时来源的读取: 读取出错和空帧持续超过 timeout 时判定为断开, 之后按退避时间重试.
+pub struct Supervisor {
+    config: ReconnectConfig,
+    last_frame: Instant,
+    // 上一帧之后已经计为丢失的帧数.
+    missed: u64,
+    disconnected: bool,
+    attempts: u32,
+    next_retry: Instant,
+    // 断开时正在录制.
+    pub recording: bool,
+}
+
+impl Supervisor {
+    pub fn new(config: &ReconnectConfig) -> Self {
+        Self {
+            config: config.clone(),
+            last_frame: Instant::now(),
+            missed: 0,
+            disconnected: false,
+            attempts: 0,
+            next_retry: Instant::now(),
+            recording: false,
+        }
+    }
+
+    pub fn is_disconnected(&self) -> bool {
+        self.disconnected
+    }
+
+    // 读到帧时调用, 之前处于断开状态时返回 true.
+    pub fn frame(&mut self) -> bool {
+        self.last_frame = Instant::now();
+        self.missed = 0;
+        let recovered = self.disconnected;
+        self.disconnected = false;
+        self.attempts = 0;
+        recovered
+    }
+
+    // 读取出错或空帧时调用.
+    pub fn failure(&mut self) -> Event {
+        if !self.config.enabled {
+            return Event::None;
+        }
+        let now = Instant::now();
+        if !self.disconnected {
+            if now.duration_since(self.last_frame) < secs(self.config.timeout) {
+                return Event::None;
+            }
+            self.disconnected = true;
+            self.attempts = 0;
+            self.next_retry = now + secs(self.config.backoff);
+            return Event::Disconnected;
+        }
+        if now < self.next_retry {
+            return Event::None;
+        }
+        self.attempts += 1;
+        self.next_retry = now + self.backoff();
+        Event::Retry(self.attempts)
+    }
+
+    // 按来源帧率估算上一帧之后新少了几帧. 读取失败的次数与帧率无关, 每帧只计一次.
+    pub fn missed(&mut self, fps: f64) -> u64 {
+        let expected = (self.last_frame.elapsed().as_secs_f64() * fps.max(1.0)) as u64;
+        let missed = expected.saturating_sub(self.missed);
+        self.missed = self.missed.max(expected);
+        missed
+    }
+
+    // 下一次重试前的等待时间.
+    pub fn backoff(&self) -> Duration {
+        let backoff = self.config.backoff * 2f64.powi(self.attempts.min(16) as i32);
+        secs(backoff.min(self.config.max_backoff))
+    }
+}
+
+fn secs(seconds: f64) -> Duration {
+    Duration::from_secs_f64(seconds.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(timeout: f64, backoff: f64) -> ReconnectConfig {
+        ReconnectConfig {
+            timeout,
+            backoff,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn disconnect_and_retry() {
+        let mut supervisor = Supervisor::new(&ReconnectConfig {
+            enabled: false,
+            ..config(0.0, 0.0)
+        });
+        assert!(matches!(supervisor.failure(), Event::None));
+
+        // 超时之前的失败不算断开.
+        let mut supervisor = Supervisor::new(&config(60.0, 0.0));
+        assert!(matches!(supervisor.failure(), Event::None));
+        assert!(!supervisor.is_disconnected());
+
+        let mut supervisor = Supervisor::new(&config(0.0, 0.0));
+        assert!(!supervisor.frame());
+        assert!(matches!(supervisor.failure(), Event::Disconnected));
+        assert!(supervisor.is_disconnected());
+        assert!(matches!(supervisor.failure(), Event::Retry(1)));
+        supervisor.config.max_backoff = 0.0;
+        assert!(matches!(supervisor.failure(), Event::Retry(2)));
+        assert!(supervisor.frame());
+        assert!(!supervisor.is_disconnected());
+        assert_eq!(supervisor.attempts, 0);
+    }
+
+    // 等待时间加倍, 不超过 max_backoff; 未到重试时间时不重试.
+    #[test]
+    fn backoff_doubles() {
+        let mut supervisor = Supervisor::new(&ReconnectConfig {
+            max_backoff: 5.0,
+            ..config(0.0, 1.0)
+        });
+        let waits: Vec<_> = (0..4)
+            .map(|attempts| {
+                supervisor.attempts = attempts;
+                supervisor.backoff().as_secs_f64()
+            })
+            .collect();
+        assert_eq!(waits, [1.0, 2.0, 4.0, 5.0]);
+
+        supervisor.attempts = 0;
+        assert!(matches!(supervisor.failure(), Event::Disconnected));
+        assert!(matches!(supervisor.failure(), Event::None));
+    }
+
+    #[test]
+    fn missed_frames_counted_once() {
+        let mut supervisor = Supervisor::new(&ReconnectConfig::default());
+        supervisor.last_frame = Instant::now() - Duration::from_millis(1050);
+        let missed = supervisor.missed(10.0);
+        assert!((10..=11).contains(&missed), "{missed}");
+        assert!(supervisor.missed(10.0) <= 1);
+        // 帧率未知时按每秒 1 帧估算.
+        assert_eq!(supervisor.missed(0.0), 0);
+
+        supervisor.frame();
+        assert_eq!(supervisor.missed(10.0), 0);
+    }
+}
@@ -0,0 +1,350 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use opencv::{core, prelude::*};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::routing;
+
+// 多机位时间对齐: 记录每个来源每帧的平均亮度, 录制结束时用亮度变化 (灯光, LED 闪光, 拍板)
+// 的互相关估计各来源相对预览来源的时间偏差, 写入录像旁的 <录像>.sync.json, 供后期对齐.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyncConfig {
+    pub enabled: bool,
+    // 只用最近多少秒的亮度估计偏差.
+    pub window: f64,
+    // 搜索的最大偏差 (秒).
+    pub max_offset: f64,
+    // 估计的精度 (秒), 亮度曲线按该间隔插值.
+    pub resolution: f64,
+    // 相关系数低于该值时认为没有找到同步事件, 不给出偏差.
+    pub min_confidence: f64,
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            window: 300.0,
+            max_offset: 2.0,
+            resolution: 0.005,
+            min_confidence: 0.5,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Series {
+    // (Unix 秒, 平均亮度).
+    samples: VecDeque<(f64, f32)>,
+    // 该来源的路由正在写入的文件.
+    files: Vec<PathBuf>,
+}
+
+// 来源名称 ("main" 为预览来源) -> 亮度曲线.
+static SERIES: Mutex<BTreeMap<String, Series>> = Mutex::new(BTreeMap::new());
+
+fn seconds(time: DateTime<Local>) -> f64 {
+    time.timestamp_micros() as f64 / 1e6
+}
+
+// 加入一帧的亮度, time 为读到该帧的时间.
+pub fn add(source: &str, time: DateTime<Local>, frame: &Mat, window: f64) -> Result<()> {
+    let mean = core::mean(frame, &core::no_array())?;
+    let channels = frame.channels().clamp(1, 4) as usize;
+    let brightness = (0..channels).map(|c| mean[c]).sum::<f64>() / channels as f64;
+    let time = seconds(time);
+    let mut series = SERIES.lock().unwrap();
+    let samples = &mut series.entry(source.to_string()).or_default().samples;
+    samples.push_back((time, brightness as f32));
+    while samples
+        .front()
+        .is_some_and(|(first, _)| time - first > window)
+    {
+        samples.pop_front();
+    }
+    Ok(())
+}
+
+// 路由打开 sink 后登记它写入的文件, 写在 manifest 中.
+pub fn register(source: &str, files: Vec<PathBuf>) {
+    SERIES
+        .lock()
+        .unwrap()
+        .entry(source.to_string())
+        .or_default()
+        .files = files;
+}
+
+// 估计 other 相对 primary 的时间偏差: 同一事件在 other 中的时间减去在 primary 中的时间.
+// 两条亮度曲线按 step 插值后取差分, 在 ±max_offset 内找相关系数最大的位移.
+// 返回 (偏差秒数, 相关系数), 重叠部分太短时为 None.
+pub fn estimate(
+    primary: &[(f64, f32)],
+    other: &[(f64, f32)],
+    max_offset: f64,
+    step: f64,
+) -> Option<(f64, f64)> {
+    let (Some(first), Some(last)) = (primary.first(), primary.last()) else {
+        return None;
+    };
+    let step = step.max(1e-4);
+    let base = first.0.min(other.first()?.0);
+    let end = last.0.max(other.last()?.0);
+    let len = ((end - base) / step) as usize + 1;
+    let a = changes(primary, base, step, len);
+    let b = changes(other, base, step, len);
+    let lags = (max_offset / step) as i64;
+    // 至少重叠 1 秒.
+    let min_pairs = (1.0 / step) as usize;
+    let mut best: Option<(i64, f64)> = None;
+    for lag in -lags..=lags {
+        let pairs = (0..len as i64).filter_map(|k| {
+            let j = usize::try_from(k + lag).ok()?;
+            Some((a[k as usize]?, *b.get(j)?.as_ref()?))
+        });
+        let Some(correlation) = pearson(pairs, min_pairs) else {
+            continue;
+        };
+        if best.map_or(true, |(_, value)| correlation > value) {
+            best = Some((lag, correlation));
+        }
+    }
+    best.map(|(lag, correlation)| (lag as f64 * step, correlation))
+}
+
+// 按 step 线性插值后的相邻差值, 曲线范围外为 None.
+fn changes(samples: &[(f64, f32)], base: f64, step: f64, len: usize) -> Vec<Option<f64>> {
+    let mut values = vec![None; len];
+    for pair in samples.windows(2) {
+        let ((t0, v0), (t1, v1)) = (pair[0], pair[1]);
+        if t1 <= t0 {
+            continue;
+        }
+        let start = ((t0 - base) / step).ceil().max(0.0) as usize;
+        let stop = (((t1 - base) / step).floor() as usize).min(len.saturating_sub(1));
+        for (k, value) in values.iter_mut().enumerate().take(stop + 1).skip(start) {
+            let t = base + k as f64 * step;
+            let f = (t - t0) / (t1 - t0);
+            *value = Some(v0 as f64 + (v1 - v0) as f64 * f);
+        }
+    }
+    std::iter::once(None)
+        .chain(values.windows(2).map(|pair| match pair {
+            [Some(previous), Some(current)] => Some(current - previous),
+            _ => None,
+        }))
+        .collect()
+}
+
+fn pearson(pairs: impl Iterator<Item = (f64, f64)>, min_pairs: usize) -> Option<f64> {
+    let (mut n, mut sa, mut sb, mut saa, mut sbb, mut sab) = (0usize, 0.0, 0.0, 0.0, 0.0, 0.0);
+    for (a, b) in pairs {
+        n += 1;
+        sa += a;
+        sb += b;
+        saa += a * a;
+        sbb += b * b;
+        sab += a * b;
+    }
+    if n < min_pairs.max(2) {
+        return None;
+    }
+    let n = n as f64;
+    let covariance = sab - sa * sb / n;
+    let variance = (saa - sa * sa / n) * (sbb - sb * sb / n);
+    (variance > 0.0).then(|| covariance / variance.sqrt())
+}
+
+pub fn manifest_path(recording: &Path) -> PathBuf {
+    recording.with_extension("sync.json")
+}
+
+// 录像开始到结束之间的各来源偏差.
+fn write_manifest(
+    config: &SyncConfig,
+    recording: &Path,
+    primary: &str,
+    started: DateTime<Local>,
+) -> Result<()> {
+    let stopped = Local::now();
+    let (from, to) = (seconds(started), seconds(stopped));
+    let series = SERIES.lock().unwrap();
+    let window = |source: &str| -> Vec<(f64, f32)> {
+        series.get(source).map_or_else(Vec::new, |series| {
+            series
+                .samples
+                .iter()
+                .filter(|(time, _)| (from..=to).contains(time))
+                .copied()
+                .collect()
+        })
+    };
+    let reference = window(routing::MAIN);
+    let mut streams = Vec::new();
+    for (source, other) in series.iter() {
+        if source == routing::MAIN {
+            continue;
+        }
+        let samples = window(source);
+        let found = estimate(&reference, &samples, config.max_offset, config.resolution)
+            .filter(|(_, confidence)| *confidence >= config.min_confidence);
+        streams.push(json!({
+            "source": source,
+            "files": other.files,
+            "offset": found.map(|(offset, _)| offset),
+            "confidence": found.map(|(_, confidence)| confidence),
+            "frames": samples.len(),
+        }));
+    }
+    let manifest = json!({
+        "recording": recording,
+        "primary": primary,
+        "started": started.to_rfc3339(),
+        "stopped": stopped.to_rfc3339(),
+        "frames": reference.len(),
+        "streams": streams,
+    });
+    let path = manifest_path(recording);
+    fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|| format!("write {}", path.display()))?;
+    Ok(())
+}
+
+// 预览来源正在写入的录像, 录像结束 (停止录制, 切换文件, 退出) 时写 manifest.
+pub struct Manifests {
+    config: SyncConfig,
+    primary: String,
+    recordings: Vec<(PathBuf, DateTime<Local>)>,
+}
+
+impl Manifests {
+    pub fn new(config: &SyncConfig) -> Self {
+        Self {
+            config: config.clone(),
+            primary: String::new(),
+            recordings: Vec::new(),
+        }
+    }
+
+    // 每帧调用, recordings 为当前正在写入的录像文件, primary 为预览来源的 URI.
+    pub fn write<'a>(&mut self, recordings: impl Iterator<Item = &'a Path>, primary: &str) {
+        if !self.config.enabled {
+            return;
+        }
+        let recordings: Vec<&Path> = recordings.collect();
+        let finished: Vec<_> = self
+            .recordings
+            .iter()
+            .filter(|(path, _)| !recordings.contains(&path.as_path()))
+            .cloned()
+            .collect();
+        for (path, started) in finished {
+            self.finish(&path, started);
+        }
+        self.recordings
+            .retain(|(path, _)| recordings.contains(&path.as_path()));
+        for recording in recordings {
+            if !self.recordings.iter().any(|(path, _)| path == recording) {
+                self.recordings
+                    .push((recording.to_path_buf(), Local::now()));
+            }
+        }
+        self.primary = primary.to_string();
+    }
+
+    fn finish(&self, recording: &Path, started: DateTime<Local>) {
+        if let Err(err) = write_manifest(&self.config, recording, &self.primary, started) {
+            eprintln!("sync manifest for {}: {:?}", recording.display(), err);
+        }
+    }
+}
+
+impl Drop for Manifests {
+    fn drop(&mut self) {
+        for (path, started) in &self.recordings {
+            self.finish(path, *started);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 6 秒 30 FPS 的亮度, 每次拍板亮 0.2 秒.
+    fn series(start: f64, claps: &[f64]) -> Vec<(f64, f32)> {
+        (0..180)
+            .map(|i| start + i as f64 / 30.0)
+            .map(|t| {
+                let lit = claps.iter().any(|&clap| (clap..clap + 0.2).contains(&t));
+                (t, if lit { 220.0 } else { 40.0 })
+            })
+            .collect()
+    }
+
+    // 同一次拍板在另一路中晚 0.4 秒或早 0.25 秒 (采样相位不同), 误差不超过一帧.
+    #[test]
+    fn offset_from_claps() {
+        let primary = series(0.0, &[2.0, 4.1]);
+        let (offset, confidence) =
+            estimate(&primary, &series(0.0, &[2.4, 4.5]), 1.0, 0.005).unwrap();
+        assert!((offset - 0.4).abs() < 1e-6);
+        assert!(confidence > 0.99);
+
+        let (offset, confidence) =
+            estimate(&primary, &series(0.01, &[1.75, 3.85]), 1.0, 0.005).unwrap();
+        assert!((offset + 0.25).abs() <= 1.0 / 30.0);
+        assert!(confidence > 0.9);
+    }
+
+    // 没有亮度变化或重叠不到 1 秒时不给出偏差.
+    #[test]
+    fn no_event_no_offset() {
+        let primary = series(0.0, &[2.0]);
+        assert_eq!(estimate(&primary, &series(0.0, &[]), 1.0, 0.005), None);
+        assert_eq!(estimate(&primary[..20], &primary[..20], 1.0, 0.005), None);
+        assert_eq!(estimate(&[], &primary, 1.0, 0.005), None);
+    }
+
+    // 录像结束时写 <录像>.sync.json, 列出其他来源的文件和偏差.
+    #[test]
+    fn manifest() {
+        let dir = std::env::temp_dir().join(format!("sync-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let recording = dir.join("capture.mp4");
+        let started = Local::now() - chrono::TimeDelta::seconds(10);
+        let start = seconds(started) + 1.0;
+        {
+            let mut all = SERIES.lock().unwrap();
+            all.entry(routing::MAIN.to_string()).or_default().samples =
+                series(start, &[start + 2.0]).into();
+            all.entry("side".to_string()).or_default().samples =
+                series(start, &[start + 2.3]).into();
+        }
+        register("side", vec![dir.join("side.mp4")]);
+        let config = SyncConfig {
+            enabled: true,
+            ..Default::default()
+        };
+        write_manifest(&config, &recording, "/dev/video0", started).unwrap();
+
+        let manifest: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(manifest_path(&recording)).unwrap()).unwrap();
+        assert_eq!(manifest["primary"], "/dev/video0");
+        assert_eq!(manifest["frames"], 180);
+        let stream = &manifest["streams"][0];
+        assert_eq!(stream["source"], "side");
+        assert_eq!(stream["files"], json!([dir.join("side.mp4")]));
+        assert!((stream["offset"].as_f64().unwrap() - 0.3).abs() <= 1.0 / 30.0);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
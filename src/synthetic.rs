@@ -0,0 +1,125 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use opencv::{
+    core::{Mat, Point, Rect, Scalar, Vec3b, CV_8UC3},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_8},
+    prelude::*,
+};
+
+use crate::{
+    config::Config,
+    source::{FrameSource, Metadata},
+};
+
+const DEFAULT_WIDTH: i32 = 1280;
+const DEFAULT_HEIGHT: i32 = 720;
+const DEFAULT_FPS: f64 = 30.0;
+
+// 读取来得太晚而跳过的帧数, 即处理和录制跟不上帧率时丢掉的帧.
+pub static SKIPPED_FRAMES: AtomicU64 = AtomicU64::new(0);
+
+// 合成的测试画面: 渐变背景, 移动的方块和帧号, 按帧率实时产生, 不需要摄像头.
+// URI 为 synthetic://[<宽>x<高>][@<帧率>], 例如 synthetic://1920x1080@60,
+// 不设置尺寸时使用 [camera] 的分辨率, 再没有时为 1280x720@30.
+pub struct Synthetic {
+    background: Mat,
+    start: Instant,
+    // 下一帧的序号, 按 start 和帧率计算它应该出现的时间.
+    next: u64,
+    metadata: Metadata,
+}
+
+pub fn open(params: &str, config: &Config) -> Result<Box<dyn FrameSource>> {
+    let (size, fps) = params.split_once('@').unwrap_or((params, ""));
+    let (width, height) = match size.split_once('x') {
+        Some((width, height)) => (
+            width.parse().context("synthetic source width")?,
+            height.parse().context("synthetic source height")?,
+        ),
+        None => (
+            config.camera.width.unwrap_or(DEFAULT_WIDTH),
+            config.camera.height.unwrap_or(DEFAULT_HEIGHT),
+        ),
+    };
+    let fps = match fps {
+        "" => DEFAULT_FPS,
+        fps => fps.parse().context("synthetic source FPS")?,
+    };
+    let (width, height): (i32, i32) = (width.max(16), height.max(16));
+    let mut background = Mat::new_rows_cols_with_default(height, width, CV_8UC3, Scalar::all(0.))?;
+    for y in 0..height {
+        for x in 0..width {
+            *background.at_2d_mut::<Vec3b>(y, x)? = Vec3b::from([
+                (x * 255 / width) as u8,
+                (y * 255 / height) as u8,
+                ((x + y) * 255 / (width + height)) as u8,
+            ]);
+        }
+    }
+    Ok(Box::new(Synthetic {
+        background,
+        start: Instant::now(),
+        next: 0,
+        metadata: Metadata {
+            description: format!("synthetic {}x{}@{}", width, height, fps),
+            profile: "synthetic".to_string(),
+            width: width as f64,
+            height: height as f64,
+            fps: fps.max(1.0),
+            live: true,
+        },
+    }))
+}
+
+impl FrameSource for Synthetic {
+    fn read(&mut self, frame: &mut Mat) -> Result<bool> {
+        let interval = 1.0 / self.metadata.fps;
+        let due = self.start + Duration::from_secs_f64(self.next as f64 * interval);
+        let now = Instant::now();
+        if now < due {
+            sleep(due - now);
+        } else {
+            // 读取晚了, 与摄像头一样跳过已经过去的帧.
+            let current = (now.duration_since(self.start).as_secs_f64() / interval) as u64;
+            if current > self.next {
+                SKIPPED_FRAMES.fetch_add(current - self.next, Ordering::Relaxed);
+                self.next = current;
+            }
+        }
+        self.background.copy_to(frame)?;
+        let (width, height) = (frame.cols(), frame.rows());
+        let side = (height / 6).max(8);
+        let x = (self.next as i32 * 4) % (width - side).max(1);
+        let y = (height - side) / 2;
+        imgproc::rectangle(
+            frame,
+            Rect::new(x, y, side, side),
+            Scalar::new(255., 255., 255., 0.),
+            -1,
+            LINE_8,
+            0,
+        )?;
+        imgproc::put_text(
+            frame,
+            &format!("#{}", self.next),
+            Point::new(16, height - 16),
+            FONT_HERSHEY_SIMPLEX,
+            1.0,
+            Scalar::new(255., 255., 255., 0.),
+            2,
+            LINE_8,
+            false,
+        )?;
+        self.next += 1;
+        Ok(true)
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+}
@@ -0,0 +1,372 @@
+use std::{
+    fs,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use opencv::{
+    core::{self, Mat, Point, Rect, Scalar, Size, Vector, CV_64F},
+    imgcodecs,
+    imgproc::{self, COLOR_BGR2GRAY, FONT_HERSHEY_SIMPLEX, INTER_AREA, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::{events::EventStore, metrics, occupancy::Debounce, pipeline::FrameProcessor};
+
+// 分析使用的画面宽度.
+const WIDTH: i32 = 160;
+
+// 遮挡检测: 镜头被遮住, 摄像头被转动或失焦时记录 tamper 事件, 运动检测发现不了这些情况.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TamperConfig {
+    pub enabled: bool,
+    // 检查间隔 (秒).
+    pub interval: f64,
+    // 平均亮度低于 dark, 或亮度标准差低于 uniform (被纸或手整个盖住) 时认为被遮挡.
+    pub dark: f64,
+    pub uniform: f64,
+    // 清晰度低于参考画面的 blur 倍时认为失焦.
+    pub blur: f64,
+    // 和参考画面相比亮度变化超过 threshold 的像素比例超过 moved 时认为被转动.
+    pub threshold: f64,
+    pub moved: f64,
+    // 异常持续这么多秒才报警, 恢复正常同样.
+    pub after: f64,
+    // 正常时每隔这么多秒更新参考画面, 跟上日夜的缓慢变化.
+    pub learn: f64,
+    // 报警以 JSON POST 到这些地址.
+    pub alert_urls: Vec<String>,
+    // 报警时的画面, 不设置时不保存.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for TamperConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: 1.0,
+            dark: 15.0,
+            uniform: 6.0,
+            blur: 0.25,
+            threshold: 40.0,
+            moved: 0.6,
+            after: 5.0,
+            learn: 60.0,
+            alert_urls: Vec::new(),
+            dir: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reason {
+    Covered,
+    Moved,
+    Defocused,
+}
+
+impl Reason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::Covered => "covered",
+            Reason::Moved => "moved",
+            Reason::Defocused => "defocused",
+        }
+    }
+}
+
+// 一次检查的测量值.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Measure {
+    pub brightness: f64,
+    pub contrast: f64,
+    pub sharpness: f64,
+    // 和参考画面相比变化的像素比例.
+    pub changed: f64,
+}
+
+// 遮挡优先: 盖住镜头时画面也会整体变化和变模糊.
+pub fn classify(measure: &Measure, sharpness: f64, config: &TamperConfig) -> Option<Reason> {
+    if measure.brightness < config.dark || measure.contrast < config.uniform {
+        Some(Reason::Covered)
+    } else if measure.changed > config.moved {
+        Some(Reason::Moved)
+    } else if measure.sharpness < config.blur * sharpness {
+        Some(Reason::Defocused)
+    } else {
+        None
+    }
+}
+
+pub struct TamperDetection {
+    config: TamperConfig,
+    events: EventStore,
+    started: Instant,
+    last: Option<Instant>,
+    learned: Instant,
+    small: Mat,
+    gray: Mat,
+    laplacian: Mat,
+    diff: Mat,
+    mask: Mat,
+    // 参考画面和它的清晰度.
+    reference: Mat,
+    sharpness: f64,
+    debounce: Debounce,
+    reason: Option<Reason>,
+}
+
+impl TamperDetection {
+    pub fn new(config: &TamperConfig, events: EventStore) -> Result<Self> {
+        if let Some(dir) = &config.dir {
+            fs::create_dir_all(dir).with_context(|| format!("create {}", dir.display()))?;
+        }
+        metrics::gauge(
+            "camera_tampered",
+            "Whether the camera looks tampered with",
+            0.0,
+        );
+        Ok(Self {
+            config: config.clone(),
+            events,
+            started: Instant::now(),
+            last: None,
+            learned: Instant::now(),
+            small: Mat::default(),
+            gray: Mat::default(),
+            laplacian: Mat::default(),
+            diff: Mat::default(),
+            mask: Mat::default(),
+            reference: Mat::default(),
+            sharpness: 0.0,
+            debounce: Debounce::default(),
+            reason: None,
+        })
+    }
+
+    fn measure(&mut self, frame: &Mat) -> Result<Measure> {
+        let scale = (WIDTH as f64 / frame.cols().max(1) as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        imgproc::cvt_color(&self.small, &mut self.gray, COLOR_BGR2GRAY, 0)?;
+        let (mut mean, mut stddev) = (Scalar::default(), Scalar::default());
+        core::mean_std_dev(&self.gray, &mut mean, &mut stddev, &core::no_array())?;
+        let (brightness, contrast) = (mean[0], stddev[0]);
+        imgproc::laplacian(
+            &self.gray,
+            &mut self.laplacian,
+            CV_64F,
+            1,
+            1.0,
+            0.0,
+            core::BORDER_DEFAULT,
+        )?;
+        core::mean_std_dev(&self.laplacian, &mut mean, &mut stddev, &core::no_array())?;
+        let sharpness = stddev[0] * stddev[0];
+        let changed = if self.reference.size()? == self.gray.size()? {
+            core::absdiff(&self.gray, &self.reference, &mut self.diff)?;
+            imgproc::threshold(
+                &self.diff,
+                &mut self.mask,
+                self.config.threshold,
+                255.0,
+                imgproc::THRESH_BINARY,
+            )?;
+            core::count_non_zero(&self.mask)? as f64 / self.mask.total().max(1) as f64
+        } else {
+            0.0
+        };
+        Ok(Measure {
+            brightness,
+            contrast,
+            sharpness,
+            changed,
+        })
+    }
+
+    fn alert(&self, reason: Reason, measure: &Measure, frame: &Mat) {
+        let time = Local::now();
+        let snapshot = self.config.dir.as_ref().and_then(|dir| {
+            let path = dir.join(format!("tamper-{}.jpg", time.format("%Y-%m-%dT%H-%M-%S")));
+            match imgcodecs::imwrite(&path.to_string_lossy(), frame, &Vector::new()) {
+                Ok(true) => Some(path.display().to_string()),
+                _ => None,
+            }
+        });
+        self.events.log(
+            "tamper",
+            json!({
+                "reason": reason.as_str(),
+                "brightness": measure.brightness,
+                "contrast": measure.contrast,
+                "sharpness": measure.sharpness,
+                "changed": measure.changed,
+                "snapshot": snapshot,
+            }),
+        );
+        if self.config.alert_urls.is_empty() {
+            return;
+        }
+        let body = json!({
+            "title": "Camera tampered",
+            "message": format!("Camera {} at {}", reason.as_str(), time.format("%H:%M:%S")),
+            "time": time.to_rfc3339(),
+            "reason": reason.as_str(),
+        })
+        .to_string();
+        let urls = self.config.alert_urls.clone();
+        // 发送提醒不阻塞采集.
+        let _ = thread::Builder::new()
+            .name("tamper-alert".to_string())
+            .spawn(move || {
+                for url in urls {
+                    if let Err(err) = ureq::post(&url)
+                        .set("Content-Type", "application/json")
+                        .send_string(&body)
+                    {
+                        eprintln!("tamper alert to {}: {:?}", url, err);
+                    }
+                }
+            });
+    }
+}
+
+impl FrameProcessor for TamperDetection {
+    fn name(&self) -> &str {
+        "tamper"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let interval = Duration::from_secs_f64(self.config.interval.max(0.1));
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        self.last = Some(Instant::now());
+        let measure = self.measure(frame)?;
+        // 第一帧作为参考画面.
+        if self.reference.empty() || self.reference.size()? != self.gray.size()? {
+            self.gray.copy_to(&mut self.reference)?;
+            self.sharpness = measure.sharpness;
+            self.learned = Instant::now();
+            return Ok(());
+        }
+        let raw = classify(&measure, self.sharpness, &self.config);
+        let now = self.started.elapsed().as_secs_f64();
+        let after = self.config.after.max(0.0);
+        match self.debounce.update(raw.is_some(), now, after, after) {
+            Some(true) => {
+                let reason = raw.unwrap_or(Reason::Covered);
+                self.reason = Some(reason);
+                metrics::gauge(
+                    "camera_tampered",
+                    "Whether the camera looks tampered with",
+                    1.0,
+                );
+                self.alert(reason, &measure, frame);
+            }
+            Some(false) if self.reason.is_some() => {
+                self.events.log(
+                    "tamper-cleared",
+                    json!({ "reason": self.reason.take().map(|r| r.as_str()) }),
+                );
+                metrics::gauge(
+                    "camera_tampered",
+                    "Whether the camera looks tampered with",
+                    0.0,
+                );
+                self.learned = Instant::now();
+            }
+            _ => {}
+        }
+        // 只在正常时更新参考画面, 否则慢慢遮住镜头也会被学进去.
+        let learn = Duration::from_secs_f64(self.config.learn.max(1.0));
+        if raw.is_none() && self.reason.is_none() && self.learned.elapsed() >= learn {
+            self.gray.copy_to(&mut self.reference)?;
+            self.sharpness = measure.sharpness;
+            self.learned = Instant::now();
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        self.reason.is_some()
+    }
+
+    // 报警时在画面顶部显示红色横幅.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        let Some(reason) = self.reason else {
+            return Ok(());
+        };
+        let red = Scalar::new(0., 0., 255., 0.);
+        imgproc::rectangle(
+            frame,
+            Rect::new(0, 0, frame.cols(), 40),
+            red,
+            imgproc::FILLED,
+            LINE_AA,
+            0,
+        )?;
+        imgproc::put_text(
+            frame,
+            &format!("CAMERA TAMPERED: {}", reason.as_str()),
+            Point::new(10, 28),
+            FONT_HERSHEY_SIMPLEX,
+            0.8,
+            Scalar::all(255.0),
+            2,
+            LINE_AA,
+            false,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 参考画面的清晰度为 1000.
+    fn reason(
+        brightness: f64,
+        contrast: f64,
+        sharpness: f64,
+        changed: f64,
+    ) -> Option<&'static str> {
+        let measure = Measure {
+            brightness,
+            contrast,
+            sharpness,
+            changed,
+        };
+        classify(&measure, 1000.0, &TamperConfig::default()).map(|reason| reason.as_str())
+    }
+
+    #[test]
+    fn reasons() {
+        assert_eq!(reason(120.0, 40.0, 900.0, 0.1), None);
+        assert_eq!(reason(10.0, 40.0, 900.0, 0.1), Some("covered"));
+        assert_eq!(reason(120.0, 3.0, 900.0, 0.1), Some("covered"));
+        assert_eq!(reason(120.0, 40.0, 900.0, 0.8), Some("moved"));
+        assert_eq!(reason(120.0, 40.0, 200.0, 0.1), Some("defocused"));
+        assert_eq!(reason(120.0, 40.0, 250.0, 0.6), None);
+    }
+
+    // 盖住镜头时画面也会变化和变模糊, 仍然报遮挡; 转动时新画面可能更模糊, 报转动.
+    #[test]
+    fn covered_first() {
+        assert_eq!(reason(5.0, 2.0, 10.0, 0.95), Some("covered"));
+        assert_eq!(reason(120.0, 40.0, 10.0, 0.95), Some("moved"));
+    }
+}
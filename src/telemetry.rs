@@ -0,0 +1,475 @@
+use std::{
+    collections::VecDeque,
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{sleep, spawn},
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, TimeDelta};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::auth::{Auth, Listener};
+
+// 按时间缓存的样本数上限, 避免发送过快时占用过多内存.
+const MAX_SAMPLES: usize = 10_000;
+
+// 外部提供的逐帧数据 (机器人遥测, 无人机 GPS 等), 按时间与帧对应, 显示在 OSD 中并写入录像的 sidecar.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    // 跟踪文件新增的行, 每行一个 JSON 对象.
+    pub file: Option<PathBuf>,
+    // 监听 WebSocket, 每条文本消息一个 JSON 对象. 发送需要 operator 权限.
+    pub websocket: Option<String>,
+    // 样本中的时间字段, RFC 3339 字符串或 Unix 秒. 没有该字段时使用收到的时间.
+    pub time_field: String,
+    // 外部时钟比本机快的秒数, 比较前从样本时间中减去.
+    pub offset: f64,
+    // 与帧的时间相差超过该值 (秒) 的样本不对应到帧.
+    pub tolerance: f64,
+    // 保留最近多少秒的样本.
+    pub buffer: f64,
+    // OSD 中显示的字段和顺序, 为空时显示所有字段.
+    pub fields: Vec<String>,
+    // 录像时在文件旁写入 <录像>.telemetry.jsonl, 每帧一行.
+    pub sidecar: bool,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            file: None,
+            websocket: None,
+            time_field: "time".to_string(),
+            offset: 0.0,
+            tolerance: 0.5,
+            buffer: 10.0,
+            fields: Vec::new(),
+            sidecar: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    // 换算到本机时钟的时间.
+    pub time: DateTime<Local>,
+    pub data: Map<String, Value>,
+}
+
+// 最近的样本, 可以在多个线程之间共享.
+#[derive(Clone)]
+pub struct Telemetry {
+    samples: Arc<Mutex<VecDeque<Sample>>>,
+    time_field: Arc<str>,
+    offset: TimeDelta,
+    tolerance: TimeDelta,
+    buffer: TimeDelta,
+    fields: Arc<[String]>,
+    pub sidecar: bool,
+    listener: Arc<Mutex<Option<Listener>>>,
+    stopped: Arc<AtomicBool>,
+}
+
+impl Telemetry {
+    pub(crate) fn start(config: &TelemetryConfig, auth: &Auth) -> Result<Self> {
+        let seconds = |value: f64| TimeDelta::milliseconds((value * 1000.0) as i64);
+        let telemetry = Self {
+            samples: Arc::default(),
+            time_field: config.time_field.as_str().into(),
+            offset: seconds(config.offset),
+            tolerance: seconds(config.tolerance.max(0.0)),
+            buffer: seconds(config.buffer.max(0.0)),
+            fields: config.fields.as_slice().into(),
+            sidecar: config.sidecar,
+            listener: Arc::default(),
+            stopped: Arc::default(),
+        };
+        if let Some(path) = &config.file {
+            let telemetry = telemetry.clone();
+            let path = path.clone();
+            spawn(move || {
+                if let Err(err) = telemetry.tail(&path) {
+                    eprintln!("telemetry file {}: {:?}", path.display(), err);
+                }
+            });
+        }
+        if let Some(addr) = &config.websocket {
+            telemetry.listen(addr, auth)?;
+        }
+        Ok(telemetry)
+    }
+
+    #[cfg(feature = "streaming")]
+    fn listen(&self, addr: &str, auth: &Auth) -> Result<()> {
+        let telemetry = self.clone();
+        let checker = auth.clone();
+        let listener = auth.listen(addr, "telemetry websocket", move |stream| {
+            telemetry.serve(stream, &checker)
+        })?;
+        *self.listener.lock().unwrap() = Some(listener);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "streaming"))]
+    fn listen(&self, addr: &str, _auth: &Auth) -> Result<()> {
+        bail!(
+            "telemetry websocket {} needs the streaming feature, rebuild with --features streaming",
+            addr
+        )
+    }
+
+    // 停止跟踪文件和监听 WebSocket, 关闭 VideoFeed 时调用.
+    pub(crate) fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        self.listener.lock().unwrap().take();
+    }
+
+    // 加入一个样本, 文件和 WebSocket 之外也可以由库使用者直接调用.
+    pub fn push(&self, value: Value) -> Result<()> {
+        let Value::Object(mut data) = value else {
+            bail!("telemetry sample must be a JSON object");
+        };
+        let time = match data.remove(&*self.time_field) {
+            Some(time) => parse_time(&time)? - self.offset,
+            None => Local::now(),
+        };
+        self.add(time, data);
+        Ok(())
+    }
+
+    // 加入一个已经换算到本机时钟的样本.
+    pub(crate) fn add(&self, time: DateTime<Local>, data: Map<String, Value>) {
+        let mut samples = self.samples.lock().unwrap();
+        // 样本可能乱序到达, 按时间插入.
+        let at = samples.partition_point(|sample| sample.time <= time);
+        samples.insert(at, Sample { time, data });
+        let newest = samples.back().map_or(time, |sample| sample.time);
+        while samples.len() > MAX_SAMPLES
+            || samples
+                .front()
+                .is_some_and(|sample| newest - sample.time > self.buffer)
+        {
+            samples.pop_front();
+        }
+    }
+
+    // 时间最接近 time 的样本, 相差超过 tolerance 时为 None.
+    pub(crate) fn at(&self, time: DateTime<Local>) -> Option<Sample> {
+        let samples = self.samples.lock().unwrap();
+        let after = samples.partition_point(|sample| sample.time < time);
+        let before = after.checked_sub(1).and_then(|index| samples.get(index));
+        [before, samples.get(after)]
+            .into_iter()
+            .flatten()
+            .map(|sample| ((sample.time - time).abs(), sample))
+            .filter(|(distance, _)| *distance <= self.tolerance)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, sample)| sample.clone())
+    }
+
+    // OSD 中显示的一行, 例如 "lat 52.37 lon 4.89 alt 12.5".
+    pub(crate) fn describe(&self, sample: &Sample) -> String {
+        let value = |value: &Value| match value {
+            Value::String(text) => text.clone(),
+            Value::Number(number) => match number.as_f64() {
+                Some(number) if number.fract() != 0.0 => format!("{:.2}", number),
+                _ => number.to_string(),
+            },
+            value => value.to_string(),
+        };
+        let mut parts = Vec::new();
+        if self.fields.is_empty() {
+            for (name, field) in &sample.data {
+                parts.push(format!("{} {}", name, value(field)));
+            }
+        } else {
+            for name in self.fields.iter() {
+                if let Some(field) = sample.data.get(name) {
+                    parts.push(format!("{} {}", name, value(field)));
+                }
+            }
+        }
+        parts.join(" ")
+    }
+
+    // 与 captions 相同, 只读取启动后新增的内容, 文件被截断时从头读.
+    fn tail(&self, path: &PathBuf) -> Result<()> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut pos = reader.seek(SeekFrom::End(0))?;
+        let mut line = String::new();
+        while !self.stopped.load(Ordering::SeqCst) {
+            line.clear();
+            let n = reader.read_line(&mut line)?;
+            if n == 0 {
+                if std::fs::metadata(path)?.len() < pos {
+                    pos = reader.seek(SeekFrom::Start(0))?;
+                }
+                sleep(Duration::from_millis(50));
+                continue;
+            }
+            pos += n as u64;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let result = serde_json::from_str(&line)
+                .map_err(anyhow::Error::from)
+                .and_then(|value| self.push(value));
+            if let Err(err) = result {
+                eprintln!("telemetry file {}: {:#}", path.display(), err);
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "streaming")]
+    fn serve(&self, stream: crate::auth::Connection, auth: &Auth) -> Result<()> {
+        let (mut socket, _, _) = auth.accept_websocket(stream, crate::auth::Role::Operator)?;
+        loop {
+            match socket.read()? {
+                tungstenite::Message::Text(text) => {
+                    let result = serde_json::from_str(&text)
+                        .map_err(anyhow::Error::from)
+                        .and_then(|value| self.push(value));
+                    if let Err(err) = result {
+                        eprintln!("telemetry websocket: {:#}", err);
+                    }
+                }
+                tungstenite::Message::Close(_) => return Ok(()),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn parse_time(value: &Value) -> Result<DateTime<Local>> {
+    let time = match value {
+        Value::String(text) => DateTime::parse_from_rfc3339(text)
+            .with_context(|| format!("telemetry time {:?}", text))?
+            .with_timezone(&Local),
+        Value::Number(number) => {
+            let seconds = number.as_f64().unwrap_or_default();
+            let nanos = (seconds.fract() * 1e9) as u32;
+            match DateTime::from_timestamp(seconds.floor() as i64, nanos) {
+                Some(time) => time.with_timezone(&Local),
+                None => bail!("telemetry time {} out of range", seconds),
+            }
+        }
+        value => bail!("telemetry time {} is not a string or a number", value),
+    };
+    Ok(time)
+}
+
+// 录像文件旁的 <录像>.telemetry.jsonl. 每个文件一个, 录像文件改变 (重新开始录制, 切换来源) 时跟着换.
+#[derive(Default)]
+pub struct Sidecars {
+    logs: Vec<Sidecar>,
+}
+
+struct Sidecar {
+    recording: PathBuf,
+    writer: BufWriter<File>,
+    // 写入该文件的帧数, 即录像中的帧序号.
+    frames: u64,
+}
+
+pub fn sidecar(recording: &Path) -> PathBuf {
+    let mut name = recording.as_os_str().to_owned();
+    name.push(".telemetry.jsonl");
+    PathBuf::from(name)
+}
+
+impl Sidecars {
+    // 写入一帧, recordings 为当前正在写入的录像文件, 没有录像时关闭所有 sidecar.
+    // 没有对应样本的帧也写一行, data 为 null, 行号与录像的帧一一对应.
+    pub fn write<'a>(
+        &mut self,
+        recordings: impl Iterator<Item = &'a Path>,
+        index: u64,
+        time: DateTime<Local>,
+        sample: Option<&Sample>,
+    ) {
+        let recordings: Vec<&Path> = recordings.collect();
+        self.logs
+            .retain(|log| recordings.contains(&log.recording.as_path()));
+        for recording in recordings {
+            if self.logs.iter().any(|log| log.recording == recording) {
+                continue;
+            }
+            let path = sidecar(recording);
+            match File::create(&path) {
+                Ok(file) => self.logs.push(Sidecar {
+                    recording: recording.to_path_buf(),
+                    writer: BufWriter::new(file),
+                    frames: 0,
+                }),
+                Err(err) => eprintln!("telemetry sidecar {}: {:?}", path.display(), err),
+            }
+        }
+        let (sample_time, data) = match sample {
+            Some(sample) => (
+                Some(sample.time.to_rfc3339()),
+                Value::Object(sample.data.clone()),
+            ),
+            None => (None, Value::Null),
+        };
+        self.logs.retain_mut(|log| {
+            let line = json!({
+                "frame": log.frames,
+                "index": index,
+                "time": time.to_rfc3339(),
+                "sample_time": sample_time,
+                "data": data,
+            });
+            log.frames += 1;
+            match writeln!(log.writer, "{}", line) {
+                Ok(()) => true,
+                Err(err) => {
+                    eprintln!(
+                        "telemetry sidecar for {}: {:?}",
+                        log.recording.display(),
+                        err
+                    );
+                    false
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthConfig;
+
+    fn telemetry(config: TelemetryConfig) -> Telemetry {
+        Telemetry::start(&config, &Auth::new(&AuthConfig::default()).unwrap()).unwrap()
+    }
+
+    fn at(base: DateTime<Local>, milliseconds: i64) -> DateTime<Local> {
+        base + TimeDelta::milliseconds(milliseconds)
+    }
+
+    fn id(sample: Option<Sample>) -> Option<i64> {
+        sample.and_then(|sample| sample.data["id"].as_i64())
+    }
+
+    // 乱序到达的样本按时间排列, 帧取时间最接近的样本, 超过容差时没有样本.
+    #[test]
+    fn nearest_sample() {
+        let telemetry = telemetry(TelemetryConfig::default());
+        let base = Local::now();
+        for (id, offset) in [(0, 0), (2, 400), (1, 200)] {
+            let time = at(base, offset).to_rfc3339();
+            telemetry.push(json!({ "time": time, "id": id })).unwrap();
+        }
+        assert_eq!(id(telemetry.at(at(base, 80))), Some(0));
+        assert_eq!(id(telemetry.at(at(base, 260))), Some(1));
+        assert_eq!(id(telemetry.at(at(base, 350))), Some(2));
+        assert_eq!(id(telemetry.at(at(base, 900))), Some(2));
+        assert_eq!(id(telemetry.at(at(base, 901))), None);
+        assert_eq!(id(telemetry.at(at(base, -600))), None);
+    }
+
+    // Unix 秒的时间减去外部时钟的偏差, 时间字段不留在数据中.
+    #[test]
+    fn unix_time_with_offset() {
+        let telemetry = telemetry(TelemetryConfig {
+            time_field: "t".to_string(),
+            offset: 2.0,
+            ..Default::default()
+        });
+        telemetry
+            .push(json!({ "t": 1_700_000_002.25, "id": 7 }))
+            .unwrap();
+        let time = DateTime::from_timestamp(1_700_000_000, 250_000_000).unwrap();
+        let sample = telemetry.at(time.with_timezone(&Local)).unwrap();
+        assert_eq!(sample.time, time);
+        assert_eq!(sample.data.keys().collect::<Vec<_>>(), ["id"]);
+        assert!(telemetry.push(json!([1, 2])).is_err());
+        assert!(telemetry.push(json!({ "t": "yesterday" })).is_err());
+    }
+
+    // 只保留最近 buffer 秒的样本.
+    #[test]
+    fn old_samples_expire() {
+        let telemetry = telemetry(TelemetryConfig {
+            buffer: 1.0,
+            ..Default::default()
+        });
+        let base = Local::now();
+        telemetry.add(base, Map::new());
+        telemetry.add(at(base, 1500), Map::new());
+        assert!(telemetry.at(base).is_none());
+        assert!(telemetry.at(at(base, 1500)).is_some());
+    }
+
+    // OSD 按配置的字段和顺序显示, 小数保留两位.
+    #[test]
+    fn describe_fields() {
+        let telemetry = telemetry(TelemetryConfig {
+            fields: vec!["alt".to_string(), "lat".to_string(), "speed".to_string()],
+            ..Default::default()
+        });
+        let Value::Object(data) = json!({ "lat": 52, "alt": 12.5, "mode": "auto" }) else {
+            unreachable!();
+        };
+        let sample = Sample {
+            time: Local::now(),
+            data,
+        };
+        assert_eq!(telemetry.describe(&sample), "alt 12.50 lat 52");
+    }
+
+    // 每个录像帧一行, 没有样本的帧 data 为 null; 录像文件改变时换新的 sidecar.
+    #[test]
+    fn sidecar_lines() {
+        let dir = std::env::temp_dir().join(format!("telemetry-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("a.mp4");
+        let second = dir.join("b.mp4");
+        let time = Local::now();
+        let Value::Object(data) = json!({ "speed": 3 }) else {
+            unreachable!();
+        };
+        let sample = Sample { time, data };
+        let mut sidecars = Sidecars::default();
+        sidecars.write([first.as_path()].into_iter(), 10, time, Some(&sample));
+        sidecars.write([first.as_path()].into_iter(), 11, time, None);
+        sidecars.write([second.as_path()].into_iter(), 12, time, None);
+        drop(sidecars);
+
+        let lines = |recording: &Path| -> Vec<Value> {
+            std::fs::read_to_string(sidecar(recording))
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect()
+        };
+        let first = lines(&first);
+        assert_eq!(first.len(), 2);
+        assert_eq!(
+            (&first[0]["frame"], &first[0]["index"]),
+            (&json!(0), &json!(10))
+        );
+        assert_eq!(first[0]["data"], json!({ "speed": 3 }));
+        assert_eq!(
+            (&first[1]["frame"], &first[1]["data"]),
+            (&json!(1), &Value::Null)
+        );
+        let second = lines(&second);
+        assert_eq!(
+            (&second[0]["frame"], &second[0]["index"]),
+            (&json!(0), &json!(12))
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    sync::{
+        mpsc::{sync_channel, SyncSender},
+        Arc, Condvar, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+// 每类线程的 CPU 亲和性和优先级, 用于在小型 ARM 板子上保证采集稳定.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThreadsConfig {
+    // 采集线程, 同时负责处理和编码.
+    pub capture: ThreadConfig,
+    // 主线程 (UI 和预览图像转换).
+    pub ui: ThreadConfig,
+    // 后台任务, 例如全景拼接和高质量静态图合成.
+    pub workers: ThreadConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThreadConfig {
+    // 允许运行的 CPU 编号, 为空时不限制.
+    pub cores: Vec<usize>,
+    // nice 值 (-20..19), 越小优先级越高, 负值需要 CAP_SYS_NICE.
+    pub nice: Option<i32>,
+    // SCHED_FIFO 实时优先级 (1..99), 设置后忽略 nice.
+    pub realtime: Option<i32>,
+}
+
+impl ThreadConfig {
+    pub fn is_empty(&self) -> bool {
+        self.cores.is_empty() && self.nice.is_none() && self.realtime.is_none()
+    }
+
+    // 应用到当前线程, 失败时只打印警告, 不影响运行.
+    pub fn apply(&self, name: &str) {
+        if self.is_empty() {
+            return;
+        }
+        if let Err(err) = self.try_apply() {
+            eprintln!("{} thread affinity/priority: {:?}", name, err);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn try_apply(&self) -> Result<()> {
+        use std::{io, mem};
+
+        use anyhow::{bail, Context};
+
+        if !self.cores.is_empty() {
+            // SAFETY: cpu_set_t 是普通的位图, 全零即空集合.
+            let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+            for &core in &self.cores {
+                if core >= libc::CPU_SETSIZE as usize {
+                    bail!("invalid core {}", core);
+                }
+                unsafe { libc::CPU_SET(core, &mut set) };
+            }
+            // pid 为 0 表示当前线程.
+            let ret =
+                unsafe { libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) };
+            if ret != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("pin to cores {:?}", self.cores));
+            }
+        }
+        if let Some(priority) = self.realtime {
+            let param = libc::sched_param {
+                sched_priority: priority,
+            };
+            let ret = unsafe {
+                libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+            };
+            if ret != 0 {
+                return Err(io::Error::from_raw_os_error(ret))
+                    .with_context(|| format!("set realtime priority {}", priority));
+            }
+        } else if let Some(nice) = self.nice {
+            // Linux 上 nice 值按线程 (tid) 生效.
+            let tid = unsafe { libc::syscall(libc::SYS_gettid) } as libc::id_t;
+            if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid, nice) } != 0 {
+                return Err(io::Error::last_os_error())
+                    .with_context(|| format!("set nice {}", nice));
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn try_apply(&self) -> Result<()> {
+        anyhow::bail!("thread affinity and priority are only supported on Linux")
+    }
+}
+
+// 处理阶段的后台线程: 按 workers 设置亲和性和优先级, 依次把通道中的任务交给 work,
+// 出错时打印后继续处理下一个. 通道容量为 capacity, 满时由调用者决定丢弃还是等待.
+// 阶段移除时返回的 SyncSender 被丢弃, 线程随之退出.
+pub fn worker<T: Send + 'static>(
+    name: &str,
+    capacity: usize,
+    workers: &ThreadConfig,
+    mut work: impl FnMut(T) -> Result<()> + Send + 'static,
+) -> Result<SyncSender<T>> {
+    let (jobs, receiver) = sync_channel(capacity);
+    let (name, workers) = (name.to_string(), workers.clone());
+    thread::Builder::new().name(name.clone()).spawn(move || {
+        workers.apply(&name);
+        for job in receiver {
+            if let Err(err) = work(job) {
+                eprintln!("{}: {:?}", name, err);
+            }
+        }
+    })?;
+    Ok(jobs)
+}
+
+// 后台循环线程 (健康检查, 时钟, 清理等) 的停止信号, 每次运行界面时创建, 关闭时让这些线程结束.
+#[derive(Clone, Default)]
+pub struct Shutdown(Arc<(Mutex<bool>, Condvar)>);
+
+impl Shutdown {
+    pub fn stop(&self) {
+        let (stopped, changed) = &*self.0;
+        *stopped.lock().unwrap() = true;
+        changed.notify_all();
+    }
+
+    // 代替 sleep, 等待期间收到停止信号时马上返回 true.
+    pub fn wait(&self, timeout: Duration) -> bool {
+        let (stopped, changed) = &*self.0;
+        let (stopped, _) = changed
+            .wait_timeout_while(stopped.lock().unwrap(), timeout, |stopped| !*stopped)
+            .unwrap();
+        *stopped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config() {
+        assert!(ThreadConfig::default().is_empty());
+        let config: ThreadsConfig =
+            toml::from_str("[capture]\ncores = [0]\n[ui]\nnice = 5").unwrap();
+        assert!(!config.capture.is_empty());
+        assert!(!config.ui.is_empty());
+        assert!(config.workers.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn pins_current_thread() {
+        let config = ThreadConfig {
+            cores: vec![0],
+            nice: Some(5),
+            realtime: None,
+        };
+        // 在新线程中修改, 不影响其他测试.
+        thread::spawn(move || {
+            config.try_apply().unwrap();
+            let mut set: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+            let ret = unsafe {
+                libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set)
+            };
+            assert_eq!(ret, 0);
+            assert_eq!(unsafe { libc::CPU_COUNT(&set) }, 1);
+            assert!(unsafe { libc::CPU_ISSET(0, &set) });
+        })
+        .join()
+        .unwrap();
+
+        let invalid = ThreadConfig {
+            cores: vec![libc::CPU_SETSIZE as usize],
+            ..Default::default()
+        };
+        assert!(invalid
+            .try_apply()
+            .unwrap_err()
+            .to_string()
+            .starts_with("invalid core"));
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+// 后台写入的限速: 录像移动到存储, 归档和上传. 正在进行的录制不受限制,
+// 录制期间后台写入只使用一部分带宽, 避免在小型设备上和录制或其他程序争抢磁盘和网络.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThrottleConfig {
+    // 磁盘写入上限 (MB/s), 不设置时不限制.
+    pub disk_mb: Option<f64>,
+    // 上传上限 (MB/s).
+    pub upload_mb: Option<f64>,
+    // 录制期间只使用上限的这个比例.
+    pub while_recording: f64,
+}
+
+impl Default for ThrottleConfig {
+    fn default() -> Self {
+        Self {
+            disk_mb: None,
+            upload_mb: None,
+            while_recording: 0.25,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Budget {
+    Disk,
+    Upload,
+}
+
+// 令牌桶, 最多积累一秒的额度.
+#[derive(Debug, Clone, Copy)]
+pub struct Bucket {
+    // 每秒字节数.
+    rate: f64,
+    available: f64,
+    last: Option<Instant>,
+}
+
+impl Bucket {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            available: rate,
+            last: None,
+        }
+    }
+
+    // 取走 bytes 字节的额度, 返回需要等待的时间. 额度可以透支, 由等待补回.
+    pub fn take(&mut self, bytes: f64, now: Instant) -> Duration {
+        if let Some(last) = self.last {
+            let refill = now.duration_since(last).as_secs_f64() * self.rate;
+            self.available = (self.available + refill).min(self.rate);
+        }
+        self.last = Some(now);
+        self.available -= bytes;
+        if self.available >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-self.available / self.rate)
+        }
+    }
+}
+
+struct Limits {
+    config: ThrottleConfig,
+    disk: Option<Bucket>,
+    upload: Option<Bucket>,
+}
+
+static LIMITS: Mutex<Option<Limits>> = Mutex::new(None);
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+pub fn configure(config: &ThrottleConfig) {
+    *LIMITS.lock().unwrap() = Some(Limits {
+        config: config.clone(),
+        disk: None,
+        upload: None,
+    });
+    update();
+}
+
+// 开始和停止录制时由采集线程调用.
+pub fn set_recording(recording: bool) {
+    if RECORDING.swap(recording, Ordering::Relaxed) != recording {
+        update();
+    }
+}
+
+// 按是否在录制重新计算两个桶的速率.
+fn update() {
+    let mut limits = LIMITS.lock().unwrap();
+    let Some(limits) = limits.as_mut() else {
+        return;
+    };
+    let share = if RECORDING.load(Ordering::Relaxed) {
+        limits.config.while_recording.clamp(0.01, 1.0)
+    } else {
+        1.0
+    };
+    let bucket = |mb: Option<f64>| {
+        mb.filter(|mb| *mb > 0.0)
+            .map(|mb| Bucket::new(mb * 1e6 * share))
+    };
+    limits.disk = bucket(limits.config.disk_mb);
+    limits.upload = bucket(limits.config.upload_mb);
+}
+
+// 当前的速率 (字节/秒), 不限制时为 None.
+pub fn rate(budget: Budget) -> Option<f64> {
+    let limits = LIMITS.lock().unwrap();
+    let limits = limits.as_ref()?;
+    match budget {
+        Budget::Disk => limits.disk,
+        Budget::Upload => limits.upload,
+    }
+    .map(|bucket| bucket.rate)
+}
+
+// 写入或上传 bytes 字节之前调用, 超出额度时等待.
+pub fn take(budget: Budget, bytes: usize) {
+    let wait = {
+        let mut limits = LIMITS.lock().unwrap();
+        let bucket = limits.as_mut().and_then(|limits| match budget {
+            Budget::Disk => limits.disk.as_mut(),
+            Budget::Upload => limits.upload.as_mut(),
+        });
+        match bucket {
+            Some(bucket) => bucket.take(bytes as f64, Instant::now()),
+            None => return,
+        }
+    };
+    sleep(wait);
+}
+
+// 限速读取, 用于上传的请求体.
+pub struct Throttled<R> {
+    inner: R,
+    budget: Budget,
+}
+
+impl<R: Read> Throttled<R> {
+    pub fn new(inner: R, budget: Budget) -> Self {
+        Self { inner, budget }
+    }
+}
+
+impl<R: Read> Read for Throttled<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // 每次最多 64 KB, 等待时间不会太长.
+        let len = buf.len().min(64 * 1024);
+        let n = self.inner.read(&mut buf[..len])?;
+        take(self.budget, n);
+        Ok(n)
+    }
+}
+
+// 限速复制文件, 代替 fs::copy.
+pub fn copy(from: &Path, to: &Path, budget: Budget) -> Result<u64> {
+    let mut reader = Throttled::new(
+        File::open(from).with_context(|| format!("open {}", from.display()))?,
+        budget,
+    );
+    let mut writer = File::create(to).with_context(|| format!("create {}", to.display()))?;
+    let bytes = io::copy(&mut reader, &mut writer)?;
+    writer.sync_all()?;
+    if let Ok(permissions) = fs::metadata(from).map(|metadata| metadata.permissions()) {
+        let _ = fs::set_permissions(to, permissions);
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 最多积累一秒的额度, 透支的部分按速率等待.
+    #[test]
+    fn bucket() {
+        let start = Instant::now();
+        let at = |seconds: f64| start + Duration::from_secs_f64(seconds);
+        let mut bucket = Bucket::new(1000.0);
+        assert_eq!(bucket.take(600.0, at(0.0)), Duration::ZERO);
+        assert_eq!(bucket.take(600.0, at(0.0)), Duration::from_millis(200));
+        assert_eq!(bucket.take(0.0, at(0.2)), Duration::ZERO);
+        // 空闲 5 秒也只积累一秒的额度.
+        assert_eq!(bucket.take(1500.0, at(5.2)), Duration::from_millis(500));
+    }
+
+    // 录制期间只使用上限的一部分, 停止后恢复.
+    #[test]
+    fn share_while_recording() {
+        configure(&ThrottleConfig {
+            disk_mb: Some(10.0),
+            upload_mb: Some(0.0),
+            while_recording: 0.25,
+        });
+        assert_eq!(rate(Budget::Disk), Some(10e6));
+        assert_eq!(rate(Budget::Upload), None);
+        set_recording(true);
+        assert_eq!(rate(Budget::Disk), Some(2.5e6));
+        set_recording(false);
+        assert_eq!(rate(Budget::Disk), Some(10e6));
+        configure(&ThrottleConfig::default());
+        assert_eq!(rate(Budget::Disk), None);
+    }
+
+    #[test]
+    fn copy_file() {
+        let dir = std::env::temp_dir().join(format!("throttle-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let data: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+        fs::write(dir.join("from"), &data).unwrap();
+        let bytes = copy(&dir.join("from"), &dir.join("to"), Budget::Disk).unwrap();
+        assert_eq!(bytes, data.len() as u64);
+        assert_eq!(fs::read(dir.join("to")).unwrap(), data);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
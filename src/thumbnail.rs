@@ -0,0 +1,174 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use opencv::{
+    core::{Mat, Size, Vector},
+    imgcodecs,
+    imgproc::{self, INTER_AREA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{photo, privacy, sink};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ThumbnailConfig {
+    pub enabled: bool,
+    // 缩略图宽度, 高度按宽高比计算.
+    pub width: i32,
+    pub quality: i32,
+    // 更新缩略图的间隔 (秒).
+    pub interval: f64,
+    // 定期保存的文件, 支持 strftime 格式, 不设置时只通过控制接口提供.
+    pub path: Option<PathBuf>,
+    // 保存到文件的间隔 (秒).
+    pub save_interval: f64,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            width: 320,
+            quality: 70,
+            interval: 1.0,
+            path: None,
+            save_interval: 60.0,
+        }
+    }
+}
+
+// 最新的缩略图 (JPEG), 采集线程写入, 控制接口读取.
+#[derive(Clone, Default)]
+pub struct Thumbnail(Arc<Mutex<Option<Arc<Vec<u8>>>>>);
+
+impl Thumbnail {
+    // 还没有生成时返回 None.
+    pub fn latest(&self) -> Option<Arc<Vec<u8>>> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+// 在采集线程中按间隔把处理后的帧缩小并编码为 JPEG.
+pub struct Thumbnails {
+    config: ThumbnailConfig,
+    thumbnail: Thumbnail,
+    params: Vector<i32>,
+    small: Mat,
+    blank: Mat,
+    updated: Option<Instant>,
+    saved: Option<Instant>,
+}
+
+impl Thumbnails {
+    pub fn new(config: &ThumbnailConfig, thumbnail: Thumbnail) -> Self {
+        Self {
+            config: config.clone(),
+            thumbnail,
+            params: photo::write_params("jpg", Some(config.quality)),
+            small: Mat::default(),
+            blank: Mat::default(),
+            updated: None,
+            saved: None,
+        }
+    }
+
+    pub fn update(&mut self, frame: &Mat) -> Result<()> {
+        if !self.config.enabled || frame.empty() {
+            return Ok(());
+        }
+        let due = |last: Option<Instant>, seconds: f64| {
+            last.map_or(true, |last| {
+                last.elapsed() >= Duration::from_secs_f64(seconds.max(0.0))
+            })
+        };
+        if !due(self.updated, self.config.interval) {
+            return Ok(());
+        }
+        self.updated = Some(Instant::now());
+        let frame = privacy::output(frame, &mut self.blank)?;
+        let width = self.config.width.clamp(16, frame.cols());
+        let height = (frame.rows() as f64 * width as f64 / frame.cols() as f64).round() as i32;
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(width, height.max(1)),
+            0.0,
+            0.0,
+            INTER_AREA,
+        )?;
+        let mut data = Vector::new();
+        imgcodecs::imencode(".jpg", &self.small, &mut data, &self.params)?;
+        let data = Arc::new(data.to_vec());
+        *self.thumbnail.0.lock().unwrap() = Some(data.clone());
+
+        if let Some(path) = &self.config.path {
+            if due(self.saved, self.config.save_interval) {
+                self.saved = Some(Instant::now());
+                // 先写临时文件再改名, 读取的一方不会看到写了一半的图片.
+                let path = sink::timestamped(path);
+                let partial = path.with_extension("jpg.partial");
+                fs::write(&partial, data.as_slice())?;
+                fs::rename(&partial, &path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, CV_8UC3};
+
+    use super::*;
+
+    fn frame() -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(90.)).unwrap()
+    }
+
+    #[test]
+    fn disabled() {
+        let thumbnail = Thumbnail::default();
+        let mut thumbnails = Thumbnails::new(&ThumbnailConfig::default(), thumbnail.clone());
+        thumbnails.update(&frame()).unwrap();
+        assert!(thumbnail.latest().is_none());
+    }
+
+    // 按间隔更新, 同时保存到文件.
+    #[test]
+    fn updates_and_saves() {
+        let dir = std::env::temp_dir().join(format!("thumbnail-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("latest.jpg");
+        let config = ThumbnailConfig {
+            enabled: true,
+            width: 32,
+            path: Some(path.clone()),
+            ..Default::default()
+        };
+        let thumbnail = Thumbnail::default();
+        let mut thumbnails = Thumbnails::new(&config, thumbnail.clone());
+        thumbnails.update(&Mat::default()).unwrap();
+        assert!(thumbnail.latest().is_none());
+        thumbnails.update(&frame()).unwrap();
+        let jpeg = thumbnail.latest().unwrap();
+        let image =
+            imgcodecs::imdecode(&Vector::from_slice(&jpeg), imgcodecs::IMREAD_COLOR).unwrap();
+        assert_eq!((image.cols(), image.rows()), (32, 24));
+        assert_eq!(fs::read(&path).unwrap(), *jpeg);
+        assert!(!path.with_extension("jpg.partial").exists());
+
+        // 间隔内的帧不更新.
+        thumbnails
+            .update(&Mat::new_rows_cols_with_default(96, 128, CV_8UC3, Scalar::all(0.)).unwrap())
+            .unwrap();
+        assert!(Arc::ptr_eq(&thumbnail.latest().unwrap(), &jpeg));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,413 @@
+use std::{collections::VecDeque, time::Instant};
+
+use anyhow::{bail, Result};
+use opencv::{
+    core::{Mat, Vector},
+    imgcodecs::{self, IMREAD_COLOR},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::photo;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TimeShiftConfig {
+    // 可以回看的时长 (秒), 0 表示不缓存.
+    pub seconds: f64,
+    // 缓存帧按 JPEG 压缩保存的质量, 1080p 下每秒约 5 MB.
+    pub quality: i32,
+}
+
+impl Default for TimeShiftConfig {
+    fn default() -> Self {
+        Self {
+            seconds: 30.0,
+            quality: 80,
+        }
+    }
+}
+
+// A-B 循环回放, speed 为相对原始速度的倍数.
+struct Looping {
+    a: u64,
+    b: u64,
+    speed: f64,
+    // 从 from 开始按 speed 回放的起始时刻.
+    started: Instant,
+    from: u64,
+}
+
+struct Buffered {
+    time: Instant,
+    data: Vector<u8>,
+}
+
+pub enum LoopMark {
+    A,
+    B,
+}
+
+// 预览画面的环形缓存, 暂停后可以回看和逐帧查看, 实时画面和录制不受影响.
+pub struct TimeShift {
+    frames: VecDeque<Buffered>,
+    capacity: usize,
+    params: Vector<i32>,
+    // frames 中第一帧的序号, 淘汰旧帧时增加.
+    first: u64,
+    // 暂停时显示的帧序号, None 表示实时.
+    position: Option<u64>,
+    decoded: Option<(u64, Mat)>,
+    // 只设置了 A 点时等待设置 B 点.
+    mark: Option<u64>,
+    looping: Option<Looping>,
+    speed: f64,
+}
+
+impl TimeShift {
+    pub fn new(config: &TimeShiftConfig, fps: f64) -> Self {
+        Self {
+            frames: VecDeque::new(),
+            capacity: (config.seconds.max(0.0) * fps.max(1.0)).round() as usize,
+            params: photo::write_params("jpg", Some(config.quality)),
+            first: 0,
+            position: None,
+            decoded: None,
+            mark: None,
+            looping: None,
+            speed: 1.0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.position.is_some()
+    }
+
+    pub fn push(&mut self, frame: &Mat) -> Result<()> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        let mut data = Vector::new();
+        imgcodecs::imencode(".jpg", frame, &mut data, &self.params)?;
+        self.frames.push_back(Buffered {
+            time: Instant::now(),
+            data,
+        });
+        while self.frames.len() > self.capacity {
+            self.frames.pop_front();
+            self.first += 1;
+        }
+        // 暂停的帧被淘汰时停在最早的一帧.
+        if let Some(position) = &mut self.position {
+            *position = (*position).max(self.first);
+        }
+        if let Some(mark) = &mut self.mark {
+            *mark = (*mark).max(self.first);
+        }
+        if let Some(looping) = &mut self.looping {
+            looping.a = looping.a.max(self.first);
+            looping.b = looping.b.max(looping.a);
+        }
+        Ok(())
+    }
+
+    // 停在最新的一帧, 缓存继续接收实时画面.
+    pub fn pause(&mut self) {
+        if self.position.is_none() {
+            self.position = self.last();
+        }
+    }
+
+    pub fn live(&mut self) {
+        self.position = None;
+        self.decoded = None;
+        self.clear_loop();
+    }
+
+    // 前后移动若干帧, 实时状态下先暂停.
+    pub fn step(&mut self, frames: i64) {
+        self.pause();
+        let (Some(position), Some(last)) = (self.position, self.last()) else {
+            return;
+        };
+        let position = (position as i64 + frames).clamp(self.first as i64, last as i64);
+        self.position = Some(position as u64);
+        self.restart_loop();
+    }
+
+    // 跳到时间线上的位置, 0 为最早的一帧, 1 为最新的一帧.
+    pub fn seek(&mut self, fraction: f64) {
+        let Some(last) = self.last() else {
+            return;
+        };
+        let span = (last - self.first) as f64;
+        self.position = Some(self.first + (fraction.clamp(0.0, 1.0) * span).round() as u64);
+        self.restart_loop();
+    }
+
+    // 在当前帧设置 A 点, 再次调用时设置 B 点并开始循环回放.
+    pub fn mark(&mut self) -> Option<LoopMark> {
+        self.pause();
+        let position = self.position?;
+        let Some(a) = self.mark.take() else {
+            self.looping = None;
+            self.mark = Some(position);
+            return Some(LoopMark::A);
+        };
+        let (a, b) = (a.min(position), a.max(position));
+        self.looping = Some(Looping {
+            a,
+            b,
+            speed: self.speed,
+            started: Instant::now(),
+            from: a,
+        });
+        self.position = Some(a);
+        Some(LoopMark::B)
+    }
+
+    // 停止循环, 停在当前帧.
+    pub fn clear_loop(&mut self) {
+        self.mark = None;
+        self.looping = None;
+    }
+
+    // 回放速度, 例如 0.25 为四分之一速度的慢动作.
+    pub fn set_speed(&mut self, speed: f64) {
+        self.speed = speed.clamp(0.05, 4.0);
+        if let Some(looping) = &mut self.looping {
+            looping.speed = self.speed;
+        }
+        self.restart_loop();
+    }
+
+    // 从当前帧重新计时, 之后按原始帧间隔和速度前进.
+    fn restart_loop(&mut self) {
+        let (Some(looping), Some(position)) = (&mut self.looping, self.position) else {
+            return;
+        };
+        looping.from = position.clamp(looping.a, looping.b);
+        looping.started = Instant::now();
+    }
+
+    // 按经过的时间前进到对应的帧, 超过 B 点后回到 A 点重新计时.
+    fn advance(&mut self) {
+        let Some(looping) = &mut self.looping else {
+            return;
+        };
+        let first = self.first;
+        let time = |index: u64| {
+            self.frames
+                .get(index.checked_sub(first)? as usize)
+                .map(|buffered| buffered.time)
+        };
+        let from = looping.from.clamp(looping.a, looping.b);
+        let (Some(start), Some(end)) = (time(from), time(looping.b)) else {
+            return;
+        };
+        let target = looping.started.elapsed().mul_f64(looping.speed);
+        if target > end.duration_since(start) {
+            looping.from = looping.a;
+            looping.started = Instant::now();
+            self.position = Some(looping.a);
+            return;
+        }
+        let mut position = from;
+        while position < looping.b
+            && time(position + 1).is_some_and(|next| next.duration_since(start) <= target)
+        {
+            position += 1;
+        }
+        self.position = Some(position);
+    }
+
+    // 暂停时显示的帧, 实时状态下返回 None.
+    pub fn frame(&mut self) -> Result<Option<&Mat>> {
+        self.advance();
+        let Some(position) = self.position else {
+            return Ok(None);
+        };
+        if self.decoded.as_ref().map(|(index, _)| *index) != Some(position) {
+            let Some(buffered) = self.get(position) else {
+                return Ok(None);
+            };
+            let image = imgcodecs::imdecode(&buffered.data, IMREAD_COLOR)?;
+            if image.empty() {
+                bail!("decode buffered frame {}", position);
+            }
+            self.decoded = Some((position, image));
+        }
+        Ok(self.decoded.as_ref().map(|(_, image)| image))
+    }
+
+    // 时间线位置 (0..1) 和落后实时画面的秒数.
+    pub fn timeline(&self) -> (f64, f64) {
+        let (Some(position), Some(last)) = (self.position, self.last()) else {
+            return (1.0, 0.0);
+        };
+        let span = (last - self.first) as f64;
+        let fraction = if span > 0.0 {
+            (position - self.first) as f64 / span
+        } else {
+            1.0
+        };
+        let behind = match (self.get(position), self.frames.back()) {
+            (Some(shown), Some(newest)) => newest.time.duration_since(shown.time).as_secs_f64(),
+            _ => 0.0,
+        };
+        (fraction, behind)
+    }
+
+    // 时间线上 A, B 点的位置 (0..1), 未设置时为 -1.
+    pub fn marks(&self) -> (f64, f64) {
+        let fraction = |index: Option<u64>| match (index, self.last()) {
+            (Some(index), Some(last)) if last > self.first => {
+                (index.max(self.first) - self.first) as f64 / (last - self.first) as f64
+            }
+            (Some(_), Some(_)) => 1.0,
+            _ => -1.0,
+        };
+        match &self.looping {
+            Some(looping) => (fraction(Some(looping.a)), fraction(Some(looping.b))),
+            None => (fraction(self.mark), -1.0),
+        }
+    }
+
+    // 缓存的帧数.
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    fn last(&self) -> Option<u64> {
+        (!self.frames.is_empty()).then(|| self.first + self.frames.len() as u64 - 1)
+    }
+
+    fn get(&self, index: u64) -> Option<&Buffered> {
+        self.frames.get(index.checked_sub(self.first)? as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    fn frame(level: f64) -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(level)).unwrap()
+    }
+
+    // 1 秒 4 FPS 缓存 4 帧, 帧的亮度为序号 * 40.
+    fn buffer(frames: usize) -> TimeShift {
+        let config = TimeShiftConfig {
+            seconds: 1.0,
+            quality: 95,
+        };
+        let mut timeshift = TimeShift::new(&config, 4.0);
+        for index in 0..frames {
+            timeshift.push(&frame(index as f64 * 40.)).unwrap();
+        }
+        timeshift
+    }
+
+    fn level(timeshift: &mut TimeShift) -> u8 {
+        let image = timeshift.frame().unwrap().unwrap();
+        image.at_2d::<Vec3b>(10, 10).unwrap()[0]
+    }
+
+    #[test]
+    fn disabled_without_seconds() {
+        let config = TimeShiftConfig {
+            seconds: 0.0,
+            ..Default::default()
+        };
+        let mut timeshift = TimeShift::new(&config, 30.0);
+        assert!(!timeshift.is_enabled());
+        timeshift.push(&frame(0.)).unwrap();
+        assert_eq!(timeshift.len(), 0);
+        timeshift.pause();
+        assert!(!timeshift.is_paused());
+        assert!(timeshift.frame().unwrap().is_none());
+    }
+
+    #[test]
+    fn keeps_newest_frames() {
+        let mut timeshift = buffer(6);
+        assert_eq!(timeshift.len(), 4);
+        assert!(timeshift.frame().unwrap().is_none());
+        assert_eq!(timeshift.timeline(), (1.0, 0.0));
+        timeshift.pause();
+        assert!(timeshift.is_paused());
+        assert!(level(&mut timeshift).abs_diff(200) <= 2);
+        timeshift.seek(0.0);
+        assert!(level(&mut timeshift).abs_diff(80) <= 2);
+        // 暂停的帧被淘汰时停在最早的一帧.
+        timeshift.push(&frame(240.)).unwrap();
+        assert!(level(&mut timeshift).abs_diff(120) <= 2);
+        timeshift.live();
+        assert!(!timeshift.is_paused());
+    }
+
+    #[test]
+    fn step_and_seek() {
+        let mut timeshift = buffer(4);
+        timeshift.step(-1);
+        assert!(level(&mut timeshift).abs_diff(80) <= 2);
+        timeshift.step(-10);
+        assert!(level(&mut timeshift).abs_diff(0) <= 2);
+        assert_eq!(timeshift.timeline().0, 0.0);
+        timeshift.step(10);
+        assert_eq!(timeshift.timeline().0, 1.0);
+        timeshift.seek(0.5);
+        // 3 帧的间隔, 0.5 四舍五入到第 2 帧.
+        assert!((timeshift.timeline().0 - 2.0 / 3.0).abs() < 1e-9);
+        assert!(timeshift.timeline().1 >= 0.0);
+    }
+
+    // 第一次设置 A 点, 第二次设置 B 点并从 A 点开始循环.
+    #[test]
+    fn loop_marks() {
+        let mut timeshift = buffer(4);
+        assert_eq!(timeshift.marks(), (-1.0, -1.0));
+        timeshift.step(-2);
+        assert!(matches!(timeshift.mark(), Some(LoopMark::A)));
+        assert!((timeshift.marks().0 - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(timeshift.marks().1, -1.0);
+        timeshift.step(1);
+        assert!(matches!(timeshift.mark(), Some(LoopMark::B)));
+        assert!((timeshift.marks().0 - 1.0 / 3.0).abs() < 1e-9);
+        assert!((timeshift.marks().1 - 2.0 / 3.0).abs() < 1e-9);
+        assert!(level(&mut timeshift).abs_diff(40) <= 2);
+        timeshift.clear_loop();
+        assert_eq!(timeshift.marks(), (-1.0, -1.0));
+        assert!(timeshift.is_paused());
+    }
+
+    // 循环按缓存时的帧间隔和速度前进, 超过 B 点后回到 A 点.
+    #[test]
+    fn loop_playback() {
+        let mut timeshift = buffer(0);
+        for index in 0..4 {
+            timeshift.push(&frame(index as f64 * 40.)).unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(40));
+        }
+        timeshift.seek(0.0);
+        timeshift.mark();
+        timeshift.seek(1.0);
+        timeshift.mark();
+        timeshift.set_speed(100.0);
+        assert!(level(&mut timeshift).abs_diff(0) <= 2);
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        // 4 倍速下经过 240ms 的缓存时间, 超过 B 点后回到 A 点.
+        assert!(level(&mut timeshift).abs_diff(0) <= 2);
+        timeshift.set_speed(1.0);
+        std::thread::sleep(std::time::Duration::from_millis(60));
+        let position = level(&mut timeshift);
+        assert!(position.abs_diff(40) <= 2 || position.abs_diff(80) <= 2);
+    }
+}
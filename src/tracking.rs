@@ -0,0 +1,424 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use opencv::{
+    core::{Mat, Point, Rect, Scalar},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{detect::Detector, pipeline::FrameProcessor, trajectory::Trajectories};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrackingConfig {
+    pub enabled: bool,
+    // 每隔多少帧运行一次检测, 中间帧只做预测.
+    pub detect_every: u32,
+    // 高于该分数的检测框先参与匹配, 并且可以创建新轨迹.
+    pub high_score: f64,
+    // 低于该分数的检测框直接丢弃; 介于两者之间的只用于延续已有轨迹.
+    pub low_score: f64,
+    pub min_iou: f64,
+    // 连续命中多少次后才确认为目标并分配 ID.
+    pub min_hits: u32,
+    // 连续多少次未匹配后删除轨迹.
+    pub max_missed: u32,
+    // 轨迹尾迹保留的点数.
+    pub trail_length: usize,
+    // 检测模型名称 (见 [models]), 不设置时使用内置的 HOG 行人检测器.
+    pub model: Option<String>,
+    // 使用检测模型时只跟踪该类别 ID.
+    pub class: Option<usize>,
+}
+
+impl Default for TrackingConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detect_every: 3,
+            high_score: 0.5,
+            low_score: 0.1,
+            min_iou: 0.3,
+            min_hits: 3,
+            max_missed: 15,
+            trail_length: 60,
+            model: None,
+            class: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Detection {
+    pub rect: Rect,
+    pub score: f64,
+    pub class: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Track {
+    // 未确认的轨迹 ID 为 0.
+    pub id: u64,
+    pub rect: Rect,
+    pub class: usize,
+    pub missed: u32,
+    pub hits: u32,
+    // 每次更新时框中心的位移, 用于预测下一次的位置.
+    velocity: (f64, f64),
+    trail: VecDeque<Point>,
+}
+
+impl Track {
+    // 目标在地面上的位置, 取框底部中点.
+    pub fn foot(&self) -> Point {
+        center_bottom(&self.rect)
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        self.id != 0
+    }
+
+    pub fn trail(&self) -> &VecDeque<Point> {
+        &self.trail
+    }
+
+    fn predicted(&self) -> Rect {
+        Rect::new(
+            self.rect.x + self.velocity.0.round() as i32,
+            self.rect.y + self.velocity.1.round() as i32,
+            self.rect.width,
+            self.rect.height,
+        )
+    }
+}
+
+// ByteTrack 风格的多目标跟踪: 按匀速模型预测位置, 先用高分检测框按 IoU 匹配,
+// 剩下的轨迹再用低分检测框匹配, 这样被遮挡时分数下降的目标不会丢失 ID.
+pub struct Tracker {
+    config: TrackingConfig,
+    tracks: Vec<Track>,
+    next_id: u64,
+}
+
+impl Tracker {
+    pub fn new(config: &TrackingConfig) -> Self {
+        Self {
+            config: config.clone(),
+            tracks: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    // 已确认过的目标总数.
+    pub fn count(&self) -> u64 {
+        self.next_id
+    }
+
+    pub fn update(&mut self, detections: &[Detection]) -> &[Track] {
+        let predicted: Vec<Rect> = self.tracks.iter().map(|t| t.predicted()).collect();
+        let (high, low): (Vec<Detection>, Vec<Detection>) = detections
+            .iter()
+            .filter(|d| d.score >= self.config.low_score)
+            .partition(|d| d.score >= self.config.high_score);
+
+        let mut matched = vec![None; self.tracks.len()];
+        let all: Vec<usize> = (0..self.tracks.len()).collect();
+        let high_used = self.associate(&predicted, &all, &high, &mut matched);
+        let remaining: Vec<usize> = all.into_iter().filter(|&t| matched[t].is_none()).collect();
+        let mut low_matched = vec![None; self.tracks.len()];
+        self.associate(&predicted, &remaining, &low, &mut low_matched);
+
+        for (t, track) in self.tracks.iter_mut().enumerate() {
+            let detection = matched[t]
+                .map(|d| high[d])
+                .or_else(|| low_matched[t].map(|d| low[d]));
+            match detection {
+                Some(detection) => {
+                    let (dx, dy) = (
+                        (detection.rect.x - track.rect.x) as f64,
+                        (detection.rect.y - track.rect.y) as f64,
+                    );
+                    track.velocity = (
+                        0.5 * track.velocity.0 + 0.5 * dx,
+                        0.5 * track.velocity.1 + 0.5 * dy,
+                    );
+                    track.rect = detection.rect;
+                    track.missed = 0;
+                    track.hits += 1;
+                    if track.id == 0 && track.hits >= self.config.min_hits {
+                        self.next_id += 1;
+                        track.id = self.next_id;
+                    }
+                }
+                None => {
+                    track.rect = predicted[t];
+                    track.missed += 1;
+                }
+            }
+            if track.missed == 0 {
+                track.trail.push_back(track.foot());
+                while track.trail.len() > self.config.trail_length {
+                    track.trail.pop_front();
+                }
+            }
+        }
+        // 未确认的轨迹一次未命中就删除, 避免误检产生 ID.
+        let max_missed = self.config.max_missed;
+        self.tracks
+            .retain(|t| t.missed == 0 || (t.is_confirmed() && t.missed <= max_missed));
+
+        for (detection, used) in high.iter().zip(&high_used) {
+            if !used {
+                self.tracks.push(Track {
+                    id: 0,
+                    rect: detection.rect,
+                    class: detection.class,
+                    missed: 0,
+                    hits: 1,
+                    velocity: (0.0, 0.0),
+                    trail: VecDeque::from([center_bottom(&detection.rect)]),
+                });
+            }
+        }
+        &self.tracks
+    }
+
+    // 按 IoU 从大到小贪心匹配, 返回每个检测框是否已被使用.
+    fn associate(
+        &self,
+        predicted: &[Rect],
+        tracks: &[usize],
+        detections: &[Detection],
+        matched: &mut [Option<usize>],
+    ) -> Vec<bool> {
+        let mut pairs = Vec::new();
+        for &t in tracks {
+            for (d, detection) in detections.iter().enumerate() {
+                let iou = iou(&predicted[t], &detection.rect);
+                if iou >= self.config.min_iou {
+                    pairs.push((iou, t, d));
+                }
+            }
+        }
+        pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut used = vec![false; detections.len()];
+        for (_, t, d) in pairs {
+            if matched[t].is_some() || used[d] {
+                continue;
+            }
+            matched[t] = Some(d);
+            used[d] = true;
+        }
+        used
+    }
+
+    pub fn tracks(&self) -> impl Iterator<Item = &Track> {
+        self.tracks.iter().filter(|t| t.is_confirmed())
+    }
+
+    // 尾迹越旧颜色越暗, 线越细.
+    pub fn draw_trails(&self, frame: &mut Mat) -> Result<()> {
+        for track in self.tracks() {
+            let color = track_color(track.id);
+            let n = track.trail.len();
+            for (i, (a, b)) in track
+                .trail
+                .iter()
+                .zip(track.trail.iter().skip(1))
+                .enumerate()
+            {
+                let fade = (i + 1) as f64 / n as f64;
+                let faded = Scalar::new(color[0] * fade, color[1] * fade, color[2] * fade, 0.);
+                imgproc::line(frame, *a, *b, faded, 1 + (fade * 2.0) as i32, LINE_AA, 0)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn draw(&self, frame: &mut Mat) -> Result<()> {
+        self.draw_trails(frame)?;
+        for track in self.tracks().filter(|t| t.missed == 0) {
+            let color = track_color(track.id);
+            imgproc::rectangle(frame, track.rect, color, 2, LINE_AA, 0)?;
+            imgproc::put_text(
+                frame,
+                &format!("#{}", track.id),
+                Point::new(track.rect.x, track.rect.y - 6),
+                FONT_HERSHEY_SIMPLEX,
+                0.5,
+                color,
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn center_bottom(rect: &Rect) -> Point {
+    Point::new(rect.x + rect.width / 2, rect.y + rect.height)
+}
+
+// 按 ID 生成区分度较高的颜色.
+pub fn track_color(id: u64) -> Scalar {
+    const COLORS: [[f64; 3]; 8] = [
+        [0., 255., 0.],
+        [255., 128., 0.],
+        [0., 128., 255.],
+        [255., 0., 255.],
+        [0., 255., 255.],
+        [255., 255., 0.],
+        [128., 0., 255.],
+        [0., 0., 255.],
+    ];
+    let [b, g, r] = COLORS[(id as usize) % COLORS.len()];
+    Scalar::new(b, g, r, 0.)
+}
+
+pub fn iou(a: &Rect, b: &Rect) -> f64 {
+    let inter = *a & *b;
+    let inter_area = (inter.width.max(0) * inter.height.max(0)) as f64;
+    let union = (a.width * a.height + b.width * b.height) as f64 - inter_area;
+    if union <= 0.0 {
+        0.0
+    } else {
+        inter_area / union
+    }
+}
+
+// 目标跟踪预设: 显示每个目标的 ID 和运动轨迹, 以及累计数量.
+pub struct ObjectTracking {
+    detector: Box<dyn Detector>,
+    tracker: Tracker,
+    trajectories: Trajectories,
+    detect_every: u64,
+    frames: u64,
+}
+
+impl ObjectTracking {
+    pub fn new(
+        config: &TrackingConfig,
+        detector: Box<dyn Detector>,
+        trajectories: Trajectories,
+    ) -> Self {
+        Self {
+            detector,
+            tracker: Tracker::new(config),
+            trajectories,
+            detect_every: config.detect_every.max(1) as u64,
+            frames: 0,
+        }
+    }
+}
+
+impl FrameProcessor for ObjectTracking {
+    fn name(&self) -> &str {
+        "tracking"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        if self.frames % self.detect_every == 0 {
+            let detections = self.detector.detect(frame)?;
+            self.tracker.update(&detections);
+            for track in self.tracker.tracks().filter(|t| t.missed == 0) {
+                let class = self.detector.label(track.class);
+                self.trajectories.record(self.frames, &class, track);
+            }
+        }
+        self.frames += 1;
+        self.tracker.draw(frame)?;
+        let visible = self.tracker.tracks().filter(|t| t.missed == 0).count();
+        imgproc::put_text(
+            frame,
+            &format!("tracked {} (total {})", visible, self.tracker.count()),
+            Point::new(8, frame.rows() - 40),
+            FONT_HERSHEY_SIMPLEX,
+            0.7,
+            Scalar::new(0., 255., 0., 0.),
+            2,
+            LINE_AA,
+            false,
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detection(x: i32, score: f64) -> Detection {
+        Detection {
+            rect: Rect::new(x, 20, 40, 80),
+            score,
+            class: 0,
+        }
+    }
+
+    fn ids(tracker: &Tracker) -> Vec<u64> {
+        tracker.tracks().map(|t| t.id).collect()
+    }
+
+    #[test]
+    fn overlap() {
+        let a = Rect::new(0, 0, 10, 10);
+        assert_eq!(iou(&a, &a), 1.0);
+        assert_eq!(iou(&a, &Rect::new(5, 0, 10, 10)), 50.0 / 150.0);
+        assert_eq!(iou(&a, &Rect::new(20, 0, 10, 10)), 0.0);
+    }
+
+    #[test]
+    fn confirms_after_min_hits() {
+        let mut tracker = Tracker::new(&TrackingConfig::default());
+        for _ in 0..2 {
+            tracker.update(&[detection(10, 0.9)]);
+            assert!(ids(&tracker).is_empty());
+        }
+        tracker.update(&[detection(12, 0.9)]);
+        assert_eq!(ids(&tracker), [1]);
+        assert_eq!(tracker.count(), 1);
+    }
+
+    // 未确认的轨迹漏检一次就删除, 重新出现时从头计数.
+    #[test]
+    fn drops_unconfirmed() {
+        let mut tracker = Tracker::new(&TrackingConfig::default());
+        tracker.update(&[detection(10, 0.9)]);
+        tracker.update(&[]);
+        tracker.update(&[detection(10, 0.9)]);
+        tracker.update(&[detection(10, 0.9)]);
+        assert!(ids(&tracker).is_empty());
+        tracker.update(&[detection(10, 0.9)]);
+        assert_eq!(ids(&tracker), [1]);
+    }
+
+    #[test]
+    fn low_scores_keep_id() {
+        let config = TrackingConfig {
+            min_hits: 1,
+            ..Default::default()
+        };
+        let mut tracker = Tracker::new(&config);
+        tracker.update(&[detection(10, 0.9)]);
+        tracker.update(&[detection(10, 0.9)]);
+        assert_eq!(ids(&tracker), [1]);
+
+        // 被遮挡时分数下降, 仍然延续原来的 ID.
+        tracker.update(&[detection(14, 0.3)]);
+        let track = tracker.tracks().next().unwrap();
+        assert_eq!((track.id, track.missed, track.rect.x), (1, 0, 14));
+        // 低分检测框不会创建新轨迹.
+        tracker.update(&[detection(14, 0.3), detection(300, 0.3)]);
+        assert_eq!(tracker.tracks.len(), 1);
+        // 低于 low_score 的直接丢弃, 轨迹保留.
+        tracker.update(&[detection(14, 0.05)]);
+        assert_eq!(tracker.tracks().next().unwrap().missed, 1);
+        tracker.update(&[detection(14, 0.9)]);
+        assert_eq!(ids(&tracker), [1]);
+        assert_eq!(tracker.count(), 1);
+        assert_eq!(tracker.tracks().next().unwrap().trail().len(), 5);
+    }
+}
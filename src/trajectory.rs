@@ -0,0 +1,223 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{self, Mat, Point, Size, CV_32F},
+    imgcodecs,
+    imgproc::{self, LINE_AA},
+    prelude::*,
+};
+use serde_json::json;
+
+use crate::tracking::{track_color, Track};
+
+struct Sample {
+    frame: u64,
+    time: DateTime<Local>,
+    position: Point,
+}
+
+struct Trajectory {
+    class: String,
+    samples: Vec<Sample>,
+}
+
+// 记录每个已确认目标的逐帧位置, 可以在多个线程之间共享.
+#[derive(Clone, Default)]
+pub struct Trajectories {
+    tracks: Arc<Mutex<BTreeMap<u64, Trajectory>>>,
+}
+
+impl Trajectories {
+    pub fn record(&self, frame: u64, class: &str, track: &Track) {
+        let mut trajectories = self.tracks.lock().unwrap();
+        let trajectory = trajectories.entry(track.id).or_insert_with(|| Trajectory {
+            class: class.to_string(),
+            samples: Vec::new(),
+        });
+        trajectory.samples.push(Sample {
+            frame,
+            time: Local::now(),
+            position: track.foot(),
+        });
+    }
+
+    pub fn clear(&self) {
+        self.tracks.lock().unwrap().clear();
+    }
+
+    // 写出 <stem>.json, <stem>.csv 和以 background 为底图的热力/路径图 <stem>.png,
+    // 返回目标数量.
+    pub fn export(&self, stem: &str, background: &Mat) -> Result<usize> {
+        let trajectories = self.tracks.lock().unwrap();
+
+        let json: Vec<_> = trajectories
+            .iter()
+            .map(|(id, trajectory)| {
+                let points: Vec<_> = trajectory
+                    .samples
+                    .iter()
+                    .map(|s| {
+                        json!({
+                            "frame": s.frame,
+                            "time": s.time.to_rfc3339(),
+                            "x": s.position.x,
+                            "y": s.position.y,
+                        })
+                    })
+                    .collect();
+                json!({"id": id, "class": trajectory.class, "points": points})
+            })
+            .collect();
+        fs::write(
+            format!("{}.json", stem),
+            serde_json::to_string_pretty(&json)?,
+        )?;
+
+        let mut csv = BufWriter::new(File::create(format!("{}.csv", stem))?);
+        writeln!(csv, "id,class,frame,time,x,y")?;
+        for (id, trajectory) in trajectories.iter() {
+            for s in &trajectory.samples {
+                writeln!(
+                    csv,
+                    "{},{},{},{},{},{}",
+                    id,
+                    trajectory.class,
+                    s.frame,
+                    s.time.to_rfc3339(),
+                    s.position.x,
+                    s.position.y
+                )?;
+            }
+        }
+        csv.flush()?;
+
+        let image = render(&trajectories, background)?;
+        imgcodecs::imwrite(&format!("{}.png", stem), &image, &core::Vector::new())?;
+        Ok(trajectories.len())
+    }
+}
+
+// 停留越久的位置越"热", 热力图叠加在变暗的底图上, 再画出每条路径.
+fn render(trajectories: &BTreeMap<u64, Trajectory>, background: &Mat) -> Result<Mat> {
+    let size = background.size()?;
+    let mut heat = Mat::zeros(size.height, size.width, CV_32F)?.to_mat()?;
+    for trajectory in trajectories.values() {
+        for s in &trajectory.samples {
+            let p = s.position;
+            if p.x >= 0 && p.y >= 0 && p.x < size.width && p.y < size.height {
+                *heat.at_2d_mut::<f32>(p.y, p.x)? += 1.0;
+            }
+        }
+    }
+    let mut blurred = Mat::default();
+    let sigma = size.width as f64 / 50.0;
+    imgproc::gaussian_blur(
+        &heat,
+        &mut blurred,
+        Size::new(0, 0),
+        sigma,
+        sigma,
+        core::BORDER_DEFAULT,
+    )?;
+    let mut normalized = Mat::default();
+    core::normalize(
+        &blurred,
+        &mut normalized,
+        0.,
+        255.,
+        core::NORM_MINMAX,
+        core::CV_8U,
+        &core::no_array(),
+    )?;
+    let mut colored = Mat::default();
+    imgproc::apply_color_map(&normalized, &mut colored, imgproc::COLORMAP_JET)?;
+
+    let mut image = Mat::default();
+    core::add_weighted(background, 0.4, &colored, 0.6, 0., &mut image, -1)?;
+    for (id, trajectory) in trajectories {
+        let color = track_color(*id);
+        for pair in trajectory.samples.windows(2) {
+            imgproc::line(
+                &mut image,
+                pair[0].position,
+                pair[1].position,
+                color,
+                2,
+                LINE_AA,
+                0,
+            )?;
+        }
+    }
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Rect, Scalar, CV_8UC3};
+
+    use super::*;
+    use crate::tracking::{Detection, Tracker, TrackingConfig};
+
+    #[test]
+    fn exports_json_csv_and_image() {
+        let config = TrackingConfig {
+            min_hits: 1,
+            ..Default::default()
+        };
+        let mut tracker = Tracker::new(&config);
+        let trajectories = Trajectories::default();
+        for frame in 0..4 {
+            let x = 10 + frame as i32 * 5;
+            tracker.update(&[Detection {
+                rect: Rect::new(x, 20, 40, 80),
+                score: 0.9,
+                class: 0,
+            }]);
+            for track in tracker.tracks() {
+                trajectories.record(frame, "person", track);
+            }
+        }
+
+        let stem = std::env::temp_dir()
+            .join(format!("trajectory-test-{}", std::process::id()))
+            .display()
+            .to_string();
+        let background =
+            Mat::new_rows_cols_with_default(120, 160, CV_8UC3, Scalar::all(50.)).unwrap();
+        assert_eq!(trajectories.export(&stem, &background).unwrap(), 1);
+
+        // 第一帧还未确认, 从第二帧开始记录, 位置是框底部中点.
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(format!("{}.json", stem)).unwrap()).unwrap();
+        assert_eq!(json[0]["id"], 1);
+        assert_eq!(json[0]["class"], "person");
+        let points = json[0]["points"].as_array().unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0]["frame"], 1);
+        assert_eq!(
+            (points[0]["x"].as_i64(), points[0]["y"].as_i64()),
+            (Some(35), Some(100))
+        );
+        let csv = fs::read_to_string(format!("{}.csv", stem)).unwrap();
+        assert_eq!(csv.lines().count(), 4);
+        assert!(csv.lines().nth(3).unwrap().starts_with("1,person,3,"));
+        let image = imgcodecs::imread(&format!("{}.png", stem), imgcodecs::IMREAD_COLOR).unwrap();
+        assert_eq!((image.cols(), image.rows()), (160, 120));
+        for ext in ["json", "csv", "png"] {
+            fs::remove_file(format!("{}.{}", stem, ext)).unwrap();
+        }
+
+        trajectories.clear();
+        assert_eq!(trajectories.export(&stem, &background).unwrap(), 0);
+        for ext in ["json", "csv", "png"] {
+            fs::remove_file(format!("{}.{}", stem, ext)).unwrap();
+        }
+    }
+}
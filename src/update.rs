@@ -0,0 +1,383 @@
+use std::{
+    fs::{self, File},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{jobs::Handle, threads::Shutdown};
+
+// 本程序的版本, 和发布的 tag 比较.
+pub const CURRENT: &str = env!("CARGO_PKG_VERSION");
+
+// 更新检查: 默认关闭. 启用后在后台定期查询发布列表, 有新版本时在窗口中显示更新说明,
+// 有当前平台的二进制文件时可以下载. 不会替换正在运行的程序.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UpdateConfig {
+    pub enabled: bool,
+    // GitHub releases API 格式的发布列表.
+    pub feed: String,
+    // 检查间隔 (小时).
+    pub interval: f64,
+    // 包括预发布版本.
+    pub prerelease: bool,
+    // 下载的保存目录.
+    pub dir: PathBuf,
+}
+
+impl Default for UpdateConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            feed: "https://api.github.com/repos/opsnull/rust-slint-opencv/releases".to_string(),
+            interval: 24.0,
+            prerelease: false,
+            dir: PathBuf::from("updates"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    // 更新说明 (Markdown).
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub html_url: String,
+    #[serde(default)]
+    pub prerelease: bool,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub assets: Vec<Asset>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+// 找到的新版本.
+#[derive(Debug, Clone)]
+pub struct Update {
+    pub version: String,
+    // 当前版本之后所有发布的更新说明, 从新到旧.
+    pub changelog: String,
+    pub page: String,
+    // 当前平台的二进制文件, 没有时只能到发布页面手动下载.
+    pub asset: Option<Asset>,
+    // 同名加 .sha256 的校验文件, 没有时也不能下载.
+    pub checksum: Option<Asset>,
+}
+
+// "v1.2.3", "1.2.3-rc.1" 中的数字部分, 不是版本号时返回 None.
+pub fn version(tag: &str) -> Option<Vec<u64>> {
+    let tag = tag.trim().trim_start_matches(['v', 'V']);
+    let core = tag.split(['-', '+']).next()?;
+    core.split('.').map(|part| part.parse().ok()).collect()
+}
+
+// tag 是否比 current 新. 数字部分相同时, 正式版比预发布版新.
+pub fn newer(tag: &str, current: &str) -> bool {
+    let (Some(mut a), Some(mut b)) = (version(tag), version(current)) else {
+        return false;
+    };
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    let pre = |text: &str| text.contains('-');
+    a > b || (a == b && pre(current) && !pre(tag))
+}
+
+// 比当前版本新的发布, 从新到旧.
+pub fn newer_releases<'a>(
+    releases: &'a [Release],
+    current: &str,
+    prerelease: bool,
+) -> Vec<&'a Release> {
+    let mut found = releases
+        .iter()
+        .filter(|release| !release.draft && (prerelease || !release.prerelease))
+        .filter(|release| newer(&release.tag_name, current))
+        .collect::<Vec<_>>();
+    found.sort_by(|a, b| version(&b.tag_name).cmp(&version(&a.tag_name)));
+    found
+}
+
+// 当前平台的二进制文件: 文件名同时包含系统和架构, 例如 slint-opencv-linux-x86_64.tar.gz.
+pub fn platform_asset(assets: &[Asset]) -> Option<&Asset> {
+    let os = match std::env::consts::OS {
+        "macos" => vec!["macos", "darwin", "apple"],
+        "windows" => vec!["windows", "win64"],
+        os => vec![os],
+    };
+    let arch = match std::env::consts::ARCH {
+        "x86_64" => vec!["x86_64", "amd64", "x64"],
+        "aarch64" => vec!["aarch64", "arm64"],
+        arch => vec![arch],
+    };
+    assets.iter().find(|asset| {
+        let name = asset.name.to_lowercase();
+        !name.ends_with(".sha256")
+            && os.iter().any(|os| name.contains(os))
+            && arch.iter().any(|arch| name.contains(arch))
+    })
+}
+
+pub fn check(config: &UpdateConfig) -> Result<Option<Update>> {
+    // ureq 没有启用 json 特性, 用 serde_json 解析.
+    let response = ureq::get(&config.feed)
+        .set(
+            "User-Agent",
+            concat!("slint-opencv/", env!("CARGO_PKG_VERSION")),
+        )
+        .set("Accept", "application/vnd.github+json")
+        .call()
+        .with_context(|| format!("query releases from {}", config.feed))?;
+    let releases: Vec<Release> =
+        serde_json::from_reader(response.into_reader()).context("parse release list")?;
+    let found = newer_releases(&releases, CURRENT, config.prerelease);
+    let Some(latest) = found.first() else {
+        return Ok(None);
+    };
+    let changelog = found
+        .iter()
+        .map(|release| {
+            let title = release.name.as_deref().unwrap_or(&release.tag_name);
+            let body = release.body.as_deref().unwrap_or("").trim();
+            format!("{}\n{}", title, body)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let asset = platform_asset(&latest.assets).cloned();
+    let checksum = asset.as_ref().and_then(|asset| {
+        let name = format!("{}.sha256", asset.name);
+        latest
+            .assets
+            .iter()
+            .find(|checksum| checksum.name == name)
+            .cloned()
+    });
+    Ok(Some(Update {
+        version: latest.tag_name.clone(),
+        changelog,
+        page: latest.html_url.clone(),
+        asset,
+        checksum,
+    }))
+}
+
+// 按 interval 定期检查, 每个新版本只通知一次.
+pub fn start(
+    config: &UpdateConfig,
+    shutdown: &Shutdown,
+    on_update: impl Fn(Update) + Send + 'static,
+) -> Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let (config, shutdown) = (config.clone(), shutdown.clone());
+    thread::Builder::new()
+        .name("update-check".to_string())
+        .spawn(move || {
+            let interval = Duration::from_secs_f64(config.interval.max(1.0) * 3600.0);
+            let mut notified = None;
+            loop {
+                match check(&config) {
+                    Ok(Some(update)) if notified.as_ref() != Some(&update.version) => {
+                        notified = Some(update.version.clone());
+                        on_update(update);
+                    }
+                    Ok(_) => {}
+                    Err(err) => eprintln!("update check: {:?}", err),
+                }
+                if shutdown.wait(interval) {
+                    return;
+                }
+            }
+        })?;
+    Ok(())
+}
+
+// 发布列表中的文件名只能是单独的文件名, 不能带目录或 "..".
+pub fn file_name(name: &str) -> Result<&str> {
+    match Path::new(name).file_name().and_then(|file| file.to_str()) {
+        Some(file) if file == name && !name.contains(['/', '\\']) => Ok(file),
+        _ => bail!("invalid asset name {:?}", name),
+    }
+}
+
+// sha256sum 格式的校验文件, 只取第一个字段.
+pub fn parse_checksum(text: &str) -> Option<String> {
+    let digest = text.split_whitespace().next()?;
+    (digest.len() == 64 && digest.bytes().all(|b| b.is_ascii_hexdigit()))
+        .then(|| digest.to_ascii_lowercase())
+}
+
+fn get(url: &str) -> Result<ureq::Response> {
+    ureq::get(url)
+        .set(
+            "User-Agent",
+            concat!("slint-opencv/", env!("CARGO_PKG_VERSION")),
+        )
+        .call()
+        .with_context(|| format!("download {}", url))
+}
+
+// 下载到 dir 并和校验文件比较, 返回保存的路径. Unix 上加上可执行权限.
+pub fn download(
+    config: &UpdateConfig,
+    asset: &Asset,
+    checksum: &Asset,
+    job: &Handle,
+) -> Result<PathBuf> {
+    let name = file_name(&asset.name)?;
+    let expected = get(&checksum.browser_download_url)?
+        .into_string()
+        .with_context(|| format!("read {}", checksum.name))?;
+    let expected = parse_checksum(&expected)
+        .with_context(|| format!("{} is not a SHA256 checksum", checksum.name))?;
+    fs::create_dir_all(&config.dir).with_context(|| format!("create {}", config.dir.display()))?;
+    let path = config.dir.join(name);
+    let partial = config.dir.join(format!("{}.part", name));
+    let mut reader = get(&asset.browser_download_url)?.into_reader();
+    let mut file =
+        File::create(&partial).with_context(|| format!("create {}", partial.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut written = 0u64;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        file.write_all(&buffer[..read])?;
+        hasher.update(&buffer[..read]);
+        written += read as u64;
+        let progress = (asset.size > 0).then(|| written as f64 / asset.size as f64);
+        // 取消时删除下载了一半的文件.
+        if let Err(err) = job.progress(progress) {
+            drop(file);
+            let _ = fs::remove_file(&partial);
+            return Err(err);
+        }
+    }
+    drop(file);
+    let digest = format!("{:x}", hasher.finalize());
+    if digest != expected {
+        let _ = fs::remove_file(&partial);
+        bail!(
+            "{}: SHA256 mismatch, expected {} got {}",
+            name,
+            expected,
+            digest
+        );
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&partial, fs::Permissions::from_mode(0o755))?;
+    }
+    fs::rename(&partial, &path).with_context(|| format!("save {}", path.display()))?;
+    Ok(path)
+}
+
+// 用默认浏览器打开发布页面.
+pub fn open_page(url: &str) -> Result<()> {
+    #[cfg(target_os = "macos")]
+    let program = "open";
+    #[cfg(target_os = "windows")]
+    let program = "explorer";
+    #[cfg(not(any(target_os = "macos", target_os = "windows")))]
+    let program = "xdg-open";
+    std::process::Command::new(program)
+        .arg(url)
+        .spawn()
+        .with_context(|| format!("open {}", url))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions() {
+        assert_eq!(version("v1.2.3"), Some(vec![1, 2, 3]));
+        assert_eq!(version("1.10-rc.1+build"), Some(vec![1, 10]));
+        assert_eq!(version("nightly"), None);
+        assert!(newer("v1.10.0", "1.9.9"));
+        assert!(newer("1.2.1", "1.2"));
+        assert!(!newer("v1.2", "1.2.0"));
+        assert!(!newer("1.2.0", "1.3.0"));
+        // 同号的正式版比预发布版新, 反过来不是.
+        assert!(newer("v1.2.0", "1.2.0-rc.1"));
+        assert!(!newer("v1.2.0-rc.1", "1.2.0"));
+        assert!(!newer("nightly", "1.2.0"));
+    }
+
+    // 跳过草稿, 只在选择预发布时包含预发布版, 从新到旧排列.
+    #[test]
+    fn release_order() {
+        let releases: Vec<Release> = serde_json::from_str(
+            r#"[
+                {"tag_name": "v1.3.0", "draft": true, "assets": []},
+                {"tag_name": "v1.1.0", "assets": []},
+                {"tag_name": "v1.10.0-rc.1", "prerelease": true, "assets": []},
+                {"tag_name": "v1.2.0", "assets": []},
+                {"tag_name": "v1.0.0", "assets": []}
+            ]"#,
+        )
+        .unwrap();
+        let tags = |prerelease| {
+            newer_releases(&releases, "1.0.0", prerelease)
+                .iter()
+                .map(|release| release.tag_name.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(tags(false), ["v1.2.0", "v1.1.0"]);
+        assert_eq!(tags(true), ["v1.10.0-rc.1", "v1.2.0", "v1.1.0"]);
+    }
+
+    #[test]
+    fn asset_file_name() {
+        assert_eq!(
+            file_name("slint-opencv-linux-x86_64").unwrap(),
+            "slint-opencv-linux-x86_64"
+        );
+        for name in [
+            "",
+            ".",
+            "..",
+            "../slint-opencv",
+            "bin/slint-opencv",
+            "/usr/bin/slint-opencv",
+            "..\\slint-opencv",
+        ] {
+            assert!(file_name(name).is_err(), "{:?}", name);
+        }
+    }
+
+    #[test]
+    fn checksum_file() {
+        let digest = "9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08";
+        assert_eq!(
+            parse_checksum(&format!("{}  slint-opencv-linux-x86_64\n", digest)),
+            Some(digest.to_ascii_lowercase())
+        );
+        assert_eq!(parse_checksum("9f86d081"), None);
+        assert_eq!(parse_checksum(""), None);
+    }
+}
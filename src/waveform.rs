@@ -0,0 +1,133 @@
+use std::{
+    io::Read,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Context, Result};
+use slint::{Rgba8Pixel, SharedPixelBuffer};
+
+use crate::frames::FrameBuffer;
+
+// 解码的采样率, 只用来画波形, 8 kHz 足够.
+const SAMPLE_RATE: usize = 8000;
+// 每 10 ms 取一个峰值, 一小时的录像约 36 万个.
+const BLOCK: usize = SAMPLE_RATE / 100;
+// 控制面板中波形图的大小 (像素).
+pub const WIDTH: usize = 480;
+pub const HEIGHT: usize = 48;
+
+// 播放器时间线下的音频波形: 用 ffmpeg 把音轨解码为单声道 PCM, 按时间分段取峰值.
+// 文件没有音轨时返回空的列表.
+pub fn load(path: &str, buckets: usize) -> Result<Vec<f32>> {
+    let mut child = Command::new("ffmpeg")
+        .args(["-v", "error", "-i", path, "-vn", "-ac", "1", "-ar"])
+        .arg(SAMPLE_RATE.to_string())
+        .args(["-f", "s16le", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("run ffmpeg")?;
+    let mut stdout = child.stdout.take().context("ffmpeg stdout")?;
+    let mut blocks = Vec::new();
+    let mut buffer = vec![0u8; BLOCK * 2];
+    loop {
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match stdout.read(&mut buffer[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled < 2 {
+            break;
+        }
+        let peak = buffer[..filled]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]).unsigned_abs())
+            .max()
+            .unwrap_or(0);
+        blocks.push(peak as f32 / i16::MAX as f32);
+        if filled < buffer.len() {
+            break;
+        }
+    }
+    let output = child.wait_with_output()?;
+    // 没有音轨时 ffmpeg 报错 "does not contain any stream".
+    if !output.status.success() && blocks.is_empty() {
+        let message = String::from_utf8_lossy(&output.stderr);
+        if message.contains("does not contain any stream") {
+            return Ok(Vec::new());
+        }
+        bail!("ffmpeg: {}", message.trim());
+    }
+    Ok(peaks(&blocks, buckets))
+}
+
+// 把峰值合并为 buckets 段, 每段取最大值, 按最响的一段归一化到 0..1.
+pub fn peaks(blocks: &[f32], buckets: usize) -> Vec<f32> {
+    if blocks.is_empty() || buckets == 0 {
+        return Vec::new();
+    }
+    let n = blocks.len();
+    let mut peaks: Vec<f32> = (0..buckets)
+        .map(|i| {
+            let start = (i * n / buckets).min(n - 1);
+            let end = ((i + 1) * n / buckets).clamp(start + 1, n);
+            blocks[start..end].iter().copied().fold(0.0, f32::max)
+        })
+        .collect();
+    let loudest = peaks.iter().copied().fold(0.0, f32::max);
+    if loudest > 0.0 {
+        for peak in &mut peaks {
+            *peak /= loudest;
+        }
+    }
+    peaks
+}
+
+// 每段一列, 以中线为轴上下对称的竖条, 背景透明.
+pub fn render(peaks: &[f32]) -> FrameBuffer {
+    let mut buffer = SharedPixelBuffer::<Rgba8Pixel>::new(peaks.len() as u32, HEIGHT as u32);
+    let pixels = buffer.make_mut_slice();
+    let middle = HEIGHT as f32 / 2.0;
+    for (x, peak) in peaks.iter().enumerate() {
+        let half = (peak * middle).max(0.5);
+        for y in 0..HEIGHT {
+            if (y as f32 + 0.5 - middle).abs() <= half {
+                pixels[y * peaks.len() + x] = Rgba8Pixel::new(150, 200, 255, 255);
+            }
+        }
+    }
+    buffer
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 每段取最大值后按最响的一段归一化, 段数多于样本时重复使用样本.
+    #[test]
+    fn bucket_peaks() {
+        assert_eq!(peaks(&[0.1, 0.5, 0.2, 0.25], 2), vec![1.0, 0.5]);
+        assert_eq!(peaks(&[0.2, 0.4], 4), vec![0.5, 0.5, 1.0, 1.0]);
+        assert_eq!(peaks(&[0.0; 10], 3), vec![0.0; 3]);
+        assert!(peaks(&[], 3).is_empty());
+        assert!(peaks(&[0.5], 0).is_empty());
+    }
+
+    // 满格的一列从上到下, 静音的一列只有中线的两个像素.
+    #[test]
+    fn render_columns() {
+        let image = render(&[1.0, 0.0, 0.5]);
+        assert_eq!((image.width(), image.height()), (3, HEIGHT as u32));
+        let column = |x: usize| {
+            (0..HEIGHT)
+                .filter(|y| image.as_slice()[y * 3 + x].a == 255)
+                .count()
+        };
+        assert_eq!(column(0), HEIGHT);
+        assert_eq!(column(1), 2);
+        assert_eq!(column(2), HEIGHT / 2);
+    }
+}
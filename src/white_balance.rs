@@ -0,0 +1,136 @@
+use anyhow::{bail, Result};
+use opencv::{
+    core::{self, Mat, Rect},
+    prelude::*,
+    videoio::{self, VideoCapture},
+};
+use serde::Deserialize;
+
+use crate::pipeline::FrameProcessor;
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WhiteBalanceConfig {
+    // 采样区域边长 (像素), 以点击位置为中心.
+    pub sample_size: i32,
+    // 同时调整摄像头自身的白平衡参数, 而不只是在软件中乘以增益.
+    pub push_to_camera: bool,
+}
+
+impl Default for WhiteBalanceConfig {
+    fn default() -> Self {
+        Self {
+            sample_size: 15,
+            push_to_camera: false,
+        }
+    }
+}
+
+// BGR 三个通道的增益.
+pub type Gains = [f64; 3];
+
+// 以绿色通道为基准, 使点击的中性灰区域三个通道相等.
+pub fn measure(frame: &Mat, x: i32, y: i32, sample_size: i32) -> Result<Gains> {
+    let half = sample_size / 2;
+    let rect = Rect::new(x - half, y - half, sample_size, sample_size)
+        & Rect::new(0, 0, frame.cols(), frame.rows());
+    if rect.width <= 0 || rect.height <= 0 {
+        bail!("white balance point outside of frame");
+    }
+    let roi = Mat::roi(frame, rect)?;
+    let mean = core::mean(&*roi, &core::no_array())?;
+    let (b, g, r) = (mean[0], mean[1], mean[2]);
+    if b < 1.0 || g < 1.0 || r < 1.0 {
+        bail!("white balance reference is too dark");
+    }
+    if b > 254.0 || g > 254.0 || r > 254.0 {
+        bail!("white balance reference is overexposed");
+    }
+    Ok([g / b, 1.0, g / r])
+}
+
+// 把增益作用到摄像头的白平衡参数上, 返回是否成功; 不支持的后端返回 false.
+pub fn push_to_camera(camera: &mut VideoCapture, gains: &Gains) -> Result<bool> {
+    let blue = camera.get(videoio::CAP_PROP_WHITE_BALANCE_BLUE_U)?;
+    let red = camera.get(videoio::CAP_PROP_WHITE_BALANCE_RED_V)?;
+    if blue <= 0.0 || red <= 0.0 {
+        return Ok(false);
+    }
+    camera.set(videoio::CAP_PROP_AUTO_WB, 0.0)?;
+    let ok = camera.set(videoio::CAP_PROP_WHITE_BALANCE_BLUE_U, blue * gains[0])?
+        && camera.set(videoio::CAP_PROP_WHITE_BALANCE_RED_V, red * gains[2])?;
+    Ok(ok)
+}
+
+pub fn reset_camera(camera: &mut VideoCapture) -> Result<()> {
+    camera.set(videoio::CAP_PROP_AUTO_WB, 1.0)?;
+    Ok(())
+}
+
+pub struct WhiteBalance {
+    matrix: Mat,
+    scratch: Mat,
+}
+
+impl WhiteBalance {
+    pub fn new(gains: &Gains) -> Result<Self> {
+        let [b, g, r] = *gains;
+        let matrix = [[b, 0., 0.], [0., g, 0.], [0., 0., r]];
+        Ok(Self {
+            matrix: Mat::from_slice_2d(&matrix)?,
+            scratch: Mat::default(),
+        })
+    }
+}
+
+impl FrameProcessor for WhiteBalance {
+    fn name(&self) -> &str {
+        "white-balance"
+    }
+
+    fn order(&self) -> i32 {
+        -20
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        core::transform(frame, &mut self.scratch, &self.matrix)?;
+        std::mem::swap(frame, &mut self.scratch);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opencv::core::{Scalar, Vec3b, CV_8UC3};
+
+    use super::*;
+
+    fn frame(b: f64, g: f64, r: f64) -> Mat {
+        Mat::new_rows_cols_with_default(60, 80, CV_8UC3, Scalar::new(b, g, r, 0.0)).unwrap()
+    }
+
+    // 点击偏色的灰卡, 乘以增益后三个通道相等.
+    #[test]
+    fn neutral_after_gains() {
+        let mut frame = frame(100.0, 120.0, 150.0);
+        let gains = measure(&frame, 40, 30, 15).unwrap();
+        assert_eq!(gains, [1.2, 1.0, 0.8]);
+        // 靠近边缘时只采样画面内的部分.
+        assert_eq!(measure(&frame, 0, 59, 15).unwrap(), gains);
+        let mut balance = WhiteBalance::new(&gains).unwrap();
+        balance.process(&mut frame).unwrap();
+        assert_eq!(
+            *frame.at_2d::<Vec3b>(10, 10).unwrap(),
+            Vec3b::from([120, 120, 120])
+        );
+    }
+
+    #[test]
+    fn unusable_reference() {
+        let gray = frame(128.0, 128.0, 128.0);
+        assert!(measure(&gray, -20, 30, 15).is_err());
+        assert!(measure(&gray, 40, 100, 15).is_err());
+        assert!(measure(&frame(0.0, 40.0, 40.0), 40, 30, 15).is_err());
+        assert!(measure(&frame(255.0, 200.0, 200.0), 40, 30, 15).is_err());
+    }
+}
@@ -0,0 +1,567 @@
+use std::{
+    collections::BTreeMap,
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Timelike};
+use opencv::{
+    core::{self, Mat, Point, Ptr, Rect, Scalar, Size, Vector},
+    imgcodecs,
+    imgproc::{
+        self, CHAIN_APPROX_SIMPLE, FONT_HERSHEY_SIMPLEX, INTER_AREA, LINE_AA, RETR_EXTERNAL,
+    },
+    prelude::*,
+    video::{self, BackgroundSubtractorMOG2},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    classify::Classifier,
+    events::EventStore,
+    exclusions::ExclusionMask,
+    photo,
+    pipeline::FrameProcessor,
+    threads::{self, ThreadConfig},
+};
+
+// 运动检测时把画面缩小到该宽度.
+const MOTION_WIDTH: i32 = 320;
+// 最近一次记录的目击在预览中显示的时长.
+const SHOW_SIGHTING: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WildlifeConfig {
+    pub enabled: bool,
+    // 分类模型名称 (见 [models], task = "classification").
+    pub model: Option<String>,
+    // 只记录这些类别, 为空时记录全部. 通用模型可以用它排除背景类别.
+    pub labels: Vec<String>,
+    pub min_score: f64,
+    // 运动区域至少占画面的比例, 更小的变化 (树叶, 噪点) 不分类.
+    pub min_area: f64,
+    // 每秒最多分类的次数, 分类在后台线程运行.
+    pub fps: f64,
+    // 同一物种两次记录的最短间隔 (秒), 避免停留在喂食器上的鸟被反复记录.
+    pub cooldown: f64,
+    // 快照和每日汇总的保存目录.
+    pub dir: PathBuf,
+}
+
+impl Default for WildlifeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: None,
+            labels: Vec::new(),
+            min_score: 0.6,
+            min_area: 0.002,
+            fps: 2.0,
+            cooldown: 60.0,
+            dir: PathBuf::from("sightings"),
+        }
+    }
+}
+
+// 每日汇总中的一个物种.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Species {
+    count: u64,
+    first: String,
+    last: String,
+    best_score: f64,
+    best_snapshot: PathBuf,
+    // 按小时统计的次数.
+    hours: [u64; 24],
+}
+
+// summary-<日期>.json, 每次记录后重写, 当天的文件始终是最新的汇总.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Day {
+    date: String,
+    total: u64,
+    species: BTreeMap<String, Species>,
+}
+
+impl Day {
+    fn path(dir: &Path, date: &str) -> PathBuf {
+        dir.join(format!("summary-{}.json", date))
+    }
+
+    // 重新启动后继续当天的汇总.
+    fn load(dir: &Path, date: &str) -> Self {
+        fs::read_to_string(Self::path(dir, date))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_else(|| Self {
+                date: date.to_string(),
+                ..Self::default()
+            })
+    }
+
+    fn add(&mut self, label: &str, score: f64, time: DateTime<Local>, snapshot: &Path) {
+        let now = time.to_rfc3339();
+        let species = self
+            .species
+            .entry(label.to_string())
+            .or_insert_with(|| Species {
+                count: 0,
+                first: now.clone(),
+                last: now.clone(),
+                best_score: 0.0,
+                best_snapshot: PathBuf::new(),
+                hours: [0; 24],
+            });
+        species.count += 1;
+        species.last = now;
+        species.hours[time.hour() as usize] += 1;
+        if score > species.best_score {
+            species.best_score = score;
+            species.best_snapshot = snapshot.to_path_buf();
+        }
+        self.total += 1;
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        fs::write(
+            Self::path(dir, &self.date),
+            serde_json::to_string_pretty(self)?,
+        )?;
+        Ok(())
+    }
+}
+
+struct Job {
+    time: DateTime<Local>,
+    frame: Mat,
+    rect: Rect,
+}
+
+struct Sighting {
+    label: String,
+    score: f64,
+    rect: Rect,
+    at: Instant,
+}
+
+// 野生动物预设: 背景建模找出运动区域, 只把有运动的区域交给分类模型, 识别出的物种
+// 连同快照记录到事件存储, 并按天汇总. 适合喂鸟器摄像头这类大部分时间静止的画面.
+pub struct Wildlife {
+    jobs: SyncSender<Job>,
+    latest: Arc<Mutex<Option<Sighting>>>,
+    subtractor: Ptr<BackgroundSubtractorMOG2>,
+    min_area: f64,
+    interval: Duration,
+    last: Option<Instant>,
+    small: Mat,
+    mask: Mat,
+    masked: Mat,
+    exclusions: ExclusionMask,
+    motion: Option<Rect>,
+}
+
+impl Wildlife {
+    pub fn new(
+        config: &WildlifeConfig,
+        mut classifier: Box<dyn Classifier>,
+        events: EventStore,
+        workers: &ThreadConfig,
+    ) -> Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+        let latest = Arc::new(Mutex::new(None));
+        let results = latest.clone();
+        let (min_area, fps) = (config.min_area, config.fps);
+        let config = config.clone();
+        let mut day = Day::load(&config.dir, &Local::now().format("%Y-%m-%d").to_string());
+        let mut recorded: BTreeMap<String, Instant> = BTreeMap::new();
+        let cooldown = Duration::from_secs_f64(config.cooldown.max(0.0));
+        let jobs = threads::worker("wildlife", 1, workers, move |job: Job| {
+            let crop = Mat::roi(&job.frame, job.rect)?;
+            let (class, score) = classifier.classify(&*crop)?;
+            let label = classifier.label(class);
+            if score < config.min_score
+                || !(config.labels.is_empty() || config.labels.contains(&label))
+            {
+                return Ok(());
+            }
+            *results.lock().unwrap() = Some(Sighting {
+                label: label.clone(),
+                score,
+                rect: job.rect,
+                at: Instant::now(),
+            });
+            if recorded
+                .get(&label)
+                .is_some_and(|last| last.elapsed() < cooldown)
+            {
+                return Ok(());
+            }
+            recorded.insert(label.clone(), Instant::now());
+            record(&config, &events, &mut day, &job, &label, score)
+        })?;
+        Ok(Self {
+            jobs,
+            latest,
+            subtractor: video::create_background_subtractor_mog2(500, 16.0, false)?,
+            min_area,
+            interval: Duration::from_secs_f64(1.0 / fps.max(0.1)),
+            last: None,
+            small: Mat::default(),
+            mask: Mat::default(),
+            masked: Mat::default(),
+            exclusions: ExclusionMask::default(),
+            motion: None,
+        })
+    }
+
+    // 所有运动区域的外接矩形 (原始画面坐标), 面积太小时返回 None.
+    fn find_motion(&mut self, frame: &Mat) -> Result<Option<Rect>> {
+        let scale = (MOTION_WIDTH as f64 / frame.cols() as f64).min(1.0);
+        imgproc::resize(
+            frame,
+            &mut self.small,
+            Size::new(0, 0),
+            scale,
+            scale,
+            INTER_AREA,
+        )?;
+        self.subtractor.apply(&self.small, &mut self.mask, -1.0)?;
+        // 水面和树叶的反光不触发分类.
+        if let Some(exclusions) = self.exclusions.get(self.mask.size()?)? {
+            core::bitwise_and(&self.mask, exclusions, &mut self.masked, &core::no_array())?;
+            mem::swap(&mut self.mask, &mut self.masked);
+        }
+        let mut contours = Vector::<Vector<Point>>::new();
+        imgproc::find_contours(
+            &self.mask,
+            &mut contours,
+            RETR_EXTERNAL,
+            CHAIN_APPROX_SIMPLE,
+            Point::new(0, 0),
+        )?;
+        let mut area = 0.0;
+        let (mut left, mut top, mut right, mut bottom) = (i32::MAX, i32::MAX, 0, 0);
+        for contour in contours.iter() {
+            area += imgproc::contour_area(&contour, false)?;
+            let rect = imgproc::bounding_rect(&contour)?;
+            left = left.min(rect.x);
+            top = top.min(rect.y);
+            right = right.max(rect.x + rect.width);
+            bottom = bottom.max(rect.y + rect.height);
+        }
+        let total = (self.small.cols() * self.small.rows()) as f64;
+        if contours.is_empty() || area < self.min_area * total {
+            return Ok(None);
+        }
+        // 放大到原始画面并留出边距, 分类模型需要看到完整的动物.
+        let margin = (right - left).max(bottom - top) as f64 * 0.2;
+        let x = ((left as f64 - margin) / scale).max(0.0) as i32;
+        let y = ((top as f64 - margin) / scale).max(0.0) as i32;
+        let right = ((right as f64 + margin) / scale) as i32;
+        let bottom = ((bottom as f64 + margin) / scale) as i32;
+        Ok(Some(Rect::new(
+            x,
+            y,
+            right.min(frame.cols()) - x,
+            bottom.min(frame.rows()) - y,
+        )))
+    }
+}
+
+// 保存带标注的快照, 写入事件存储并更新当天的汇总.
+fn record(
+    config: &WildlifeConfig,
+    events: &EventStore,
+    day: &mut Day,
+    job: &Job,
+    label: &str,
+    score: f64,
+) -> Result<()> {
+    let date = job.time.format("%Y-%m-%d").to_string();
+    if day.date != date {
+        *day = Day::load(&config.dir, &date);
+    }
+    let dir = config.dir.join(&date);
+    fs::create_dir_all(&dir)?;
+    let name: String = label
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let snapshot = dir.join(format!("{}-{}.jpg", job.time.format("%H-%M-%S"), name));
+    let mut image = job.frame.try_clone()?;
+    draw_sighting(&mut image, label, score, job.rect)?;
+    let params = photo::write_params("jpg", Some(90));
+    if !imgcodecs::imwrite(&snapshot.to_string_lossy(), &image, &params)? {
+        bail!("write {}", snapshot.display());
+    }
+    events.log(
+        "sighting",
+        json!({
+            "label": label,
+            "score": score,
+            "snapshot": snapshot,
+            "x": job.rect.x,
+            "y": job.rect.y,
+            "width": job.rect.width,
+            "height": job.rect.height,
+        }),
+    );
+    day.add(label, score, job.time, &snapshot);
+    day.save(&config.dir)
+}
+
+fn draw_sighting(frame: &mut Mat, label: &str, score: f64, rect: Rect) -> Result<()> {
+    let color = Scalar::new(80., 220., 80., 0.);
+    imgproc::rectangle(frame, rect, color, 2, LINE_AA, 0)?;
+    imgproc::put_text(
+        frame,
+        &format!("{} {:.0}%", label, score * 100.0),
+        Point::new(rect.x, (rect.y - 8).max(16)),
+        FONT_HERSHEY_SIMPLEX,
+        0.7,
+        color,
+        2,
+        LINE_AA,
+        false,
+    )?;
+    Ok(())
+}
+
+impl FrameProcessor for Wildlife {
+    fn name(&self) -> &str {
+        "wildlife"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        self.motion = self.find_motion(frame)?;
+        let Some(rect) = self.motion else {
+            return Ok(());
+        };
+        if self.last.is_some_and(|last| last.elapsed() < self.interval) {
+            return Ok(());
+        }
+        let job = Job {
+            time: Local::now(),
+            frame: frame.try_clone()?,
+            rect,
+        };
+        match self.jobs.try_send(job) {
+            Ok(()) => self.last = Some(Instant::now()),
+            Err(TrySendError::Full(_)) => {}
+            Err(TrySendError::Disconnected(_)) => bail!("wildlife thread exited"),
+        }
+        Ok(())
+    }
+
+    fn has_overlay(&self) -> bool {
+        true
+    }
+
+    // 预览中画出运动区域和最近识别出的物种.
+    fn draw_overlay(&self, frame: &mut Mat) -> Result<()> {
+        if let Some(rect) = self.motion {
+            imgproc::rectangle(
+                frame,
+                rect,
+                Scalar::new(200., 200., 200., 0.),
+                1,
+                LINE_AA,
+                0,
+            )?;
+        }
+        if let Some(sighting) = &*self.latest.lock().unwrap() {
+            if sighting.at.elapsed() < SHOW_SIGHTING {
+                draw_sighting(frame, &sighting.label, sighting.score, sighting.rect)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread::sleep,
+    };
+
+    use chrono::TimeZone;
+    use opencv::core::CV_8UC3;
+
+    use super::*;
+
+    // 固定返回一个类别的分类器, 并统计调用次数.
+    struct Fixed(usize, Arc<AtomicUsize>);
+
+    impl Classifier for Fixed {
+        fn classify(&mut self, _image: &Mat) -> Result<(usize, f64)> {
+            self.1.fetch_add(1, Ordering::SeqCst);
+            Ok((self.0, 0.9))
+        }
+
+        fn label(&self, class: usize) -> String {
+            ["great tit", "crow"][class].to_string()
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("wildlife-{}-{}", name, std::process::id()))
+    }
+
+    fn blank() -> Mat {
+        Mat::new_rows_cols_with_default(48, 64, CV_8UC3, Scalar::all(0.)).unwrap()
+    }
+
+    // 黑色背景上的一个白色方块.
+    fn square(x: i32) -> Mat {
+        let mut frame = blank();
+        imgproc::rectangle(
+            &mut frame,
+            Rect::new(x, 10, 16, 16),
+            Scalar::all(255.),
+            -1,
+            imgproc::LINE_8,
+            0,
+        )
+        .unwrap();
+        frame
+    }
+
+    #[test]
+    fn day_summary() {
+        let dir = temp_dir("day");
+        fs::create_dir_all(&dir).unwrap();
+        let mut day = Day::load(&dir, "2026-05-01");
+        assert_eq!((day.date.as_str(), day.total), ("2026-05-01", 0));
+
+        let at = |hour| Local.with_ymd_and_hms(2026, 5, 1, hour, 30, 0).unwrap();
+        day.add("robin", 0.7, at(8), Path::new("a.jpg"));
+        day.add("robin", 0.9, at(9), Path::new("b.jpg"));
+        day.add("robin", 0.8, at(9), Path::new("c.jpg"));
+        day.add("crow", 0.6, at(17), Path::new("d.jpg"));
+        day.save(&dir).unwrap();
+
+        // 重新启动后继续当天的汇总.
+        let day = Day::load(&dir, "2026-05-01");
+        assert_eq!(day.total, 4);
+        let robin = &day.species["robin"];
+        assert_eq!(robin.count, 3);
+        assert_eq!(robin.best_score, 0.9);
+        assert_eq!(robin.best_snapshot, Path::new("b.jpg"));
+        assert_eq!((robin.hours[8], robin.hours[9]), (1, 2));
+        assert_eq!(robin.first, at(8).to_rfc3339());
+        assert_eq!(robin.last, at(9).to_rfc3339());
+        assert_eq!(day.species["crow"].hours[17], 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 静止的画面没有运动区域, 出现的目标被框出并留有边距.
+    #[test]
+    fn finds_motion() {
+        let dir = temp_dir("motion");
+        let events = EventStore::open(
+            std::env::temp_dir().join(format!("wildlife-motion-{}.jsonl", std::process::id())),
+        )
+        .unwrap();
+        let classifier = Fixed(0, Arc::default());
+        let config = WildlifeConfig {
+            dir: dir.clone(),
+            ..Default::default()
+        };
+        let mut wildlife = Wildlife::new(
+            &config,
+            Box::new(classifier),
+            events,
+            &ThreadConfig::default(),
+        )
+        .unwrap();
+        for _ in 0..5 {
+            wildlife.find_motion(&blank()).unwrap();
+        }
+        assert_eq!(wildlife.find_motion(&blank()).unwrap(), None);
+
+        let rect = wildlife.find_motion(&square(20)).unwrap().unwrap();
+        assert!(rect.x < 20 && rect.y < 10, "{rect:?}");
+        assert!(
+            rect.x + rect.width > 36 && rect.y + rect.height > 26,
+            "{rect:?}"
+        );
+        assert!(
+            rect.x + rect.width <= 64 && rect.y + rect.height <= 48,
+            "{rect:?}"
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn run(name: &str, class: usize) -> (PathBuf, Vec<crate::events::Event>) {
+        let dir = temp_dir(name);
+        let path =
+            std::env::temp_dir().join(format!("wildlife-{}-{}.jsonl", name, std::process::id()));
+        let events = EventStore::open(&path).unwrap();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let config = WildlifeConfig {
+            labels: vec!["great tit".to_string()],
+            min_score: 0.5,
+            fps: 1000.0,
+            cooldown: 3600.0,
+            dir: dir.clone(),
+            ..Default::default()
+        };
+        let classifier = Fixed(class, calls.clone());
+        let mut wildlife = Wildlife::new(
+            &config,
+            Box::new(classifier),
+            events,
+            &ThreadConfig::default(),
+        )
+        .unwrap();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut x = 0;
+        while calls.load(Ordering::SeqCst) < 3 {
+            assert!(Instant::now() < deadline, "timed out");
+            // 方块不断移动, 每帧都有运动.
+            x = (x + 8) % 48;
+            wildlife.process(&mut square(x)).unwrap();
+            sleep(Duration::from_millis(5));
+        }
+        drop(wildlife);
+        sleep(Duration::from_millis(50));
+        let logged = crate::events::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        (dir, logged)
+    }
+
+    // 冷却时间内同一物种只记录一次, 写出快照和当天的汇总.
+    #[test]
+    fn records_sightings() {
+        let (dir, logged) = run("records", 0);
+        assert_eq!(logged.len(), 1);
+        assert_eq!(logged[0].kind, "sighting");
+        assert_eq!(logged[0].data["label"], "great tit");
+        let snapshot = PathBuf::from(logged[0].data["snapshot"].as_str().unwrap());
+        assert!(snapshot.exists());
+        assert!(snapshot.to_string_lossy().ends_with("-great_tit.jpg"));
+
+        let date = logged[0].time.format("%Y-%m-%d").to_string();
+        let day = Day::load(&dir, &date);
+        assert_eq!(day.total, 1);
+        assert_eq!(day.species["great tit"].best_snapshot, snapshot);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // 不在 labels 中的类别不记录.
+    #[test]
+    fn skips_other_labels() {
+        let (dir, logged) = run("skips", 1);
+        assert!(logged.is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,252 @@
+use std::{
+    cell::Cell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    thread,
+};
+
+use anyhow::{Context, Result};
+use slint::{ComponentHandle, ModelRc, SharedString, VecModel};
+
+use crate::{
+    camera::{self, Backend, CameraConfig, Device},
+    doctor,
+    migrate::CONFIG_VERSION,
+    sink::CODECS,
+    Wizard,
+};
+
+const RESOLUTIONS: [&str; 5] = ["Default", "640x480", "1280x720", "1920x1080", "3840x2160"];
+
+// 向导的结果, calibrate 表示启动后进行白平衡校准.
+pub struct Setup {
+    pub calibrate: bool,
+}
+
+// 显示设置向导并把结果写入 path, 跳过时返回 None.
+pub fn run(path: &Path) -> Result<Option<Setup>> {
+    let devices = Rc::new(camera::devices(&CameraConfig::default()));
+    let wizard = Wizard::new()?;
+    let names: Vec<SharedString> = devices.iter().map(|d| d.to_string().into()).collect();
+    wizard.set_cameras(ModelRc::new(VecModel::from(names)));
+    let resolutions: Vec<SharedString> = RESOLUTIONS.iter().map(|&r| r.into()).collect();
+    wizard.set_resolutions(ModelRc::new(VecModel::from(resolutions)));
+
+    // 测试在后台线程中进行, 避免界面卡住.
+    let weak = wizard.as_weak();
+    let list = devices.clone();
+    wizard.on_test_camera(move |index, resolution| {
+        let device = pick(&list, index);
+        let resolution = parse_resolution(&resolution);
+        let weak = weak.clone();
+        if let Some(wizard) = weak.upgrade() {
+            wizard.set_busy(true);
+            wizard.set_camera_result(format!("Testing {}...", device).into());
+        }
+        thread::spawn(move || {
+            let text = match doctor::test_camera(&device, resolution) {
+                Ok(text) => format!("{}: {}", device, text),
+                Err(err) => format!("{} failed: {:#}", device, err),
+            };
+            let _ = weak.upgrade_in_event_loop(move |wizard| {
+                wizard.set_busy(false);
+                wizard.set_camera_result(text.into());
+            });
+        });
+    });
+    let weak = wizard.as_weak();
+    wizard.on_check_output(move |dir| {
+        let dir = PathBuf::from(dir.as_str());
+        let weak = weak.clone();
+        if let Some(wizard) = weak.upgrade() {
+            wizard.set_busy(true);
+            wizard.set_output_result("Checking...".into());
+        }
+        thread::spawn(move || {
+            let text = check_output(&dir).unwrap_or_else(|err| format!("Check failed: {}", err));
+            let _ = weak.upgrade_in_event_loop(move |wizard| {
+                wizard.set_busy(false);
+                wizard.set_output_result(text.into());
+            });
+        });
+    });
+
+    let result = Rc::new(Cell::new(None));
+    let saved = result.clone();
+    let weak = wizard.as_weak();
+    let config_path = path.to_path_buf();
+    wizard.on_save(move || {
+        let Some(wizard) = weak.upgrade() else {
+            return;
+        };
+        let device = pick(&devices, wizard.get_camera());
+        let resolution = parse_resolution(&wizard.get_resolution());
+        let output = PathBuf::from(wizard.get_output_dir().as_str());
+        match write_config(&config_path, &device, resolution, &output) {
+            Ok(()) => {
+                saved.set(Some(wizard.get_calibrate()));
+                let _ = slint::quit_event_loop();
+            }
+            Err(err) => wizard.set_output_result(format!("Save failed: {}", err).into()),
+        }
+    });
+    wizard.on_skip(|| {
+        let _ = slint::quit_event_loop();
+    });
+    wizard.run()?;
+
+    let Some(calibrate) = result.get() else {
+        // 写入只有注释的配置文件, 下次启动不再显示向导.
+        fs::write(
+            path,
+            "# First-run setup skipped, all settings use their defaults.\n",
+        )
+        .with_context(|| format!("write config {}", path.display()))?;
+        return Ok(None);
+    };
+    Ok(Some(Setup { calibrate }))
+}
+
+// 没有检测到摄像头时使用 camera 0.
+fn pick(devices: &[Device], index: i32) -> Device {
+    devices.get(index as usize).cloned().unwrap_or(Device {
+        index: 0,
+        name: None,
+        backend: Backend::default(),
+    })
+}
+
+fn parse_resolution(text: &str) -> Option<(i32, i32)> {
+    let (width, height) = text.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn check_output(dir: &Path) -> Result<String> {
+    let speed = doctor::write_speed(dir)?;
+    let codecs = doctor::available_codecs(dir);
+    let mut text = format!("Write speed {:.0} MB/s", speed);
+    if speed < doctor::MIN_WRITE_SPEED {
+        text += " (too slow for HD recordings)";
+    }
+    if codecs.is_empty() {
+        text += ", no video codec available";
+    } else {
+        text += &format!(", codecs: {}", codecs.join(", "));
+    }
+    Ok(text)
+}
+
+fn write_config(
+    path: &Path,
+    device: &Device,
+    resolution: Option<(i32, i32)>,
+    output: &Path,
+) -> Result<()> {
+    let mut camera = toml::Table::new();
+    match &device.name {
+        Some(name) => camera.insert("device".to_string(), name.clone().into()),
+        None => camera.insert("index".to_string(), i64::from(device.index).into()),
+    };
+    if let Some((width, height)) = resolution {
+        camera.insert("width".to_string(), i64::from(width).into());
+        camera.insert("height".to_string(), i64::from(height).into());
+    }
+
+    fs::create_dir_all(output).with_context(|| format!("create {}", output.display()))?;
+    let codecs = doctor::available_codecs(output);
+    let (code, extension) = CODECS
+        .into_iter()
+        .find(|(code, _)| codecs.contains(code))
+        .unwrap_or(CODECS[1]);
+    let mut sink = toml::Table::new();
+    sink.insert("kind".to_string(), "file".into());
+    let recording = output.join(format!("recording-%Y-%m-%dT%H-%M-%S.{}", extension));
+    sink.insert(
+        "path".to_string(),
+        recording.to_string_lossy().into_owned().into(),
+    );
+    sink.insert("fourcc".to_string(), code.into());
+
+    let mut config = toml::Table::new();
+    config.insert("version".to_string(), CONFIG_VERSION.into());
+    config.insert("camera".to_string(), toml::Value::Table(camera));
+    config.insert(
+        "sinks".to_string(),
+        toml::Value::Array(vec![toml::Value::Table(sink)]),
+    );
+    let text = format!(
+        "# Created by the first-run setup.\n{}",
+        toml::to_string(&config)?
+    );
+    fs::write(path, text).with_context(|| format!("write config {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn resolution() {
+        assert_eq!(parse_resolution("1280x720"), Some((1280, 720)));
+        assert_eq!(parse_resolution("Default"), None);
+        assert_eq!(parse_resolution("640xabc"), None);
+    }
+
+    #[test]
+    fn pick_falls_back_to_camera_0() {
+        let devices = [Device {
+            index: 3,
+            name: Some("USB Camera".to_string()),
+            backend: Backend::default(),
+        }];
+        assert_eq!(pick(&devices, 0).index, 3);
+        let fallback = pick(&devices, 1);
+        assert_eq!(fallback.index, 0);
+        assert!(fallback.name.is_none());
+        assert_eq!(pick(&[], 0).index, 0);
+    }
+
+    // 写出的配置可以直接加载, 选择第一个可用的编码.
+    #[test]
+    fn writes_loadable_config() {
+        let dir = std::env::temp_dir().join(format!("wizard-test-{}", std::process::id()));
+        let path = dir.join("config.toml");
+        let output = dir.join("recordings");
+        fs::create_dir_all(&dir).unwrap();
+        let device = Device {
+            index: 1,
+            name: Some("USB Camera".to_string()),
+            backend: Backend::default(),
+        };
+        write_config(&path, &device, Some((1280, 720)), &output).unwrap();
+        assert!(output.is_dir());
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.camera.device.as_deref(), Some("USB Camera"));
+        assert_eq!(config.camera.width, Some(1280));
+        assert_eq!(config.camera.height, Some(720));
+        assert_eq!(config.sinks.len(), 1);
+        let sink = &config.sinks[0];
+        assert_eq!(sink.kind, "file");
+        let code = sink.options["fourcc"].as_str().unwrap();
+        let (_, extension) = CODECS.iter().find(|(c, _)| *c == code).unwrap();
+        let recording = sink.options["path"].as_str().unwrap();
+        assert!(recording.starts_with(&*output.to_string_lossy()));
+        assert!(recording.ends_with(&format!(".{}", extension)));
+
+        // 没有名称的设备按编号选择.
+        let device = Device {
+            name: None,
+            ..device
+        };
+        write_config(&path, &device, None, &output).unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.camera.index, 1);
+        assert!(config.camera.device.is_none());
+        assert!(config.camera.width.is_none());
+
+        assert!(check_output(&output).unwrap().starts_with("Write speed"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::BTreeMap,
+    fs::OpenOptions,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use opencv::{
+    core::{Mat, Point, Scalar, Vector},
+    imgproc::{self, FONT_HERSHEY_SIMPLEX, LINE_AA},
+    prelude::*,
+};
+use serde::Deserialize;
+
+use crate::{
+    detect::Detector,
+    pipeline::FrameProcessor,
+    tracking::{track_color, Track, Tracker, TrackingConfig},
+};
+
+// 用户定义的区域, 顶点坐标为画面宽高的比例.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Zone {
+    pub name: String,
+    pub points: Vec<[f64; 2]>,
+}
+
+impl Zone {
+    // 射线法判断点是否在多边形内.
+    pub fn contains(&self, x: f64, y: f64) -> bool {
+        let mut inside = false;
+        let n = self.points.len();
+        for i in 0..n {
+            let [xi, yi] = self.points[i];
+            let [xj, yj] = self.points[(i + n - 1) % n];
+            if (yi > y) != (yj > y) && x < (xj - xi) * (y - yi) / (yj - yi) + xi {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    pub fn pixels(&self, cols: i32, rows: i32) -> Vector<Point> {
+        self.points
+            .iter()
+            .map(|[x, y]| Point::new((x * cols as f64) as i32, (y * rows as f64) as i32))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DwellConfig {
+    pub enabled: bool,
+    // 每隔多少帧运行一次行人检测.
+    pub detect_every: u32,
+    // 导出间隔 (秒).
+    pub export_interval: f64,
+    pub export_file: PathBuf,
+}
+
+impl Default for DwellConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            detect_every: 3,
+            export_interval: 60.0,
+            export_file: PathBuf::from("dwell.csv"),
+        }
+    }
+}
+
+struct Visit {
+    first: DateTime<Local>,
+    last: DateTime<Local>,
+    seconds: f64,
+}
+
+// 区域停留时间统计: 行人检测 + 跟踪, 累计每个目标在每个区域内的停留时间.
+pub struct DwellAnalytics {
+    config: DwellConfig,
+    zones: Vec<Zone>,
+    detector: Box<dyn Detector>,
+    tracker: Tracker,
+    tracks: Vec<Track>,
+    frames: u64,
+    last_update: Instant,
+    last_export: Instant,
+    // (目标 ID, 区域序号) -> 停留记录.
+    visits: BTreeMap<(u64, usize), Visit>,
+}
+
+impl DwellAnalytics {
+    pub fn new(
+        config: &DwellConfig,
+        zones: &[Zone],
+        tracking: &TrackingConfig,
+        detector: Box<dyn Detector>,
+    ) -> Self {
+        Self {
+            config: config.clone(),
+            zones: zones.to_vec(),
+            detector,
+            tracker: Tracker::new(tracking),
+            tracks: Vec::new(),
+            frames: 0,
+            last_update: Instant::now(),
+            last_export: Instant::now(),
+            visits: BTreeMap::new(),
+        }
+    }
+
+    // 把已经离开画面的目标的停留记录追加到 CSV, 并从内存中移除.
+    fn export(&mut self, all: bool) -> Result<()> {
+        let alive: Vec<u64> = self.tracks.iter().map(|t| t.id).collect();
+        let done: Vec<(u64, usize)> = self
+            .visits
+            .keys()
+            .filter(|(id, _)| all || !alive.contains(id))
+            .copied()
+            .collect();
+        if done.is_empty() {
+            return Ok(());
+        }
+        let path = &self.config.export_file;
+        let new = !path.exists();
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if new {
+            writeln!(file, "track_id,zone,seconds,first_seen,last_seen")?;
+        }
+        for key in done {
+            let visit = self.visits.remove(&key).unwrap();
+            writeln!(
+                file,
+                "{},{},{:.1},{},{}",
+                key.0,
+                self.zones[key.1].name,
+                visit.seconds,
+                visit.first.to_rfc3339(),
+                visit.last.to_rfc3339()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for DwellAnalytics {
+    fn drop(&mut self) {
+        if let Err(err) = self.export(true) {
+            eprintln!("export dwell times: {:?}", err);
+        }
+    }
+}
+
+impl FrameProcessor for DwellAnalytics {
+    fn name(&self) -> &str {
+        "dwell"
+    }
+
+    fn process(&mut self, frame: &mut Mat) -> Result<()> {
+        let (cols, rows) = (frame.cols(), frame.rows());
+        if self.frames % self.config.detect_every.max(1) as u64 == 0 {
+            let detections = self.detector.detect(frame)?;
+            self.tracker.update(&detections);
+            self.tracks = self.tracker.tracks().cloned().collect();
+        }
+        self.frames += 1;
+
+        let now = Instant::now();
+        let dt = now.duration_since(self.last_update).as_secs_f64();
+        self.last_update = now;
+        let time = Local::now();
+        let mut occupants = vec![0; self.zones.len()];
+        for track in self.tracks.iter().filter(|t| t.missed == 0) {
+            let foot = track.foot();
+            let (x, y) = (foot.x as f64 / cols as f64, foot.y as f64 / rows as f64);
+            for (z, zone) in self.zones.iter().enumerate() {
+                if zone.contains(x, y) {
+                    occupants[z] += 1;
+                    let visit = self.visits.entry((track.id, z)).or_insert(Visit {
+                        first: time,
+                        last: time,
+                        seconds: 0.0,
+                    });
+                    visit.seconds += dt;
+                    visit.last = time;
+                }
+            }
+        }
+
+        // 区域轮廓和实时计数.
+        let zone_color = Scalar::new(255., 128., 0., 0.);
+        for (z, zone) in self.zones.iter().enumerate() {
+            if zone.points.len() < 3 {
+                continue;
+            }
+            let points = zone.pixels(cols, rows);
+            let mut polygons = Vector::<Vector<Point>>::new();
+            polygons.push(points.clone());
+            imgproc::polylines(frame, &polygons, true, zone_color, 2, LINE_AA, 0)?;
+            let dwell: Vec<f64> = self
+                .visits
+                .iter()
+                .filter(|((_, vz), _)| *vz == z)
+                .map(|(_, v)| v.seconds)
+                .collect();
+            let average = if dwell.is_empty() {
+                0.0
+            } else {
+                dwell.iter().sum::<f64>() / dwell.len() as f64
+            };
+            let label = format!(
+                "{}: {} inside, avg {:.0}s",
+                zone.name, occupants[z], average
+            );
+            let origin = points.get(0)?;
+            imgproc::put_text(
+                frame,
+                &label,
+                Point::new(origin.x + 4, origin.y + 20),
+                FONT_HERSHEY_SIMPLEX,
+                0.6,
+                zone_color,
+                2,
+                LINE_AA,
+                false,
+            )?;
+        }
+        self.tracker.draw_trails(frame)?;
+        for track in self.tracks.iter().filter(|t| t.missed == 0) {
+            let color = track_color(track.id);
+            let seconds: f64 = self
+                .visits
+                .range((track.id, 0)..(track.id + 1, 0))
+                .map(|(_, v)| v.seconds)
+                .sum();
+            imgproc::rectangle(frame, track.rect, color, 2, LINE_AA, 0)?;
+            imgproc::put_text(
+                frame,
+                &format!("#{} {:.0}s", track.id, seconds),
+                Point::new(track.rect.x, track.rect.y - 6),
+                FONT_HERSHEY_SIMPLEX,
+                0.5,
+                color,
+                1,
+                LINE_AA,
+                false,
+            )?;
+        }
+
+        if self.last_export.elapsed() >= Duration::from_secs_f64(self.config.export_interval) {
+            self.last_export = Instant::now();
+            self.export(false)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, thread};
+
+    use opencv::core::{Rect, CV_8UC3};
+
+    use super::*;
+    use crate::tracking::Detection;
+
+    // 每帧都返回同样的检测框.
+    struct Fixed(Vec<Detection>);
+
+    impl Detector for Fixed {
+        fn detect(&mut self, _frame: &Mat) -> Result<Vec<Detection>> {
+            Ok(self.0.clone())
+        }
+
+        fn label(&self, _class: usize) -> String {
+            "person".to_string()
+        }
+    }
+
+    fn zone(name: &str, left: f64, right: f64) -> Zone {
+        Zone {
+            name: name.to_string(),
+            points: vec![[left, 0.0], [right, 0.0], [right, 1.0], [left, 1.0]],
+        }
+    }
+
+    #[test]
+    fn contains() {
+        let zone = Zone {
+            name: "triangle".to_string(),
+            points: vec![[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]],
+        };
+        assert!(zone.contains(0.2, 0.2));
+        assert!(!zone.contains(0.8, 0.8));
+        assert!(!zone.contains(-0.1, 0.5));
+    }
+
+    #[test]
+    fn dwell_per_zone() {
+        let path = std::env::temp_dir().join(format!("dwell-test-{}.csv", std::process::id()));
+        let config = DwellConfig {
+            detect_every: 1,
+            export_file: path.clone(),
+            ..Default::default()
+        };
+        let tracking = TrackingConfig {
+            min_hits: 1,
+            ..Default::default()
+        };
+        // 脚在 (20, 60), 位于左半边.
+        let person = Detection {
+            rect: Rect::new(10, 20, 20, 40),
+            score: 0.9,
+            class: 0,
+        };
+        let zones = [zone("left", 0.0, 0.5), zone("right", 0.5, 1.0)];
+        let mut dwell =
+            DwellAnalytics::new(&config, &zones, &tracking, Box::new(Fixed(vec![person])));
+        let mut frame =
+            Mat::new_rows_cols_with_default(100, 100, CV_8UC3, Scalar::all(0.)).unwrap();
+        for _ in 0..3 {
+            thread::sleep(Duration::from_millis(10));
+            dwell.process(&mut frame).unwrap();
+        }
+        assert_eq!(dwell.visits.len(), 1);
+        assert!(dwell.visits[&(1, 0)].seconds >= 0.02);
+
+        // 目标还在画面中, 不导出.
+        dwell.export(false).unwrap();
+        assert!(!path.exists());
+        drop(dwell);
+        let csv = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "track_id,zone,seconds,first_seen,last_seen");
+        assert!(lines[1].starts_with("1,left,"));
+    }
+}